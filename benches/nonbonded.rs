@@ -0,0 +1,109 @@
+//! Nonbonded forces for 4 atom pairs: one pair at a time, vs all 4 at once with AVX; and over a
+//! periodic box of atoms, with neighbor lists. Run with `cargo bench --bench nonbonded`.
+//! `test_nonbonded_x4` checks that the scalar and AVX forces agree.
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    use std::hint::black_box;
+
+    use criterion::Criterion;
+    use daedalus_core::dynamics::{
+        AtomDynamics, AtomDynamicsx4, MdState, f_nonbonded, simd::f_nonbonded_x4,
+    };
+    use lin_alg::f64::{Vec3, Vec3x4, f64x4};
+    use na_seq::Element;
+
+    fn atom(posit: Vec3, partial_charge: f64, lj_sigma: f64, lj_eps: f64) -> AtomDynamics {
+        AtomDynamics {
+            force_field_type: String::new(),
+            element: Element::Carbon,
+            posit,
+            vel: Vec3::new_zero(),
+            accel: Vec3::new_zero(),
+            mass: 12.,
+            partial_charge,
+            lj_sigma,
+            lj_eps,
+        }
+    }
+
+    pub fn nonbonded(c: &mut Criterion) {
+        if !is_x86_feature_detected!("avx") {
+            eprintln!("AVX isn't available; skipping.");
+            return;
+        }
+
+        let a_0 = atom(Vec3::new(1., 2., 3.), -0.3, 3.4, 0.086);
+        let others = [
+            atom(Vec3::new(4.2, 2.1, 3.3), 0.41, 3.1, 0.12),
+            atom(Vec3::new(1.5, -1.8, 2.2), -0.52, 2.9, 0.21),
+            atom(Vec3::new(-3., 4.5, 6.), 0.08, 2.6, 0.015),
+            atom(Vec3::new(1.2, 2.3, 9.1), 0.33, 3.3, 0.1),
+        ];
+        let dv = others.each_ref().map(|a| a.posit - a_0.posit);
+        let packed = AtomDynamicsx4::from_array(others.each_ref());
+
+        let mut group = c.benchmark_group("Nonbonded, 4 pairs");
+
+        group.bench_function("Scalar", |b| {
+            b.iter(|| {
+                for (a, dv) in others.iter().zip(dv) {
+                    black_box(f_nonbonded(black_box(dv), &a_0, a, 1., 1.));
+                }
+            })
+        });
+
+        group.bench_function("x4", |b| {
+            b.iter(|| {
+                black_box(f_nonbonded_x4(
+                    black_box(Vec3x4::from_array(dv)),
+                    &a_0,
+                    &packed,
+                    f64x4::splat(1.),
+                    f64x4::splat(1.),
+                ))
+            })
+        });
+
+        group.finish();
+    }
+
+    /// Atoms per side of the lattice in `apply_nonbonded_forces`.
+    const LATTICE_SIDE: usize = 12;
+    /// Å. About the O-O spacing in liquid water.
+    const LATTICE_SPACING: f64 = 3.1;
+
+    /// All nonbonded forces over a periodic lattice of atoms with alternating charges. This uses
+    /// AVX if available, and the scalar path otherwise.
+    pub fn apply_nonbonded_forces(c: &mut Criterion) {
+        let mut atoms = Vec::new();
+        for i in 0..LATTICE_SIDE {
+            for j in 0..LATTICE_SIDE {
+                for k in 0..LATTICE_SIDE {
+                    let posit = Vec3::new(i as f64, j as f64, k as f64) * LATTICE_SPACING;
+                    let q = if (i + j + k) % 2 == 0 { -0.4 } else { 0.4 };
+                    atoms.push(atom(posit, q, 3.15, 0.15));
+                }
+            }
+        }
+
+        let mut md = MdState::default();
+        md.atoms = atoms;
+        md.cell.lo = Vec3::splat(-0.5 * LATTICE_SPACING);
+        md.cell.hi = md.cell.lo + Vec3::splat(LATTICE_SIDE as f64 * LATTICE_SPACING);
+        md.build_neighbours();
+
+        c.bench_function(
+            &format!("apply_nonbonded_forces, {} atoms", md.atoms.len()),
+            |b| b.iter(|| black_box(md.apply_nonbonded_forces())),
+        );
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+criterion::criterion_group!(benches, x86::nonbonded, x86::apply_nonbonded_forces);
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+criterion::criterion_main!(benches);
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn main() {}
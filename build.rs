@@ -10,7 +10,12 @@ fn main() {
     #[cfg(feature = "cuda")]
     build(
         GpuArchitecture::Rtx4,
-        &["src/cuda/cuda.cu", "src/cuda/util.cu"],
+        &[
+            "src/cuda/cuda.cu",
+            "src/cuda/util.cu",
+            "src/cuda/dynamics.cu",
+            "src/cuda/neighbors.cu",
+        ],
     );
 
     if env::var_os("CARGO_CFG_WINDOWS").is_some() {
@@ -0,0 +1,283 @@
+//! Manual backbone remodeling: Set a residue's φ or ψ by rotating the downstream part of its chain
+//! about the corresponding bond. Optionally, keep the C-terminal part of the chain fixed; the gap
+//! this opens is closed by adjusting the torsions of the next few residues, using cyclic coordinate
+//! descent (CCD).
+
+use bio_files::ResidueType;
+use lin_alg::f64::{Quaternion, Vec3, calc_dihedral_angle_v2};
+use na_seq::Element;
+
+use crate::{
+    analysis::ramachandran::PEPTIDE_BOND_MAX,
+    molecule::{AtomRole, Molecule},
+};
+
+/// Number of residues after the edited one whose torsions are adjusted to close the chain, when
+/// pinning the C terminus.
+const CLOSURE_WINDOW: usize = 4;
+const CLOSURE_ITERS: usize = 200;
+/// Å. RMSD of the closure window's end residue backbone from its original position.
+const CLOSURE_TOL: f64 = 0.02;
+
+/// Å. Used to find the atoms bonded to backbone N and C'.
+const N_H_LEN_MAX: f64 = 1.3;
+const C_O_LEN_MAX: f64 = 1.7;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BackboneTorsion {
+    /// About the N-Cα bond.
+    Phi,
+    /// About the Cα-C' bond.
+    Psi,
+}
+
+/// Atom indices.
+#[derive(Clone, Copy, Debug)]
+struct Backbone {
+    n: usize,
+    ca: usize,
+    cp: usize,
+}
+
+impl Backbone {
+    fn axis(&self, torsion: BackboneTorsion) -> (usize, usize) {
+        match torsion {
+            BackboneTorsion::Phi => (self.n, self.ca),
+            BackboneTorsion::Psi => (self.ca, self.cp),
+        }
+    }
+}
+
+fn backbone_atoms(mol: &Molecule, res: usize) -> Option<Backbone> {
+    let (mut n, mut ca, mut cp) = (None, None, None);
+
+    for &i in &mol.residues[res].atoms {
+        match mol.atoms[i].role {
+            Some(AtomRole::N_Backbone) => n = Some(i),
+            Some(AtomRole::C_Alpha) => ca = Some(i),
+            Some(AtomRole::C_Prime) => cp = Some(i),
+            _ => (),
+        }
+    }
+
+    Some(Backbone {
+        n: n?,
+        ca: ca?,
+        cp: cp?,
+    })
+}
+
+/// The contiguous run of amino acid residues, without chain breaks, that contains `res`; in chain
+/// order.
+fn segment(mol: &Molecule, res: usize) -> Option<Vec<(usize, Backbone)>> {
+    let chain = mol.chains.iter().find(|c| c.residues.contains(&res))?;
+
+    let mut result: Vec<(usize, Backbone)> = Vec::new();
+    let mut found = false;
+
+    for &r in &chain.residues {
+        if !matches!(mol.residues[r].res_type, ResidueType::AminoAcid(_)) {
+            continue;
+        }
+
+        let bb = backbone_atoms(mol, r);
+        let connected = match (result.last(), &bb) {
+            (Some((_, prev)), Some(bb)) => {
+                (mol.atoms[bb.n].posit - mol.atoms[prev.cp].posit).magnitude() < PEPTIDE_BOND_MAX
+            }
+            _ => false,
+        };
+
+        if !connected {
+            if found {
+                break;
+            }
+            result.clear();
+        }
+
+        let Some(bb) = bb else {
+            continue;
+        };
+        result.push((r, bb));
+
+        if r == res {
+            found = true;
+        }
+    }
+
+    found.then_some(result)
+}
+
+/// The atoms that move when rotating a torsion of the residue at position `k` in the segment,
+/// through the residue at position `end`.
+fn moving_atoms(
+    mol: &Molecule,
+    seg: &[(usize, Backbone)],
+    k: usize,
+    torsion: BackboneTorsion,
+    end: usize,
+) -> Vec<usize> {
+    let (res, bb) = &seg[k];
+    let dist = |i: usize, j: usize| (mol.atoms[i].posit - mol.atoms[j].posit).magnitude();
+
+    let mut result: Vec<_> = mol.residues[*res]
+        .atoms
+        .iter()
+        .copied()
+        .filter(|&i| match torsion {
+            // All but N, and its hydrogens.
+            BackboneTorsion::Phi => {
+                i != bb.n
+                    && !(mol.atoms[i].element == Element::Hydrogen && dist(i, bb.n) < N_H_LEN_MAX)
+            }
+            // The carbonyl oxygen, or both terminal oxygens.
+            BackboneTorsion::Psi => i != bb.ca && i != bb.cp && dist(i, bb.cp) < C_O_LEN_MAX,
+        })
+        .collect();
+
+    for (r, _) in &seg[k + 1..=end] {
+        result.extend(&mol.residues[*r].atoms);
+    }
+
+    result
+}
+
+/// Radians. `None` at the ends of the segment, where the torsion is undefined.
+fn torsion_angle(
+    posits: &[Vec3],
+    seg: &[(usize, Backbone)],
+    k: usize,
+    torsion: BackboneTorsion,
+) -> Option<f64> {
+    let bb = &seg[k].1;
+
+    let atoms = match torsion {
+        BackboneTorsion::Phi => (seg[k.checked_sub(1)?].1.cp, bb.n, bb.ca, bb.cp),
+        BackboneTorsion::Psi => (bb.n, bb.ca, bb.cp, seg.get(k + 1)?.1.n),
+    };
+
+    Some(calc_dihedral_angle_v2(&(
+        posits[atoms.0],
+        posits[atoms.1],
+        posits[atoms.2],
+        posits[atoms.3],
+    )))
+}
+
+/// Right-handed rotation about the axis from `axis_0` to `axis_1`.
+fn rotate(posits: &mut [Vec3], atoms: &[usize], axis_0: Vec3, axis_1: Vec3, angle: f64) {
+    let rotator = Quaternion::from_axis_angle((axis_1 - axis_0).to_normalized(), angle);
+
+    for &i in atoms {
+        posits[i] = axis_0 + rotator.rotate_vec(posits[i] - axis_0);
+    }
+}
+
+/// Return the end residue's backbone atoms to their original positions by adjusting the free
+/// torsions between it and the edited one. Each step rotates a single torsion by the angle
+/// that minimizes the end atoms' squared distance from their targets. Returns the final RMSD, in Å.
+fn close_chain(
+    mol: &Molecule,
+    seg: &[(usize, Backbone)],
+    k: usize,
+    edited: BackboneTorsion,
+    end: usize,
+    posits: &mut [Vec3],
+) -> f64 {
+    let bb_end = &seg[end].1;
+    let effectors = [bb_end.n, bb_end.ca, bb_end.cp];
+    let targets = effectors.map(|i| mol.atoms[i].posit);
+
+    let mut free = Vec::new();
+    if edited == BackboneTorsion::Phi {
+        free.push((k, BackboneTorsion::Psi));
+    }
+    for j in k + 1..end {
+        free.push((j, BackboneTorsion::Phi));
+        free.push((j, BackboneTorsion::Psi));
+    }
+    // Moves the end residue's C' only.
+    free.push((end, BackboneTorsion::Phi));
+
+    let moving: Vec<_> = free
+        .iter()
+        .map(|&(j, t)| moving_atoms(mol, seg, j, t, end))
+        .collect();
+
+    let rmsd = |posits: &[Vec3]| {
+        let sum_sq: f64 = effectors
+            .iter()
+            .zip(&targets)
+            .map(|(&i, t)| (posits[i] - *t).magnitude_squared())
+            .sum();
+        (sum_sq / effectors.len() as f64).sqrt()
+    };
+
+    for _ in 0..CLOSURE_ITERS {
+        if rmsd(posits) < CLOSURE_TOL {
+            break;
+        }
+
+        for (&(j, t), atoms) in free.iter().zip(&moving) {
+            let (a, b) = seg[j].1.axis(t);
+            let (origin, axis_end) = (posits[a], posits[b]);
+            let u = (axis_end - origin).to_normalized();
+
+            // Maximize Σ f · (r cos θ + (u × r) sin θ), where r is the effector's position
+            // perpendicular to the axis, and f is the target's, relative to the axis origin.
+            let (mut num, mut den) = (0., 0.);
+            for (&e, target) in effectors.iter().zip(&targets) {
+                let m = posits[e] - origin;
+                let r = m - u * m.dot(u);
+                let f = *target - origin;
+
+                num += f.dot(u.cross(r));
+                den += f.dot(r);
+            }
+
+            rotate(posits, atoms, origin, axis_end, num.atan2(den));
+        }
+    }
+
+    rmsd(posits)
+}
+
+/// Set a residue's φ or ψ, in radians, rotating the atoms downstream of the bond. If `pin_c_term`
+/// is set, only the next few residues move, and their torsions are adjusted so the chain
+/// after them stays connected and in place. Returns the remaining gap at the end of the moved
+/// region, as an RMSD in Å (0 if not pinning), or `None` if the residue's torsion is undefined.
+pub fn set_backbone_torsion(
+    mol: &mut Molecule,
+    res: usize,
+    torsion: BackboneTorsion,
+    angle: f64,
+    pin_c_term: bool,
+) -> Option<f64> {
+    let seg = segment(mol, res)?;
+    let k = seg.iter().position(|(r, _)| *r == res)?;
+
+    let mut posits: Vec<_> = mol.atoms.iter().map(|a| a.posit).collect();
+    let current = torsion_angle(&posits, &seg, k, torsion)?;
+
+    // If the closure window reaches the end of the segment, there's nothing to pin.
+    let last = seg.len() - 1;
+    let closing = pin_c_term && k + CLOSURE_WINDOW < last;
+    let end = if closing { k + CLOSURE_WINDOW } else { last };
+
+    let (a, b) = seg[k].1.axis(torsion);
+    let (axis_0, axis_1) = (posits[a], posits[b]);
+    let atoms = moving_atoms(mol, &seg, k, torsion, end);
+    rotate(&mut posits, &atoms, axis_0, axis_1, angle - current);
+
+    let gap = if closing {
+        close_chain(mol, &seg, k, torsion, end, &mut posits)
+    } else {
+        0.
+    };
+
+    for (atom, posit) in mol.atoms.iter_mut().zip(posits) {
+        atom.posit = posit;
+    }
+
+    Some(gap)
+}
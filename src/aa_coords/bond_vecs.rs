@@ -5,9 +5,10 @@
 
 // [Includes some common bond angles](https://www.ncbi.nlm.nih.gov/pmc/articles/PMC2810841/)
 
-use std::f64::consts::TAU;
+use std::{f64::consts::TAU, sync::LazyLock};
 
 use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::AminoAcid;
 
 // use crate::water;
 
@@ -63,98 +64,22 @@ pub const ANCHOR_BOND_VEC: Vec3 = Vec3 {
     z: 0.,
 };
 
-// These bonds are unit vecs populated by init_local_bond_vecs.
-
-// We use `static mut` here instead of constant, since we need non-const fns (like sin and cos, and
-// the linear algebra operations that operate on them) in their construction.
+// Bond vectors anchored to `ANCHOR_BOND_VEC`. The others, which need non-const fns (like sin and
+// cos, and the linear algebra operations that operate on them) in their construction, are in
+// `ResidueGeometry`.
 pub const CALPHA_CP_BOND: Vec3 = ANCHOR_BOND_VEC;
-
-pub static mut CALPHA_N_BOND: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-pub static mut CALPHA_R_BOND: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-pub static mut CALPHA_H_BOND: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
 pub const CP_N_BOND: Vec3 = ANCHOR_BOND_VEC;
-
-pub static mut CP_CALPHA_BOND: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-// The O bond on CP turns out to be [-0.5402403204776551, 0, 0.841510781945306], given our calculated
-// anchors for the N and Calpha bonds on it.
-pub static mut CP_O_BOND: Vec3 = Vec3 {
-    // todo: These are arbitrary values
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
 pub const N_CALPHA_BOND: Vec3 = ANCHOR_BOND_VEC;
 
-pub static mut N_CP_BOND: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-pub static mut N_H_BOND: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
 pub const O_CP_BOND: Vec3 = ANCHOR_BOND_VEC;
 pub const H_CALPHA_BOND: Vec3 = ANCHOR_BOND_VEC;
 pub const H_N_BOND: Vec3 = ANCHOR_BOND_VEC;
 
-// These are updated in `init_local_bond_vecs`.
-
-// Generic bond geometry; real world values vary slightly from this. Initialized below.
+// Generic bond geometry; real world values vary slightly from this.
 
 // *const* substitute for `Tetrahedral`.
 pub const TETRA_A: Vec3 = ANCHOR_BOND_VEC;
-pub static mut TETRA_B: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-pub static mut TETRA_C: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-pub static mut TETRA_D: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
 pub const PLANAR3_A: Vec3 = ANCHOR_BOND_VEC;
-pub static mut PLANAR3_B: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-pub static mut PLANAR3_C: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
 
 pub const RING_BOND_IN: Vec3 = Vec3 {
     // The anchor vec
@@ -164,47 +89,6 @@ pub const RING_BOND_IN: Vec3 = Vec3 {
 };
 
 pub const WATER_BOND_H_A: Vec3 = ANCHOR_BOND_VEC;
-pub static mut WATER_BOND_H_B: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-pub static mut WATER_BOND_M: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-// Formular for N-sided ring: TAU/2 - TAU/N
-// 1.884955
-// These ring bond out angles are for planar rings, with the input being the anchor vec.
-pub static mut RING5_BOND_OUT: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-// Other direction.
-pub static mut RING5_BOND_OUT_B: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-// 2.094395
-pub static mut RING6_BOND_OUT: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
-// Other direction.
-pub static mut RING6_BOND_OUT_B: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
 
 // H Dummy bonds, for semantic clarity. They're the same, but we need both in our forward-kinematics API.
 pub const H_BOND_IN: Vec3 = Vec3 {
@@ -225,15 +109,6 @@ pub const O_BOND_IN: Vec3 = Vec3 {
     z: 0.,
 };
 
-// todo:  What should this be? Can it rotate freely??
-// todo: Should it be a different fixed angle? Currently have it slightly greater than TAU/4, initialized
-// todo below
-pub static mut O_BOND_OUT: Vec3 = Vec3 {
-    x: 0.,
-    y: 0.,
-    z: 0.,
-};
-
 /// 4 tetrahedral bonds. Eg Carbon.
 pub struct Tetrahedral {
     pub bond_a: Vec3,
@@ -327,107 +202,255 @@ fn find_third_bond_vec(
     result
 }
 
-/// Calculate local bond vectors based on relative angles, and arbitrary constants.
+/// Ideal residue geometry: Local bond unit vectors used by forward kinematics, built from bond
+/// angles, and sidechain bond lengths. Immutable once built, so it's safe to share between threads;
+/// get it with `residue_geometry()`.
+///
 /// The absolute bonds used are arbitrary; their positions relative to each other are
 /// defined by the bond angles.
 /// As an arbitrary convention, we'll make the first vector the one to the next atom
 /// in the chain, and the second to the previous. The third is for C'oxygen, or Cα side chain.
-pub fn init_local_bond_vecs() {
-    // Calculate (arbitrary) vectors normal to the anchor vectors for each atom.
-    // Find the second bond vector by rotating the first around this by the angle
-    // between the two.
-    // Given we're anchoring the initial vecs to a specific vector
-    // ANCHOR_BOND_VEC = (1, 0, 0), we can
-    // skip this and use a known orthonormal vec to it like 0, 1, 0.
+#[derive(Debug)]
+pub struct ResidueGeometry {
+    pub calpha_n: Vec3,
+    pub calpha_r: Vec3,
+    pub cp_calpha: Vec3,
+    // The O bond on CP turns out to be [-0.5402403204776551, 0, 0.841510781945306], given our calculated
+    // anchors for the N and Calpha bonds on it.
+    pub cp_o: Vec3,
+    pub n_cp: Vec3,
+    pub tetra_b: Vec3,
+    pub tetra_c: Vec3,
+    pub tetra_d: Vec3,
+    pub planar3_b: Vec3,
+    pub planar3_c: Vec3,
+    // Formular for N-sided ring: TAU/2 - TAU/N
+    // 1.884955
+    // This ring bond out angle is for planar rings, with the input being the anchor vec.
+    pub ring5_bond_out: Vec3,
+    // todo:  What should this be? Can it rotate freely??
+    // todo: Should it be a different fixed angle? Currently have it slightly greater than TAU/4.
+    pub o_bond_out: Vec3,
+}
 
-    // let normal_cα = Vec3::new(0., 1., 0.).cross(CALPHA_CP_BOND);
+impl ResidueGeometry {
+    fn new() -> Self {
+        // Calculate (arbitrary) vectors normal to the anchor vectors for each atom.
+        // Find the second bond vector by rotating the first around this by the angle
+        // between the two.
+        // Given we're anchoring the initial vecs to a specific vector
+        // ANCHOR_BOND_VEC = (1, 0, 0), we can
+        // skip this and use a known orthonormal vec to it like 0, 1, 0.
 
-    // We use this normal plane for all rotations if the bond are in plane. We use it for
-    // the first rotation if not.
-    let rot_plane_norm = Vec3::new(0., 0., 1.);
+        // We use this normal plane for all rotations if the bond are in plane. We use it for
+        // the first rotation if not.
+        let rot_plane_norm = Vec3::new(0., 0., 1.);
 
-    // The first bond vectors are defined as the anchor vec. These are Calpha's CP bond, Cp's N bond,
-    // and N's Calpha bond. They are also the generic geometry's `bond_a` for planar and tetrahedral.
+        // The first bond vectors are defined as the anchor vec. These are Calpha's CP bond, Cp's N bond,
+        // and N's Calpha bond. They are also the generic geometry's `bond_a` for planar and tetrahedral.
 
-    unsafe {
-        // Store globals for generic geometric bond angles, to prevent repeated calculation.
+        // Generic geometric bond angles.
         let tetra = Tetrahedral::default();
-        // TETRA_A = tetra.bond_a; // const; anchor
-        TETRA_B = tetra.bond_b;
-        TETRA_C = tetra.bond_c;
-        TETRA_D = tetra.bond_d;
-
         let planar3 = Planar3::default();
-        // PLANAR3_A = planar3.bond_a; // const; anchor
-        PLANAR3_B = planar3.bond_b;
-        PLANAR3_C = planar3.bond_c;
 
         let z = Vec3::new(0., 0., 1.);
         let bond_angle_ring5 = TAU / 2. - TAU / 5.;
-        let bond_angle_ring6 = TAU / 2. - TAU / 6.;
         let bond_angle_ho = TAU * 0.3;
-        let bond_angle_water = θ_HOH_ANGLE;
-
-        RING5_BOND_OUT =
-            Quaternion::from_axis_angle(z, bond_angle_ring5).rotate_vec(ANCHOR_BOND_VEC);
-        RING6_BOND_OUT =
-            Quaternion::from_axis_angle(z, bond_angle_ring6).rotate_vec(ANCHOR_BOND_VEC);
-
-        RING5_BOND_OUT_B =
-            Quaternion::from_axis_angle(z, -bond_angle_ring5).rotate_vec(ANCHOR_BOND_VEC);
-        RING6_BOND_OUT_B =
-            Quaternion::from_axis_angle(z, -bond_angle_ring6).rotate_vec(ANCHOR_BOND_VEC);
-
-        O_BOND_OUT = Quaternion::from_axis_angle(z, bond_angle_ho).rotate_vec(ANCHOR_BOND_VEC);
-
-        WATER_BOND_H_B =
-            Quaternion::from_axis_angle(z, bond_angle_water).rotate_vec(ANCHOR_BOND_VEC);
-        WATER_BOND_M =
-            Quaternion::from_axis_angle(z, bond_angle_water / 2.).rotate_vec(ANCHOR_BOND_VEC);
 
         // Find the second bond vectors. The initial anchor bonds (eg `CALPHA_CP_BOND`) are rotated
         // along the (underconstrained) normal plane above.
-        CALPHA_N_BOND = rotate_vec(CALPHA_CP_BOND, BOND_ANGLE_CALPHA_CP_N, rot_plane_norm);
-        CP_CALPHA_BOND = rotate_vec(CP_N_BOND, BOND_ANGLE_CP_N_CALPHA, rot_plane_norm);
-        N_CP_BOND = rotate_vec(N_CALPHA_BOND, BOND_ANGLE_N_CALPHA_CP, rot_plane_norm);
-
-        // todo: We don't have actual H measurements; using a dummy angle for now.
-        // todo: Rather than assume it's in plane, we may need to apply the `find_third_bond_vec`
-        // todo logic below to find H atom locations.
-        N_H_BOND = rotate_vec(N_CALPHA_BOND, TAU * 2. / 3., rot_plane_norm);
+        let calpha_n = rotate_vec(CALPHA_CP_BOND, BOND_ANGLE_CALPHA_CP_N, rot_plane_norm);
+        let cp_calpha = rotate_vec(CP_N_BOND, BOND_ANGLE_CP_N_CALPHA, rot_plane_norm);
+        let n_cp = rotate_vec(N_CALPHA_BOND, BOND_ANGLE_N_CALPHA_CP, rot_plane_norm);
 
         // todo: To find the 3rd (and later 4th, ie hydrogen-to-atom) bonds, we we
         // todo taking an iterative approach based on that above. Long-term, you should
         // todo be able to calculate one of 2 valid choices (for carbon).
 
-        CALPHA_R_BOND = find_third_bond_vec(
+        let calpha_r = find_third_bond_vec(
             CALPHA_CP_BOND,
-            CALPHA_N_BOND,
+            calpha_n,
             BOND_ANGLE_CALPHA_CP_R,
             BOND_ANGLE_CALPHA_N_R,
             rot_plane_norm,
         );
 
-        // println!("CALPHA_R_BOND {:?}", CALPHA_R_BOND);
-        // println!("CALPHA_N_BOND {:?}", CALPHA_N_BOND);
-        // println!("CALPHA_CP_BOND {:?}", CALPHA_CP_BOND);
-
-        // Using generic tetrahedron geometry for now, since we don't have average measured angles.
-        CALPHA_H_BOND = tetra.bond_d;
-
-        // todo temp
-        // CALPHA_H_BOND = Vec3::new(0.7, -1., 1.).to_normalized();
-
-        CP_O_BOND = find_third_bond_vec(
+        let cp_o = find_third_bond_vec(
             CP_N_BOND,
-            CP_CALPHA_BOND,
+            cp_calpha,
             BOND_ANGLE_CP_N_O,
             BOND_ANGLE_CP_CALPHA_O,
             rot_plane_norm,
         );
 
-        // todo: The other ones.
+        Self {
+            calpha_n,
+            calpha_r,
+            cp_calpha,
+            cp_o,
+            n_cp,
+            tetra_b: tetra.bond_b,
+            tetra_c: tetra.bond_c,
+            tetra_d: tetra.bond_d,
+            planar3_b: planar3.bond_b,
+            planar3_c: planar3.bond_c,
+            ring5_bond_out: Quaternion::from_axis_angle(z, bond_angle_ring5)
+                .rotate_vec(ANCHOR_BOND_VEC),
+            o_bond_out: Quaternion::from_axis_angle(z, bond_angle_ho).rotate_vec(ANCHOR_BOND_VEC),
+        }
+    }
+
+    /// Ideal length of a sidechain bond, in Å, between two heavy atoms named per the PDB
+    /// convention, in either order. Falls back to a generic C-C single bond.
+    pub fn bond_len(&self, aa: AminoAcid, atom_0: &str, atom_1: &str) -> f64 {
+        sidechain_bond_lens(aa)
+            .iter()
+            .find(|(a, b, _)| (*a == atom_0 && *b == atom_1) || (*a == atom_1 && *b == atom_0))
+            .map(|(_, _, len)| *len)
+            .unwrap_or(LEN_C_C)
+    }
+}
+
+static GEOMETRY: LazyLock<ResidueGeometry> = LazyLock::new(ResidueGeometry::new);
+
+pub fn residue_geometry() -> &'static ResidueGeometry {
+    &GEOMETRY
+}
 
-        // Find vectors from C' to O, and Cα to R, given the previous 2 bonds for each.
+/// Å. Generic sp3 C-C single bond.
+const LEN_C_C: f64 = 1.53;
+
+/// Ideal sidechain heavy-atom bond lengths, in Å, for the bonds we place atoms along, and the
+/// bonds that close rings. From Engh and Huber (1991), which Amber's residue templates also use.
+/// Se-C is from small-molecule structures.
+fn sidechain_bond_lens(aa: AminoAcid) -> &'static [(&'static str, &'static str, f64)] {
+    match aa {
+        AminoAcid::Arg => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.520),
+            ("CG", "CD", 1.520),
+            ("CD", "NE", 1.460),
+            ("NE", "CZ", 1.329),
+            ("CZ", "NH1", 1.326),
+            ("CZ", "NH2", 1.326),
+        ],
+        AminoAcid::His => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.497),
+            ("CG", "ND1", 1.378),
+            ("CG", "CD2", 1.354),
+            ("ND1", "CE1", 1.321),
+            ("CD2", "NE2", 1.374),
+            ("CE1", "NE2", 1.321),
+        ],
+        AminoAcid::Lys => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.520),
+            ("CG", "CD", 1.520),
+            ("CD", "CE", 1.520),
+            ("CE", "NZ", 1.489),
+        ],
+        AminoAcid::Asp => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.516),
+            ("CG", "OD1", 1.249),
+            ("CG", "OD2", 1.249),
+        ],
+        AminoAcid::Glu => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.520),
+            ("CG", "CD", 1.516),
+            ("CD", "OE1", 1.249),
+            ("CD", "OE2", 1.249),
+        ],
+        AminoAcid::Ser => &[("CA", "CB", 1.530), ("CB", "OG", 1.417)],
+        AminoAcid::Thr => &[
+            ("CA", "CB", 1.540),
+            ("CB", "OG1", 1.433),
+            ("CB", "CG2", 1.521),
+        ],
+        AminoAcid::Asn => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.516),
+            ("CG", "OD1", 1.231),
+            ("CG", "ND2", 1.328),
+        ],
+        AminoAcid::Gln => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.520),
+            ("CG", "CD", 1.516),
+            ("CD", "OE1", 1.231),
+            ("CD", "NE2", 1.328),
+        ],
+        AminoAcid::Cys => &[("CA", "CB", 1.530), ("CB", "SG", 1.808)],
+        AminoAcid::Sec => &[("CA", "CB", 1.530), ("CB", "SE", 1.950)],
+        AminoAcid::Gly => &[],
+        AminoAcid::Pro => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.492),
+            ("CG", "CD", 1.503),
+            // The ring closes on the backbone N.
+            ("CD", "N", 1.473),
+            ("N", "CA", 1.466),
+        ],
+        AminoAcid::Ala => &[("CA", "CB", 1.521)],
+        AminoAcid::Val => &[
+            ("CA", "CB", 1.540),
+            ("CB", "CG1", 1.521),
+            ("CB", "CG2", 1.521),
+        ],
+        AminoAcid::Ile => &[
+            ("CA", "CB", 1.540),
+            ("CB", "CG1", 1.530),
+            ("CB", "CG2", 1.521),
+            ("CG1", "CD1", 1.513),
+        ],
+        AminoAcid::Leu => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.530),
+            ("CG", "CD1", 1.521),
+            ("CG", "CD2", 1.521),
+        ],
+        AminoAcid::Met => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.520),
+            ("CG", "SD", 1.803),
+            ("SD", "CE", 1.791),
+        ],
+        AminoAcid::Phe => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.502),
+            ("CG", "CD1", 1.384),
+            ("CG", "CD2", 1.384),
+            ("CD1", "CE1", 1.382),
+            ("CD2", "CE2", 1.382),
+            ("CE1", "CZ", 1.382),
+            ("CE2", "CZ", 1.382),
+        ],
+        AminoAcid::Tyr => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.512),
+            ("CG", "CD1", 1.389),
+            ("CG", "CD2", 1.389),
+            ("CD1", "CE1", 1.382),
+            ("CD2", "CE2", 1.382),
+            ("CE1", "CZ", 1.378),
+            ("CE2", "CZ", 1.378),
+            ("CZ", "OH", 1.376),
+        ],
+        AminoAcid::Trp => &[
+            ("CA", "CB", 1.530),
+            ("CB", "CG", 1.498),
+            ("CG", "CD1", 1.365),
+            ("CD1", "NE1", 1.374),
+            ("NE1", "CE2", 1.370),
+            ("CE2", "CZ2", 1.394),
+            ("CZ2", "CH2", 1.368),
+            ("CH2", "CZ3", 1.400),
+            ("CZ3", "CE3", 1.382),
+            ("CE3", "CD2", 1.398),
+            ("CD2", "CG", 1.433),
+            ("CD2", "CE2", 1.409),
+        ],
     }
 }
@@ -0,0 +1,422 @@
+//! Build an all-atom peptide de novo from its sequence and backbone dihedral angles. Backbone and
+//! sidechain heavy atoms are placed by forward kinematics, from ideal bond lengths and angles.
+//! Sidechains use their default χ angles. Hydrogens, bonds etc are added by `Molecule::new`.
+
+use std::{f64::consts::TAU, fmt, io, io::ErrorKind, str::FromStr};
+
+use bio_files::{Chain, ResidueType};
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::{
+    AtomTypeInRes,
+    Element::{self, *},
+};
+
+use crate::{
+    aa_coords::{
+        PHI_SHEET, PSI_SHEET,
+        bond_vecs::{
+            CALPHA_CP_BOND, CP_N_BOND, LEN_CALPHA_CP, LEN_CP_N, LEN_CP_O, LEN_N_CALPHA,
+            N_CALPHA_BOND, ResidueGeometry, residue_geometry,
+        },
+        sc_atom_placement::find_atom_placement,
+        sidechain::{Sidechain, set_χ},
+    },
+    molecule::{Atom, AtomRole, Molecule, Residue},
+};
+
+// Radians.
+pub const PHI_ALPHA_HELIX: f64 = -57. * TAU / 360.;
+pub const PSI_ALPHA_HELIX: f64 = -47. * TAU / 360.;
+/// Polyproline II helix.
+pub const PHI_PPII: f64 = -75. * TAU / 360.;
+pub const PSI_PPII: f64 = 145. * TAU / 360.;
+/// Trans peptide bonds.
+const OMEGA: f64 = TAU / 2.;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum BackbonePreset {
+    #[default]
+    AlphaHelix,
+    Sheet,
+    Ppii,
+    /// φ and ψ, in radians.
+    Custom(f64, f64),
+}
+
+impl BackbonePreset {
+    /// φ and ψ, in radians.
+    pub fn dihedrals(self) -> (f64, f64) {
+        match self {
+            Self::AlphaHelix => (PHI_ALPHA_HELIX, PSI_ALPHA_HELIX),
+            Self::Sheet => (PHI_SHEET, PSI_SHEET),
+            Self::Ppii => (PHI_PPII, PSI_PPII),
+            Self::Custom(φ, ψ) => (φ, ψ),
+        }
+    }
+}
+
+impl fmt::Display for BackbonePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlphaHelix => write!(f, "α helix"),
+            Self::Sheet => write!(f, "β sheet"),
+            Self::Ppii => write!(f, "PPII"),
+            Self::Custom(_, _) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Sidechain heavy atoms, by name, from forward kinematics. The χ angles are as measured by
+/// `Sidechain::from_coords`.
+pub(crate) fn sidechain_atoms(
+    sc: &Sidechain,
+    c_alpha: Vec3,
+    c_alpha_or: Quaternion,
+    n: Vec3,
+    geom: &ResidueGeometry,
+) -> Vec<(&'static str, Element, Vec3)> {
+    let sc_atoms = match sc {
+        Sidechain::Arg(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD", Carbon, c.c_delta),
+                ("NE", Nitrogen, c.n_eps),
+                ("CZ", Carbon, c.c_zeta),
+                ("NH1", Nitrogen, c.n_eta1),
+                ("NH2", Nitrogen, c.n_eta2),
+            ]
+        }
+        Sidechain::His(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("ND1", Nitrogen, c.n_delta2),
+                ("CD2", Carbon, c.c_delta1),
+                ("CE1", Carbon, c.c_eps2),
+                ("NE2", Nitrogen, c.n_eps1),
+            ]
+        }
+        Sidechain::Lys(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD", Carbon, c.c_delta),
+                ("CE", Carbon, c.c_eps),
+                ("NZ", Nitrogen, c.n_zeta),
+            ]
+        }
+        Sidechain::Asp(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("OD1", Oxygen, c.o_delta1),
+                ("OD2", Oxygen, c.o_delta2),
+            ]
+        }
+        Sidechain::Glu(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD", Carbon, c.c_delta),
+                ("OE1", Oxygen, c.o_eps1),
+                ("OE2", Oxygen, c.o_eps2),
+            ]
+        }
+        Sidechain::Ser(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![("CB", Carbon, c.c_beta), ("OG", Oxygen, c.o_gamma)]
+        }
+        Sidechain::Thr(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("OG1", Oxygen, c.o_gamma1),
+                ("CG2", Carbon, c.c_gamma2),
+            ]
+        }
+        Sidechain::Asn(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("OD1", Oxygen, c.o_delta1),
+                ("ND2", Nitrogen, c.n_delta2),
+            ]
+        }
+        Sidechain::Gln(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD", Carbon, c.c_delta),
+                ("OE1", Oxygen, c.o_eps1),
+                ("NE2", Nitrogen, c.n_eps2),
+            ]
+        }
+        Sidechain::Cys(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![("CB", Carbon, c.c_beta), ("SG", Sulfur, c.s_gamma)]
+        }
+        Sidechain::Sec(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![("CB", Carbon, c.c_beta), ("SE", Selenium, c.se_gamma)]
+        }
+        Sidechain::Gly(_) => Vec::new(),
+        Sidechain::Pro(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD", Carbon, c.c_delta),
+            ]
+        }
+        Sidechain::Ala(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![("CB", Carbon, c.c_beta)]
+        }
+        Sidechain::Val(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG1", Carbon, c.c_gamma1),
+                ("CG2", Carbon, c.c_gamma2),
+            ]
+        }
+        Sidechain::Ile(aa) => {
+            // Our δ carbon is bonded to γ2; the PDB convention is γ1.
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG1", Carbon, c.c_gamma2),
+                ("CG2", Carbon, c.c_gamma1),
+                ("CD1", Carbon, c.c_delta),
+            ]
+        }
+        Sidechain::Leu(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD1", Carbon, c.c_delta1),
+                ("CD2", Carbon, c.c_delta2),
+            ]
+        }
+        Sidechain::Met(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("SD", Sulfur, c.s_delta),
+                ("CE", Carbon, c.c_eps),
+            ]
+        }
+        Sidechain::Phe(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD1", Carbon, c.c_delta1),
+                ("CD2", Carbon, c.c_delta2),
+                ("CE1", Carbon, c.c_eps1),
+                ("CE2", Carbon, c.c_eps2),
+                ("CZ", Carbon, c.c_zeta),
+            ]
+        }
+        Sidechain::Tyr(aa) => {
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD1", Carbon, c.c_delta1),
+                ("CD2", Carbon, c.c_delta2),
+                ("CE1", Carbon, c.c_eps1),
+                ("CE2", Carbon, c.c_eps2),
+                ("CZ", Carbon, c.c_zeta),
+                ("OH", Oxygen, c.o_eta),
+            ]
+        }
+        Sidechain::Trp(aa) => {
+            // Our atoms are named in order around the ring system, starting from Cγ.
+            let c = aa.sidechain_cart_coords(c_alpha, c_alpha_or, n, geom);
+            vec![
+                ("CB", Carbon, c.c_beta),
+                ("CG", Carbon, c.c_gamma),
+                ("CD1", Carbon, c.c_delta),
+                ("NE1", Nitrogen, c.n_eps),
+                ("CE2", Carbon, c.c_zeta),
+                ("CZ2", Carbon, c.c_eta),
+                ("CH2", Carbon, c.c_theta),
+                ("CZ3", Carbon, c.c_iota),
+                ("CE3", Carbon, c.c_kappa),
+                ("CD2", Carbon, c.c_lambda),
+            ]
+        }
+    };
+
+    // Forward kinematics measures dihedrals from bond vectors, which doesn't match how we measure
+    // them from coordinates. Set each χ as measured, so building and measuring round-trip.
+    let mut result = vec![("N", Nitrogen, n), ("CA", Carbon, c_alpha)];
+    result.extend(sc_atoms);
+    set_χ(&mut result, sc.aa_type(), &sc.χ_angles());
+
+    result.split_off(2)
+}
+
+/// Sidechains, with default χ angles, from a one-letter sequence. Whitespace is ignored.
+pub(super) fn sidechains_from_seq(seq: &str) -> io::Result<Vec<Sidechain>> {
+    let seq: Vec<char> = seq.chars().filter(|c| !c.is_whitespace()).collect();
+    if seq.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Empty sequence"));
+    }
+
+    let mut result = Vec::with_capacity(seq.len());
+    for c in &seq {
+        match Sidechain::from_ident_single_letter(&c.to_ascii_uppercase().to_string()) {
+            Some(sc) => result.push(sc),
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid amino acid: {c}"),
+                ));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Build a peptide from a one-letter sequence, e.g. "ACDKW", with the same φ and ψ for each
+/// residue. The first N is at the origin.
+pub fn build_peptide(seq: &str, backbone: BackbonePreset) -> io::Result<Molecule> {
+    let sidechains = sidechains_from_seq(seq)?;
+
+    let (φ, ψ) = backbone.dihedrals();
+    let geom = residue_geometry();
+
+    let mut atoms = Vec::new();
+    let mut residues = Vec::with_capacity(sidechains.len());
+
+    // A virtual C' before the first residue, to define its φ.
+    let mut n = Vec3::new_zero();
+    let mut n_or = Quaternion::new_identity();
+    let mut cp_prev = n + n_or.rotate_vec(geom.n_cp) * LEN_CP_N;
+
+    for (res_i, sc) in sidechains.iter().enumerate() {
+        let (c_alpha, c_alpha_or) = find_atom_placement(
+            n_or,
+            geom.calpha_n,
+            CALPHA_CP_BOND,
+            φ,
+            n,
+            cp_prev,
+            N_CALPHA_BOND,
+            LEN_N_CALPHA,
+        );
+
+        let (cp, cp_or) = find_atom_placement(
+            c_alpha_or,
+            geom.cp_calpha,
+            CP_N_BOND,
+            ψ,
+            c_alpha,
+            n,
+            CALPHA_CP_BOND,
+            LEN_CALPHA_CP,
+        );
+
+        let (n_next, n_next_or) = find_atom_placement(
+            cp_or,
+            geom.n_cp,
+            N_CALPHA_BOND,
+            OMEGA,
+            cp,
+            c_alpha,
+            CP_N_BOND,
+            LEN_CP_N,
+        );
+
+        let o = cp + cp_or.rotate_vec(geom.cp_o) * LEN_CP_O;
+
+        let mut res_atoms = vec![
+            ("N", Nitrogen, n),
+            ("CA", Carbon, c_alpha),
+            ("C", Carbon, cp),
+            ("O", Oxygen, o),
+        ];
+        res_atoms.extend(sidechain_atoms(sc, c_alpha, c_alpha_or, n, geom));
+
+        // The C-terminal carboxylate's second O takes the place of the next N.
+        if res_i == sidechains.len() - 1 {
+            let dir = (n_next - cp).to_normalized();
+            res_atoms.push(("OXT", Oxygen, cp + dir * LEN_CP_O));
+        }
+
+        let mut res = Residue {
+            serial_number: res_i as isize + 1,
+            res_type: ResidueType::AminoAcid(sc.aa_type()),
+            atoms: Vec::with_capacity(res_atoms.len()),
+            dihedral: None,
+            props: Default::default(),
+            variant: None,
+        };
+
+        for (name, element, posit) in res_atoms {
+            res.atoms.push(atoms.len());
+            atoms.push(Atom {
+                serial_number: atoms.len() + 1,
+                posit,
+                element,
+                type_in_res: AtomTypeInRes::from_str(name).ok(),
+                force_field_type: None,
+                dock_type: None,
+                role: Some(AtomRole::from_name(name)),
+                residue: Some(res_i),
+                hetero: false,
+                occupancy: None,
+                partial_charge: None,
+                temperature_factor: None,
+                alt_loc: None,
+                anisou: None,
+                props: Default::default(),
+                in_ring: false,
+                aromatic: false,
+            });
+        }
+        residues.push(res);
+
+        cp_prev = cp;
+        n = n_next;
+        n_or = n_next_or;
+    }
+
+    let chain = Chain {
+        id: "A".to_owned(),
+        atoms: (0..atoms.len()).collect(),
+        residues: (0..residues.len()).collect(),
+        visible: true,
+    };
+
+    let ident = if sidechains.len() <= 8 {
+        sidechains
+            .iter()
+            .map(|sc| sc.aa_ident_single_letter())
+            .collect()
+    } else {
+        "peptide".to_owned()
+    };
+
+    Ok(Molecule::new(
+        ident,
+        atoms,
+        vec![chain],
+        residues,
+        None,
+        None,
+    ))
+}
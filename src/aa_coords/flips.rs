@@ -0,0 +1,553 @@
+//! Hydrogen-bond network optimization, similar to Reduce's flip correction. Asn and Gln amides,
+//! and His rings, are often built 180° from their true orientation, since O, N, and C are hard to
+//! distinguish in electron density. Hydroxyl hydrogens of Ser, Thr, and Tyr aren't resolved at
+//! all. We choose each group's orientation to maximize hydrogen bonding with its surroundings,
+//! and penalize polar hydrogen or lone pair clashes. For His, we also choose the tautomer: HID, HIE,
+//! or HIP, by which ring nitrogens carry hydrogens.
+//!
+//! Groups are optimized greedily, one at a time given the current state of the others, repeating
+//! until none change.
+
+use std::f64::consts::{PI, TAU};
+
+use bio_files::ResidueType;
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::{
+    AminoAcid, AminoAcidProtenationVariant,
+    AminoAcidProtenationVariant::{Hid, Hie, Hip},
+    Element,
+};
+
+use crate::molecule::Molecule;
+
+/// Å. Atoms within this of a group's axis are considered when scoring it.
+const ENV_DIST: f64 = 7.;
+const MAX_PASSES: usize = 5;
+/// Orientations for hydroxyl hydrogens, evenly spaced.
+const HYDROXYL_STEPS: usize = 12;
+/// A change must improve the score by at least this. Prevents flips based on noise.
+const SCORE_IMPROVEMENT_MIN: f64 = 0.2;
+
+/// Å. H to acceptor.
+const HB_DIST_IDEAL: f64 = 1.9;
+const HB_DIST_MAX: f64 = 2.6;
+/// Radians. Donor-H-acceptor angle.
+const HB_ANGLE_MIN: f64 = 100. * PI / 180.;
+/// Score units are approximately kcal/mol.
+const HB_SCORE: f64 = 2.;
+/// Å. Polar hydrogens closer than this clash.
+const H_H_CLASH_DIST: f64 = 1.7;
+const H_H_CLASH_SCORE: f64 = 4.;
+/// Å. Acceptors without hydrogens (e.g. carbonyl O, unprotonated His N) closer than this repel.
+const ACC_ACC_CLASH_DIST: f64 = 3.1;
+const ACC_ACC_CLASH_SCORE: f64 = 2.;
+/// The cost of HIP, the charged tautomer, when the protonation state hasn't been assigned: its
+/// free energy at pH 7, from His's model pKa of 6.5.
+const HIP_PENALTY: f64 = 0.7;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FlipKind {
+    /// Asn or Gln.
+    Amide,
+    /// His.
+    Imidazole,
+    /// Ser, Thr, or Tyr.
+    Hydroxyl,
+}
+
+#[derive(Clone, Debug)]
+pub struct SidechainFlip {
+    /// Residue index.
+    pub res: usize,
+    pub kind: FlipKind,
+    /// Radians; the rotation applied.
+    pub angle: f64,
+    /// His only: HID, HIE, or HIP.
+    pub tautomer: Option<AminoAcidProtenationVariant>,
+    /// The change in score; negative is an improvement.
+    pub score_change: f64,
+}
+
+impl SidechainFlip {
+    /// E.g. "Asn 45: flipped (-2.1)".
+    pub fn descrip(&self, mol: &Molecule) -> String {
+        let res = &mol.residues[self.res];
+        let res_name = match &res.res_type {
+            ResidueType::AminoAcid(aa) => aa.to_string(),
+            ResidueType::Water => "Water".to_owned(),
+            ResidueType::Other(n) => n.clone(),
+        };
+
+        let change = match self.kind {
+            FlipKind::Amide => "flipped".to_owned(),
+            FlipKind::Imidazole => {
+                let tautomer = match self.tautomer {
+                    Some(t) => format!("{t:?}").to_uppercase(),
+                    None => String::new(),
+                };
+                if self.angle == 0. {
+                    tautomer
+                } else {
+                    format!("flipped, {tautomer}")
+                }
+            }
+            FlipKind::Hydroxyl => {
+                let angle = if self.angle > PI {
+                    self.angle - TAU
+                } else {
+                    self.angle
+                };
+                format!("H rotated {:.0}°", angle.to_degrees())
+            }
+        };
+
+        format!(
+            "{res_name} {}: {change} ({:.1})",
+            res.serial_number, self.score_change
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GroupState {
+    /// Radians, relative to the original orientation.
+    angle: f64,
+    tautomer: Option<AminoAcidProtenationVariant>,
+}
+
+/// Atom indices of His's ring nitrogens, and the hydrogens on them.
+#[derive(Clone, Copy)]
+struct HisRing {
+    nd1: usize,
+    hd1: usize,
+    ne2: usize,
+    he2: usize,
+}
+
+struct Group {
+    res: usize,
+    kind: FlipKind,
+    /// Atom indices. Moving atoms rotate about the axis from the first to the second.
+    axis: (usize, usize),
+    moving: Vec<usize>,
+    states: Vec<GroupState>,
+    /// Set for His with both ring hydrogens, so we can choose its tautomer.
+    his_ring: Option<HisRing>,
+    /// Whether HIP is a candidate state, but its protonation state wasn't assigned.
+    hip_penalty: bool,
+}
+
+/// Per-atom polar properties, from elements and bonding.
+struct Polarity {
+    /// For polar hydrogens, the N or O they're bonded to.
+    donor: Vec<Option<usize>>,
+    acceptor: Vec<bool>,
+    /// An acceptor with no hydrogens, so it can't donate in return.
+    acceptor_only: Vec<bool>,
+}
+
+impl Polarity {
+    fn new(mol: &Molecule) -> Self {
+        let adj = &mol.adjacency_list;
+        let n = mol.atoms.len();
+
+        let bonded_h = |i: usize| {
+            adj[i]
+                .iter()
+                .any(|&j| mol.atoms[j].element == Element::Hydrogen)
+        };
+
+        let mut donor = vec![None; n];
+        let mut acceptor = vec![false; n];
+        let mut acceptor_only = vec![false; n];
+
+        for (i, atom) in mol.atoms.iter().enumerate() {
+            match atom.element {
+                Element::Hydrogen => {
+                    donor[i] = adj[i].iter().copied().find(|&j| {
+                        matches!(mol.atoms[j].element, Element::Nitrogen | Element::Oxygen)
+                    });
+                }
+                Element::Oxygen => {
+                    acceptor[i] = true;
+                    acceptor_only[i] = !bonded_h(i);
+                }
+                // E.g. His's unprotonated ring N. Excludes amines, amides, and Pro's N.
+                Element::Nitrogen => {
+                    acceptor[i] = !bonded_h(i) && adj[i].len() < 3;
+                    acceptor_only[i] = acceptor[i];
+                }
+                _ => (),
+            }
+        }
+
+        Self {
+            donor,
+            acceptor,
+            acceptor_only,
+        }
+    }
+}
+
+fn find_groups(mol: &Molecule) -> Vec<Group> {
+    let adj = &mol.adjacency_list;
+    let mut result = Vec::new();
+
+    for (i_res, res) in mol.residues.iter().enumerate() {
+        let ResidueType::AminoAcid(aa) = res.res_type else {
+            continue;
+        };
+
+        // (Axis atoms, heavy atoms that move, kind)
+        let (axis, heavy, kind): ((&str, &str), &[&str], _) = match aa {
+            AminoAcid::Asn => (("CB", "CG"), &["OD1", "ND2"], FlipKind::Amide),
+            AminoAcid::Gln => (("CG", "CD"), &["OE1", "NE2"], FlipKind::Amide),
+            AminoAcid::His => (
+                ("CB", "CG"),
+                &["ND1", "CD2", "CE1", "NE2"],
+                FlipKind::Imidazole,
+            ),
+            AminoAcid::Ser => (("CB", "OG"), &[], FlipKind::Hydroxyl),
+            AminoAcid::Thr => (("CB", "OG1"), &[], FlipKind::Hydroxyl),
+            AminoAcid::Tyr => (("CZ", "OH"), &[], FlipKind::Hydroxyl),
+            _ => continue,
+        };
+
+        let (Some(axis_0), Some(axis_1)) = (
+            mol.atom_in_res(i_res, axis.0),
+            mol.atom_in_res(i_res, axis.1),
+        ) else {
+            continue;
+        };
+
+        let Some(mut moving) = heavy
+            .iter()
+            .map(|name| mol.atom_in_res(i_res, name))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+
+        // Hydrogens move with their heavy atom. For hydroxyls, this is the only moving atom.
+        let parents = if kind == FlipKind::Hydroxyl {
+            vec![axis_1]
+        } else {
+            moving.clone()
+        };
+        for parent in parents {
+            moving.extend(
+                adj[parent]
+                    .iter()
+                    .filter(|&&j| mol.atoms[j].element == Element::Hydrogen),
+            );
+        }
+
+        let angles = match (kind, aa) {
+            (FlipKind::Hydroxyl, AminoAcid::Tyr) => vec![0., PI],
+            (FlipKind::Hydroxyl, _) => (0..HYDROXYL_STEPS)
+                .map(|k| k as f64 * TAU / HYDROXYL_STEPS as f64)
+                .collect(),
+            _ => vec![0., PI],
+        };
+
+        if kind == FlipKind::Hydroxyl && moving.is_empty() {
+            continue;
+        }
+
+        // His with hydrogens on both ring nitrogens, e.g. as we add them: choose the tautomer too.
+        // If the protonation state is assigned, only choose between the neutral ones.
+        let mut his_ring = None;
+        let mut tautomers = vec![None];
+        let mut hip_penalty = false;
+
+        if kind == FlipKind::Imidazole {
+            let ring_h = |n: usize| {
+                adj[n]
+                    .iter()
+                    .copied()
+                    .find(|&j| mol.atoms[j].element == Element::Hydrogen)
+            };
+
+            if let (Some(nd1), Some(ne2)) =
+                (mol.atom_in_res(i_res, "ND1"), mol.atom_in_res(i_res, "NE2"))
+            {
+                if let (Some(hd1), Some(he2)) = (ring_h(nd1), ring_h(ne2)) {
+                    his_ring = Some(HisRing { nd1, hd1, ne2, he2 });
+
+                    tautomers = match res.variant {
+                        Some(Hip) => vec![Some(Hip)],
+                        Some(_) => vec![Some(Hie), Some(Hid)],
+                        None => {
+                            hip_penalty = true;
+                            vec![Some(Hip), Some(Hie), Some(Hid)]
+                        }
+                    };
+                }
+            }
+        }
+
+        let states = angles
+            .iter()
+            .flat_map(|&angle| {
+                tautomers
+                    .iter()
+                    .map(move |&tautomer| GroupState { angle, tautomer })
+            })
+            .collect();
+
+        result.push(Group {
+            res: i_res,
+            kind,
+            axis: (axis_0, axis_1),
+            moving,
+            states,
+            his_ring,
+            hip_penalty,
+        });
+    }
+
+    result
+}
+
+/// Set the polarity of His's ring atoms for a tautomer: A ring N with a hydrogen donates through
+/// it; one without is an acceptor. Hydrogens of the tautomer not in use aren't scored.
+fn set_tautomer(pol: &mut Polarity, ring: &HisRing, tautomer: AminoAcidProtenationVariant) {
+    let (d1, e2) = match tautomer {
+        Hid => (true, false),
+        Hie => (false, true),
+        _ => (true, true),
+    };
+
+    for (n, h, protonated) in [(ring.nd1, ring.hd1, d1), (ring.ne2, ring.he2, e2)] {
+        pol.donor[h] = protonated.then_some(n);
+        pol.acceptor[n] = !protonated;
+        pol.acceptor_only[n] = !protonated;
+    }
+}
+
+/// H-bond score between a polar H and an acceptor. Linear in distance from ideal, and scaled down
+/// as the donor-H-acceptor angle departs from linear.
+fn h_bond_score(posits: &[Vec3], donor: usize, h: usize, acc: usize) -> f64 {
+    let dist = (posits[acc] - posits[h]).magnitude();
+    if dist > HB_DIST_MAX {
+        return 0.;
+    }
+
+    let h_d = (posits[donor] - posits[h]).to_normalized();
+    let h_a = (posits[acc] - posits[h]).to_normalized();
+    let angle = h_d.dot(h_a).clamp(-1., 1.).acos();
+    if angle < HB_ANGLE_MIN {
+        return 0.;
+    }
+
+    let dist_factor = (1. - (dist - HB_DIST_IDEAL).abs() / (HB_DIST_MAX - HB_DIST_IDEAL)).max(0.);
+    let angle_factor = (angle - HB_ANGLE_MIN) / (PI - HB_ANGLE_MIN);
+
+    -HB_SCORE * dist_factor * angle_factor
+}
+
+/// Score of a pair of atoms, where at least one is in the group being scored. Lower is better.
+fn pair_score(posits: &[Vec3], pol: &Polarity, i: usize, j: usize) -> f64 {
+    let dist = (posits[i] - posits[j]).magnitude();
+    let mut result = 0.;
+
+    if let Some(d) = pol.donor[i] {
+        if pol.acceptor[j] && d != j {
+            result += h_bond_score(posits, d, i, j);
+        }
+        if pol.donor[j].is_some() && dist < H_H_CLASH_DIST {
+            result += H_H_CLASH_SCORE * (H_H_CLASH_DIST - dist);
+        }
+    }
+    if let Some(d) = pol.donor[j] {
+        if pol.acceptor[i] && d != i {
+            result += h_bond_score(posits, d, j, i);
+        }
+    }
+
+    if pol.acceptor_only[i] && pol.acceptor_only[j] && dist < ACC_ACC_CLASH_DIST {
+        result += ACC_ACC_CLASH_SCORE * (ACC_ACC_CLASH_DIST - dist);
+    }
+
+    result
+}
+
+fn group_score(posits: &[Vec3], pol: &Polarity, group: &Group, env: &[usize]) -> f64 {
+    group
+        .moving
+        .iter()
+        .flat_map(|&i| env.iter().map(move |&j| (i, j)))
+        .map(|(i, j)| pair_score(posits, pol, i, j))
+        .sum()
+}
+
+fn rotate_group(posits: &mut [Vec3], group: &Group, angle: f64) {
+    let origin = posits[group.axis.0];
+    let axis = (posits[group.axis.1] - origin).to_normalized();
+    let rotator = Quaternion::from_axis_angle(axis, angle);
+
+    for &i in &group.moving {
+        posits[i] = origin + rotator.rotate_vec(posits[i] - origin);
+    }
+}
+
+/// Move a group from one of its states to another.
+fn set_state(
+    posits: &mut [Vec3],
+    pol: &mut Polarity,
+    group: &Group,
+    from: GroupState,
+    to: GroupState,
+) {
+    if to.angle != from.angle {
+        rotate_group(posits, group, to.angle - from.angle);
+    }
+    if let (Some(ring), Some(tautomer)) = (&group.his_ring, to.tautomer) {
+        set_tautomer(pol, ring, tautomer);
+    }
+}
+
+fn state_score(
+    posits: &[Vec3],
+    pol: &Polarity,
+    group: &Group,
+    env: &[usize],
+    state: GroupState,
+) -> f64 {
+    let penalty = if group.hip_penalty && state.tautomer == Some(Hip) {
+        HIP_PENALTY
+    } else {
+        0.
+    };
+
+    group_score(posits, pol, group, env) + penalty
+}
+
+/// Flip Asn, Gln, and His sidechains, choose His tautomers, and rotate hydroxyl hydrogens, to
+/// optimize the hydrogen bond network. Run after hydrogens are added, and bonds are inferred. Sets
+/// His residues' variants, and removes the hydrogens of tautomers not chosen; this changes atom
+/// indices. Returns the changes applied, and the His tautomers.
+pub fn optimize_h_bond_network(mol: &mut Molecule) -> Vec<SidechainFlip> {
+    let groups = find_groups(mol);
+    if groups.is_empty() {
+        return Vec::new();
+    }
+
+    let mut pol = Polarity::new(mol);
+    let mut posits: Vec<_> = mol.atoms.iter().map(|a| a.posit).collect();
+
+    // Polar atoms near each group, outside its residue. This includes N atoms that may become
+    // acceptors, depending on His tautomers.
+    let envs: Vec<Vec<usize>> = groups
+        .iter()
+        .map(|g| {
+            let center = posits[g.axis.1];
+            let res_atoms = &mol.residues[g.res].atoms;
+            (0..mol.atoms.len())
+                .filter(|&j| {
+                    pol.donor[j].is_some()
+                        || matches!(mol.atoms[j].element, Element::Nitrogen | Element::Oxygen)
+                })
+                .filter(|j| !res_atoms.contains(j))
+                .filter(|&j| (posits[j] - center).magnitude() < ENV_DIST)
+                .collect()
+        })
+        .collect();
+
+    for group in &groups {
+        if let (Some(ring), Some(tautomer)) = (&group.his_ring, group.states[0].tautomer) {
+            set_tautomer(&mut pol, ring, tautomer);
+        }
+    }
+
+    let scores_init: Vec<_> = groups
+        .iter()
+        .zip(&envs)
+        .map(|(g, env)| state_score(&posits, &pol, g, env, g.states[0]))
+        .collect();
+
+    // Index into each group's states.
+    let mut current = vec![0; groups.len()];
+
+    for _ in 0..MAX_PASSES {
+        let mut changed = false;
+
+        for (i_g, (group, env)) in groups.iter().zip(&envs).enumerate() {
+            let state_current = group.states[current[i_g]];
+            let score_current = state_score(&posits, &pol, group, env, state_current);
+
+            let mut best = (current[i_g], score_current);
+            for (i_s, &state) in group.states.iter().enumerate() {
+                if i_s == current[i_g] {
+                    continue;
+                }
+
+                set_state(&mut posits, &mut pol, group, state_current, state);
+                let score = state_score(&posits, &pol, group, env, state);
+                set_state(&mut posits, &mut pol, group, state, state_current);
+
+                if score < best.1 - SCORE_IMPROVEMENT_MIN {
+                    best = (i_s, score);
+                }
+            }
+
+            if best.0 != current[i_g] {
+                set_state(
+                    &mut posits,
+                    &mut pol,
+                    group,
+                    state_current,
+                    group.states[best.0],
+                );
+                current[i_g] = best.0;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    for (atom, posit) in mol.atoms.iter_mut().zip(posits.iter()) {
+        atom.posit = *posit;
+    }
+
+    let result = groups
+        .iter()
+        .zip(&envs)
+        .enumerate()
+        .filter(|(i_g, (group, _))| current[*i_g] != 0 || group.his_ring.is_some())
+        .map(|(i_g, (group, env))| {
+            let state = group.states[current[i_g]];
+            SidechainFlip {
+                res: group.res,
+                kind: group.kind,
+                angle: state.angle,
+                tautomer: state.tautomer,
+                score_change: state_score(&posits, &pol, group, env, state) - scores_init[i_g],
+            }
+        })
+        .collect();
+
+    // Set the chosen tautomers, and remove hydrogens the others would have.
+    let mut remove = Vec::new();
+    for (i_g, group) in groups.iter().enumerate() {
+        let (Some(ring), Some(tautomer)) = (&group.his_ring, group.states[current[i_g]].tautomer)
+        else {
+            continue;
+        };
+
+        mol.residues[group.res].variant = Some(tautomer);
+        match tautomer {
+            Hid => remove.push(ring.he2),
+            Hie => remove.push(ring.hd1),
+            _ => (),
+        }
+    }
+    if !remove.is_empty() {
+        mol.remove_atoms(&remove);
+    }
+
+    result
+}
@@ -0,0 +1,542 @@
+//! Loop modeling: Find chain breaks, e.g. where residues are missing from the model, and build a
+//! closed backbone segment between the residues on either side of one (the anchors). The segment
+//! is grown from the N-terminal anchor with ideal geometry, then closed onto the C-terminal anchor
+//! using cyclic coordinate descent (CCD) over its φ and ψ angles. We try several starting
+//! conformations, and keep the closed one with the fewest clashes. Sidechains are then added one
+//! residue at a time, using the rotamer from the library that clashes least.
+
+use std::{f64::consts::TAU, io, io::ErrorKind, str::FromStr};
+
+use bio_files::ResidueType;
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::{
+    AminoAcid, AtomTypeInRes,
+    Element::{self, *},
+};
+
+use crate::{
+    aa_coords::{
+        PHI_SHEET,
+        bond_vecs::{
+            CALPHA_CP_BOND, LEN_CALPHA_CP, LEN_CP_N, LEN_CP_O, LEN_N_CALPHA, N_CALPHA_BOND,
+            residue_geometry,
+        },
+        build_peptide::{BackbonePreset, sidechain_atoms, sidechains_from_seq},
+        rotamers::rotamers,
+        sc_atom_placement::find_atom_placement,
+        sidechain::{Sidechain, set_χ},
+    },
+    analysis::ramachandran::PEPTIDE_BOND_MAX,
+    molecule::{Atom, AtomRole, Molecule, Residue},
+};
+
+// Radians. Ideal backbone bond angles, from Engh and Huber.
+const ANGLE_C_N_CA: f64 = 121.7 * TAU / 360.;
+const ANGLE_N_CA_C: f64 = 111.2 * TAU / 360.;
+const ANGLE_CA_C_N: f64 = 116.2 * TAU / 360.;
+/// Trans peptide bonds.
+const OMEGA: f64 = TAU / 2.;
+
+const CLOSURE_ITERS: usize = 500;
+/// Å. RMSD of the C-terminal anchor's backbone from where the loop places it.
+const CLOSURE_TOL: f64 = 0.05;
+/// Å. We reject loops that don't close better than this.
+const CLOSURE_MAX: f64 = 0.5;
+/// Each preset for the whole loop, then mixtures of them.
+const START_COUNT: usize = 6;
+
+/// Å. Heavy atoms in different residues closer than this clash.
+const CLASH_DIST: f64 = 3.;
+/// Loop residues at least this many apart are checked for backbone clashes with each other.
+const CLASH_MIN_SEP: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct ChainBreak {
+    pub chain_id: String,
+    /// Indices of the residues before and after the break.
+    pub res_before: usize,
+    pub res_after: usize,
+    pub serial_before: isize,
+    pub serial_after: isize,
+    /// Inferred from the serial numbers; 0 if these are consecutive.
+    pub missing: usize,
+    /// Å, between the C' before the break and the N after it.
+    pub gap: f64,
+}
+
+impl ChainBreak {
+    pub fn descrip(&self) -> String {
+        format!(
+            "{} {}–{}: {} missing, {:.1} Å",
+            self.chain_id, self.serial_before, self.serial_after, self.missing, self.gap
+        )
+    }
+}
+
+/// Atom indices of N, Cα, and C'.
+fn backbone(mol: &Molecule, res: usize) -> Option<[usize; 3]> {
+    let (mut n, mut ca, mut cp) = (None, None, None);
+
+    for &i in &mol.residues[res].atoms {
+        match mol.atoms[i].role {
+            Some(AtomRole::N_Backbone) => n = Some(i),
+            Some(AtomRole::C_Alpha) => ca = Some(i),
+            Some(AtomRole::C_Prime) => cp = Some(i),
+            _ => (),
+        }
+    }
+
+    Some([n?, ca?, cp?])
+}
+
+/// Find gaps between consecutive amino acid residues in each chain that are too long to be
+/// peptide bonds.
+pub fn find_chain_breaks(mol: &Molecule) -> Vec<ChainBreak> {
+    let mut result = Vec::new();
+
+    for chain in &mol.chains {
+        let mut prev: Option<(usize, [usize; 3])> = None;
+
+        for &r in &chain.residues {
+            if !matches!(mol.residues[r].res_type, ResidueType::AminoAcid(_)) {
+                continue;
+            }
+            let Some(bb) = backbone(mol, r) else {
+                continue;
+            };
+
+            if let Some((r_prev, bb_prev)) = prev {
+                let gap = (mol.atoms[bb[0]].posit - mol.atoms[bb_prev[2]].posit).magnitude();
+
+                if gap > PEPTIDE_BOND_MAX {
+                    let serial_before = mol.residues[r_prev].serial_number;
+                    let serial_after = mol.residues[r].serial_number;
+
+                    result.push(ChainBreak {
+                        chain_id: chain.id.clone(),
+                        res_before: r_prev,
+                        res_after: r,
+                        serial_before,
+                        serial_after,
+                        missing: (serial_after - serial_before - 1).max(0) as usize,
+                        gap,
+                    });
+                }
+            }
+            prev = Some((r, bb));
+        }
+    }
+
+    result
+}
+
+/// Place an atom bonded to `c`, from the two atoms before it: bond length `len`, angle b-c-d
+/// `angle`, and dihedral a-b-c-d `dihedral`.
+fn place(a: Vec3, b: Vec3, c: Vec3, len: f64, angle: f64, dihedral: f64) -> Vec3 {
+    let bc = (c - b).to_normalized();
+    let n = (b - a).cross(bc).to_normalized();
+    let m = n.cross(bc);
+
+    c + bc * (-len * angle.cos())
+        + m * (len * angle.sin() * dihedral.cos())
+        + n * (len * angle.sin() * dihedral.sin())
+}
+
+/// In the peptide plane, bisecting the outside of the Cα-C'-N angle.
+fn carbonyl_o(c_alpha: Vec3, cp: Vec3, n_next: Vec3) -> Vec3 {
+    let dir = ((cp - c_alpha).to_normalized() + (cp - n_next).to_normalized()).to_normalized();
+    cp + dir * LEN_CP_O
+}
+
+/// Starting (φ, ψ) for each residue, for closure attempt `start`.
+fn start_dihedrals(start: usize, len: usize) -> Vec<(f64, f64)> {
+    let presets = [
+        BackbonePreset::Ppii,
+        BackbonePreset::Sheet,
+        BackbonePreset::AlphaHelix,
+    ];
+
+    (0..len)
+        .map(|i| {
+            let preset = if start < presets.len() {
+                presets[start]
+            } else {
+                presets[(i + start) % presets.len()]
+            };
+            preset.dihedrals()
+        })
+        .collect()
+}
+
+/// Backbone positions: N, Cα and C' of the N-terminal anchor, of each loop residue, then of a
+/// copy of the C-terminal anchor, which closure moves onto the real one. `dihedrals` includes
+/// an entry for the copy.
+fn grow_backbone(anchor: [Vec3; 3], n_first: Vec3, dihedrals: &[(f64, f64)]) -> Vec<Vec3> {
+    let mut result = anchor.to_vec();
+    result.push(n_first);
+
+    for (k, (φ, ψ)) in dihedrals.iter().enumerate() {
+        let j = result.len();
+        let (ca_prev, cp_prev, n) = (result[j - 3], result[j - 2], result[j - 1]);
+
+        let ca = place(ca_prev, cp_prev, n, LEN_N_CALPHA, ANGLE_C_N_CA, OMEGA);
+        let cp = place(cp_prev, n, ca, LEN_CALPHA_CP, ANGLE_N_CA_C, *φ);
+        result.push(ca);
+        result.push(cp);
+
+        if k < dihedrals.len() - 1 {
+            result.push(place(n, ca, cp, LEN_CP_N, ANGLE_CA_C_N, *ψ));
+        }
+    }
+
+    result
+}
+
+/// Move the backbone's last three atoms onto `targets` by adjusting the anchor's ψ, each loop
+/// residue's φ and ψ, and the final residue's φ, which moves its C' only. Each step rotates a single
+/// torsion by the angle that minimizes the end atoms' squared distance from their targets.
+/// Returns the final RMSD, in Å.
+fn close(bb: &mut [Vec3], targets: [Vec3; 3]) -> f64 {
+    let len = bb.len();
+    let loop_len = len / 3 - 2;
+
+    // Indices of the first atom of each rotatable bond. Rotating it moves every atom after the
+    // bond.
+    let mut free = vec![1];
+    for k in 1..=loop_len {
+        free.push(3 * k);
+        free.push(3 * k + 1);
+    }
+    free.push(3 * (loop_len + 1));
+
+    let rmsd = |bb: &[Vec3]| {
+        let sum_sq: f64 = bb[len - 3..]
+            .iter()
+            .zip(&targets)
+            .map(|(p, t)| (*p - *t).magnitude_squared())
+            .sum();
+        (sum_sq / 3.).sqrt()
+    };
+
+    for _ in 0..CLOSURE_ITERS {
+        if rmsd(bb) < CLOSURE_TOL {
+            break;
+        }
+
+        for &j in &free {
+            let origin = bb[j];
+            let u = (bb[j + 1] - origin).to_normalized();
+
+            // See `backbone_edit::close_chain`.
+            let (mut num, mut den) = (0., 0.);
+            for (e, target) in bb[len - 3..].iter().zip(&targets) {
+                let m = *e - origin;
+                let r = m - u * m.dot(u);
+                let f = *target - origin;
+
+                num += f.dot(u.cross(r));
+                den += f.dot(r);
+            }
+
+            let rotator = Quaternion::from_axis_angle(u, num.atan2(den));
+            for p in &mut bb[j + 2..] {
+                *p = origin + rotator.rotate_vec(*p - origin);
+            }
+        }
+    }
+
+    rmsd(bb)
+}
+
+fn clash_score(posits: &[Vec3], env: &[Vec3]) -> f64 {
+    let mut result = 0.;
+    for p in posits {
+        for e in env {
+            let dist = (*p - *e).magnitude();
+            if dist < CLASH_DIST {
+                result += (CLASH_DIST - dist).powi(2);
+            }
+        }
+    }
+    result
+}
+
+/// Clashes of the loop backbone with its surroundings, and with itself.
+fn backbone_clash_score(bb: &[Vec3], env: &[Vec3]) -> f64 {
+    let loop_atoms = &bb[3..bb.len() - 3];
+    let mut result = clash_score(loop_atoms, env);
+
+    for (i, p) in loop_atoms.iter().enumerate() {
+        for (j, q) in loop_atoms.iter().enumerate().skip(i + 1) {
+            if j / 3 - i / 3 >= CLASH_MIN_SEP {
+                result += clash_score(&[*p], &[*q]);
+            }
+        }
+    }
+    result
+}
+
+/// Orthonormal axes, with x along `origin` → `b`, and `a` in the x-y plane.
+fn frame(a: Vec3, origin: Vec3, b: Vec3) -> [Vec3; 3] {
+    let x = (b - origin).to_normalized();
+    let y = a - origin;
+    let y = (y - x * y.dot(x)).to_normalized();
+    [x, y, x.cross(y)]
+}
+
+/// Sidechain heavy atoms, with default χ angles, attached to this backbone. Cβ is placed to give an
+/// L amino acid.
+fn place_sidechain(
+    sc: &Sidechain,
+    n: Vec3,
+    c_alpha: Vec3,
+    cp: Vec3,
+) -> Vec<(&'static str, Element, Vec3)> {
+    // Build it in a local frame, as in `build_peptide`.
+    let geom = residue_geometry();
+    let n_local = Vec3::new_zero();
+    let n_or = Quaternion::new_identity();
+    let cp_prev = n_local + n_or.rotate_vec(geom.n_cp) * LEN_CP_N;
+
+    let (ca_local, ca_or) = find_atom_placement(
+        n_or,
+        geom.calpha_n,
+        CALPHA_CP_BOND,
+        PHI_SHEET,
+        n_local,
+        cp_prev,
+        N_CALPHA_BOND,
+        LEN_N_CALPHA,
+    );
+
+    let atoms = sidechain_atoms(sc, ca_local, ca_or, n_local, geom);
+    let Some(&(_, _, cb_local)) = atoms.iter().find(|(name, _, _)| *name == "CB") else {
+        return Vec::new();
+    };
+
+    // Ideal Cβ position, from the backbone.
+    let b = c_alpha - n;
+    let c = cp - c_alpha;
+    let cb = c_alpha + b.cross(c) * -0.58273431 + b * 0.56802827 - c * 0.54067466;
+
+    let from = frame(n_local, ca_local, cb_local);
+    let to = frame(n, c_alpha, cb);
+
+    atoms
+        .into_iter()
+        .map(|(name, el, p)| {
+            let d = p - ca_local;
+            let posit =
+                c_alpha + to[0] * d.dot(from[0]) + to[1] * d.dot(from[1]) + to[2] * d.dot(from[2]);
+            (name, el, posit)
+        })
+        .collect()
+}
+
+/// Build residues with one-letter sequence `seq` into a chain break, closing the chain. Returns
+/// a new molecule with the loop inserted, and the closure RMSD in Å. The N-terminal anchor's
+/// carbonyl O is moved to match the new peptide bond; other existing atoms are unchanged.
+pub fn build_loop(mol: &Molecule, brk: &ChainBreak, seq: &str) -> io::Result<(Molecule, f64)> {
+    let sidechains = sidechains_from_seq(seq)?;
+    let loop_len = sidechains.len();
+
+    let err = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_owned());
+    let bb_before = backbone(mol, brk.res_before)
+        .ok_or_else(|| err("Missing backbone atoms before the break"))?;
+    let bb_after = backbone(mol, brk.res_after)
+        .ok_or_else(|| err("Missing backbone atoms after the break"))?;
+
+    let anchor = bb_before.map(|i| mol.atoms[i].posit);
+    let targets = bb_after.map(|i| mol.atoms[i].posit);
+    let o_before = mol.atom_in_res(brk.res_before, "O");
+
+    // Start with the peptide bond out of the anchor trans to its carbonyl O, if present.
+    let n_first = match o_before {
+        Some(o) => {
+            let o = mol.atoms[o].posit;
+            let dir = ((anchor[2] - anchor[1]).to_normalized() + (anchor[2] - o).to_normalized())
+                .to_normalized();
+            anchor[2] + dir * LEN_CP_N
+        }
+        None => {
+            let (_, ψ) = BackbonePreset::Ppii.dihedrals();
+            place(anchor[0], anchor[1], anchor[2], LEN_CP_N, ANGLE_CA_C_N, ψ)
+        }
+    };
+
+    // Heavy atoms near the break, other than the anchors'.
+    let center = (anchor[2] + targets[0]) / 2.;
+    let radius = (brk.gap + 3.8 * loop_len as f64) / 2. + 2. * CLASH_DIST;
+    let env: Vec<Vec3> = mol
+        .atoms
+        .iter()
+        .filter(|a| {
+            a.element != Hydrogen
+                && a.residue != Some(brk.res_before)
+                && a.residue != Some(brk.res_after)
+                && (a.posit - center).magnitude() < radius
+        })
+        .map(|a| a.posit)
+        .collect();
+
+    // (Not closed, clash score or RMSD), closure RMSD, backbone.
+    let mut best: Option<((bool, f64), f64, Vec<Vec3>)> = None;
+    for start in 0..START_COUNT {
+        let mut bb = grow_backbone(anchor, n_first, &start_dihedrals(start, loop_len + 1));
+        let rmsd = close(&mut bb, targets);
+
+        let score = if rmsd < CLOSURE_MAX {
+            (false, backbone_clash_score(&bb, &env))
+        } else {
+            (true, rmsd)
+        };
+
+        if best.as_ref().is_none_or(|(s, _, _)| score < *s) {
+            best = Some((score, rmsd, bb));
+        }
+    }
+    let (_, rmsd, bb) = best.unwrap();
+
+    if rmsd >= CLOSURE_MAX {
+        return Err(err(&format!(
+            "Unable to close the loop; closure RMSD {rmsd:.2} Å. The gap ({:.1} Å) may need more \
+            than {loop_len} residues.",
+            brk.gap
+        )));
+    }
+
+    // Grows as sidechains are placed.
+    let mut env_sc = env;
+
+    // Heavy atoms of each loop residue, by name.
+    let mut loop_res = Vec::with_capacity(loop_len);
+    for (k, sc) in sidechains.iter().enumerate() {
+        let i = 3 * (k + 1);
+        let (n, ca, cp) = (bb[i], bb[i + 1], bb[i + 2]);
+
+        let mut atoms = vec![
+            ("N", Nitrogen, n),
+            ("CA", Carbon, ca),
+            ("C", Carbon, cp),
+            ("O", Oxygen, carbonyl_o(ca, cp, bb[i + 3])),
+        ];
+        atoms.extend(place_sidechain(sc, n, ca, cp));
+
+        // The loop backbone, other than this residue's.
+        let mut env_other = env_sc.clone();
+        for (j, p) in bb[3..bb.len() - 3].iter().enumerate() {
+            if j / 3 != k {
+                env_other.push(*p);
+            }
+        }
+
+        let aa = sc.aa_type();
+        let mut best: Option<(f64, Vec<_>)> = None;
+        for rotamer in rotamers(aa) {
+            let χ: Vec<_> = rotamer.χ.iter().map(|a| a.to_radians()).collect();
+            let mut candidate = atoms.clone();
+            set_χ(&mut candidate, aa, &χ);
+
+            let sc_posits: Vec<_> = candidate[4..].iter().map(|a| a.2).collect();
+            let score = clash_score(&sc_posits, &env_other);
+
+            if best.as_ref().is_none_or(|(s, _)| score < *s) {
+                best = Some((score, candidate));
+            }
+        }
+        if let Some((_, candidate)) = best {
+            atoms = candidate;
+        }
+
+        // Its carbonyl O, and sidechain.
+        env_sc.extend(atoms[3..].iter().map(|a| a.2));
+        loop_res.push(atoms);
+    }
+
+    let mut atoms = mol.atoms.clone();
+    let mut residues = mol.residues.clone();
+    let mut chains = mol.chains.clone();
+
+    if let Some(o) = o_before {
+        atoms[o].posit = carbonyl_o(anchor[1], anchor[2], bb[3]);
+    }
+
+    // Insert the new residues after the N-terminal anchor, so residue order follows the chain.
+    let insert_at = brk.res_before + 1;
+    for atom in &mut atoms {
+        if let Some(r) = &mut atom.residue {
+            if *r >= insert_at {
+                *r += loop_len;
+            }
+        }
+    }
+    for chain in &mut chains {
+        for r in &mut chain.residues {
+            if *r >= insert_at {
+                *r += loop_len;
+            }
+        }
+    }
+
+    let mut serial_number = atoms.iter().map(|a| a.serial_number).max().unwrap_or(0);
+    let mut new_atoms = Vec::new();
+    let mut new_residues = Vec::with_capacity(loop_len);
+
+    for (k, (sc, res_atoms)) in sidechains.iter().zip(loop_res).enumerate() {
+        let res_i = insert_at + k;
+        let mut res = Residue {
+            serial_number: brk.serial_before + k as isize + 1,
+            res_type: ResidueType::AminoAcid(sc.aa_type()),
+            atoms: Vec::with_capacity(res_atoms.len()),
+            dihedral: None,
+            props: Default::default(),
+            variant: None,
+        };
+
+        for (name, element, posit) in res_atoms {
+            serial_number += 1;
+            res.atoms.push(atoms.len());
+            new_atoms.push(atoms.len());
+
+            atoms.push(Atom {
+                serial_number,
+                posit,
+                element,
+                type_in_res: AtomTypeInRes::from_str(name).ok(),
+                role: Some(AtomRole::from_name(name)),
+                residue: Some(res_i),
+                ..Default::default()
+            });
+        }
+        new_residues.push(res);
+    }
+
+    residues.splice(insert_at..insert_at, new_residues);
+
+    if let Some(chain) = chains
+        .iter_mut()
+        .find(|c| c.residues.contains(&brk.res_before))
+    {
+        let pos = chain
+            .residues
+            .iter()
+            .position(|r| *r == brk.res_before)
+            .unwrap();
+        chain
+            .residues
+            .splice(pos + 1..pos + 1, insert_at..insert_at + loop_len);
+        chain.atoms.extend(new_atoms);
+    }
+
+    let mut result = Molecule::new(
+        mol.ident.clone(),
+        atoms,
+        chains,
+        residues,
+        mol.pubchem_cid,
+        mol.drugbank_id.clone(),
+    );
+    result.metadata = mol.metadata.clone();
+
+    Ok((result, rmsd))
+}
@@ -1,9 +1,10 @@
 //! Adapted from `peptide`. Operations related to the geometry of atomic coordinates.
 
-use std::{f64::consts::TAU, fmt, fmt::Formatter, str::FromStr};
+use std::{f64::consts::TAU, str::FromStr};
 
 use bio_files::{BondGeneric, ResidueType};
 use lin_alg::f64::{Quaternion, Vec3, calc_dihedral_angle, calc_dihedral_angle_v2};
+use log::warn;
 use na_seq::{
     AtomTypeInRes, Element,
     Element::{Carbon, Hydrogen, Nitrogen, Oxygen},
@@ -12,16 +13,22 @@ use na_seq::{
 use crate::{
     aa_coords::{
         bond_vecs::{
-            LEN_C_H, LEN_CALPHA_H, LEN_N_H, LEN_O_H, PLANAR3_A, PLANAR3_B, PLANAR3_C, TETRA_A,
-            TETRA_B, TETRA_C, TETRA_D,
+            LEN_C_H, LEN_CALPHA_H, LEN_N_H, LEN_O_H, PLANAR3_A, TETRA_A, residue_geometry,
         },
         sidechain::Sidechain,
     },
     add_hydrogens::{BondGeometry, bonded_heavy_atoms, h_at_type_in_res},
-    molecule::{Atom, AtomRole},
+    molecule::{Atom, AtomRole, Molecule},
+    units::AngleUnit,
 };
 
+pub mod backbone_edit;
 pub mod bond_vecs;
+pub mod build_peptide;
+pub mod flips;
+pub mod loop_model;
+pub mod ring_closure;
+pub mod rotamers;
 pub mod sc_atom_placement;
 pub mod sidechain;
 
@@ -75,25 +82,44 @@ pub struct Dihedral {
     // pub dipole: Vec3,
 }
 
-impl fmt::Display for Dihedral {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+impl Dihedral {
+    /// The backbone angles, in the display unit.
+    pub fn descrip(&self, angle_unit: AngleUnit) -> String {
         let mut result = String::new();
 
-        // todo: Sort out the initial space on the first item.
-
-        if let Some(ω) = self.ω {
-            result = format!("  ω: {:.2}τ", ω / TAU) + " " + &result;
+        for (name, angle) in [("ω", self.ω), ("φ", self.φ), ("ψ", self.ψ)] {
+            if let Some(a) = angle {
+                if !result.is_empty() {
+                    result += "  ";
+                }
+                result += &format!("{name}: {}", angle_unit.fmt(a));
+            }
         }
 
-        if let Some(φ) = self.φ {
-            result += &format!("  φ: {:.2}τ", φ / TAU);
+        for (i, χ) in self.sidechain.χ_angles().into_iter().enumerate() {
+            result += &format!("  χ{}: {}", i + 1, angle_unit.fmt(χ));
         }
+        result
+    }
+}
 
-        if let Some(ψ) = self.ψ {
-            result += &format!("  ψ: {:.2}τ", ψ / TAU);
-        }
-        write!(f, "{result}")?;
-        Ok(())
+/// Measure each amino acid residue's χ angles from its atom positions, and store them in its
+/// `dihedral` field; the reverse of building sidechains from angles. This lets loaded structures
+/// be perturbed in dihedral space. Backbone angles are set by `aa_data_from_coords`.
+pub fn calc_sidechain_dihedrals(mol: &mut Molecule) {
+    for res_i in 0..mol.residues.len() {
+        let ResidueType::AminoAcid(aa) = mol.residues[res_i].res_type else {
+            continue;
+        };
+
+        let sidechain = Sidechain::from_coords(aa, |name| {
+            mol.atom_in_res(res_i, name).map(|i| mol.atoms[i].posit)
+        });
+
+        mol.residues[res_i]
+            .dihedral
+            .get_or_insert_with(Default::default)
+            .sidechain = sidechain;
     }
 }
 
@@ -109,7 +135,7 @@ pub fn tetra_atoms(atom_center: Vec3, atom_a: Vec3, atom_b: Vec3, atom_c: Vec3)
 
 /// Given the positions of two atoms of a tetrahedron, find the remaining two.
 /// `len` is the length between the center, and each apex.
-fn tetra_atoms_2(center: Vec3, atom_0: Vec3, atom_1: Vec3, len: f64) -> (Vec3, Vec3) {
+pub fn tetra_atoms_2(center: Vec3, atom_0: Vec3, atom_1: Vec3, len: f64) -> (Vec3, Vec3) {
     // Move from world-space to local.
     let bond_0 = (atom_0 - center).to_normalized();
     let bond_1 = (center - atom_1).to_normalized();
@@ -117,9 +143,10 @@ fn tetra_atoms_2(center: Vec3, atom_0: Vec3, atom_1: Vec3, len: f64) -> (Vec3, V
     // Aligns the tetrahedron leg A to bond 0.
     let rotator_a = Quaternion::from_unit_vecs(TETRA_A, bond_0);
 
-    // Once the TETRA_A is aligned to bond_0, rotate the tetrahedron around this until TETRA_B aligs
+    // Once the TETRA_A is aligned to bond_0, rotate the tetrahedron around this until its B leg aligs
     // with bond_1. Then, the other two tetra parts will be where we place our hydrogens.
-    let tetra_b_rotated = rotator_a.rotate_vec(unsafe { TETRA_B });
+    let geom = residue_geometry();
+    let tetra_b_rotated = rotator_a.rotate_vec(geom.tetra_b);
 
     let dihedral = calc_dihedral_angle(bond_0, tetra_b_rotated, bond_1);
 
@@ -127,12 +154,10 @@ fn tetra_atoms_2(center: Vec3, atom_0: Vec3, atom_1: Vec3, len: f64) -> (Vec3, V
 
     let rotator = rotator_b * rotator_a;
 
-    unsafe {
-        (
-            center + rotator.rotate_vec(TETRA_C) * len,
-            center + rotator.rotate_vec(TETRA_D) * len,
-        )
-    }
+    (
+        center + rotator.rotate_vec(geom.tetra_c) * len,
+        center + rotator.rotate_vec(geom.tetra_d) * len,
+    )
 }
 
 /// Find the position of the third planar (SP2) atom.
@@ -197,6 +222,8 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
     // Handle sidechains.
     // todo: If this algorithm proves general enough, perhaps apply it to the backbone as well (?)
 
+    let geom = residue_geometry();
+
     let h_default_sc = Atom {
         role: Some(AtomRole::H_Sidechain),
         ..h_default.clone()
@@ -219,7 +246,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
             Carbon => {
                 // todo: Handle O bonded (double bonds).
                 match atoms_bonded.len() {
-                    1 => unsafe {
+                    1 => {
                         // Methyl.
                         // todo: DRY with your Amine code below
                         let (bond_prev, bond_back2) =
@@ -235,7 +262,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                         // but needs an additional rotation around the bond vec axis.
                         let rotator_a = Quaternion::from_unit_vecs(TETRA_A, bond_prev);
 
-                        let tetra_rotated = rotator_a.rotate_vec(TETRA_B);
+                        let tetra_rotated = rotator_a.rotate_vec(geom.tetra_b);
                         let dihedral = calc_dihedral_angle(bond_prev, tetra_rotated, bond_back2);
 
                         // Offset; don't align; avoids steric hindrence.
@@ -243,7 +270,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                             Quaternion::from_axis_angle(bond_prev, -dihedral + TAU / 6.);
                         let rotator = rotator_b * rotator_a;
 
-                        for tetra_bond in [TETRA_B, TETRA_C, TETRA_D] {
+                        for tetra_bond in [geom.tetra_b, geom.tetra_c, geom.tetra_d] {
                             let at =
                                 h_at_type_in_res(Carbon, BondGeometry::Tetrahedral, neighbor_count);
                             hydrogens.push(Atom {
@@ -253,7 +280,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                                 ..h_default_sc.clone()
                             });
                         }
-                    },
+                    }
                     2 => {
                         let mut planar = false;
                         if atoms_bonded[0].1.element == Nitrogen
@@ -362,14 +389,14 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
             }
             Nitrogen => {
                 match atoms_bonded.len() {
-                    1 => unsafe {
+                    1 => {
                         // Add 2 H. (Amine)
                         // todo: DRY with methyl code above
                         let (bond_prev, bond_back2) =
                             match get_prev_bonds(atom, atoms, i, atoms_bonded[0]) {
                                 Ok(v) => v,
                                 Err(_) => {
-                                    eprintln!("Error: Could not find prev bonds on Amine");
+                                    warn!("Error: Could not find prev bonds on Amine");
                                     continue;
                                 }
                             };
@@ -378,13 +405,13 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                         // but needs an additional rotation around the bond vec axis.
                         let rotator_a = Quaternion::from_unit_vecs(PLANAR3_A, bond_prev);
 
-                        let planar_3_rotated = rotator_a.rotate_vec(PLANAR3_B);
+                        let planar_3_rotated = rotator_a.rotate_vec(geom.planar3_b);
                         let dihedral = calc_dihedral_angle(bond_prev, planar_3_rotated, bond_back2);
 
                         let rotator_b = Quaternion::from_axis_angle(bond_prev, -dihedral);
                         let rotator = rotator_b * rotator_a;
 
-                        for planar_bond in [PLANAR3_B, PLANAR3_C] {
+                        for planar_bond in [geom.planar3_b, geom.planar3_c] {
                             let at =
                                 h_at_type_in_res(Nitrogen, BondGeometry::Planar, neighbor_count);
                             hydrogens.push(Atom {
@@ -394,7 +421,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                                 ..h_default_sc.clone()
                             });
                         }
-                    },
+                    }
                     2 => {
                         // Add 1 H.
                         let bond_0 = atom.posit - atoms_bonded[0].1.posit;
@@ -413,7 +440,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
             }
             Oxygen => {
                 match atoms_bonded.len() {
-                    1 => unsafe {
+                    1 => {
                         // Hydroxyl. Add a single H with tetrahedral geometry.
                         // todo: The bonds are coming out right; not sure why.
                         // todo: This segment is DRY with 2+ sections above.
@@ -421,7 +448,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                             match get_prev_bonds(atom, atoms, i, atoms_bonded[0]) {
                                 Ok(v) => v,
                                 Err(_) => {
-                                    eprintln!("Error: Could not find prev bonds on Hydroxyl");
+                                    warn!("Error: Could not find prev bonds on Hydroxyl");
                                     continue;
                                 }
                             };
@@ -435,7 +462,7 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
 
                         let rotator_a = Quaternion::from_unit_vecs(TETRA_A, bond_prev);
 
-                        let tetra_rotated = rotator_a.rotate_vec(TETRA_B);
+                        let tetra_rotated = rotator_a.rotate_vec(geom.tetra_b);
                         let dihedral = calc_dihedral_angle(bond_prev, tetra_rotated, bond_back2);
 
                         // Offset; don't align; avoids steric hindrence.
@@ -446,12 +473,12 @@ fn add_h_sidechain(hydrogens: &mut Vec<Atom>, atoms: &[&Atom], h_default: &Atom)
                         let at =
                             h_at_type_in_res(Oxygen, BondGeometry::Tetrahedral, neighbor_count);
                         hydrogens.push(Atom {
-                            posit: atom.posit + rotator.rotate_vec(TETRA_B) * LEN_O_H,
+                            posit: atom.posit + rotator.rotate_vec(geom.tetra_b) * LEN_O_H,
                             type_in_res: Some(at),
                             // force_field_type: Some(name),
                             ..h_default_sc.clone()
                         });
-                    },
+                    }
                     _ => (),
                 }
             }
@@ -496,7 +523,7 @@ fn handle_backbone(
 
     let (Some(c_alpha_posit), Some(c_p_posit), Some(n_posit)) = (c_alpha_posit, c_p_posit, n_posit)
     else {
-        eprintln!("Error: Missing backbone atoms in coords.");
+        warn!("Error: Missing backbone atoms in coords.");
         return (dihedral, None);
     };
 
@@ -523,7 +550,7 @@ fn handle_backbone(
 
         // todo temp: C' Gly #154 is showing as the coords for Calpha.
         if dihedral.ω.unwrap().is_nan() {
-            println!("NAN: prev_cp: {cp_prev} prev_ca: {ca_prev}\n")
+            warn!("NAN: prev_cp: {cp_prev} prev_ca: {ca_prev}")
         }
 
         // Add a H to the backbone N. (Amine) Sp2/Planar.
@@ -549,7 +576,15 @@ fn handle_backbone(
     }
 
     if posits_sc.is_empty() {
-        // This generally means the residue is Glycine, which doesn't have a sidechain.
+        // This generally means the residue is Glycine, which doesn't have a sidechain. Its α carbon
+        // has two hydrogens.
+        let (h_0, h_1) = tetra_atoms_2(c_alpha_posit, n_posit, c_p_posit, LEN_CALPHA_H);
+        for posit in [h_0, h_1] {
+            hydrogens.push(Atom {
+                posit,
+                ..h_default.clone()
+            });
+        }
 
         // Note: This will also populate hydrogens on first and last backbones, and potentially
         // on residues that don't have roles marked.
@@ -617,6 +652,11 @@ pub fn aa_data_from_coords(
         occupancy: None,
         partial_charge: None,
         temperature_factor: None,
+        alt_loc: None,
+        anisou: None,
+        props: Default::default(),
+        in_ring: false,
+        aromatic: false,
     };
 
     // todo: Populate sidechain and main angles now based on coords. (?)
@@ -0,0 +1,317 @@
+//! Close sidechain rings. We place ring atoms one at a time, from generic bond vectors, so errors
+//! accumulate around the ring, and its last bond doesn't close. Here, we move the ring atoms until
+//! their bond lengths and angles match ideal values, by repeatedly projecting each onto its
+//! constraints, as SHAKE does. Aromatic rings are held in the plane set by χ2.
+
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::AminoAcid;
+
+use crate::aa_coords::bond_vecs::ResidueGeometry;
+
+const MAX_ITERS: usize = 2_000;
+/// Å. We stop once no atom moves more than this in an iteration.
+const CONVERGENCE_THRESH: f64 = 1e-6;
+
+/// How far we move atoms towards satisfying each constraint in an iteration. Less than 1 for angles,
+/// so bond lengths take precedence where a template's values aren't quite consistent.
+const STIFFNESS_BOND: f64 = 1.;
+const STIFFNESS_ANGLE: f64 = 0.5;
+
+/// Ideal ring geometry for a residue. Atoms are by PDB name.
+pub struct RingTemplate {
+    pub aa: AminoAcid,
+    /// Atoms the solver doesn't move come first, then the ones it does. For planar rings, the
+    /// fixed atoms are Cβ and Cγ, and the first moving one is the atom χ2 is measured to.
+    pub atoms: &'static [&'static str],
+    pub num_fixed: usize,
+    /// Bond lengths are from `ResidueGeometry::bond_len`.
+    pub bonds: &'static [(&'static str, &'static str)],
+    /// Degrees. The vertex is the middle atom.
+    pub angles: &'static [(&'static str, &'static str, &'static str, f64)],
+    pub planar: bool,
+}
+
+impl RingTemplate {
+    fn index(&self, name: &str) -> usize {
+        self.atoms.iter().position(|a| *a == name).unwrap()
+    }
+
+    /// The first two atoms bonded to this one; these define its local frame.
+    fn neighbors(&self, name: &str) -> (usize, usize) {
+        let mut result = self.bonds.iter().filter_map(|(a, b)| {
+            if *a == name {
+                Some(self.index(b))
+            } else if *b == name {
+                Some(self.index(a))
+            } else {
+                None
+            }
+        });
+
+        (result.next().unwrap(), result.next().unwrap())
+    }
+}
+
+// Angles are from Engh and Huber (1991). His uses the values for the doubly-protonated ring, which
+// match the bond lengths we use.
+
+pub const RING_PHE: RingTemplate = RingTemplate {
+    aa: AminoAcid::Phe,
+    atoms: &["CB", "CG", "CD1", "CE1", "CZ", "CE2", "CD2"],
+    num_fixed: 2,
+    bonds: &[
+        ("CB", "CG"),
+        ("CG", "CD1"),
+        ("CD1", "CE1"),
+        ("CE1", "CZ"),
+        ("CE2", "CZ"),
+        ("CD2", "CE2"),
+        ("CG", "CD2"),
+    ],
+    angles: &[
+        ("CB", "CG", "CD1", 120.7),
+        ("CB", "CG", "CD2", 120.7),
+        ("CD1", "CG", "CD2", 118.6),
+        ("CG", "CD1", "CE1", 120.7),
+        ("CD1", "CE1", "CZ", 120.7),
+        ("CE1", "CZ", "CE2", 120.0),
+        ("CZ", "CE2", "CD2", 120.7),
+        ("CE2", "CD2", "CG", 120.7),
+    ],
+    planar: true,
+};
+
+pub const RING_TYR: RingTemplate = RingTemplate {
+    aa: AminoAcid::Tyr,
+    atoms: &["CB", "CG", "CD1", "CE1", "CZ", "CE2", "CD2"],
+    num_fixed: 2,
+    bonds: RING_PHE.bonds,
+    angles: &[
+        ("CB", "CG", "CD1", 121.0),
+        ("CB", "CG", "CD2", 121.0),
+        ("CD1", "CG", "CD2", 118.1),
+        ("CG", "CD1", "CE1", 121.3),
+        ("CD1", "CE1", "CZ", 119.8),
+        ("CE1", "CZ", "CE2", 119.8),
+        ("CZ", "CE2", "CD2", 119.8),
+        ("CE2", "CD2", "CG", 121.3),
+    ],
+    planar: true,
+};
+
+pub const RING_HIS: RingTemplate = RingTemplate {
+    aa: AminoAcid::His,
+    atoms: &["CB", "CG", "ND1", "CE1", "NE2", "CD2"],
+    num_fixed: 2,
+    bonds: &[
+        ("CB", "CG"),
+        ("CG", "ND1"),
+        ("ND1", "CE1"),
+        ("CE1", "NE2"),
+        ("CD2", "NE2"),
+        ("CG", "CD2"),
+    ],
+    angles: &[
+        ("CB", "CG", "ND1", 122.7),
+        ("CB", "CG", "CD2", 131.2),
+        ("ND1", "CG", "CD2", 106.1),
+        ("CG", "ND1", "CE1", 109.3),
+        ("ND1", "CE1", "NE2", 108.4),
+        ("CE1", "NE2", "CD2", 109.0),
+        ("NE2", "CD2", "CG", 107.1),
+    ],
+    planar: true,
+};
+
+pub const RING_TRP: RingTemplate = RingTemplate {
+    aa: AminoAcid::Trp,
+    atoms: &[
+        "CB", "CG", "CD1", "NE1", "CE2", "CZ2", "CH2", "CZ3", "CE3", "CD2",
+    ],
+    num_fixed: 2,
+    bonds: &[
+        ("CB", "CG"),
+        ("CG", "CD1"),
+        ("CD1", "NE1"),
+        ("NE1", "CE2"),
+        ("CD2", "CE2"),
+        ("CD2", "CG"),
+        ("CE2", "CZ2"),
+        ("CZ2", "CH2"),
+        ("CH2", "CZ3"),
+        ("CZ3", "CE3"),
+        ("CE3", "CD2"),
+    ],
+    angles: &[
+        ("CB", "CG", "CD1", 127.0),
+        ("CB", "CG", "CD2", 126.6),
+        ("CD1", "CG", "CD2", 106.3),
+        ("CG", "CD1", "NE1", 110.1),
+        ("CD1", "NE1", "CE2", 109.0),
+        ("NE1", "CE2", "CD2", 107.3),
+        ("CE2", "CD2", "CG", 107.3),
+        ("NE1", "CE2", "CZ2", 130.4),
+        ("CD2", "CE2", "CZ2", 122.3),
+        ("CE2", "CZ2", "CH2", 117.4),
+        ("CZ2", "CH2", "CZ3", 121.6),
+        ("CH2", "CZ3", "CE3", 121.2),
+        ("CZ3", "CE3", "CD2", 118.6),
+        ("CE3", "CD2", "CE2", 118.8),
+        ("CE3", "CD2", "CG", 133.9),
+    ],
+    planar: true,
+};
+
+/// Pro's ring includes the backbone N and Cα. It isn't planar; its pucker comes from the starting
+/// positions. Cβ moves too, since the Pro N-Cα-Cβ angle is well below tetrahedral.
+pub const RING_PRO: RingTemplate = RingTemplate {
+    aa: AminoAcid::Pro,
+    atoms: &["N", "CA", "CB", "CG", "CD"],
+    num_fixed: 2,
+    bonds: &[
+        ("N", "CA"),
+        ("CA", "CB"),
+        ("CB", "CG"),
+        ("CG", "CD"),
+        ("CD", "N"),
+    ],
+    angles: &[
+        ("N", "CA", "CB", 103.0),
+        ("CA", "CB", "CG", 104.5),
+        ("CB", "CG", "CD", 106.1),
+        ("CG", "CD", "N", 103.2),
+        ("CD", "N", "CA", 112.0),
+    ],
+    planar: false,
+};
+
+/// Ring atom positions after closure, and the rotation each moving atom's local frame underwent.
+pub struct RingClosure {
+    template: &'static RingTemplate,
+    posits: Vec<Vec3>,
+    rotations: Vec<Quaternion>,
+}
+
+impl RingClosure {
+    /// The corrected position of a ring atom, and its orientation as placed, updated to match.
+    /// Atoms placed from this orientation afterwards, e.g. hydrogens, follow the ring.
+    pub fn atom(&self, name: &str, orientation: Quaternion) -> (Vec3, Quaternion) {
+        let i = self.template.index(name);
+        (self.posits[i], self.rotations[i] * orientation)
+    }
+}
+
+/// Solve for ring atom positions that match the template's ideal geometry. `posits` are the
+/// starting positions, in the template's atom order. Fixed atoms are returned unchanged.
+pub fn close_ring(
+    template: &'static RingTemplate,
+    posits: &[Vec3],
+    geom: &ResidueGeometry,
+) -> RingClosure {
+    let len = |a, b| geom.bond_len(template.aa, a, b);
+
+    // (atom 0, atom 1, distance, stiffness)
+    let mut constraints = Vec::new();
+    for &(a, b) in template.bonds {
+        constraints.push((
+            template.index(a),
+            template.index(b),
+            len(a, b),
+            STIFFNESS_BOND,
+        ));
+    }
+    // Represent each angle as the distance between its outer atoms.
+    for &(a, vertex, b, angle) in template.angles {
+        let (len_a, len_b) = (len(a, vertex), len(vertex, b));
+        let dist =
+            (len_a.powi(2) + len_b.powi(2) - 2. * len_a * len_b * angle.to_radians().cos()).sqrt();
+
+        constraints.push((template.index(a), template.index(b), dist, STIFFNESS_ANGLE));
+    }
+
+    let num_fixed = template.num_fixed;
+    let fixed = |i: usize| i < num_fixed;
+
+    // The plane containing Cβ, Cγ, and the χ2 atom, as placed.
+    let plane = if template.planar {
+        let (c_beta, c_gamma, χ2_atom) = (posits[0], posits[1], posits[num_fixed]);
+        Some((
+            c_gamma,
+            (c_gamma - c_beta).cross(χ2_atom - c_gamma).to_normalized(),
+        ))
+    } else {
+        None
+    };
+
+    let mut result = posits.to_vec();
+
+    for _ in 0..MAX_ITERS {
+        let prev = result.clone();
+
+        for &(i, j, dist, stiffness) in &constraints {
+            let diff = result[j] - result[i];
+            let dist_current = diff.magnitude();
+            let correction = diff * (stiffness * (dist_current - dist) / dist_current);
+
+            match (fixed(i), fixed(j)) {
+                (true, true) => (),
+                (true, false) => result[j] -= correction,
+                (false, true) => result[i] += correction,
+                (false, false) => {
+                    result[i] += correction * 0.5;
+                    result[j] -= correction * 0.5;
+                }
+            }
+        }
+
+        if let Some((point, normal)) = plane {
+            for posit in &mut result[num_fixed..] {
+                *posit -= normal * (*posit - point).dot(normal);
+            }
+        }
+
+        let moved_max = result
+            .iter()
+            .zip(&prev)
+            .map(|(a, b)| (*a - *b).magnitude())
+            .fold(0., f64::max);
+
+        if moved_max < CONVERGENCE_THRESH {
+            break;
+        }
+    }
+
+    let rotations = template
+        .atoms
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            if fixed(i) {
+                return Quaternion::new_identity();
+            }
+            let (n0, n1) = template.neighbors(name);
+            frame_rotation(
+                (posits[n0] - posits[i], posits[n1] - posits[i]),
+                (result[n0] - result[i], result[n1] - result[i]),
+            )
+        })
+        .collect();
+
+    RingClosure {
+        template,
+        posits: result,
+        rotations,
+    }
+}
+
+/// The rotation taking an atom's bonds to two neighbors before, to those after.
+fn frame_rotation(before: (Vec3, Vec3), after: (Vec3, Vec3)) -> Quaternion {
+    let axis = after.0.to_normalized();
+    let align_0 = Quaternion::from_unit_vecs(before.0.to_normalized(), axis);
+
+    // Then rotate around the first bond to align the second.
+    let perp = |v: Vec3| (v - axis * v.dot(axis)).to_normalized();
+    let align_1 = Quaternion::from_unit_vecs(perp(align_0.rotate_vec(before.1)), perp(after.1));
+
+    align_1 * align_0
+}
@@ -0,0 +1,163 @@
+//! A small backbone-independent rotamer library: modal χ angles of the most common rotamers of each
+//! amino acid, rounded from the Lovell et al. (2000) penultimate rotamer library. Names follow that
+//! library: p, t and m for χ near +60°, 180° and -60°, and a number for planar end groups.
+//!
+//! Angles are in degrees, in the order, and with the atom definitions, of `sidechain::χ_atoms`.
+//! Arg χ5 isn't included; the guanidinium group is planar.
+
+use na_seq::AminoAcid;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rotamer {
+    pub name: &'static str,
+    /// Degrees.
+    pub χ: &'static [f64],
+}
+
+const fn rot(name: &'static str, χ: &'static [f64]) -> Rotamer {
+    Rotamer { name, χ }
+}
+
+const SER: &[Rotamer] = &[rot("p", &[64.]), rot("t", &[178.]), rot("m", &[-65.])];
+
+const THR: &[Rotamer] = &[rot("p", &[59.]), rot("t", &[-171.]), rot("m", &[-61.])];
+
+const CYS: &[Rotamer] = &[rot("p", &[62.]), rot("t", &[-177.]), rot("m", &[-65.])];
+
+const VAL: &[Rotamer] = &[rot("p", &[63.]), rot("t", &[175.]), rot("m", &[-60.])];
+
+const LEU: &[Rotamer] = &[
+    rot("mt", &[-65., 175.]),
+    rot("tp", &[-172., 65.]),
+    rot("tt", &[-172., 145.]),
+    rot("mp", &[-85., 65.]),
+    rot("pp", &[62., 80.]),
+];
+
+const ILE: &[Rotamer] = &[
+    rot("mt", &[-65., 170.]),
+    rot("mm", &[-57., -60.]),
+    rot("pt", &[62., 170.]),
+    rot("tt", &[-177., 166.]),
+    rot("tp", &[-177., 66.]),
+    rot("mp", &[-65., 100.]),
+    rot("pp", &[62., 100.]),
+];
+
+const PHE_TYR: &[Rotamer] = &[
+    rot("m-85", &[-65., -85.]),
+    rot("t80", &[-177., 80.]),
+    rot("p90", &[62., 90.]),
+    rot("m-30", &[-65., -30.]),
+];
+
+const TRP: &[Rotamer] = &[
+    rot("m95", &[-65., 95.]),
+    rot("m-90", &[-65., -90.]),
+    rot("t-105", &[-177., -105.]),
+    rot("t90", &[-177., 90.]),
+    rot("p-90", &[62., -90.]),
+    rot("p90", &[62., 90.]),
+    rot("m0", &[-65., -5.]),
+];
+
+const HIS: &[Rotamer] = &[
+    rot("m-70", &[-65., -70.]),
+    rot("t-160", &[-177., -165.]),
+    rot("t-80", &[-177., -80.]),
+    rot("m170", &[-65., 165.]),
+    rot("t60", &[-177., 60.]),
+    rot("m80", &[-65., 80.]),
+    rot("p-80", &[62., -75.]),
+    rot("p80", &[62., 80.]),
+];
+
+const ASP: &[Rotamer] = &[
+    rot("m-20", &[-70., -15.]),
+    rot("t0", &[-177., 0.]),
+    rot("t70", &[-177., 65.]),
+    rot("p-10", &[62., -10.]),
+    rot("p30", &[62., 30.]),
+];
+
+const ASN: &[Rotamer] = &[
+    rot("m-20", &[-65., -20.]),
+    rot("m-80", &[-65., -75.]),
+    rot("t30", &[-177., 30.]),
+    rot("t-20", &[-174., -20.]),
+    rot("m120", &[-65., 120.]),
+    rot("p-10", &[62., -10.]),
+    rot("p30", &[62., 30.]),
+];
+
+const GLU: &[Rotamer] = &[
+    rot("mt-10", &[-65., 180., -10.]),
+    rot("tp10", &[-177., 65., 10.]),
+    rot("mm-40", &[-65., -65., -40.]),
+    rot("tt0", &[-177., 180., 0.]),
+    rot("pt-20", &[62., 180., -20.]),
+    rot("mp0", &[-65., 85., 0.]),
+];
+
+const GLN: &[Rotamer] = &[
+    rot("mt-30", &[-65., 180., -25.]),
+    rot("tp60", &[-177., 65., 60.]),
+    rot("mm-40", &[-65., -65., -40.]),
+    rot("tt0", &[-177., 180., 0.]),
+    rot("tp-100", &[-177., 65., -100.]),
+    rot("pt20", &[62., 180., 20.]),
+    rot("mm100", &[-65., -65., 100.]),
+];
+
+const MET: &[Rotamer] = &[
+    rot("mmm", &[-65., -65., -70.]),
+    rot("mtm", &[-65., 180., -75.]),
+    rot("mtp", &[-65., 180., 75.]),
+    rot("tpp", &[-177., 65., 75.]),
+    rot("ttm", &[-177., 180., -75.]),
+    rot("ttp", &[-177., 180., 75.]),
+    rot("ptm", &[62., 180., -75.]),
+];
+
+const LYS: &[Rotamer] = &[
+    rot("mttt", &[-65., 180., 180., 180.]),
+    rot("tttt", &[-177., 180., 180., 180.]),
+    rot("mtmt", &[-65., 180., -65., 180.]),
+    rot("mmtt", &[-62., -68., 180., 180.]),
+    rot("tptt", &[-177., 68., 180., 180.]),
+    rot("mttm", &[-65., 180., 180., -65.]),
+    rot("pttt", &[62., 180., 180., 180.]),
+];
+
+const ARG: &[Rotamer] = &[
+    rot("mtt180", &[-67., 180., 180., 180.]),
+    rot("mtt85", &[-67., 180., 180., 85.]),
+    rot("ttt180", &[-177., 180., 180., 180.]),
+    rot("mtp180", &[-67., 180., 65., 180.]),
+    rot("mmt-85", &[-62., -68., 180., -85.]),
+    rot("tpt170", &[-177., 65., 180., 170.]),
+    rot("ptt180", &[62., 180., 180., 180.]),
+];
+
+/// Rotamers, most common first. Empty for residues without rotatable sidechains.
+pub fn rotamers(aa: AminoAcid) -> &'static [Rotamer] {
+    match aa {
+        AminoAcid::Ser => SER,
+        AminoAcid::Thr => THR,
+        AminoAcid::Cys | AminoAcid::Sec => CYS,
+        AminoAcid::Val => VAL,
+        AminoAcid::Leu => LEU,
+        AminoAcid::Ile => ILE,
+        AminoAcid::Phe | AminoAcid::Tyr => PHE_TYR,
+        AminoAcid::Trp => TRP,
+        AminoAcid::His => HIS,
+        AminoAcid::Asp => ASP,
+        AminoAcid::Asn => ASN,
+        AminoAcid::Glu => GLU,
+        AminoAcid::Gln => GLN,
+        AminoAcid::Met => MET,
+        AminoAcid::Lys => LYS,
+        AminoAcid::Arg => ARG,
+        AminoAcid::Gly | AminoAcid::Ala | AminoAcid::Pro => &[],
+    }
+}
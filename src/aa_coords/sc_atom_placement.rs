@@ -4,7 +4,14 @@ use std::f64::consts::TAU;
 
 use lin_alg::f64::{Quaternion, Vec3};
 
-use crate::aa_coords::{bond_vecs::*, calc_dihedral_angle, sidechain::*};
+use na_seq::AminoAcid;
+
+use crate::aa_coords::{
+    bond_vecs::*,
+    calc_dihedral_angle,
+    ring_closure::{RING_HIS, RING_PHE, RING_PRO, RING_TRP, RING_TYR, close_ring},
+    sidechain::*,
+};
 
 /// Calculate the orientation, as a quaternion, and position, as a vector, of an atom, given the orientation of a
 /// previous atom, and the bond angle. `bond_to_prev_local` is the vector representing the bond to
@@ -77,7 +84,10 @@ impl Arg {
         c_alpha_orientation: Quaternion,
         // todo: Do we want our prev bond anchor to be n-calpha?
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsArg {
+        let len = |a, b| geom.bond_len(AminoAcid::Arg, a, b);
+
         // These are the angles between each of 2 4 equally-spaced atoms on a tetrahedron,
         // with center of (0., 0., 0.). They are the angle formed between 3 atoms.
         // We have chosen the two angles to describe the backbone. We have chosen these arbitrarily.
@@ -85,82 +95,82 @@ impl Arg {
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_3,
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "CD"),
         );
 
         let (n_eps, n_eps_orientation) = find_atom_placement(
             c_delta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             self.χ_4,
             c_delta,
             c_gamma,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CD", "NE"),
         );
 
         let (c_zeta, c_zeta_orientation) = find_atom_placement(
             n_eps_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             self.χ_5,
             n_eps,
             c_delta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("NE", "CZ"),
         );
 
         let (n_eta1, n_eta1_orientation) = find_atom_placement(
             c_zeta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_zeta,
             n_eps,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CZ", "NH1"),
         );
 
         let (n_eta2, n_eta2_orientation) = find_atom_placement(
             c_zeta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_zeta,
             n_eps,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CZ", "NH2"),
         );
 
         let (h_n_eps, _) = find_atom_placement(
@@ -170,7 +180,7 @@ impl Arg {
             TAU_DIV2,
             n_eps,
             c_delta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -181,7 +191,7 @@ impl Arg {
             TAU_DIV2,
             n_eta1,
             c_zeta,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_N_H,
         );
 
@@ -192,7 +202,7 @@ impl Arg {
             TAU_DIV2,
             n_eta1,
             c_zeta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -203,7 +213,7 @@ impl Arg {
             TAU_DIV2,
             n_eta2,
             c_zeta,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_N_H,
         );
 
@@ -214,7 +224,7 @@ impl Arg {
             TAU_DIV2,
             n_eta2,
             c_zeta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
         let (h_c_beta_a, _) = find_atom_placement(
@@ -224,7 +234,7 @@ impl Arg {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -235,7 +245,7 @@ impl Arg {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -246,7 +256,7 @@ impl Arg {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
         let (h_c_gamma_b, _) = find_atom_placement(
@@ -256,7 +266,7 @@ impl Arg {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -267,7 +277,7 @@ impl Arg {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -278,7 +288,7 @@ impl Arg {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -319,28 +329,30 @@ impl His {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsHis {
-        // todo: ring. Find planar bond angles.
+        let len = |a, b| geom.bond_len(AminoAcid::His, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         // todo: You can probably get a neater/cleaner ring setup by iterating from one to the next
@@ -348,47 +360,58 @@ impl His {
         let (c_delta1, c_delta1_orientation) = find_atom_placement(
             c_gamma_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             1.8849555, // tau / 2 - tau / 5 // todo: Not quite planar, but close.
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "CD2"),
         );
 
         let (n_delta2, n_delta2_orientation) = find_atom_placement(
             c_gamma_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             -1.8849555, // tau / 2 - tau / 5 // todo: Not quite planar, but close.
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
-            LEN_SC,
+            geom.tetra_c,
+            len("CG", "ND1"),
         );
 
         // todo: Is this right for N in a ring?
         let (n_eps1, n_eps1_orientation) = find_atom_placement(
             c_delta1_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             0.,
             c_delta1,
             c_gamma,
-            unsafe { RING5_BOND_OUT },
-            LEN_SC,
+            geom.ring5_bond_out,
+            len("CD2", "NE2"),
         );
 
         let (c_eps2, c_eps2_orientation) = find_atom_placement(
             n_delta2_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             0.,
             n_delta2,
             c_gamma,
-            unsafe { RING5_BOND_OUT },
-            LEN_SC,
+            geom.ring5_bond_out,
+            len("ND1", "CE1"),
+        );
+
+        // Forward kinematics leaves the ring open; close it.
+        let ring = close_ring(
+            &RING_HIS,
+            &[c_beta, c_gamma, n_delta2, c_eps2, n_eps1, c_delta1],
+            geom,
         );
+        let (n_delta2, n_delta2_orientation) = ring.atom("ND1", n_delta2_orientation);
+        let (c_eps2, c_eps2_orientation) = ring.atom("CE1", c_eps2_orientation);
+        let (n_eps1, n_eps1_orientation) = ring.atom("NE2", n_eps1_orientation);
+        let (c_delta1, c_delta1_orientation) = ring.atom("CD2", c_delta1_orientation);
 
         let (h_n_delta, _) = find_atom_placement(
             n_delta2_orientation,
@@ -397,7 +420,7 @@ impl His {
             TAU_DIV2,
             n_delta2,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -408,7 +431,7 @@ impl His {
         //     TAU_DIV2,
         //     n_eps1,
         //     c_delta1,
-        //     unsafe { PLANAR3_C },
+        //     geom.planar3_c,
         //     LEN_N_H,
         // );
 
@@ -419,7 +442,7 @@ impl His {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_N_H,
         );
 
@@ -430,7 +453,7 @@ impl His {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_N_H,
         );
 
@@ -441,7 +464,7 @@ impl His {
             TAU_DIV2,
             c_delta1,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -452,11 +475,9 @@ impl His {
             TAU_DIV2,
             c_eps2,
             n_delta2,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
-        // todo: These bond vecs are wrong! Needs to be tighter angles
-        // todo due to there only being 5 atoms in the ring.
 
         CoordsHis {
             c_beta,
@@ -493,7 +514,10 @@ impl Lys {
         c_alpha_orientation: Quaternion,
         // todo: Do we want our prev bond anchor to be n-calpha?
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsLys {
+        let len = |a, b| geom.bond_len(AminoAcid::Lys, a, b);
+
         // These are the angles between each of 2 4 equally-spaced atoms on a tetrahedron,
         // with center of (0., 0., 0.). They are the angle formed between 3 atoms.
         // We have chosen the two angles to describe the backbone. We have chosen these arbitrarily.
@@ -501,60 +525,60 @@ impl Lys {
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_3,
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "CD"),
         );
 
         let (c_eps, c_eps_orientation) = find_atom_placement(
             c_delta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_4,
             c_delta,
             c_gamma,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CD", "CE"),
         );
 
         let (n_zeta, n_zeta_orientation) = find_atom_placement(
             c_eps_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_eps,
             c_delta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CE", "NZ"),
         );
 
         let (h_n_zeta_a, _) = find_atom_placement(
@@ -564,7 +588,7 @@ impl Lys {
             TAU_DIV2,
             n_zeta,
             c_eps,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_N_H,
         );
 
@@ -575,7 +599,7 @@ impl Lys {
             TAU_DIV2,
             n_zeta,
             c_eps,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -586,7 +610,7 @@ impl Lys {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -597,7 +621,7 @@ impl Lys {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -608,7 +632,7 @@ impl Lys {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -619,7 +643,7 @@ impl Lys {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -630,7 +654,7 @@ impl Lys {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -641,7 +665,7 @@ impl Lys {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -652,7 +676,7 @@ impl Lys {
             TAU_DIV2,
             c_eps,
             c_delta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -663,7 +687,7 @@ impl Lys {
             TAU_DIV2,
             c_eps,
             c_delta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -701,53 +725,56 @@ impl Asp {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsAsp {
+        let len = |a, b| geom.bond_len(AminoAcid::Asp, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (o_delta1, o_delta1_orientation) = find_atom_placement(
             c_gamma_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CG", "OD1"),
         );
 
         let (o_delta2, o_delta2_orientation) = find_atom_placement(
             c_gamma_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CG", "OD2"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -757,7 +784,7 @@ impl Asp {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -768,7 +795,7 @@ impl Asp {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -794,66 +821,69 @@ impl Glu {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsGlu {
+        let len = |a, b| geom.bond_len(AminoAcid::Glu, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_3,
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "CD"),
         );
 
         let (o_eps1, o_eps1_orientation) = find_atom_placement(
             c_delta_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CD", "OE1"),
         );
 
         let (o_eps2, o_eps2_orientation) = find_atom_placement(
             c_delta_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CD", "OE2"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -863,7 +893,7 @@ impl Glu {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -874,7 +904,7 @@ impl Glu {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -885,7 +915,7 @@ impl Glu {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -896,7 +926,7 @@ impl Glu {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -926,27 +956,30 @@ impl Ser {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsSer {
+        let len = |a, b| geom.bond_len(AminoAcid::Ser, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (o_gamma, o_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "OG"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -956,7 +989,7 @@ impl Ser {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -967,7 +1000,7 @@ impl Ser {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -978,7 +1011,7 @@ impl Ser {
             TAU_DIV2,
             o_gamma,
             c_beta,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_O_H,
         );
 
@@ -1001,38 +1034,41 @@ impl Thr {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsThr {
+        let len = |a, b| geom.bond_len(AminoAcid::Thr, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma2, c_gamma2_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG2"),
         );
 
         let (o_gamma1, o_gamma1_orientation) = find_atom_placement(
             c_beta_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
-            LEN_SC,
+            geom.tetra_c,
+            len("CB", "OG1"),
         );
 
         let (h_c_beta, _) = find_atom_placement(
@@ -1042,7 +1078,7 @@ impl Thr {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1053,7 +1089,7 @@ impl Thr {
             TAU_DIV2,
             o_gamma1,
             c_beta,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             LEN_O_H,
         );
 
@@ -1064,7 +1100,7 @@ impl Thr {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -1075,7 +1111,7 @@ impl Thr {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1086,7 +1122,7 @@ impl Thr {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1113,49 +1149,52 @@ impl Asn {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsAsn {
+        let len = |a, b| geom.bond_len(AminoAcid::Asn, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (o_delta1, _) = find_atom_placement(
             c_gamma_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CG", "OD1"),
         );
 
         let (n_delta2, n_delta2_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CG", "ND2"),
         );
 
         let (h_n_delta_a, _) = find_atom_placement(
@@ -1165,7 +1204,7 @@ impl Asn {
             TAU_DIV2,
             n_delta2,
             c_gamma,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_N_H,
         );
 
@@ -1176,7 +1215,7 @@ impl Asn {
             TAU_DIV2,
             n_delta2,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -1187,7 +1226,7 @@ impl Asn {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_N_H,
         );
 
@@ -1198,7 +1237,7 @@ impl Asn {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_N_H,
         );
 
@@ -1225,60 +1264,63 @@ impl Gln {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsGln {
+        let len = |a, b| geom.bond_len(AminoAcid::Gln, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             self.χ_3,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CG", "CD"),
         );
 
         let (o_eps1, _) = find_atom_placement(
             c_delta_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CD", "OE1"),
         );
 
         let (n_eps2, n_eps2_orientation) = find_atom_placement(
             c_delta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CD", "NE2"),
         );
 
         let (h_n_eps_a, _) = find_atom_placement(
@@ -1288,7 +1330,7 @@ impl Gln {
             TAU_DIV2,
             n_eps2,
             c_delta,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_N_H,
         );
 
@@ -1299,7 +1341,7 @@ impl Gln {
             TAU_DIV2,
             n_eps2,
             c_delta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_N_H,
         );
 
@@ -1310,7 +1352,7 @@ impl Gln {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_N_H,
         );
 
@@ -1321,7 +1363,7 @@ impl Gln {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_N_H,
         );
 
@@ -1332,7 +1374,7 @@ impl Gln {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_N_H,
         );
 
@@ -1343,7 +1385,7 @@ impl Gln {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_N_H,
         );
 
@@ -1374,32 +1416,35 @@ impl Cys {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsCys {
+        let len = |a, b| geom.bond_len(AminoAcid::Cys, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (s_gamma, s_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             // todo: Using O bonds for S here. Is this right?
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "SG"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -1409,7 +1454,7 @@ impl Cys {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1420,7 +1465,7 @@ impl Cys {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1431,7 +1476,7 @@ impl Cys {
             TAU_DIV2,
             s_gamma,
             c_beta,
-            unsafe { O_BOND_OUT }, // todo: For S.
+            geom.o_bond_out, // todo: For S.
             LEN_C_H,
         );
 
@@ -1454,32 +1499,35 @@ impl Sec {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsSec {
+        let len = |a, b| geom.bond_len(AminoAcid::Sec, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (se_gamma, se_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             // todo: Using O bonds for S here. Is this right?
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "SE"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -1489,7 +1537,7 @@ impl Sec {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1500,7 +1548,7 @@ impl Sec {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1522,6 +1570,7 @@ impl Gly {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsGly {
         // H on the C alpha.
         let (h, _) = find_atom_placement(
@@ -1531,7 +1580,7 @@ impl Gly {
             TAU_DIV2,
             c_alpha,
             n_pos,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1545,40 +1594,49 @@ impl Pro {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsPro {
+        let len = |a, b| geom.bond_len(AminoAcid::Pro, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             0.,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             0.,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             0.,
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "CD"),
         );
 
+        // Forward kinematics leaves the ring open; close it onto the backbone N.
+        let ring = close_ring(&RING_PRO, &[n_pos, c_alpha, c_beta, c_gamma, c_delta], geom);
+        let (c_beta, c_beta_orientation) = ring.atom("CB", c_beta_orientation);
+        let (c_gamma, c_gamma_orientation) = ring.atom("CG", c_gamma_orientation);
+        let (c_delta, c_delta_orientation) = ring.atom("CD", c_delta_orientation);
+
         let (h_c_beta_a, _) = find_atom_placement(
             c_beta_orientation,
             H_BOND_IN,
@@ -1586,7 +1644,7 @@ impl Pro {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
         let (h_c_beta_b, _) = find_atom_placement(
@@ -1596,7 +1654,7 @@ impl Pro {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1607,7 +1665,7 @@ impl Pro {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
         let (h_c_gamma_b, _) = find_atom_placement(
@@ -1617,7 +1675,7 @@ impl Pro {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1628,7 +1686,7 @@ impl Pro {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
         let (h_c_delta_b, _) = find_atom_placement(
@@ -1638,7 +1696,7 @@ impl Pro {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1666,16 +1724,19 @@ impl Ala {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsAla {
+        let len = |a, b| geom.bond_len(AminoAcid::Ala, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -1685,7 +1746,7 @@ impl Ala {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -1696,7 +1757,7 @@ impl Ala {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1707,7 +1768,7 @@ impl Ala {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1727,38 +1788,41 @@ impl Val {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsVal {
+        let len = |a, b| geom.bond_len(AminoAcid::Val, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma1, c_gamma1_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG1"),
         );
 
         let (c_gamma2, c_gamma2_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
-            LEN_SC,
+            geom.tetra_c,
+            len("CB", "CG2"),
         );
 
         let (h_c_beta, _) = find_atom_placement(
@@ -1768,7 +1832,7 @@ impl Val {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1779,7 +1843,7 @@ impl Val {
             TAU_DIV2,
             c_gamma1,
             c_beta,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -1790,7 +1854,7 @@ impl Val {
             TAU_DIV2,
             c_gamma1,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1801,7 +1865,7 @@ impl Val {
             TAU_DIV2,
             c_gamma1,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1812,7 +1876,7 @@ impl Val {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -1823,7 +1887,7 @@ impl Val {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1834,7 +1898,7 @@ impl Val {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1863,50 +1927,53 @@ impl Ile {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsIle {
+        let len = |a, b| geom.bond_len(AminoAcid::Ile, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma1, c_gamma1_orientation) = find_atom_placement(
             // Non-continuing chain
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
-            LEN_SC,
+            geom.tetra_c,
+            len("CB", "CG2"),
         );
 
         let (c_gamma2, c_gamma2_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B }, // todo?
+            geom.tetra_b, // todo?
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG1"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma2_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG1", "CD1"),
         );
 
         let (h_c_beta, _) = find_atom_placement(
@@ -1916,7 +1983,7 @@ impl Ile {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1927,7 +1994,7 @@ impl Ile {
             TAU_DIV2,
             c_gamma1,
             c_beta,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -1938,7 +2005,7 @@ impl Ile {
             TAU_DIV2,
             c_gamma1,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1949,7 +2016,7 @@ impl Ile {
             TAU_DIV2,
             c_gamma1,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1960,7 +2027,7 @@ impl Ile {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -1971,7 +2038,7 @@ impl Ile {
             TAU_DIV2,
             c_gamma2,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -1982,7 +2049,7 @@ impl Ile {
             TAU_DIV2,
             c_delta,
             c_gamma2,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -1993,7 +2060,7 @@ impl Ile {
             TAU_DIV2,
             c_delta,
             c_gamma2,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2004,7 +2071,7 @@ impl Ile {
             TAU_DIV2,
             c_delta,
             c_gamma2,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2037,49 +2104,52 @@ impl Leu {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsLeu {
+        let len = |a, b| geom.bond_len(AminoAcid::Leu, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta1, c_delta1_orientation) = find_atom_placement(
             c_gamma_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "CD1"),
         );
 
         let (c_delta2, c_delta2_orientation) = find_atom_placement(
             c_gamma_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
-            LEN_SC,
+            geom.tetra_c,
+            len("CG", "CD2"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -2089,7 +2159,7 @@ impl Leu {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2100,7 +2170,7 @@ impl Leu {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2111,7 +2181,7 @@ impl Leu {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2122,7 +2192,7 @@ impl Leu {
             TAU_DIV2,
             c_delta1,
             c_gamma,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -2133,7 +2203,7 @@ impl Leu {
             TAU_DIV2,
             c_delta1,
             c_gamma,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2144,7 +2214,7 @@ impl Leu {
             TAU_DIV2,
             c_delta1,
             c_gamma,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2155,7 +2225,7 @@ impl Leu {
             TAU_DIV2,
             c_delta2,
             c_gamma,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -2166,7 +2236,7 @@ impl Leu {
             TAU_DIV2,
             c_delta2,
             c_gamma,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2177,7 +2247,7 @@ impl Leu {
             TAU_DIV2,
             c_delta2,
             c_gamma,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2210,52 +2280,55 @@ impl Met {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsMet {
+        let len = |a, b| geom.bond_len(AminoAcid::Met, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             // Use our info about the previous 2 atoms so we can define the dihedral angle properly.
             // (world space)
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (s_delta, s_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             // todo: Is this right for s? Tetra, line, or what?
-            PLANAR3_A,            // QC this for S
-            unsafe { PLANAR3_B }, // QC this for S
+            PLANAR3_A,      // QC this for S
+            geom.planar3_b, // QC this for S
             self.χ_3,
             c_gamma,
             c_beta,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CG", "SD"),
         );
 
         let (c_eps, c_eps_orientation) = find_atom_placement(
             s_delta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             TAU_DIV2,
             s_delta,
             c_gamma,
-            unsafe { PLANAR3_B }, // QC this for S
-            LEN_SC,
+            geom.planar3_b, // QC this for S
+            len("SD", "CE"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -2265,7 +2338,7 @@ impl Met {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2276,7 +2349,7 @@ impl Met {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2287,7 +2360,7 @@ impl Met {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2298,7 +2371,7 @@ impl Met {
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2309,7 +2382,7 @@ impl Met {
             TAU_DIV2,
             c_eps,
             s_delta,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             LEN_C_H,
         );
 
@@ -2320,7 +2393,7 @@ impl Met {
             TAU_DIV2,
             c_eps,
             s_delta,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2331,7 +2404,7 @@ impl Met {
             TAU_DIV2,
             c_eps,
             s_delta,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2362,86 +2435,101 @@ impl Phe {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsPhe {
+        let len = |a, b| geom.bond_len(AminoAcid::Phe, a, b);
+
         // todo: I think the RING6s you use here are equiv to the `PLANAR3` bonds
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
-            LEN_SC,
+            geom.tetra_c,
+            len("CB", "CG"),
         );
 
         let (c_delta1, c_delta1_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CG", "CD1"),
         );
 
         let (c_delta2, c_delta2_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CG", "CD2"),
         );
 
         let (c_eps1, c_eps1_orientation) = find_atom_placement(
             c_delta1_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_delta1,
             c_gamma,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CD1", "CE1"),
         );
 
         let (c_eps2, c_eps2_orientation) = find_atom_placement(
             c_delta2_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_delta2,
             c_gamma,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CD2", "CE2"),
         );
 
         // We anchor c_zeta off eps1.
         let (c_zeta, c_zeta_orientation) = find_atom_placement(
             c_eps1_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_eps1,
             c_delta1,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CE1", "CZ"),
         );
 
+        // Forward kinematics leaves the ring open; close it.
+        let ring = close_ring(
+            &RING_PHE,
+            &[c_beta, c_gamma, c_delta1, c_eps1, c_zeta, c_eps2, c_delta2],
+            geom,
+        );
+        let (c_delta1, c_delta1_orientation) = ring.atom("CD1", c_delta1_orientation);
+        let (c_eps1, c_eps1_orientation) = ring.atom("CE1", c_eps1_orientation);
+        let (c_zeta, c_zeta_orientation) = ring.atom("CZ", c_zeta_orientation);
+        let (c_eps2, c_eps2_orientation) = ring.atom("CE2", c_eps2_orientation);
+        let (c_delta2, c_delta2_orientation) = ring.atom("CD2", c_delta2_orientation);
+
         let (h_c_beta_a, _) = find_atom_placement(
             c_beta_orientation,
             H_BOND_IN,
@@ -2449,7 +2537,7 @@ impl Phe {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B }, // Non-standard B vice C here.
+            geom.tetra_b, // Non-standard B vice C here.
             LEN_C_H,
         );
 
@@ -2460,7 +2548,7 @@ impl Phe {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2471,7 +2559,7 @@ impl Phe {
             TAU_DIV2,
             c_delta1,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2482,7 +2570,7 @@ impl Phe {
             TAU_DIV2,
             c_delta2,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2493,7 +2581,7 @@ impl Phe {
             TAU_DIV2,
             c_eps1,
             c_delta1,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2504,7 +2592,7 @@ impl Phe {
             TAU_DIV2,
             c_eps2,
             c_delta2,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2515,7 +2603,7 @@ impl Phe {
             TAU_DIV2,
             c_zeta,
             c_eps2,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_C_H,
         );
 
@@ -2552,94 +2640,109 @@ impl Tyr {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsTyr {
+        let len = |a, b| geom.bond_len(AminoAcid::Tyr, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta1, c_delta1_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CG", "CD1"),
         );
 
         let (c_delta2, c_delta2_orientation) = find_atom_placement(
             c_gamma_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { PLANAR3_C },
-            LEN_SC,
+            geom.planar3_c,
+            len("CG", "CD2"),
         );
 
         let (c_eps1, c_eps1_orientation) = find_atom_placement(
             c_delta1_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_delta1,
             c_gamma,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CD1", "CE1"),
         );
 
         let (c_eps2, c_eps2_orientation) = find_atom_placement(
             c_delta2_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_delta2,
             c_gamma,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CD2", "CE2"),
         );
 
         // We anchor c_zeta off eps1.
         let (c_zeta, c_zeta_orientation) = find_atom_placement(
             c_eps1_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_eps1,
             c_delta1,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CE1", "CZ"),
         );
 
+        // Forward kinematics leaves the ring open; close it.
+        let ring = close_ring(
+            &RING_TYR,
+            &[c_beta, c_gamma, c_delta1, c_eps1, c_zeta, c_eps2, c_delta2],
+            geom,
+        );
+        let (c_delta1, c_delta1_orientation) = ring.atom("CD1", c_delta1_orientation);
+        let (c_eps1, c_eps1_orientation) = ring.atom("CE1", c_eps1_orientation);
+        let (c_zeta, c_zeta_orientation) = ring.atom("CZ", c_zeta_orientation);
+        let (c_eps2, c_eps2_orientation) = ring.atom("CE2", c_eps2_orientation);
+        let (c_delta2, c_delta2_orientation) = ring.atom("CD2", c_delta2_orientation);
+
         let (o_eta, o_eta_orientation) = find_atom_placement(
             c_zeta_orientation,
             O_BOND_IN,
-            unsafe { O_BOND_OUT },
+            geom.o_bond_out,
             TAU_DIV2,
             c_zeta,
             c_eps2,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CZ", "OH"),
         );
 
         let (h_c_beta_a, _) = find_atom_placement(
@@ -2649,7 +2752,7 @@ impl Tyr {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2660,7 +2763,7 @@ impl Tyr {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2671,7 +2774,7 @@ impl Tyr {
             TAU_DIV2,
             c_delta1,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2682,7 +2785,7 @@ impl Tyr {
             TAU_DIV2,
             c_delta2,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2693,7 +2796,7 @@ impl Tyr {
             TAU_DIV2,
             c_eps1,
             c_delta1,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2704,7 +2807,7 @@ impl Tyr {
             TAU_DIV2,
             c_eps2,
             c_delta2,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2715,7 +2818,7 @@ impl Tyr {
             TAU_DIV2,
             o_eta,
             c_zeta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_O_H,
         );
 
@@ -2754,118 +2857,138 @@ impl Trp {
         c_alpha: Vec3,
         c_alpha_orientation: Quaternion,
         n_pos: Vec3,
+        geom: &ResidueGeometry,
     ) -> CoordsTrp {
+        let len = |a, b| geom.bond_len(AminoAcid::Trp, a, b);
+
         let (c_beta, c_beta_orientation) = find_atom_placement(
             c_alpha_orientation,
             TETRA_A,
-            unsafe { TETRA_B },
+            geom.tetra_b,
             self.χ_1,
             c_alpha,
             n_pos,
-            unsafe { CALPHA_R_BOND },
-            LEN_SC,
+            geom.calpha_r,
+            len("CA", "CB"),
         );
 
         let (c_gamma, c_gamma_orientation) = find_atom_placement(
             c_beta_orientation,
             TETRA_A,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             self.χ_2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_B },
-            LEN_SC,
+            geom.tetra_b,
+            len("CB", "CG"),
         );
 
         let (c_delta, c_delta_orientation) = find_atom_placement(
             c_gamma_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             TAU_DIV2,
             c_gamma,
             c_beta,
-            unsafe { RING5_BOND_OUT },
-            LEN_SC,
+            geom.ring5_bond_out,
+            len("CG", "CD1"),
         );
 
         let (n_eps, n_eps_orientation) = find_atom_placement(
             c_delta_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             0.,
             c_delta,
             c_gamma,
-            unsafe { RING5_BOND_OUT },
-            LEN_SC,
+            geom.ring5_bond_out,
+            len("CD1", "NE1"),
         );
 
         // Between rings
         let (c_zeta, c_zeta_orientation) = find_atom_placement(
             n_eps_orientation,
             RING_BOND_IN,
-            unsafe { RING5_BOND_OUT },
+            geom.ring5_bond_out,
             0.,
             n_eps,
             c_delta,
-            unsafe { RING5_BOND_OUT },
-            LEN_SC,
+            geom.ring5_bond_out,
+            len("NE1", "CE2"),
         );
 
         // Between rings
         let (c_eta, c_eta_orientation) = find_atom_placement(
             c_zeta_orientation,
             RING_BOND_IN,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_zeta,
             n_eps,
-            unsafe { RING5_BOND_OUT },
-            LEN_SC,
+            geom.ring5_bond_out,
+            len("CE2", "CZ2"),
         );
 
         let (c_theta, c_theta_orientation) = find_atom_placement(
             c_eta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_eta,
             c_zeta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CZ2", "CH2"),
         );
 
         let (c_iota, c_iota_orientation) = find_atom_placement(
             c_theta_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_theta,
             c_eta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CH2", "CZ3"),
         );
 
         let (c_kappa, c_kappa_orientation) = find_atom_placement(
             c_iota_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             0.,
             c_iota,
             c_theta,
-            unsafe { PLANAR3_B },
-            LEN_SC,
+            geom.planar3_b,
+            len("CZ3", "CE3"),
         );
 
         let (c_lambda, c_lambda_orientation) = find_atom_placement(
             c_kappa_orientation,
             PLANAR3_A,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             TAU_DIV2,
             c_kappa,
             c_iota,
-            unsafe { PLANAR3_B },
-            LEN_SC,
-        );
+            geom.planar3_b,
+            len("CE3", "CD2"),
+        );
+
+        // Forward kinematics leaves the rings open; close them.
+        let ring = close_ring(
+            &RING_TRP,
+            &[
+                c_beta, c_gamma, c_delta, n_eps, c_zeta, c_eta, c_theta, c_iota, c_kappa, c_lambda,
+            ],
+            geom,
+        );
+        let (c_delta, c_delta_orientation) = ring.atom("CD1", c_delta_orientation);
+        let (n_eps, n_eps_orientation) = ring.atom("NE1", n_eps_orientation);
+        let (c_zeta, c_zeta_orientation) = ring.atom("CE2", c_zeta_orientation);
+        let (c_eta, c_eta_orientation) = ring.atom("CZ2", c_eta_orientation);
+        let (c_theta, c_theta_orientation) = ring.atom("CH2", c_theta_orientation);
+        let (c_iota, c_iota_orientation) = ring.atom("CZ3", c_iota_orientation);
+        let (c_kappa, c_kappa_orientation) = ring.atom("CE3", c_kappa_orientation);
+        let (c_lambda, c_lambda_orientation) = ring.atom("CD2", c_lambda_orientation);
 
         let (h_c_beta_a, _) = find_atom_placement(
             c_beta_orientation,
@@ -2874,7 +2997,7 @@ impl Trp {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_C },
+            geom.tetra_c,
             LEN_C_H,
         );
 
@@ -2885,7 +3008,7 @@ impl Trp {
             TAU_DIV2,
             c_beta,
             c_alpha,
-            unsafe { TETRA_D },
+            geom.tetra_d,
             LEN_C_H,
         );
 
@@ -2896,7 +3019,7 @@ impl Trp {
             TAU_DIV2,
             c_delta,
             c_gamma,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
 
@@ -2907,7 +3030,7 @@ impl Trp {
             TAU_DIV2,
             n_eps,
             c_delta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
         let (h_c_theta, _) = find_atom_placement(
@@ -2917,7 +3040,7 @@ impl Trp {
             TAU_DIV2,
             c_theta,
             c_eta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
         let (h_c_iota, _) = find_atom_placement(
@@ -2927,7 +3050,7 @@ impl Trp {
             TAU_DIV2,
             c_iota,
             c_theta,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
         let (h_c_kappa, _) = find_atom_placement(
@@ -2937,7 +3060,7 @@ impl Trp {
             TAU_DIV2,
             c_kappa,
             c_iota,
-            unsafe { PLANAR3_C },
+            geom.planar3_c,
             LEN_C_H,
         );
         let (h_c_lambda, _) = find_atom_placement(
@@ -2947,7 +3070,7 @@ impl Trp {
             TAU_DIV2,
             c_lambda,
             c_kappa,
-            unsafe { PLANAR3_B },
+            geom.planar3_b,
             LEN_C_H,
         );
 
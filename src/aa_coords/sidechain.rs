@@ -5,14 +5,11 @@
 // Don't show warnings for un`
 use std::{f64::consts::TAU, fmt};
 
-use lin_alg::f64::{Quaternion, Vec3};
-use na_seq::AminoAcid;
+use lin_alg::f64::{Quaternion, Vec3, calc_dihedral_angle_v2};
+use na_seq::{AminoAcid, Element};
 
 pub const TAU_DIV2: f64 = TAU / 2.;
 
-// todo: These are temp
-pub const LEN_SC: f64 = 1.53;
-
 pub const PRO_PHI_MIN: f64 = 4.83456;
 pub const PRO_PHI_MAX: f64 = 5.53269;
 
@@ -210,7 +207,133 @@ impl fmt::Display for Sidechain {
     }
 }
 
+/// The atoms defining each χ angle, by name in residue, in order from χ1. Where a branch makes the
+/// choice arbitrary, we use the lower-numbered atom, per IUPAC convention.
+pub fn χ_atoms(aa: AminoAcid) -> &'static [[&'static str; 4]] {
+    match aa {
+        AminoAcid::Arg => &[
+            ["N", "CA", "CB", "CG"],
+            ["CA", "CB", "CG", "CD"],
+            ["CB", "CG", "CD", "NE"],
+            ["CG", "CD", "NE", "CZ"],
+            ["CD", "NE", "CZ", "NH1"],
+        ],
+        AminoAcid::His => &[["N", "CA", "CB", "CG"], ["CA", "CB", "CG", "ND1"]],
+        AminoAcid::Lys => &[
+            ["N", "CA", "CB", "CG"],
+            ["CA", "CB", "CG", "CD"],
+            ["CB", "CG", "CD", "CE"],
+            ["CG", "CD", "CE", "NZ"],
+        ],
+        AminoAcid::Asp => &[["N", "CA", "CB", "CG"], ["CA", "CB", "CG", "OD1"]],
+        AminoAcid::Glu => &[
+            ["N", "CA", "CB", "CG"],
+            ["CA", "CB", "CG", "CD"],
+            ["CB", "CG", "CD", "OE1"],
+        ],
+        AminoAcid::Ser => &[["N", "CA", "CB", "OG"]],
+        AminoAcid::Thr => &[["N", "CA", "CB", "OG1"]],
+        AminoAcid::Asn => &[["N", "CA", "CB", "CG"], ["CA", "CB", "CG", "OD1"]],
+        AminoAcid::Gln => &[
+            ["N", "CA", "CB", "CG"],
+            ["CA", "CB", "CG", "CD"],
+            ["CB", "CG", "CD", "OE1"],
+        ],
+        AminoAcid::Cys => &[["N", "CA", "CB", "SG"]],
+        AminoAcid::Sec => &[["N", "CA", "CB", "SE"]],
+        AminoAcid::Val => &[["N", "CA", "CB", "CG1"]],
+        AminoAcid::Ile => &[["N", "CA", "CB", "CG1"], ["CA", "CB", "CG1", "CD1"]],
+        AminoAcid::Leu => &[["N", "CA", "CB", "CG"], ["CA", "CB", "CG", "CD1"]],
+        AminoAcid::Met => &[
+            ["N", "CA", "CB", "CG"],
+            ["CA", "CB", "CG", "SD"],
+            ["CB", "CG", "SD", "CE"],
+        ],
+        AminoAcid::Phe | AminoAcid::Tyr | AminoAcid::Trp => {
+            &[["N", "CA", "CB", "CG"], ["CA", "CB", "CG", "CD1"]]
+        }
+        AminoAcid::Gly | AminoAcid::Pro | AminoAcid::Ala => &[],
+    }
+}
+
+/// Position along the sidechain from the atom name's Greek letter: 1 for β, 2 for γ etc. 0 for
+/// backbone atoms.
+fn remoteness(name: &str) -> usize {
+    match name.chars().nth(1) {
+        Some('B') => 1,
+        Some('G') => 2,
+        Some('D') => 3,
+        Some('E') => 4,
+        Some('Z') => 5,
+        Some('H') => 6,
+        _ => 0,
+    }
+}
+
+/// Set χ angles, in radians, by rotating the atoms past each χ bond. `atoms` includes N and Cα.
+pub(super) fn set_χ(atoms: &mut [(&'static str, Element, Vec3)], aa: AminoAcid, χ: &[f64]) {
+    for (k, (names, target)) in χ_atoms(aa).iter().zip(χ).enumerate() {
+        let p: Option<Vec<Vec3>> = names
+            .iter()
+            .map(|n| atoms.iter().find(|a| a.0 == *n).map(|a| a.2))
+            .collect();
+        let Some(p) = p else {
+            continue;
+        };
+
+        let current = calc_dihedral_angle_v2(&(p[0], p[1], p[2], p[3]));
+        let rotator = Quaternion::from_axis_angle((p[2] - p[1]).to_normalized(), target - current);
+
+        for (name, _, posit) in atoms.iter_mut() {
+            if remoteness(name) > k + 1 {
+                *posit = p[1] + rotator.rotate_vec(*posit - p[1]);
+            }
+        }
+    }
+}
+
 impl Sidechain {
+    /// Construct an AA with χ angles measured from atom coordinates; the reverse of placing
+    /// sidechain atoms from angles. `posit` looks up an atom's position by name in residue. Angles
+    /// whose atoms are missing keep their defaults.
+    pub fn from_coords(aa_type: AminoAcid, posit: impl Fn(&str) -> Option<Vec3>) -> Self {
+        let mut result = Self::from_aa_type(aa_type);
+
+        for (i, names) in χ_atoms(aa_type).iter().enumerate() {
+            let Some(p) = names.iter().map(|n| posit(n)).collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let χ = calc_dihedral_angle_v2(&(p[0], p[1], p[2], p[3]));
+
+            let angle = match i {
+                0 => result.get_mut_χ1(),
+                1 => result.get_mut_χ2(),
+                2 => result.get_mut_χ3(),
+                3 => result.get_mut_χ4(),
+                _ => result.get_mut_χ5(),
+            };
+            if let Some(a) = angle {
+                *a = χ;
+            }
+        }
+
+        result
+    }
+
+    /// The χ angles this sidechain has, in order.
+    pub fn χ_angles(&self) -> Vec<f64> {
+        [
+            self.get_χ1(),
+            self.get_χ2(),
+            self.get_χ3(),
+            self.get_χ4(),
+            self.get_χ5(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
     /// Construct an AA (with default dihedral angles) from an amino acid type.
     pub fn from_aa_type(aa_type: AminoAcid) -> Self {
         match aa_type {
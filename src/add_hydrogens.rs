@@ -1,10 +1,27 @@
+//! Adds hydrogens to structures that lack them, e.g. most crystal structures. Amino acid residues
+//! are handled with the residue-aware geometry in `aa_coords`, and their hydrogens are named to
+//! match Amber's residue templates, so force field types and charges can be assigned. Small
+//! molecules are handled by valence, bond orders, and hybridization.
+
+use std::{f64::consts::TAU, str::FromStr};
+
+use lin_alg::f64::{Quaternion, Vec3, calc_dihedral_angle};
 use na_seq::{AtomTypeInRes, Element, Element::*};
 
 use crate::{
-    aa_coords::aa_data_from_coords,
-    molecule::{Atom, AtomRole, Molecule},
+    aa_coords::{
+        Hybridization, aa_data_from_coords,
+        bond_vecs::{LEN_C_H, LEN_N_H, LEN_O_H, PLANAR3_A, TETRA_A, residue_geometry},
+        tetra_atoms_2, tetra_legs,
+    },
+    molecule::{Atom, AtomRole, Bond, BondCount, BondType, Molecule},
 };
 
+/// Å
+const LEN_S_H: f64 = 1.34;
+/// Å. A hydrogen is assigned to the nearest heavy atom within this distance, for naming.
+const H_PARENT_DIST_MAX: f64 = 1.3;
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum BondGeometry {
     Planar,
@@ -73,8 +90,65 @@ pub fn bonded_heavy_atoms<'a>(atoms_bonded: &'a [(usize, &'a Atom)]) -> Vec<&'a
     atoms_bonded.iter().map(|(_, a)| *a).collect()
 }
 
+/// Name hydrogens added to a residue after the heavy atom each is bonded to, using the
+/// Amber/PDB convention: e.g. "HA" on "CA", "HB2" and "HB3" on "CB", "HD11" - "HD13" on "CD1". These
+/// names are how we look up force field types and charges in the Amber residue templates.
+pub fn name_hydrogens(hydrogens: &mut [Atom], res_atoms: &[&Atom]) {
+    // Index into `res_atoms` of each hydrogen's parent.
+    let parents: Vec<Option<usize>> = hydrogens
+        .iter()
+        .map(|h| {
+            res_atoms
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.element != Hydrogen)
+                .map(|(i, a)| (i, (a.posit - h.posit).magnitude()))
+                .filter(|(_, dist)| *dist < H_PARENT_DIST_MAX)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+        })
+        .collect();
+
+    for (parent_i, parent) in res_atoms.iter().enumerate() {
+        let on_parent: Vec<usize> = parents
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| **p == Some(parent_i))
+            .map(|(h_i, _)| h_i)
+            .collect();
+
+        let Some(parent_name) = &parent.type_in_res else {
+            continue;
+        };
+        if on_parent.is_empty() {
+            continue;
+        }
+
+        // Remove the element, e.g. "CB" -> "B", "OG1" -> "G1", "N" -> "".
+        let parent_name = parent_name.to_string();
+        let suffix = parent_name.get(1..).unwrap_or_default();
+
+        // Methylene hydrogens are numbered from 2, e.g. "HB2", "HB3", or "HG12", "HG13" on Ile's
+        // CG1. Amine hydrogens from 1, e.g. "HD21", "HD22" on Asn's ND2.
+        let first = match on_parent.len() {
+            1 => None,
+            2 if parent.element == Carbon => Some(2),
+            _ => Some(1),
+        };
+
+        for (n, h_i) in on_parent.iter().enumerate() {
+            let name = match first {
+                None => format!("H{suffix}"),
+                Some(first) => format!("H{suffix}{}", first + n),
+            };
+            hydrogens[*h_i].type_in_res = AtomTypeInRes::from_str(&name).ok();
+        }
+    }
+}
+
 impl Molecule {
-    /// Adds hydrogens, and populdates residue dihedral angles.
+    /// Adds hydrogens, and populdates residue dihedral angles. Residues that already have hydrogens,
+    /// e.g. from the file, are left as they are.
     pub fn populate_hydrogens_angles(&mut self) {
         // todo: Move this fn to this module? Split this and its diehdral component, or not?
 
@@ -105,9 +179,14 @@ impl Molecule {
             }
 
             // if let ResidueType::AminoAcid(aa) = &res.res_type {
-            let (dihedral, hydrogens, this_cp_ca) =
+            let (dihedral, mut hydrogens, this_cp_ca) =
                 aa_data_from_coords(&atoms, &res.res_type, res_i, prev_cp_ca, n_next_pos);
 
+            if atoms.iter().any(|a| a.element == Hydrogen) {
+                hydrogens.clear();
+            }
+            name_hydrogens(&mut hydrogens, &atoms);
+
             for h in hydrogens {
                 self.atoms.push(h);
                 res.atoms.push(self.atoms.len() - 1);
@@ -120,3 +199,206 @@ impl Molecule {
         }
     }
 }
+
+/// The number of bonds, counting bond order, an uncharged atom of this element usually forms.
+/// `None` for elements we don't add hydrogens to.
+fn valence(el: Element) -> Option<u8> {
+    match el {
+        Carbon => Some(4),
+        Nitrogen => Some(3),
+        Oxygen | Sulfur => Some(2),
+        _ => None,
+    }
+}
+
+pub fn len_h(el: Element) -> f64 {
+    match el {
+        Nitrogen => LEN_N_H,
+        Oxygen => LEN_O_H,
+        Sulfur => LEN_S_H,
+        _ => LEN_C_H,
+    }
+}
+
+/// Rotate template bond vectors so that `template_a` points along `bond`, and `template_b` is at
+/// dihedral angle `offset` from `bond_back`, the bond one step farther along.
+pub fn align_template(
+    template_a: Vec3,
+    template_b: Vec3,
+    bond: Vec3,
+    bond_back: Vec3,
+    offset: f64,
+) -> Quaternion {
+    let rotator_a = Quaternion::from_unit_vecs(template_a, bond);
+
+    let rotated = rotator_a.rotate_vec(template_b);
+    let dihedral = calc_dihedral_angle(bond, rotated, bond_back);
+
+    Quaternion::from_axis_angle(bond, -dihedral + offset) * rotator_a
+}
+
+impl Molecule {
+    /// Add hydrogens to heavy atoms with fewer bonds than their usual valence, taking bond orders
+    /// into account. For small molecules, e.g. ligands from files without hydrogens. Formal charges
+    /// aren't considered, e.g. amines are added as neutral. Returns the number of hydrogens added.
+    pub fn add_hydrogens_ligand(&mut self) -> usize {
+        let adj = self.build_adjacency_list();
+
+        // Sum of bond orders, and the highest bond order, by atom.
+        let mut order_sum = vec![0.; self.atoms.len()];
+        let mut order_max = vec![BondCount::Single; self.atoms.len()];
+        let mut double_count = vec![0; self.atoms.len()];
+
+        for bond in &self.bonds {
+            let BondType::Covalent { count } = bond.bond_type else {
+                continue;
+            };
+            for i in [bond.atom_0, bond.atom_1] {
+                order_sum[i] += count.value();
+                if count.value() > order_max[i].value() {
+                    order_max[i] = count;
+                }
+                if count == BondCount::Double {
+                    double_count[i] += 1;
+                }
+            }
+        }
+
+        // (Parent index, posit)
+        let mut added = Vec::new();
+
+        for (i, atom) in self.atoms.iter().enumerate() {
+            let Some(valence) = valence(atom.element) else {
+                continue;
+            };
+
+            let h_count = (valence as f64 - (order_sum[i] + 0.01).floor()).max(0.) as usize;
+            if h_count == 0 || adj[i].is_empty() {
+                continue;
+            }
+
+            let hybridization = if order_max[i] == BondCount::Triple || double_count[i] >= 2 {
+                Hybridization::Sp
+            } else if order_max[i] != BondCount::Single {
+                Hybridization::Sp2
+            } else {
+                Hybridization::Sp3
+            };
+
+            // Unit vectors from this atom to its neighbors.
+            let bonds: Vec<Vec3> = adj[i]
+                .iter()
+                .map(|j| (self.atoms[*j].posit - atom.posit).to_normalized())
+                .collect();
+
+            // For atoms with a single neighbor, orient the hydrogens relative to a bond one step
+            // farther along, e.g. staggered for a methyl group.
+            let bond_back = || {
+                let j = adj[i][0];
+                match adj[j].iter().find(|k| **k != i) {
+                    Some(k) => (self.atoms[j].posit - self.atoms[*k].posit).to_normalized(),
+                    // Arbitrary, but not parallel to the bond.
+                    None if bonds[0].x.abs() < 0.9 => Vec3::new(1., 0., 0.),
+                    None => Vec3::new(0., 1., 0.),
+                }
+            };
+
+            let len = len_h(atom.element);
+            let geom = residue_geometry();
+
+            let dirs: Vec<Vec3> = match (hybridization, bonds.len()) {
+                (Hybridization::Sp, 1) => vec![-bonds[0]],
+                (Hybridization::Sp2, 1) => {
+                    let rotator =
+                        align_template(PLANAR3_A, geom.planar3_b, bonds[0], bond_back(), 0.);
+                    vec![
+                        rotator.rotate_vec(geom.planar3_b),
+                        rotator.rotate_vec(geom.planar3_c),
+                    ]
+                }
+                (Hybridization::Sp2, 2) => vec![(-(bonds[0] + bonds[1])).to_normalized()],
+                (Hybridization::Sp3, 1) => {
+                    // Offset; don't align; avoids steric hindrence.
+                    let rotator =
+                        align_template(TETRA_A, geom.tetra_b, bonds[0], bond_back(), TAU / 6.);
+                    [geom.tetra_b, geom.tetra_c, geom.tetra_d]
+                        .iter()
+                        .map(|b| rotator.rotate_vec(*b))
+                        .collect()
+                }
+                (Hybridization::Sp3, 2) => {
+                    let (h_0, h_1) = tetra_atoms_2(
+                        atom.posit,
+                        self.atoms[adj[i][0]].posit,
+                        self.atoms[adj[i][1]].posit,
+                        1.,
+                    );
+                    vec![h_0 - atom.posit, h_1 - atom.posit]
+                }
+                (Hybridization::Sp3, 3) => vec![tetra_legs(bonds[0], bonds[1], bonds[2])],
+                _ => Vec::new(),
+            };
+
+            for dir in dirs.into_iter().take(h_count) {
+                added.push((i, atom.posit + dir * len));
+            }
+        }
+
+        let mut sn = self
+            .atoms
+            .iter()
+            .map(|a| a.serial_number)
+            .max()
+            .unwrap_or(0);
+
+        for (parent_i, posit) in &added {
+            let parent = &self.atoms[*parent_i];
+            sn += 1;
+
+            let h = Atom {
+                serial_number: sn,
+                posit: *posit,
+                element: Hydrogen,
+                type_in_res: None,
+                force_field_type: None,
+                dock_type: None,
+                role: None,
+                residue: parent.residue,
+                hetero: parent.hetero,
+                occupancy: None,
+                partial_charge: None,
+                temperature_factor: None,
+                alt_loc: None,
+                anisou: None,
+                props: Default::default(),
+                in_ring: false,
+                aromatic: false,
+            };
+            let h_i = self.atoms.len();
+
+            if let Some(res_i) = h.residue {
+                if let Some(res) = self.residues.get_mut(res_i) {
+                    res.atoms.push(h_i);
+                }
+            }
+
+            self.atoms.push(h);
+            self.bonds.push(Bond {
+                bond_type: BondType::Covalent {
+                    count: BondCount::Single,
+                },
+                atom_0: *parent_i,
+                atom_1: h_i,
+                is_backbone: false,
+                in_ring: false,
+                aromatic: false,
+            });
+        }
+
+        if !added.is_empty() {
+            self.adjacency_list = self.build_adjacency_list();
+        }
+
+        added.len()
+    }
+}
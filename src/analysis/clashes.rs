@@ -0,0 +1,90 @@
+//! Steric clashes: pairs of atoms that aren't bonded, but whose Van der Waals spheres overlap
+//! substantially. These indicate modeling errors, e.g. from hydrogen placement, mutation, or
+//! manual backbone edits.
+
+use std::collections::HashSet;
+
+use bio_files::ResidueType;
+use rayon::prelude::*;
+
+use crate::{molecule::Molecule, util::setup_neighbor_pairs};
+
+/// Å. Overlaps of at least this are clashes. This is the threshold MolProbity uses.
+pub const CLASH_OVERLAP_MIN: f64 = 0.4;
+
+/// Å. Must be at least the largest sum of two VdW radii, less the minimum overlap.
+const GRID_SIZE: f64 = 4.;
+
+#[derive(Clone, Debug)]
+pub struct Clash {
+    /// Atom indices.
+    pub atom_0: usize,
+    pub atom_1: usize,
+    /// Å. The sum of VdW radii, less the distance between atoms.
+    pub overlap: f64,
+}
+
+/// Finds all clashes, sorted by descending overlap. Excludes atoms bonded to each other (1-2), or
+/// to a common atom (1-3), and hydrogen-bonded pairs, whose close approach is expected.
+pub fn find_clashes(mol: &Molecule) -> Vec<Clash> {
+    let posits: Vec<_> = mol.atoms.iter().map(|a| &a.posit).collect();
+    let indices: Vec<_> = (0..mol.atoms.len()).collect();
+
+    let h_bonded: HashSet<(usize, usize)> = mol
+        .bonds_hydrogen
+        .iter()
+        .flat_map(|b| [(b.donor, b.acceptor), (b.hydrogen, b.acceptor)])
+        .map(|(i, j)| (i.min(j), i.max(j)))
+        .collect();
+
+    let adj = &mol.adjacency_list;
+    let excluded = |i: usize, j: usize| {
+        let (Some(nbrs_i), Some(nbrs_j)) = (adj.get(i), adj.get(j)) else {
+            return false;
+        };
+        nbrs_i.contains(&j) || nbrs_i.iter().any(|n| nbrs_j.contains(n))
+    };
+
+    let mut result: Vec<_> = setup_neighbor_pairs(&posits, &indices, GRID_SIZE)
+        .into_par_iter()
+        .filter_map(|(i, j)| {
+            let (a_0, a_1) = (&mol.atoms[i], &mol.atoms[j]);
+            let radii = (a_0.element.vdw_radius() + a_1.element.vdw_radius()) as f64;
+            let overlap = radii - (a_0.posit - a_1.posit).magnitude();
+
+            if overlap < CLASH_OVERLAP_MIN || excluded(i, j) || h_bonded.contains(&(i, j)) {
+                return None;
+            }
+
+            Some(Clash {
+                atom_0: i,
+                atom_1: j,
+                overlap,
+            })
+        })
+        .collect();
+
+    result.sort_by(|a, b| b.overlap.total_cmp(&a.overlap));
+    result
+}
+
+/// E.g. "45 Leu CD1".
+pub fn atom_label(mol: &Molecule, i: usize) -> String {
+    let atom = &mol.atoms[i];
+
+    let name = match &atom.type_in_res {
+        Some(t) => t.to_string(),
+        None => atom.element.to_letter(),
+    };
+
+    let Some(res) = atom.residue.and_then(|r| mol.residues.get(r)) else {
+        return format!("#{} {name}", atom.serial_number);
+    };
+
+    let res_name = match &res.res_type {
+        ResidueType::AminoAcid(aa) => aa.to_string(),
+        ResidueType::Water => "Water".to_owned(),
+        ResidueType::Other(n) => n.clone(),
+    };
+    format!("{} {res_name} {name}", res.serial_number)
+}
@@ -0,0 +1,119 @@
+//! Residue-residue distance matrices, and contact maps derived from them. These show secondary
+//! structure (helices along the diagonal, sheets as off-diagonal bands), and domain packing.
+
+use std::fmt;
+
+use bio_files::ResidueType;
+use lin_alg::f64::Vec3;
+use na_seq::Element;
+use rayon::prelude::*;
+
+use crate::molecule::{AtomRole, Molecule};
+
+/// Å. Conventional contact cutoffs for each mode.
+pub const CONTACT_THRESH_CA: f32 = 8.;
+pub const CONTACT_THRESH_MIN_ATOM: f32 = 4.5;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum ContactMode {
+    /// Distance between alpha carbons.
+    #[default]
+    CAlpha,
+    /// Minimum distance between any two heavy atoms.
+    MinAtom,
+}
+
+impl fmt::Display for ContactMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::CAlpha => "Cα-Cα",
+            Self::MinAtom => "Min atom",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ContactMap {
+    pub mode: ContactMode,
+    /// Residue indices, in the molecule, of each row and column.
+    pub residues: Vec<usize>,
+    /// Å. Row-major; `residues.len()` on each side.
+    pub dists: Vec<f32>,
+}
+
+impl ContactMap {
+    /// Computes distances between all pairs of amino acid residues. Residues without a Cα are
+    /// skipped in Cα mode.
+    pub fn new(mol: &Molecule, mode: ContactMode) -> Self {
+        // Position sets per residue: the Cα, or all heavy atoms.
+        let mut residues = Vec::new();
+        let mut posits: Vec<Vec<Vec3>> = Vec::new();
+
+        for (i, res) in mol.residues.iter().enumerate() {
+            if !matches!(res.res_type, ResidueType::AminoAcid(_)) {
+                continue;
+            }
+
+            let p: Vec<_> = res
+                .atoms
+                .iter()
+                .map(|&j| &mol.atoms[j])
+                .filter(|a| match mode {
+                    ContactMode::CAlpha => a.role == Some(AtomRole::C_Alpha),
+                    ContactMode::MinAtom => a.element != Element::Hydrogen,
+                })
+                .map(|a| a.posit)
+                .collect();
+
+            if p.is_empty() {
+                continue;
+            }
+            residues.push(i);
+            posits.push(p);
+        }
+
+        let n = residues.len();
+        let dists = (0..n * n)
+            .into_par_iter()
+            .map(|k| {
+                let (i, j) = (k / n, k % n);
+                let mut min = f64::MAX;
+                for a in &posits[i] {
+                    for b in &posits[j] {
+                        min = min.min((*a - *b).magnitude_squared());
+                    }
+                }
+                min.sqrt() as f32
+            })
+            .collect();
+
+        Self {
+            mode,
+            residues,
+            dists,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.residues.len()
+    }
+
+    /// Indices are into `residues`; not the molecule's.
+    pub fn dist(&self, i: usize, j: usize) -> f32 {
+        self.dists[i * self.len() + j]
+    }
+
+    /// Å
+    pub fn contact_thresh(&self) -> f32 {
+        match self.mode {
+            ContactMode::CAlpha => CONTACT_THRESH_CA,
+            ContactMode::MinAtom => CONTACT_THRESH_MIN_ATOM,
+        }
+    }
+
+    pub fn in_contact(&self, i: usize, j: usize) -> bool {
+        self.dist(i, j) <= self.contact_thresh()
+    }
+}
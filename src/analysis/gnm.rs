@@ -0,0 +1,166 @@
+//! Gaussian network model (GNM): an elastic network over Cα atoms, with identical springs between
+//! all pairs within a cutoff distance. Per-residue fluctuations predicted from its Kirchhoff
+//! (connectivity) matrix correlate with experimental B-factors. Regions where the two disagree may
+//! indicate modeling errors, crystal contacts, or flexibility that contacts alone don't explain.
+//!
+//! The Kirchhoff matrix, and its pseudo-inverse, are the basis for other elastic network analyses,
+//! e.g. slow modes and residue cross-correlations.
+
+use lin_alg::f64::Vec3;
+use nalgebra::{DMatrix, SymmetricEigen};
+
+use crate::molecule::{AtomRole, Molecule};
+
+/// Å. Cα pairs closer than this are connected by a spring. 7-7.5Å is typical for GNM.
+pub const GNM_CUTOFF: f64 = 7.3;
+/// Eigenvalues below this are treated as zero modes; one per disconnected part of the network.
+const EIGEN_ZERO_THRESH: f64 = 1e-6;
+/// Residues whose predicted and experimental B-factors differ by at least this many standard
+/// deviations, after normalizing each profile, are flagged.
+pub const DISAGREE_Z_MIN: f64 = 1.5;
+
+#[derive(Clone, Debug)]
+pub struct GnmResult {
+    /// Residue indices, in the order of the values below.
+    pub residues: Vec<usize>,
+    /// Å². Predicted B-factors, fit to the experimental ones by least squares. If there are no
+    /// experimental values, these are the raw fluctuations, in arbitrary units.
+    pub b_pred: Vec<f64>,
+    /// Å². From Cα temperature factors.
+    pub b_exp: Vec<Option<f64>>,
+    /// Pearson correlation between predicted and experimental, over residues with both.
+    pub correlation: Option<f64>,
+    /// Indices into `residues`.
+    pub flagged: Vec<usize>,
+}
+
+/// The Kirchhoff matrix: -1 for each connected pair, and each diagonal element is its node's
+/// number of connections.
+pub fn kirchhoff(posits: &[Vec3], cutoff: f64) -> DMatrix<f64> {
+    let n = posits.len();
+    let cutoff_sq = cutoff.powi(2);
+    let mut result = DMatrix::zeros(n, n);
+
+    for i in 0..n {
+        for j in i + 1..n {
+            if (posits[i] - posits[j]).magnitude_squared() < cutoff_sq {
+                result[(i, j)] = -1.;
+                result[(j, i)] = -1.;
+                result[(i, i)] += 1.;
+                result[(j, j)] += 1.;
+            }
+        }
+    }
+
+    result
+}
+
+/// Mean-square fluctuation of each node, in arbitrary units: The diagonal of the Kirchhoff
+/// matrix's pseudo-inverse, summed over its non-zero modes.
+pub fn fluctuations(kirchhoff: DMatrix<f64>) -> Vec<f64> {
+    let n = kirchhoff.nrows();
+    let eigen = SymmetricEigen::new(kirchhoff);
+
+    let mut result = vec![0.; n];
+    for (k, &λ) in eigen.eigenvalues.iter().enumerate() {
+        if λ < EIGEN_ZERO_THRESH {
+            continue;
+        }
+        let v = eigen.eigenvectors.column(k);
+        for i in 0..n {
+            result[i] += v[i].powi(2) / λ;
+        }
+    }
+
+    result
+}
+
+fn mean_sd(vals: &[f64]) -> (f64, f64) {
+    let mean = vals.iter().sum::<f64>() / vals.len() as f64;
+    let var = vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / vals.len() as f64;
+    (mean, var.sqrt())
+}
+
+/// Predict B-factors for each residue with a Cα, and compare them to the experimental ones.
+/// Returns `None` if there are too few residues to build a network.
+pub fn gnm_b_factors(mol: &Molecule) -> Option<GnmResult> {
+    let mut residues = Vec::new();
+    let mut posits = Vec::new();
+    let mut b_exp = Vec::new();
+
+    for atom in &mol.atoms {
+        if atom.role != Some(AtomRole::C_Alpha) || atom.hetero {
+            continue;
+        }
+        let Some(res) = atom.residue else {
+            continue;
+        };
+        // Skip alternate locations.
+        if residues.last() == Some(&res) {
+            continue;
+        }
+
+        residues.push(res);
+        posits.push(atom.posit);
+        b_exp.push(atom.temperature_factor.map(|b| b as f64));
+    }
+
+    if residues.len() < 3 {
+        return None;
+    }
+
+    let fluct = fluctuations(kirchhoff(&posits, GNM_CUTOFF));
+
+    // Pairs of (predicted, experimental), and their indices.
+    let (paired_i, paired): (Vec<usize>, Vec<(f64, f64)>) = fluct
+        .iter()
+        .zip(&b_exp)
+        .enumerate()
+        .filter_map(|(i, (f, b))| b.map(|b| (i, (*f, b))))
+        .unzip();
+
+    let mut correlation = None;
+    let mut flagged = Vec::new();
+
+    // Fit B = scale * fluct + offset. The offset accounts for disorder the model doesn't include,
+    // e.g. of the lattice.
+    let b_pred = if paired.len() >= 3 {
+        let (f, b): (Vec<f64>, Vec<f64>) = paired.iter().copied().unzip();
+        let (f_mean, f_sd) = mean_sd(&f);
+        let (b_mean, b_sd) = mean_sd(&b);
+
+        if f_sd > 0. && b_sd > 0. {
+            let cov = f
+                .iter()
+                .zip(&b)
+                .map(|(f, b)| (f - f_mean) * (b - b_mean))
+                .sum::<f64>()
+                / f.len() as f64;
+            correlation = Some(cov / (f_sd * b_sd));
+
+            for (k, &i) in paired_i.iter().enumerate() {
+                let z_f = (f[k] - f_mean) / f_sd;
+                let z_b = (b[k] - b_mean) / b_sd;
+                if (z_f - z_b).abs() >= DISAGREE_Z_MIN {
+                    flagged.push(i);
+                }
+            }
+
+            let scale = cov / f_sd.powi(2);
+            let offset = b_mean - scale * f_mean;
+            fluct.iter().map(|f| scale * f + offset).collect()
+        } else {
+            fluct
+        }
+    } else {
+        fluct
+    };
+
+    Some(GnmResult {
+        residues,
+        b_pred,
+        b_exp,
+        correlation,
+        flagged,
+    })
+}
@@ -0,0 +1,201 @@
+//! Binding-site water placement, for pockets without crystallographic waters. We score a water
+//! oxygen probe on a fine grid over the pocket, with Lennard-Jones and hydrogen bond terms, then
+//! place waters greedily at the lowest-energy points. Each placed water becomes a hydrogen bond
+//! partner for later ones, so networks bridging polar groups form.
+//!
+//! Hydrogens are ignored (i.e. a united-atom model), and water orientation isn't considered; this
+//! is for a sensible starting hydration for docking and MD, not a free energy estimate.
+
+use bio_files::ResidueType;
+use lin_alg::f64::Vec3;
+use na_seq::Element;
+
+use crate::{
+    analysis::pockets::Pocket,
+    forces::V_lj,
+    molecule::{Atom, AtomRole, Molecule, Residue},
+};
+
+/// Å. Spacing of the probe grid; half that of the pocket grid.
+const PROBE_SPACING: f64 = 0.5;
+/// Å. Atoms farther than this from a probe point don't contribute to its energy.
+const CUTOFF: f64 = 8.;
+/// kcal/mol. LJ well depth for all probe-atom pairs.
+const LJ_EPS: f32 = 0.15;
+/// Å. Water oxygen's VDW radius.
+const WATER_VDW_RADIUS: f32 = 1.52;
+/// Å. Optimal donor-acceptor distance for a hydrogen bond with the probe. Also used as the LJ
+/// minimum for polar pairs, since they approach closer than their VDW radii.
+const H_BOND_DIST: f64 = 2.9;
+/// Å. The hydrogen bond term falls linearly to 0 this far from the optimum.
+const H_BOND_WIDTH: f64 = 0.5;
+/// kcal/mol. Energy of an optimal hydrogen bond.
+const H_BOND_E: f64 = -2.;
+/// Å. Placed waters are at least this far apart, and from existing waters.
+const WATER_MIN_DIST: f64 = 2.6;
+/// kcal/mol. Points above this energy aren't hydrated; roughly one good hydrogen bond.
+const PLACE_E_MAX: f64 = -1.5;
+
+fn is_polar(el: &Element) -> bool {
+    matches!(el, Element::Nitrogen | Element::Oxygen)
+}
+
+/// Energy of a water probe interacting with a single atom, or another water.
+fn pair_energy(dist: f64, vdw_radius: f32, polar: bool) -> f64 {
+    let r_min = if polar {
+        H_BOND_DIST as f32
+    } else {
+        vdw_radius + WATER_VDW_RADIUS
+    };
+    // V_lj is minimized at 2^(1/6) σ.
+    let sigma = r_min / 2_f32.powf(1. / 6.);
+    let mut result = V_lj(dist as f32, sigma, LJ_EPS) as f64;
+
+    if polar {
+        let dev = (dist - H_BOND_DIST).abs();
+        if dev < H_BOND_WIDTH {
+            result += H_BOND_E * (1. - dev / H_BOND_WIDTH);
+        }
+    }
+
+    result
+}
+
+/// Water oxygen positions in the pocket, in order of placement, i.e. most favorable first. Existing
+/// atoms, including waters, are taken into account.
+pub fn place_waters(mol: &Molecule, pocket: &Pocket) -> Vec<Vec3> {
+    let pocket_radius = pocket
+        .points
+        .iter()
+        .map(|p| (*p - pocket.center).magnitude())
+        .fold(0., f64::max);
+
+    // (Posit, VDW radius, polar)
+    let atoms: Vec<(Vec3, f32, bool)> = mol
+        .atoms
+        .iter()
+        .filter(|a| a.element != Element::Hydrogen)
+        .filter(|a| (a.posit - pocket.center).magnitude() < pocket_radius + CUTOFF)
+        .map(|a| (a.posit, a.element.vdw_radius(), is_polar(&a.element)))
+        .collect();
+
+    let waters_existing: Vec<Vec3> = mol
+        .atoms
+        .iter()
+        .filter(|a| a.role == Some(AtomRole::Water) && a.element == Element::Oxygen)
+        .map(|a| a.posit)
+        .collect();
+
+    // Subdivide each pocket grid cell.
+    let offset = PROBE_SPACING / 2.;
+    let mut probes = Vec::with_capacity(pocket.points.len() * 8);
+    for p in &pocket.points {
+        for dx in [-offset, offset] {
+            for dy in [-offset, offset] {
+                for dz in [-offset, offset] {
+                    let probe = *p + Vec3::new(dx, dy, dz);
+                    if waters_existing
+                        .iter()
+                        .all(|w| (*w - probe).magnitude() >= WATER_MIN_DIST)
+                    {
+                        probes.push(probe);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut energies: Vec<f64> = probes
+        .iter()
+        .map(|probe| {
+            atoms
+                .iter()
+                .map(|(posit, vdw_radius, polar)| (*posit - *probe, *vdw_radius, *polar))
+                .filter(|(diff, _, _)| diff.magnitude() < CUTOFF)
+                .map(|(diff, vdw_radius, polar)| pair_energy(diff.magnitude(), vdw_radius, polar))
+                .sum()
+        })
+        .collect();
+
+    let mut available = vec![true; probes.len()];
+    let mut result = Vec::new();
+
+    loop {
+        let best = energies
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| available[*i])
+            .min_by(|a, b| a.1.total_cmp(b.1));
+
+        let Some((best_i, &e)) = best else {
+            break;
+        };
+        if e > PLACE_E_MAX {
+            break;
+        }
+
+        let water = probes[best_i];
+        result.push(water);
+
+        // Exclude points too close to this water, and let the rest hydrogen bond with it.
+        for (i, probe) in probes.iter().enumerate() {
+            if !available[i] {
+                continue;
+            }
+            let dist = (*probe - water).magnitude();
+            if dist < WATER_MIN_DIST {
+                available[i] = false;
+            } else if dist < CUTOFF {
+                energies[i] += pair_energy(dist, WATER_VDW_RADIUS, true);
+            }
+        }
+    }
+
+    result
+}
+
+/// Add water oxygens to the molecule, each as its own hetero residue.
+pub fn add_waters(mol: &mut Molecule, posits: &[Vec3]) {
+    let mut atom_sn = mol.atoms.iter().map(|a| a.serial_number).max().unwrap_or(0);
+    let mut res_sn = mol
+        .residues
+        .iter()
+        .map(|r| r.serial_number)
+        .max()
+        .unwrap_or(0);
+
+    for posit in posits {
+        atom_sn += 1;
+        res_sn += 1;
+
+        mol.atoms.push(Atom {
+            serial_number: atom_sn,
+            posit: *posit,
+            element: Element::Oxygen,
+            type_in_res: None,
+            force_field_type: None,
+            dock_type: None,
+            role: Some(AtomRole::Water),
+            residue: Some(mol.residues.len()),
+            hetero: true,
+            occupancy: None,
+            partial_charge: None,
+            temperature_factor: None,
+            alt_loc: None,
+            anisou: None,
+            props: Default::default(),
+            in_ring: false,
+            aromatic: false,
+        });
+        mol.adjacency_list.push(Vec::new());
+
+        mol.residues.push(Residue {
+            serial_number: res_sn,
+            res_type: ResidueType::Water,
+            atoms: vec![mol.atoms.len() - 1],
+            dihedral: None,
+            props: Default::default(),
+            variant: None,
+        });
+    }
+}
@@ -0,0 +1,412 @@
+//! Non-covalent interactions other than hydrogen bonds: salt bridges, π-stacking, and cation-π
+//! within a molecule, and halogen bonds and S···O chalcogen contacts between a ligand and its
+//! receptor. Hydrogen bonds are inferred along with covalent bonds, in `bond_inference`.
+//!
+//! Charged groups are taken from amino acid sidechains: Lys, Arg, and His are cationic; Asp and
+//! Glu are anionic. Geometric criteria follow PLIP (Salentin et al, 2015).
+
+use bio_files::ResidueType;
+use lin_alg::f64::Vec3;
+use na_seq::{AminoAcid, Element};
+
+use crate::{
+    analysis::rings::Ring,
+    molecule::{AtomRole, Molecule},
+};
+
+/// Å. Between a cationic N, and an anionic O.
+const SALT_BRIDGE_DIST: f64 = 4.0;
+/// Å. Between ring centers.
+const PI_STACK_DIST: f64 = 5.5;
+/// Å. Between a cation's charge center, and a ring center.
+const CATION_PI_DIST: f64 = 6.0;
+/// Å. Maximum lateral displacement of one ring center, or a cation, from the other ring's axis.
+const PI_OFFSET_MAX: f64 = 2.0;
+/// Degrees. Maximum deviation of the angle between ring planes from 0° (parallel), or 90° (T-shaped).
+const PI_STACK_ANGLE_DEV: f64 = 30.;
+/// Å. Between the halogen and acceptor.
+const HALOGEN_BOND_DIST: f64 = 4.0;
+/// Degrees. Minimum C-X···A angle. Halogen bonds form along the C-X axis, at the σ-hole.
+const HALOGEN_BOND_ANGLE_MIN: f64 = 140.;
+/// Å. Between S and O; slightly over the sum of their VdW radii.
+const CHALCOGEN_DIST: f64 = 3.6;
+/// Degrees. Minimum C-S···O angle, for one of the S's substituents.
+const CHALCOGEN_ANGLE_MIN: f64 = 140.;
+
+#[derive(Clone, Debug)]
+pub struct SaltBridge {
+    /// Atom indices: The closest pair of charged atoms.
+    pub cation: usize,
+    pub anion: usize,
+    /// Å.
+    pub dist: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PiStackKind {
+    /// Face to face.
+    Parallel,
+    /// Edge to face.
+    TShaped,
+}
+
+#[derive(Clone, Debug)]
+pub struct PiStack {
+    /// Indices into `Molecule::rings_aromatic`.
+    pub ring_0: usize,
+    pub ring_1: usize,
+    pub kind: PiStackKind,
+    /// Å, between ring centers.
+    pub dist: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct CationPi {
+    /// Atom index: The cationic N closest to the ring.
+    pub cation: usize,
+    /// Index into `Molecule::rings_aromatic`.
+    pub ring: usize,
+    /// Å, between the charge center and the ring center.
+    pub dist: f64,
+}
+
+/// C-X···O/N, where X is Cl, Br, or I on the ligand.
+#[derive(Clone, Debug)]
+pub struct HalogenBond {
+    /// Atom index in the ligand: The halogen.
+    pub lig_atom: usize,
+    /// Atom index in the receptor: The acceptor.
+    pub rec_atom: usize,
+    /// Å.
+    pub dist: f64,
+    /// Degrees. C-X···A.
+    pub angle: f64,
+}
+
+/// S···O, with the S on either the ligand (e.g. a thiophene) or the receptor (Met or Cys).
+#[derive(Clone, Debug)]
+pub struct ChalcogenContact {
+    /// Atom indices.
+    pub lig_atom: usize,
+    pub rec_atom: usize,
+    /// Å.
+    pub dist: f64,
+}
+
+struct ChargedGroup {
+    res: usize,
+    /// Indices of the charge-bearing N or O atoms.
+    atoms: Vec<usize>,
+    center: Vec3,
+}
+
+/// Cationic and anionic sidechain groups. His is included as a cation, as it's often protonated
+/// when in a salt bridge. It's excluded from cation-π, since its own ring makes that ambiguous.
+fn charged_groups(mol: &Molecule, include_his: bool) -> (Vec<ChargedGroup>, Vec<ChargedGroup>) {
+    let mut cations = Vec::new();
+    let mut anions = Vec::new();
+
+    for (i_res, res) in mol.residues.iter().enumerate() {
+        let ResidueType::AminoAcid(aa) = res.res_type else {
+            continue;
+        };
+
+        let (element, dest) = match aa {
+            AminoAcid::Lys | AminoAcid::Arg => (Element::Nitrogen, &mut cations),
+            AminoAcid::His if include_his => (Element::Nitrogen, &mut cations),
+            AminoAcid::Asp | AminoAcid::Glu => (Element::Oxygen, &mut anions),
+            _ => continue,
+        };
+
+        let atoms: Vec<_> = res
+            .atoms
+            .iter()
+            .copied()
+            .filter(|&i| {
+                let atom = &mol.atoms[i];
+                atom.element == element && atom.role == Some(AtomRole::Sidechain)
+            })
+            .collect();
+
+        if atoms.is_empty() {
+            continue;
+        }
+
+        let center = atoms
+            .iter()
+            .fold(Vec3::new_zero(), |acc, &i| acc + mol.atoms[i].posit)
+            / atoms.len() as f64;
+
+        dest.push(ChargedGroup {
+            res: i_res,
+            atoms,
+            center,
+        });
+    }
+
+    (cations, anions)
+}
+
+/// Distance from `posit` to the line through the ring center, along its normal.
+fn ring_offset(ring: &Ring, posit: Vec3) -> f64 {
+    let diff = posit - ring.center;
+    (diff - ring.normal * diff.dot(ring.normal)).magnitude()
+}
+
+fn ring_res(mol: &Molecule, ring: &Ring) -> Option<usize> {
+    mol.atoms[ring.atoms[0]].residue
+}
+
+/// Atom indices of charged sidechain atoms: (cationic, anionic).
+pub fn charged_atoms(mol: &Molecule) -> (Vec<usize>, Vec<usize>) {
+    let (cations, anions) = charged_groups(mol, true);
+    let flatten = |groups: Vec<ChargedGroup>| groups.into_iter().flat_map(|g| g.atoms).collect();
+
+    (flatten(cations), flatten(anions))
+}
+
+/// One per pair of charged groups, between their closest charged atoms.
+pub fn find_salt_bridges(mol: &Molecule) -> Vec<SaltBridge> {
+    let (cations, anions) = charged_groups(mol, true);
+    let mut result = Vec::new();
+
+    for cation in &cations {
+        for anion in &anions {
+            if cation.res == anion.res
+                || (cation.center - anion.center).magnitude() > SALT_BRIDGE_DIST * 2.
+            {
+                continue;
+            }
+
+            let closest = cation
+                .atoms
+                .iter()
+                .flat_map(|&c| anion.atoms.iter().map(move |&a| (c, a)))
+                .map(|(c, a)| (c, a, (mol.atoms[c].posit - mol.atoms[a].posit).magnitude()))
+                .min_by(|x, y| x.2.total_cmp(&y.2));
+
+            if let Some((c, a, dist)) = closest {
+                if dist <= SALT_BRIDGE_DIST {
+                    result.push(SaltBridge {
+                        cation: c,
+                        anion: a,
+                        dist,
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// The kind of stacking between two rings, if any.
+pub fn stack_kind(ring_0: &Ring, ring_1: &Ring) -> Option<PiStackKind> {
+    if (ring_1.center - ring_0.center).magnitude() > PI_STACK_DIST {
+        return None;
+    }
+
+    let angle = ring_0
+        .normal
+        .dot(ring_1.normal)
+        .abs()
+        .min(1.)
+        .acos()
+        .to_degrees();
+    let offset = ring_offset(ring_0, ring_1.center).min(ring_offset(ring_1, ring_0.center));
+
+    if offset > PI_OFFSET_MAX {
+        return None;
+    }
+
+    if angle < PI_STACK_ANGLE_DEV {
+        Some(PiStackKind::Parallel)
+    } else if angle > 90. - PI_STACK_ANGLE_DEV {
+        Some(PiStackKind::TShaped)
+    } else {
+        None
+    }
+}
+
+/// Stacking between pairs of rings in different residues, or in different het molecules.
+pub fn find_pi_stacks(mol: &Molecule, rings: &[Ring]) -> Vec<PiStack> {
+    let mut result = Vec::new();
+
+    for (i, ring_0) in rings.iter().enumerate() {
+        for (j, ring_1) in rings.iter().enumerate().skip(i + 1) {
+            let same_res =
+                ring_res(mol, ring_0).is_some() && ring_res(mol, ring_0) == ring_res(mol, ring_1);
+            let fused = ring_0.atoms.iter().any(|a| ring_1.atoms.contains(a));
+            if same_res || fused {
+                continue;
+            }
+
+            if let Some(kind) = stack_kind(ring_0, ring_1) {
+                result.push(PiStack {
+                    ring_0: i,
+                    ring_1: j,
+                    kind,
+                    dist: (ring_1.center - ring_0.center).magnitude(),
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// Lys and Arg charge centers over aromatic rings.
+pub fn find_cation_pi(mol: &Molecule, rings: &[Ring]) -> Vec<CationPi> {
+    let (cations, _) = charged_groups(mol, false);
+    let mut result = Vec::new();
+
+    for cation in &cations {
+        for (i_ring, ring) in rings.iter().enumerate() {
+            if ring_res(mol, ring) == Some(cation.res) {
+                continue;
+            }
+
+            let dist = (cation.center - ring.center).magnitude();
+            if dist > CATION_PI_DIST || ring_offset(ring, cation.center) > PI_OFFSET_MAX {
+                continue;
+            }
+
+            let closest = cation.atoms.iter().copied().min_by(|&a, &b| {
+                let dist_a = (mol.atoms[a].posit - ring.center).magnitude();
+                let dist_b = (mol.atoms[b].posit - ring.center).magnitude();
+                dist_a.total_cmp(&dist_b)
+            });
+
+            if let Some(atom) = closest {
+                result.push(CationPi {
+                    cation: atom,
+                    ring: i_ring,
+                    dist,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// Degrees. The angle at `b`, between `a` and `c`.
+fn angle_at(a: Vec3, b: Vec3, c: Vec3) -> f64 {
+    let (ba, bc) = ((a - b).to_normalized(), (c - b).to_normalized());
+    ba.dot(bc).clamp(-1., 1.).acos().to_degrees()
+}
+
+/// Heavy-atom neighbors of an atom.
+fn heavy_neighbors(mol: &Molecule, i: usize) -> impl Iterator<Item = usize> + '_ {
+    mol.adjacency_list
+        .get(i)
+        .into_iter()
+        .flatten()
+        .copied()
+        .filter(|&j| mol.atoms[j].element != Element::Hydrogen)
+}
+
+/// Halogen bonds from ligand halogens to receptor O and N. `lig_posits` are the ligand's posed
+/// atom positions. The acceptor must be approached from outside its own bonds, i.e. X···A-Y is at
+/// least 90° for each of its heavy neighbors Y.
+pub fn find_halogen_bonds(rec: &Molecule, lig: &Molecule, lig_posits: &[Vec3]) -> Vec<HalogenBond> {
+    let mut result = Vec::new();
+
+    for (i_x, atom_x) in lig.atoms.iter().enumerate() {
+        if !matches!(
+            atom_x.element,
+            Element::Chlorine | Element::Bromine | Element::Iodine
+        ) {
+            continue;
+        }
+        let posit_x = lig_posits[i_x];
+        let Some(i_c) = heavy_neighbors(lig, i_x).next() else {
+            continue;
+        };
+        let posit_c = lig_posits[i_c];
+
+        for (i_a, atom_a) in rec.atoms.iter().enumerate() {
+            if !matches!(atom_a.element, Element::Oxygen | Element::Nitrogen) {
+                continue;
+            }
+
+            let dist = (atom_a.posit - posit_x).magnitude();
+            if dist > HALOGEN_BOND_DIST {
+                continue;
+            }
+
+            let angle = angle_at(posit_c, posit_x, atom_a.posit);
+            if angle < HALOGEN_BOND_ANGLE_MIN {
+                continue;
+            }
+
+            let exposed = heavy_neighbors(rec, i_a)
+                .all(|y| angle_at(posit_x, atom_a.posit, rec.atoms[y].posit) >= 90.);
+
+            if exposed {
+                result.push(HalogenBond {
+                    lig_atom: i_x,
+                    rec_atom: i_a,
+                    dist,
+                    angle,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// S···O contacts between a ligand and receptor, in either direction. `lig_posits` are the
+/// ligand's posed atom positions.
+pub fn find_chalcogen_contacts(
+    rec: &Molecule,
+    lig: &Molecule,
+    lig_posits: &[Vec3],
+) -> Vec<ChalcogenContact> {
+    let rec_posits: Vec<_> = rec.atoms.iter().map(|a| a.posit).collect();
+    let mut result = Vec::new();
+
+    // (Molecule with the S, its positions, the other molecule, its positions, if the S is on the ligand)
+    let directions = [
+        (lig, lig_posits, rec, &rec_posits[..], true),
+        (rec, &rec_posits[..], lig, lig_posits, false),
+    ];
+
+    for (mol_s, posits_s, mol_o, posits_o, s_on_lig) in directions {
+        for (i_s, atom_s) in mol_s.atoms.iter().enumerate() {
+            if atom_s.element != Element::Sulfur {
+                continue;
+            }
+            let posit_s = posits_s[i_s];
+            let substituents: Vec<_> = heavy_neighbors(mol_s, i_s).map(|i| posits_s[i]).collect();
+
+            for (i_o, atom_o) in mol_o.atoms.iter().enumerate() {
+                if atom_o.element != Element::Oxygen {
+                    continue;
+                }
+
+                let dist = (posits_o[i_o] - posit_s).magnitude();
+                if dist > CHALCOGEN_DIST {
+                    continue;
+                }
+
+                let aligned = substituents
+                    .iter()
+                    .any(|&c| angle_at(c, posit_s, posits_o[i_o]) >= CHALCOGEN_ANGLE_MIN);
+                if !aligned {
+                    continue;
+                }
+
+                let (lig_atom, rec_atom) = if s_on_lig { (i_s, i_o) } else { (i_o, i_s) };
+                result.push(ChalcogenContact {
+                    lig_atom,
+                    rec_atom,
+                    dist,
+                });
+            }
+        }
+    }
+
+    result
+}
@@ -0,0 +1,146 @@
+//! Protein-protein interface analysis: Buried surface area (BSA) between two chains, and the
+//! residues that make up the interface. A residue is at the interface if it loses solvent-accessible
+//! area when the other chain is present. The same, for a ligand pose and the receptor.
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    molecule::{Atom, AtomRole, Molecule},
+    sa_surface::calc_sasa,
+};
+
+/// Å². Residues burying more than this are considered part of the interface.
+const INTERFACE_DSASA_THRESH: f32 = 1.;
+/// Å. Only receptor atoms this close to the ligand are included in its BSA calculation. Atoms
+/// farther than twice the largest probe-inflated radius can't be buried by it, and their own
+/// neighbors don't affect which of their points the ligand buries.
+const LIG_BSA_SCOPE_DIST: f64 = 10.;
+
+#[derive(Clone, Debug)]
+pub struct Interface {
+    /// Indices into the molecule's chains.
+    pub chain_a: usize,
+    pub chain_b: usize,
+    /// Å². SASA(A) + SASA(B) - SASA(AB). The interface area is often reported as half of this.
+    pub bsa: f32,
+    /// (Residue index, ΔSASA in Å²), sorted by ΔSASA, descending.
+    pub residues: Vec<(usize, f32)>,
+}
+
+impl Interface {
+    /// All atoms of the interface residues; e.g. for selecting them.
+    pub fn atoms(&self, mol: &Molecule) -> Vec<usize> {
+        self.residues
+            .iter()
+            .filter_map(|(r, _)| mol.residues.get(*r))
+            .flat_map(|r| r.atoms.iter().copied())
+            .collect()
+    }
+}
+
+fn chain_atoms(mol: &Molecule, chain: usize) -> Vec<&Atom> {
+    match mol.chains.get(chain) {
+        Some(c) => c
+            .atoms
+            .iter()
+            .map(|i| &mol.atoms[*i])
+            .filter(|a| a.role != Some(AtomRole::Water))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Compute buried surface area and interface residues between two chains.
+pub fn analyze_interface(mol: &Molecule, chain_a: usize, chain_b: usize) -> Interface {
+    let atoms_a = chain_atoms(mol, chain_a);
+    let atoms_b = chain_atoms(mol, chain_b);
+    let atoms_ab: Vec<_> = atoms_a.iter().chain(&atoms_b).copied().collect();
+
+    let n_res = mol.residues.len();
+    let sasa_a = calc_sasa(&atoms_a, n_res);
+    let sasa_b = calc_sasa(&atoms_b, n_res);
+    let sasa_ab = calc_sasa(&atoms_ab, n_res);
+
+    // Each residue is in one chain, so the other chain's per-residue term is 0.
+    let mut residues: Vec<_> = (0..n_res)
+        .map(|r| {
+            let alone = sasa_a.per_residue[r] + sasa_b.per_residue[r];
+            (r, alone - sasa_ab.per_residue[r])
+        })
+        .filter(|(_, d)| *d > INTERFACE_DSASA_THRESH)
+        .collect();
+    residues.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    Interface {
+        chain_a,
+        chain_b,
+        bsa: sasa_a.total + sasa_b.total - sasa_ab.total,
+        residues,
+    }
+}
+
+/// Surface area buried on binding: of the ligand at its current pose, and of each receptor residue.
+#[derive(Clone, Debug)]
+pub struct LigandBsa {
+    /// Å². SASA of the ligand alone, and buried in the complex.
+    pub lig_sasa: f32,
+    pub lig_buried: f32,
+    /// Å². Buried receptor area.
+    pub rec_buried: f32,
+    /// (Residue index, ΔSASA in Å²), sorted by ΔSASA, descending.
+    pub residues: Vec<(usize, f32)>,
+}
+
+/// ΔSASA of the ligand, and of receptor residues, on complex formation. `lig_posits` are the
+/// ligand's atom positions at its pose. Only receptor atoms near the ligand are included, which
+/// doesn't change the result.
+pub fn ligand_bsa(rec: &Molecule, lig: &Molecule, lig_posits: &[Vec3]) -> LigandBsa {
+    // Ligand atoms at their posed positions. Ligand residues are unrelated to receptor ones.
+    let lig_atoms: Vec<Atom> = lig
+        .atoms
+        .iter()
+        .zip(lig_posits)
+        .map(|(a, p)| Atom {
+            posit: *p,
+            residue: None,
+            ..a.clone()
+        })
+        .collect();
+    let lig_atoms: Vec<&Atom> = lig_atoms.iter().collect();
+
+    let scope_sq = LIG_BSA_SCOPE_DIST.powi(2);
+    let rec_atoms: Vec<&Atom> = rec
+        .sasa_atoms()
+        .into_iter()
+        .filter(|a| {
+            lig_posits
+                .iter()
+                .any(|p| (a.posit - *p).magnitude_squared() < scope_sq)
+        })
+        .collect();
+
+    let n_res = rec.residues.len();
+    let sasa_lig = calc_sasa(&lig_atoms, 0);
+    let sasa_rec = calc_sasa(&rec_atoms, n_res);
+
+    let complex: Vec<&Atom> = lig_atoms.iter().chain(&rec_atoms).copied().collect();
+    let sasa_complex = calc_sasa(&complex, n_res);
+
+    // Ligand atoms are first in the complex.
+    let lig_in_complex: f32 = sasa_complex.per_atom[..lig_atoms.len()].iter().sum();
+    let rec_in_complex = sasa_complex.total - lig_in_complex;
+
+    // Ligand atoms have no residue, so per-residue values in the complex are from receptor atoms.
+    let mut residues: Vec<_> = (0..n_res)
+        .map(|r| (r, sasa_rec.per_residue[r] - sasa_complex.per_residue[r]))
+        .filter(|(_, d)| *d > INTERFACE_DSASA_THRESH)
+        .collect();
+    residues.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    LigandBsa {
+        lig_sasa: sasa_lig.total,
+        lig_buried: sasa_lig.total - lig_in_complex,
+        rec_buried: sasa_rec.total - rec_in_complex,
+        residues,
+    }
+}
@@ -0,0 +1,216 @@
+//! MM-GBSA estimates of binding free energy. For each frame, e.g. MD snapshots or a minimized pose,
+//! we compute ΔG = ΔE_MM + ΔG_GB + ΔG_SA, between the complex, and the receptor and ligand apart.
+//!
+//! We use the single-trajectory approach: the receptor and ligand take their geometries from the
+//! complex, so internal (bonded) energies cancel, and ΔE_MM is their nonbonded interaction. ΔG_GB
+//! is the change in Generalized Born solvation energy, and ΔG_SA is proportional to the change in
+//! solvent-accessible surface area. Entropy isn't included, so values are useful for ranking
+//! poses and ligands, vice as absolute binding free energies.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use lin_alg::f64::Vec3;
+use na_seq::element::LjTable;
+
+use crate::{
+    analysis::{
+        interface::ligand_bsa,
+        residue_energy::{decompose_energy, lig_charges},
+    },
+    dynamics::{
+        MdState,
+        gb::{born_radii, gb_energy},
+    },
+    molecule::Molecule,
+};
+
+/// kcal/(mol·Å²). Amber's default surface tension.
+const SURFACE_TENSION: f64 = 0.0072;
+/// Snapshots are subsampled evenly to at most this many frames.
+pub const MMGBSA_FRAMES_MAX: usize = 50;
+/// Å. Receptor atoms farther than this from the ligand are omitted from the GB terms. Their
+/// contributions to the complex and the receptor alone nearly cancel.
+const GB_SCOPE_DIST: f64 = 12.;
+
+/// Terms are kcal/mol, and are the complex's less the receptor's and ligand's.
+#[derive(Clone, Debug, Default)]
+pub struct MmgbsaFrame {
+    /// fs. 0 for a static pose.
+    pub time: f64,
+    pub vdw: f64,
+    pub elec: f64,
+    pub gb: f64,
+    pub sa: f64,
+}
+
+impl MmgbsaFrame {
+    /// kcal/mol
+    pub fn total(&self) -> f64 {
+        self.vdw + self.elec + self.gb + self.sa
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mmgbsa {
+    pub frames: Vec<MmgbsaFrame>,
+}
+
+impl Mmgbsa {
+    /// Each term, averaged over frames.
+    pub fn mean(&self) -> MmgbsaFrame {
+        let n = self.frames.len().max(1) as f64;
+        let mut result = MmgbsaFrame::default();
+        for f in &self.frames {
+            result.vdw += f.vdw / n;
+            result.elec += f.elec / n;
+            result.gb += f.gb / n;
+            result.sa += f.sa / n;
+        }
+        result
+    }
+
+    /// kcal/mol. The standard deviation of the total, over frames.
+    pub fn std_dev(&self) -> f64 {
+        let n = self.frames.len();
+        if n < 2 {
+            return 0.;
+        }
+        let mean = self.mean().total();
+        let var = self
+            .frames
+            .iter()
+            .map(|f| (f.total() - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1) as f64;
+        var.sqrt()
+    }
+
+    /// Per-frame terms as CSV, with the average in a final row.
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(
+            file,
+            "time_fs,vdw_kcal_mol,elec_kcal_mol,gb_kcal_mol,sa_kcal_mol,total_kcal_mol"
+        )?;
+
+        let mut write_row = |label: String, f: &MmgbsaFrame| {
+            writeln!(
+                file,
+                "{label},{:.4},{:.4},{:.4},{:.4},{:.4}",
+                f.vdw,
+                f.elec,
+                f.gb,
+                f.sa,
+                f.total()
+            )
+        };
+
+        for f in &self.frames {
+            write_row(format!("{:.3}", f.time), f)?;
+        }
+        write_row("mean".to_owned(), &self.mean())?;
+
+        Ok(())
+    }
+}
+
+/// One frame. `rec` has the receptor's atom positions for this frame, and `lig_posits` the
+/// ligand's.
+pub fn mmgbsa_frame(
+    rec: &Molecule,
+    lig: &Molecule,
+    lig_posits: &[Vec3],
+    lj_lut: &LjTable,
+    time: f64,
+) -> MmgbsaFrame {
+    let decomp = decompose_energy(rec, lig, lig_posits, lj_lut);
+
+    // ΔSASA is negative, since binding buries area.
+    let bsa = ligand_bsa(rec, lig, lig_posits);
+    let sa = -SURFACE_TENSION * (bsa.lig_buried + bsa.rec_buried) as f64;
+
+    // GB, with receptor atoms near the ligand.
+    let scope_sq = GB_SCOPE_DIST.powi(2);
+    let rec_atoms: Vec<_> = rec
+        .atoms
+        .iter()
+        .filter(|a| {
+            !a.hetero
+                && lig_posits
+                    .iter()
+                    .any(|p| (a.posit - *p).magnitude_squared() < scope_sq)
+        })
+        .collect();
+
+    let rec_posits: Vec<_> = rec_atoms.iter().map(|a| a.posit).collect();
+    let rec_q: Vec<_> = rec_atoms
+        .iter()
+        .map(|a| a.partial_charge.unwrap_or_default() as f64)
+        .collect();
+    let rec_radii: Vec<_> = rec_atoms
+        .iter()
+        .map(|a| a.element.vdw_radius() as f64)
+        .collect();
+
+    let lig_q = lig_charges(lig);
+    let lig_radii: Vec<_> = lig
+        .atoms
+        .iter()
+        .map(|a| a.element.vdw_radius() as f64)
+        .collect();
+
+    let solvation = |posits: &[Vec3], q: &[f64], radii: &[f64]| {
+        gb_energy(posits, q, &born_radii(posits, radii))
+    };
+
+    let complex_posits: Vec<_> = rec_posits.iter().chain(lig_posits).copied().collect();
+    let complex_q: Vec<_> = rec_q.iter().chain(&lig_q).copied().collect();
+    let complex_radii: Vec<_> = rec_radii.iter().chain(&lig_radii).copied().collect();
+
+    let gb = solvation(&complex_posits, &complex_q, &complex_radii)
+        - solvation(&rec_posits, &rec_q, &rec_radii)
+        - solvation(lig_posits, &lig_q, &lig_radii);
+
+    MmgbsaFrame {
+        time,
+        vdw: decomp.lj,
+        elec: decomp.coulomb,
+        gb,
+        sa,
+    }
+}
+
+/// Over a docking MD run's snapshots, subsampled to at most `MMGBSA_FRAMES_MAX` frames. Flexible
+/// receptor atoms take their positions from each snapshot.
+pub fn mmgbsa_md(rec: &Molecule, lig: &Molecule, md: &MdState, lj_lut: &LjTable) -> Mmgbsa {
+    let n_lig = lig.atoms.len();
+    let stride = md.snapshots.len().div_ceil(MMGBSA_FRAMES_MAX).max(1);
+
+    let frames = md
+        .snapshots
+        .iter()
+        .step_by(stride)
+        .filter(|snap| snap.atom_posits.len() >= n_lig)
+        .map(|snap| {
+            let lig_posits = &snap.atom_posits[..n_lig];
+            let flex_posits = md.flexible_posits_at(snap);
+
+            if flex_posits.is_empty() {
+                return mmgbsa_frame(rec, lig, lig_posits, lj_lut, snap.time);
+            }
+
+            let mut rec = rec.clone();
+            for (i, posit) in flex_posits {
+                rec.atoms[i].posit = posit;
+            }
+            mmgbsa_frame(&rec, lig, lig_posits, lj_lut, snap.time)
+        })
+        .collect();
+
+    Mmgbsa { frames }
+}
@@ -0,0 +1,16 @@
+//! Structural analyses of a loaded molecule, e.g. binding pocket detection.
+
+pub mod clashes;
+pub mod contact_map;
+pub mod gnm;
+pub mod hydration;
+pub mod interactions;
+pub mod interface;
+pub mod mmgbsa;
+pub mod plif;
+pub mod pockets;
+pub mod ramachandran;
+pub mod residue_energy;
+pub mod rings;
+pub mod trajectory;
+pub mod validation;
@@ -0,0 +1,387 @@
+//! Protein-ligand interaction fingerprints (PLIF). For each receptor residue the ligand contacts,
+//! a set of bits indicating the kinds of interactions: hydrogen bonds with the residue as donor or
+//! acceptor, hydrophobic contacts, ionic interactions, and π-stacking. Fingerprints of different
+//! poses, e.g. from docking, or frames of an MD run, can be compared by Tanimoto similarity, and
+//! clustered by interaction pattern.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bio_files::ResidueType;
+use lin_alg::f64::Vec3;
+use na_seq::Element;
+
+use crate::{
+    analysis::{
+        interactions::{charged_atoms, stack_kind},
+        rings::Ring,
+    },
+    bond_inference::{HBondConfig, create_hydrogen_bonds_one_way},
+    molecule::{BondCount, BondType, Molecule},
+};
+
+/// Å. Receptor atoms farther than this from all ligand atoms are ignored.
+const NEAR_DIST: f64 = 6.;
+/// Å. Between hydrophobic carbons (or ligand halogens).
+const HYDROPHOBIC_DIST: f64 = 4.;
+/// Å. Between oppositely-charged atoms.
+const IONIC_DIST: f64 = 4.;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlifBit {
+    /// The residue donates a hydrogen bond to the ligand.
+    HBondDonor,
+    /// The residue accepts a hydrogen bond from the ligand.
+    HBondAcceptor,
+    Hydrophobic,
+    Ionic,
+    Stacking,
+}
+
+impl PlifBit {
+    pub const ALL: [Self; 5] = [
+        Self::HBondDonor,
+        Self::HBondAcceptor,
+        Self::Hydrophobic,
+        Self::Ionic,
+        Self::Stacking,
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn abbrev(self) -> &'static str {
+        match self {
+            Self::HBondDonor => "HBD",
+            Self::HBondAcceptor => "HBA",
+            Self::Hydrophobic => "Hyd",
+            Self::Ionic => "Ion",
+            Self::Stacking => "Stack",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plif {
+    /// Interaction bits (See `PlifBit::mask`), by receptor residue index. Only contains residues
+    /// with at least one interaction.
+    pub residues: BTreeMap<usize, u8>,
+}
+
+impl Plif {
+    fn set(&mut self, res: Option<usize>, bit: PlifBit) {
+        if let Some(res) = res {
+            *self.residues.entry(res).or_default() |= bit.mask();
+        }
+    }
+
+    /// The fingerprint as a string of 0s and 1s, over the residues given, in order. Use the
+    /// same residues for all fingerprints being compared; e.g. from `residue_union`.
+    pub fn to_bitstring(&self, residues: &[usize]) -> String {
+        residues
+            .iter()
+            .flat_map(|r| {
+                let bits = self.residues.get(r).copied().unwrap_or_default();
+                PlifBit::ALL.map(|b| if bits & b.mask() != 0 { '1' } else { '0' })
+            })
+            .collect()
+    }
+
+    /// Tanimoto similarity: Shared bits over the total set. 1 if both are empty.
+    pub fn tanimoto(&self, other: &Self) -> f64 {
+        let (mut both, mut either) = (0, 0);
+
+        let residues: BTreeSet<_> = self.residues.keys().chain(other.residues.keys()).collect();
+
+        for res in residues {
+            let a = self.residues.get(res).copied().unwrap_or_default();
+            let b = other.residues.get(res).copied().unwrap_or_default();
+            both += (a & b).count_ones();
+            either += (a | b).count_ones();
+        }
+
+        if either == 0 {
+            1.
+        } else {
+            both as f64 / either as f64
+        }
+    }
+
+    /// E.g. "Asp 45: HBA Ion, Phe 82: Stack".
+    pub fn descrip(&self, mol: &Molecule) -> String {
+        self.residues
+            .iter()
+            .map(|(&r, &bits)| {
+                let res = &mol.residues[r];
+                let name = match &res.res_type {
+                    ResidueType::AminoAcid(aa) => aa.to_string(),
+                    ResidueType::Water => "Water".to_owned(),
+                    ResidueType::Other(n) => n.clone(),
+                };
+
+                let kinds: Vec<_> = PlifBit::ALL
+                    .iter()
+                    .filter(|b| bits & b.mask() != 0)
+                    .map(|b| b.abbrev())
+                    .collect();
+
+                format!("{name} {}: {}", res.serial_number, kinds.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// All residues contacted in any of a set of fingerprints, in order.
+pub fn residue_union(plifs: &[Plif]) -> Vec<usize> {
+    let set: BTreeSet<_> = plifs
+        .iter()
+        .flat_map(|p| p.residues.keys())
+        .copied()
+        .collect();
+    set.into_iter().collect()
+}
+
+/// The fraction of fingerprints in which each receptor residue interacts with the ligand, by
+/// residue index. Over a set of screening hits, high values reveal anchor residues that most
+/// binders contact.
+pub fn residue_occupancy(plifs: &[Plif], res_count: usize) -> Vec<f32> {
+    let mut result = vec![0.; res_count];
+    if plifs.is_empty() {
+        return result;
+    }
+
+    for plif in plifs {
+        for &res in plif.residues.keys() {
+            if let Some(v) = result.get_mut(res) {
+                *v += 1.;
+            }
+        }
+    }
+
+    for v in &mut result {
+        *v /= plifs.len() as f32;
+    }
+    result
+}
+
+/// Leader clustering: Each fingerprint joins the first cluster whose first member it's at least
+/// `sim_min` similar to, or starts a new one. Returns a cluster index for each fingerprint.
+pub fn cluster_plifs(plifs: &[Plif], sim_min: f64) -> Vec<usize> {
+    let mut leaders: Vec<usize> = Vec::new();
+
+    plifs
+        .iter()
+        .enumerate()
+        .map(|(i, plif)| {
+            match leaders
+                .iter()
+                .position(|&l| plifs[l].tanimoto(plif) >= sim_min)
+            {
+                Some(cluster) => cluster,
+                None => {
+                    leaders.push(i);
+                    leaders.len() - 1
+                }
+            }
+        })
+        .collect()
+}
+
+/// A carbon bonded only to carbons and hydrogens.
+fn is_hydrophobic_c(mol: &Molecule, i: usize) -> bool {
+    mol.atoms[i].element == Element::Carbon
+        && mol.adjacency_list.get(i).is_some_and(|nbrs| {
+            nbrs.iter()
+                .all(|&j| matches!(mol.atoms[j].element, Element::Carbon | Element::Hydrogen))
+        })
+}
+
+/// Charges of ligand atoms likely charged at physiological pH, inferred from bonding: Carboxylate,
+/// phosphate, and sulfonate O are anionic. Aliphatic amines, quaternary N, and non-aromatic
+/// amidine or guanidine N are cationic.
+fn lig_charges(lig: &Molecule) -> Vec<i8> {
+    let adj = &lig.adjacency_list;
+    let el = |i: usize| lig.atoms[i].element;
+    let heavy = |i: usize| {
+        adj[i]
+            .iter()
+            .filter(|&&j| el(j) != Element::Hydrogen)
+            .count()
+    };
+    let aromatic = |i: usize| lig.atoms[i].aromatic;
+
+    let mut unsaturated = vec![false; lig.atoms.len()];
+    let mut double_to: Vec<Vec<usize>> = vec![Vec::new(); lig.atoms.len()];
+    for bond in &lig.bonds {
+        let single = matches!(
+            bond.bond_type,
+            BondType::Covalent {
+                count: BondCount::Single
+            }
+        );
+        if !single {
+            let (a, b) = (bond.atom_0, bond.atom_1);
+            unsaturated[a] = true;
+            unsaturated[b] = true;
+            double_to[a].push(b);
+            double_to[b].push(a);
+        }
+    }
+
+    let mut result = vec![0; lig.atoms.len()];
+
+    for i in 0..lig.atoms.len().min(adj.len()) {
+        match el(i) {
+            Element::Oxygen if heavy(i) == 1 => {
+                let Some(&x) = adj[i].iter().find(|&&j| el(j) != Element::Hydrogen) else {
+                    continue;
+                };
+                let terminal_o = adj[x]
+                    .iter()
+                    .filter(|&&j| el(j) == Element::Oxygen && heavy(j) == 1)
+                    .count();
+
+                if matches!(
+                    el(x),
+                    Element::Carbon | Element::Phosphorus | Element::Sulfur
+                ) && terminal_o >= 2
+                {
+                    result[i] = -1;
+                }
+            }
+            Element::Nitrogen => {
+                let quaternary = adj[i].len() == 4;
+                let amine = !unsaturated[i]
+                    && adj[i].iter().all(|&j| {
+                        el(j) == Element::Hydrogen || (el(j) == Element::Carbon && !unsaturated[j])
+                    });
+                // N on a non-aromatic C with 2 or more N, one double-bonded, and no O.
+                let amidine = !aromatic(i)
+                    && adj[i].iter().any(|&c| {
+                        let n_count = adj[c].iter().filter(|&&j| el(j) == Element::Nitrogen);
+                        el(c) == Element::Carbon
+                            && !aromatic(c)
+                            && n_count.count() >= 2
+                            && double_to[c].iter().any(|&j| el(j) == Element::Nitrogen)
+                            && !adj[c].iter().any(|&j| el(j) == Element::Oxygen)
+                    });
+
+                if quaternary || amine || amidine {
+                    result[i] = 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    result
+}
+
+/// Compute the interaction fingerprint of a ligand pose. `lig_posits` are the ligand's posed atom
+/// positions.
+pub fn calc_plif(
+    rec: &Molecule,
+    lig: &Molecule,
+    lig_posits: &[Vec3],
+    h_bond_cfg: &HBondConfig,
+) -> Plif {
+    let mut result = Plif::default();
+    if lig_posits.len() != lig.atoms.len() {
+        return result;
+    }
+
+    let near: Vec<usize> = (0..rec.atoms.len())
+        .filter(|&i| {
+            let posit = rec.atoms[i].posit;
+            lig_posits
+                .iter()
+                .any(|p| (*p - posit).magnitude() < NEAR_DIST)
+        })
+        .collect();
+    let res_of = |i: usize| rec.atoms[i].residue;
+
+    // Hydrogen bonds. Run in each direction, so we know which side donates.
+    let rec_atoms: Vec<_> = near.iter().map(|&i| rec.atoms[i].clone()).collect();
+    let rec_bonds: Vec<_> = rec
+        .bonds
+        .iter()
+        .filter(|b| near.contains(&b.atom_0) && near.contains(&b.atom_1))
+        .cloned()
+        .collect();
+
+    let mut lig_atoms = lig.atoms.clone();
+    for (atom, posit) in lig_atoms.iter_mut().zip(lig_posits) {
+        atom.posit = *posit;
+    }
+    let lig_indices: Vec<_> = (0..lig.atoms.len()).collect();
+
+    for hb in create_hydrogen_bonds_one_way(
+        &rec_atoms,
+        &near,
+        &rec_bonds,
+        &lig_atoms,
+        &lig_indices,
+        false,
+        h_bond_cfg,
+    ) {
+        result.set(res_of(hb.donor), PlifBit::HBondDonor);
+    }
+    for hb in create_hydrogen_bonds_one_way(
+        &lig_atoms,
+        &lig_indices,
+        &lig.bonds,
+        &rec_atoms,
+        &near,
+        false,
+        h_bond_cfg,
+    ) {
+        result.set(res_of(hb.acceptor), PlifBit::HBondAcceptor);
+    }
+
+    // Hydrophobic contacts, and ionic interactions.
+    let (rec_cations, rec_anions) = charged_atoms(rec);
+    let lig_charges = lig_charges(lig);
+
+    for &i_rec in &near {
+        let posit = rec.atoms[i_rec].posit;
+        let hydrophobic = is_hydrophobic_c(rec, i_rec);
+        let charge = if rec_cations.contains(&i_rec) {
+            1
+        } else if rec_anions.contains(&i_rec) {
+            -1
+        } else {
+            0
+        };
+
+        for (i_lig, lig_posit) in lig_posits.iter().enumerate() {
+            let dist = (*lig_posit - posit).magnitude();
+
+            let lig_hydrophobic = is_hydrophobic_c(lig, i_lig)
+                || matches!(
+                    lig.atoms[i_lig].element,
+                    Element::Chlorine | Element::Bromine | Element::Iodine
+                );
+            if hydrophobic && lig_hydrophobic && dist <= HYDROPHOBIC_DIST {
+                result.set(res_of(i_rec), PlifBit::Hydrophobic);
+            }
+
+            if charge != 0 && charge == -lig_charges[i_lig] && dist <= IONIC_DIST {
+                result.set(res_of(i_rec), PlifBit::Ionic);
+            }
+        }
+    }
+
+    // π-stacking, between the receptor's rings and the ligand's, at its pose.
+    for lig_ring in &lig.rings_aromatic {
+        let lig_ring = Ring::new(lig_posits, lig_ring.atoms.clone());
+
+        for rec_ring in &rec.rings_aromatic {
+            if stack_kind(rec_ring, &lig_ring).is_some() {
+                result.set(res_of(rec_ring.atoms[0]), PlifBit::Stacking);
+            }
+        }
+    }
+
+    result
+}
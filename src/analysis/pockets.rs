@@ -0,0 +1,309 @@
+//! Binding pocket and cavity detection. Grid-based, similar to
+//! [LIGSITE](https://doi.org/10.1016/S1093-3263(98)00002-3): We mark grid points occupied by the
+//! protein, then score each empty point by how enclosed it is: the number of scan directions in
+//! which the protein lies on both sides of it. Well-enclosed points are clustered into pockets.
+//!
+//! Pockets are ranked by a rough druggability estimate, built from volume, enclosure, and how
+//! hydrophobic the lining residues are.
+
+use std::collections::VecDeque;
+
+use bio_files::ResidueType;
+use graphics::Mesh;
+use lin_alg::{f32::Vec3 as Vec3F32, f64::Vec3};
+
+use crate::{
+    docking::DockingSite,
+    molecule::{Atom, Molecule, hydropathy},
+    sa_surface::{atoms_near_points, make_sphere_union_mesh},
+};
+
+/// Å. Grid edge length. Each pocket point represents this, cubed, of volume.
+const GRID_SPACING: f64 = 1.;
+/// Å. Added to the VDW radius when marking grid points as occupied by an atom.
+const OCCUPIED_PAD: f64 = 0.5;
+/// Å. How far along each scan direction we look for protein, from an empty point.
+const SCAN_DIST: f64 = 10.;
+/// Out of the 7 scan directions. Empty points enclosed in at least this many are pocket points.
+const MIN_BURIEDNESS: u8 = 5;
+/// Clusters smaller than this are discarded as noise. Å³, with 1Å grid spacing.
+const MIN_POCKET_POINTS: usize = 30;
+/// Å. Atoms within this distance of a pocket point line the pocket.
+const LINING_DIST: f32 = 4.;
+/// Å³. Pockets at or above this volume get the full volume score.
+const DRUGGABLE_VOLUME: f64 = 500.;
+
+/// Radius of the spheres drawn at each point, for the pocket mesh. Slightly above half the grid
+/// spacing, so neighboring points merge.
+const MESH_POINT_RADIUS: f32 = 0.8;
+const MESH_PRECISION: f32 = 0.5;
+
+/// The x, y, and z axes, and the 4 body diagonals.
+const SCAN_DIRS: [(i32, i32, i32); 7] = [
+    (1, 0, 0),
+    (0, 1, 0),
+    (0, 0, 1),
+    (1, 1, 1),
+    (1, 1, -1),
+    (1, -1, 1),
+    (-1, 1, 1),
+];
+
+#[derive(Clone, Debug)]
+pub struct Pocket {
+    /// Grid points making up the pocket.
+    pub points: Vec<Vec3>,
+    pub center: Vec3,
+    /// Å³
+    pub volume: f64,
+    /// Mean number of scan directions enclosed, out of 7.
+    pub buriedness: f32,
+    /// Indices into the molecule's residues.
+    pub lining_residues: Vec<usize>,
+    /// 0 to 1. The fraction of lining amino acids that are hydrophobic.
+    pub hydrophobic_frac: f32,
+    /// 0 to 1. A heuristic; higher is more likely to bind a drug-like molecule.
+    pub druggability: f32,
+}
+
+impl Pocket {
+    /// A docking site centered on the pocket, large enough to contain all of its points.
+    pub fn docking_site(&self) -> DockingSite {
+        let radius = self
+            .points
+            .iter()
+            .map(|p| (*p - self.center).magnitude())
+            .fold(0., f64::max);
+
+        DockingSite {
+            site_center: self.center,
+            site_radius: radius + GRID_SPACING,
+        }
+    }
+}
+
+/// Find pockets in the molecule's non-hetero atoms, ranked by druggability, descending.
+pub fn find_pockets(mol: &Molecule) -> Vec<Pocket> {
+    // We exclude hetero atoms so that pockets occupied by a ligand are still found.
+    let atoms: Vec<&Atom> = mol.atoms.iter().filter(|a| !a.hetero).collect();
+    if atoms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut bb_min = Vec3::new(f64::MAX, f64::MAX, f64::MAX);
+    let mut bb_max = Vec3::new(f64::MIN, f64::MIN, f64::MIN);
+    for a in &atoms {
+        bb_min = Vec3::new(
+            bb_min.x.min(a.posit.x),
+            bb_min.y.min(a.posit.y),
+            bb_min.z.min(a.posit.z),
+        );
+        bb_max = Vec3::new(
+            bb_max.x.max(a.posit.x),
+            bb_max.y.max(a.posit.y),
+            bb_max.z.max(a.posit.z),
+        );
+    }
+
+    let dims = (
+        ((bb_max.x - bb_min.x) / GRID_SPACING).ceil() as usize + 1,
+        ((bb_max.y - bb_min.y) / GRID_SPACING).ceil() as usize + 1,
+        ((bb_max.z - bb_min.z) / GRID_SPACING).ceil() as usize + 1,
+    );
+
+    let idx = |x: usize, y: usize, z: usize| (z * dims.1 + y) * dims.0 + x;
+    let posit = |x: usize, y: usize, z: usize| {
+        bb_min + Vec3::new(x as f64, y as f64, z as f64) * GRID_SPACING
+    };
+
+    // Mark points occupied by atoms.
+    let mut occupied = vec![false; dims.0 * dims.1 * dims.2];
+    for a in &atoms {
+        let rad = a.element.vdw_radius() as f64 + OCCUPIED_PAD;
+        let lo = (a.posit - Vec3::splat(rad) - bb_min) / GRID_SPACING;
+        let hi = (a.posit + Vec3::splat(rad) - bb_min) / GRID_SPACING;
+
+        let x1 = (hi.x.ceil() as usize).min(dims.0 - 1);
+        let y1 = (hi.y.ceil() as usize).min(dims.1 - 1);
+        let z1 = (hi.z.ceil() as usize).min(dims.2 - 1);
+
+        for z in lo.z.floor().max(0.) as usize..=z1 {
+            for y in lo.y.floor().max(0.) as usize..=y1 {
+                for x in lo.x.floor().max(0.) as usize..=x1 {
+                    if (posit(x, y, z) - a.posit).magnitude_squared() < rad * rad {
+                        occupied[idx(x, y, z)] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns true if there is an occupied point along the direction, within the scan distance.
+    let hits = |x: usize, y: usize, z: usize, (dx, dy, dz): (i32, i32, i32)| -> bool {
+        let step_len = GRID_SPACING * ((dx * dx + dy * dy + dz * dz) as f64).sqrt();
+        let n_steps = (SCAN_DIST / step_len) as i32;
+
+        for i in 1..=n_steps {
+            let (px, py, pz) = (x as i32 + dx * i, y as i32 + dy * i, z as i32 + dz * i);
+            if px < 0
+                || py < 0
+                || pz < 0
+                || px as usize >= dims.0
+                || py as usize >= dims.1
+                || pz as usize >= dims.2
+            {
+                return false;
+            }
+            if occupied[idx(px as usize, py as usize, pz as usize)] {
+                return true;
+            }
+        }
+        false
+    };
+
+    // Buriedness of each empty point; 0 for occupied ones.
+    let mut buriedness = vec![0_u8; occupied.len()];
+    for z in 0..dims.2 {
+        for y in 0..dims.1 {
+            for x in 0..dims.0 {
+                let i = idx(x, y, z);
+                if occupied[i] {
+                    continue;
+                }
+                for (dx, dy, dz) in SCAN_DIRS {
+                    if hits(x, y, z, (dx, dy, dz)) && hits(x, y, z, (-dx, -dy, -dz)) {
+                        buriedness[i] += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // Cluster pocket points by flood fill over face neighbors.
+    let mut visited = vec![false; occupied.len()];
+    let mut result = Vec::new();
+
+    for z in 0..dims.2 {
+        for y in 0..dims.1 {
+            for x in 0..dims.0 {
+                let i = idx(x, y, z);
+                if visited[i] || buriedness[i] < MIN_BURIEDNESS {
+                    continue;
+                }
+
+                let mut cluster = Vec::new();
+                let mut queue = VecDeque::from([(x, y, z)]);
+                visited[i] = true;
+
+                while let Some((cx, cy, cz)) = queue.pop_front() {
+                    cluster.push((cx, cy, cz));
+
+                    for (dx, dy, dz) in [
+                        (1, 0, 0),
+                        (-1, 0, 0),
+                        (0, 1, 0),
+                        (0, -1, 0),
+                        (0, 0, 1),
+                        (0, 0, -1),
+                    ] {
+                        let (nx, ny, nz) = (cx as i32 + dx, cy as i32 + dy, cz as i32 + dz);
+                        if nx < 0
+                            || ny < 0
+                            || nz < 0
+                            || nx as usize >= dims.0
+                            || ny as usize >= dims.1
+                            || nz as usize >= dims.2
+                        {
+                            continue;
+                        }
+                        let n = idx(nx as usize, ny as usize, nz as usize);
+                        if !visited[n] && buriedness[n] >= MIN_BURIEDNESS {
+                            visited[n] = true;
+                            queue.push_back((nx as usize, ny as usize, nz as usize));
+                        }
+                    }
+                }
+
+                if cluster.len() < MIN_POCKET_POINTS {
+                    continue;
+                }
+
+                let buried_sum: u32 = cluster
+                    .iter()
+                    .map(|&(x, y, z)| buriedness[idx(x, y, z)] as u32)
+                    .sum();
+                let points: Vec<_> = cluster.iter().map(|&(x, y, z)| posit(x, y, z)).collect();
+
+                result.push(make_pocket(
+                    mol,
+                    &atoms,
+                    points,
+                    buried_sum as f32 / cluster.len() as f32,
+                ));
+            }
+        }
+    }
+
+    result.sort_by(|a, b| b.druggability.total_cmp(&a.druggability));
+    result
+}
+
+fn make_pocket(mol: &Molecule, atoms: &[&Atom], points: Vec<Vec3>, buriedness: f32) -> Pocket {
+    let mut center = Vec3::new_zero();
+    for p in &points {
+        center += *p;
+    }
+    center = center / points.len() as f64;
+
+    let volume = points.len() as f64 * GRID_SPACING.powi(3);
+
+    let points_f32: Vec<Vec3F32> = points.iter().map(|p| (*p).into()).collect();
+    let mut lining_residues: Vec<usize> = atoms_near_points(&points_f32, atoms, LINING_DIST)
+        .into_iter()
+        .flatten()
+        .filter_map(|i| atoms[i].residue)
+        .collect();
+    lining_residues.sort_unstable();
+    lining_residues.dedup();
+
+    let mut n_aa = 0;
+    let mut n_hydrophobic = 0;
+    for &i in &lining_residues {
+        if let ResidueType::AminoAcid(aa) = mol.residues[i].res_type {
+            n_aa += 1;
+            if hydropathy(aa) > 0. {
+                n_hydrophobic += 1;
+            }
+        }
+    }
+    let hydrophobic_frac = if n_aa == 0 {
+        0.
+    } else {
+        n_hydrophobic as f32 / n_aa as f32
+    };
+
+    let volume_score = (volume / DRUGGABLE_VOLUME).min(1.) as f32;
+    let enclosure_score = (buriedness - MIN_BURIEDNESS as f32) / (7 - MIN_BURIEDNESS) as f32;
+    let druggability = 0.4 * volume_score + 0.3 * enclosure_score + 0.3 * hydrophobic_frac;
+
+    Pocket {
+        points,
+        center,
+        volume,
+        buriedness,
+        lining_residues,
+        hydrophobic_frac,
+        druggability,
+    }
+}
+
+/// A single mesh covering the points of all pockets passed.
+pub fn make_pocket_mesh(pockets: &[&Pocket]) -> Mesh {
+    let spheres: Vec<_> = pockets
+        .iter()
+        .flat_map(|pocket| &pocket.points)
+        .map(|p| ((*p).into(), MESH_POINT_RADIUS))
+        .collect();
+
+    make_sphere_union_mesh(&spheres, MESH_PRECISION)
+}
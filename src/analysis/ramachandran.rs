@@ -0,0 +1,198 @@
+//! Backbone φ/ψ dihedral angles, and their classification into Ramachandran regions. Residues in
+//! disallowed regions often indicate modeling errors, or strained conformations worth inspecting.
+
+use std::{
+    f64::consts::{PI, TAU},
+    fmt,
+};
+
+use bio_files::ResidueType;
+use lin_alg::f64::{Vec3, calc_dihedral_angle_v2};
+use na_seq::AminoAcid;
+
+use crate::{
+    aa_coords::Dihedral,
+    molecule::{AtomRole, Molecule},
+};
+
+/// Å. C'-N distances above this indicate a chain break; no φ, ψ, or ω is computed across it.
+pub const PEPTIDE_BOND_MAX: f64 = 2.0;
+
+/// (φ min, φ max, ψ min, ψ max), in degrees. Coarse rectangular approximations of the contours in
+/// Lovell et al, 2003. Adequate for flagging residues for inspection; not a substitute for
+/// full validation.
+pub type RamaRect = (f64, f64, f64, f64);
+
+pub const FAVORED_GENERAL: [RamaRect; 2] = [
+    // β sheet
+    (-180., -45., 90., 180.),
+    // Right-handed α helix
+    (-160., -45., -75., -5.),
+];
+
+pub const ALLOWED_GENERAL: [RamaRect; 3] = [
+    (-180., -25., -100., 180.),
+    (-180., -25., -180., -150.),
+    // Left-handed α helix
+    (30., 100., -20., 90.),
+];
+
+const FAVORED_PRO: [RamaRect; 2] = [(-95., -45., -60., -10.), (-95., -45., 100., 180.)];
+
+const ALLOWED_PRO: [RamaRect; 1] = [(-110., -35., -180., 180.)];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RamaRegion {
+    Favored,
+    Allowed,
+    Outlier,
+}
+
+impl fmt::Display for RamaRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::Favored => "Favored",
+            Self::Allowed => "Allowed",
+            Self::Outlier => "Outlier",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RamaPoint {
+    /// Residue index, in the molecule.
+    pub res: usize,
+    /// Radians, in (-π, π].
+    pub φ: f64,
+    /// Radians, in (-π, π].
+    pub ψ: f64,
+    pub region: RamaRegion,
+}
+
+/// Radians, to (-π, π].
+fn wrap_angle(a: f64) -> f64 {
+    let result = (a + PI).rem_euclid(TAU) - PI;
+    if result == -PI { PI } else { result }
+}
+
+fn in_rects(rects: &[RamaRect], φ: f64, ψ: f64) -> bool {
+    rects
+        .iter()
+        .any(|(φ_0, φ_1, ψ_0, ψ_1)| φ >= *φ_0 && φ <= *φ_1 && ψ >= *ψ_0 && ψ <= *ψ_1)
+}
+
+/// φ and ψ are in radians. Glycine's distribution is approximately symmetric about the origin, so
+/// it's classified using the general regions, and their inversion.
+pub fn classify(aa: AminoAcid, φ: f64, ψ: f64) -> RamaRegion {
+    let (φ, ψ) = (φ.to_degrees(), ψ.to_degrees());
+
+    let (favored, allowed): (&[RamaRect], &[RamaRect]) = match aa {
+        AminoAcid::Pro => (&FAVORED_PRO, &ALLOWED_PRO),
+        _ => (&FAVORED_GENERAL, &ALLOWED_GENERAL),
+    };
+
+    let check = |rects: &[RamaRect]| {
+        in_rects(rects, φ, ψ) || (aa == AminoAcid::Gly && in_rects(rects, -φ, -ψ))
+    };
+
+    if check(favored) {
+        RamaRegion::Favored
+    } else if check(allowed) {
+        RamaRegion::Allowed
+    } else {
+        RamaRegion::Outlier
+    }
+}
+
+/// (N, Cα, C') positions.
+fn backbone(mol: &Molecule, res: usize) -> Option<(Vec3, Vec3, Vec3)> {
+    let (mut n, mut ca, mut cp) = (None, None, None);
+
+    for &i in &mol.residues[res].atoms {
+        let atom = &mol.atoms[i];
+        match atom.role {
+            Some(AtomRole::N_Backbone) => n = Some(atom.posit),
+            Some(AtomRole::C_Alpha) => ca = Some(atom.posit),
+            Some(AtomRole::C_Prime) => cp = Some(atom.posit),
+            _ => (),
+        }
+    }
+
+    Some((n?, ca?, cp?))
+}
+
+/// Compute backbone dihedrals (ω, φ, ψ) from atom positions, for every amino acid residue, and
+/// store them in each residue's `dihedral` field. Existing sidechain angles are preserved.
+pub fn calc_backbone_dihedrals(mol: &mut Molecule) {
+    let mut updates = Vec::new();
+
+    for chain in &mol.chains {
+        let bb: Vec<_> = chain
+            .residues
+            .iter()
+            .filter(|&&r| matches!(mol.residues[r].res_type, ResidueType::AminoAcid(_)))
+            .map(|&r| (r, backbone(mol, r)))
+            .collect();
+
+        for (k, (r, this)) in bb.iter().enumerate() {
+            let Some((n, ca, cp)) = *this else {
+                continue;
+            };
+
+            let prev = k
+                .checked_sub(1)
+                .and_then(|j| bb[j].1)
+                .filter(|(_, _, cp_prev)| (n - *cp_prev).magnitude() < PEPTIDE_BOND_MAX);
+            let next = bb
+                .get(k + 1)
+                .and_then(|(_, b)| *b)
+                .filter(|(n_next, _, _)| (*n_next - cp).magnitude() < PEPTIDE_BOND_MAX);
+
+            let mut dihedral = Dihedral::default();
+            if let Some((_, ca_prev, cp_prev)) = prev {
+                dihedral.φ = Some(wrap_angle(calc_dihedral_angle_v2(&(cp_prev, n, ca, cp))));
+                dihedral.ω = Some(wrap_angle(calc_dihedral_angle_v2(&(
+                    ca_prev, cp_prev, n, ca,
+                ))));
+            }
+            if let Some((n_next, _, _)) = next {
+                dihedral.ψ = Some(wrap_angle(calc_dihedral_angle_v2(&(n, ca, cp, n_next))));
+            }
+
+            updates.push((*r, dihedral));
+        }
+    }
+
+    for (r, mut dihedral) in updates {
+        let res = &mut mol.residues[r];
+        if let Some(existing) = res.dihedral.take() {
+            dihedral.sidechain = existing.sidechain;
+        }
+        res.dihedral = Some(dihedral);
+    }
+}
+
+/// Ramachandran points for all amino acid residues that have both φ and ψ. Run
+/// `calc_backbone_dihedrals` first.
+pub fn ramachandran(mol: &Molecule) -> Vec<RamaPoint> {
+    mol.residues
+        .iter()
+        .enumerate()
+        .filter_map(|(i, res)| {
+            let ResidueType::AminoAcid(aa) = res.res_type else {
+                return None;
+            };
+            let dihedral = res.dihedral.as_ref()?;
+            let (φ, ψ) = (wrap_angle(dihedral.φ?), wrap_angle(dihedral.ψ?));
+
+            Some(RamaPoint {
+                res: i,
+                φ,
+                ψ,
+                region: classify(aa, φ, ψ),
+            })
+        })
+        .collect()
+}
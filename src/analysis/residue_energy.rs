@@ -0,0 +1,151 @@
+//! Decompose the nonbonded interaction energy between a ligand pose and the receptor by receptor
+//! residue: Lennard-Jones and Coulomb terms, summed over each residue's atoms. Residues with
+//! large, favorable totals are binding hot spots; repulsive ones point to clashes or buried
+//! like charges.
+//!
+//! This uses the same element-pair Lennard-Jones table as docking, and partial charges from the
+//! atoms. Receptor atoms without charges contribute no electrostatics.
+
+use lin_alg::f64::Vec3;
+use na_seq::element::LjTable;
+
+use crate::{
+    docking::partial_charge::gasteiger_charges,
+    dynamics::{V_coulomb, V_lj},
+    molecule::Molecule,
+};
+
+/// Å. Pairs farther apart than this are omitted.
+pub const RES_ENERGY_CUTOFF: f64 = 12.;
+/// Å². Keeps the Coulomb term finite for overlapping atoms.
+const SOFTENING_FACTOR_SQ: f64 = 1e-6;
+
+/// Interaction energy between the ligand and one receptor residue.
+#[derive(Clone, Debug)]
+pub struct ResidueEnergy {
+    /// Index into the receptor's residues.
+    pub residue: usize,
+    /// kcal/mol
+    pub lj: f64,
+    /// kcal/mol
+    pub coulomb: f64,
+}
+
+impl ResidueEnergy {
+    /// kcal/mol
+    pub fn total(&self) -> f64 {
+        self.lj + self.coulomb
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EnergyDecomp {
+    /// Residues within the cutoff of any ligand atom, sorted by total energy, ascending; the most
+    /// favorable first.
+    pub residues: Vec<ResidueEnergy>,
+    /// kcal/mol. Summed over all residues.
+    pub lj: f64,
+    pub coulomb: f64,
+}
+
+impl EnergyDecomp {
+    /// kcal/mol
+    pub fn total(&self) -> f64 {
+        self.lj + self.coulomb
+    }
+
+    /// The total energy of each receptor residue, indexed by residue; 0 for ones out of range.
+    /// E.g. for storing as a residue property.
+    pub fn per_residue(&self, res_count: usize) -> Vec<f64> {
+        let mut result = vec![0.; res_count];
+        for r in &self.residues {
+            result[r.residue] = r.total();
+        }
+        result
+    }
+
+    /// Residues at least as favorable as `thresh`, in kcal/mol, in ranked order.
+    pub fn hot_spots(&self, thresh: f64) -> impl Iterator<Item = &ResidueEnergy> {
+        self.residues
+            .iter()
+            .take_while(move |r| r.total() <= thresh)
+    }
+}
+
+/// The ligand's partial charges, falling back to Gasteiger charges if any are missing.
+pub fn lig_charges(lig: &Molecule) -> Vec<f64> {
+    if lig.atoms.iter().any(|a| a.partial_charge.is_none()) {
+        let q = gasteiger_charges(&lig.atoms, &lig.bonds);
+        lig.atoms
+            .iter()
+            .zip(q)
+            .map(|(a, q)| a.partial_charge.unwrap_or(q) as f64)
+            .collect()
+    } else {
+        lig.atoms
+            .iter()
+            .map(|a| a.partial_charge.unwrap_or_default() as f64)
+            .collect()
+    }
+}
+
+/// Decompose the interaction energy between the ligand, at `lig_posits`, and the receptor, by
+/// receptor residue. Ligands without partial charges are assigned Gasteiger charges.
+pub fn decompose_energy(
+    rec: &Molecule,
+    lig: &Molecule,
+    lig_posits: &[Vec3],
+    lj_lut: &LjTable,
+) -> EnergyDecomp {
+    let mut result = EnergyDecomp::default();
+    if lig_posits.len() != lig.atoms.len() {
+        return result;
+    }
+
+    let lig_charges = lig_charges(lig);
+
+    // (LJ, Coulomb, if any pair is in range), by residue.
+    let mut per_res = vec![(0., 0., false); rec.residues.len()];
+
+    for atom_rec in &rec.atoms {
+        let Some(res) = atom_rec.residue else {
+            continue;
+        };
+        let q_rec = atom_rec.partial_charge.unwrap_or_default() as f64;
+
+        for ((atom_lig, posit_lig), q_lig) in lig.atoms.iter().zip(lig_posits).zip(&lig_charges) {
+            let dist = (atom_rec.posit - *posit_lig).magnitude();
+            if dist > RES_ENERGY_CUTOFF {
+                continue;
+            }
+
+            let Some((σ, ε)) = lj_lut.get(&(atom_rec.element, atom_lig.element)) else {
+                continue;
+            };
+
+            let entry = &mut per_res[res];
+            entry.0 += V_lj(dist, *σ as f64, *ε as f64);
+            entry.1 += V_coulomb(dist, q_rec, *q_lig, SOFTENING_FACTOR_SQ);
+            entry.2 = true;
+        }
+    }
+
+    for (residue, (lj, coulomb, in_range)) in per_res.into_iter().enumerate() {
+        if !in_range {
+            continue;
+        }
+        result.lj += lj;
+        result.coulomb += coulomb;
+        result.residues.push(ResidueEnergy {
+            residue,
+            lj,
+            coulomb,
+        });
+    }
+
+    result
+        .residues
+        .sort_by(|a, b| a.total().total_cmp(&b.total()));
+
+    result
+}
@@ -0,0 +1,48 @@
+//! Aromatic ring geometry; e.g. of Phe, Tyr, Trp, and His sidechains, nucleobases, and aromatic
+//! ligands. These are the rings that participate in π-stacking and cation-π interactions.
+
+use lin_alg::f64::Vec3;
+
+use crate::molecule::Molecule;
+
+#[derive(Clone, Debug)]
+pub struct Ring {
+    /// Atom indices, in order around the ring.
+    pub atoms: Vec<usize>,
+    pub center: Vec3,
+    /// Unit vector perpendicular to the ring's plane.
+    pub normal: Vec3,
+}
+
+impl Ring {
+    /// `posits` are for all atoms in the molecule; e.g. a ligand's posed positions.
+    pub fn new(posits: &[Vec3], atoms: Vec<usize>) -> Self {
+        let posits: Vec<_> = atoms.iter().map(|&i| posits[i]).collect();
+        let center = posits.iter().fold(Vec3::new_zero(), |acc, p| acc + *p) / posits.len() as f64;
+
+        // Newell's method: the sum of cross products of consecutive atoms, relative to the center.
+        let mut normal = Vec3::new_zero();
+        for (k, p) in posits.iter().enumerate() {
+            let next = posits[(k + 1) % posits.len()];
+            normal = normal + (*p - center).cross(next - center);
+        }
+
+        Self {
+            atoms,
+            center,
+            normal: normal.to_normalized(),
+        }
+    }
+}
+
+/// Aromatic rings, from ring perception; see `bond_inference::perceive_rings`. Fused systems,
+/// e.g. Trp's indole, are returned as their individual rings.
+pub fn find_aromatic_rings(mol: &Molecule) -> Vec<Ring> {
+    let posits: Vec<_> = mol.atoms.iter().map(|a| a.posit).collect();
+
+    mol.rings
+        .iter()
+        .filter(|ring| ring.iter().all(|&i| mol.atoms[i].aromatic))
+        .map(|ring| Ring::new(&posits, ring.clone()))
+        .collect()
+}
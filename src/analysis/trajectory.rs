@@ -0,0 +1,270 @@
+//! Time series from an MD trajectory: RMSD to the first frame, per-residue RMSF, radius of
+//! gyration, and, if a ligand is present, its RMSD and distance from the binding pocket.
+//!
+//! Receptor metrics cover the receptor atoms that move in the simulation: all protein atoms in
+//! protein MD, or flexible residues in docking MD. If enough Cα atoms move, each frame is
+//! superposed onto the first over them, so overall rotation and drift don't count as deviation.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use lin_alg::f64::Vec3;
+use na_seq::Element;
+
+use crate::{
+    dynamics::MdState,
+    molecule::{AtomRole, Molecule},
+    superpose::{kabsch, rmsd},
+};
+
+/// Å. Receptor heavy atoms this close to a ligand heavy atom in the first frame define the pocket.
+const POCKET_DIST: f64 = 6.;
+
+#[derive(Clone, Debug, Default)]
+pub struct TrajFrame {
+    /// fs
+    pub time: f64,
+    /// Å. Of the moving receptor heavy atoms.
+    pub rmsd: f64,
+    /// Å. Of the moving receptor heavy atoms, unweighted by mass.
+    pub rg: f64,
+    /// Å. Of the ligand's heavy atoms, in the receptor's frame.
+    pub lig_rmsd: Option<f64>,
+    /// Å. From the ligand's heavy-atom centroid to the pocket's.
+    pub pocket_dist: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResidueRmsf {
+    /// Index into the receptor's residues.
+    pub residue: usize,
+    /// Å. Averaged over the residue's moving heavy atoms.
+    pub rmsf: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TrajAnalysis {
+    pub frames: Vec<TrajFrame>,
+    pub rmsf: Vec<ResidueRmsf>,
+    /// If frames were superposed on Cα atoms.
+    pub fitted: bool,
+}
+
+impl TrajAnalysis {
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "time_fs,rmsd_a,rg_a,lig_rmsd_a,pocket_dist_a")?;
+        let opt = |v: Option<f64>| v.map(|v| format!("{v:.4}")).unwrap_or_default();
+
+        for f in &self.frames {
+            writeln!(
+                file,
+                "{:.3},{:.4},{:.4},{},{}",
+                f.time,
+                f.rmsd,
+                f.rg,
+                opt(f.lig_rmsd),
+                opt(f.pocket_dist)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_rmsf_csv(&self, mol: &Molecule, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "residue,rmsf_a")?;
+        for r in &self.rmsf {
+            let serial = mol
+                .residues
+                .get(r.residue)
+                .map(|res| res.serial_number)
+                .unwrap_or_default();
+            writeln!(file, "{serial},{:.4}", r.rmsf)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn centroid(posits: &[Vec3]) -> Vec3 {
+    let mut sum = Vec3::new_zero();
+    for p in posits {
+        sum += *p;
+    }
+    sum / posits.len().max(1) as f64
+}
+
+/// Compute metrics over all of `md`'s snapshots. `lig` is the ligand simulated with the receptor,
+/// if any. Returns `None` if there are no snapshots.
+pub fn analyze_trajectory(
+    mol: &Molecule,
+    lig: Option<&Molecule>,
+    md: &MdState,
+) -> Option<TrajAnalysis> {
+    let first = md.snapshots.first()?;
+
+    // Receptor heavy atoms that move, and where to find them in snapshots.
+    let (tracked, tracked_md): (Vec<_>, Vec<_>) = md
+        .flexible
+        .iter()
+        .filter(|(_, i_rec)| {
+            mol.atoms
+                .get(*i_rec)
+                .is_some_and(|a| a.element != Element::Hydrogen)
+        })
+        .map(|(i_md, i_rec)| (*i_rec, *i_md))
+        .unzip();
+
+    let mut rec_to_tracked = vec![None; mol.atoms.len()];
+    for (t, i_rec) in tracked.iter().enumerate() {
+        rec_to_tracked[*i_rec] = Some(t);
+    }
+
+    // Indices into `tracked`.
+    let fit_atoms: Vec<_> = (0..tracked.len())
+        .filter(|i| mol.atoms[tracked[*i]].role == Some(AtomRole::C_Alpha))
+        .collect();
+    let fitted = fit_atoms.len() >= 3;
+
+    // Ligand heavy atoms, by index in the ligand and in snapshots, which are the same.
+    let lig_heavy: Vec<_> = match lig {
+        Some(lig) if md.has_ligand() => (0..lig.atoms.len())
+            .filter(|i| lig.atoms[*i].element != Element::Hydrogen)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let rec_posits = |snap_posits: &[Vec3]| -> Vec<Vec3> {
+        tracked_md
+            .iter()
+            .map(|i| snap_posits.get(*i).copied().unwrap_or(Vec3::new_zero()))
+            .collect()
+    };
+    let lig_posits = |snap_posits: &[Vec3]| -> Vec<Vec3> {
+        lig_heavy
+            .iter()
+            .map(|i| snap_posits.get(*i).copied().unwrap_or(Vec3::new_zero()))
+            .collect()
+    };
+
+    let ref_rec = rec_posits(&first.atom_posits);
+    let ref_lig = lig_posits(&first.atom_posits);
+    let ref_fit: Vec<_> = fit_atoms.iter().map(|i| ref_rec[*i]).collect();
+
+    // The pocket: Receptor heavy atoms near the ligand in the first frame. Atoms that move are
+    // read from each frame; static ones stay put.
+    let pocket: Vec<_> = if ref_lig.is_empty() {
+        Vec::new()
+    } else {
+        let first_posit = |i_rec: usize| match rec_to_tracked[i_rec] {
+            Some(t) => ref_rec[t],
+            None => mol.atoms[i_rec].posit,
+        };
+        (0..mol.atoms.len())
+            .filter(|i| !mol.atoms[*i].hetero && mol.atoms[*i].element != Element::Hydrogen)
+            .filter(|i| {
+                let p = first_posit(*i);
+                ref_lig.iter().any(|l| (*l - p).magnitude() <= POCKET_DIST)
+            })
+            .map(|i| (i, rec_to_tracked[i]))
+            .collect()
+    };
+
+    let mut frames = Vec::with_capacity(md.snapshots.len());
+    // Fitted receptor positions, for RMSF.
+    let mut rec_frames = Vec::with_capacity(md.snapshots.len());
+
+    for snap in &md.snapshots {
+        let mut rec = rec_posits(&snap.atom_posits);
+        let mut lig = lig_posits(&snap.atom_posits);
+
+        if fitted {
+            let fit: Vec<_> = fit_atoms.iter().map(|i| rec[*i]).collect();
+            if let Some(xform) = kabsch(&fit, &ref_fit) {
+                for p in rec.iter_mut().chain(lig.iter_mut()) {
+                    *p = xform.apply(*p);
+                }
+            }
+        }
+
+        let rg = if rec.is_empty() {
+            0.
+        } else {
+            let center = centroid(&rec);
+            let sum_sq: f64 = rec.iter().map(|p| (*p - center).magnitude_squared()).sum();
+            (sum_sq / rec.len() as f64).sqrt()
+        };
+
+        let (lig_rmsd, pocket_dist) = if lig.is_empty() || pocket.is_empty() {
+            (None, None)
+        } else {
+            let pocket_posits: Vec<_> = pocket
+                .iter()
+                .map(|(i_rec, t)| match t {
+                    Some(t) => rec[*t],
+                    None => mol.atoms[*i_rec].posit,
+                })
+                .collect();
+            (
+                Some(rmsd(&lig, &ref_lig)),
+                Some((centroid(&lig) - centroid(&pocket_posits)).magnitude()),
+            )
+        };
+
+        frames.push(TrajFrame {
+            time: snap.time,
+            rmsd: rmsd(&rec, &ref_rec),
+            rg,
+            lig_rmsd,
+            pocket_dist,
+        });
+        rec_frames.push(rec);
+    }
+
+    // RMSF: Fluctuation of each atom about its mean position, averaged by residue.
+    let n_frames = rec_frames.len() as f64;
+    // Sum of atom RMSFs, and atom count.
+    let mut by_res = vec![(0., 0); mol.residues.len()];
+
+    for (t, i_rec) in tracked.iter().enumerate() {
+        let Some(res) = mol.atoms[*i_rec].residue.filter(|r| *r < by_res.len()) else {
+            continue;
+        };
+
+        let mean = rec_frames
+            .iter()
+            .map(|f| f[t])
+            .fold(Vec3::new_zero(), |a, p| a + p)
+            / n_frames;
+        let msf = rec_frames
+            .iter()
+            .map(|f| (f[t] - mean).magnitude_squared())
+            .sum::<f64>()
+            / n_frames;
+
+        by_res[res].0 += msf.sqrt();
+        by_res[res].1 += 1;
+    }
+
+    let rmsf = by_res
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (_, count))| *count > 0)
+        .map(|(residue, (sum, count))| ResidueRmsf {
+            residue,
+            rmsf: sum / count as f64,
+        })
+        .collect();
+
+    Some(TrajAnalysis {
+        frames,
+        rmsf,
+        fitted,
+    })
+}
@@ -0,0 +1,446 @@
+//! Structure quality metrics, similar to MolProbity's: clashscore, Ramachandran and rotamer
+//! outliers, and backbone bond length and angle deviations from ideal values. Use as a check
+//! before simulation or docking; modeling errors flagged here often cause problems there.
+//!
+//! Rotamers are checked against staggered χ positions only, and geometry against backbone
+//! ideals only; this flags clear problems, but is coarser than a full validation.
+//!
+//! For experimental structures, residues with high B-factors or low occupancy are listed too; their
+//! positions are less certain.
+
+use std::f64::consts::TAU;
+
+use bio_files::ResidueType;
+use lin_alg::f64::{Vec3, calc_dihedral_angle_v2};
+use na_seq::AminoAcid;
+
+use crate::{
+    analysis::{
+        clashes::{Clash, find_clashes},
+        ramachandran::{PEPTIDE_BOND_MAX, RamaRegion, calc_backbone_dihedrals, ramachandran},
+    },
+    molecule::{Atom, Molecule, Residue},
+};
+
+/// Deviations from ideal of at least this many standard deviations are outliers, as in MolProbity.
+const GEOM_Z_MAX: f64 = 4.;
+/// Radians. χ angles farther than this from all staggered positions (60°, 180°, 300°) are rotamer
+/// outliers; 40°.
+const ROTAMER_TOL: f64 = TAU * 40. / 360.;
+/// Residues whose mean B-factor is at least this many standard deviations above the mean across
+/// residues are flagged.
+const B_FACTOR_Z_MAX: f32 = 2.;
+/// Residues with atoms below this occupancy, other than alternate locations, are flagged.
+const OCCUPANCY_MIN: f32 = 0.5;
+
+// (Ideal, σ), in Å. Engh and Huber, 1991.
+const BOND_N_CA: (f64, f64) = (1.458, 0.019);
+const BOND_CA_C: (f64, f64) = (1.525, 0.021);
+const BOND_C_O: (f64, f64) = (1.231, 0.020);
+/// Peptide bond.
+const BOND_C_N: (f64, f64) = (1.329, 0.014);
+
+// (Ideal, σ), in degrees.
+const ANGLE_N_CA_C: (f64, f64) = (111.2, 2.8);
+const ANGLE_CA_C_O: (f64, f64) = (120.8, 1.7);
+const ANGLE_CA_C_N: (f64, f64) = (116.2, 2.0);
+const ANGLE_O_C_N: (f64, f64) = (123.0, 1.6);
+const ANGLE_C_N_CA: (f64, f64) = (121.7, 1.8);
+
+// (Good max, fair max) for each metric.
+const CLASHSCORE_GRADES: (f64, f64) = (10., 20.);
+const RAMA_UNFAVORED_PCT_GRADES: (f64, f64) = (2., 5.);
+const RAMA_OUTLIER_PCT_GRADES: (f64, f64) = (0.5, 2.);
+const ROTAMER_OUTLIER_PCT_GRADES: (f64, f64) = (1., 3.);
+const RMSZ_GRADES: (f64, f64) = (1., 2.);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Grade {
+    Good,
+    Fair,
+    Poor,
+}
+
+impl Grade {
+    /// Lower values are better.
+    fn new(val: f64, (good_max, fair_max): (f64, f64)) -> Self {
+        if val <= good_max {
+            Self::Good
+        } else if val <= fair_max {
+            Self::Fair
+        } else {
+            Self::Poor
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GeomOutlier {
+    /// Atom indices: 2 for a bond, or 3 for an angle, with the vertex in the middle.
+    pub atoms: Vec<usize>,
+    /// Å for bonds; radians for angles.
+    pub value: f64,
+    pub ideal: f64,
+    /// Deviation from ideal, in standard deviations.
+    pub z: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct RotamerOutlier {
+    /// Residue index.
+    pub res: usize,
+    /// Radians. χ1, and χ2 where it's between sp3 atoms.
+    pub χ: Vec<f64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub clashes: Vec<Clash>,
+    /// Clashes per 1,000 atoms.
+    pub clashscore: f64,
+    /// The number of residues with both φ and ψ.
+    pub rama_count: usize,
+    pub rama_favored: usize,
+    /// Residue indices.
+    pub rama_outliers: Vec<usize>,
+    /// The number of residues with a χ1 angle.
+    pub rotamer_count: usize,
+    pub rotamer_outliers: Vec<RotamerOutlier>,
+    pub bond_outliers: Vec<GeomOutlier>,
+    pub angle_outliers: Vec<GeomOutlier>,
+    /// Root-mean-square Z score, over all backbone bonds checked.
+    pub bond_rmsz: f64,
+    pub angle_rmsz: f64,
+    /// (Residue index, mean B-factor). Highest first.
+    pub high_b_residues: Vec<(usize, f32)>,
+    /// (Residue index, lowest occupancy).
+    pub low_occupancy_residues: Vec<(usize, f32)>,
+}
+
+impl ValidationReport {
+    /// (Name, value, grade) for each summary metric.
+    pub fn metrics(&self) -> Vec<(&'static str, String, Grade)> {
+        let pct = |n: usize, total: usize| {
+            if total == 0 {
+                0.
+            } else {
+                n as f64 * 100. / total as f64
+            }
+        };
+
+        let rama_favored_pct = pct(self.rama_favored, self.rama_count);
+        let rama_outlier_pct = pct(self.rama_outliers.len(), self.rama_count);
+        let rotamer_outlier_pct = pct(self.rotamer_outliers.len(), self.rotamer_count);
+
+        vec![
+            (
+                "Clashscore",
+                format!("{:.1}", self.clashscore),
+                Grade::new(self.clashscore, CLASHSCORE_GRADES),
+            ),
+            (
+                "Ramachandran favored",
+                format!("{rama_favored_pct:.1}%"),
+                Grade::new(100. - rama_favored_pct, RAMA_UNFAVORED_PCT_GRADES),
+            ),
+            (
+                "Ramachandran outliers",
+                format!("{rama_outlier_pct:.1}%"),
+                Grade::new(rama_outlier_pct, RAMA_OUTLIER_PCT_GRADES),
+            ),
+            (
+                "Rotamer outliers",
+                format!("{rotamer_outlier_pct:.1}%"),
+                Grade::new(rotamer_outlier_pct, ROTAMER_OUTLIER_PCT_GRADES),
+            ),
+            (
+                "Bond length RMSZ",
+                format!("{:.2}", self.bond_rmsz),
+                Grade::new(self.bond_rmsz, RMSZ_GRADES),
+            ),
+            (
+                "Bond angle RMSZ",
+                format!("{:.2}", self.angle_rmsz),
+                Grade::new(self.angle_rmsz, RMSZ_GRADES),
+            ),
+        ]
+    }
+}
+
+/// Accumulates deviations from ideal, of one kind of geometry.
+#[derive(Default)]
+struct GeomStats {
+    outliers: Vec<GeomOutlier>,
+    z_sq_sum: f64,
+    count: usize,
+}
+
+impl GeomStats {
+    fn add(&mut self, atoms: Vec<usize>, value: f64, (ideal, σ): (f64, f64)) {
+        let z = (value - ideal) / σ;
+        self.z_sq_sum += z * z;
+        self.count += 1;
+
+        if z.abs() >= GEOM_Z_MAX {
+            self.outliers.push(GeomOutlier {
+                atoms,
+                value,
+                ideal,
+                z,
+            });
+        }
+    }
+
+    fn rmsz(&self) -> f64 {
+        if self.count == 0 {
+            0.
+        } else {
+            (self.z_sq_sum / self.count as f64).sqrt()
+        }
+    }
+}
+
+/// Radians. The angle at `b`.
+fn bond_angle(a: Vec3, b: Vec3, c: Vec3) -> f64 {
+    (a - b)
+        .to_normalized()
+        .dot((c - b).to_normalized())
+        .clamp(-1., 1.)
+        .acos()
+}
+
+/// Angles in degrees, to radians.
+fn to_rad((ideal, σ): (f64, f64)) -> (f64, f64) {
+    (ideal.to_radians(), σ.to_radians())
+}
+
+/// Bond lengths and angles of the backbone, including peptide bonds between consecutive residues.
+fn backbone_geometry(mol: &Molecule) -> (GeomStats, GeomStats) {
+    let mut bonds = GeomStats::default();
+    let mut angles = GeomStats::default();
+    let p = |i: usize| mol.atoms[i].posit;
+
+    for chain in &mol.chains {
+        // (N, Cα, C', O) by residue, in chain order.
+        let bb: Vec<_> = chain
+            .residues
+            .iter()
+            .filter(|&&r| matches!(mol.residues[r].res_type, ResidueType::AminoAcid(_)))
+            .map(|&r| {
+                let atom = |name| mol.atom_in_res(r, name);
+                Some((atom("N")?, atom("CA")?, atom("C")?, atom("O")?))
+            })
+            .collect();
+
+        for (k, this) in bb.iter().enumerate() {
+            let Some((n, ca, c, o)) = *this else {
+                continue;
+            };
+
+            bonds.add(vec![n, ca], (p(ca) - p(n)).magnitude(), BOND_N_CA);
+            bonds.add(vec![ca, c], (p(c) - p(ca)).magnitude(), BOND_CA_C);
+            bonds.add(vec![c, o], (p(o) - p(c)).magnitude(), BOND_C_O);
+            angles.add(
+                vec![n, ca, c],
+                bond_angle(p(n), p(ca), p(c)),
+                to_rad(ANGLE_N_CA_C),
+            );
+            angles.add(
+                vec![ca, c, o],
+                bond_angle(p(ca), p(c), p(o)),
+                to_rad(ANGLE_CA_C_O),
+            );
+
+            let Some((n_next, ca_next, _, _)) = bb.get(k + 1).copied().flatten() else {
+                continue;
+            };
+            let peptide_len = (p(n_next) - p(c)).magnitude();
+            if peptide_len > PEPTIDE_BOND_MAX {
+                continue; // Chain break.
+            }
+
+            bonds.add(vec![c, n_next], peptide_len, BOND_C_N);
+            angles.add(
+                vec![ca, c, n_next],
+                bond_angle(p(ca), p(c), p(n_next)),
+                to_rad(ANGLE_CA_C_N),
+            );
+            angles.add(
+                vec![o, c, n_next],
+                bond_angle(p(o), p(c), p(n_next)),
+                to_rad(ANGLE_O_C_N),
+            );
+            angles.add(
+                vec![c, n_next, ca_next],
+                bond_angle(p(c), p(n_next), p(ca_next)),
+                to_rad(ANGLE_C_N_CA),
+            );
+        }
+    }
+
+    (bonds, angles)
+}
+
+/// χ1, and χ2 where it's between sp3 atoms, from atom positions. `None` for residues without a
+/// χ1, or whose χ angles are constrained by a ring, i.e. Pro.
+fn sidechain_χ(mol: &Molecule, res: usize) -> Option<Vec<f64>> {
+    let ResidueType::AminoAcid(aa) = mol.residues[res].res_type else {
+        return None;
+    };
+
+    // The atoms after CA and CB defining χ1, and after CB and the first of these, χ2.
+    let (χ1_atom, χ2_atom) = match aa {
+        AminoAcid::Ser => ("OG", None),
+        AminoAcid::Cys => ("SG", None),
+        AminoAcid::Thr => ("OG1", None),
+        AminoAcid::Val => ("CG1", None),
+        AminoAcid::Ile => ("CG1", Some("CD1")),
+        AminoAcid::Leu => ("CG", Some("CD1")),
+        AminoAcid::Met => ("CG", Some("SD")),
+        AminoAcid::Lys | AminoAcid::Arg | AminoAcid::Glu | AminoAcid::Gln => ("CG", Some("CD")),
+        AminoAcid::Phe
+        | AminoAcid::Tyr
+        | AminoAcid::Trp
+        | AminoAcid::His
+        | AminoAcid::Asp
+        | AminoAcid::Asn => ("CG", None),
+        _ => return None,
+    };
+
+    let posit = |name| Some(mol.atoms[mol.atom_in_res(res, name)?].posit);
+    let (n, ca, cb, g) = (posit("N")?, posit("CA")?, posit("CB")?, posit(χ1_atom)?);
+
+    let mut result = vec![calc_dihedral_angle_v2(&(n, ca, cb, g))];
+    if let Some(d) = χ2_atom.and_then(posit) {
+        result.push(calc_dihedral_angle_v2(&(ca, cb, g, d)));
+    }
+
+    Some(result)
+}
+
+/// Radians. If near 60°, 180°, or 300°.
+fn is_staggered(χ: f64) -> bool {
+    [TAU / 6., TAU / 2., TAU * 5. / 6.].iter().any(|s| {
+        let diff = (χ - s).rem_euclid(TAU);
+        diff.min(TAU - diff) <= ROTAMER_TOL
+    })
+}
+
+/// Residues with high mean B-factors relative to others, and those with atoms at low occupancy,
+/// other than alternate locations. Waters are skipped; their B-factors are usually high.
+fn poorly_ordered(mol: &Molecule) -> (Vec<(usize, f32)>, Vec<(usize, f32)>) {
+    fn residue_atoms<'a>(mol: &'a Molecule, res: &'a Residue) -> impl Iterator<Item = &'a Atom> {
+        let atoms = if matches!(res.res_type, ResidueType::Water) {
+            &[][..]
+        } else {
+            &res.atoms[..]
+        };
+        atoms.iter().map(|&i| &mol.atoms[i])
+    }
+
+    let mean_b: Vec<Option<f32>> = mol
+        .residues
+        .iter()
+        .map(|res| {
+            let b: Vec<_> = residue_atoms(mol, res)
+                .filter_map(|a| a.temperature_factor)
+                .collect();
+            (!b.is_empty()).then(|| b.iter().sum::<f32>() / b.len() as f32)
+        })
+        .collect();
+
+    let vals: Vec<f32> = mean_b.iter().flatten().copied().collect();
+    let mut high_b = Vec::new();
+    if !vals.is_empty() {
+        let mean = vals.iter().sum::<f32>() / vals.len() as f32;
+        let σ = (vals.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / vals.len() as f32).sqrt();
+
+        if σ > 0. {
+            for (res, b) in mean_b.iter().enumerate() {
+                let Some(b) = b else {
+                    continue;
+                };
+                if (b - mean) / σ >= B_FACTOR_Z_MAX {
+                    high_b.push((res, *b));
+                }
+            }
+        }
+    }
+    high_b.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let low_occupancy = mol
+        .residues
+        .iter()
+        .enumerate()
+        .filter_map(|(res_i, res)| {
+            let occ = residue_atoms(mol, res)
+                .filter(|a| a.alt_loc.is_none())
+                .filter_map(|a| a.occupancy)
+                .min_by(f32::total_cmp)?;
+            (occ < OCCUPANCY_MIN).then_some((res_i, occ))
+        })
+        .collect();
+
+    (high_b, low_occupancy)
+}
+
+/// Compute all metrics. Updates backbone dihedrals from atom positions.
+pub fn validate(mol: &mut Molecule) -> ValidationReport {
+    calc_backbone_dihedrals(mol);
+    let mol: &Molecule = mol;
+
+    let clashes = find_clashes(mol);
+    let clashscore = if mol.atoms.is_empty() {
+        0.
+    } else {
+        clashes.len() as f64 * 1_000. / mol.atoms.len() as f64
+    };
+
+    let rama = ramachandran(mol);
+
+    let mut rotamer_count = 0;
+    let mut rotamer_outliers = Vec::new();
+    for res in 0..mol.residues.len() {
+        let Some(χ) = sidechain_χ(mol, res) else {
+            continue;
+        };
+        rotamer_count += 1;
+
+        if !χ.iter().all(|&a| is_staggered(a)) {
+            rotamer_outliers.push(RotamerOutlier { res, χ });
+        }
+    }
+
+    let (bonds, angles) = backbone_geometry(mol);
+    let (bond_rmsz, angle_rmsz) = (bonds.rmsz(), angles.rmsz());
+
+    let mut bond_outliers = bonds.outliers;
+    let mut angle_outliers = angles.outliers;
+    bond_outliers.sort_by(|a, b| b.z.abs().total_cmp(&a.z.abs()));
+    angle_outliers.sort_by(|a, b| b.z.abs().total_cmp(&a.z.abs()));
+
+    let (high_b_residues, low_occupancy_residues) = poorly_ordered(mol);
+
+    ValidationReport {
+        clashes,
+        clashscore,
+        rama_count: rama.len(),
+        rama_favored: rama
+            .iter()
+            .filter(|p| p.region == RamaRegion::Favored)
+            .count(),
+        rama_outliers: rama
+            .iter()
+            .filter(|p| p.region == RamaRegion::Outlier)
+            .map(|p| p.res)
+            .collect(),
+        rotamer_count,
+        rotamer_outliers,
+        bond_outliers,
+        angle_outliers,
+        bond_rmsz,
+        angle_rmsz,
+        high_b_residues,
+        low_occupancy_residues,
+    }
+}
@@ -0,0 +1,647 @@
+//! Biological assemblies: the arrangement of chains thought to be the physiologically relevant
+//! form, e.g. a dimer. A crystal structure's asymmetric unit may contain part of one, or several.
+//! Each assembly applies rotation and translation operators to a set of chains. These are in the
+//! mmCIF `pdbx_struct_assembly_gen` and `pdbx_struct_oper_list` categories, and in PDB files'
+//! REMARK 350 BIOMT records.
+//!
+//! An assembly can be drawn as transformed copies of the molecule's chains, leaving the molecule
+//! unchanged, or built into a new molecule, with atoms for each copy.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+};
+
+use bincode::{Decode, Encode};
+use bio_files::Chain;
+use lin_alg::f64::Vec3;
+
+use crate::molecule::{Atom, Molecule, Residue};
+
+/// mmCIF categories we read rows from.
+const CIF_CATEGORIES: [&str; 3] = [
+    "_pdbx_struct_assembly",
+    "_pdbx_struct_assembly_gen",
+    "_pdbx_struct_oper_list",
+];
+
+/// Rotation, then translation.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct AssemblyOp {
+    /// Row-major.
+    pub rotation: [[f64; 3]; 3],
+    /// Å
+    pub translation: Vec3,
+}
+
+impl AssemblyOp {
+    pub fn new_identity() -> Self {
+        Self {
+            rotation: [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]],
+            translation: Vec3::new_zero(),
+        }
+    }
+
+    pub fn apply(&self, posit: Vec3) -> Vec3 {
+        let r = &self.rotation;
+        let t = self.translation;
+        Vec3::new(
+            r[0][0] * posit.x + r[0][1] * posit.y + r[0][2] * posit.z + t.x,
+            r[1][0] * posit.x + r[1][1] * posit.y + r[1][2] * posit.z + t.y,
+            r[2][0] * posit.x + r[2][1] * posit.y + r[2][2] * posit.z + t.z,
+        )
+    }
+
+    /// This operator, applied after `other`.
+    fn after(&self, other: &Self) -> Self {
+        let mut rotation = [[0.; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                rotation[i][j] = (0..3)
+                    .map(|k| self.rotation[i][k] * other.rotation[k][j])
+                    .sum();
+            }
+        }
+
+        Self {
+            rotation,
+            translation: self.apply(other.translation),
+        }
+    }
+
+    /// Operators that leave chains where they are; their copies are the molecule itself.
+    pub fn is_identity(&self) -> bool {
+        const EPS: f64 = 1e-4;
+
+        let identity = Self::new_identity();
+        let rot_same = (0..3)
+            .all(|i| (0..3).all(|j| (self.rotation[i][j] - identity.rotation[i][j]).abs() < EPS));
+
+        rot_same && self.translation.magnitude() < EPS
+    }
+}
+
+/// Operators, and the chains each is applied to.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct AssemblyGen {
+    /// Chain IDs. From mmCIF, these are `label_asym_id`s, and the `auth_asym_id`s they map to.
+    pub chains: Vec<String>,
+    pub ops: Vec<AssemblyOp>,
+}
+
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct Assembly {
+    pub id: String,
+    /// E.g. "dimeric".
+    pub details: String,
+    pub gens: Vec<AssemblyGen>,
+}
+
+impl Assembly {
+    /// The number of times chains are placed, including the original ones.
+    pub fn copy_count(&self) -> usize {
+        self.gens.iter().map(|g| g.ops.len()).sum()
+    }
+}
+
+impl fmt::Display for Assembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)?;
+        if !self.details.is_empty() {
+            write!(f, ": {}", self.details)?;
+        }
+        write!(f, " ({} copies)", self.copy_count())
+    }
+}
+
+/// Split a line of mmCIF into values. Quoted values may contain spaces.
+fn cif_tokens(line: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut token = String::new();
+        if c == '\'' || c == '"' {
+            chars.next();
+            // A quote only ends the value if followed by whitespace, or the line's end.
+            while let Some(c_next) = chars.next() {
+                if c_next == c && chars.peek().is_none_or(|n| n.is_whitespace()) {
+                    break;
+                }
+                token.push(c_next);
+            }
+        } else {
+            while let Some(&c_next) = chars.peek() {
+                if c_next.is_whitespace() {
+                    break;
+                }
+                token.push(c_next);
+                chars.next();
+            }
+        }
+        result.push(token);
+    }
+
+    result
+}
+
+/// Operator IDs, from a comma-separated list that may contain ranges, e.g. "1,2" or "1-60".
+fn expand_op_ids(list: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if let Some((start, end)) = part.split_once('-') {
+            if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                result.extend((start..=end).map(|i| i.to_string()));
+                continue;
+            }
+        }
+        result.push(part.to_owned());
+    }
+    result
+}
+
+/// Operators from an expression, e.g. "1", "(1-60)", or "(X0)(1-60)". The last is a product: each
+/// combination of the groups' operators, with the rightmost applied first. `None` if it refers to
+/// an operator that isn't defined.
+fn parse_oper_expression(
+    expr: &str,
+    opers: &HashMap<String, AssemblyOp>,
+) -> Option<Vec<AssemblyOp>> {
+    let groups: Vec<Vec<String>> = expr
+        .split(['(', ')'])
+        .filter(|g| !g.trim().is_empty())
+        .map(expand_op_ids)
+        .collect();
+
+    let mut result = vec![AssemblyOp::new_identity()];
+    for group in groups {
+        let mut next = Vec::with_capacity(result.len() * group.len());
+        for op in &result {
+            for id in &group {
+                next.push(op.after(opers.get(id)?));
+            }
+        }
+        result = next;
+    }
+
+    Some(result)
+}
+
+/// Rows of the categories in `CIF_CATEGORIES`, as { field: value }, by category. These may be loops,
+/// or a single row of key-value pairs. Also returns { label_asym_id: auth_asym_id }, from
+/// `atom_site`.
+fn cif_rows(
+    lines: impl Iterator<Item = String>,
+) -> (
+    HashMap<String, Vec<HashMap<String, String>>>,
+    HashMap<String, String>,
+) {
+    let mut rows: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+    let mut single_rows: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut asym_to_auth = HashMap::new();
+
+    let mut add_row = |tags: &[String], vals: Vec<String>| {
+        let Some((cat, _)) = tags[0].split_once('.') else {
+            return;
+        };
+        let fields = tags.iter().map(|t| t.split_once('.').unwrap_or_default().1);
+
+        if cat == "_atom_site" {
+            let mut label = None;
+            let mut auth = None;
+            for (field, val) in fields.zip(&vals) {
+                match field {
+                    "label_asym_id" => label = Some(val),
+                    "auth_asym_id" => auth = Some(val),
+                    _ => (),
+                }
+            }
+            if let (Some(label), Some(auth)) = (label, auth) {
+                if !asym_to_auth.contains_key(label) {
+                    asym_to_auth.insert(label.clone(), auth.clone());
+                }
+            }
+        } else {
+            let row = fields.map(|f| f.to_owned()).zip(vals).collect();
+            rows.entry(cat.to_owned()).or_default().push(row);
+        }
+    };
+
+    let wanted = |tag: &str| {
+        let cat = tag.split_once('.').unwrap_or_default().0;
+        cat == "_atom_site" || CIF_CATEGORIES.contains(&cat)
+    };
+
+    let mut loop_tags: Vec<String> = Vec::new();
+    let mut loop_vals: Vec<String> = Vec::new();
+    let mut in_loop = false;
+    let mut loop_has_data = false;
+    // A key whose value is on the next line.
+    let mut pending_key: Option<String> = None;
+    let mut in_text = false;
+
+    for line in lines {
+        // Multi-line text fields, delimited by lines starting with `;`. We don't use their
+        // contents, but they count as a value.
+        if line.starts_with(';') {
+            in_text = !in_text;
+            if in_text {
+                if let Some(key) = pending_key.take() {
+                    if wanted(&key) {
+                        let (cat, field) = key.split_once('.').unwrap_or_default();
+                        single_rows
+                            .entry(cat.to_owned())
+                            .or_default()
+                            .insert(field.to_owned(), String::new());
+                    }
+                } else if in_loop {
+                    loop_vals.push(String::new());
+                }
+            }
+            continue;
+        }
+        if in_text {
+            continue;
+        }
+
+        let t = line.trim();
+        if t.is_empty() {
+            continue;
+        }
+
+        if t == "loop_"
+            || t == "#"
+            || t.starts_with("data_")
+            || (in_loop && loop_has_data && t.starts_with('_'))
+        {
+            in_loop = false;
+            loop_tags.clear();
+            loop_vals.clear();
+            if t == "loop_" {
+                in_loop = true;
+                loop_has_data = false;
+                continue;
+            }
+            if !t.starts_with('_') {
+                continue;
+            }
+        }
+
+        if in_loop {
+            if t.starts_with('_') && !loop_has_data {
+                loop_tags.push(t.to_owned());
+                continue;
+            }
+
+            loop_has_data = true;
+            if loop_tags.is_empty() || !wanted(&loop_tags[0]) {
+                continue;
+            }
+
+            loop_vals.extend(cif_tokens(t));
+            while loop_vals.len() >= loop_tags.len() {
+                let rest = loop_vals.split_off(loop_tags.len());
+                add_row(&loop_tags, loop_vals);
+                loop_vals = rest;
+            }
+            continue;
+        }
+
+        let mut tokens = cif_tokens(t).into_iter();
+        let (key, val) = match pending_key.take() {
+            Some(key) => (key, tokens.next()),
+            None => {
+                let Some(key) = tokens.next() else {
+                    continue;
+                };
+                if !key.starts_with('_') {
+                    continue;
+                }
+                match tokens.next() {
+                    Some(val) => (key, Some(val)),
+                    None => {
+                        pending_key = Some(key);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let Some(val) = val else {
+            continue;
+        };
+        if wanted(&key) {
+            let (cat, field) = key.split_once('.').unwrap_or_default();
+            single_rows
+                .entry(cat.to_owned())
+                .or_default()
+                .insert(field.to_owned(), val);
+        }
+    }
+
+    for (cat, row) in single_rows {
+        rows.entry(cat).or_default().push(row);
+    }
+
+    (rows, asym_to_auth)
+}
+
+fn parse_cif(lines: impl Iterator<Item = String>) -> Vec<Assembly> {
+    let (rows, asym_to_auth) = cif_rows(lines);
+    let rows_of = |cat: &str| rows.get(cat).map(|r| r.as_slice()).unwrap_or_default();
+
+    let mut opers = HashMap::new();
+    for row in rows_of("_pdbx_struct_oper_list") {
+        let Some(id) = row.get("id") else {
+            continue;
+        };
+        let val = |field: &str| row.get(field).and_then(|v| v.parse::<f64>().ok());
+
+        let mut op = AssemblyOp::new_identity();
+        let mut complete = true;
+        for i in 0..3 {
+            for j in 0..3 {
+                match val(&format!("matrix[{}][{}]", i + 1, j + 1)) {
+                    Some(v) => op.rotation[i][j] = v,
+                    None => complete = false,
+                }
+            }
+        }
+        match (val("vector[1]"), val("vector[2]"), val("vector[3]")) {
+            (Some(x), Some(y), Some(z)) => op.translation = Vec3::new(x, y, z),
+            _ => complete = false,
+        }
+
+        if complete {
+            opers.insert(id.clone(), op);
+        }
+    }
+
+    let mut result: Vec<Assembly> = rows_of("_pdbx_struct_assembly")
+        .iter()
+        .filter_map(|row| {
+            Some(Assembly {
+                id: row.get("id")?.clone(),
+                details: row.get("oligomeric_details").cloned().unwrap_or_default(),
+                gens: Vec::new(),
+            })
+        })
+        .collect();
+
+    for row in rows_of("_pdbx_struct_assembly_gen") {
+        let (Some(id), Some(expr), Some(asym_ids)) = (
+            row.get("assembly_id"),
+            row.get("oper_expression"),
+            row.get("asym_id_list"),
+        ) else {
+            continue;
+        };
+        let Some(ops) = parse_oper_expression(expr, &opers) else {
+            continue;
+        };
+
+        let mut chains = Vec::new();
+        for asym_id in asym_ids.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            chains.push(asym_id.to_owned());
+            if let Some(auth) = asym_to_auth.get(asym_id) {
+                chains.push(auth.clone());
+            }
+        }
+        chains.sort();
+        chains.dedup();
+
+        let i = match result.iter().position(|a| a.id == *id) {
+            Some(i) => i,
+            None => {
+                result.push(Assembly {
+                    id: id.clone(),
+                    ..Default::default()
+                });
+                result.len() - 1
+            }
+        };
+        result[i].gens.push(AssemblyGen { chains, ops });
+    }
+
+    result.retain(|a| !a.gens.is_empty());
+    result
+}
+
+/// From PDB REMARK 350 records.
+fn parse_remark_350(lines: impl Iterator<Item = String>) -> Vec<Assembly> {
+    let mut result: Vec<Assembly> = Vec::new();
+
+    for line in lines {
+        let Some(text) = line.strip_prefix("REMARK 350") else {
+            continue;
+        };
+        let text = text.trim();
+
+        if let Some(id) = text.strip_prefix("BIOMOLECULE:") {
+            result.push(Assembly {
+                id: id.trim().to_owned(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(assembly) = result.last_mut() else {
+            continue;
+        };
+
+        if let Some((_, details)) = text.split_once("BIOLOGICAL UNIT:") {
+            // Prefer the author's, which comes first.
+            if assembly.details.is_empty() {
+                assembly.details = details.trim().to_lowercase();
+            }
+        } else if let Some((_, chains)) = text.split_once("CHAINS:") {
+            let chains = chains
+                .split(',')
+                .map(|c| c.trim().to_owned())
+                .filter(|c| !c.is_empty());
+
+            // "AND CHAINS:" continues the previous line's list.
+            if text.starts_with("APPLY") || assembly.gens.is_empty() {
+                assembly.gens.push(AssemblyGen {
+                    chains: chains.collect(),
+                    ops: Vec::new(),
+                });
+            } else if let Some(asm_gen) = assembly.gens.last_mut() {
+                asm_gen.chains.extend(chains);
+            }
+        } else if text.starts_with("BIOMT") {
+            // E.g. "BIOMT1   1  1.000000  0.000000  0.000000        0.00000"
+            let cols: Vec<_> = text.split_whitespace().collect();
+            // Row, serial, 3 matrix elements, and the translation; skip truncated lines.
+            if cols.len() < 6 {
+                continue;
+            }
+            let Some(row) = cols[0][5..]
+                .parse::<usize>()
+                .ok()
+                .filter(|r| (1..=3).contains(r))
+            else {
+                continue;
+            };
+            let vals: Vec<f64> = cols[2..].iter().filter_map(|v| v.parse().ok()).collect();
+            if vals.len() < 4 {
+                continue;
+            }
+            let Some(asm_gen) = assembly.gens.last_mut() else {
+                continue;
+            };
+
+            if row == 1 {
+                asm_gen.ops.push(AssemblyOp::new_identity());
+            }
+            let Some(op) = asm_gen.ops.last_mut() else {
+                continue;
+            };
+            op.rotation[row - 1] = [vals[0], vals[1], vals[2]];
+            match row {
+                1 => op.translation.x = vals[3],
+                2 => op.translation.y = vals[3],
+                _ => op.translation.z = vals[3],
+            }
+        }
+    }
+
+    for assembly in &mut result {
+        assembly.gens.retain(|g| !g.ops.is_empty());
+    }
+    result.retain(|a| !a.gens.is_empty());
+    result
+}
+
+/// Parse assemblies from the text of an mmCIF or PDB file.
+pub fn load_assemblies<R: Read + Seek>(mut data: R) -> io::Result<Vec<Assembly>> {
+    data.seek(SeekFrom::Start(0))?;
+    let mut lines = BufReader::new(data)
+        .lines()
+        .map_while(Result::ok)
+        .skip_while(|l| l.trim().is_empty())
+        .peekable();
+
+    let is_cif = lines.peek().is_some_and(|l| l.starts_with("data_"));
+
+    Ok(if is_cif {
+        parse_cif(lines)
+    } else {
+        parse_remark_350(lines)
+    })
+}
+
+impl Molecule {
+    /// (Atom indices, operator) for each copy of chains in an assembly, including those whose
+    /// operator is the identity.
+    pub fn assembly_copies(&self, assembly: usize) -> Vec<(Vec<usize>, AssemblyOp)> {
+        let Some(assembly) = self.assemblies.get(assembly) else {
+            return Vec::new();
+        };
+
+        let mut result = Vec::new();
+        for asm_gen in &assembly.gens {
+            let atoms: Vec<usize> = self
+                .chains
+                .iter()
+                .filter(|c| asm_gen.chains.contains(&c.id))
+                .flat_map(|c| c.atoms.iter().copied())
+                .collect();
+
+            for op in &asm_gen.ops {
+                result.push((atoms.clone(), op.clone()));
+            }
+        }
+
+        result
+    }
+
+    /// A new molecule, with atoms, residues, and chains for each copy in an assembly. Copies of a
+    /// chain after the first have the copy number appended to their ID, e.g. "A-2". Atoms not in a
+    /// chain aren't included.
+    pub fn build_assembly(&self, assembly: usize) -> Option<Self> {
+        let asm = self.assemblies.get(assembly)?;
+
+        let mut atoms: Vec<Atom> = Vec::new();
+        let mut residues: Vec<Residue> = Vec::new();
+        let mut chains = Vec::new();
+        // { chain ID: copies so far }
+        let mut copy_counts: HashMap<&str, usize> = HashMap::new();
+
+        for asm_gen in &asm.gens {
+            for op in &asm_gen.ops {
+                for chain in self
+                    .chains
+                    .iter()
+                    .filter(|c| asm_gen.chains.contains(&c.id))
+                {
+                    let count = copy_counts.entry(&chain.id).or_default();
+                    *count += 1;
+                    let id = if *count == 1 {
+                        chain.id.clone()
+                    } else {
+                        format!("{}-{count}", chain.id)
+                    };
+
+                    // { old atom index: new }
+                    let mut atom_map = HashMap::new();
+                    let atoms_start = atoms.len();
+                    for &i in &chain.atoms {
+                        let atom = &self.atoms[i];
+                        atom_map.insert(i, atoms.len());
+                        atoms.push(Atom {
+                            serial_number: atoms.len() + 1,
+                            posit: op.apply(atom.posit),
+                            residue: None,
+                            ..atom.clone()
+                        });
+                    }
+
+                    let mut chain_residues = Vec::with_capacity(chain.residues.len());
+                    for &r in &chain.residues {
+                        let res = &self.residues[r];
+                        let res_atoms: Vec<_> = res
+                            .atoms
+                            .iter()
+                            .filter_map(|a| atom_map.get(a).copied())
+                            .collect();
+
+                        for &a in &res_atoms {
+                            atoms[a].residue = Some(residues.len());
+                        }
+                        chain_residues.push(residues.len());
+                        residues.push(Residue {
+                            atoms: res_atoms,
+                            ..res.clone()
+                        });
+                    }
+
+                    chains.push(Chain {
+                        id,
+                        atoms: (atoms_start..atoms.len()).collect(),
+                        residues: chain_residues,
+                        visible: true,
+                    });
+                }
+            }
+        }
+
+        if atoms.is_empty() {
+            return None;
+        }
+
+        Some(Molecule::new(
+            format!("{} assembly {}", self.ident, asm.id),
+            atoms,
+            chains,
+            residues,
+            self.pubchem_cid,
+            self.drugbank_id.clone(),
+        ))
+    }
+}
@@ -0,0 +1,148 @@
+//! A/B blink comparison: rapidly alternate between two stored sets of atom positions in the same
+//! camera view; e.g. two poses, apo vs holo, or before and after minimization. A classic
+//! crystallography trick for spotting differences.
+
+use graphics::Scene;
+use lin_alg::f64::Vec3;
+
+use crate::{
+    State,
+    mol_drawing::{draw_ligand, draw_molecule},
+};
+
+/// Seconds between switching frames.
+pub const BLINK_INTERVAL_DEFAULT: f32 = 0.5;
+pub const BLINK_INTERVAL_MIN: f32 = 0.1;
+pub const BLINK_INTERVAL_MAX: f32 = 2.;
+
+/// Atom positions of the molecule and ligand, at one point in time.
+#[derive(Clone, Debug, Default)]
+pub struct BlinkFrame {
+    pub mol_posits: Vec<Vec3>,
+    pub lig_posits: Vec<Vec3>,
+}
+
+impl BlinkFrame {
+    pub fn from_state(state: &State) -> Self {
+        let mol_posits = match &state.molecule {
+            Some(mol) => mol.atoms.iter().map(|a| a.posit).collect(),
+            None => Vec::new(),
+        };
+        let lig_posits = match &state.ligand {
+            Some(lig) => lig.atom_posits.clone(),
+            None => Vec::new(),
+        };
+
+        Self {
+            mol_posits,
+            lig_posits,
+        }
+    }
+
+    /// Positions atoms from this frame. Skips the molecule or ligand if its atom count doesn't match,
+    /// e.g. if a different one was opened since storing the frame.
+    fn apply(&self, state: &mut State) {
+        if let Some(mol) = &mut state.molecule {
+            if mol.atoms.len() == self.mol_posits.len() {
+                for (atom, posit) in mol.atoms.iter_mut().zip(&self.mol_posits) {
+                    atom.posit = *posit;
+                }
+            }
+        }
+
+        if let Some(lig) = &mut state.ligand {
+            if lig.atom_posits.len() == self.lig_posits.len() {
+                lig.atom_posits = self.lig_posits.clone();
+            }
+        }
+    }
+}
+
+pub struct Blink {
+    pub a: Option<BlinkFrame>,
+    pub b: Option<BlinkFrame>,
+    /// Positions prior to starting; restored when stopped. `Some` while blinking.
+    original: Option<BlinkFrame>,
+    pub showing_b: bool,
+    /// Seconds since the last switch.
+    timer: f32,
+    /// Seconds.
+    pub interval: f32,
+}
+
+impl Default for Blink {
+    fn default() -> Self {
+        Self {
+            a: None,
+            b: None,
+            original: None,
+            showing_b: false,
+            timer: 0.,
+            interval: BLINK_INTERVAL_DEFAULT,
+        }
+    }
+}
+
+impl Blink {
+    pub fn running(&self) -> bool {
+        self.original.is_some()
+    }
+}
+
+fn redraw(state: &mut State, scene: &mut Scene) {
+    draw_molecule(state, scene);
+    draw_ligand(state, scene);
+}
+
+/// Start alternating between frames A and B. Does nothing if either is missing.
+pub fn blink_start(state: &mut State, scene: &mut Scene) {
+    let Some(a) = state.volatile.blink.a.clone() else {
+        return;
+    };
+    if state.volatile.blink.b.is_none() {
+        return;
+    }
+
+    state.volatile.blink.original = Some(BlinkFrame::from_state(state));
+    state.volatile.blink.showing_b = false;
+    state.volatile.blink.timer = 0.;
+
+    a.apply(state);
+    redraw(state, scene);
+}
+
+/// Stop blinking, and restore the positions from before we started.
+pub fn blink_stop(state: &mut State, scene: &mut Scene) {
+    if let Some(orig) = state.volatile.blink.original.take() {
+        orig.apply(state);
+        redraw(state, scene);
+    }
+}
+
+/// Run this each frame. Returns `true` if we switched frames, and entities need updating.
+pub fn blink_step(state: &mut State, scene: &mut Scene, dt: f32) -> bool {
+    let blink = &mut state.volatile.blink;
+    if !blink.running() {
+        return false;
+    }
+
+    blink.timer += dt;
+    if blink.timer < blink.interval {
+        return false;
+    }
+    blink.timer = 0.;
+    blink.showing_b = !blink.showing_b;
+
+    let frame = if blink.showing_b {
+        blink.b.clone()
+    } else {
+        blink.a.clone()
+    };
+
+    if let Some(f) = frame {
+        f.apply(state);
+        redraw(state, scene);
+    }
+
+    true
+}
@@ -5,10 +5,19 @@
 //! Some info here: https://www.ruppweb.org/Xray/tutorial/protein_structure.htm
 //! https://itp.uni-frankfurt.de/~engel/amino.html
 //!
+//! We also perceive rings and aromaticity here, and assign bond orders that distance alone doesn't
+//! determine.
+//!
 //! All lengths are in angstrom (Å)
 
-use std::f64::consts::TAU;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    f64::consts::TAU,
+};
 
+use bincode::{Decode, Encode};
+use lin_alg::f64::Vec3;
+use log::warn;
 use na_seq::{
     Element,
     Element::{Carbon, Fluorine, Hydrogen, Nitrogen, Oxygen, Sulfur},
@@ -16,6 +25,7 @@ use na_seq::{
 use rayon::prelude::*;
 
 use crate::{
+    analysis::rings::Ring,
     molecule::{
         Atom, Bond,
         BondCount::*,
@@ -60,7 +70,35 @@ const H_BOND_S_F_DIST: f64 = 3.2; // rarate
 const H_BOND_DIST_THRESH: f64 = 0.3;
 const H_BOND_DIST_GRID: f64 = 3.6;
 
-const H_BOND_ANGLE_THRESH: f64 = TAU / 3.;
+/// Rings larger than this aren't perceived. Covers 7 and 8-membered rings, e.g. of benzodiazepines.
+const RING_SIZE_MAX: usize = 8;
+/// Å. Maximum distance of an aromatic ring atom from the ring's mean plane.
+const AROMATIC_PLANARITY_TOL: f64 = 0.15;
+/// Å. Aromatic bonds are 1.34-1.41 Å; sp3 ring bonds, e.g. of Pro or sugars, are 1.43-1.54.
+const AROMATIC_BOND_LEN_MAX: f64 = 1.45;
+
+/// Radians. Donor-H···acceptor; 100°.
+const H_BOND_ANGLE_MIN: f64 = TAU * 100. / 360.;
+
+/// Geometric criteria for hydrogen bonds. Conventions differ between communities and programs; e.g.
+/// tighter distance windows, or near-linear angles only.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct HBondConfig {
+    /// Å. Donor-acceptor distances within this of the typical distance for the pair's elements
+    /// are accepted.
+    pub dist_tol: f64,
+    /// Radians. The minimum donor-H···acceptor angle; π is linear.
+    pub angle_min: f64,
+}
+
+impl Default for HBondConfig {
+    fn default() -> Self {
+        Self {
+            dist_tol: H_BOND_DIST_THRESH,
+            angle_min: H_BOND_ANGLE_MIN,
+        }
+    }
+}
 
 #[rustfmt::skip]
 fn get_specs() -> Vec<BondSpecs> {
@@ -214,6 +252,8 @@ pub fn create_bonds(atoms: &[Atom]) -> Vec<Bond> {
                         atom_0: *i,
                         atom_1: *j,
                         is_backbone: atom_0.is_backbone() && atom_1.is_backbone(),
+                        in_ring: false,
+                        aromatic: false,
                     })
                 } else {
                     None
@@ -223,6 +263,212 @@ pub fn create_bonds(atoms: &[Atom]) -> Vec<Bond> {
         .collect()
 }
 
+/// The shortest path from `start` to `end` that doesn't use the bond between them, if it closes a
+/// ring of at most `RING_SIZE_MAX` atoms. Returns atoms in path order.
+fn smallest_ring(adj: &[Vec<usize>], start: usize, end: usize) -> Option<Vec<usize>> {
+    // (Parent, depth), by atom. A map vice a Vec, since only a few atoms are visited.
+    let mut visited = HashMap::from([(start, (start, 0))]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(i) = queue.pop_front() {
+        let depth = visited[&i].1;
+        if depth + 1 >= RING_SIZE_MAX {
+            continue;
+        }
+
+        for &n in &adj[i] {
+            if visited.contains_key(&n) || (i == start && n == end) {
+                continue;
+            }
+            visited.insert(n, (i, depth + 1));
+
+            if n == end {
+                let mut path = vec![end];
+                let mut current = end;
+                while current != start {
+                    current = visited[&current].0;
+                    path.push(current);
+                }
+                return Some(path);
+            }
+            queue.push_back(n);
+        }
+    }
+
+    None
+}
+
+/// The smallest set of smallest rings (SSSR), over heavy atoms. Candidates are the smallest ring
+/// through each bond. These are taken in order of size, keeping each whose bonds aren't a
+/// combination (symmetric difference) of those of rings already kept. Returns atom indices, in order
+/// around each ring.
+pub fn find_sssr(atoms: &[Atom], bonds: &[Bond], adj: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+
+    for bond in bonds {
+        let (a, b) = (bond.atom_0, bond.atom_1);
+        if a >= adj.len() || b >= adj.len() || adj[a].len() < 2 || adj[b].len() < 2 {
+            continue;
+        }
+        if atoms[a].element == Hydrogen || atoms[b].element == Hydrogen {
+            continue;
+        }
+
+        let Some(ring) = smallest_ring(adj, a, b) else {
+            continue;
+        };
+
+        let mut key = ring.clone();
+        key.sort_unstable();
+        if seen.insert(key) {
+            candidates.push(ring);
+        }
+    }
+    candidates.sort_by_key(|r| r.len());
+
+    // Ring bonds are indexed in the order encountered. Each kept ring is stored as a bit set of
+    // these, with its lowest set bit as pivot, reduced against those kept before it.
+    let mut edge_indices = HashMap::new();
+    let mut basis: Vec<(usize, Vec<u64>)> = Vec::new();
+    let mut result = Vec::new();
+
+    for ring in candidates {
+        let mut bits: Vec<u64> = Vec::new();
+        for (k, &i) in ring.iter().enumerate() {
+            let j = ring[(k + 1) % ring.len()];
+            let next = edge_indices.len();
+            let e = *edge_indices.entry((i.min(j), i.max(j))).or_insert(next);
+
+            if bits.len() <= e / 64 {
+                bits.resize(e / 64 + 1, 0);
+            }
+            bits[e / 64] |= 1 << (e % 64);
+        }
+
+        for (pivot, b) in &basis {
+            if bits
+                .get(pivot / 64)
+                .is_some_and(|w| w & (1 << (pivot % 64)) != 0)
+            {
+                if bits.len() < b.len() {
+                    bits.resize(b.len(), 0);
+                }
+                for (w, v) in bits.iter_mut().zip(b) {
+                    *w ^= v;
+                }
+            }
+        }
+
+        // All bits cleared: This ring is a combination of smaller ones; e.g. the perimeter of a
+        // fused system.
+        let Some(pivot) = bits
+            .iter()
+            .enumerate()
+            .find(|(_, w)| **w != 0)
+            .map(|(k, w)| k * 64 + w.trailing_zeros() as usize)
+        else {
+            continue;
+        };
+
+        basis.push((pivot, bits));
+        result.push(ring);
+    }
+
+    result
+}
+
+/// Aromaticity from geometry: 5 or 6-membered rings of C, N, O, or S that are planar, with short
+/// bonds. This doesn't depend on bond orders, which are often missing or Kekulé-specific.
+fn ring_is_aromatic(atoms: &[Atom], posits: &[Vec3], ring: &[usize]) -> bool {
+    if !(5..=6).contains(&ring.len()) {
+        return false;
+    }
+
+    let conj_elements = ring
+        .iter()
+        .all(|&i| matches!(atoms[i].element, Carbon | Nitrogen | Oxygen | Sulfur));
+    if !conj_elements {
+        return false;
+    }
+
+    let geom = Ring::new(posits, ring.to_vec());
+    let planar = ring
+        .iter()
+        .all(|&i| (posits[i] - geom.center).dot(geom.normal).abs() < AROMATIC_PLANARITY_TOL);
+
+    let bond_len_sum: f64 = ring
+        .iter()
+        .enumerate()
+        .map(|(k, &i)| (posits[i] - posits[ring[(k + 1) % ring.len()]]).magnitude())
+        .sum();
+
+    planar && bond_len_sum / (ring.len() as f64) < AROMATIC_BOND_LEN_MAX
+}
+
+/// Find rings, and mark ring membership and aromaticity on atoms and bonds. Returns the rings,
+/// as from `find_sssr`.
+pub fn perceive_rings(
+    atoms: &mut [Atom],
+    bonds: &mut [Bond],
+    adj: &[Vec<usize>],
+) -> Vec<Vec<usize>> {
+    let rings = find_sssr(atoms, bonds, adj);
+    let posits: Vec<_> = atoms.iter().map(|a| a.posit).collect();
+
+    for atom in atoms.iter_mut() {
+        atom.in_ring = false;
+        atom.aromatic = false;
+    }
+
+    // (Lower index, higher index)
+    let mut ring_bonds = HashSet::new();
+    let mut aromatic_bonds = HashSet::new();
+
+    for ring in &rings {
+        let aromatic = ring_is_aromatic(atoms, &posits, ring);
+
+        for (k, &i) in ring.iter().enumerate() {
+            let j = ring[(k + 1) % ring.len()];
+            atoms[i].in_ring = true;
+            atoms[i].aromatic |= aromatic;
+
+            ring_bonds.insert((i.min(j), i.max(j)));
+            if aromatic {
+                aromatic_bonds.insert((i.min(j), i.max(j)));
+            }
+        }
+    }
+
+    for bond in bonds {
+        let key = (bond.atom_0.min(bond.atom_1), bond.atom_0.max(bond.atom_1));
+        bond.in_ring = ring_bonds.contains(&key);
+        bond.aromatic = aromatic_bonds.contains(&key);
+    }
+
+    rings
+}
+
+/// Set bond orders that distance alone doesn't determine, for bonds from `create_bonds`. Aromatic
+/// bonds become single-double hybrids. Other C-C bonds inferred as hybrids, e.g. sp2-sp3 bonds like
+/// Phe's Cβ-Cγ, or Pro's ring bonds, become single. Run `perceive_rings` first.
+pub fn assign_bond_orders(bonds: &mut [Bond], atoms: &[Atom]) {
+    let hybrid = Covalent {
+        count: SingleDoubleHybrid,
+    };
+
+    for bond in bonds {
+        if bond.aromatic {
+            bond.bond_type = hybrid;
+        } else if bond.bond_type == hybrid
+            && atoms[bond.atom_0].element == Carbon
+            && atoms[bond.atom_1].element == Carbon
+        {
+            bond.bond_type = Covalent { count: Single };
+        }
+    }
+}
+
 /// Helper
 fn h_bond_candidate_el(atom: &Atom) -> bool {
     matches!(atom.element, Nitrogen | Oxygen | Sulfur | Fluorine)
@@ -237,6 +483,7 @@ fn hydrogen_bond_inner(
     donor_h_i: usize,
     acc_i: usize,
     relaxed_dist_thresh: bool,
+    cfg: &HBondConfig,
 ) {
     let d_e = donor_heavy.element; // Cleans up the verbose code below.
     let a_e = acc_candidate.element;
@@ -254,9 +501,9 @@ fn hydrogen_bond_inner(
     };
 
     let modifier = if relaxed_dist_thresh {
-        H_BOND_DIST_THRESH * 2.
+        cfg.dist_tol * 2.
     } else {
-        H_BOND_DIST_THRESH
+        cfg.dist_tol
     };
 
     let dist_thresh_min = dist_thresh - modifier;
@@ -267,17 +514,19 @@ fn hydrogen_bond_inner(
         return;
     }
 
+    // At the hydrogen.
     let angle = {
-        let donor_h = donor_h.posit - donor_heavy.posit;
-        let donor_acceptor = donor_heavy.posit - acc_candidate.posit;
+        let h_donor = donor_heavy.posit - donor_h.posit;
+        let h_acceptor = acc_candidate.posit - donor_h.posit;
 
-        donor_acceptor
+        h_donor
             .to_normalized()
-            .dot(donor_h.to_normalized())
+            .dot(h_acceptor.to_normalized())
+            .clamp(-1., 1.)
             .acos()
     };
 
-    if angle > H_BOND_ANGLE_THRESH {
+    if angle >= cfg.angle_min {
         bonds.push(HydrogenBond {
             donor: donor_heavy_i,
             acceptor: acc_i,
@@ -288,9 +537,13 @@ fn hydrogen_bond_inner(
 
 /// Create hydrogen bonds between all atomsm in a group. See `create_hydrogen_bonds_one_way` for the more
 /// flexible fn it calls.
-pub fn create_hydrogen_bonds(atoms: &[Atom], bonds: &[Bond]) -> Vec<HydrogenBond> {
+pub fn create_hydrogen_bonds(
+    atoms: &[Atom],
+    bonds: &[Bond],
+    cfg: &HBondConfig,
+) -> Vec<HydrogenBond> {
     let indices: Vec<_> = (0..atoms.len()).collect();
-    create_hydrogen_bonds_one_way(atoms, &indices, bonds, atoms, &indices, false)
+    create_hydrogen_bonds_one_way(atoms, &indices, bonds, atoms, &indices, false, cfg)
 }
 
 /// Infer hydrogen bonds from a list of atoms. This takes into account bond distance between suitable
@@ -307,6 +560,7 @@ pub fn create_hydrogen_bonds_one_way(
     atoms_acc: &[Atom],
     atoms_acc_i: &[usize],
     relaxed_dist_thresh: bool,
+    cfg: &HBondConfig,
 ) -> Vec<HydrogenBond> {
     let mut result = Vec::new();
 
@@ -319,9 +573,7 @@ pub fn create_hydrogen_bonds_one_way(
             let atom_1 = find_atom(atoms_donor, atoms_donor_i, b.atom_1);
 
             let (Some(atom_0), Some(atom_1)) = (atom_0, atom_1) else {
-                eprintln!(
-                    "Error! Can't find atoms from indices when making H bonds (Donor finding)"
-                );
+                warn!("Error! Can't find atoms from indices when making H bonds (Donor finding)");
                 return false;
             };
 
@@ -344,7 +596,7 @@ pub fn create_hydrogen_bonds_one_way(
         let donor_1 = find_atom(atoms_donor, atoms_donor_i, donor_bond.atom_1);
 
         let (Some(donor_0), Some(donor_1)) = (donor_0, donor_1) else {
-            eprintln!("Error! Can't find atoms from indices when making H bonds");
+            warn!("Error! Can't find atoms from indices when making H bonds");
             continue;
         };
 
@@ -364,6 +616,7 @@ pub fn create_hydrogen_bonds_one_way(
                 donor_h_i,
                 *acc_i,
                 relaxed_dist_thresh,
+                cfg,
             );
         }
     }
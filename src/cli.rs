@@ -1,7 +1,15 @@
-//! Our CLI system. Apes PyMol's syntax. We don't introduce our own commands, as this functionality
-//! is primarily for PyMol users who are comfortable with this workflow.
+//! Our CLI system. Apes PyMol's syntax. We mostly don't introduce our own commands, as this
+//! functionality is primarily for PyMol users who are comfortable with this workflow. Exceptions
+//! are `dock` and `md`, which drive functionality PyMol doesn't have, and `assembly`, which shows
+//! (`assembly 1`) or builds (`assembly 1, atoms`) a biological assembly.
 //!
 //! On PyMol selection syntax: https://pymolwiki.org/index.php/Selection_Algebra
+//!
+//! Selections can be named, PyMol-style, e.g. `select pocket, resi 45`, then recalled with
+//! `select pocket`, or referred to in other commands, e.g. `show surface, pocket`.
+//!
+//! Commands, and some UI actions, can be recorded as a macro: a script of commands, one per line,
+//! that can be saved, and replayed on another structure with `run` or `@`.
 
 use std::{
     env,
@@ -14,14 +22,18 @@ use std::{
 
 use bio_files::ResidueType;
 use graphics::{EngineUpdates, FWD_VEC, RIGHT_VEC, Scene, UP_VEC, arc_rotation};
+use lin_alg::f64::calc_dihedral_angle_v2;
 use na_seq::{AminoAcid, Element};
-use regex::Regex;
+use regex::{Match, Regex};
 
 use crate::{
-    Selection, State,
-    molecule::AtomRole,
-    render::set_flashlight,
-    ui::load_file,
+    Selection, State, ViewSelLevel,
+    docking::dock_ligand,
+    dynamics::protein::run_protein_md,
+    file_io::load_file,
+    mol_drawing::draw_ligand,
+    molecule::{AtomRole, PROP_B_FACTOR},
+    scene::set_flashlight,
     util,
     util::{cam_look_at, reset_camera},
 };
@@ -31,15 +43,17 @@ fn new_invalid(msg: &str) -> io::Error {
 }
 
 // We use this for autocomplete.
-pub const CLI_CMDS: [&str; 20] = [
+pub const CLI_CMDS: [&str; 30] = [
     "help",
     "fetch",
     "save",
     "load",
     "show",
+    "show surface",
     "show_as",
     "view",
     "hide",
+    "color",
     "remove",
     "orient",
     "turn",
@@ -51,7 +65,15 @@ pub const CLI_CMDS: [&str; 20] = [
     "select resn",
     "select resi",
     "select elem",
+    "distance",
+    "angle",
+    "dihedral",
     "set",
+    "dock",
+    "md",
+    "record",
+    "run",
+    "assembly",
 ];
 
 /// Process a raw CLI command from the user. Return the CLI output from the entered command.
@@ -67,6 +89,84 @@ pub fn handle_cmd(
     state.volatile.cli_input_history.push(input.clone());
     state.volatile.cli_input_selected += 1;
 
+    let result = run_cmd(&input, state, scene, engine_updates, redraw, reset_cam);
+    if result.is_ok() {
+        record_cmd(state, &input);
+    }
+
+    result
+}
+
+/// Commands that control recording and replay. We don't record these themselves.
+fn is_macro_cmd(input: &str) -> bool {
+    let lower = input.trim_start().to_lowercase();
+    lower.starts_with("record") || lower.starts_with("run") || lower.starts_with('@')
+}
+
+/// If recording a macro, add a command to it. UI actions with an equivalent command call this too.
+pub fn record_cmd(state: &mut State, cmd: &str) {
+    if is_macro_cmd(cmd) {
+        return;
+    }
+    if let Some(rec) = &mut state.volatile.macro_recording {
+        rec.push(cmd.to_owned());
+    }
+}
+
+/// Save a macro as a script; one command per line.
+pub fn save_macro(cmds: &[String], path: &Path) -> io::Result<()> {
+    let mut data = String::from(
+        "# Daedalus macro. One command per line; lines starting with # are ignored.\n",
+    );
+    for cmd in cmds {
+        data += cmd;
+        data.push('\n');
+    }
+
+    fs::write(path, data)
+}
+
+/// Run each command in a script file, in order. Stops at the first error, reporting its line.
+pub fn run_script(
+    path: &Path,
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    redraw: &mut bool,
+    reset_cam: &mut bool,
+) -> io::Result<String> {
+    let data = fs::read_to_string(path)?;
+
+    let mut count = 0;
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if is_macro_cmd(line) {
+            return Err(new_invalid(&format!(
+                "Line {}: Scripts can't record, or run other scripts.",
+                i + 1
+            )));
+        }
+
+        run_cmd(line, state, scene, engine_updates, redraw, reset_cam)
+            .map_err(|e| new_invalid(&format!("Line {}: {line}: {e}", i + 1)))?;
+        count += 1;
+    }
+
+    Ok(format!("Ran {count} commands from {}", path.display()))
+}
+
+/// Execute a single command.
+pub fn run_cmd(
+    input: &str,
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    redraw: &mut bool,
+    reset_cam: &mut bool,
+) -> io::Result<String> {
     // todo: Helpers to reduce regex DRY.
     let re_help = Regex::new(r"(?i)^help$").unwrap();
     //
@@ -78,6 +178,7 @@ pub fn handle_cmd(
     // todo: Shoudl this be get_view and set_view? Have seen both.
     let re_view = Regex::new(r"(?i)^view\s+([^,\s]+)(?:\s*,\s*(store|recall))?\s*$").unwrap();
     let re_hide = Regex::new(r"(?i)^hide\s+([a-z0-9\s]+)$").unwrap();
+    let re_color = Regex::new(r"(?i)^color\s+([a-z0-9_\-]+)$").unwrap();
     let re_remove = Regex::new(r"(?i)^remove\s+([a-z0-9\s]+)$").unwrap();
     //
     let re_orient = Regex::new(r"(?i)^orient\s*(?:sel)?$").unwrap();
@@ -90,21 +191,46 @@ pub fn handle_cmd(
     let re_ls = Regex::new(r"(?i)^ls\s*$").unwrap();
     let re_cd = Regex::new(r"(?i)^cd\s+(.+)$").unwrap();
 
-    let re_sel_resi = Regex::new(r"(?i)^(?:sele|select)\s+resi\s+([0-9]+)$").unwrap();
-    let re_sel_resn = Regex::new(r"(?i)^(?:sele|select)\s+resn\s+([a-z]{3})$").unwrap();
-    let re_sel_elem = Regex::new(r"(?i)^(?:sele|select)\s+elem\s+([a-z]{1,2})$").unwrap();
+    // These take an optional name to save the selection under, e.g. `select pocket, resi 45`.
+    let re_sel_resi =
+        Regex::new(r"(?i)^(?:sele|select)\s+(?:([a-z0-9_\-]+)\s*,\s*)?resi\s+([0-9]+)$").unwrap();
+    let re_sel_resn =
+        Regex::new(r"(?i)^(?:sele|select)\s+(?:([a-z0-9_\-]+)\s*,\s*)?resn\s+([a-z]{3})$").unwrap();
+    let re_sel_elem =
+        Regex::new(r"(?i)^(?:sele|select)\s+(?:([a-z0-9_\-]+)\s*,\s*)?elem\s+([a-z]{1,2})$")
+            .unwrap();
+    // Recall a named selection.
+    let re_sel_named = Regex::new(r"(?i)^(?:sele|select)\s+([a-z0-9_\-]+)$").unwrap();
+    let re_show_surface = Regex::new(r"(?i)^show\s+surface\s*,\s*([a-z0-9_\-]+)$").unwrap();
+
+    // By atom serial number, e.g. `distance 12, 40`.
+    let re_measure =
+        Regex::new(r"(?i)^(distance|angle|dihedral)\s+([0-9]+(?:\s*,\s*[0-9]+){1,3})$").unwrap();
+
+    let re_dock = Regex::new(r"(?i)^dock\s*$").unwrap();
+    let re_md = Regex::new(r"(?i)^md(?:\s+([0-9]+))?\s*$").unwrap();
 
     let re_set = Regex::new(r"(?i)^set\s+([a-z0-9\s\-_]+)(?:,\s*([a-z0-9]+))?$").unwrap();
 
-    if let Some(_caps) = re_help.captures(&input) {
+    let re_record =
+        Regex::new(r"(?i)^record(?:\s+(start|stop|save)(?:\s+([a-z0-9./\-_]+))?)?\s*$").unwrap();
+    let re_run = Regex::new(r"(?i)^(?:run\s+|@)([a-z0-9./\-_]+)$").unwrap();
+    // Biological assemblies; not in PyMol, which uses `set assembly`.
+    let re_assembly =
+        Regex::new(r"(?i)^assembly(?:\s+([a-z0-9]+)(?:\s*,\s*(atoms))?)?\s*$").unwrap();
+
+    if let Some(_caps) = re_help.captures(input) {
         // todo: Multiline, once you set that up.
+        let mut cmds = CLI_CMDS.to_vec();
+        cmds.extend(state.plugins.commands());
+
         return Ok(format!(
             "The following commands are available: {}",
-            CLI_CMDS.join(", ")
+            cmds.join(", ")
         ));
     }
 
-    if let Some(caps) = re_fetch.captures(&input) {
+    if let Some(caps) = re_fetch.captures(input) {
         let ident = &caps[1];
         util::load_atom_coords_rcsb(ident, state, scene, engine_updates, redraw, reset_cam);
 
@@ -113,29 +239,45 @@ pub fn handle_cmd(
 
     // todo: Save and load: Limited functionalitiy, and DRY with ui.
 
-    if let Some(caps) = re_save.captures(&input) {
+    if let Some(caps) = re_save.captures(input) {
         let filename = &caps[1];
         let path = PathBuf::from_str(filename).unwrap();
 
-        state.save(&path)?;
+        state.save(&path, &scene.camera)?;
 
         return Ok(format!("Saved {filename}"));
     }
 
     // todo: Load other types of file, e.g. map and mtz.
-    if let Some(caps) = re_load.captures(&input) {
+    if let Some(caps) = re_load.captures(input) {
         let filename = &caps[1];
         let path = PathBuf::from_str(filename).unwrap();
 
-        load_file(&path, state, redraw, reset_cam, engine_updates)?;
+        // Scripts expect the molecule to be ready after this.
+        load_file(&path, state, false, redraw, reset_cam, engine_updates)?;
         set_flashlight(scene);
         engine_updates.lighting = true;
 
         return Ok(format!("Loaded {filename}"));
     }
 
+    if let Some(caps) = re_show_surface.captures(input) {
+        let (Some(mol), Some(sel)) = (&state.molecule, state.named_selection(&caps[1])) else {
+            return Err(new_invalid("Unable to find this selection"));
+        };
+
+        let atoms = mol.sel_atom_indices(sel);
+
+        state.volatile.partial_surfaces.sel_atoms = atoms;
+        state.ui.visibility.hide_sel_surface = false;
+        state.volatile.flags.update_sel_sfc_mesh = true;
+        *redraw = true;
+
+        return Ok("Complete".to_owned());
+    }
+
     // Note: We don't have show and hide for the varous display items; this sets the display.
-    if let Some(caps) = re_show.captures(&input) {
+    if let Some(caps) = re_show.captures(input) {
         let mode = &caps[1];
 
         state.ui.mol_view = mode.parse()?;
@@ -143,7 +285,7 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_view.captures(&input) {
+    if let Some(caps) = re_view.captures(input) {
         let name = &caps[1];
 
         let mut recall = false;
@@ -175,7 +317,7 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_hide.captures(&input) {
+    if let Some(caps) = re_hide.captures(input) {
         let item = &caps[1].to_lowercase();
 
         // todo: To match PyMol, this should be much more robust. Hiding chains, residues etc.
@@ -196,6 +338,10 @@ pub fn handle_cmd(
                 // todo: The space won't work in the regex.
                 state.ui.visibility.hide_hydrogen = true;
             }
+            "surface" => {
+                state.ui.visibility.hide_sel_surface = true;
+                state.ui.visibility.hide_chain_surface = true;
+            }
             _ => (),
         }
 
@@ -203,7 +349,48 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_remove.captures(&input) {
+    // Color schemes, vice PyMol's per-selection colors.
+    if let Some(caps) = re_color.captures(input) {
+        let scheme = caps[1].to_lowercase();
+
+        state.ui.atom_color_by_charge = false;
+        state.ui.res_color_by_index = false;
+        state.ui.color_by_prop = None;
+
+        match scheme.as_ref() {
+            "element" | "atomic" => (),
+            "charge" => {
+                state.ui.atom_color_by_charge = true;
+                state.ui.view_sel_level = ViewSelLevel::Atom;
+            }
+            "index" | "resi" | "spectrum" => {
+                state.ui.res_color_by_index = true;
+                state.ui.view_sel_level = ViewSelLevel::Residue;
+            }
+            // PyMol's name.
+            "b" => state.ui.color_by_prop = Some(PROP_B_FACTOR.to_owned()),
+            _ => {
+                let Some(mol) = &state.molecule else {
+                    return Err(new_invalid("Can't color without a molecule"));
+                };
+                let Some(key) = mol
+                    .prop_keys()
+                    .into_iter()
+                    .find(|k| k.eq_ignore_ascii_case(&scheme))
+                else {
+                    return Err(new_invalid(
+                        "Color by element, charge, index, or a stored property",
+                    ));
+                };
+                state.ui.color_by_prop = Some(key);
+            }
+        }
+
+        *redraw = true;
+        return Ok("Complete".to_owned());
+    }
+
+    if let Some(caps) = re_remove.captures(input) {
         let item = &caps[1].to_lowercase();
 
         // todo: To match PyMol, this should be much more robust. Removing chains, residues etc.
@@ -257,13 +444,13 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_orient.captures(&input) {
+    if let Some(caps) = re_orient.captures(input) {
         engine_updates.camera = true;
 
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_turn.captures(&input) {
+    if let Some(caps) = re_turn.captures(input) {
         let Some(mol) = &state.molecule else {
             return Ok(String::from("Can't turn without a molecule"));
         };
@@ -287,7 +474,7 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_move.captures(&input) {
+    if let Some(caps) = re_move.captures(input) {
         let axis = match caps[1].to_lowercase().as_ref() {
             "x" => RIGHT_VEC,
             "y" => UP_VEC,
@@ -305,7 +492,7 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if let Some(caps) = re_orient.captures(&input) {
+    if let Some(caps) = re_orient.captures(input) {
         if let Some(mol) = &state.molecule {
             let atom_sel = mol.get_sel_atom(&state.ui.selection);
 
@@ -319,7 +506,7 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if re_reset.captures(&input).is_some() {
+    if re_reset.captures(input).is_some() {
         if let Some(mol) = &state.molecule {
             reset_camera(scene, &mut state.ui.view_depth, engine_updates, mol);
             engine_updates.camera = true;
@@ -327,16 +514,16 @@ pub fn handle_cmd(
         return Ok("Complete".to_owned());
     }
 
-    if re_pwd.captures(&input).is_some() {
+    if re_pwd.captures(input).is_some() {
         return Ok(format!("{}", env::current_dir()?.display()));
     }
 
-    if re_ls.captures(&input).is_some() {
+    if re_ls.captures(input).is_some() {
         let names = get_files_curdir()?;
         return Ok(names.join("   "));
     }
 
-    if let Some(caps) = re_cd.captures(&input) {
+    if let Some(caps) = re_cd.captures(input) {
         let dir = &caps[1];
 
         // Note: This doesn't handle ~ properly.
@@ -346,9 +533,9 @@ pub fn handle_cmd(
     }
 
     // Selections
-    if let Some(caps) = re_sel_resn.captures(&input) {
+    if let Some(caps) = re_sel_resn.captures(input) {
         if let Some(mol) = &state.molecule {
-            let aa = AminoAcid::from_str(&caps[1])?;
+            let aa = AminoAcid::from_str(&caps[2])?;
 
             let mut result = Vec::new();
 
@@ -360,23 +547,25 @@ pub fn handle_cmd(
                 }
             }
 
-            state.ui.selection = Selection::Atoms(result);
-            *redraw = true;
-            return Ok("Complete".to_owned());
+            return Ok(set_selection(
+                state,
+                Selection::Atoms(result),
+                caps.get(1),
+                redraw,
+            ));
         }
     }
 
-    if let Some(caps) = re_sel_resi.captures(&input) {
+    if let Some(caps) = re_sel_resi.captures(input) {
         if let Some(mol) = &state.molecule {
-            let i: isize = caps[1]
+            let i: isize = caps[2]
                 .parse()
                 .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid index."))?;
 
             for (i_res, res) in mol.residues.iter().enumerate() {
                 if res.serial_number == i {
-                    state.ui.selection = Selection::Residue(i_res);
-                    *redraw = true;
-                    return Ok("Complete".to_owned());
+                    let sel = Selection::Residue(i_res);
+                    return Ok(set_selection(state, sel, caps.get(1), redraw));
                 }
             }
 
@@ -384,9 +573,9 @@ pub fn handle_cmd(
         }
     }
 
-    if let Some(caps) = re_sel_elem.captures(&input) {
+    if let Some(caps) = re_sel_elem.captures(input) {
         if let Some(mol) = &state.molecule {
-            let el = Element::from_letter(&caps[1])?;
+            let el = Element::from_letter(&caps[2])?;
 
             let mut result = Vec::new();
             for (i, atom) in mol.atoms.iter().enumerate() {
@@ -395,13 +584,26 @@ pub fn handle_cmd(
                 }
             }
 
-            state.ui.selection = Selection::Atoms(result);
-            *redraw = true;
-            return Ok("Complete".to_owned());
+            return Ok(set_selection(
+                state,
+                Selection::Atoms(result),
+                caps.get(1),
+                redraw,
+            ));
         }
     }
 
-    if let Some(caps) = re_set.captures(&input) {
+    if let Some(caps) = re_sel_named.captures(input) {
+        let Some(sel) = state.named_selection(&caps[1]) else {
+            return Err(new_invalid("Unable to find this selection"));
+        };
+
+        state.ui.selection = sel.clone();
+        *redraw = true;
+        return Ok("Complete".to_owned());
+    }
+
+    if let Some(caps) = re_set.captures(input) {
         let action = &caps[1].to_lowercase();
 
         match action.as_ref() {
@@ -426,9 +628,175 @@ pub fn handle_cmd(
         }
     }
 
+    if let Some(caps) = re_measure.captures(input) {
+        let Some(mol) = &state.molecule else {
+            return Err(new_invalid("Can't measure without a molecule"));
+        };
+
+        let mut posits = Vec::new();
+        for sn in caps[2].split(',') {
+            let sn: usize = sn
+                .trim()
+                .parse()
+                .map_err(|_| new_invalid("Invalid atom serial number"))?;
+            let Some(atom) = mol.atoms.iter().find(|a| a.serial_number == sn) else {
+                return Err(new_invalid(&format!("Unable to find atom {sn}")));
+            };
+            posits.push(atom.posit);
+        }
+
+        let unit = state.to_save.angle_unit;
+        return match (caps[1].to_lowercase().as_ref(), posits.as_slice()) {
+            ("distance", [a, b]) => Ok(format!("{:.3} Å", (*b - *a).magnitude())),
+            ("angle", [a, b, c]) => {
+                let (u, v) = ((*a - *b).to_normalized(), (*c - *b).to_normalized());
+                Ok(unit.fmt(u.dot(v).clamp(-1., 1.).acos()))
+            }
+            ("dihedral", [a, b, c, d]) => Ok(unit.fmt(calc_dihedral_angle_v2(&(*a, *b, *c, *d)))),
+            _ => Err(new_invalid(
+                "Distances take 2 atoms, angles 3, and dihedrals 4",
+            )),
+        };
+    }
+
+    if re_dock.captures(input).is_some() {
+        dock_ligand(state, scene, engine_updates).map_err(|e| new_invalid(&e.to_string()))?;
+        draw_ligand(state, scene);
+        engine_updates.entities = true;
+
+        return Ok("Docked the ligand".to_owned());
+    }
+
+    if let Some(caps) = re_md.captures(input) {
+        if let Some(steps) = caps.get(1) {
+            state.ui.protein_md_steps = steps
+                .as_str()
+                .parse()
+                .map_err(|_| new_invalid("Invalid step count"))?;
+        }
+
+        return run_protein_md(state, scene, engine_updates)
+            .map_err(|e| new_invalid(&e.to_string()));
+    }
+
+    if let Some(caps) = re_record.captures(input) {
+        let action = caps.get(1).map(|m| m.as_str().to_lowercase());
+
+        match action.as_deref() {
+            None | Some("start") => {
+                state.volatile.macro_recording = Some(Vec::new());
+                return Ok("Recording. Use `record stop` to finish.".to_owned());
+            }
+            Some("stop") => {
+                let Some(cmds) = state.volatile.macro_recording.take() else {
+                    return Err(new_invalid("Not recording"));
+                };
+                let len = cmds.len();
+                state.volatile.macro_last = cmds;
+                return Ok(format!(
+                    "Recorded {len} commands. Save with `record save <file>`."
+                ));
+            }
+            _ => {
+                let Some(filename) = caps.get(2) else {
+                    return Err(new_invalid("Missing a file name to save the macro to"));
+                };
+                let cmds = match &state.volatile.macro_recording {
+                    Some(cmds) => cmds,
+                    None => &state.volatile.macro_last,
+                };
+
+                save_macro(cmds, Path::new(filename.as_str()))?;
+                return Ok(format!(
+                    "Saved {} commands to {}",
+                    cmds.len(),
+                    filename.as_str()
+                ));
+            }
+        }
+    }
+
+    if let Some(caps) = re_run.captures(input) {
+        let path = PathBuf::from_str(&caps[1]).unwrap();
+        return run_script(&path, state, scene, engine_updates, redraw, reset_cam);
+    }
+
+    if let Some(caps) = re_assembly.captures(input) {
+        let Some(mol) = &state.molecule else {
+            return Err(new_invalid("No molecule is open"));
+        };
+
+        let Some(id) = caps.get(1) else {
+            if mol.assemblies.is_empty() {
+                return Ok("This molecule has no biological assemblies".to_owned());
+            }
+            let list: Vec<_> = mol.assemblies.iter().map(|a| a.to_string()).collect();
+            return Ok(list.join("\n"));
+        };
+
+        if id.as_str().eq_ignore_ascii_case("off") {
+            state.ui.assembly = None;
+            *redraw = true;
+            return Ok("Complete".to_owned());
+        }
+
+        let Some(i) = mol
+            .assemblies
+            .iter()
+            .position(|a| a.id.eq_ignore_ascii_case(id.as_str()))
+        else {
+            return Err(new_invalid(&format!(
+                "Unable to find assembly {}",
+                id.as_str()
+            )));
+        };
+
+        if caps.get(2).is_some() {
+            let Some(built) = mol.build_assembly(i) else {
+                return Err(new_invalid("Unable to build this assembly"));
+            };
+            let msg = format!(
+                "Built assembly {} with {} atoms",
+                id.as_str(),
+                built.atoms.len()
+            );
+
+            state.open_built_molecule(built);
+            *redraw = true;
+            return Ok(msg);
+        }
+
+        state.ui.assembly = Some(i);
+        *redraw = true;
+        return Ok(format!("Showing assembly {}", mol.assemblies[i]));
+    }
+
+    if let Some(result) = state.run_plugin_cmd(input) {
+        return result;
+    }
+
     Err(new_invalid("Can't find that command"))
 }
 
+/// Make a selection current, and if a name is given, save it under that name.
+fn set_selection(
+    state: &mut State,
+    sel: Selection,
+    name: Option<Match>,
+    redraw: &mut bool,
+) -> String {
+    state.ui.selection = sel.clone();
+    *redraw = true;
+
+    match name {
+        Some(name) => {
+            state.save_named_selection(name.as_str(), sel);
+            format!("Saved selection {}", name.as_str())
+        }
+        None => "Complete".to_owned(),
+    }
+}
+
 fn get_files_curdir() -> io::Result<Vec<String>> {
     let entries = fs::read_dir(env::current_dir()?)?;
     Ok(entries
@@ -0,0 +1,253 @@
+//! A compact, struct-of-arrays layout for atoms, for memory budget mode. Positions are `f32`, and
+//! names and force field types are interned, so each is stored once per molecule instead of once
+//! per atom. Other fields are packed, with sentinel values in place of `Option`s. This cuts memory
+//! per atom by roughly 4x compared to `Vec<Atom>`.
+//!
+//! Molecules in this form aren't drawn, or used in calculations; expand them back to `Atom`s first.
+//! `posit()` converts a single position to `f64`, for physics that doesn't need the rest.
+
+use std::{collections::HashMap, mem::size_of, str::FromStr};
+
+use lin_alg::f64::Vec3;
+use na_seq::{AtomTypeInRes, Element};
+
+use crate::{
+    docking::prep::DockType,
+    molecule::{Atom, AtomRole, PropVal, Properties},
+};
+
+/// In place of `None`, for indices.
+const NONE_U32: u32 = u32::MAX;
+
+const FLAG_HETERO: u8 = 1;
+const FLAG_IN_RING: u8 = 1 << 1;
+const FLAG_AROMATIC: u8 = 1 << 2;
+
+/// Stores each distinct string once; atoms refer to them by index.
+#[derive(Clone, Debug, Default)]
+pub struct StrInterner {
+    strings: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl StrInterner {
+    pub fn intern(&mut self, s: &str) -> u32 {
+        if let Some(i) = self.indices.get(s) {
+            return *i;
+        }
+        let i = self.strings.len() as u32;
+        self.strings.push(s.to_owned());
+        self.indices.insert(s.to_owned(), i);
+        i
+    }
+
+    pub fn get(&self, i: u32) -> Option<&str> {
+        self.strings.get(i as usize).map(|s| s.as_str())
+    }
+
+    fn heap_size(&self) -> usize {
+        self.strings.iter().map(|s| 2 * s.len()).sum::<usize>()
+            + self.strings.capacity() * size_of::<String>()
+            + self.indices.capacity() * (size_of::<String>() + size_of::<u32>())
+    }
+}
+
+/// `None` maps to NaN.
+fn pack_f32(v: Option<f32>) -> f32 {
+    v.unwrap_or(f32::NAN)
+}
+
+fn unpack_f32(v: f32) -> Option<f32> {
+    (!v.is_nan()).then_some(v)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CompactAtoms {
+    pub serial_numbers: Vec<u32>,
+    /// Å
+    pub posits: Vec<[f32; 3]>,
+    pub elements: Vec<Element>,
+    /// Indices into `strings`.
+    pub type_in_res: Vec<u32>,
+    pub force_field_types: Vec<u32>,
+    pub dock_types: Vec<Option<DockType>>,
+    pub roles: Vec<Option<AtomRole>>,
+    pub residues: Vec<u32>,
+    pub occupancies: Vec<f32>,
+    pub partial_charges: Vec<f32>,
+    pub temperature_factors: Vec<f32>,
+    /// ASCII; 0 for none.
+    pub alt_locs: Vec<u8>,
+    /// Only atoms that have anisotropic displacement parameters are stored.
+    pub anisou: HashMap<u32, [f32; 6]>,
+    /// Hetero, in ring, aromatic.
+    pub flags: Vec<u8>,
+    /// Few atoms have properties; only those that do are stored.
+    pub props: HashMap<u32, Properties>,
+    pub strings: StrInterner,
+}
+
+impl CompactAtoms {
+    pub fn from_atoms(atoms: &[Atom]) -> Self {
+        let n = atoms.len();
+        let mut result = Self {
+            serial_numbers: Vec::with_capacity(n),
+            posits: Vec::with_capacity(n),
+            elements: Vec::with_capacity(n),
+            type_in_res: Vec::with_capacity(n),
+            force_field_types: Vec::with_capacity(n),
+            dock_types: Vec::with_capacity(n),
+            roles: Vec::with_capacity(n),
+            residues: Vec::with_capacity(n),
+            occupancies: Vec::with_capacity(n),
+            partial_charges: Vec::with_capacity(n),
+            temperature_factors: Vec::with_capacity(n),
+            alt_locs: Vec::with_capacity(n),
+            flags: Vec::with_capacity(n),
+            ..Default::default()
+        };
+
+        for (i, atom) in atoms.iter().enumerate() {
+            let type_in_res = match &atom.type_in_res {
+                Some(t) => result.strings.intern(&t.to_string()),
+                None => NONE_U32,
+            };
+            let ff_type = match &atom.force_field_type {
+                Some(t) => result.strings.intern(t),
+                None => NONE_U32,
+            };
+
+            let mut flags = 0;
+            if atom.hetero {
+                flags |= FLAG_HETERO;
+            }
+            if atom.in_ring {
+                flags |= FLAG_IN_RING;
+            }
+            if atom.aromatic {
+                flags |= FLAG_AROMATIC;
+            }
+
+            result.serial_numbers.push(atom.serial_number as u32);
+            result.posits.push([
+                atom.posit.x as f32,
+                atom.posit.y as f32,
+                atom.posit.z as f32,
+            ]);
+            result.elements.push(atom.element);
+            result.type_in_res.push(type_in_res);
+            result.force_field_types.push(ff_type);
+            result.dock_types.push(atom.dock_type);
+            result.roles.push(atom.role);
+            result
+                .residues
+                .push(atom.residue.map(|r| r as u32).unwrap_or(NONE_U32));
+            result.occupancies.push(pack_f32(atom.occupancy));
+            result.partial_charges.push(pack_f32(atom.partial_charge));
+            result
+                .temperature_factors
+                .push(pack_f32(atom.temperature_factor));
+            result
+                .alt_locs
+                .push(atom.alt_loc.map(|c| c as u8).unwrap_or_default());
+            result.flags.push(flags);
+
+            if let Some(u) = atom.anisou {
+                result.anisou.insert(i as u32, u);
+            }
+
+            if !atom.props.is_empty() {
+                result.props.insert(i as u32, atom.props.clone());
+            }
+        }
+
+        result
+    }
+
+    pub fn len(&self) -> usize {
+        self.posits.len()
+    }
+
+    pub fn posit(&self, i: usize) -> Vec3 {
+        let [x, y, z] = self.posits[i];
+        Vec3::new(x as f64, y as f64, z as f64)
+    }
+
+    fn string(&self, i: u32) -> Option<&str> {
+        if i == NONE_U32 {
+            None
+        } else {
+            self.strings.get(i)
+        }
+    }
+
+    pub fn atom(&self, i: usize) -> Atom {
+        let flags = self.flags[i];
+
+        Atom {
+            serial_number: self.serial_numbers[i] as usize,
+            posit: self.posit(i),
+            element: self.elements[i],
+            type_in_res: self
+                .string(self.type_in_res[i])
+                .and_then(|s| AtomTypeInRes::from_str(s).ok()),
+            force_field_type: self.string(self.force_field_types[i]).map(|s| s.to_owned()),
+            dock_type: self.dock_types[i],
+            role: self.roles[i],
+            residue: match self.residues[i] {
+                NONE_U32 => None,
+                r => Some(r as usize),
+            },
+            hetero: flags & FLAG_HETERO != 0,
+            occupancy: unpack_f32(self.occupancies[i]),
+            partial_charge: unpack_f32(self.partial_charges[i]),
+            temperature_factor: unpack_f32(self.temperature_factors[i]),
+            alt_loc: match self.alt_locs[i] {
+                0 => None,
+                c => Some(c as char),
+            },
+            anisou: self.anisou.get(&(i as u32)).copied(),
+            props: self.props.get(&(i as u32)).cloned().unwrap_or_default(),
+            in_ring: flags & FLAG_IN_RING != 0,
+            aromatic: flags & FLAG_AROMATIC != 0,
+        }
+    }
+
+    /// Expand to the full representation. Positions lose precision beyond `f32`'s; about 1e-5 Å
+    /// for typical coordinates.
+    pub fn to_atoms(&self) -> Vec<Atom> {
+        (0..self.len()).map(|i| self.atom(i)).collect()
+    }
+
+    /// Bytes, approximately.
+    pub fn heap_size(&self) -> usize {
+        let n = self.len();
+        n * (size_of::<u32>() * 3
+            + size_of::<[f32; 3]>()
+            + size_of::<Element>()
+            + size_of::<Option<DockType>>()
+            + size_of::<Option<AtomRole>>()
+            + size_of::<u32>() // residue
+            + size_of::<f32>() * 3
+            + size_of::<u8>() * 2)
+            + self.anisou.len() * (size_of::<u32>() + size_of::<[f32; 6]>())
+            + self.props.len() * (size_of::<u32>() + size_of::<Properties>())
+            + self.strings.heap_size()
+    }
+}
+
+/// Bytes, approximately, used by atoms in the full representation; for comparison with
+/// `CompactAtoms::heap_size`.
+pub fn atoms_heap_size(atoms: &[Atom]) -> usize {
+    atoms
+        .iter()
+        .map(|a| {
+            size_of::<Atom>()
+                + a.force_field_type
+                    .as_ref()
+                    .map(|s| s.capacity())
+                    .unwrap_or(0)
+                + a.props.capacity() * size_of::<(String, PropVal)>()
+        })
+        .sum()
+}
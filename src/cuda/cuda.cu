@@ -2,6 +2,8 @@
 #include <initializer_list>
 
 #include "util.cu"
+#include "dynamics.cu"
+#include "neighbors.cu"
 
 
 // __device__
@@ -149,4 +151,254 @@ void reflection_transform_kernel(
         //  real part of  F · e^{iφ} · e^{iarg} = amp·cos(φ+arg)
         out[i] += amp[i]* cosf(phase[i] + arg);
     }
-}
\ No newline at end of file
+}
+// Signed-squared-distance field of a union of spheres, for the solvent-accessible surface.
+// One block per sphere; its threads split the voxels in the sphere's bounding box. `out` must be
+// initialized to a value well outside the surface.
+extern "C" __global__
+void sas_field_kernel(
+    float *out,
+    const float3 *centers,
+    const float *radii,
+    size_t N_spheres,
+    float bb_min_x,
+    float bb_min_y,
+    float bb_min_z,
+    float precision,
+    size_t dim_x,
+    size_t dim_y,
+    size_t dim_z
+) {
+    size_t i_sphere = blockIdx.x;
+    if (i_sphere >= N_spheres) return;
+
+    float3 center = centers[i_sphere];
+    float rad = radii[i_sphere];
+    float rad2 = rad * rad;
+
+    size_t x0 = (size_t)fmaxf(floorf((center.x - rad - bb_min_x) / precision), 0.0f);
+    size_t y0 = (size_t)fmaxf(floorf((center.y - rad - bb_min_y) / precision), 0.0f);
+    size_t z0 = (size_t)fmaxf(floorf((center.z - rad - bb_min_z) / precision), 0.0f);
+
+    size_t x1 = (size_t)fminf(ceilf((center.x + rad - bb_min_x) / precision), (float)(dim_x - 1));
+    size_t y1 = (size_t)fminf(ceilf((center.y + rad - bb_min_y) / precision), (float)(dim_y - 1));
+    size_t z1 = (size_t)fminf(ceilf((center.z + rad - bb_min_z) / precision), (float)(dim_z - 1));
+
+    size_t nx = x1 - x0 + 1;
+    size_t ny = y1 - y0 + 1;
+    size_t n_box = nx * ny * (z1 - z0 + 1);
+
+    for (size_t i = threadIdx.x; i < n_box; i += blockDim.x) {
+        size_t x = x0 + i % nx;
+        size_t y = y0 + (i / nx) % ny;
+        size_t z = z0 + i / (nx * ny);
+
+        float3 p = make_float3(
+            bb_min_x + x * precision,
+            bb_min_y + y * precision,
+            bb_min_z + z * precision
+        );
+        float3 diff = p - center;
+        float v = diff.x * diff.x + diff.y * diff.y + diff.z * diff.z - rad2;
+
+        atomicMinFloat(&out[(z * dim_y + y) * dim_x + x], v);
+    }
+}
+
+// Marching cubes over a field from `sas_field_kernel`, with the surface at 0. Grid edge
+// `3 * voxel + axis` runs from the voxel along +x, +y, or +z. We flag edges the surface crosses,
+// scan the flags into vertex indices (`nb_scan_kernel`), and place a vertex on each. Then we count
+// triangles per cube from the case table, scan the counts, and write the triangles.
+
+// Corners of each cube edge. Matches `CUBE_EDGES` in `sa_surface.rs`.
+__constant__ unsigned char MC_EDGE_CORNERS[12][2] = {
+    {0, 1}, {2, 3}, {4, 5}, {6, 7},
+    {0, 2}, {1, 3}, {4, 6}, {5, 7},
+    {0, 4}, {1, 5}, {2, 6}, {3, 7},
+};
+
+// Entries per case in the table: up to 5 triangles, and a -1 terminator.
+__device__
+const unsigned int MC_TABLE_ROW = 16;
+
+__device__ inline size_t mc_voxel(size_t x, size_t y, size_t z, size_t dim_x, size_t dim_y) {
+    return (z * dim_y + y) * dim_x + x;
+}
+
+// Bit `i` is set if corner `i` of the cube at (x, y, z) is inside the surface.
+__device__ inline unsigned int mc_case(
+    const float *field, size_t x, size_t y, size_t z, size_t dim_x, size_t dim_y
+) {
+    unsigned int result = 0;
+    for (unsigned int c = 0; c < 8; c++) {
+        size_t i = mc_voxel(x + (c & 1), y + ((c >> 1) & 1), z + ((c >> 2) & 1), dim_x, dim_y);
+        if (field[i] < 0.0f) result |= 1u << c;
+    }
+    return result;
+}
+
+// Central differences, or one-sided at the grid's boundary. This points out of the surface.
+__device__ inline float3 mc_gradient(
+    const float *field, size_t x, size_t y, size_t z, size_t dim_x, size_t dim_y, size_t dim_z
+) {
+    size_t x0 = x > 0 ? x - 1 : x, x1 = x + 1 < dim_x ? x + 1 : x;
+    size_t y0 = y > 0 ? y - 1 : y, y1 = y + 1 < dim_y ? y + 1 : y;
+    size_t z0 = z > 0 ? z - 1 : z, z1 = z + 1 < dim_z ? z + 1 : z;
+
+    return make_float3(
+        (field[mc_voxel(x1, y, z, dim_x, dim_y)] - field[mc_voxel(x0, y, z, dim_x, dim_y)]) / (float)(x1 - x0),
+        (field[mc_voxel(x, y1, z, dim_x, dim_y)] - field[mc_voxel(x, y0, z, dim_x, dim_y)]) / (float)(y1 - y0),
+        (field[mc_voxel(x, y, z1, dim_x, dim_y)] - field[mc_voxel(x, y, z0, dim_x, dim_y)]) / (float)(z1 - z0)
+    );
+}
+
+// Flag grid edges the surface crosses. `flags` has `3 * N_voxels + 1` entries, for the scan.
+extern "C" __global__
+void mc_edge_flags_kernel(
+    unsigned int *flags,
+    const float *field,
+    size_t dim_x,
+    size_t dim_y,
+    size_t dim_z
+) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= 3 * dim_x * dim_y * dim_z) return;
+
+    size_t vox = i / 3;
+    unsigned int axis = i % 3;
+
+    size_t x = vox % dim_x;
+    size_t y = (vox / dim_x) % dim_y;
+    size_t z = vox / (dim_x * dim_y);
+
+    unsigned int flag = 0;
+    if ((axis == 0 && x + 1 < dim_x) || (axis == 1 && y + 1 < dim_y) || (axis == 2 && z + 1 < dim_z)) {
+        size_t stride = axis == 0 ? 1 : (axis == 1 ? dim_x : dim_x * dim_y);
+        flag = (field[vox] < 0.0f) != (field[vox + stride] < 0.0f);
+    }
+    flags[i] = flag;
+}
+
+// Place a vertex at the zero crossing of each flagged edge, with a normal from the field's
+// gradient. `offsets` is the scanned flags.
+extern "C" __global__
+void mc_vertices_kernel(
+    float3 *posits,
+    float3 *normals,
+    const unsigned int *offsets,
+    const float *field,
+    float bb_min_x,
+    float bb_min_y,
+    float bb_min_z,
+    float precision,
+    size_t dim_x,
+    size_t dim_y,
+    size_t dim_z
+) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= 3 * dim_x * dim_y * dim_z) return;
+    if (offsets[i + 1] == offsets[i]) return;
+
+    size_t vox = i / 3;
+    unsigned int axis = i % 3;
+
+    size_t x = vox % dim_x;
+    size_t y = (vox / dim_x) % dim_y;
+    size_t z = vox / (dim_x * dim_y);
+
+    size_t x1 = x + (axis == 0);
+    size_t y1 = y + (axis == 1);
+    size_t z1 = z + (axis == 2);
+
+    float v0 = field[vox];
+    float v1 = field[mc_voxel(x1, y1, z1, dim_x, dim_y)];
+    // The flag means these have opposite signs.
+    float t = v0 / (v0 - v1);
+
+    float3 p0 = make_float3(bb_min_x + x * precision, bb_min_y + y * precision, bb_min_z + z * precision);
+    float3 p1 = make_float3(bb_min_x + x1 * precision, bb_min_y + y1 * precision, bb_min_z + z1 * precision);
+
+    float3 n = mc_gradient(field, x, y, z, dim_x, dim_y, dim_z) * (1.0f - t)
+        + mc_gradient(field, x1, y1, z1, dim_x, dim_y, dim_z) * t;
+    float n_len = sqrtf(dot3(n, n));
+
+    posits[offsets[i]] = p0 + (p1 - p0) * t;
+    normals[offsets[i]] = n_len > 0.0f ? n / n_len : n;
+}
+
+// Count triangles in each cube. `counts` has `N_cubes + 1` entries, for the scan.
+extern "C" __global__
+void mc_tri_counts_kernel(
+    unsigned int *counts,
+    const float *field,
+    const signed char *tri_table,
+    size_t dim_x,
+    size_t dim_y,
+    size_t dim_z
+) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t nx = dim_x - 1;
+    size_t ny = dim_y - 1;
+    if (i >= nx * ny * (dim_z - 1)) return;
+
+    unsigned int case_ = mc_case(field, i % nx, (i / nx) % ny, i / (nx * ny), dim_x, dim_y);
+    const signed char *row = tri_table + MC_TABLE_ROW * case_;
+
+    unsigned int n = 0;
+    while (row[n] >= 0) n++;
+    counts[i] = n / 3;
+}
+
+// Write each cube's triangles, as indices into the vertices. `tri_offsets` and `vert_offsets` are
+// the scanned counts and flags. Triangles wind counter-clockwise, seen from outside.
+extern "C" __global__
+void mc_triangles_kernel(
+    unsigned int *indices,
+    const unsigned int *tri_offsets,
+    const unsigned int *vert_offsets,
+    const float3 *posits,
+    const float3 *normals,
+    const float *field,
+    const signed char *tri_table,
+    size_t dim_x,
+    size_t dim_y,
+    size_t dim_z
+) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t nx = dim_x - 1;
+    size_t ny = dim_y - 1;
+    if (i >= nx * ny * (dim_z - 1)) return;
+    if (tri_offsets[i + 1] == tri_offsets[i]) return;
+
+    size_t x = i % nx;
+    size_t y = (i / nx) % ny;
+    size_t z = i / (nx * ny);
+
+    const signed char *row = tri_table + MC_TABLE_ROW * mc_case(field, x, y, z, dim_x, dim_y);
+    unsigned int out = 3 * tri_offsets[i];
+
+    for (unsigned int t = 0; row[t] >= 0; t += 3) {
+        unsigned int v[3];
+        for (unsigned int k = 0; k < 3; k++) {
+            unsigned int a = MC_EDGE_CORNERS[row[t + k]][0];
+            unsigned int b = MC_EDGE_CORNERS[row[t + k]][1];
+            unsigned int axis = (a ^ b) == 1 ? 0 : ((a ^ b) == 2 ? 1 : 2);
+
+            size_t vox = mc_voxel(x + (a & 1), y + ((a >> 1) & 1), z + ((a >> 2) & 1), dim_x, dim_y);
+            v[k] = vert_offsets[3 * vox + axis];
+        }
+
+        // The table doesn't track winding; orient each triangle with its vertices' normals.
+        float3 n_face = cross3(posits[v[1]] - posits[v[0]], posits[v[2]] - posits[v[0]]);
+        float3 n_verts = normals[v[0]] + normals[v[1]] + normals[v[2]];
+        if (dot3(n_face, n_verts) < 0.0f) {
+            unsigned int tmp = v[1];
+            v[1] = v[2];
+            v[2] = tmp;
+        }
+
+        indices[out++] = v[0];
+        indices[out++] = v[1];
+        indices[out++] = v[2];
+    }
+}
@@ -0,0 +1,553 @@
+// Kernels for running molecular dynamics steps on the GPU. These mirror `MdState::step`, and its
+// force terms, so that atom state stays on the device between steps. Positions, velocities, and
+// accelerations are in the same units as on the CPU: Å, Å/fs, and (until `md_kick_kernel`)
+// force / mass.
+
+__device__
+const float COULOMB_CONST = 332.0636f;
+
+__device__
+const float ACCEL_CONV = 4.184e-4f;
+
+// Indices into `terms`, which the force kernels accumulate energies, and the virial (Σ r·f), into, in
+// kcal/mol. Matches `ReportTerms`.
+__device__
+const unsigned int TERM_BONDED = 0;
+__device__
+const unsigned int TERM_LJ = 1;
+__device__
+const unsigned int TERM_COULOMB = 2;
+__device__
+const unsigned int TERM_VIRIAL = 3;
+
+__device__ inline float dot3(float3 a, float3 b) {
+    return a.x * b.x + a.y * b.y + a.z * b.z;
+}
+
+__device__ inline float3 cross3(float3 a, float3 b) {
+    return make_float3(a.y * b.z - a.z * b.y, a.z * b.x - a.x * b.z, a.x * b.y - a.y * b.x);
+}
+
+// Minimum-image displacement in an orthorhombic box.
+__device__ inline float3 min_image(float3 d, float3 ext) {
+    return make_float3(
+        d.x - rintf(d.x / ext.x) * ext.x,
+        d.y - rintf(d.y / ext.y) * ext.y,
+        d.z - rintf(d.z / ext.z) * ext.z
+    );
+}
+
+__device__ inline float wrap_axis(float v, float lo, float ext) {
+    float rel = fmodf(v - lo, ext);
+    if (rel < 0.0f) rel += ext;
+    return rel + lo;
+}
+
+__device__ inline void atomicAdd3(float3 *addr, float3 v) {
+    atomicAdd(&addr->x, v.x);
+    atomicAdd(&addr->y, v.y);
+    atomicAdd(&addr->z, v.z);
+}
+
+// Atomic max for non-negative floats, which order the same as their bits as ints.
+__device__ inline void atomicMaxFloatPos(float *addr, float value) {
+    atomicMax((int *)addr, __float_as_int(value));
+}
+
+// First half-kick, and drift. Wraps positions into the box, and zeros accelerations for the force
+// kernels to accumulate into.
+extern "C" __global__
+void md_kick_drift_kernel(
+    float3 *posits,
+    float3 *vels,
+    float3 *accels,
+    float dt,
+    float lo_x,
+    float lo_y,
+    float lo_z,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        float3 v = vels[i] + accels[i] * (0.5f * dt);
+        float3 p = posits[i] + v * dt;
+
+        posits[i] = make_float3(
+            wrap_axis(p.x, lo_x, ext_x),
+            wrap_axis(p.y, lo_y, ext_y),
+            wrap_axis(p.z, lo_z, ext_z)
+        );
+        vels[i] = v;
+        accels[i] = make_float3(0.0f, 0.0f, 0.0f);
+    }
+}
+
+// Converts accumulated force / mass to acceleration, and applies the second half-kick.
+extern "C" __global__
+void md_kick_kernel(
+    float3 *vels,
+    float3 *accels,
+    float dt,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        float3 a = accels[i] * ACCEL_CONV;
+        accels[i] = a;
+        vels[i] = vels[i] + a * (0.5f * dt);
+    }
+}
+
+// Sum of m·v², in amu·Å²/fs². Divide by 2 · ACCEL_CONV for kinetic energy in kcal/mol.
+extern "C" __global__
+void md_mv_sq_kernel(
+    float *out,
+    const float3 *vels,
+    const float *masses,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        atomicAdd(out, masses[i] * dot3(vels[i], vels[i]));
+    }
+}
+
+extern "C" __global__
+void md_scale_vels_kernel(
+    float3 *vels,
+    float scale,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        vels[i] = vels[i] * scale;
+    }
+}
+
+// The largest squared distance any atom has moved since the neighbor lists were built.
+extern "C" __global__
+void md_max_disp_sq_kernel(
+    float *out,
+    const float3 *posits,
+    const float3 *posits_at_build,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        float3 d = min_image(posits[i] - posits_at_build[i], ext);
+        atomicMaxFloatPos(out, dot3(d, d));
+    }
+}
+
+// One thread per bond. Amber convention: V = k(r - r₀)²
+extern "C" __global__
+void md_bond_stretching_kernel(
+    float3 *accels,
+    float *terms,
+    const float3 *posits,
+    const float *masses,
+    const unsigned int *bond_atoms, // 2 per bond
+    const float *k_b,
+    const float *r_0,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    size_t N_bonds
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t b = index; b < N_bonds; b += stride) {
+        unsigned int i = bond_atoms[2 * b];
+        unsigned int j = bond_atoms[2 * b + 1];
+
+        float3 diff = min_image(posits[j] - posits[i], ext);
+        float dist = sqrtf(dot3(diff, diff));
+        float r_delta = dist - r_0[b];
+
+        float3 f = diff * (2.0f * k_b[b] * r_delta / fmaxf(dist, 1e-12f));
+
+        atomicAdd3(&accels[i], f / masses[i]);
+        atomicAdd3(&accels[j], f * (-1.0f / masses[j]));
+        atomicAdd(&terms[TERM_BONDED], k_b[b] * r_delta * r_delta);
+        atomicAdd(&terms[TERM_VIRIAL], -dot3(diff, f));
+    }
+}
+
+// One thread per valence angle. The middle atom is the vertex.
+extern "C" __global__
+void md_angle_bending_kernel(
+    float3 *accels,
+    float *terms,
+    const float3 *posits,
+    const float *masses,
+    const unsigned int *angle_atoms, // 3 per angle
+    const float *k,
+    const float *theta_0,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    size_t N_angles
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t a = index; a < N_angles; a += stride) {
+        unsigned int i0 = angle_atoms[3 * a];
+        unsigned int i1 = angle_atoms[3 * a + 1];
+        unsigned int i2 = angle_atoms[3 * a + 2];
+
+        float3 b01 = min_image(posits[i0] - posits[i1], ext);
+        float3 b21 = min_image(posits[i2] - posits[i1], ext);
+
+        float b01_sq = dot3(b01, b01);
+        float b21_sq = dot3(b21, b21);
+        if (b01_sq < 1e-8f || b21_sq < 1e-8f) continue;
+
+        float b01_len = sqrtf(b01_sq);
+        float b21_len = sqrtf(b21_sq);
+
+        float cos_theta = fminf(fmaxf(dot3(b01, b21) / (b01_len * b21_len), -1.0f), 1.0f);
+        if (1.0f - cos_theta * cos_theta < 1e-8f) continue;
+
+        float d_theta = theta_0[a] - acosf(cos_theta);
+        float dV_dtheta = 2.0f * k[a] * d_theta;
+
+        float3 c = cross3(b01, b21);
+        float c_len_sq = dot3(c, c);
+
+        float3 f0 = cross3(c, b01) * (-dV_dtheta * b21_len / c_len_sq);
+        float3 f2 = cross3(b21, c) * (-dV_dtheta * b01_len / c_len_sq);
+        float3 f1 = (f0 + f2) * -1.0f;
+
+        atomicAdd3(&accels[i0], f0 / masses[i0]);
+        atomicAdd3(&accels[i1], f1 / masses[i1]);
+        atomicAdd3(&accels[i2], f2 / masses[i2]);
+        atomicAdd(&terms[TERM_BONDED], k[a] * d_theta * d_theta);
+        atomicAdd(&terms[TERM_VIRIAL], dot3(b01, f0) + dot3(b21, f2));
+    }
+}
+
+// The dihedral angle, IUPAC convention, and its gradient with respect to each atom's position, from
+// the bond vectors f = r0 - r1, g = r1 - r2, and h = r3 - r2. Matches `dihedral_grad`. Returns false
+// if either plane is degenerate. (Blondel and Karplus, 1996)
+__device__ inline bool dihedral_grad(float3 f, float3 g, float3 h, float *phi, float3 *d) {
+    float3 a = cross3(f, g);
+    float3 b = cross3(h, g);
+
+    float a_sq = dot3(a, a);
+    float b_sq = dot3(b, b);
+    float g_len = sqrtf(dot3(g, g));
+    if (a_sq < 1e-8f || b_sq < 1e-8f || g_len < 1e-8f) return false;
+
+    *phi = atan2f(dot3(cross3(b, a), g) / g_len, dot3(a, b));
+
+    float fg = dot3(f, g) / (a_sq * g_len);
+    float hg = dot3(h, g) / (b_sq * g_len);
+
+    d[0] = a * (-g_len / a_sq);
+    d[3] = b * (g_len / b_sq);
+    d[1] = d[0] * -1.0f + a * fg - b * hg;
+    d[2] = d[3] * -1.0f - a * fg + b * hg;
+    return true;
+}
+
+// One thread per proper or improper dihedral. Matches `f_dihedral`; like it, `barrier_height` is
+// pre-divided.
+extern "C" __global__
+void md_dihedral_kernel(
+    float3 *accels,
+    float *terms,
+    const float3 *posits,
+    const float *masses,
+    const unsigned int *dihedral_atoms, // 4 per dihedral
+    const float *barrier_height,
+    const float *phase,
+    const float *periodicity,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    size_t N_dihedrals
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t d = index; d < N_dihedrals; d += stride) {
+        unsigned int i0 = dihedral_atoms[4 * d];
+        unsigned int i1 = dihedral_atoms[4 * d + 1];
+        unsigned int i2 = dihedral_atoms[4 * d + 2];
+        unsigned int i3 = dihedral_atoms[4 * d + 3];
+
+        float phi;
+        float3 dphi[4];
+        if (!dihedral_grad(
+            min_image(posits[i0] - posits[i1], ext),
+            min_image(posits[i1] - posits[i2], ext),
+            min_image(posits[i3] - posits[i2], ext),
+            &phi,
+            dphi
+        )) continue;
+
+        float per = periodicity[d];
+        float arg = per * phi - phase[d];
+        float dV_dphi = -barrier_height[d] * per * sinf(arg);
+
+        atomicAdd3(&accels[i0], dphi[0] * (-dV_dphi / masses[i0]));
+        atomicAdd3(&accels[i1], dphi[1] * (-dV_dphi / masses[i1]));
+        atomicAdd3(&accels[i2], dphi[2] * (-dV_dphi / masses[i2]));
+        atomicAdd3(&accels[i3], dphi[3] * (-dV_dphi / masses[i3]));
+        // Dihedrals don't change under uniform scaling, so these don't contribute to the virial.
+        atomicAdd(&terms[TERM_BONDED], barrier_height[d] * (1.0f + cosf(arg)));
+    }
+}
+
+// The cell index along a CMAP axis, and the position in it, from 0 to 1. Matches `cell_coord`.
+__device__ inline unsigned int cmap_cell(float angle, float spacing, unsigned int n, float *t) {
+    float x = fmodf(angle + 0.5f * TAU, TAU);
+    if (x < 0.0f) x += TAU;
+    x /= spacing;
+
+    unsigned int i = min((unsigned int)floorf(x), n - 1);
+    *t = x - (float)i;
+    return i;
+}
+
+// One thread per CMAP term, over φ = atoms 0-3, and ψ = atoms 1-4. Matches `CmapGrid::energy`:
+// each grid cell has 16 bicubic coefficients, `c[a][b]` at `4a + b`, over cell coordinates in
+// [0, 1). Grid `g` has `grid_res[g]` points per axis, and its cells start at cell
+// `grid_offsets[g]` of `coeffs`.
+extern "C" __global__
+void md_cmap_kernel(
+    float3 *accels,
+    float *terms,
+    const float3 *posits,
+    const float *masses,
+    const unsigned int *cmap_atoms, // 5 per term
+    const unsigned int *cmap_grids, // 1 per term
+    const unsigned int *grid_offsets,
+    const unsigned int *grid_res,
+    const float *coeffs,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    size_t N_terms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t m = index; m < N_terms; m += stride) {
+        const unsigned int *atoms = &cmap_atoms[5 * m];
+
+        float3 b[4];
+        for (int k = 0; k < 4; k++) {
+            b[k] = min_image(posits[atoms[k]] - posits[atoms[k + 1]], ext);
+        }
+
+        float phi, psi;
+        float3 dphi[4], dpsi[4];
+        // h = r3 - r2 is the reverse of the bond vector from 3 to 2.
+        if (!dihedral_grad(b[0], b[1], b[2] * -1.0f, &phi, dphi)) continue;
+        if (!dihedral_grad(b[1], b[2], b[3] * -1.0f, &psi, dpsi)) continue;
+
+        unsigned int g = cmap_grids[m];
+        unsigned int n = grid_res[g];
+        float spacing = TAU / (float)n;
+
+        float t, u;
+        unsigned int i = cmap_cell(phi, spacing, n, &t);
+        unsigned int j = cmap_cell(psi, spacing, n, &u);
+        const float *c = &coeffs[16 * (grid_offsets[g] + i * n + j)];
+
+        float e = 0.0f, de_dt = 0.0f, de_du = 0.0f;
+        for (int a = 3; a >= 0; a--) {
+            float row = 0.0f, row_du = 0.0f;
+            for (int k = 3; k >= 0; k--) {
+                row = row * u + c[4 * a + k];
+                if (k > 0) row_du = row_du * u + (float)k * c[4 * a + k];
+            }
+            e = e * t + row;
+            de_du = de_du * t + row_du;
+            if (a > 0) de_dt = de_dt * t + (float)a * row;
+        }
+        float de_dphi = de_dt / spacing;
+        float de_dpsi = de_du / spacing;
+
+        float3 f[5];
+        for (int k = 0; k < 5; k++) f[k] = make_float3(0.0f, 0.0f, 0.0f);
+        for (int k = 0; k < 4; k++) {
+            f[k] = f[k] - dphi[k] * de_dphi;
+            f[k + 1] = f[k + 1] - dpsi[k] * de_dpsi;
+        }
+
+        for (int k = 0; k < 5; k++) {
+            atomicAdd3(&accels[atoms[k]], f[k] / masses[atoms[k]]);
+        }
+        // As for dihedrals, no virial.
+        atomicAdd(&terms[TERM_BONDED], e);
+    }
+}
+
+// Coulomb and Lennard-Jones force on atom 0 from atom 1. `dv` points from atom 0 to atom 1. Adds
+// the pair's energies to `e_lj` and `e_coul`.
+__device__ inline float3 nonbonded_pair(
+    float3 dv,
+    float dist,
+    float q0,
+    float q1,
+    float sigma,
+    float eps,
+    float scale_lj,
+    float scale_coul,
+    float *e_lj,
+    float *e_coul
+) {
+    float3 dir = dv / dist;
+
+    float sr = sigma / dist;
+    float sr6 = sr * sr * sr * sr * sr * sr;
+    float sr12 = sr6 * sr6;
+
+    float3 f_lj = dir * (-24.0f * eps * (2.0f * sr12 - sr6) / dist * scale_lj);
+    float3 f_coul = dir * (-COULOMB_CONST * q0 * q1 / (dist * dist + SOFTENING_FACTOR_SQ) * scale_coul);
+
+    *e_lj += 4.0f * eps * (sr12 - sr6) * scale_lj;
+    *e_coul += COULOMB_CONST * q0 * q1 / sqrtf(dist * dist + SOFTENING_FACTOR_SQ) * scale_coul;
+
+    return f_lj + f_coul;
+}
+
+// One thread per mobile atom, over its neighbor list, in CSR form. Each pair is in both atoms'
+// lists; each thread only writes to its own atom, so there are no races, and we halve the energies
+// and virial.
+// Flags: 0 for normal pairs, 1 for excluded (1-2, 1-3) pairs, and 2 for scaled 1-4 pairs.
+extern "C" __global__
+void md_nonbonded_kernel(
+    float3 *accels,
+    float *terms,
+    const float3 *posits,
+    const float *masses,
+    const float *charges,
+    const float *sigmas,
+    const float *epss,
+    const unsigned int *nb_offsets, // N_atoms + 1
+    const unsigned int *nb_indices,
+    const unsigned char *nb_flags,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    float cutoff_sq,
+    float scale_lj_14,
+    float scale_coul_14,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        float3 f = make_float3(0.0f, 0.0f, 0.0f);
+        float e_lj = 0.0f, e_coul = 0.0f, virial = 0.0f;
+
+        for (unsigned int n = nb_offsets[i]; n < nb_offsets[i + 1]; n++) {
+            unsigned char flag = nb_flags[n];
+            if (flag == 1) continue;
+
+            unsigned int j = nb_indices[n];
+            float3 dv = min_image(posits[j] - posits[i], ext);
+            float r_sq = dot3(dv, dv);
+            if (r_sq > cutoff_sq) continue;
+
+            float sigma = 0.5f * (sigmas[i] + sigmas[j]);
+            float eps = sqrtf(epss[i] * epss[j]);
+
+            float3 f_pair = nonbonded_pair(
+                dv, sqrtf(r_sq), charges[i], charges[j], sigma, eps,
+                flag == 2 ? scale_lj_14 : 1.0f,
+                flag == 2 ? scale_coul_14 : 1.0f,
+                &e_lj, &e_coul
+            );
+            f = f + f_pair;
+            virial -= dot3(dv, f_pair);
+        }
+
+        accels[i] = accels[i] + f / masses[i];
+        atomicAdd(&terms[TERM_LJ], 0.5f * e_lj);
+        atomicAdd(&terms[TERM_COULOMB], 0.5f * e_coul);
+        atomicAdd(&terms[TERM_VIRIAL], 0.5f * virial);
+    }
+}
+
+// One thread per mobile atom, over the static atoms near it, in CSR form.
+extern "C" __global__
+void md_nonbonded_static_kernel(
+    float3 *accels,
+    float *terms,
+    const float3 *posits,
+    const float *masses,
+    const float *charges,
+    const float *sigmas,
+    const float *epss,
+    const float3 *posits_static,
+    const float *charges_static,
+    const float *sigmas_static,
+    const float *epss_static,
+    const unsigned int *nb_offsets, // N_atoms + 1
+    const unsigned int *nb_indices,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    float cutoff_sq,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        float3 f = make_float3(0.0f, 0.0f, 0.0f);
+        float e_lj = 0.0f, e_coul = 0.0f, virial = 0.0f;
+
+        for (unsigned int n = nb_offsets[i]; n < nb_offsets[i + 1]; n++) {
+            unsigned int j = nb_indices[n];
+            float3 dv = min_image(posits_static[j] - posits[i], ext);
+            float r_sq = dot3(dv, dv);
+            if (r_sq > cutoff_sq) continue;
+
+            float sigma = 0.5f * (sigmas[i] + sigmas_static[j]);
+            float eps = sqrtf(epss[i] * epss_static[j]);
+
+            float3 f_pair = nonbonded_pair(
+                dv, sqrtf(r_sq), charges[i], charges_static[j], sigma, eps, 1.0f, 1.0f,
+                &e_lj, &e_coul
+            );
+            f = f + f_pair;
+            virial -= dot3(dv, f_pair);
+        }
+
+        accels[i] = accels[i] + f / masses[i];
+        atomicAdd(&terms[TERM_LJ], e_lj);
+        atomicAdd(&terms[TERM_COULOMB], e_coul);
+        atomicAdd(&terms[TERM_VIRIAL], virial);
+    }
+}
@@ -0,0 +1,226 @@
+// Kernels for building neighbor (Verlet) lists on the GPU, with a cell grid. These mirror
+// `MdState::build_neighbours`. We bin atoms into cells with sides at least the list radius, then
+// search each atom's cell and its neighbors. Lists are in CSR form: we count neighbors per atom,
+// scan the counts into offsets, then fill.
+//
+// Requires `min_image` and `wrap_axis` from `dynamics.cu`.
+
+// Threads for the single-block scan.
+#define NB_SCAN_THREADS 1024
+
+// Flags for mobile pairs; these match `md_nonbonded_kernel`.
+#define NB_PAIR_NORMAL 0
+#define NB_PAIR_EXCLUDED 1
+#define NB_PAIR_SCALED_14 2
+
+__device__ inline unsigned int cell_axis(float v, float lo, float ext, unsigned int n) {
+    float rel = wrap_axis(v, lo, ext) - lo;
+    unsigned int result = (unsigned int)(rel / ext * (float)n);
+    return result < n ? result : n - 1;
+}
+
+__device__ inline uint3 cell_of(float3 p, float3 lo, float3 ext, uint3 dims) {
+    return make_uint3(
+        cell_axis(p.x, lo.x, ext.x, dims.x),
+        cell_axis(p.y, lo.y, ext.y, dims.y),
+        cell_axis(p.z, lo.z, ext.z, dims.z)
+    );
+}
+
+__device__ inline unsigned int cell_flat(unsigned int x, unsigned int y, unsigned int z, uint3 dims) {
+    return (x * dims.y + y) * dims.z + z;
+}
+
+// The `d`th distinct cell along an axis adjacent to `i`, wrapping. There are min(n, 3) of these.
+__device__ inline unsigned int cell_adjacent(unsigned int i, unsigned int d, unsigned int n) {
+    return (i + n - 1 + (n == 1 ? 1 : d)) % n;
+}
+
+__device__ inline bool in_list(const unsigned int *offsets, const unsigned int *indices, unsigned int i, unsigned int j) {
+    for (unsigned int n = offsets[i]; n < offsets[i + 1]; n++) {
+        if (indices[n] == j) return true;
+    }
+    return false;
+}
+
+// Bin atoms into cells, and count atoms per cell. Zero `cell_counts` first.
+extern "C" __global__
+void nb_cell_ids_kernel(
+    unsigned int *cell_ids,
+    unsigned int *cell_counts,
+    const float3 *posits,
+    float lo_x,
+    float lo_y,
+    float lo_z,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    unsigned int dims_x,
+    unsigned int dims_y,
+    unsigned int dims_z,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    float3 lo = make_float3(lo_x, lo_y, lo_z);
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+    uint3 dims = make_uint3(dims_x, dims_y, dims_z);
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        uint3 c = cell_of(posits[i], lo, ext, dims);
+        unsigned int flat = cell_flat(c.x, c.y, c.z, dims);
+
+        cell_ids[i] = flat;
+        atomicAdd(&cell_counts[flat], 1u);
+    }
+}
+
+// In-place exclusive scan of `data[0..N]`, writing the total to `data[N]`. Launch as a single block
+// of `NB_SCAN_THREADS` threads; each scans a contiguous chunk, then we scan the chunk sums.
+extern "C" __global__
+void nb_scan_kernel(
+    unsigned int *data,
+    size_t N
+) {
+    __shared__ unsigned int sums[NB_SCAN_THREADS];
+
+    size_t t = threadIdx.x;
+    size_t chunk = (N + NB_SCAN_THREADS - 1) / NB_SCAN_THREADS;
+    size_t start = min(t * chunk, N);
+    size_t end = min(start + chunk, N);
+
+    unsigned int sum = 0;
+    for (size_t i = start; i < end; i++) {
+        sum += data[i];
+    }
+    sums[t] = sum;
+    __syncthreads();
+
+    // Hillis-Steele inclusive scan of the chunk sums.
+    for (unsigned int offset = 1; offset < NB_SCAN_THREADS; offset *= 2) {
+        unsigned int v = t >= offset ? sums[t - offset] : 0;
+        __syncthreads();
+        sums[t] += v;
+        __syncthreads();
+    }
+
+    unsigned int running = t == 0 ? 0 : sums[t - 1];
+    for (size_t i = start; i < end; i++) {
+        unsigned int v = data[i];
+        data[i] = running;
+        running += v;
+    }
+
+    if (t == NB_SCAN_THREADS - 1) {
+        data[N] = sums[t];
+    }
+}
+
+// Scatter atom indices into their cells. `cell_offsets` is the scanned counts; zero `cell_fill` first.
+extern "C" __global__
+void nb_cell_fill_kernel(
+    unsigned int *cell_atoms,
+    unsigned int *cell_fill,
+    const unsigned int *cell_ids,
+    const unsigned int *cell_offsets,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        unsigned int c = cell_ids[i];
+        unsigned int slot = cell_offsets[c] + atomicAdd(&cell_fill[c], 1u);
+        cell_atoms[slot] = (unsigned int)i;
+    }
+}
+
+// One thread per mobile atom. Finds atoms of the gridded set (mobile if `same_set`, or static)
+// within `radius_sq`. With `fill` of 0, writes counts to `nb_offsets[i]`, to be scanned. Otherwise,
+// writes indices from `nb_offsets[i]`, and for mobile pairs, flags from the exclusion and 1-4 lists.
+extern "C" __global__
+void nb_list_kernel(
+    unsigned int *nb_offsets, // N_atoms + 1
+    unsigned int *nb_indices,
+    unsigned char *nb_flags,
+    const float3 *posits,
+    const float3 *posits_grid,
+    const unsigned int *cell_offsets,
+    const unsigned int *cell_atoms,
+    const unsigned int *excl_offsets,
+    const unsigned int *excl_indices,
+    const unsigned int *s14_offsets,
+    const unsigned int *s14_indices,
+    float lo_x,
+    float lo_y,
+    float lo_z,
+    float ext_x,
+    float ext_y,
+    float ext_z,
+    unsigned int dims_x,
+    unsigned int dims_y,
+    unsigned int dims_z,
+    float radius_sq,
+    unsigned int same_set,
+    unsigned int fill,
+    size_t N_atoms
+) {
+    size_t index = blockIdx.x * blockDim.x + threadIdx.x;
+    size_t stride = blockDim.x * gridDim.x;
+
+    float3 lo = make_float3(lo_x, lo_y, lo_z);
+    float3 ext = make_float3(ext_x, ext_y, ext_z);
+    uint3 dims = make_uint3(dims_x, dims_y, dims_z);
+
+    unsigned int n_x = min(dims.x, 3u);
+    unsigned int n_y = min(dims.y, 3u);
+    unsigned int n_z = min(dims.z, 3u);
+
+    for (size_t i = index; i < N_atoms; i += stride) {
+        float3 p = posits[i];
+        uint3 c = cell_of(p, lo, ext, dims);
+
+        unsigned int count = 0;
+        unsigned int out = fill ? nb_offsets[i] : 0;
+
+        for (unsigned int dx = 0; dx < n_x; dx++) {
+            for (unsigned int dy = 0; dy < n_y; dy++) {
+                for (unsigned int dz = 0; dz < n_z; dz++) {
+                    unsigned int cell = cell_flat(
+                        cell_adjacent(c.x, dx, dims.x),
+                        cell_adjacent(c.y, dy, dims.y),
+                        cell_adjacent(c.z, dz, dims.z),
+                        dims
+                    );
+
+                    for (unsigned int n = cell_offsets[cell]; n < cell_offsets[cell + 1]; n++) {
+                        unsigned int j = cell_atoms[n];
+                        if (same_set && j == i) continue;
+
+                        float3 dv = min_image(posits_grid[j] - p, ext);
+                        if (dot3(dv, dv) >= radius_sq) continue;
+
+                        if (fill) {
+                            nb_indices[out + count] = j;
+                            if (same_set) {
+                                unsigned char flag = NB_PAIR_NORMAL;
+                                if (in_list(excl_offsets, excl_indices, i, j)) {
+                                    flag = NB_PAIR_EXCLUDED;
+                                } else if (in_list(s14_offsets, s14_indices, i, j)) {
+                                    flag = NB_PAIR_SCALED_14;
+                                }
+                                nb_flags[out + count] = flag;
+                            }
+                        }
+                        count++;
+                    }
+                }
+            }
+        }
+
+        if (!fill) {
+            nb_offsets[i] = count;
+        }
+    }
+}
@@ -77,7 +77,15 @@ float3 lj_force(
     float sr6 = powf(sr, 6.);
     float sr12 = sr6 * sr6;
 
-    float mag = -24.0f * eps * (2. * sr12 - sr6) / (r * r);
+    float mag = -24.0f * eps * (2. * sr12 - sr6) / r;
 
     return dir * mag;
-}
\ No newline at end of file
+}
+// Atomic min for floats, of either sign. Positive floats order the same as their bits as ints;
+// negative ones, in reverse as unsigned ints.
+__device__ inline float atomicMinFloat(float *addr, float value) {
+    if (value >= 0.0f) {
+        return __int_as_float(atomicMin((int *)addr, __float_as_int(value)));
+    }
+    return __uint_as_float(atomicMax((unsigned int *)addr, __float_as_uint(value)));
+}
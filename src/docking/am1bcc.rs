@@ -0,0 +1,180 @@
+//! AM1-BCC partial charges for ligands, from AmberTools' `antechamber`, which runs the `sqm`
+//! semi-empirical program. These are the charges GAFF parameters are designed for, so are preferable
+//! to EEM or Gasteiger charges for MD. Optional: AmberTools must be installed, with `antechamber`
+//! on the path.
+//!
+//! A run takes seconds to minutes, so we run it in a thread, and cache results on disk, keyed by a
+//! hash of the molecule.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use bio_files::Mol2;
+use log::{info, warn};
+
+use crate::molecule::{BondType, Molecule};
+
+/// In the prefs directory.
+pub const CHARGE_CACHE_DIR: &str = "charge_cache";
+
+const ANTECHAMBER: &str = "antechamber";
+
+/// The result of a run: Charges by atom index, or an error.
+pub type Am1BccPending = Receiver<io::Result<Vec<f32>>>;
+
+pub fn antechamber_avail() -> bool {
+    Command::new(ANTECHAMBER)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .args(["-h"])
+        .status()
+        .is_ok()
+}
+
+/// Identifies a molecule for caching: Elements, bonds, coordinates to 0.01Å, and net charge.
+/// Coordinates are included since sqm's result depends slightly on the starting geometry.
+pub fn mol_hash(mol: &Molecule, net_charge: i32) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    net_charge.hash(&mut hasher);
+    for atom in &mol.atoms {
+        atom.element.to_letter().hash(&mut hasher);
+        for v in [atom.posit.x, atom.posit.y, atom.posit.z] {
+            ((v * 100.).round() as i64).hash(&mut hasher);
+        }
+    }
+    for bond in &mol.bonds {
+        (bond.atom_0, bond.atom_1).hash(&mut hasher);
+        if let BondType::Covalent { count } = bond.bond_type {
+            count.to_str().hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+fn cache_path(cache_dir: &Path, hash: u64) -> PathBuf {
+    cache_dir.join(format!("{hash:016x}.txt"))
+}
+
+/// One charge per line, in atom order.
+fn load_cached(path: &Path, atom_count: usize) -> Option<Vec<f32>> {
+    let text = fs::read_to_string(path).ok()?;
+    let charges: Vec<f32> = text
+        .lines()
+        .map(|l| l.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    (charges.len() == atom_count).then_some(charges)
+}
+
+fn save_cached(path: &Path, charges: &[f32]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text: Vec<_> = charges.iter().map(|q| format!("{q:.6}")).collect();
+    fs::write(path, text.join("\n"))
+}
+
+/// Run antechamber on the molecule, and read back its charges. Blocking.
+fn run_antechamber(mol: &Mol2, net_charge: i32, work_dir: &Path) -> io::Result<Vec<f32>> {
+    fs::create_dir_all(work_dir)?;
+
+    let input = work_dir.join("input.mol2");
+    let output = work_dir.join("output.mol2");
+    mol.save(&input)?;
+
+    let result = Command::new(ANTECHAMBER)
+        .current_dir(work_dir)
+        .args([
+            "-i",
+            "input.mol2",
+            "-fi",
+            "mol2",
+            "-o",
+            "output.mol2",
+            "-fo",
+            "mol2",
+            "-c",
+            "bcc",
+            "-nc",
+            &net_charge.to_string(),
+            "-at",
+            "gaff2",
+            // Remove intermediate files.
+            "-pf",
+            "y",
+        ])
+        .output()?;
+
+    if !result.status.success() || !output.exists() {
+        let log = String::from_utf8_lossy(&result.stdout);
+        let tail: Vec<_> = log.lines().rev().take(5).collect();
+        return Err(io::Error::other(format!(
+            "antechamber failed: {}",
+            tail.into_iter().rev().collect::<Vec<_>>().join(" ")
+        )));
+    }
+
+    // Atom order is preserved.
+    let charges: Vec<f32> = Mol2::load(&output)?
+        .atoms
+        .iter()
+        .map(|a| a.partial_charge.unwrap_or_default())
+        .collect();
+
+    let _ = fs::remove_dir_all(work_dir);
+
+    if charges.len() != mol.atoms.len() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "antechamber returned a different number of atoms",
+        ));
+    }
+
+    Ok(charges)
+}
+
+/// Compute AM1-BCC charges in a new thread, or load them from the cache. Poll the returned
+/// receiver from the UI; it's non-blocking.
+pub fn start_am1bcc(mol: &Molecule, net_charge: i32, cache_dir: &Path) -> Am1BccPending {
+    let hash = mol_hash(mol, net_charge);
+    let cache = cache_path(cache_dir, hash);
+    // Only the Mol2 form is needed in the thread.
+    let mol = mol.to_mol2();
+
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let result = match load_cached(&cache, mol.atoms.len()) {
+            Some(charges) => {
+                info!("Loaded AM1-BCC charges from the cache");
+                Ok(charges)
+            }
+            None => {
+                let work_dir = std::env::temp_dir().join(format!("daedalus_am1bcc_{hash:016x}"));
+                let result = run_antechamber(&mol, net_charge, &work_dir);
+                if let Ok(charges) = &result {
+                    if let Err(e) = save_cached(&cache, charges) {
+                        warn!("Unable to cache AM1-BCC charges: {e}");
+                    }
+                }
+                result
+            }
+        };
+
+        let _ = tx.send(result);
+    });
+
+    rx
+}
@@ -0,0 +1,403 @@
+//! Geometry cleanup for imported ligands. SDF and Mol2 files, e.g. from 2D-to-3D conversion, often
+//! have distorted rings, and bad bond lengths. We restrain bond lengths and angles to ideal values,
+//! keeping torsions, and minimize. Ideal values are from the ligand force field's (e.g. GAFF2) bond
+//! and angle parameters, where atoms have FF types, and parameters are loaded. Otherwise, we estimate
+//! them from covalent radii, bond orders, and hybridization.
+//!
+//! All terms are harmonic distance restraints: Angles are restrained by their 1-3 distance, and
+//! torsions by their 1-4 distance, given ideal bonds and angles, and the original dihedral angle.
+//! Dihedrals about double and aromatic bonds are snapped to 0 or 180°.
+
+use std::{
+    collections::HashMap,
+    f64::consts::{PI, TAU},
+};
+
+use bio_files::amber_params::ForceFieldParamsKeyed;
+use lin_alg::f64::Vec3;
+use na_seq::{Element, Element::*};
+
+use crate::molecule::{BondCount, BondType, Molecule};
+
+/// kcal/mol/Å². Restraint strengths for bonds, angles (as 1-3 distances), and torsions (as 1-4
+/// distances).
+const K_BOND: f64 = 300.;
+const K_ANGLE: f64 = 60.;
+const K_TORSION: f64 = 10.;
+
+const MAX_STEPS: usize = 2_000;
+/// kcal/mol/Å. Converged once no atom's gradient exceeds this.
+const GRAD_TOL: f64 = 0.01;
+/// Å. The most an atom moves in a step.
+const STEP_MAX: f64 = 0.1;
+
+/// Radians. Tetrahedral angle.
+const ANGLE_SP3: f64 = 1.9106332;
+
+/// Å. Single bond covalent radii, from Cordero et al, 2008.
+fn covalent_radius(el: Element) -> f64 {
+    match el {
+        Hydrogen => 0.31,
+        Carbon => 0.76,
+        Nitrogen => 0.71,
+        Oxygen => 0.66,
+        Fluorine => 0.57,
+        Phosphorus => 1.07,
+        Sulfur => 1.05,
+        Chlorine => 1.02,
+        Bromine => 1.20,
+        Iodine => 1.39,
+        _ => 0.75,
+    }
+}
+
+/// Å. Shortening relative to the single bond length; e.g. 1.54 Å for C-C, 1.39 for aromatic C-C,
+/// 1.34 for C=C, and 1.20 for C≡C.
+fn bond_order_shortening(count: BondCount) -> f64 {
+    match count {
+        BondCount::Single => 0.,
+        BondCount::SingleDoubleHybrid => 0.13,
+        BondCount::Double => 0.18,
+        BondCount::Triple => 0.32,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CleanupReport {
+    /// Å. RMS deviation of bond lengths from their ideal values.
+    pub bond_rmsd_before: f32,
+    pub bond_rmsd_after: f32,
+    /// Radians. RMS deviation of bond angles from their ideal values.
+    pub angle_rmsd_before: f32,
+    pub angle_rmsd_after: f32,
+    /// Å. Between the original and cleaned-up coordinates.
+    pub posit_rmsd: f32,
+}
+
+impl CleanupReport {
+    pub fn descrip(&self) -> String {
+        format!(
+            "Bond length RMSD: {:.3} → {:.3} Å\nBond angle RMSD: {:.1} → {:.1}°\nAtoms moved: {:.2} Å RMSD",
+            self.bond_rmsd_before,
+            self.bond_rmsd_after,
+            self.angle_rmsd_before.to_degrees(),
+            self.angle_rmsd_after.to_degrees(),
+            self.posit_rmsd
+        )
+    }
+}
+
+struct Restraint {
+    atoms: (usize, usize),
+    dist: f64,
+    k: f64,
+}
+
+/// An ideal angle, at the center atom.
+struct Angle {
+    atoms: (usize, usize, usize),
+    angle: f64,
+}
+
+fn bond_angle(posits: &[Vec3], (i, j, k): (usize, usize, usize)) -> f64 {
+    let a = (posits[i] - posits[j]).to_normalized();
+    let b = (posits[k] - posits[j]).to_normalized();
+    a.dot(b).clamp(-1., 1.).acos()
+}
+
+/// Cosine of the dihedral angle; 1 for cis, and -1 for trans.
+fn dihedral_cos(posits: &[Vec3], (i, j, k, l): (usize, usize, usize, usize)) -> f64 {
+    let b1 = posits[j] - posits[i];
+    let b2 = posits[k] - posits[j];
+    let b3 = posits[l] - posits[k];
+
+    let n1 = b1.cross(b2);
+    let n2 = b2.cross(b3);
+    let denom = n1.magnitude() * n2.magnitude();
+    if denom < 1e-9 {
+        return 1.;
+    }
+    (n1.dot(n2) / denom).clamp(-1., 1.)
+}
+
+/// The 1-3 distance for two bonds with the angle between them.
+fn dist_13(r_0: f64, r_1: f64, angle: f64) -> f64 {
+    (r_0.powi(2) + r_1.powi(2) - 2. * r_0 * r_1 * angle.cos()).sqrt()
+}
+
+/// The 1-4 distance for three bonds, the two angles between them, and the dihedral angle's cosine.
+fn dist_14(r: (f64, f64, f64), angles: (f64, f64), dihedral_cos: f64) -> f64 {
+    // Central bond along x; the first atom in the xy plane.
+    let dx = r.1 - r.2 * angles.1.cos() - r.0 * angles.0.cos();
+    let (s0, s1) = (r.0 * angles.0.sin(), r.2 * angles.1.sin());
+
+    (dx.powi(2) + s0.powi(2) + s1.powi(2) - 2. * s0 * s1 * dihedral_cos).sqrt()
+}
+
+fn rms(vals: impl Iterator<Item = f64>) -> f32 {
+    let (sum, n) = vals.fold((0., 0), |(s, n), v| (s + v.powi(2), n + 1));
+    if n == 0 {
+        0.
+    } else {
+        (sum / n as f64).sqrt() as f32
+    }
+}
+
+/// Ideal bond lengths and angles for the molecule.
+fn ideal_geometry(
+    mol: &Molecule,
+    adj: &[Vec<usize>],
+    params_general: Option<&ForceFieldParamsKeyed>,
+    params_specific: Option<&ForceFieldParamsKeyed>,
+) -> (HashMap<(usize, usize), f64>, Vec<Angle>) {
+    let param_sets: Vec<_> = [params_specific, params_general]
+        .into_iter()
+        .flatten()
+        .collect();
+    let ff_type = |i: usize| mol.atoms[i].force_field_type.clone();
+
+    let mut bond_order: HashMap<(usize, usize), BondCount> = HashMap::new();
+    let mut bonds = HashMap::new();
+
+    for bond in &mol.bonds {
+        let (i, j) = (bond.atom_0, bond.atom_1);
+        let count = match bond.bond_type {
+            BondType::Covalent { count } => count,
+            _ => continue,
+        };
+        let key = (i.min(j), i.max(j));
+        bond_order.insert(key, count);
+
+        let ff = match (ff_type(i), ff_type(j)) {
+            (Some(t_i), Some(t_j)) => param_sets.iter().find_map(|p| {
+                p.bond
+                    .get(&(t_i.clone(), t_j.clone()))
+                    .or_else(|| p.bond.get(&(t_j.clone(), t_i.clone())))
+                    .map(|b| b.r_0 as f64)
+            }),
+            _ => None,
+        };
+
+        let len = ff.unwrap_or_else(|| {
+            covalent_radius(mol.atoms[i].element) + covalent_radius(mol.atoms[j].element)
+                - bond_order_shortening(count)
+        });
+        bonds.insert(key, len);
+    }
+
+    let mut angles = Vec::new();
+
+    for (j, neighbors) in adj.iter().enumerate() {
+        let orders: Vec<_> = neighbors
+            .iter()
+            .filter_map(|n| bond_order.get(&(j.min(*n), j.max(*n))))
+            .collect();
+        let doubles = orders.iter().filter(|c| ***c == BondCount::Double).count();
+
+        let hybrid_angle = if orders.contains(&&BondCount::Triple) || doubles >= 2 {
+            PI
+        } else if mol.atoms[j].aromatic || orders.iter().any(|c| **c != BondCount::Single) {
+            TAU / 3.
+        } else {
+            ANGLE_SP3
+        };
+
+        for (a, &i) in neighbors.iter().enumerate() {
+            for &k in &neighbors[a + 1..] {
+                let ff = match (ff_type(i), ff_type(j), ff_type(k)) {
+                    (Some(t_i), Some(t_j), Some(t_k)) => param_sets.iter().find_map(|p| {
+                        p.angle
+                            .get(&(t_i.clone(), t_j.clone(), t_k.clone()))
+                            .or_else(|| p.angle.get(&(t_k.clone(), t_j.clone(), t_i.clone())))
+                            .map(|a| a.theta_0 as f64)
+                    }),
+                    _ => None,
+                };
+
+                // Small rings are strained; use the angle of a regular polygon.
+                let ring_angle = mol
+                    .rings
+                    .iter()
+                    .filter(|r| r.len() <= 5)
+                    .find(|r| [i, j, k].iter().all(|a| r.contains(a)))
+                    .map(|r| PI * (r.len() as f64 - 2.) / r.len() as f64);
+
+                angles.push(Angle {
+                    atoms: (i, j, k),
+                    angle: ff.or(ring_angle).unwrap_or(hybrid_angle),
+                });
+            }
+        }
+    }
+
+    (bonds, angles)
+}
+
+/// Restrained minimization of bond lengths and angles, keeping torsions. Modifies atom positions in
+/// place. Ligand-specific FF parameters take precedence over general ones.
+pub fn clean_up_geometry(
+    mol: &mut Molecule,
+    params_general: Option<&ForceFieldParamsKeyed>,
+    params_specific: Option<&ForceFieldParamsKeyed>,
+) -> Option<CleanupReport> {
+    if mol.bonds.is_empty() {
+        return None;
+    }
+
+    let adj = mol.build_adjacency_list();
+    let (bonds, angles) = ideal_geometry(mol, &adj, params_general, params_specific);
+    let posits_orig: Vec<Vec3> = mol.atoms.iter().map(|a| a.posit).collect();
+
+    let bond_len = |i: usize, j: usize| bonds.get(&(i.min(j), i.max(j))).copied();
+    let angle_ideal: HashMap<(usize, usize, usize), f64> = angles
+        .iter()
+        .flat_map(|a| {
+            let (i, j, k) = a.atoms;
+            [((i, j, k), a.angle), ((k, j, i), a.angle)]
+        })
+        .collect();
+
+    let mut restraints = Vec::new();
+
+    for (&(i, j), &len) in &bonds {
+        restraints.push(Restraint {
+            atoms: (i, j),
+            dist: len,
+            k: K_BOND,
+        });
+    }
+
+    for angle in &angles {
+        let (i, j, k) = angle.atoms;
+        let (Some(r_0), Some(r_1)) = (bond_len(i, j), bond_len(j, k)) else {
+            continue;
+        };
+        restraints.push(Restraint {
+            atoms: (i, k),
+            dist: dist_13(r_0, r_1, angle.angle),
+            k: K_ANGLE,
+        });
+    }
+
+    // Torsions, about each bond j-k.
+    for &(j, k) in bonds.keys() {
+        let planar = mol.bonds.iter().any(|b| {
+            (b.atom_0.min(b.atom_1), b.atom_0.max(b.atom_1)) == (j, k)
+                && (b.aromatic
+                    || matches!(
+                        b.bond_type,
+                        BondType::Covalent {
+                            count: BondCount::Double | BondCount::SingleDoubleHybrid
+                        }
+                    ))
+        });
+
+        for &i in adj[j].iter().filter(|i| **i != k) {
+            for &l in adj[k].iter().filter(|l| **l != j && **l != i) {
+                let (Some(r_ij), Some(r_jk), Some(r_kl)) =
+                    (bond_len(i, j), bond_len(j, k), bond_len(k, l))
+                else {
+                    continue;
+                };
+                let (Some(&a_ijk), Some(&a_jkl)) =
+                    (angle_ideal.get(&(i, j, k)), angle_ideal.get(&(j, k, l)))
+                else {
+                    continue;
+                };
+
+                let mut cos = dihedral_cos(&posits_orig, (i, j, k, l));
+                if planar {
+                    cos = cos.signum();
+                }
+
+                restraints.push(Restraint {
+                    atoms: (i, l),
+                    dist: dist_14((r_ij, r_jk, r_kl), (a_ijk, a_jkl), cos),
+                    k: K_TORSION,
+                });
+            }
+        }
+    }
+
+    let energy_grad = |posits: &[Vec3], grad: &mut Vec<Vec3>| {
+        grad.clear();
+        grad.resize(posits.len(), Vec3::new_zero());
+
+        let mut energy = 0.;
+        for r in &restraints {
+            let diff = posits[r.atoms.0] - posits[r.atoms.1];
+            let dist = diff.magnitude().max(1e-6);
+            let dev = dist - r.dist;
+
+            energy += r.k * dev.powi(2);
+            let g = diff * (2. * r.k * dev / dist);
+            grad[r.atoms.0] += g;
+            grad[r.atoms.1] -= g;
+        }
+        energy
+    };
+
+    // Gradient descent, with an adaptive step.
+    let mut posits = posits_orig.clone();
+    let mut grad = Vec::new();
+    let mut grad_trial = Vec::new();
+    let mut energy = energy_grad(&posits, &mut grad);
+    let mut step = 1e-3;
+
+    for _ in 0..MAX_STEPS {
+        let grad_max = grad.iter().map(|g| g.magnitude()).fold(0., f64::max);
+        if grad_max < GRAD_TOL {
+            break;
+        }
+
+        let scale = step.min(STEP_MAX / grad_max);
+        let trial: Vec<Vec3> = posits
+            .iter()
+            .zip(&grad)
+            .map(|(p, g)| *p - *g * scale)
+            .collect();
+        let energy_trial = energy_grad(&trial, &mut grad_trial);
+
+        if energy_trial < energy {
+            posits = trial;
+            energy = energy_trial;
+            std::mem::swap(&mut grad, &mut grad_trial);
+            step *= 1.2;
+        } else {
+            step *= 0.5;
+            if step < 1e-12 {
+                break;
+            }
+        }
+    }
+
+    let bond_devs = |posits: &[Vec3]| {
+        bonds
+            .iter()
+            .map(|(&(i, j), len)| (posits[i] - posits[j]).magnitude() - len)
+            .collect::<Vec<_>>()
+    };
+    let angle_devs = |posits: &[Vec3]| {
+        angles
+            .iter()
+            .map(|a| bond_angle(posits, a.atoms) - a.angle)
+            .collect::<Vec<_>>()
+    };
+
+    let result = CleanupReport {
+        bond_rmsd_before: rms(bond_devs(&posits_orig).into_iter()),
+        bond_rmsd_after: rms(bond_devs(&posits).into_iter()),
+        angle_rmsd_before: rms(angle_devs(&posits_orig).into_iter()),
+        angle_rmsd_after: rms(angle_devs(&posits).into_iter()),
+        posit_rmsd: rms(posits
+            .iter()
+            .zip(&posits_orig)
+            .map(|(a, b)| (*a - *b).magnitude())),
+    };
+
+    for (atom, posit) in mol.atoms.iter_mut().zip(posits) {
+        atom.posit = posit;
+    }
+
+    Some(result)
+}
@@ -0,0 +1,216 @@
+//! Ligand conformer generation, by torsion driving. We sample random angles for the ligand's
+//! rotatable bonds, relax each sample greedily against intramolecular Lennard-Jones energy, and
+//! keep a diverse set of the lowest-energy results. Bond lengths, angles, and rings stay as loaded.
+//!
+//! Conformers seed the docking search, and the lowest-energy one can relax a ligand whose
+//! torsions are strained, e.g. from a crude 2D-to-3D build.
+
+use std::f32::consts::TAU;
+
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::element::LjTable;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rayon::prelude::*;
+
+use crate::{
+    docking::{ConformationType, prep::Torsion, search::rmsd},
+    forces::V_lj,
+    molecule::Ligand,
+};
+
+/// Atoms at most this many bonds apart don't interact.
+const BONDS_EXCLUDED: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct ConformerParams {
+    /// The most conformers to return.
+    pub num_conformers: usize,
+    /// Random starting points.
+    pub num_samples: usize,
+    /// Greedy torsion moves per sample.
+    pub refine_steps: usize,
+    /// Radians. The largest torsion change in a refinement step.
+    pub step_angle: f32,
+    /// Å. Conformers closer than this to a lower-energy one are dropped.
+    pub rmsd_distinct: f64,
+    /// If present, generation is reproducible.
+    pub seed: Option<u64>,
+}
+
+impl Default for ConformerParams {
+    fn default() -> Self {
+        Self {
+            num_conformers: 10,
+            num_samples: 200,
+            refine_steps: 200,
+            step_angle: 0.3,
+            rmsd_distinct: 0.5,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Conformer {
+    pub torsions: Vec<Torsion>,
+    /// kcal/mol. Intramolecular, between atoms in different rigid fragments.
+    pub energy: f32,
+    /// Atom positions, relative to the anchor, with identity orientation.
+    pub atom_posits: Vec<Vec3>,
+}
+
+/// Atom pairs whose distance torsions change, and which are far enough apart in the bond graph
+/// to interact, with their LJ parameters.
+fn interacting_pairs(lig: &Ligand, lj_lut: &LjTable) -> Vec<(usize, usize, f32, f32)> {
+    let atoms = &lig.molecule.atoms;
+    let adj = &lig.molecule.adjacency_list;
+
+    let mut fragment_of = vec![0; atoms.len()];
+    for (i, fragment) in lig.torsion_tree.fragments.iter().enumerate() {
+        for atom in fragment {
+            fragment_of[*atom] = i;
+        }
+    }
+
+    let mut result = Vec::new();
+    for i in 0..atoms.len() {
+        // Atoms within `BONDS_EXCLUDED` bonds of this one.
+        let mut near = vec![i];
+        let mut frontier = vec![i];
+        for _ in 0..BONDS_EXCLUDED {
+            let mut next = Vec::new();
+            for a in frontier {
+                for b in adj.get(a).into_iter().flatten() {
+                    if !near.contains(b) {
+                        near.push(*b);
+                        next.push(*b);
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        for j in i + 1..atoms.len() {
+            if fragment_of[i] == fragment_of[j] || near.contains(&j) {
+                continue;
+            }
+            if let Some((sigma, eps)) = lj_lut.get(&(atoms[i].element, atoms[j].element)) {
+                result.push((i, j, *sigma, *eps));
+            }
+        }
+    }
+
+    result
+}
+
+fn energy(lig: &Ligand, pairs: &[(usize, usize, f32, f32)]) -> f32 {
+    pairs
+        .iter()
+        .map(|(i, j, sigma, eps)| {
+            let r = (lig.atom_posits[*i] - lig.atom_posits[*j]).magnitude() as f32;
+            V_lj(r, *sigma, *eps)
+        })
+        .sum()
+}
+
+fn set_torsions(lig: &mut Ligand, torsions: &[Torsion]) {
+    lig.pose.conformation_type = ConformationType::Flexible {
+        torsions: torsions.to_vec(),
+    };
+    lig.position_atoms(None);
+}
+
+fn sample(
+    lig: &mut Ligand,
+    pairs: &[(usize, usize, f32, f32)],
+    params: &ConformerParams,
+    seed: u64,
+) -> Conformer {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut torsions: Vec<_> = lig
+        .flexible_bonds
+        .iter()
+        .map(|&bond| Torsion {
+            bond,
+            dihedral_angle: rng.random_range(0. ..TAU),
+        })
+        .collect();
+
+    set_torsions(lig, &torsions);
+    let mut e = energy(lig, pairs);
+
+    if !torsions.is_empty() {
+        for _ in 0..params.refine_steps {
+            let i = rng.random_range(0..torsions.len());
+            let prev = torsions[i].dihedral_angle;
+            let max = params.step_angle;
+            torsions[i].dihedral_angle = (prev + rng.random_range(-max..=max)).rem_euclid(TAU);
+
+            set_torsions(lig, &torsions);
+            let e_new = energy(lig, pairs);
+            if e_new < e {
+                e = e_new;
+            } else {
+                torsions[i].dihedral_angle = prev;
+            }
+        }
+        set_torsions(lig, &torsions);
+    }
+
+    Conformer {
+        torsions,
+        energy: e,
+        atom_posits: lig.atom_posits.clone(),
+    }
+}
+
+/// Generate up to `params.num_conformers` distinct conformers, lowest energy first. Doesn't change
+/// `ligand`.
+pub fn generate_conformers(
+    ligand: &Ligand,
+    lj_lut: &LjTable,
+    params: &ConformerParams,
+) -> Vec<Conformer> {
+    let pairs = interacting_pairs(ligand, lj_lut);
+    let seed_base = params.seed.unwrap_or_else(|| rand::rng().random());
+
+    // Without rotatable bonds, there's only one conformer.
+    let num_samples = if ligand.flexible_bonds.is_empty() {
+        1
+    } else {
+        params.num_samples
+    };
+
+    let mut samples: Vec<_> = (0..num_samples)
+        .into_par_iter()
+        .map(|i| {
+            let mut lig = ligand.clone();
+            lig.pose.anchor_posit = Vec3::new_zero();
+            lig.pose.orientation = Quaternion::new_identity();
+            sample(&mut lig, &pairs, params, seed_base.wrapping_add(i as u64))
+        })
+        .collect();
+
+    samples.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+
+    let mut result: Vec<Conformer> = Vec::new();
+    for conformer in samples {
+        if result.len() >= params.num_conformers {
+            break;
+        }
+        if result
+            .iter()
+            .all(|c| rmsd(&c.atom_posits, &conformer.atom_posits) >= params.rmsd_distinct)
+        {
+            result.push(conformer);
+        }
+    }
+
+    result
+}
+
+/// Set the ligand's torsions to a conformer's, keeping its position and orientation.
+pub fn apply_conformer(ligand: &mut Ligand, conformer: &Conformer) {
+    set_torsions(ligand, &conformer.torsions);
+}
@@ -4,7 +4,7 @@
 //! Experimental molecular dynamics, with a playback system. Starting with fixed-ligand position only,
 //! referencing the anchor.
 
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, sync::Arc};
 
 use bio_files::amber_params::{ChargeParams, ForceFieldParamsKeyed};
 
@@ -27,6 +27,7 @@ use lin_alg::{
     // f32::{Vec3x8, f32x8, pack_slice, pack_vec3},
     f64::{Vec3x4, f64x4, pack_slice, pack_vec3},
 };
+use log::info;
 use na_seq::AminoAcid;
 use rayon::prelude::*;
 
@@ -37,9 +38,15 @@ use crate::{
         BindingEnergy, ConformationType, Pose,
         prep::{DockingSetup, Torsion},
     },
-    dynamics::{AtomDynamics, AtomDynamicsx4, MdState, ParamError, SnapshotDynamics},
+    dynamics::{
+        AtomDynamics, AtomDynamicsx4, MdConfig, MdRun, MdState, ParamError, SnapshotDynamics,
+        flexible::FlexibleResidues, minimize::MinimizeResult, steered::SteeringState,
+    },
+    error::DaedalusError,
     forces::force_lj,
+    logging::Span,
     molecule::{Atom, Ligand, Residue},
+    progress::{Progress, Task},
 };
 // This seems to be how we control rotation vice movement. A higher value means
 // more movement, less rotation for a given dt.
@@ -159,16 +166,19 @@ fn calc_dt_dynamic(
 fn bodies_from_atoms_x8(atoms: &[Atom]) -> (Vec<AtomDynamicsx4>, usize) {
     let mut posits: Vec<Vec3> = Vec::with_capacity(atoms.len());
     let mut els = Vec::with_capacity(atoms.len());
+    let mut charges = Vec::with_capacity(atoms.len());
 
     for atom in atoms {
         posits.push(atom.posit.into());
         els.push(atom.element);
+        charges.push(atom.partial_charge.unwrap_or_default() as f64);
     }
 
     let (posits_x8, valid_lanes) = pack_vec3(&posits);
 
     // let (els_x8, _) = pack_slice::<_, 8>(&els);
     let (els_x4, _) = pack_slice::<_, 4>(&els);
+    let (charges_x4, _) = pack_slice::<_, 4>(&charges);
     let mut result = Vec::with_capacity(posits_x8.len());
 
     for (i, posit) in posits_x8.iter().enumerate() {
@@ -185,6 +195,10 @@ fn bodies_from_atoms_x8(atoms: &[Atom]) -> (Vec<AtomDynamicsx4>, usize) {
             vel: Vec3x4::new_zero(),
             accel: Vec3x4::new_zero(),
             mass,
+            partial_charge: f64x4::from_array(charges_x4[i]),
+            // These come from force field parameters, which we don't have here.
+            lj_sigma: f64x4::splat(0.),
+            lj_eps: f64x4::splat(0.),
             element: els_x4[i],
         })
     }
@@ -284,7 +298,8 @@ fn scalar_f_t(
         )
 }
 
-/// Keeps orientation fixed and body rigid, for now.
+/// Keeps orientation fixed and body rigid, for now. Runs `n_steps` steps of `dt` fs, counting
+/// them in `progress`.
 ///
 /// Observation: We can use analytic VDW force to position individual atoms, but once we treat
 /// the molecule together, we seem to get bogus results using this approach. Instead, we use a numerical
@@ -296,11 +311,13 @@ pub fn build_dock_dynamics(
     ff_params: &FfParamSet,
     residues: &[Residue],
     n_steps: usize,
+    dt: f64,
+    cfg: &MdConfig,
+    progress: &Progress,
     // ) -> Vec<Snapshot> {
     // ) -> Vec<SnapshotDynamics> {
-) -> Result<MdState, ParamError> {
-    println!("Building docking dyanmics...");
-    let start = Instant::now();
+) -> Result<MdState, DaedalusError> {
+    let _span = Span::new("Docking dynamics");
 
     lig.pose.conformation_type = ConformationType::AbsolutePosits;
 
@@ -317,23 +334,126 @@ pub fn build_dock_dynamics(
             ff_params,
             residues,
         )?;
+        add_flexible_residues(&mut md_state, setup, ff_params, residues, cfg)?;
+        md_state.check_params(cfg.allow_missing_params)?;
+
+        md_state.steering = cfg
+            .steering
+            .clone()
+            .map(|params| SteeringState::new(params, &md_state));
+        md_state.restrain_atoms(&cfg.restrained, cfg.restraint_k);
+        md_state.freeze_atoms(&cfg.frozen);
 
-        // todo: Expose these in the GUI.
-        let n_steps = 50_000;
-        let dt = 0.001;
+        md_state.apply_config(cfg, ff_params)?;
 
-        for _ in 0..n_steps {
-            md_state.step(dt)
+        md_state.progress = progress.clone();
+        md_state.run_on(dev, cfg.protocol.as_ref(), n_steps, dt)?;
+
+        if let Some(gamd) = &md_state.gamd {
+            let (mean, std_dev, max) = gamd.boost_stats();
+            info!("GaMD boost potential (kcal/mol). Mean: {mean:.3} σ: {std_dev:.3} Max: {max:.3}");
         }
 
-        for (i, atom) in md_state.atoms.iter().enumerate() {
-            lig.molecule.atoms[i].posit = atom.posit;
+        // Waters and flexible receptor atoms follow the ligand's.
+        for (atom_lig, atom) in lig.molecule.atoms.iter_mut().zip(&md_state.atoms) {
+            atom_lig.posit = atom.posit;
         }
 
         Ok(md_state)
     }
 }
 
+/// As `build_dock_dynamics`, on the CPU, in a new thread.
+pub fn start_dock_md(
+    mut lig: Ligand,
+    setup: Arc<DockingSetup>,
+    ff_params: Arc<FfParamSet>,
+    residues: Vec<Residue>,
+    n_steps: usize,
+    dt: f64,
+    cfg: MdConfig,
+) -> Task<Result<MdRun, DaedalusError>> {
+    Task::spawn(move |progress| {
+        let md = build_dock_dynamics(
+            &ComputationDevice::Cpu,
+            &mut lig,
+            &setup,
+            &ff_params,
+            &residues,
+            n_steps,
+            dt,
+            &cfg,
+            progress,
+        )?;
+
+        Ok(MdRun {
+            md,
+            ligand: Some(lig),
+        })
+    })
+}
+
+/// Make the receptor residues in `cfg.flexible_residues` mobile, vice static.
+fn add_flexible_residues(
+    md_state: &mut MdState,
+    setup: &DockingSetup,
+    ff_params: &FfParamSet,
+    residues: &[Residue],
+    cfg: &MdConfig,
+) -> Result<(), ParamError> {
+    if cfg.flexible_residues.is_empty() {
+        return Ok(());
+    }
+    let Some(ff_params_prot) = &ff_params.prot_general else {
+        return Err(ParamError::new("Missing prot params general params"));
+    };
+
+    let flex = FlexibleResidues::new(
+        &setup.rec_atoms_near_site,
+        &setup.rec_indices,
+        &setup.rec_bonds_near_site,
+        residues,
+        &cfg.flexible_residues,
+    );
+    md_state.add_flexible(&flex, ff_params_prot, &ff_params.dihedral_series)
+}
+
+/// Relax the ligand in the docking site, e.g. to clear clashes after placing it, with the receptor
+/// rigid, except for flexible residues. Updates the ligand's atom positions. Returns the new
+/// positions of flexible receptor atoms, by receptor atom index.
+pub fn minimize_ligand(
+    lig: &mut Ligand,
+    setup: &DockingSetup,
+    ff_params: &FfParamSet,
+    residues: &[Residue],
+    cfg: &MdConfig,
+) -> Result<(MinimizeResult, Vec<(usize, Vec3)>), DaedalusError> {
+    lig.pose.conformation_type = ConformationType::AbsolutePosits;
+
+    let mut md_state = MdState::new(
+        &lig.molecule.atoms,
+        &lig.atom_posits,
+        &lig.molecule.adjacency_list,
+        &lig.molecule.bonds,
+        &setup.rec_atoms_near_site,
+        ff_params,
+        residues,
+    )?;
+    add_flexible_residues(&mut md_state, setup, ff_params, residues, cfg)?;
+    md_state.check_params(cfg.allow_missing_params)?;
+    md_state.external_fields = cfg.external_fields.clone();
+    md_state.set_nonbonded_scaling(cfg.nonbonded_scaling.clone());
+    md_state.restrain_atoms(&cfg.restrained, cfg.restraint_k);
+    md_state.freeze_atoms(&cfg.frozen);
+
+    let result = md_state.minimize(&cfg.minimize);
+
+    let n_lig = lig.atom_posits.len();
+    lig.atom_posits = md_state.atoms[..n_lig].iter().map(|a| a.posit).collect();
+
+    Ok((result, md_state.flexible_posits()))
+}
+
 /// Body masses are separate from the snapshot, since it's invariant.
 pub fn change_snapshot(
     entities: &mut [Entity],
@@ -372,7 +492,13 @@ pub fn change_snapshot_md(
     // Position atoms from pose  here? You could, but the snapshot has them pre-positioned.
     // This may make changing snapshots faster. But uses more memory from storing each
 
-    lig.atom_posits = snapshot.atom_posits.iter().map(|p| (*p).into()).collect();
+    // Solvent atoms, if present, follow the ligand's.
+    lig.atom_posits = snapshot
+        .atom_posits
+        .iter()
+        .take(lig.molecule.atoms.len())
+        .map(|p| (*p).into())
+        .collect();
 
     // *energy_disp = snapshot.energy.clone();
 }
@@ -8,6 +8,8 @@ use std::{
     str::FromStr,
 };
 
+use log::{error, info, warn};
+
 use crate::{
     docking::{DockingSite, Pose},
     molecule::{Ligand, Molecule},
@@ -41,7 +43,7 @@ fn run_adv(
     target_path: &Path,
     ligand_path: &Path,
 ) -> io::Result<Pose> {
-    println!("Running Autodock Vina...");
+    info!("Running Autodock Vina...");
 
     let output_filename = "docking_result.pdbqt";
 
@@ -74,7 +76,7 @@ fn run_adv(
         // .output()?;
         .status()?;
 
-    println!("Complete.");
+    info!("Complete.");
     //
     // // todo: Create a post from output text.
     // println!("\n\nOutput text: {:?}\n\n", output_text);
@@ -93,10 +95,10 @@ pub fn dock_with_vina(mol: &Molecule, ligand: &Ligand, vina_path: &Option<PathBu
             &PathBuf::from_str(&format!("{}_target.pdbqt", mol.ident)).unwrap(),
             &PathBuf::from_str(&format!("{}_ligand.pdbqt", ligand.molecule.ident)).unwrap(),
         ) {
-            Ok(_r) => println!("Docking successful"),
-            Err(e) => eprintln!("Docking failed: {e:?}"),
+            Ok(_r) => info!("Docking successful"),
+            Err(e) => error!("Docking failed: {e:?}"),
         }
     } else {
-        eprintln!("No Autodock Vina install located yet.");
+        warn!("No Autodock Vina install located yet.");
     }
 }
@@ -0,0 +1,205 @@
+//! Precomputed affinity grid maps over the docking site, as in AutoDock and Vina. Since the
+//! receptor doesn't move, we compute its interaction energy with a probe atom once, at each point
+//! of a grid covering the site. Scoring a pose is then a trilinear interpolation per ligand atom,
+//! vice a sum over all receptor-ligand pairs.
+//!
+//! We build one map per ligand atom type, from the same pair terms as `scoring::vina_score`, so a
+//! search using the maps optimizes the score it reports; the two differ only by interpolation, and
+//! the cap on map values.
+
+use lin_alg::f32::Vec3 as Vec3F32;
+use rayon::prelude::*;
+
+use crate::{
+    docking::{
+        prep::DockingSetup,
+        scoring::{XsType, pair_energy, rot_penalty},
+    },
+    molecule::Ligand,
+};
+
+/// Å. AutoDock's default.
+pub const GRID_SPACING_DEFAULT: f32 = 0.375;
+/// Å. Receptor atoms farther than this from a grid point don't contribute to it. Matches the Vina
+/// score's.
+const CUTOFF: f32 = 8.;
+/// kcal/mol. We cap map values, so interpolation near overlapping atoms stays well-behaved.
+const ENERGY_MAX: f32 = 10.;
+/// kcal/mol, per ligand atom outside the grid.
+const OUT_OF_GRID: f32 = ENERGY_MAX;
+
+#[derive(Clone, Debug)]
+pub struct GridMaps {
+    /// The grid point with the lowest coordinates.
+    pub origin: Vec3F32,
+    /// Å
+    pub spacing: f32,
+    /// Points along each axis.
+    pub dims: [usize; 3],
+    /// Ligand atom types, with a map for each.
+    pub types: Vec<XsType>,
+    /// Indices into `types`, for each ligand atom. `None` for hydrogens, which the score omits.
+    lig_types: Vec<Option<usize>>,
+    /// The Vina rotatable bond penalty, for the ligand.
+    rot_penalty: f32,
+    /// All maps, interleaved by grid point, so one lookup reads them together.
+    values: Vec<f32>,
+}
+
+impl GridMaps {
+    /// Build maps for this ligand's atom types, covering its docking site. We extend the grid past
+    /// the site by the ligand's extent from its anchor, so all atoms stay in it for any pose whose
+    /// anchor is in the site.
+    pub fn new(setup: &DockingSetup, ligand: &Ligand, spacing: f32) -> Self {
+        let atoms = &ligand.molecule.atoms;
+
+        let extent = match atoms.get(ligand.anchor_atom) {
+            Some(anchor) => atoms
+                .iter()
+                .map(|a| (a.posit - anchor.posit).magnitude())
+                .fold(0., f64::max),
+            None => 0.,
+        };
+
+        let rec: Vec<_> = setup
+            .rec_atoms_near_site
+            .iter()
+            .zip(&setup.rec_xs_types)
+            .filter_map(|(a, t)| Some((Vec3F32::from(a.posit), (*t)?)))
+            .collect();
+
+        let site = &ligand.docking_site;
+
+        Self::from_types(
+            &rec,
+            &setup.lig_xs_types,
+            ligand.flexible_bonds.len(),
+            site.site_center.into(),
+            (site.site_radius + extent) as f32,
+            spacing,
+        )
+    }
+
+    /// Build maps from receptor atom positions and types, covering a cube of half-width
+    /// `half_width` around `center`. `lig_types` are each ligand atom's, in order.
+    pub fn from_types(
+        rec: &[(Vec3F32, XsType)],
+        lig_types: &[Option<XsType>],
+        num_rotatable: usize,
+        center: Vec3F32,
+        half_width: f32,
+        spacing: f32,
+    ) -> Self {
+        let mut types = Vec::new();
+        let lig_types = lig_types
+            .iter()
+            .map(|t| {
+                let t = (*t)?;
+                Some(match types.iter().position(|u| *u == t) {
+                    Some(i) => i,
+                    None => {
+                        types.push(t);
+                        types.len() - 1
+                    }
+                })
+            })
+            .collect();
+
+        let origin = center - Vec3F32::new(half_width, half_width, half_width);
+        let n = (2. * half_width / spacing).ceil() as usize + 1;
+        let dims = [n; 3];
+
+        let values = (0..n * n * n)
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                let (ix, iy, iz) = (i % n, (i / n) % n, i / (n * n));
+                let point = origin + Vec3F32::new(ix as f32, iy as f32, iz as f32) * spacing;
+
+                let mut result = vec![0.; types.len()];
+
+                for (posit, rec_type) in rec {
+                    let r = (*posit - point).magnitude();
+                    if r > CUTOFF {
+                        continue;
+                    }
+                    for (v, lig_type) in result.iter_mut().zip(&types) {
+                        *v += pair_energy(rec_type, lig_type, r);
+                    }
+                }
+
+                for v in &mut result {
+                    *v = v.clamp(-ENERGY_MAX, ENERGY_MAX);
+                }
+                result
+            })
+            .collect();
+
+        Self {
+            origin,
+            spacing,
+            dims,
+            types,
+            lig_types,
+            rot_penalty: rot_penalty(num_rotatable),
+            values,
+        }
+    }
+
+    /// Trilinear interpolation of each map at a point. `None` if it's outside the grid.
+    fn interp(&self, posit: Vec3F32, out: &mut [f32]) -> Option<()> {
+        let rel = (posit - self.origin) * (1. / self.spacing);
+        let [nx, ny, nz] = self.dims;
+
+        let (fx, fy, fz) = (rel.x.floor(), rel.y.floor(), rel.z.floor());
+        if fx < 0. || fy < 0. || fz < 0. {
+            return None;
+        }
+        let (x0, y0, z0) = (fx as usize, fy as usize, fz as usize);
+        if x0 + 1 >= nx || y0 + 1 >= ny || z0 + 1 >= nz {
+            return None;
+        }
+        let (tx, ty, tz) = (rel.x - fx, rel.y - fy, rel.z - fz);
+
+        let stride = out.len();
+        out.fill(0.);
+
+        for (dz, wz) in [(0, 1. - tz), (1, tz)] {
+            for (dy, wy) in [(0, 1. - ty), (1, ty)] {
+                for (dx, wx) in [(0, 1. - tx), (1, tx)] {
+                    let w = wx * wy * wz;
+                    let i = ((z0 + dz) * ny + y0 + dy) * nx + x0 + dx;
+
+                    for (o, v) in out
+                        .iter_mut()
+                        .zip(&self.values[i * stride..(i + 1) * stride])
+                    {
+                        *o += w * v;
+                    }
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    /// kcal/mol. The ligand's Vina score, with its atoms at these positions; in the same order as
+    /// the ligand the maps were built for.
+    pub fn energy(&self, lig_posits: &[Vec3F32]) -> f32 {
+        let mut vals = vec![0.; self.types.len()];
+
+        let mut result = 0.;
+        for (posit, lig_type) in lig_posits.iter().zip(&self.lig_types) {
+            let Some(lig_type) = lig_type else {
+                continue;
+            };
+            if self.interp(*posit, &mut vals).is_none() {
+                result += OUT_OF_GRID;
+                continue;
+            }
+
+            result += vals[*lig_type];
+        }
+
+        result / self.rot_penalty
+    }
+}
@@ -42,6 +42,7 @@
 use std::{f32::consts::TAU, time::Instant};
 
 use bincode::{Decode, Encode};
+use graphics::{EngineUpdates, Scene};
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use lin_alg::f32::{f32x8, pack_float, pack_vec3};
 use lin_alg::{
@@ -49,29 +50,44 @@ use lin_alg::{
     f64::{FORWARD, Quaternion, RIGHT, UP, Vec3},
     linspace,
 };
+use log::{debug, info};
 use na_seq::Element;
 use partial_charge::create_partial_charges;
 use rand::Rng;
 use rayon::prelude::*;
 
 use crate::{
-    ComputationDevice,
+    ComputationDevice, State,
     bond_inference::create_hydrogen_bonds_one_way,
     docking::{
         dynamics::build_dock_dynamics,
         prep::{DockingSetup, LIGAND_SAMPLE_RATIO, Torsion},
+        scoring::{VinaScore, vina_score},
     },
+    dynamics::ParamError,
+    error::DaedalusError,
+    events::ViewerEvent,
     forces,
     forces::{V_lj, V_lj_x8},
     molecule::{Atom, Ligand},
+    units::EnergyUnit,
+    util::cam_look_at_outside,
 };
 
+pub mod am1bcc;
+pub mod cleanup;
+pub mod conformers;
 pub mod dynamics;
 pub mod external;
 pub mod find_sites;
+pub mod grid;
 pub mod partial_charge;
+pub mod pharmacophore;
 pub mod prep;
+pub mod scoring;
+pub mod search;
 pub mod site_surface;
+pub mod waters;
 
 const GRID_SPACING_SITE_FINDING: f64 = 5.0;
 
@@ -182,24 +198,25 @@ pub struct BindingEnergy {
     /// An ad-hoc metric of making sure the ligand is close to molecules.
     /// a geometric method?
     proximity: f32,
+    vina: VinaScore,
+    /// We rank poses by this. It's the Vina score; the other terms are for reference.
     score: f32,
 }
 
 impl BindingEnergy {
-    pub fn new(vdw: f32, h_bond_count: usize, hydrophobic: f32, electrostatic: f32) -> Self {
+    pub fn new(
+        vdw: f32,
+        h_bond_count: usize,
+        hydrophobic: f32,
+        electrostatic: f32,
+        vina: VinaScore,
+    ) -> Self {
         const E_PER_H_BOND: f32 = -1.2; // todo A/R.
 
         let h_bond = h_bond_count as f32 * E_PER_H_BOND;
 
-        let weight_vdw = 1.;
-        let weight_hydrophobic = 1.;
-        let weight_electrostatic = 10.;
-
         // A low score is considered to be a better pose.
-        let score = weight_vdw * vdw
-            + h_bond
-            + weight_hydrophobic * hydrophobic
-            + weight_electrostatic * electrostatic;
+        let score = vina.score;
 
         let proximity = 0.; // todo temp
 
@@ -211,8 +228,23 @@ impl BindingEnergy {
             electrostatic,
             score,
             proximity,
+            vina,
         }
     }
+
+    /// Score, and its components, in the display unit.
+    pub fn descrip(&self, energy_unit: EnergyUnit) -> String {
+        let e = |v: f32| energy_unit.fmt(v as f64);
+        format!(
+            "{}\nVdW: {}  H bonds ({}): {}  Hydrophobic: {}  Electrostatic: {}",
+            self.vina.descrip(energy_unit),
+            e(self.vdw),
+            self.h_bond_count,
+            e(self.h_bond),
+            e(self.hydrophobic),
+            e(self.electrostatic),
+        )
+    }
 }
 
 /// todo: Improve this.
@@ -299,6 +331,7 @@ pub fn calc_binding_energy(
             &lig_atoms_positioned,
             &lig_indices,
             true,
+            &Default::default(),
         );
 
         let h_bonds_lig_donor = create_hydrogen_bonds_one_way(
@@ -308,6 +341,7 @@ pub fn calc_binding_energy(
             &setup.rec_atoms_near_site,
             &setup.rec_indices,
             true,
+            &Default::default(),
         );
 
         h_bonds_rec_donor.len() + h_bonds_lig_donor.len()
@@ -407,11 +441,19 @@ pub fn calc_binding_energy(
         force.magnitude()
     };
 
+    let vina = vina_score(
+        &distances,
+        &setup.rec_xs_types,
+        &setup.lig_xs_types,
+        ligand.flexible_bonds.len(),
+    );
+
     Some(BindingEnergy::new(
         vdw,
         h_bond_count,
         hydrophobic_score,
         electrostatic,
+        vina,
     ))
 }
 
@@ -548,7 +590,7 @@ fn process_poses(
 
     // Optimization; Hydrogens are always close to another atom, and we have many; we can likely rely
     // on that other atom, and save ~n^2 computation here.
-    println!("Eliminating poses with atoms too close together...");
+    debug!("Eliminating poses with atoms too close together...");
 
     let mut geometry_poses_skip = Vec::new();
 
@@ -653,7 +695,7 @@ fn process_poses(
         // }
     }
 
-    println!(
+    debug!(
         "Complete. iterating through {} poses...",
         poses.len() - geometry_poses_skip.len()
     );
@@ -721,7 +763,7 @@ pub fn find_optimal_pose(
     // todo: Evaluate if you can cache EEM charges. Look into how position-dependent they are between ligand flexible
     // todo bond conformations, and lig/receptor interactions.
 
-    println!(
+    debug!(
         "Atom counts. Rec: {} Lig: {}",
         setup.rec_atoms_near_site.len(),
         ligand.molecule.atoms.len()
@@ -740,7 +782,7 @@ pub fn find_optimal_pose(
         num_orientations,
         angles_per_bond,
     );
-    println!("Initial pose count: {} poses...", poses.len());
+    debug!("Initial pose count: {} poses...", poses.len());
 
     // todo: Increase.
     let top_pose_count = 10;
@@ -771,20 +813,61 @@ pub fn find_optimal_pose(
         // );
     }
 
-    println!("Complete. \n\nBest pose init: {best_pose:?} \n\nScores: {best_energy:.3?}\n\n");
+    debug!("Complete. Best pose init: {best_pose:?} Scores: {best_energy:.3?}");
 
     // Vary orientations and positiosn of the best poses, pre and/or pose md sim?
 
-    println!("\nBest initial pose: {best_pose:?} \nScores: {best_energy:.3?}\n");
+    debug!("Best initial pose: {best_pose:?} Scores: {best_energy:.3?}");
 
     // Some ad-hoc tweaking.
     //let new_poses = vary_pose(best_pose);
 
     let elapsed = start.elapsed();
-    println!("Time: {}ms", elapsed.as_millis());
-    println!("Complete. \n\nBest pose: {best_pose:?} \n\nScores: {best_energy:.3?}\n\n");
+    info!("Time: {}ms", elapsed.as_millis());
+    info!("Complete. Best pose: {best_pose:?} Scores: {best_energy:.3?}");
     (best_pose.clone(), best_energy.clone())
 }
 
 // Find hydrogen bond interaction, hydrophobic interactions between ligand and protein.
 // Find the "perfect" "Het" or "lead" molecule that will act as drug receptor
+
+/// Find the ligand's optimal pose in the docking site, and point the camera at it. Used by the
+/// Dock button, and the `dock` command.
+pub fn dock_ligand(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) -> Result<(), DaedalusError> {
+    // todo: Ideally move the camera to the docking site prior to docking. You could do this
+    // todo by deferring the docking below to the next frame.
+    if state.get_make_docking_setup().is_none() {
+        return Err(ParamError::new("Docking requires a molecule and ligand").into());
+    }
+    let (Some(mol), Some(lig), Some(setup)) = (
+        &state.molecule,
+        &mut state.ligand,
+        &state.volatile.docking_setup,
+    ) else {
+        return Err(ParamError::new("Docking requires a molecule and ligand").into());
+    };
+
+    // todo For now. GPU currently is going slower than CPU for VDW.
+    let (pose, binding_energy) = find_optimal_pose(&ComputationDevice::Cpu, setup, lig);
+
+    state.events.emit(ViewerEvent::DockingPoseUpdated {
+        pose: pose.clone(),
+        binding_energy,
+    });
+    lig.pose = pose;
+    lig.position_atoms(None);
+
+    let lig_pos: Vec3F32 = lig.atom_posits[lig.anchor_atom].into();
+    let ctr: Vec3F32 = mol.center.into();
+
+    cam_look_at_outside(&mut scene.camera, lig_pos, ctr);
+
+    engine_updates.camera = true;
+    state.ui.cam_snapshot = None;
+
+    Ok(())
+}
@@ -5,6 +5,7 @@
 
 use barnes_hut::BodyModel;
 use lin_alg::{f32::Vec3, f64::Vec3 as Vec3F64};
+use log::error;
 use na_seq::Element;
 use nalgebra::{DMatrix, DVector};
 
@@ -375,7 +376,7 @@ pub fn assign_eem_charges(
         // optionally store or ignore lambda
         // println!("Lambda = {}", x[n]);
     } else {
-        eprintln!("Failed to solve EEM system!");
+        error!("Failed to solve EEM system!");
     }
 }
 
@@ -411,6 +412,111 @@ pub fn create_partial_charges(atoms: &[Atom], posits: Option<&[Vec3]>) -> Vec<Pa
     result
 }
 
+/// Gasteiger-Marsili iterations. Charge transfer is damped by half each iteration, so more has
+/// little effect.
+const GASTEIGER_ITERS: usize = 6;
+/// Hydrogen's electronegativity as a cation is a special case in Gasteiger's scheme.
+const GASTEIGER_H_CATION: f32 = 20.02;
+
+/// (a, b, c) for χ(q) = a + bq + cq², by element and hybridization. Gasteiger and Marsili, 1980.
+/// `max_order` is the highest bond order of the atom's bonds; 2 for sp2, and 3 for sp.
+fn gasteiger_params(el: Element, max_order: u8) -> Option<(f32, f32, f32)> {
+    use Element::*;
+
+    Some(match (el, max_order) {
+        (Hydrogen, _) => (7.17, 6.24, -0.56),
+        (Carbon, 3) => (10.39, 9.45, 0.73),
+        (Carbon, 2) => (8.79, 9.32, 1.51),
+        (Carbon, _) => (7.98, 9.18, 1.88),
+        (Nitrogen, 3) => (15.68, 11.7, -0.27),
+        (Nitrogen, 2) => (12.87, 11.15, 0.85),
+        (Nitrogen, _) => (11.54, 10.82, 1.36),
+        (Oxygen, 2) => (17.07, 13.79, 0.47),
+        (Oxygen, _) => (14.18, 12.92, 1.39),
+        (Sulfur, 2) => (10.88, 9.49, 1.33),
+        (Sulfur, _) => (10.14, 9.13, 1.38),
+        (Phosphorus, _) => (8.9, 8.24, 0.96),
+        (Fluorine, _) => (14.66, 13.85, 2.31),
+        (Chlorine, _) => (11., 9.69, 1.35),
+        (Bromine, _) => (10.08, 8.47, 1.16),
+        (Iodine, _) => (9.9, 7.96, 0.96),
+        _ => return None,
+    })
+}
+
+/// Gasteiger-Marsili partial charges, from bonded topology alone: Charge flows along each bond from
+/// the less, to the more electronegative atom, with electronegativity updated from the charge each
+/// iteration. For structures that don't come with charges, e.g. for docking and MD. Atoms of
+/// unparameterized elements, e.g. metals, are left neutral.
+pub fn gasteiger_charges(atoms: &[Atom], bonds: &[Bond]) -> Vec<f32> {
+    let n = atoms.len();
+
+    let mut max_order = vec![1; n];
+    for bond in bonds {
+        let order = match bond.bond_type {
+            BondType::Covalent { count } => match count {
+                BondCount::Triple => 3,
+                BondCount::Double | BondCount::SingleDoubleHybrid => 2,
+                BondCount::Single => 1,
+            },
+            _ => continue,
+        };
+        for i in [bond.atom_0, bond.atom_1] {
+            max_order[i] = max_order[i].max(order);
+        }
+    }
+
+    let params: Vec<_> = atoms
+        .iter()
+        .zip(&max_order)
+        .map(|(a, &order)| gasteiger_params(a.element, order))
+        .collect();
+
+    let mut charges = vec![0.; n];
+    let mut damping = 1.;
+
+    for _ in 0..GASTEIGER_ITERS {
+        damping *= 0.5;
+
+        let χ: Vec<_> = params
+            .iter()
+            .zip(&charges)
+            .map(|(p, q)| p.map(|(a, b, c)| a + b * q + c * q * q))
+            .collect();
+
+        let mut dq = vec![0.; n];
+        for bond in bonds {
+            if !matches!(bond.bond_type, BondType::Covalent { .. }) {
+                continue;
+            }
+            let (i, j) = (bond.atom_0, bond.atom_1);
+            let (Some(χ_i), Some(χ_j)) = (χ[i], χ[j]) else {
+                continue;
+            };
+
+            // Normalize by the cationic electronegativity of the atom losing charge.
+            let donor = if χ_j > χ_i { i } else { j };
+            let χ_cation = match atoms[donor].element {
+                Element::Hydrogen => GASTEIGER_H_CATION,
+                _ => {
+                    let (a, b, c) = params[donor].unwrap();
+                    a + b + c
+                }
+            };
+
+            let transfer = damping * (χ_j - χ_i) / χ_cation;
+            dq[i] += transfer;
+            dq[j] -= transfer;
+        }
+
+        for (q, d) in charges.iter_mut().zip(&dq) {
+            *q += d;
+        }
+    }
+
+    charges
+}
+
 //
 // /// Create a set of partial charges around atoms. Rough simulation of electronic density imbalances
 // /// in charges molecules, and/or at short distances. It places a single positive charge
@@ -0,0 +1,132 @@
+//! Pharmacophore constraints: interactions the user requires a docked pose to make, e.g. a hydrogen
+//! bond to a catalytic residue, or a hydrophobic contact in a sub-pocket. The search adds a
+//! penalty for each one a pose doesn't satisfy, and optionally rejects poses that miss any.
+
+use lin_alg::f64::Vec3;
+
+use crate::{analysis::clashes::atom_label, docking::scoring::XsType, molecule::Molecule};
+
+/// Å. Between heavy atoms.
+const H_BOND_DIST_MAX: f64 = 3.5;
+/// Å. Hydrophobic contacts are with ligand atoms within this of the chosen receptor atom.
+pub const HYDROPHOBIC_RADIUS_DEFAULT: f64 = 4.5;
+/// kcal/mol, per unsatisfied constraint.
+pub const PENALTY_DEFAULT: f32 = 2.;
+
+#[derive(Clone, Debug)]
+pub enum PharmFeature {
+    /// A hydrogen bond between a ligand heavy atom, and this receptor atom.
+    HBond {
+        /// Index into the receptor's atoms.
+        rec_atom: usize,
+        posit: Vec3,
+        donor: bool,
+        acceptor: bool,
+        label: String,
+    },
+    /// A hydrophobic ligand atom, within a radius of a point.
+    Hydrophobic {
+        center: Vec3,
+        /// Å
+        radius: f64,
+        label: String,
+    },
+}
+
+impl PharmFeature {
+    /// Returns `None` if the atom can't hydrogen bond, e.g. a carbon.
+    pub fn h_bond(mol: &Molecule, rec_atom: usize) -> Option<Self> {
+        let atom = mol.atoms.get(rec_atom)?;
+        let neighbors: Vec<_> = mol
+            .adjacency_list
+            .get(rec_atom)
+            .map(|adj| adj.iter().map(|j| mol.atoms[*j].element).collect())
+            .unwrap_or_default();
+
+        let xs = XsType::new(atom.element, &neighbors)?;
+        if !xs.donor && !xs.acceptor {
+            return None;
+        }
+
+        Some(Self::HBond {
+            rec_atom,
+            posit: atom.posit,
+            donor: xs.donor,
+            acceptor: xs.acceptor,
+            label: atom_label(mol, rec_atom),
+        })
+    }
+
+    /// Centered on a receptor atom lining the sub-pocket.
+    pub fn hydrophobic(mol: &Molecule, rec_atom: usize) -> Option<Self> {
+        let atom = mol.atoms.get(rec_atom)?;
+
+        Some(Self::Hydrophobic {
+            center: atom.posit,
+            radius: HYDROPHOBIC_RADIUS_DEFAULT,
+            label: atom_label(mol, rec_atom),
+        })
+    }
+
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::HBond { label, .. } => format!("H bond: {label}"),
+            Self::Hydrophobic { label, radius, .. } => {
+                format!("Hydrophobic: {label} ({radius:.1} Å)")
+            }
+        }
+    }
+
+    /// `lig_types` are in the same order as `lig_posits`.
+    pub fn satisfied(&self, lig_posits: &[Vec3], lig_types: &[Option<XsType>]) -> bool {
+        lig_posits.iter().zip(lig_types).any(|(p, t)| {
+            let Some(t) = t else {
+                return false;
+            };
+
+            match self {
+                Self::HBond {
+                    posit,
+                    donor,
+                    acceptor,
+                    ..
+                } => {
+                    ((*donor && t.acceptor) || (*acceptor && t.donor))
+                        && (*p - *posit).magnitude() <= H_BOND_DIST_MAX
+                }
+                Self::Hydrophobic { center, radius, .. } => {
+                    t.hydrophobic && (*p - *center).magnitude() <= *radius
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PharmConstraints {
+    pub features: Vec<PharmFeature>,
+    /// kcal/mol, added to the search objective for each unsatisfied feature.
+    pub penalty: f32,
+    /// If set, drop poses that don't satisfy all features from search results.
+    pub required: bool,
+}
+
+impl Default for PharmConstraints {
+    fn default() -> Self {
+        Self {
+            features: Vec::new(),
+            penalty: PENALTY_DEFAULT,
+            required: false,
+        }
+    }
+}
+
+impl PharmConstraints {
+    /// The number of features this pose doesn't satisfy.
+    pub fn unsatisfied(&self, lig_posits: &[Vec3], lig_types: &[Option<XsType>]) -> usize {
+        self.features
+            .iter()
+            .filter(|f| !f.satisfied(lig_posits, lig_types))
+            .count()
+    }
+}
@@ -5,9 +5,11 @@
 use std::{collections::HashMap, fmt::Display};
 
 use barnes_hut::{BhConfig, Cube, Tree};
-use lin_alg::f32::Vec3;
+use bincode::{Decode, Encode};
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use lin_alg::f32::{Vec3x8, f32x8, pack_float};
+use lin_alg::{f32::Vec3, f64::Vec3 as Vec3F64};
+use log::{debug, warn};
 use na_seq::{Element, element::LjTable};
 
 use crate::{
@@ -16,6 +18,8 @@ use crate::{
         partial_charge::{
             EemParams, EemSet, PartialCharge, assign_eem_charges, create_partial_charges,
         },
+        scoring::{XsType, xs_types},
+        waters::water_atoms,
     },
     forces::setup_sigma_eps_x8,
     molecule::{Atom, Bond, BondCount, BondType, Ligand, Molecule},
@@ -33,14 +37,14 @@ fn setup_eem_charges(
     rec_atoms_near_site: &mut [Atom],
     rec_atom_indices: &[usize], // Indices of the whole molecule. Used for matching with bonds.
 ) -> Vec<PartialCharge> {
-    println!("Starting EEM charge setup...");
+    debug!("Starting EEM charge setup...");
 
     let eem_params = EemParams::new(EemSet::AimB3); // todo: WHich set?
 
     // todo: This is problematic given the flexible bonds. Too expensive to do each conformation though.
     // todo: Let it ride for now?
     if !ligand.molecule.eem_charges_assigned {
-        println!("Assigning EEM charges for the Ligand......");
+        debug!("Assigning EEM charges for the Ligand......");
         let indices: Vec<usize> = (0..ligand.molecule.atoms.len()).collect();
         assign_eem_charges(
             &mut ligand.molecule.atoms,
@@ -50,11 +54,11 @@ fn setup_eem_charges(
             &eem_params,
             0., // todo!
         );
-        println!("Complete.");
+        debug!("Complete.");
         ligand.molecule.eem_charges_assigned = true;
     }
 
-    println!("Assigning EEM charges to receptor atoms...");
+    debug!("Assigning EEM charges to receptor atoms...");
     assign_eem_charges(
         rec_atoms_near_site,
         rec_atom_indices,
@@ -75,7 +79,7 @@ fn setup_eem_charges(
     // fewer charges than the other.
     let partial_charges_rec = create_partial_charges(rec_atoms_near_site, None);
 
-    println!("EEM setup complete.");
+    debug!("EEM setup complete.");
 
     partial_charges_rec
 }
@@ -106,6 +110,9 @@ pub struct DockingSetup {
     pub bh_config: BhConfig,
     /// Used for some cheap computations that eliminate poses, for example.
     pub rec_atoms_sample: Vec<Atom>,
+    /// For Vina scoring. Same order as `rec_atoms_near_site`.
+    pub rec_xs_types: Vec<Option<XsType>>,
+    pub lig_xs_types: Vec<Option<XsType>>,
 }
 
 impl DockingSetup {
@@ -115,8 +122,11 @@ impl DockingSetup {
         lj_lut: &LjTable,
         bh_config: &BhConfig,
     ) -> Self {
-        let (mut rec_atoms_near_site, rec_indices) =
-            find_rec_atoms_near_site(receptor, &ligand.docking_site);
+        let (mut rec_atoms_near_site, rec_indices) = find_rec_atoms_near_site(
+            receptor,
+            &ligand.docking_site,
+            &water_atoms(receptor, &ligand.site_waters),
+        );
 
         // let (rec_indices_x8, _) = pack_slice(&rec_indices);
 
@@ -189,6 +199,11 @@ impl DockingSetup {
             .map(|(_, a)| a.clone())
             .collect();
 
+        // Typing depends on bonded neighbors, so we use the whole receptor.
+        let rec_types_all = xs_types(&receptor.atoms, &receptor.adjacency_list);
+        let rec_xs_types = rec_indices.iter().map(|i| rec_types_all[*i]).collect();
+        let lig_xs_types = xs_types(&ligand.molecule.atoms, &ligand.molecule.adjacency_list);
+
         let partial_charges_rec = Vec::new(); // todo: Load from Amber.
         let charge_tree = Tree::default(); // todo temp; handle once you apply amber params here.
 
@@ -210,6 +225,8 @@ impl DockingSetup {
             charge_tree,
             bh_config: bh_config.clone(),
             rec_atoms_sample,
+            rec_xs_types,
+            lig_xs_types,
         }
     }
 }
@@ -227,7 +244,7 @@ fn bonded_to_h(bonds: &[Bond], atoms: &[Atom]) -> bool {
     false
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Encode, Decode)]
 pub enum DockType {
     // Standard AutoDock4/Vina atom types
     A,  // Aromatic carbon
@@ -367,7 +384,7 @@ impl DockType {
                 } else if s.starts_with("H") {
                     Self::H
                 } else {
-                    eprintln!("Unknown dock type: {}", s);
+                    warn!("Unknown dock type: {}", s);
                     Self::Other
                 }
             }
@@ -465,15 +482,49 @@ pub struct Torsion {
     pub dihedral_angle: f32,
 }
 
-/// Counts the number of bonds connected to a given atom. Used for flexibility computation.
-fn count_bonds(atom_index: usize, mol: &Molecule) -> usize {
-    mol.bonds
-        .iter()
-        .filter(|bond| bond.atom_0 == atom_index || bond.atom_1 == atom_index)
-        .count()
+/// Heavy (non-hydrogen) neighbors of an atom.
+fn heavy_neighbors(atom_index: usize, mol: &Molecule) -> Vec<usize> {
+    get_neighbors(atom_index, mol)
+        .into_iter()
+        .filter(|n| mol.atoms[*n].element != Element::Hydrogen)
+        .collect()
+}
+
+/// A C-N bond, where the carbon is double-bonded to an O or S. These have partial double bond
+/// character, so we don't rotate them.
+fn is_amide(bond: &Bond, mol: &Molecule) -> bool {
+    let (c, n) = match (
+        mol.atoms[bond.atom_0].element,
+        mol.atoms[bond.atom_1].element,
+    ) {
+        (Element::Carbon, Element::Nitrogen) => (bond.atom_0, bond.atom_1),
+        (Element::Nitrogen, Element::Carbon) => (bond.atom_1, bond.atom_0),
+        _ => return false,
+    };
+
+    mol.bonds.iter().any(|b| {
+        let other = if b.atom_0 == c {
+            b.atom_1
+        } else if b.atom_1 == c {
+            b.atom_0
+        } else {
+            return false;
+        };
+
+        other != n
+            && matches!(mol.atoms[other].element, Element::Oxygen | Element::Sulfur)
+            && matches!(
+                b.bond_type,
+                BondType::Covalent {
+                    count: BondCount::Double,
+                    ..
+                }
+            )
+    })
 }
 
-// Set up which atoms in a ligand are flexible.
+/// Find a ligand's rotatable bonds: Non-ring single bonds between heavy atoms, excluding amides,
+/// and terminal bonds, whose rotation only moves hydrogens (e.g. methyl and hydroxyl groups).
 pub fn setup_flexibility(mol: &Molecule) -> Vec<usize> {
     let mut flexible_bonds = Vec::new();
 
@@ -487,26 +538,26 @@ pub fn setup_flexibility(mol: &Molecule) -> Vec<usize> {
             continue;
         }
 
-        // Exclude bonds that are part of a ring.
-        if is_bond_in_ring(bond, mol) {
+        if mol.atoms[bond.atom_0].element == Element::Hydrogen
+            || mol.atoms[bond.atom_1].element == Element::Hydrogen
+        {
             continue;
         }
 
-        // Retrieve atoms at each end.
-        let atom0 = &mol.atoms[bond.atom_0];
-        let atom1 = &mol.atoms[bond.atom_1];
-
-        // Check if both atoms are carbon.
-        if atom0.element != Element::Carbon || atom1.element != Element::Carbon {
+        if heavy_neighbors(bond.atom_0, mol).len() <= 1
+            || heavy_neighbors(bond.atom_1, mol).len() <= 1
+        {
             continue;
         }
 
-        // Exclude terminal bonds (e.g., bonds where an atom only has one connection).
-        if count_bonds(bond.atom_0, mol) <= 1 || count_bonds(bond.atom_1, mol) <= 1 {
+        // Exclude bonds that are part of a ring.
+        if is_bond_in_ring(bond, mol) {
             continue;
         }
 
-        // Additional heuristics (e.g. hybridization or sterics) could be added here.
+        if is_amide(bond, mol) {
+            continue;
+        }
 
         flexible_bonds.push(i);
     }
@@ -514,6 +565,165 @@ pub fn setup_flexibility(mol: &Molecule) -> Vec<usize> {
     flexible_bonds
 }
 
+/// A rotatable bond in the torsion tree, and the atoms rotating it moves.
+#[derive(Clone, Debug)]
+pub struct TorsionBranch {
+    /// Index into the molecule's bonds.
+    pub bond: usize,
+    /// The bond's atom on the root side. It stays fixed.
+    pub pivot: usize,
+    /// The bond's other atom, and all atoms beyond it.
+    pub moving: Vec<usize>,
+}
+
+/// Rigid fragments, joined by rotatable bonds. The root fragment contains the anchor atom, and
+/// stays fixed when torsions change; each rotatable bond moves the part of the ligand away from
+/// it. This keeps the anchor, and therefore the pose's position, fixed under torsion changes.
+#[derive(Clone, Debug, Default)]
+pub struct TorsionTree {
+    /// Atom indices.
+    pub fragments: Vec<Vec<usize>>,
+    /// Index into `fragments`.
+    pub root: usize,
+    pub anchor: usize,
+    /// In the same order as the flexible bonds it's built from.
+    pub branches: Vec<TorsionBranch>,
+}
+
+impl TorsionTree {
+    /// The root is the largest rigid fragment, by heavy atom count; ties go to the fragment closest
+    /// to the molecule's center. The anchor is the root's heavy atom closest to its center.
+    pub fn new(mol: &Molecule, flexible_bonds: &[usize]) -> Self {
+        let n = mol.atoms.len();
+        if n == 0 {
+            return Default::default();
+        }
+
+        let is_flexible = |a: usize, b: usize| {
+            flexible_bonds.iter().any(|i| {
+                let bond = &mol.bonds[*i];
+                (bond.atom_0 == a && bond.atom_1 == b) || (bond.atom_0 == b && bond.atom_1 == a)
+            })
+        };
+
+        // Connected components, without crossing rotatable bonds.
+        let mut fragment_of = vec![usize::MAX; n];
+        let mut fragments = Vec::new();
+
+        for start in 0..n {
+            if fragment_of[start] != usize::MAX {
+                continue;
+            }
+            let i_frag = fragments.len();
+            let mut fragment = Vec::new();
+            let mut stack = vec![start];
+            fragment_of[start] = i_frag;
+
+            while let Some(current) = stack.pop() {
+                fragment.push(current);
+                for nbr in get_neighbors(current, mol) {
+                    if fragment_of[nbr] == usize::MAX && !is_flexible(current, nbr) {
+                        fragment_of[nbr] = i_frag;
+                        stack.push(nbr);
+                    }
+                }
+            }
+            fragments.push(fragment);
+        }
+
+        let centroid = |atoms: &[usize]| {
+            let heavy: Vec<_> = atoms
+                .iter()
+                .filter(|i| mol.atoms[**i].element != Element::Hydrogen)
+                .collect();
+            let mut sum = Vec3F64::new_zero();
+            for i in &heavy {
+                sum += mol.atoms[**i].posit;
+            }
+            (sum / heavy.len().max(1) as f64, heavy.len())
+        };
+
+        let all: Vec<_> = (0..n).collect();
+        let (center, _) = centroid(&all);
+
+        let mut root = 0;
+        let mut best = (0, f64::MAX);
+        for (i, fragment) in fragments.iter().enumerate() {
+            let (c, heavy_count) = centroid(fragment);
+            let dist = (c - center).magnitude();
+            if heavy_count > best.0 || (heavy_count == best.0 && dist < best.1) {
+                root = i;
+                best = (heavy_count, dist);
+            }
+        }
+
+        let (root_center, _) = centroid(&fragments[root]);
+        let anchor = fragments[root]
+            .iter()
+            .copied()
+            .filter(|i| mol.atoms[*i].element != Element::Hydrogen)
+            .min_by(|a, b| {
+                let dist = |i: usize| (mol.atoms[i].posit - root_center).magnitude();
+                dist(*a).total_cmp(&dist(*b))
+            })
+            .unwrap_or(fragments[root][0]);
+
+        // Breadth-first distance from the anchor, to find which side of each bond is the root's.
+        let mut depth = vec![usize::MAX; n];
+        let mut queue = std::collections::VecDeque::from([anchor]);
+        depth[anchor] = 0;
+        while let Some(current) = queue.pop_front() {
+            for nbr in get_neighbors(current, mol) {
+                if depth[nbr] == usize::MAX {
+                    depth[nbr] = depth[current] + 1;
+                    queue.push_back(nbr);
+                }
+            }
+        }
+
+        let branches = flexible_bonds
+            .iter()
+            .map(|&i| {
+                let bond = &mol.bonds[i];
+                let (pivot, side) = if depth[bond.atom_0] <= depth[bond.atom_1] {
+                    (bond.atom_0, bond.atom_1)
+                } else {
+                    (bond.atom_1, bond.atom_0)
+                };
+
+                let mut visited = vec![false; n];
+                visited[pivot] = true;
+                visited[side] = true;
+                let mut stack = vec![side];
+                let mut moving = Vec::new();
+
+                while let Some(current) = stack.pop() {
+                    moving.push(current);
+                    for nbr in get_neighbors(current, mol) {
+                        if !visited[nbr] {
+                            visited[nbr] = true;
+                            stack.push(nbr);
+                        }
+                    }
+                }
+
+                TorsionBranch {
+                    bond: i,
+                    pivot,
+                    moving,
+                }
+            })
+            .collect();
+
+        Self {
+            fragments,
+            root,
+            anchor,
+            branches,
+        }
+    }
+}
+
 /// Returns the list of neighboring atom indices for a given atom.
 fn get_neighbors(atom_index: usize, mol: &Molecule) -> Vec<usize> {
     let mut neighbors = Vec::new();
@@ -549,7 +759,7 @@ fn is_bond_in_ring(bond: &Bond, mol: &Molecule) -> bool {
             }
 
             if neighbor >= visited.len() {
-                eprintln!("Error checking if bond is in a ring: neighbor > visited len");
+                warn!("Error checking if bond is in a ring: neighbor > visited len");
                 return false;
             }
 
@@ -562,8 +772,13 @@ fn is_bond_in_ring(bond: &Bond, mol: &Molecule) -> bool {
 }
 
 /// Find the subet of receptor atoms near a docking site. Only perform force calculations
-/// between this set and the ligand, to keep computational complexity under control.
-fn find_rec_atoms_near_site(receptor: &Molecule, site: &DockingSite) -> (Vec<Atom>, Vec<usize>) {
+/// between this set and the ligand, to keep computational complexity under control. We skip
+/// hetero atoms, other than the `waters` atoms, which the user has chosen to keep.
+fn find_rec_atoms_near_site(
+    receptor: &Molecule,
+    site: &DockingSite,
+    waters: &[usize],
+) -> (Vec<Atom>, Vec<usize>) {
     let dist_thresh = ATOM_NEAR_SITE_DIST_THRESH * site.site_radius;
 
     let mut indices = Vec::new();
@@ -575,7 +790,8 @@ fn find_rec_atoms_near_site(receptor: &Molecule, site: &DockingSite) -> (Vec<Ato
         .iter()
         .enumerate()
         .filter(|(i, a)| {
-            let r = (a.posit - site.site_center).magnitude() < dist_thresh && !a.hetero;
+            let r = (a.posit - site.site_center).magnitude() < dist_thresh
+                && (!a.hetero || waters.contains(i));
             if r {
                 indices.push(*i);
             }
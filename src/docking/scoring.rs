@@ -0,0 +1,215 @@
+//! An empirical scoring function, after Autodock Vina's. We sum steric, hydrophobic, and hydrogen
+//! bond terms over ligand-receptor heavy atom pairs, as functions of their surface distance: the
+//! distance between atom centers, less their van der Waals radii. The sum is then divided by a
+//! penalty for the ligand's rotatable bonds, to account for the entropy lost on binding.
+//!
+//! [Trott and Olson, 2010](https://doi.org/10.1002/jcc.21334)
+
+use na_seq::Element::{self, *};
+
+use crate::{molecule::Atom, units::EnergyUnit};
+
+/// Å. Pairs farther apart than this don't contribute.
+const CUTOFF: f32 = 8.;
+
+// Term weights from the Vina paper. The result is in approximately kcal/mol.
+const W_GAUSS_1: f32 = -0.035_579;
+const W_GAUSS_2: f32 = -0.005_156;
+const W_REPULSION: f32 = 0.840_245;
+const W_HYDROPHOBIC: f32 = -0.035_069;
+const W_H_BOND: f32 = -0.587_439;
+const W_ROT: f32 = 0.058_459;
+
+/// Atom properties the score depends on. (X-Score types in Vina) Hydrogens have none; they're
+/// implicit in their heavy atoms' donor status.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct XsType {
+    /// Å
+    pub radius: f32,
+    pub hydrophobic: bool,
+    pub donor: bool,
+    pub acceptor: bool,
+}
+
+impl XsType {
+    /// `neighbors` are the elements of atoms bonded to this one.
+    pub fn new(el: Element, neighbors: &[Element]) -> Option<Self> {
+        let has_h = neighbors.contains(&Hydrogen);
+        let bonded_to_hetero = neighbors.iter().any(|n| matches!(n, Nitrogen | Oxygen));
+        let num_heavy = neighbors.iter().filter(|n| **n != Hydrogen).count();
+
+        let (radius, hydrophobic, donor, acceptor) = match el {
+            Hydrogen => return None,
+            Carbon => (1.9, !bonded_to_hetero, false, false),
+            // E.g. aromatic, or imine N, without H, are acceptors; amides and amines aren't.
+            Nitrogen => (1.8, false, has_h, !has_h && num_heavy < 3),
+            Oxygen => (1.7, false, has_h, true),
+            Sulfur => (2.0, false, false, false),
+            Phosphorus => (2.1, false, false, false),
+            Fluorine => (1.5, true, false, false),
+            Chlorine => (1.8, true, false, false),
+            Bromine => (2.0, true, false, false),
+            Iodine => (2.2, true, false, false),
+            // Metal ions are treated as donors.
+            Zinc | Iron | Magnesium | Calcium | Manganese => (1.2, false, true, false),
+            _ => (1.9, false, false, false),
+        };
+
+        Some(Self {
+            radius,
+            hydrophobic,
+            donor,
+            acceptor,
+        })
+    }
+}
+
+/// Assign types to each atom. If `adjacency_list` is empty, e.g. for molecules whose bonds
+/// haven't been built, we type atoms as if they had no neighbors.
+pub fn xs_types(atoms: &[Atom], adjacency_list: &[Vec<usize>]) -> Vec<Option<XsType>> {
+    atoms
+        .iter()
+        .enumerate()
+        .map(|(i, atom)| {
+            let neighbors: Vec<_> = adjacency_list
+                .get(i)
+                .map(|adj| adj.iter().map(|j| atoms[*j].element).collect())
+                .unwrap_or_default();
+            XsType::new(atom.element, &neighbors)
+        })
+        .collect()
+}
+
+/// Unweighted term sums, and the weighted result.
+#[derive(Clone, Debug, Default)]
+pub struct VinaScore {
+    pub gauss_1: f32,
+    pub gauss_2: f32,
+    pub repulsion: f32,
+    pub hydrophobic: f32,
+    pub h_bond: f32,
+    pub num_rotatable: usize,
+    /// kcal/mol. The weighted sum, divided by the rotatable bond penalty. Lower is better.
+    pub score: f32,
+}
+
+impl VinaScore {
+    pub fn descrip(&self, energy_unit: EnergyUnit) -> String {
+        format!(
+            "Vina: {}  Gauss: {:.2}, {:.2}  Repulsion: {:.2}  Hydrophobic: {:.2}  H bond: {:.2}  Rot: {}",
+            energy_unit.fmt(self.score as f64),
+            self.gauss_1,
+            self.gauss_2,
+            self.repulsion,
+            self.hydrophobic,
+            self.h_bond,
+            self.num_rotatable,
+        )
+    }
+}
+
+/// 1 below `good`, 0 above `bad`, and linear between.
+fn slope_step(d: f32, good: f32, bad: f32) -> f32 {
+    if d <= good {
+        1.
+    } else if d >= bad {
+        0.
+    } else {
+        (bad - d) / (bad - good)
+    }
+}
+
+/// Unweighted terms for one receptor-ligand pair, `r` apart: Gauss 1, Gauss 2, repulsion,
+/// hydrophobic, and H bond.
+fn pair_terms(rec: &XsType, lig: &XsType, r: f32) -> [f32; 5] {
+    // Surface distance.
+    let d = r - rec.radius - lig.radius;
+
+    let hydrophobic = if rec.hydrophobic && lig.hydrophobic {
+        slope_step(d, 0.5, 1.5)
+    } else {
+        0.
+    };
+    let h_bond = if (rec.donor && lig.acceptor) || (rec.acceptor && lig.donor) {
+        slope_step(d, -0.7, 0.)
+    } else {
+        0.
+    };
+
+    [
+        (-(d / 0.5).powi(2)).exp(),
+        (-((d - 3.) / 2.).powi(2)).exp(),
+        if d < 0. { d * d } else { 0. },
+        hydrophobic,
+        h_bond,
+    ]
+}
+
+/// kcal/mol. One pair's weighted terms, before dividing by the rotatable bond penalty. Summing
+/// this over pairs, then dividing by `rot_penalty`, gives the score; grid maps store these sums.
+pub fn pair_energy(rec: &XsType, lig: &XsType, r: f32) -> f32 {
+    if r > CUTOFF {
+        return 0.;
+    }
+    let [gauss_1, gauss_2, repulsion, hydrophobic, h_bond] = pair_terms(rec, lig, r);
+
+    W_GAUSS_1 * gauss_1
+        + W_GAUSS_2 * gauss_2
+        + W_REPULSION * repulsion
+        + W_HYDROPHOBIC * hydrophobic
+        + W_H_BOND * h_bond
+}
+
+/// The weighted term sum is divided by this.
+pub fn rot_penalty(num_rotatable: usize) -> f32 {
+    1. + W_ROT * num_rotatable as f32
+}
+
+/// Score a pose. `distances` are between each receptor and ligand atom, with the receptor in the
+/// outer loop, as in `calc_binding_energy`.
+pub fn vina_score(
+    distances: &[f32],
+    rec_types: &[Option<XsType>],
+    lig_types: &[Option<XsType>],
+    num_rotatable: usize,
+) -> VinaScore {
+    let mut result = VinaScore {
+        num_rotatable,
+        ..Default::default()
+    };
+
+    let len_lig = lig_types.len();
+
+    for (i_rec, rec) in rec_types.iter().enumerate() {
+        let Some(rec) = rec else {
+            continue;
+        };
+
+        for (i_lig, lig) in lig_types.iter().enumerate() {
+            let Some(lig) = lig else {
+                continue;
+            };
+
+            let r = distances[i_rec * len_lig + i_lig];
+            if r > CUTOFF {
+                continue;
+            }
+
+            let terms = pair_terms(rec, lig, r);
+            result.gauss_1 += terms[0];
+            result.gauss_2 += terms[1];
+            result.repulsion += terms[2];
+            result.hydrophobic += terms[3];
+            result.h_bond += terms[4];
+        }
+    }
+
+    let inter = W_GAUSS_1 * result.gauss_1
+        + W_GAUSS_2 * result.gauss_2
+        + W_REPULSION * result.repulsion
+        + W_HYDROPHOBIC * result.hydrophobic
+        + W_H_BOND * result.h_bond;
+
+    result.score = inter / rot_penalty(num_rotatable);
+    result
+}
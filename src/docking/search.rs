@@ -0,0 +1,406 @@
+//! Global pose search, using Monte Carlo, as in Vina. Each run starts from a random pose in the
+//! docking site: a position, orientation, and angle for each of the ligand's flexible bonds. It
+//! then perturbs one of these at a time, accepting changes with the Metropolis criterion on the
+//! Vina score, and finishes with a greedy refinement of its best pose. Runs are independent, and
+//! in parallel. We cluster their results by RMSD, and return each cluster's best pose.
+//!
+//! By default, runs score poses using precomputed grid maps of the Vina terms, which is much faster
+//! than summing over atom pairs. We then rescore each run's best pose exactly, without
+//! interpolation, and rank by that.
+
+use std::{f32::consts::TAU, sync::Arc};
+
+use lin_alg::{
+    f32::Vec3 as Vec3F32,
+    f64::{Quaternion, Vec3},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rayon::prelude::*;
+
+use crate::{
+    FfParamSet,
+    docking::{
+        ConformationType, Pose,
+        conformers::Conformer,
+        dynamics::minimize_ligand,
+        grid::{GRID_SPACING_DEFAULT, GridMaps},
+        pharmacophore::PharmConstraints,
+        prep::{DockingSetup, Torsion},
+        scoring::{VinaScore, vina_score},
+    },
+    dynamics::{MdConfig, solvent::random_orientation},
+    error::DaedalusError,
+    molecule::{Ligand, Residue},
+    progress::{Progress, Task},
+};
+
+#[derive(Clone, Debug)]
+pub struct SearchParams {
+    /// Independent Monte Carlo runs, each from a random start.
+    pub num_runs: usize,
+    pub steps_per_run: usize,
+    /// Steps of greedy refinement of each run's best pose, with smaller perturbations.
+    pub refine_steps: usize,
+    /// kcal/mol. (kT) Higher values accept more uphill moves.
+    pub temperature: f32,
+    /// Å. The largest translation in one step.
+    pub step_translation: f64,
+    /// Radians. The largest rotation, or torsion change, in one step.
+    pub step_angle: f32,
+    /// The most poses (clusters) to return.
+    pub num_poses: usize,
+    /// Å. Poses within this RMSD of a cluster's best pose join that cluster.
+    pub cluster_rmsd: f64,
+    /// If present, runs are reproducible.
+    pub seed: Option<u64>,
+    /// Å. If present, score with grid maps of this spacing during runs. If `None`, use the Vina
+    /// score throughout; this is slower.
+    pub grid_spacing: Option<f32>,
+    /// Interactions poses must make. Runs add a penalty for each they miss.
+    pub constraints: PharmConstraints,
+    /// If present, runs start from these conformers' torsions, in turn, vice random ones.
+    pub conformers: Vec<Conformer>,
+    /// Counts finished runs. If cancelled, runs not yet started are skipped.
+    pub progress: Progress,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            num_runs: 16,
+            steps_per_run: 2_000,
+            refine_steps: 300,
+            temperature: 1.2,
+            step_translation: 1.,
+            step_angle: 0.3,
+            num_poses: 9,
+            cluster_rmsd: 2.,
+            seed: None,
+            grid_spacing: Some(GRID_SPACING_DEFAULT),
+            constraints: Default::default(),
+            conformers: Vec::new(),
+            progress: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RankedPose {
+    pub pose: Pose,
+    pub score: VinaScore,
+    /// Absolute ligand atom positions at this pose.
+    pub atom_posits: Vec<Vec3>,
+    /// The number of runs whose result fell in this pose's cluster. A large population suggests
+    /// the search converged on this pose.
+    pub population: usize,
+    /// The number of pharmacophore constraints this pose doesn't satisfy.
+    pub unsatisfied: usize,
+    /// Flexible receptor atom positions, by receptor atom index, if minimized with flexible
+    /// residues.
+    pub flex_posits: Vec<(usize, Vec3)>,
+}
+
+fn posits_f32(lig: &Ligand) -> Vec<Vec3F32> {
+    lig.atom_posits.iter().map(|p| (*p).into()).collect()
+}
+
+/// Score the ligand's current atom positions.
+fn score_posits(setup: &DockingSetup, lig: &Ligand) -> VinaScore {
+    let lig_posits = posits_f32(lig);
+
+    let mut distances = Vec::with_capacity(setup.rec_atoms_near_site.len() * lig_posits.len());
+    for atom_rec in &setup.rec_atoms_near_site {
+        let posit_rec: Vec3F32 = atom_rec.posit.into();
+        for posit_lig in &lig_posits {
+            distances.push((posit_rec - *posit_lig).magnitude());
+        }
+    }
+
+    vina_score(
+        &distances,
+        &setup.rec_xs_types,
+        &setup.lig_xs_types,
+        lig.flexible_bonds.len(),
+    )
+}
+
+fn torsions(pose: &Pose) -> &[Torsion] {
+    match &pose.conformation_type {
+        ConformationType::Flexible { torsions } => torsions,
+        ConformationType::AbsolutePosits => &[],
+    }
+}
+
+/// A uniformly-random point in a ball.
+fn random_in_sphere(rng: &mut StdRng, center: Vec3, radius: f64) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+        );
+        if v.magnitude_squared() <= 1. {
+            return center + v * radius;
+        }
+    }
+}
+
+/// A random position and orientation in the site. Torsions are from `conformer` if present;
+/// otherwise, random.
+fn random_pose(rng: &mut StdRng, lig: &Ligand, conformer: Option<&Conformer>) -> Pose {
+    let site = &lig.docking_site;
+
+    let torsions = match conformer {
+        Some(c) => c.torsions.clone(),
+        None => lig
+            .flexible_bonds
+            .iter()
+            .map(|&bond| Torsion {
+                bond,
+                dihedral_angle: rng.random_range(0. ..TAU),
+            })
+            .collect(),
+    };
+
+    Pose {
+        anchor_posit: random_in_sphere(rng, site.site_center, site.site_radius),
+        orientation: random_orientation(rng),
+        conformation_type: ConformationType::Flexible { torsions },
+    }
+}
+
+/// Change one of the pose's position, orientation, or a torsion. `scale` shrinks the step sizes.
+/// Returns `None` if the move would leave the docking site.
+fn perturb(
+    rng: &mut StdRng,
+    pose: &Pose,
+    lig: &Ligand,
+    params: &SearchParams,
+    scale: f64,
+) -> Option<Pose> {
+    let mut result = pose.clone();
+    let num_torsions = torsions(pose).len();
+
+    match rng.random_range(0..2 + num_torsions) {
+        0 => {
+            let step = random_in_sphere(rng, Vec3::new_zero(), params.step_translation * scale);
+            result.anchor_posit += step;
+
+            let site = &lig.docking_site;
+            if (result.anchor_posit - site.site_center).magnitude() > site.site_radius {
+                return None;
+            }
+        }
+        1 => {
+            let axis = random_in_sphere(rng, Vec3::new_zero(), 1.).to_normalized();
+            let max = params.step_angle as f64 * scale;
+            let angle = rng.random_range(-max..=max);
+            result.orientation = Quaternion::from_axis_angle(axis, angle) * pose.orientation;
+        }
+        i => {
+            if let ConformationType::Flexible { torsions } = &mut result.conformation_type {
+                let max = params.step_angle * scale as f32;
+                let torsion = &mut torsions[i - 2];
+                torsion.dihedral_angle =
+                    (torsion.dihedral_angle + rng.random_range(-max..=max)).rem_euclid(TAU);
+            }
+        }
+    }
+
+    Some(result)
+}
+
+/// The energy runs minimize, at the ligand's current atom positions.
+fn objective(
+    setup: &DockingSetup,
+    grid: Option<&GridMaps>,
+    constraints: &PharmConstraints,
+    lig: &Ligand,
+) -> f32 {
+    let energy = match grid {
+        Some(g) => g.energy(&posits_f32(lig)),
+        None => score_posits(setup, lig).score,
+    };
+
+    let unsatisfied = constraints.unsatisfied(&lig.atom_posits, &setup.lig_xs_types);
+    energy + unsatisfied as f32 * constraints.penalty
+}
+
+/// One Monte Carlo run, followed by greedy refinement. Returns the best pose found.
+fn run(
+    setup: &DockingSetup,
+    grid: Option<&GridMaps>,
+    lig: &mut Ligand,
+    params: &SearchParams,
+    conformer: Option<&Conformer>,
+    seed: u64,
+) -> RankedPose {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut pose = random_pose(&mut rng, lig, conformer);
+    lig.position_atoms(Some(&pose));
+    let mut score = objective(setup, grid, &params.constraints, lig);
+
+    let mut best = (pose.clone(), score);
+
+    for i in 0..params.steps_per_run + params.refine_steps {
+        let refining = i >= params.steps_per_run;
+        if refining && i == params.steps_per_run {
+            (pose, score) = best.clone();
+        }
+
+        let scale = if refining { 0.25 } else { 1. };
+        let Some(candidate) = perturb(&mut rng, &pose, lig, params, scale) else {
+            continue;
+        };
+
+        lig.position_atoms(Some(&candidate));
+        let score_new = objective(setup, grid, &params.constraints, lig);
+
+        let delta = score_new - score;
+        let accept =
+            delta < 0. || (!refining && rng.random::<f32>() < (-delta / params.temperature).exp());
+
+        if accept {
+            pose = candidate;
+            score = score_new;
+
+            if score < best.1 {
+                best = (pose.clone(), score);
+            }
+        }
+    }
+
+    lig.position_atoms(Some(&best.0));
+    RankedPose {
+        pose: best.0,
+        score: score_posits(setup, lig),
+        atom_posits: lig.atom_posits.clone(),
+        population: 1,
+        flex_posits: Vec::new(),
+        unsatisfied: params
+            .constraints
+            .unsatisfied(&lig.atom_posits, &setup.lig_xs_types),
+    }
+}
+
+/// Å. Between two conformations of the same molecule, without superposition.
+pub fn rmsd(a: &[Vec3], b: &[Vec3]) -> f64 {
+    if a.is_empty() {
+        return 0.;
+    }
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (*a - *b).magnitude_squared())
+        .sum();
+    (sum / a.len() as f64).sqrt()
+}
+
+/// Search for low-scoring ligand poses in its docking site. Returns up to `params.num_poses`
+/// distinct poses, best (lowest score) first. Doesn't change `ligand`.
+pub fn search_poses(
+    setup: &DockingSetup,
+    ligand: &Ligand,
+    params: &SearchParams,
+) -> Vec<RankedPose> {
+    let seed_base = params.seed.unwrap_or_else(|| rand::rng().random());
+
+    params.progress.set_total(params.num_runs);
+    params.progress.set_stage("Grid maps");
+    let grid = params
+        .grid_spacing
+        .map(|spacing| GridMaps::new(setup, ligand, spacing));
+
+    params.progress.set_stage("Runs");
+    let mut results: Vec<_> = (0..params.num_runs)
+        .into_par_iter()
+        .filter_map(|i| {
+            if params.progress.is_cancelled() {
+                return None;
+            }
+
+            let mut lig = ligand.clone();
+            let result = run(
+                setup,
+                grid.as_ref(),
+                &mut lig,
+                params,
+                params.conformers.get(i % params.conformers.len().max(1)),
+                seed_base.wrapping_add(i as u64),
+            );
+
+            params.progress.inc();
+            Some(result)
+        })
+        .collect();
+
+    if params.constraints.required {
+        results.retain(|r| r.unsatisfied == 0);
+    }
+
+    let mut clusters = cluster_poses(results, params.cluster_rmsd);
+    clusters.truncate(params.num_poses);
+    clusters
+}
+
+/// As `search_poses`, in a new thread, using the task's progress in place of `params.progress`.
+pub fn start_pose_search(
+    setup: Arc<DockingSetup>,
+    ligand: Ligand,
+    mut params: SearchParams,
+) -> Task<Vec<RankedPose>> {
+    Task::spawn(move |progress| {
+        params.progress = progress.clone();
+        search_poses(&setup, &ligand, &params)
+    })
+}
+
+/// Cluster poses greedily, best first: each pose joins the first cluster whose representative is
+/// within `rmsd_thresh`, or starts a new one. Returns the representatives, with their cluster
+/// populations, best first.
+pub fn cluster_poses(mut poses: Vec<RankedPose>, rmsd_thresh: f64) -> Vec<RankedPose> {
+    poses.sort_by(|a, b| a.score.score.total_cmp(&b.score.score));
+
+    let mut result: Vec<RankedPose> = Vec::new();
+    for pose in poses {
+        match result
+            .iter_mut()
+            .find(|c| rmsd(&c.atom_posits, &pose.atom_posits) < rmsd_thresh)
+        {
+            Some(cluster) => cluster.population += pose.population,
+            None => result.push(pose),
+        }
+    }
+
+    result
+}
+
+/// Minimize each pose in the receptor's field, using the MD force field, then rescore and re-rank
+/// them. This relaxes bond lengths and angles, which the search holds fixed, and clears small
+/// clashes. Flexible residues in `cfg` are minimized too. Poses become absolute positions, vice
+/// torsions.
+pub fn minimize_poses(
+    poses: &mut Vec<RankedPose>,
+    ligand: &Ligand,
+    setup: &DockingSetup,
+    ff_params: &FfParamSet,
+    residues: &[Residue],
+    cfg: &MdConfig,
+) -> Result<(), DaedalusError> {
+    for ranked in poses.iter_mut() {
+        let mut lig = ligand.clone();
+        lig.pose = ranked.pose.clone();
+        lig.atom_posits = ranked.atom_posits.clone();
+
+        let (_, flex_posits) = minimize_ligand(&mut lig, setup, ff_params, residues, cfg)?;
+
+        ranked.pose = lig.pose.clone();
+        ranked.score = score_posits(setup, &lig);
+        ranked.atom_posits = lig.atom_posits;
+        ranked.flex_posits = flex_posits;
+    }
+
+    poses.sort_by(|a, b| a.score.score.total_cmp(&b.score.score));
+    Ok(())
+}
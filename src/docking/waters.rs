@@ -0,0 +1,108 @@
+//! Crystallographic waters in the docking site. Waters that bridge the ligand and receptor through
+//! hydrogen bonds often dominate binding, so docking without them can miss the correct pose. We
+//! flag waters likely to be conserved, and let the user keep any of them as part of the receptor.
+
+use na_seq::Element::{Nitrogen, Oxygen};
+
+use crate::{
+    docking::DockingSite,
+    molecule::{AtomRole, Molecule},
+};
+
+/// Å. Between the water oxygen and a receptor N or O.
+const H_BOND_DIST_MAX: f64 = 3.5;
+/// Waters with at least this many hydrogen bonds to the receptor are considered conserved.
+const CONSERVED_H_BONDS_MIN: usize = 2;
+
+#[derive(Clone, Debug)]
+pub struct SiteWater {
+    /// The oxygen's index in the receptor's atoms.
+    pub atom: usize,
+    /// With receptor (non-water) N and O atoms.
+    pub h_bonds: usize,
+    pub b_factor: Option<f32>,
+    /// Tightly bound, per our heuristic: enough hydrogen bonds, and a B factor no higher than the
+    /// site's receptor atoms' average.
+    pub conserved: bool,
+}
+
+impl SiteWater {
+    pub fn descrip(&self, mol: &Molecule) -> String {
+        // Fall back to the index, e.g. if a different molecule has been opened since.
+        let serial = mol
+            .atoms
+            .get(self.atom)
+            .map(|a| a.serial_number)
+            .unwrap_or(self.atom);
+        match self.b_factor {
+            Some(b) => format!("HOH #{serial}: {} H bonds, B {b:.1}", self.h_bonds),
+            None => format!("HOH #{serial}: {} H bonds", self.h_bonds),
+        }
+    }
+}
+
+/// Find waters in the docking site.
+pub fn find_site_waters(mol: &Molecule, site: &DockingSite) -> Vec<SiteWater> {
+    let in_site =
+        |i: usize| (mol.atoms[i].posit - site.site_center).magnitude() <= site.site_radius;
+
+    let is_water = |i: usize| mol.atoms[i].role == Some(AtomRole::Water);
+
+    // Mean B factor of receptor atoms in the site, for comparison.
+    let b_factors: Vec<_> = (0..mol.atoms.len())
+        .filter(|i| !is_water(*i) && in_site(*i))
+        .filter_map(|i| mol.atoms[i].temperature_factor)
+        .collect();
+    let b_mean = if b_factors.is_empty() {
+        None
+    } else {
+        Some(b_factors.iter().sum::<f32>() / b_factors.len() as f32)
+    };
+
+    (0..mol.atoms.len())
+        .filter(|i| is_water(*i) && mol.atoms[*i].element == Oxygen && in_site(*i))
+        .map(|i| {
+            let posit = mol.atoms[i].posit;
+            let h_bonds = mol
+                .atoms
+                .iter()
+                .enumerate()
+                .filter(|(j, a)| {
+                    !is_water(*j)
+                        && matches!(a.element, Nitrogen | Oxygen)
+                        && (a.posit - posit).magnitude() <= H_BOND_DIST_MAX
+                })
+                .count();
+
+            let b_factor = mol.atoms[i].temperature_factor;
+            let b_ok = match (b_factor, b_mean) {
+                (Some(b), Some(mean)) => b <= mean,
+                _ => true,
+            };
+
+            SiteWater {
+                atom: i,
+                h_bonds,
+                b_factor,
+                conserved: h_bonds >= CONSERVED_H_BONDS_MIN && b_ok,
+            }
+        })
+        .collect()
+}
+
+/// All atoms of the waters whose oxygens are listed, i.e. including hydrogens, if present.
+pub fn water_atoms(mol: &Molecule, oxygens: &[usize]) -> Vec<usize> {
+    let mut result = Vec::new();
+
+    for &o in oxygens {
+        let Some(atom) = mol.atoms.get(o) else {
+            continue;
+        };
+        match atom.residue.and_then(|r| mol.residues.get(r)) {
+            Some(res) => result.extend(res.atoms.iter().copied()),
+            None => result.push(o),
+        }
+    }
+
+    result
+}
@@ -2,6 +2,7 @@
 
 use bio_apis::{ReqError, drugbank, pubchem, rcsb};
 use bio_files::Mol2;
+use log::error;
 use pdbtbx::PDB;
 
 use crate::{file_io::cif_pdb::read_pdb, molecule::Molecule};
@@ -11,7 +12,7 @@ pub fn load_cif_rcsb(ident: &str) -> Result<(PDB, String), ReqError> {
     let cif_data = rcsb::load_cif(ident)?;
 
     let pdb = read_pdb(&cif_data).map_err(|e| {
-        eprintln!("Error parsing mmCIF file: {e}");
+        error!("Error parsing mmCIF file: {e}");
         e
     });
 
@@ -1,23 +1,52 @@
 //! For water molecules, the sim box, thermostat etc.
 
+use bincode::{Decode, Encode};
 use lin_alg::f64::{Quaternion, Vec3};
 use na_seq::Element;
 
 use crate::dynamics::{AtomDynamics, MdState};
 
 /// Simulation cell (orthorhombic for now)
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Debug, Default, Encode, Decode)]
 pub struct SimBox {
     pub lo: Vec3,
     pub hi: Vec3,
 }
 
 impl SimBox {
+    /// The box bounding `posits`, padded by `pad` Å on each side.
+    pub fn around(posits: impl Iterator<Item = Vec3>, pad: f64) -> Self {
+        let (mut min, mut max) = (Vec3::splat(f64::INFINITY), Vec3::splat(f64::NEG_INFINITY));
+        for p in posits {
+            min = min.min(p);
+            max = max.max(p);
+        }
+
+        Self {
+            lo: min - Vec3::splat(pad),
+            hi: max + Vec3::splat(pad),
+        }
+    }
+
     #[inline]
     pub fn extent(&self) -> Vec3 {
         self.hi - self.lo
     }
 
+    /// Å³
+    #[inline]
+    pub fn volume(&self) -> f64 {
+        let ext = self.extent();
+        ext.x * ext.y * ext.z
+    }
+
+    /// Scale the box about its center, by `factor` along each axis.
+    pub fn scale(&mut self, factor: f64) {
+        let center = (self.lo + self.hi) * 0.5;
+        self.lo = center + (self.lo - center) * factor;
+        self.hi = center + (self.hi - center) * factor;
+    }
+
     /// wrap an absolute coordinate back into the box (orthorhombic)
     #[inline]
     pub fn wrap(&self, p: Vec3) -> Vec3 {
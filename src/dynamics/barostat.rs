@@ -0,0 +1,181 @@
+//! Monte Carlo barostat, for sampling at constant pressure (NPT). Every few steps, we propose a
+//! random change to the box volume, scale molecule centers with the box, and accept or reject the
+//! move with the Metropolis criterion. Molecules are scaled as rigid bodies, so bonds don't
+//! stretch. [Chow and Ferguson, 1995](https://doi.org/10.1016/0010-4655(95)00059-O);
+//! [Åqvist et al, 2004](https://doi.org/10.1016/j.cplett.2003.12.039)
+//!
+//! This only samples the volume; pair it with a thermostat. It's mainly useful with explicit
+//! solvent, where the box is filled. Static atoms, e.g. a rigid receptor, don't move.
+
+use lin_alg::f64::Vec3;
+use rand::Rng;
+
+use crate::{
+    dynamics::MdState,
+    units::{ACCEL_CONV, BAR_A3_TO_KCAL_MOL, K_B},
+};
+
+/// Attempts between adjustments of the maximum volume change.
+const ADAPT_INTERVAL: usize = 10;
+/// We adjust the maximum volume change to keep the acceptance rate in this range.
+const ACCEPTANCE_MIN: f64 = 0.25;
+const ACCEPTANCE_MAX: f64 = 0.75;
+
+#[derive(Clone, Debug)]
+pub struct BarostatParams {
+    /// bar
+    pub pressure: f64,
+    /// Attempt a volume change every this many steps.
+    pub frequency: usize,
+}
+
+impl Default for BarostatParams {
+    fn default() -> Self {
+        Self {
+            pressure: 1.013_25,
+            frequency: 25,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BarostatState {
+    pub params: BarostatParams,
+    /// Å³. Adapted as we go.
+    volume_step_max: f64,
+    attempted: usize,
+    accepted: usize,
+    /// Atom indices of each molecule; connected components of the bond graph.
+    molecules: Vec<Vec<usize>>,
+}
+
+impl BarostatState {
+    pub fn new(params: BarostatParams, md: &MdState) -> Self {
+        Self {
+            params,
+            volume_step_max: 0.01 * md.cell.volume(),
+            attempted: 0,
+            accepted: 0,
+            molecules: molecules(&md.adjacency_list, md.atoms.len()),
+        }
+    }
+}
+
+/// Group atoms into molecules, by walking bonds.
+fn molecules(adjacency_list: &[Vec<usize>], num_atoms: usize) -> Vec<Vec<usize>> {
+    let mut result = Vec::new();
+    let mut visited = vec![false; num_atoms];
+
+    for start in 0..num_atoms {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+
+        let mut mol = Vec::new();
+        let mut stack = vec![start];
+
+        while let Some(i) = stack.pop() {
+            mol.push(i);
+            for &j in adjacency_list.get(i).into_iter().flatten() {
+                if j < num_atoms && !visited[j] {
+                    visited[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        result.push(mol);
+    }
+
+    result
+}
+
+impl MdState {
+    /// Attempt a volume change, if one is due this step. Call after the step's forces, and
+    /// `energy_potential`, are current.
+    ///
+    /// Note: With GaMD, the accelerations from an accepted move aren't boosted until the next step.
+    pub(super) fn apply_barostat(&mut self) {
+        let Some(mut baro) = self.barostat.take() else {
+            return;
+        };
+
+        if baro.params.frequency > 0 && self.step_count % baro.params.frequency == 0 {
+            self.attempt_volume_change(&mut baro);
+        }
+
+        self.barostat = Some(baro);
+    }
+
+    fn attempt_volume_change(&mut self, baro: &mut BarostatState) {
+        let mut rng = rand::rng();
+
+        let volume = self.cell.volume();
+        let volume_new = volume + baro.volume_step_max * rng.random_range(-1.0..1.0);
+        if volume_new <= 0. {
+            return;
+        }
+        let factor = (volume_new / volume).cbrt();
+
+        baro.attempted += 1;
+
+        let posits_prev: Vec<_> = self.atoms.iter().map(|a| a.posit).collect();
+        let accels_prev: Vec<_> = self.atoms.iter().map(|a| a.accel).collect();
+        let cell_prev = self.cell;
+
+        let center = (self.cell.lo + self.cell.hi) * 0.5;
+        for mol in &baro.molecules {
+            // Atoms may be wrapped to opposite sides of the cell; unwrap relative to the first.
+            let anchor = self.atoms[mol[0]].posit;
+            let mut mass = 0.;
+            let mut com = Vec3::new_zero();
+            for &i in mol {
+                let posit = anchor + self.cell.min_image(self.atoms[i].posit - anchor);
+                com += posit * self.atoms[i].mass;
+                mass += self.atoms[i].mass;
+            }
+            com = com / mass;
+
+            let shift = (com - center) * (factor - 1.);
+            for &i in mol {
+                self.atoms[i].posit += shift;
+            }
+        }
+        self.cell.scale(factor);
+
+        let (energy_new, forces) = self.potential_and_forces();
+
+        let kt = K_B * self.target_temp;
+        // Enthalpy change, less the entropic term from scaling molecule centers.
+        let w = energy_new - self.energy_potential
+            + baro.params.pressure * BAR_A3_TO_KCAL_MOL * (volume_new - volume)
+            - baro.molecules.len() as f64 * kt * (volume_new / volume).ln();
+
+        if w <= 0. || rng.random::<f64>() < (-w / kt).exp() {
+            baro.accepted += 1;
+            self.energy_potential = energy_new;
+
+            for (a, f) in self.atoms.iter_mut().zip(forces) {
+                a.accel = f / a.mass * ACCEL_CONV;
+            }
+            self.build_neighbours();
+        } else {
+            for ((a, posit), accel) in self.atoms.iter_mut().zip(posits_prev).zip(accels_prev) {
+                a.posit = posit;
+                a.accel = accel;
+            }
+            self.cell = cell_prev;
+        }
+
+        if baro.attempted >= ADAPT_INTERVAL {
+            let rate = baro.accepted as f64 / baro.attempted as f64;
+            if rate < ACCEPTANCE_MIN {
+                baro.volume_step_max /= 1.1;
+            } else if rate > ACCEPTANCE_MAX {
+                baro.volume_step_max = (baro.volume_step_max * 1.1).min(0.3 * volume);
+            }
+            baro.attempted = 0;
+            baro.accepted = 0;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Linked-cell grid, for building neighbor lists in O(N). We bin atoms into cells over the sim box,
+//! with sides at least the search radius; each atom's neighbors are then in its own cell, or one of
+//! the 26 adjacent ones, taking periodic boundaries into account.
+
+use lin_alg::f64::Vec3;
+
+use crate::dynamics::ambient::SimBox;
+
+pub(super) struct CellGrid {
+    /// Cells along each axis.
+    dims: [usize; 3],
+    /// Atom indices in each cell. Flat, x-major.
+    cells: Vec<Vec<usize>>,
+}
+
+impl CellGrid {
+    pub fn new(cell: &SimBox, posits: impl Iterator<Item = Vec3>, radius: f64) -> Self {
+        let ext = cell.extent();
+        let dims = [ext.x, ext.y, ext.z].map(|len| ((len / radius).floor() as usize).max(1));
+
+        let mut result = Self {
+            dims,
+            cells: vec![Vec::new(); dims[0] * dims[1] * dims[2]],
+        };
+
+        for (i, p) in posits.enumerate() {
+            let idx = result.index(cell, p);
+            result.cells[result.flat(idx)].push(i);
+        }
+
+        result
+    }
+
+    /// The cell containing a position. Positions outside the box map to the cell of their periodic
+    /// image.
+    pub fn index(&self, cell: &SimBox, p: Vec3) -> [usize; 3] {
+        let rel = cell.wrap(p) - cell.lo;
+        let ext = cell.extent();
+
+        let axis = |v: f64, len: f64, n: usize| ((v / len * n as f64) as usize).min(n - 1);
+        [
+            axis(rel.x, ext.x, self.dims[0]),
+            axis(rel.y, ext.y, self.dims[1]),
+            axis(rel.z, ext.z, self.dims[2]),
+        ]
+    }
+
+    fn flat(&self, idx: [usize; 3]) -> usize {
+        (idx[0] * self.dims[1] + idx[1]) * self.dims[2] + idx[2]
+    }
+
+    /// Atom indices in the cell at `idx`, and its neighbors. Each cell is visited once, even when
+    /// there are fewer than 3 cells along an axis.
+    pub fn neighbors(&self, idx: [usize; 3]) -> impl Iterator<Item = usize> + '_ {
+        // Distinct cells along each axis, wrapping around the box.
+        let axis = |i: usize, n: usize| {
+            let mut result = Vec::with_capacity(3);
+            for d in [n - 1, 0, 1] {
+                let j = (i + d) % n;
+                if !result.contains(&j) {
+                    result.push(j);
+                }
+            }
+            result
+        };
+
+        let (xs, ys, zs) = (
+            axis(idx[0], self.dims[0]),
+            axis(idx[1], self.dims[1]),
+            axis(idx[2], self.dims[2]),
+        );
+
+        let mut cells = Vec::with_capacity(27);
+        for &x in &xs {
+            for &y in &ys {
+                for &z in &zs {
+                    cells.push(self.flat([x, y, z]));
+                }
+            }
+        }
+
+        cells
+            .into_iter()
+            .flat_map(move |c| self.cells[c].iter().copied())
+    }
+}
@@ -0,0 +1,405 @@
+//! CMAP corrections: tabulated energies over a residue's backbone φ and ψ dihedrals, added to the
+//! bonded terms. ff19SB's dihedral parameters were fit along with its maps; without them, backbone
+//! conformational preferences are wrong. There is one map per residue type, or group of types.
+//!
+//! Maps are periodic grids, which we interpolate bicubically. As in Amber and CHARMM, derivatives at
+//! grid points come from periodic cubic splines.
+//!
+//! [MacKerell et al, 2004](https://doi.org/10.1021/ja036959e)
+//! [ff19SB](https://doi.org/10.1021/acs.jctc.9b00591)
+
+use std::f64::consts::{PI, TAU};
+
+use bio_files::ResidueType;
+use lin_alg::f64::Vec3;
+use na_seq::AaIdent;
+
+use crate::{
+    dynamics::{MdState, ParamError, dihedral_grad, split4_mut},
+    error::DaedalusError,
+    molecule::{Atom, AtomRole, Residue},
+};
+
+/// Iterations for solving the periodic spline system. Each one reduces the error by at least half.
+const SPLINE_ITERS: usize = 60;
+
+/// A correction map over (φ, ψ), in kcal/mol.
+#[derive(Clone, Debug)]
+pub struct CmapGrid {
+    /// Residue names this map applies to, e.g. "ALA", or "HIE".
+    pub residues: Vec<String>,
+    /// Points per axis. Both axes start at -180°.
+    pub resolution: usize,
+    /// kcal/mol. φ-major: index `i_φ * resolution + i_ψ`.
+    pub values: Vec<f64>,
+    /// Bicubic coefficients of each cell, in the same order as `values`, over cell coordinates in
+    /// [0, 1): `E = Σ c[i][j] tⁱ uʲ`.
+    coeffs: Vec<[[f64; 4]; 4]>,
+}
+
+impl CmapGrid {
+    pub fn new(
+        residues: Vec<String>,
+        resolution: usize,
+        values: Vec<f64>,
+    ) -> Result<Self, ParamError> {
+        let n = resolution;
+        if n < 4 || values.len() != n * n {
+            return Err(ParamError::new(&format!(
+                "CMAP grid has {} values; expected {n}²",
+                values.len()
+            )));
+        }
+
+        let at = |v: &[f64], i: usize, j: usize| v[(i % n) * n + j % n];
+
+        // Derivatives, in grid units: along φ, along ψ, and the cross term.
+        let mut d_φ = vec![0.; n * n];
+        let mut d_ψ = vec![0.; n * n];
+        for k in 0..n {
+            let col: Vec<_> = (0..n).map(|i| values[i * n + k]).collect();
+            for (i, d) in spline_derivs(&col).into_iter().enumerate() {
+                d_φ[i * n + k] = d;
+            }
+            d_ψ[k * n..(k + 1) * n].copy_from_slice(&spline_derivs(&values[k * n..(k + 1) * n]));
+        }
+        let mut d_φψ = vec![0.; n * n];
+        for i in 0..n {
+            d_φψ[i * n..(i + 1) * n].copy_from_slice(&spline_derivs(&d_φ[i * n..(i + 1) * n]));
+        }
+
+        // p(t, u) = [1 t t² t³] M F Mᵀ [1 u u² u³]ᵀ, with F the corner values and derivatives.
+        const M: [[f64; 4]; 4] = [
+            [1., 0., 0., 0.],
+            [0., 0., 1., 0.],
+            [-3., 3., -2., -1.],
+            [2., -2., 1., 1.],
+        ];
+
+        let mut coeffs = Vec::with_capacity(n * n);
+        for i in 0..n {
+            for j in 0..n {
+                let f = [
+                    [
+                        at(&values, i, j),
+                        at(&values, i, j + 1),
+                        at(&d_ψ, i, j),
+                        at(&d_ψ, i, j + 1),
+                    ],
+                    [
+                        at(&values, i + 1, j),
+                        at(&values, i + 1, j + 1),
+                        at(&d_ψ, i + 1, j),
+                        at(&d_ψ, i + 1, j + 1),
+                    ],
+                    [
+                        at(&d_φ, i, j),
+                        at(&d_φ, i, j + 1),
+                        at(&d_φψ, i, j),
+                        at(&d_φψ, i, j + 1),
+                    ],
+                    [
+                        at(&d_φ, i + 1, j),
+                        at(&d_φ, i + 1, j + 1),
+                        at(&d_φψ, i + 1, j),
+                        at(&d_φψ, i + 1, j + 1),
+                    ],
+                ];
+
+                let mut c = [[0.; 4]; 4];
+                for (a, c_row) in c.iter_mut().enumerate() {
+                    for (b, c_ab) in c_row.iter_mut().enumerate() {
+                        for k in 0..4 {
+                            for l in 0..4 {
+                                *c_ab += M[a][k] * f[k][l] * M[b][l];
+                            }
+                        }
+                    }
+                }
+                coeffs.push(c);
+            }
+        }
+
+        Ok(Self {
+            residues,
+            resolution,
+            values,
+            coeffs,
+        })
+    }
+
+    /// Bicubic coefficients of each cell, φ-major. See `coeffs`.
+    pub(super) fn cell_coeffs(&self) -> &[[[f64; 4]; 4]] {
+        &self.coeffs
+    }
+
+    /// Energy, in kcal/mol, and its derivatives with respect to φ and ψ, in kcal/mol/rad. Angles
+    /// are in radians.
+    pub fn energy(&self, φ: f64, ψ: f64) -> (f64, f64, f64) {
+        let n = self.resolution;
+        let spacing = TAU / n as f64;
+
+        let (i, t) = cell_coord(φ, spacing, n);
+        let (j, u) = cell_coord(ψ, spacing, n);
+        let c = &self.coeffs[i * n + j];
+
+        let (mut e, mut de_dt, mut de_du) = (0., 0., 0.);
+        for a in (0..4).rev() {
+            let (mut row, mut row_du) = (0., 0.);
+            for b in (0..4).rev() {
+                row = row * u + c[a][b];
+                if b > 0 {
+                    row_du = row_du * u + b as f64 * c[a][b];
+                }
+            }
+            e = e * t + row;
+            de_du = de_du * t + row_du;
+            if a > 0 {
+                de_dt = de_dt * t + a as f64 * row;
+            }
+        }
+
+        (e, de_dt / spacing, de_du / spacing)
+    }
+}
+
+/// The cell index along an axis, and the position in it, from 0 to 1.
+fn cell_coord(angle: f64, spacing: f64, n: usize) -> (usize, f64) {
+    let x = (angle + PI).rem_euclid(TAU) / spacing;
+    let i = (x.floor() as usize).min(n - 1);
+    (i, x - i as f64)
+}
+
+/// First derivatives of a periodic cubic spline through `vals`, at unit spacing. These satisfy
+/// d[i-1] + 4d[i] + d[i+1] = 3(v[i+1] - v[i-1]); the system is diagonally dominant, so we solve it
+/// with Gauss-Seidel iterations.
+fn spline_derivs(vals: &[f64]) -> Vec<f64> {
+    let n = vals.len();
+    let rhs: Vec<_> = (0..n)
+        .map(|i| 3. * (vals[(i + 1) % n] - vals[(i + n - 1) % n]))
+        .collect();
+
+    let mut d = vec![0.; n];
+    for _ in 0..SPLINE_ITERS {
+        for i in 0..n {
+            d[i] = (rhs[i] - d[(i + n - 1) % n] - d[(i + 1) % n]) / 4.;
+        }
+    }
+    d
+}
+
+/// Parse CMAP maps from an Amber parameter file, e.g. `frcmod.ff19SB`: `%FLAG CMAP_COUNT` starts
+/// each map, followed by its `CMAP_RESLIST`, `CMAP_RESOLUTION`, and `CMAP_PARAMETER` values. Other
+/// flags are ignored. Returns an empty set if there are no maps.
+pub fn parse_cmaps(text: &str) -> Result<Vec<CmapGrid>, DaedalusError> {
+    struct Pending {
+        residues: Vec<String>,
+        resolution: usize,
+        values: Vec<f64>,
+    }
+
+    let mut result = Vec::new();
+    let mut pending: Option<Pending> = None;
+    let mut field = "";
+
+    let finish =
+        |pending: Option<Pending>, result: &mut Vec<CmapGrid>| -> Result<(), DaedalusError> {
+            if let Some(p) = pending {
+                result.push(CmapGrid::new(p.residues, p.resolution, p.values)?);
+            }
+            Ok(())
+        };
+
+    for line in text.lines() {
+        // Comments start with `!`.
+        let line = line.split('!').next().unwrap_or_default().trim();
+
+        if let Some(flag) = line.strip_prefix("%FLAG") {
+            let mut parts = flag.split_whitespace();
+            field = parts.next().unwrap_or_default();
+
+            match field {
+                "CMAP_COUNT" => {
+                    finish(pending.take(), &mut result)?;
+                    pending = Some(Pending {
+                        residues: Vec::new(),
+                        resolution: 0,
+                        values: Vec::new(),
+                    });
+                }
+                "CMAP_RESOLUTION" => {
+                    if let Some(p) = &mut pending {
+                        p.resolution =
+                            parts.next().and_then(|r| r.parse().ok()).ok_or_else(|| {
+                                DaedalusError::Parse("Invalid CMAP resolution".to_owned())
+                            })?;
+                    }
+                }
+                _ => (),
+            }
+            continue;
+        }
+
+        if line.is_empty() || line.starts_with('%') {
+            continue;
+        }
+
+        let Some(p) = &mut pending else {
+            continue;
+        };
+
+        match field {
+            "CMAP_RESLIST" => {
+                // The first line may be a count.
+                p.residues.extend(
+                    line.split_whitespace()
+                        .filter(|t| t.parse::<usize>().is_err())
+                        .map(|t| t.to_uppercase()),
+                );
+            }
+            "CMAP_PARAMETER" => {
+                for token in line.split_whitespace() {
+                    match token.parse() {
+                        Ok(v) => p.values.push(v),
+                        Err(_) => {
+                            // The end of the CMAP section.
+                            field = "";
+                            break;
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    finish(pending, &mut result)?;
+
+    Ok(result)
+}
+
+/// One residue's correction: over φ = C(i-1)-N-CA-C, and ψ = N-CA-C-N(i+1).
+#[derive(Clone, Debug)]
+pub struct CmapTerm {
+    /// C(i-1), N, CA, C, N(i+1).
+    pub atoms: [usize; 5],
+    /// Index into `MdState::cmap_grids`.
+    pub grid: usize,
+}
+
+/// Find CMAP terms for amino acid residues with a map in `grids`. `atoms` and `adjacency_list` index
+/// the simulated atoms; their residue indices are into `residues`. Terminal residues, missing φ or
+/// ψ, are skipped.
+pub fn find_cmap_terms(
+    atoms: &[Atom],
+    adjacency_list: &[Vec<usize>],
+    residues: &[Residue],
+    grids: &[CmapGrid],
+) -> Vec<CmapTerm> {
+    let mut result = Vec::new();
+    if grids.is_empty() {
+        return result;
+    }
+
+    // A bonded neighbor of `i`, with this role, in or out of `i`'s residue.
+    let neighbor = |i: usize, role: AtomRole, same_res: bool| {
+        adjacency_list[i].iter().copied().find(|&j| {
+            atoms[j].role == Some(role) && (atoms[j].residue == atoms[i].residue) == same_res
+        })
+    };
+
+    for (ca, atom) in atoms.iter().enumerate() {
+        if atom.role != Some(AtomRole::C_Alpha) {
+            continue;
+        }
+        let Some(res) = atom.residue.and_then(|r| residues.get(r)) else {
+            continue;
+        };
+        let ResidueType::AminoAcid(aa) = &res.res_type else {
+            continue;
+        };
+
+        let name = match res.variant {
+            Some(v) => format!("{v:?}").to_uppercase(),
+            None => aa.to_str(AaIdent::ThreeLetters).to_uppercase(),
+        };
+        let Some(grid) = grids.iter().position(|g| g.residues.contains(&name)) else {
+            continue;
+        };
+
+        let (Some(n), Some(c)) = (
+            neighbor(ca, AtomRole::N_Backbone, true),
+            neighbor(ca, AtomRole::C_Prime, true),
+        ) else {
+            continue;
+        };
+        let (Some(c_prev), Some(n_next)) = (
+            neighbor(n, AtomRole::C_Prime, false),
+            neighbor(c, AtomRole::N_Backbone, false),
+        ) else {
+            continue;
+        };
+
+        result.push(CmapTerm {
+            atoms: [c_prev, n, ca, c, n_next],
+            grid,
+        });
+    }
+
+    result
+}
+
+impl MdState {
+    /// Energy, in kcal/mol, and the force on each of the term's atoms, in kcal/mol/Å.
+    fn cmap_term(&self, term: &CmapTerm) -> Option<(f64, [Vec3; 5])> {
+        // Bonded atoms may be wrapped to opposite sides of the cell.
+        let mut r = [Vec3::new_zero(); 5];
+        r[0] = self.atoms[term.atoms[0]].posit;
+        for k in 1..5 {
+            r[k] = r[k - 1]
+                + self
+                    .cell
+                    .min_image(self.atoms[term.atoms[k]].posit - r[k - 1]);
+        }
+
+        let (φ, dφ) = dihedral_grad([r[0], r[1], r[2], r[3]])?;
+        let (ψ, dψ) = dihedral_grad([r[1], r[2], r[3], r[4]])?;
+
+        let (e, de_dφ, de_dψ) = self.cmap_grids[term.grid].energy(φ, ψ);
+
+        let mut forces = [Vec3::new_zero(); 5];
+        for k in 0..4 {
+            forces[k] -= dφ[k] * de_dφ;
+            forces[k + 1] -= dψ[k] * de_dψ;
+        }
+
+        Some((e, forces))
+    }
+
+    /// Returns potential energy, in kcal/mol.
+    pub(super) fn apply_cmap_forces(&mut self) -> f64 {
+        let mut energy = 0.;
+
+        for i in 0..self.cmap_terms.len() {
+            let term = &self.cmap_terms[i];
+            let Some((e, forces)) = self.cmap_term(term) else {
+                continue;
+            };
+            energy += e;
+
+            let atoms = term.atoms;
+            let (a_0, a_1, a_2, a_3) =
+                split4_mut(&mut self.atoms, atoms[0], atoms[1], atoms[2], atoms[3]);
+            a_0.accel += forces[0] / a_0.mass;
+            a_1.accel += forces[1] / a_1.mass;
+            a_2.accel += forces[2] / a_2.mass;
+            a_3.accel += forces[3] / a_3.mass;
+
+            let a_4 = &mut self.atoms[atoms[4]];
+            a_4.accel += forces[4] / a_4.mass;
+        }
+
+        // Dihedrals don't change under uniform scaling, so these don't contribute to the virial.
+        self.report_bonded(energy, 0.);
+        energy
+    }
+}
@@ -0,0 +1,344 @@
+//! Collective variables (CVs): Scalar functions of atom positions that describe a process of
+//! interest, e.g. a distance between two domains, or a torsion angle. Each provides its value, and
+//! its gradient with respect to the positions of the atoms involved.
+//!
+//! Restraints, steering, and enhanced-sampling biases are built on these, so they work with any CV.
+//! Forces from a bias potential V(ξ) are -dV/dξ · ∇ξ.
+
+use std::f64::consts::TAU;
+
+use lin_alg::f64::Vec3;
+
+use crate::dynamics::EPS;
+
+/// Default switching-function parameters for the coordination number, as in PLUMED.
+pub const COORD_N_DEFAULT: i32 = 6;
+pub const COORD_M_DEFAULT: i32 = 12;
+
+/// Gradient of a CV: (atom index, dξ/dr) for each atom involved. Atoms may repeat.
+pub type CvGradient = Vec<(usize, Vec3)>;
+
+pub trait ColVar {
+    /// The CV's value, and its gradient with respect to the atom positions.
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient);
+
+    fn value(&self, posits: &[Vec3]) -> f64 {
+        self.eval(posits).0
+    }
+
+    /// The period, for CVs that wrap, e.g. dihedral angles. Used for taking differences.
+    fn period(&self) -> Option<f64> {
+        None
+    }
+
+    /// The difference `a - b`, accounting for periodicity.
+    fn diff(&self, a: f64, b: f64) -> f64 {
+        let d = a - b;
+        match self.period() {
+            Some(p) => d - p * (d / p).round(),
+            None => d,
+        }
+    }
+}
+
+/// Distance between two atoms. Å
+#[derive(Clone, Debug)]
+pub struct Distance {
+    pub atoms: (usize, usize),
+}
+
+impl ColVar for Distance {
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let (i, j) = self.atoms;
+        let diff = posits[j] - posits[i];
+        let dist = diff.magnitude();
+        if dist < EPS {
+            return (dist, Vec::new());
+        }
+
+        let u = diff / dist;
+        (dist, vec![(i, -u), (j, u)])
+    }
+}
+
+/// The angle between three atoms, with the second as the vertex. Radians.
+#[derive(Clone, Debug)]
+pub struct Angle {
+    pub atoms: (usize, usize, usize),
+}
+
+impl ColVar for Angle {
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let (i, j, k) = self.atoms;
+        let u = posits[i] - posits[j];
+        let v = posits[k] - posits[j];
+        let (u_len, v_len) = (u.magnitude(), v.magnitude());
+        if u_len < EPS || v_len < EPS {
+            return (0., Vec::new());
+        }
+
+        let (u_hat, v_hat) = (u / u_len, v / v_len);
+        let cos_θ = u_hat.dot(v_hat).clamp(-1., 1.);
+        let θ = cos_θ.acos();
+
+        let sin_θ = (1. - cos_θ * cos_θ).sqrt();
+        // The gradient is undefined for linear arrangements.
+        if sin_θ < EPS {
+            return (θ, Vec::new());
+        }
+
+        let g_i = (u_hat * cos_θ - v_hat) / (u_len * sin_θ);
+        let g_k = (v_hat * cos_θ - u_hat) / (v_len * sin_θ);
+
+        (θ, vec![(i, g_i), (k, g_k), (j, -(g_i + g_k))])
+    }
+}
+
+/// The dihedral angle between four atoms. Radians, from -τ/2 to τ/2.
+#[derive(Clone, Debug)]
+pub struct Dihedral {
+    pub atoms: (usize, usize, usize, usize),
+}
+
+impl ColVar for Dihedral {
+    /// See Blondel and Karplus, 1996, for the gradient.
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let (i, j, k, l) = self.atoms;
+        let b1 = posits[j] - posits[i];
+        let b2 = posits[k] - posits[j];
+        let b3 = posits[l] - posits[k];
+
+        let m = b1.cross(b2);
+        let n = b2.cross(b3);
+        let (m_sq, n_sq) = (m.magnitude_squared(), n.magnitude_squared());
+        let b2_len = b2.magnitude();
+
+        let φ = (b2_len * b1.dot(n)).atan2(m.dot(n));
+
+        if m_sq < EPS || n_sq < EPS || b2_len < EPS {
+            return (φ, Vec::new());
+        }
+
+        let g_i = m * (-b2_len / m_sq);
+        let g_l = n * (b2_len / n_sq);
+
+        let b2_sq = b2_len * b2_len;
+        let p = b1.dot(b2) / b2_sq;
+        let q = b3.dot(b2) / b2_sq;
+
+        let g_j = g_l * q - g_i * (1. + p);
+        let g_k = g_i * p - g_l * (1. + q);
+
+        (φ, vec![(i, g_i), (j, g_j), (k, g_k), (l, g_l)])
+    }
+
+    fn period(&self) -> Option<f64> {
+        Some(TAU)
+    }
+}
+
+/// Distance between the centers of mass of two groups of atoms. Å
+#[derive(Clone, Debug)]
+pub struct ComDistance {
+    /// (Atom index, mass)
+    pub group_a: Vec<(usize, f64)>,
+    pub group_b: Vec<(usize, f64)>,
+}
+
+pub(super) fn center_of_mass(group: &[(usize, f64)], posits: &[Vec3]) -> (Vec3, f64) {
+    let mut sum = Vec3::new_zero();
+    let mut mass = 0.;
+    for &(i, m) in group {
+        sum += posits[i] * m;
+        mass += m;
+    }
+    if mass < EPS {
+        return (Vec3::new_zero(), 0.);
+    }
+    (sum / mass, mass)
+}
+
+impl ColVar for ComDistance {
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let (com_a, mass_a) = center_of_mass(&self.group_a, posits);
+        let (com_b, mass_b) = center_of_mass(&self.group_b, posits);
+
+        let diff = com_b - com_a;
+        let dist = diff.magnitude();
+        if dist < EPS || mass_a < EPS || mass_b < EPS {
+            return (dist, Vec::new());
+        }
+
+        let u = diff / dist;
+        let mut grad = Vec::with_capacity(self.group_a.len() + self.group_b.len());
+        for &(i, m) in &self.group_a {
+            grad.push((i, u * (-m / mass_a)));
+        }
+        for &(i, m) in &self.group_b {
+            grad.push((i, u * (m / mass_b)));
+        }
+
+        (dist, grad)
+    }
+}
+
+/// Displacement of a group's center of mass from a fixed point, along a direction. E.g. how far
+/// a ligand has been pulled out of its pocket. Å
+#[derive(Clone, Debug)]
+pub struct ComProjection {
+    /// (Atom index, mass)
+    pub group: Vec<(usize, f64)>,
+    pub origin: Vec3,
+    /// Unit length.
+    pub direction: Vec3,
+}
+
+impl ColVar for ComProjection {
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let (com, mass) = center_of_mass(&self.group, posits);
+        if mass < EPS {
+            return (0., Vec::new());
+        }
+
+        let grad = self
+            .group
+            .iter()
+            .map(|&(i, m)| (i, self.direction * (m / mass)))
+            .collect();
+
+        ((com - self.origin).dot(self.direction), grad)
+    }
+}
+
+/// A smooth count of atom pairs between two groups within a cutoff distance, using the switching
+/// function s(r) = (1 - (r/r₀)ⁿ) / (1 - (r/r₀)ᵐ).
+#[derive(Clone, Debug)]
+pub struct CoordinationNumber {
+    pub group_a: Vec<usize>,
+    pub group_b: Vec<usize>,
+    /// Å
+    pub r_0: f64,
+    pub n: i32,
+    pub m: i32,
+}
+
+impl CoordinationNumber {
+    pub fn new(group_a: Vec<usize>, group_b: Vec<usize>, r_0: f64) -> Self {
+        Self {
+            group_a,
+            group_b,
+            r_0,
+            n: COORD_N_DEFAULT,
+            m: COORD_M_DEFAULT,
+        }
+    }
+
+    /// The switching function, and its derivative with respect to r.
+    fn switch(&self, r: f64) -> (f64, f64) {
+        let mut x = r / self.r_0;
+        // s(r) has a removable singularity at r = r₀.
+        if (x - 1.).abs() < 1e-6 {
+            x += 1e-6;
+        }
+
+        let (n, m) = (self.n as f64, self.m as f64);
+        let xn = x.powi(self.n);
+        let xm = x.powi(self.m);
+        let denom = 1. - xm;
+
+        let s = (1. - xn) / denom;
+        let ds_dx = (-n * xn / x * denom + m * xm / x * (1. - xn)) / (denom * denom);
+
+        (s, ds_dx / self.r_0)
+    }
+}
+
+impl ColVar for CoordinationNumber {
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let mut value = 0.;
+        let mut grad = Vec::new();
+
+        for &i in &self.group_a {
+            for &j in &self.group_b {
+                if i == j {
+                    continue;
+                }
+                let diff = posits[j] - posits[i];
+                let r = diff.magnitude();
+                if r < EPS {
+                    continue;
+                }
+
+                let (s, ds_dr) = self.switch(r);
+                value += s;
+
+                let g = diff * (ds_dr / r);
+                grad.push((i, -g));
+                grad.push((j, g));
+            }
+        }
+
+        (value, grad)
+    }
+}
+
+/// Root-mean-square deviation of a set of atoms from reference positions, without fitting. Å
+#[derive(Clone, Debug)]
+pub struct Rmsd {
+    pub atoms: Vec<usize>,
+    /// Same order as `atoms`.
+    pub reference: Vec<Vec3>,
+}
+
+impl ColVar for Rmsd {
+    fn eval(&self, posits: &[Vec3]) -> (f64, CvGradient) {
+        let n = self.atoms.len();
+        if n == 0 {
+            return (0., Vec::new());
+        }
+
+        let mut sum_sq = 0.;
+        for (&i, r) in self.atoms.iter().zip(&self.reference) {
+            sum_sq += (posits[i] - *r).magnitude_squared();
+        }
+        let rmsd = (sum_sq / n as f64).sqrt();
+
+        if rmsd < EPS {
+            return (rmsd, Vec::new());
+        }
+
+        let grad = self
+            .atoms
+            .iter()
+            .zip(&self.reference)
+            .map(|(&i, r)| (i, (posits[i] - *r) / (n as f64 * rmsd)))
+            .collect();
+
+        (rmsd, grad)
+    }
+}
+
+/// A harmonic bias on a CV: V = ½k(ξ - ξ₀)². The building block for restraints, and, with a moving
+/// target, steering.
+pub struct CvRestraint {
+    pub cv: Box<dyn ColVar + Send + Sync>,
+    /// kcal/mol per CV unit²
+    pub k: f64,
+    pub target: f64,
+}
+
+impl CvRestraint {
+    /// Returns the bias energy in kcal/mol, and the force on each atom involved.
+    pub fn forces(&self, posits: &[Vec3]) -> (f64, Vec<(usize, Vec3)>) {
+        let (value, grad) = self.cv.eval(posits);
+        let d = self.cv.diff(value, self.target);
+
+        let energy = 0.5 * self.k * d * d;
+        let forces = grad
+            .into_iter()
+            .map(|(i, g)| (i, g * (-self.k * d)))
+            .collect();
+
+        (energy, forces)
+    }
+}
@@ -0,0 +1,135 @@
+//! Optional external potentials: A uniform electric field, walls at the sim box faces, and
+//! spherical containment. These act on each atom independently. Walls and containment keep
+//! un-solvated systems from drifting apart; the field is for electroporation-style studies.
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    dynamics::{AtomDynamics, ambient::SimBox},
+    units::EV_TO_KCAL_MOL,
+};
+
+/// Å. Closest distance to a wall we compute repulsion at; prevents a singularity.
+const WALL_DIST_MIN: f64 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum WallKind {
+    /// Zero inside the active region; ½kd² past it.
+    #[default]
+    Harmonic,
+    /// k/d⁹, where d is the distance to the face. Always active.
+    Repulsive,
+}
+
+#[derive(Clone, Debug)]
+pub struct Walls {
+    pub kind: WallKind,
+    /// kcal/mol/Å² for harmonic walls; kcal/mol·Å⁹ for repulsive ones.
+    pub k: f64,
+    /// Å. Harmonic walls start this far inside each box face.
+    pub inset: f64,
+}
+
+impl Default for Walls {
+    fn default() -> Self {
+        Self {
+            kind: Default::default(),
+            k: 10.,
+            inset: 2.,
+        }
+    }
+}
+
+/// A flat-bottomed harmonic potential, past a radius from a center.
+#[derive(Clone, Debug)]
+pub struct SphereContainment {
+    pub center: Vec3,
+    /// Å
+    pub radius: f64,
+    /// kcal/mol/Å²
+    pub k: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExternalFields {
+    /// Uniform electric field. V/Å
+    pub e_field: Option<Vec3>,
+    pub walls: Option<Walls>,
+    pub sphere: Option<SphereContainment>,
+}
+
+impl ExternalFields {
+    pub fn is_empty(&self) -> bool {
+        self.e_field.is_none() && self.walls.is_none() && self.sphere.is_none()
+    }
+
+    /// Returns the potential energy in kcal/mol, and each atom's force, in kcal/mol/Å.
+    /// The field's energy is relative to the origin.
+    pub fn forces(&self, atom: &AtomDynamics, cell: &SimBox) -> (f64, Vec3) {
+        let mut energy = 0.;
+        let mut f = Vec3::new_zero();
+
+        if let Some(e) = self.e_field {
+            let e = e * EV_TO_KCAL_MOL;
+            f += e * atom.partial_charge;
+            energy -= atom.partial_charge * e.dot(atom.posit);
+        }
+
+        if let Some(walls) = &self.walls {
+            let (e_wall, f_wall) = wall_forces(walls, atom.posit, cell);
+            energy += e_wall;
+            f += f_wall;
+        }
+
+        if let Some(sphere) = &self.sphere {
+            let diff = atom.posit - sphere.center;
+            let dist = diff.magnitude();
+            if dist > sphere.radius {
+                let d = dist - sphere.radius;
+                energy += 0.5 * sphere.k * d * d;
+                f -= diff / dist * (sphere.k * d);
+            }
+        }
+
+        (energy, f)
+    }
+}
+
+fn wall_forces(walls: &Walls, posit: Vec3, cell: &SimBox) -> (f64, Vec3) {
+    let mut energy = 0.;
+    let mut f = [0.; 3];
+
+    let p = [posit.x, posit.y, posit.z];
+    let lo = [cell.lo.x, cell.lo.y, cell.lo.z];
+    let hi = [cell.hi.x, cell.hi.y, cell.hi.z];
+
+    for axis in 0..3 {
+        // Distance from each face; positive inside the box.
+        let d_lo = p[axis] - lo[axis];
+        let d_hi = hi[axis] - p[axis];
+
+        match walls.kind {
+            WallKind::Harmonic => {
+                let past_lo = walls.inset - d_lo;
+                if past_lo > 0. {
+                    energy += 0.5 * walls.k * past_lo * past_lo;
+                    f[axis] += walls.k * past_lo;
+                }
+                let past_hi = walls.inset - d_hi;
+                if past_hi > 0. {
+                    energy += 0.5 * walls.k * past_hi * past_hi;
+                    f[axis] -= walls.k * past_hi;
+                }
+            }
+            WallKind::Repulsive => {
+                let d_lo = d_lo.max(WALL_DIST_MIN);
+                let d_hi = d_hi.max(WALL_DIST_MIN);
+                energy += walls.k * (d_lo.powi(-9) + d_hi.powi(-9));
+                // -dV/dx: pushes away from each face.
+                f[axis] += 9. * walls.k * (d_lo.powi(-10) - d_hi.powi(-10));
+            }
+        }
+    }
+
+    (energy, Vec3::new(f[0], f[1], f[2]))
+}
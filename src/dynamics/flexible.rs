@@ -0,0 +1,205 @@
+//! Flexible receptor residues. The receptor is normally static: its atoms only exert nonbonded
+//! forces on the mobile ones. Here, we carve selected residues out of the static set, and add them
+//! to the mobile one with their bonded parameters, so sidechains can respond to the ligand.
+//! (Induced fit)
+//!
+//! We move whole residues, and freeze their backbone atoms. This keeps sidechains attached to the
+//! rest of the chain, which stays static, without bonded terms between mobile and static atoms.
+
+use std::collections::HashMap;
+
+use bio_files::amber_params::ForceFieldParamsKeyed;
+use lin_alg::f64::Vec3;
+
+use crate::{
+    dynamics::{
+        AtomDynamics, ForceFieldParamsIndexed, MdState, ParamError, SnapshotDynamics,
+        prep::DihedralSeries,
+    },
+    molecule::{Atom, AtomRole, Bond, Residue},
+};
+
+/// Receptor atoms to move from the static set into the mobile one.
+#[derive(Clone, Debug, Default)]
+pub struct FlexibleResidues {
+    pub atoms: Vec<Atom>,
+    /// Indices are into `atoms`.
+    pub bonds: Vec<Bond>,
+    /// Same order as `atoms`. Frozen in the simulation.
+    pub backbone: Vec<bool>,
+    /// Indices into the static atoms of each of `atoms`.
+    pub static_indices: Vec<usize>,
+    /// Indices into the receptor's atoms, for writing positions back.
+    pub rec_indices: Vec<usize>,
+}
+
+impl FlexibleResidues {
+    /// `atoms_static` are the receptor atoms the simulation uses, e.g. those near a docking site,
+    /// and `static_to_rec` maps them to indices in the receptor. `bonds` and `residues` index the
+    /// receptor's atoms. Only atoms present in `atoms_static` are included.
+    pub fn new(
+        atoms_static: &[Atom],
+        static_to_rec: &[usize],
+        bonds: &[Bond],
+        residues: &[Residue],
+        flexible: &[usize],
+    ) -> Self {
+        let rec_to_static: HashMap<usize, usize> = static_to_rec
+            .iter()
+            .enumerate()
+            .map(|(i_static, i_rec)| (*i_rec, i_static))
+            .collect();
+
+        let mut result = Self::default();
+        // Receptor atom index to index in `result.atoms`.
+        let mut rec_to_flex = HashMap::new();
+
+        for res in flexible.iter().filter_map(|r| residues.get(*r)) {
+            for i_rec in &res.atoms {
+                let Some(&i_static) = rec_to_static.get(i_rec) else {
+                    continue;
+                };
+                let atom = &atoms_static[i_static];
+                let backbone = matches!(
+                    atom.role,
+                    Some(
+                        AtomRole::C_Alpha
+                            | AtomRole::C_Prime
+                            | AtomRole::N_Backbone
+                            | AtomRole::O_Backbone
+                            | AtomRole::H_Backbone
+                    )
+                );
+
+                rec_to_flex.insert(*i_rec, result.atoms.len());
+                result.atoms.push(atom.clone());
+                result.backbone.push(backbone);
+                result.static_indices.push(i_static);
+                result.rec_indices.push(*i_rec);
+            }
+        }
+
+        for bond in bonds {
+            if let (Some(&i), Some(&j)) =
+                (rec_to_flex.get(&bond.atom_0), rec_to_flex.get(&bond.atom_1))
+            {
+                result.bonds.push(Bond {
+                    atom_0: i,
+                    atom_1: j,
+                    ..bond.clone()
+                });
+            }
+        }
+
+        result
+    }
+}
+
+impl MdState {
+    /// Move receptor atoms into the mobile set, with bonded parameters from `ff_params`, and freeze
+    /// their backbone atoms. They're appended after existing mobile atoms, so indices of those
+    /// don't change. Call this before solvating, and setting up the thermostat.
+    pub fn add_flexible(
+        &mut self,
+        flex: &FlexibleResidues,
+        ff_params: &ForceFieldParamsKeyed,
+        dihedral_series: &DihedralSeries,
+    ) -> Result<(), ParamError> {
+        if flex.atoms.is_empty() {
+            return Ok(());
+        }
+
+        let mut adjacency_list = vec![Vec::new(); flex.atoms.len()];
+        for bond in &flex.bonds {
+            adjacency_list[bond.atom_0].push(bond.atom_1);
+            adjacency_list[bond.atom_1].push(bond.atom_0);
+        }
+
+        let params = ForceFieldParamsIndexed::new(
+            ff_params,
+            None,
+            dihedral_series,
+            &flex.atoms,
+            &flex.bonds,
+            &adjacency_list,
+            &mut self.param_report,
+        )?;
+
+        let offset = self.atoms.len();
+        let posits: Vec<Vec3> = flex.atoms.iter().map(|a| a.posit).collect();
+
+        for (i, atom) in flex.atoms.iter().enumerate() {
+            self.atoms
+                .push(AtomDynamics::new(atom, &posits, &params, i)?);
+        }
+        for neighbors in adjacency_list {
+            self.adjacency_list
+                .push(neighbors.into_iter().map(|j| j + offset).collect());
+        }
+
+        let ff = &mut self.force_field_params;
+        for ((i, j), p) in params.bond_stretching {
+            ff.bond_stretching.insert((i + offset, j + offset), p);
+        }
+        for ((i, j, k), p) in params.angle {
+            ff.angle.insert((i + offset, j + offset, k + offset), p);
+        }
+        for ((i, j, k, l), p) in params.dihedral {
+            ff.dihedral
+                .insert((i + offset, j + offset, k + offset, l + offset), p);
+        }
+        for (i, p) in params.mass {
+            ff.mass.insert(i + offset, p);
+        }
+        for (i, p) in params.van_der_waals {
+            ff.van_der_waals.insert(i + offset, p);
+        }
+
+        let mut i_static = 0;
+        self.atoms_static.retain(|_| {
+            let keep = !flex.static_indices.contains(&i_static);
+            i_static += 1;
+            keep
+        });
+
+        let backbone: Vec<_> = flex
+            .backbone
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b)
+            .map(|(i, _)| i + offset)
+            .collect();
+        self.freeze_atoms(&backbone);
+
+        for (i, i_rec) in flex.rec_indices.iter().enumerate() {
+            self.flexible.push((i + offset, *i_rec));
+        }
+
+        self.build_masks();
+        self.build_neighbours();
+
+        Ok(())
+    }
+
+    /// Current positions of flexible receptor atoms, by receptor atom index.
+    pub fn flexible_posits(&self) -> Vec<(usize, Vec3)> {
+        self.flexible
+            .iter()
+            .map(|(i, i_rec)| (*i_rec, self.atoms[*i].posit))
+            .collect()
+    }
+
+    /// As `flexible_posits`, at a snapshot.
+    pub fn flexible_posits_at(&self, snapshot: &SnapshotDynamics) -> Vec<(usize, Vec3)> {
+        self.flexible
+            .iter()
+            .filter_map(|(i, i_rec)| snapshot.atom_posits.get(*i).map(|p| (*i_rec, *p)))
+            .collect()
+    }
+
+    /// If the mobile atoms start with a ligand's. Receptor atoms are appended after them; in
+    /// protein-only runs, there is no ligand.
+    pub fn has_ligand(&self) -> bool {
+        self.flexible.first().is_none_or(|(i, _)| *i > 0)
+    }
+}
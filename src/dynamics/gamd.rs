@@ -0,0 +1,282 @@
+//! Gaussian accelerated molecular dynamics (GaMD): An enhanced sampling method that adds a harmonic
+//! boost potential when the system potential is below a threshold energy, smoothing the energy
+//! landscape without requiring collective variables.
+//!
+//! [Miao, Feher, McCammon, 2015](https://pubs.acs.org/doi/10.1021/acs.jctc.5b00436)
+//!
+//! Boost parameters are estimated from statistics collected over a short conventional MD (cMD) run
+//! at the start of the simulation. We log the boost potential at each step, for reweighting.
+
+use std::{fs::File, io, io::Write, path::Path};
+
+use log::info;
+
+/// Upper limit of the boost potential's standard deviation. Lower values result in a more
+/// accurate reweighting, but less acceleration. kcal/mol. (Amber's default)
+pub const SIGMA_0_DEFAULT: f64 = 6.;
+/// Steps of conventional MD used to collect potential statistics, prior to boosting.
+pub const N_CMD_STEPS_DEFAULT: usize = 2_000;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum GamdMode {
+    /// Boost the total potential.
+    #[default]
+    Total,
+    /// Boost the dihedral potential only.
+    Dihedral,
+    /// Boost both the dihedral, and total potential.
+    Dual,
+}
+
+impl GamdMode {
+    fn boosts_total(self) -> bool {
+        matches!(self, Self::Total | Self::Dual)
+    }
+
+    pub fn boosts_dihedral(self) -> bool {
+        matches!(self, Self::Dihedral | Self::Dual)
+    }
+}
+
+/// Which bound we use for the threshold energy, E.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum GamdThreshold {
+    /// E = V_max. More conservative.
+    #[default]
+    Lower,
+    /// E = V_min + (V_max - V_min) / k0. Falls back to the lower bound if k0 is out of range.
+    Upper,
+}
+
+#[derive(Clone, Debug)]
+pub struct GamdParams {
+    pub mode: GamdMode,
+    pub threshold: GamdThreshold,
+    /// kcal/mol. Applied to both the total and dihedral boosts.
+    pub sigma_0: f64,
+    pub n_cmd_steps: usize,
+}
+
+impl Default for GamdParams {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            threshold: Default::default(),
+            sigma_0: SIGMA_0_DEFAULT,
+            n_cmd_steps: N_CMD_STEPS_DEFAULT,
+        }
+    }
+}
+
+/// Running statistics of a potential term, using Welford's algorithm.
+#[derive(Clone, Debug)]
+pub struct PotentialStats {
+    pub v_min: f64,
+    pub v_max: f64,
+    pub v_mean: f64,
+    /// Sum of squared differences from the mean.
+    m2: f64,
+    pub count: usize,
+}
+
+impl Default for PotentialStats {
+    fn default() -> Self {
+        Self {
+            v_min: f64::MAX,
+            v_max: f64::MIN,
+            v_mean: 0.,
+            m2: 0.,
+            count: 0,
+        }
+    }
+}
+
+impl PotentialStats {
+    pub fn add(&mut self, v: f64) {
+        self.count += 1;
+        self.v_min = self.v_min.min(v);
+        self.v_max = self.v_max.max(v);
+
+        let delta = v - self.v_mean;
+        self.v_mean += delta / self.count as f64;
+        self.m2 += delta * (v - self.v_mean);
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+}
+
+/// Threshold energy and force constant for one boosted potential term.
+#[derive(Clone, Copy, Debug)]
+pub struct BoostParams {
+    /// Threshold energy. kcal/mol
+    pub e: f64,
+    /// Harmonic force constant. (kcal/mol)⁻¹
+    pub k: f64,
+}
+
+impl BoostParams {
+    /// See the GaMD paper, equations 13 to 16.
+    pub fn from_stats(stats: &PotentialStats, sigma_0: f64, threshold: GamdThreshold) -> Self {
+        let range = (stats.v_max - stats.v_min).max(f64::EPSILON);
+        let σ_v = stats.std_dev().max(f64::EPSILON);
+
+        let lower = || {
+            let k_0 =
+                (sigma_0 / σ_v * range / (stats.v_max - stats.v_mean).max(f64::EPSILON)).min(1.);
+            Self {
+                e: stats.v_max,
+                k: k_0 / range,
+            }
+        };
+
+        match threshold {
+            GamdThreshold::Lower => lower(),
+            GamdThreshold::Upper => {
+                let k_0 =
+                    (1. - sigma_0 / σ_v) * range / (stats.v_mean - stats.v_min).max(f64::EPSILON);
+                if k_0 > 0. && k_0 <= 1. {
+                    Self {
+                        e: stats.v_min + range / k_0,
+                        k: k_0 / range,
+                    }
+                } else {
+                    lower()
+                }
+            }
+        }
+    }
+
+    /// Returns the boost potential ΔV, and the factor to scale this term's forces by.
+    pub fn boost(&self, v: f64) -> (f64, f64) {
+        if v >= self.e {
+            return (0., 1.);
+        }
+
+        let diff = self.e - v;
+        (0.5 * self.k * diff * diff, 1. - self.k * diff)
+    }
+}
+
+/// Per-step data, for reweighting.
+#[derive(Clone, Debug)]
+pub struct GamdLogEntry {
+    pub step: usize,
+    /// fs
+    pub time: f64,
+    /// Unboosted potentials. kcal/mol
+    pub v_total: f64,
+    pub v_dihedral: f64,
+    /// Boost potentials. kcal/mol
+    pub boost_total: f64,
+    pub boost_dihedral: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GamdState {
+    pub params: GamdParams,
+    pub stats_total: PotentialStats,
+    pub stats_dihedral: PotentialStats,
+    /// `None` until the cMD stage completes.
+    pub boost_total: Option<BoostParams>,
+    pub boost_dihedral: Option<BoostParams>,
+    pub log: Vec<GamdLogEntry>,
+}
+
+impl GamdState {
+    pub fn new(params: GamdParams) -> Self {
+        Self {
+            params,
+            ..Default::default()
+        }
+    }
+
+    /// Call once per step with the unboosted potentials. During the cMD stage, this collects
+    /// statistics. After, it returns the factors to scale the total, and dihedral forces by.
+    pub fn update(&mut self, step: usize, time: f64, v_total: f64, v_dihedral: f64) -> (f64, f64) {
+        let mode = self.params.mode;
+
+        if step < self.params.n_cmd_steps {
+            self.stats_total.add(v_total);
+            self.stats_dihedral.add(v_dihedral);
+            return (1., 1.);
+        }
+
+        if step == self.params.n_cmd_steps {
+            if mode.boosts_total() {
+                self.boost_total = Some(BoostParams::from_stats(
+                    &self.stats_total,
+                    self.params.sigma_0,
+                    self.params.threshold,
+                ));
+            }
+            if mode.boosts_dihedral() {
+                self.boost_dihedral = Some(BoostParams::from_stats(
+                    &self.stats_dihedral,
+                    self.params.sigma_0,
+                    self.params.threshold,
+                ));
+            }
+            info!(
+                "GaMD cMD stage complete. Total boost: {:?}, Dihedral boost: {:?}",
+                self.boost_total, self.boost_dihedral
+            );
+        }
+
+        let (boost_total, factor_total) = match &self.boost_total {
+            Some(b) => b.boost(v_total),
+            None => (0., 1.),
+        };
+        let (boost_dihedral, factor_dihedral) = match &self.boost_dihedral {
+            Some(b) => b.boost(v_dihedral),
+            None => (0., 1.),
+        };
+
+        self.log.push(GamdLogEntry {
+            step,
+            time,
+            v_total,
+            v_dihedral,
+            boost_total,
+            boost_dihedral,
+        });
+
+        (factor_total, factor_dihedral)
+    }
+
+    /// Mean, standard deviation, and max of the total boost potential applied. These indicate
+    /// how accurate reweighting will be; a σ above ~10 kcal/mol is problematic.
+    pub fn boost_stats(&self) -> (f64, f64, f64) {
+        let mut stats = PotentialStats::default();
+        for entry in &self.log {
+            stats.add(entry.boost_total + entry.boost_dihedral);
+        }
+        if stats.count == 0 {
+            return (0., 0., 0.);
+        }
+        (stats.v_mean, stats.std_dev(), stats.v_max)
+    }
+
+    /// Write the boost log as CSV, for reweighting with external tools.
+    pub fn save_log(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(
+            file,
+            "step,time_fs,v_total,v_dihedral,boost_total,boost_dihedral"
+        )?;
+        for e in &self.log {
+            writeln!(
+                file,
+                "{},{:.3},{:.4},{:.4},{:.4},{:.4}",
+                e.step, e.time, e.v_total, e.v_dihedral, e.boost_total, e.boost_dihedral
+            )?;
+        }
+
+        Ok(())
+    }
+}
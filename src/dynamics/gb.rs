@@ -0,0 +1,82 @@
+//! Generalized Born implicit solvation. This approximates the electrostatic free energy of moving
+//! a set of charges from a low-dielectric (the solute) into water, without explicit water
+//! molecules. Each atom's effective Born radius measures how buried it is; we compute these with
+//! the pairwise descreening of Hawkins, Cramer, and Truhlar (HCT), and combine them with Still's
+//! formula.
+//!
+//! [Hawkins, Cramer, Truhlar, 1996](https://doi.org/10.1021/jp961710n)
+
+use lin_alg::f64::Vec3;
+
+use crate::units::COULOMB_CONST;
+
+/// Relative permittivity of water.
+pub const DIELECTRIC_WATER: f64 = 78.5;
+/// Å. Intrinsic radii are reduced by this, as in Amber's GB models.
+const RADIUS_OFFSET: f64 = 0.09;
+/// Descreening scale factor; corrects for overlap between atomic spheres.
+const SCREEN_SCALE: f64 = 0.8;
+/// Å. An upper bound on Born radii, for atoms at the center of large solutes.
+const BORN_RADIUS_MAX: f64 = 30.;
+
+/// Effective Born radii, in Å. `radii` are intrinsic atomic radii, e.g. Bondi.
+pub fn born_radii(posits: &[Vec3], radii: &[f64]) -> Vec<f64> {
+    posits
+        .iter()
+        .zip(radii)
+        .enumerate()
+        .map(|(i, (p_i, r_i))| {
+            let rho_i = r_i - RADIUS_OFFSET;
+            let mut sum = 0.;
+
+            for (j, (p_j, r_j)) in posits.iter().zip(radii).enumerate() {
+                if i == j {
+                    continue;
+                }
+                let r = (*p_j - *p_i).magnitude();
+                let sr_j = SCREEN_SCALE * (r_j - RADIUS_OFFSET);
+
+                // Atom j's scaled sphere is inside atom i's.
+                if r + sr_j <= rho_i {
+                    continue;
+                }
+
+                let l = rho_i.max((r - sr_j).abs());
+                let u = r + sr_j;
+                let (l2, u2) = (1. / (l * l), 1. / (u * u));
+
+                sum += 0.5
+                    * (1. / l - 1. / u
+                        + 0.25 * r * (u2 - l2)
+                        + 0.5 / r * (l / u).ln()
+                        + 0.25 * sr_j * sr_j / r * (l2 - u2));
+            }
+
+            let inv = (1. / rho_i - sum).max(1. / BORN_RADIUS_MAX);
+            1. / inv
+        })
+        .collect()
+}
+
+/// kcal/mol. The GB polarization (solvation) energy, with a solute dielectric of 1. Includes
+/// self terms.
+pub fn gb_energy(posits: &[Vec3], charges: &[f64], born_radii: &[f64]) -> f64 {
+    let prefactor = -0.5 * COULOMB_CONST * (1. - 1. / DIELECTRIC_WATER);
+
+    let mut result = 0.;
+    for i in 0..posits.len() {
+        // Self term.
+        result += charges[i] * charges[i] / born_radii[i];
+
+        for j in i + 1..posits.len() {
+            let r_sq = (posits[j] - posits[i]).magnitude_squared();
+            let rr = born_radii[i] * born_radii[j];
+            let f_gb = (r_sq + rr * (-r_sq / (4. * rr)).exp()).sqrt();
+
+            // Each pair appears twice in the full sum.
+            result += 2. * charges[i] * charges[j] / f_gb;
+        }
+    }
+
+    prefactor * result
+}
@@ -0,0 +1,880 @@
+//! Runs MD steps on the GPU. We upload atoms and parameters once, and keep them on the device
+//! between steps; per step, we only transfer single values, e.g. for the thermostat, and the
+//! displacement check that decides when to rebuild neighbor lists. Neighbor lists are built on the
+//! device too, with a cell grid, as in `MdState::build_neighbours`. The force kernels accumulate
+//! energy terms and the virial, so reports don't need atoms on the host; we only download those
+//! for snapshots.
+//!
+//! This mirrors `MdState::step`, in f32. GaMD, collective variable and positional restraints, frozen
+//! atoms, steering, external fields, and the barostat run on the CPU only; `run_md` falls back to
+//! it when they're present.
+
+use std::{collections::HashSet, sync::Arc};
+
+use cudarc::driver::{
+    CudaFunction, CudaModule, CudaSlice, CudaStream, DeviceRepr, LaunchConfig, PushKernelArg,
+    ValidAsZeroBits,
+};
+use lin_alg::{
+    f32::{Vec3 as Vec3F32, vec3s_from_dev, vec3s_to_dev},
+    f64::Vec3,
+};
+use log::warn;
+
+use crate::{
+    dynamics::{
+        AtomDynamics, CUTOFF, MdState, ParamError, SKIN, SNAPSHOT_RATIO, ambient::SimBox,
+        report::ReportTerms, thermostat::Thermostat,
+    },
+    logging::Span,
+    units::{ACCEL_CONV, FS_PER_PS, temperature},
+};
+
+/// Threads in the single-block scan; matches `NB_SCAN_THREADS` in `neighbors.cu`.
+const SCAN_THREADS: u32 = 1_024;
+/// When a neighbor list outgrows its buffer, we reallocate with this much extra room, so we don't
+/// reallocate on every rebuild.
+const LIST_SLACK: f64 = 1.2;
+
+struct Kernels {
+    kick_drift: CudaFunction,
+    kick: CudaFunction,
+    mv_sq: CudaFunction,
+    scale_vels: CudaFunction,
+    max_disp_sq: CudaFunction,
+    bond_stretching: CudaFunction,
+    angle_bending: CudaFunction,
+    dihedral: CudaFunction,
+    cmap: CudaFunction,
+    nonbonded: CudaFunction,
+    nonbonded_static: CudaFunction,
+    cell_ids: CudaFunction,
+    scan: CudaFunction,
+    cell_fill: CudaFunction,
+    list: CudaFunction,
+}
+
+impl Kernels {
+    fn new(module: &Arc<CudaModule>) -> Self {
+        let load = |name| module.load_function(name).unwrap();
+
+        Self {
+            kick_drift: load("md_kick_drift_kernel"),
+            kick: load("md_kick_kernel"),
+            mv_sq: load("md_mv_sq_kernel"),
+            scale_vels: load("md_scale_vels_kernel"),
+            max_disp_sq: load("md_max_disp_sq_kernel"),
+            bond_stretching: load("md_bond_stretching_kernel"),
+            angle_bending: load("md_angle_bending_kernel"),
+            dihedral: load("md_dihedral_kernel"),
+            cmap: load("md_cmap_kernel"),
+            nonbonded: load("md_nonbonded_kernel"),
+            nonbonded_static: load("md_nonbonded_static_kernel"),
+            cell_ids: load("nb_cell_ids_kernel"),
+            scan: load("nb_scan_kernel"),
+            cell_fill: load("nb_cell_fill_kernel"),
+            list: load("nb_list_kernel"),
+        }
+    }
+
+    /// Exclusive scan of `data[..n]` in place, with the total at `data[n]`.
+    fn scan(&self, stream: &Arc<CudaStream>, data: &mut CudaSlice<u32>, n: usize) {
+        let cfg = LaunchConfig {
+            grid_dim: (1, 1, 1),
+            block_dim: (SCAN_THREADS, 1, 1),
+            shared_mem_bytes: 0,
+        };
+
+        let mut args = stream.launch_builder(&self.scan);
+        args.arg(data);
+        args.arg(&n);
+        unsafe { args.launch(cfg) }.unwrap();
+    }
+
+    /// Bin `n` atoms into a cell grid over the box, with sides at least `radius`. Matches `CellGrid`.
+    fn build_grid(
+        &self,
+        stream: &Arc<CudaStream>,
+        posits: &CudaSlice<f32>,
+        n: usize,
+        cell: &SimBox,
+        radius: f64,
+    ) -> GridDev {
+        let ext = cell.extent();
+        let dims = [ext.x, ext.y, ext.z].map(|len| ((len / radius).floor() as u32).max(1));
+        let n_cells = dims.iter().product::<u32>() as usize;
+
+        let lo: Vec3F32 = cell.lo.into();
+        let ext: Vec3F32 = ext.into();
+
+        let mut ids = stream.alloc_zeros::<u32>(n).unwrap();
+        let mut offsets = stream.alloc_zeros::<u32>(n_cells + 1).unwrap();
+        {
+            let mut args = stream.launch_builder(&self.cell_ids);
+            args.arg(&mut ids);
+            args.arg(&mut offsets);
+            args.arg(posits);
+            args.arg(&lo.x);
+            args.arg(&lo.y);
+            args.arg(&lo.z);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&dims[0]);
+            args.arg(&dims[1]);
+            args.arg(&dims[2]);
+            args.arg(&n);
+            unsafe { args.launch(launch_cfg(n)) }.unwrap();
+        }
+
+        self.scan(stream, &mut offsets, n_cells);
+
+        let mut atoms = stream.alloc_zeros::<u32>(n).unwrap();
+        let mut fill = stream.alloc_zeros::<u32>(n_cells).unwrap();
+        {
+            let mut args = stream.launch_builder(&self.cell_fill);
+            args.arg(&mut atoms);
+            args.arg(&mut fill);
+            args.arg(&ids);
+            args.arg(&offsets);
+            args.arg(&n);
+            unsafe { args.launch(launch_cfg(n)) }.unwrap();
+        }
+
+        GridDev {
+            dims,
+            lo,
+            ext,
+            radius_sq: (radius * radius) as f32,
+            offsets,
+            atoms,
+        }
+    }
+
+    /// Find atoms in `grid`, at `posits_grid`, near each atom at `posits`. Pass `exclusions` when
+    /// these are the same set of atoms, i.e. for mobile-mobile lists; this skips self-pairs, and
+    /// sets pair flags.
+    fn build_list(
+        &self,
+        stream: &Arc<CudaStream>,
+        list: &mut NbList,
+        posits: &CudaSlice<f32>,
+        posits_grid: &CudaSlice<f32>,
+        grid: &GridDev,
+        exclusions: Option<&Exclusions>,
+    ) {
+        let n = list.offsets.len() - 1;
+        let same_set = exclusions.is_some() as u32;
+
+        // Count, scan the counts into offsets, then fill.
+        for fill in [0_u32, 1] {
+            if fill == 1 {
+                self.scan(stream, &mut list.offsets, n);
+
+                let total = stream.memcpy_dtov(&list.offsets.slice(n..)).unwrap()[0] as usize;
+                if total > list.capacity {
+                    list.capacity = (total as f64 * LIST_SLACK) as usize;
+                    list.indices = stream.alloc_zeros(list.capacity).unwrap();
+                    list.flags = stream.alloc_zeros(list.capacity).unwrap();
+                }
+            }
+
+            let mut args = stream.launch_builder(&self.list);
+            args.arg(&mut list.offsets);
+            args.arg(&mut list.indices);
+            args.arg(&mut list.flags);
+            args.arg(posits);
+            args.arg(posits_grid);
+            args.arg(&grid.offsets);
+            args.arg(&grid.atoms);
+            match exclusions {
+                Some(e) => {
+                    args.arg(&e.excl_offsets);
+                    args.arg(&e.excl_indices);
+                    args.arg(&e.s14_offsets);
+                    args.arg(&e.s14_indices);
+                }
+                // Unused by the kernel.
+                None => {
+                    for _ in 0..4 {
+                        args.arg(&grid.offsets);
+                    }
+                }
+            }
+            args.arg(&grid.lo.x);
+            args.arg(&grid.lo.y);
+            args.arg(&grid.lo.z);
+            args.arg(&grid.ext.x);
+            args.arg(&grid.ext.y);
+            args.arg(&grid.ext.z);
+            args.arg(&grid.dims[0]);
+            args.arg(&grid.dims[1]);
+            args.arg(&grid.dims[2]);
+            args.arg(&grid.radius_sq);
+            args.arg(&same_set);
+            args.arg(&fill);
+            args.arg(&n);
+            unsafe { args.launch(launch_cfg(n)) }.unwrap();
+        }
+    }
+}
+
+/// A cell grid on the device. See `CellGrid`.
+struct GridDev {
+    dims: [u32; 3],
+    lo: Vec3F32,
+    ext: Vec3F32,
+    /// Å². The square of the neighbor list radius.
+    radius_sq: f32,
+    /// Scanned atom counts per cell: cell `c` holds `atoms[offsets[c]..offsets[c + 1]]`.
+    offsets: CudaSlice<u32>,
+    atoms: CudaSlice<u32>,
+}
+
+/// A neighbor list on the device, in CSR form: atom `i`'s neighbors are
+/// `indices[offsets[i]..offsets[i + 1]]`.
+struct NbList {
+    offsets: CudaSlice<u32>,
+    indices: CudaSlice<u32>,
+    /// Per pair, for mobile-mobile lists. See `md_nonbonded_kernel`.
+    flags: CudaSlice<u8>,
+    /// Pairs the index and flag buffers fit.
+    capacity: usize,
+}
+
+impl NbList {
+    fn new(stream: &Arc<CudaStream>, n_atoms: usize) -> Self {
+        Self {
+            offsets: stream.alloc_zeros(n_atoms + 1).unwrap(),
+            indices: stream.alloc_zeros(1).unwrap(),
+            flags: stream.alloc_zeros(1).unwrap(),
+            capacity: 1,
+        }
+    }
+}
+
+/// Excluded (1-2, 1-3), and scaled (1-4) partners of each atom, in CSR form.
+struct Exclusions {
+    excl_offsets: CudaSlice<u32>,
+    excl_indices: CudaSlice<u32>,
+    s14_offsets: CudaSlice<u32>,
+    s14_indices: CudaSlice<u32>,
+}
+
+impl Exclusions {
+    fn new(stream: &Arc<CudaStream>, md: &MdState) -> Self {
+        let per_atom = |pairs: &HashSet<(usize, usize)>| {
+            let mut result = vec![Vec::new(); md.atoms.len()];
+            for &(i, j) in pairs {
+                result[i].push(j);
+                result[j].push(i);
+            }
+            to_csr(&result)
+        };
+
+        let (excl_offsets, excl_indices) = per_atom(&md.excluded_pairs);
+        let (s14_offsets, s14_indices) = per_atom(&md.scaled14_pairs);
+
+        Self {
+            excl_offsets: to_dev(stream, &excl_offsets),
+            excl_indices: to_dev(stream, &excl_indices),
+            s14_offsets: to_dev(stream, &s14_offsets),
+            s14_indices: to_dev(stream, &s14_indices),
+        }
+    }
+}
+
+/// Copy to the device. Allocates one element for empty slices, as kernels still take a pointer.
+fn to_dev<T: DeviceRepr + ValidAsZeroBits>(stream: &Arc<CudaStream>, data: &[T]) -> CudaSlice<T> {
+    if data.is_empty() {
+        stream.alloc_zeros(1).unwrap()
+    } else {
+        stream.memcpy_stod(data).unwrap()
+    }
+}
+
+/// Convert to f32, and copy to the device.
+fn vec3s_to_dev_f64(stream: &Arc<CudaStream>, data: impl Iterator<Item = Vec3>) -> CudaSlice<f32> {
+    let mut data: Vec<Vec3F32> = data.map(|v| v.into()).collect();
+    if data.is_empty() {
+        data.push(Vec3F32::new_zero());
+    }
+    vec3s_to_dev(stream, &data)
+}
+
+fn launch_cfg(n: usize) -> LaunchConfig {
+    LaunchConfig::for_num_elems(n as u32)
+}
+
+/// Flatten per-atom lists into offsets and indices. (CSR)
+fn to_csr(lists: &[Vec<usize>]) -> (Vec<u32>, Vec<u32>) {
+    let mut offsets = Vec::with_capacity(lists.len() + 1);
+    let mut indices = Vec::new();
+
+    offsets.push(0);
+    for list in lists {
+        indices.extend(list.iter().map(|&j| j as u32));
+        offsets.push(indices.len() as u32);
+    }
+
+    (offsets, indices)
+}
+
+/// Atom state, parameters, and neighbor lists, resident on the GPU.
+pub struct MdGpu {
+    stream: Arc<CudaStream>,
+    kernels: Kernels,
+    n_atoms: usize,
+    posits: CudaSlice<f32>,
+    vels: CudaSlice<f32>,
+    accels: CudaSlice<f32>,
+    posits_at_build: CudaSlice<f32>,
+    masses: CudaSlice<f32>,
+    charges: CudaSlice<f32>,
+    sigmas: CudaSlice<f32>,
+    epss: CudaSlice<f32>,
+    n_bonds: usize,
+    bond_atoms: CudaSlice<u32>,
+    bond_k: CudaSlice<f32>,
+    bond_r_0: CudaSlice<f32>,
+    n_angles: usize,
+    angle_atoms: CudaSlice<u32>,
+    angle_k: CudaSlice<f32>,
+    angle_θ_0: CudaSlice<f32>,
+    n_dihedrals: usize,
+    dihedral_atoms: CudaSlice<u32>,
+    /// kcal/mol. Pre-divided.
+    dihedral_barrier: CudaSlice<f32>,
+    dihedral_phase: CudaSlice<f32>,
+    dihedral_periodicity: CudaSlice<f32>,
+    n_cmap: usize,
+    /// 5 per term.
+    cmap_atoms: CudaSlice<u32>,
+    /// Per term, an index into the grids.
+    cmap_grids: CudaSlice<u32>,
+    /// Per grid, its first cell in `cmap_coeffs`.
+    cmap_grid_offsets: CudaSlice<u32>,
+    /// Per grid, points per axis.
+    cmap_grid_res: CudaSlice<u32>,
+    /// 16 bicubic coefficients per cell. See `CmapGrid`.
+    cmap_coeffs: CudaSlice<f32>,
+    posits_static: CudaSlice<f32>,
+    charges_static: CudaSlice<f32>,
+    sigmas_static: CudaSlice<f32>,
+    epss_static: CudaSlice<f32>,
+    /// Static atoms don't move, so we bin them once.
+    grid_static: Option<GridDev>,
+    exclusions: Exclusions,
+    nb: NbList,
+    nb_static: NbList,
+    /// Energy terms and the virial from the most recent step, in `ReportTerms` order. See
+    /// `TERM_BONDED` etc in `dynamics.cu`.
+    terms: CudaSlice<f32>,
+    /// A single value, for reductions.
+    scratch: CudaSlice<f32>,
+}
+
+impl MdGpu {
+    pub fn new(
+        stream: &Arc<CudaStream>,
+        module: &Arc<CudaModule>,
+        md: &MdState,
+    ) -> Result<Self, ParamError> {
+        let _span = Span::new(format!("GPU upload of {} atoms", md.atoms.len()));
+
+        if md.atoms.is_empty() {
+            return Err(ParamError::new("No atoms to simulate"));
+        }
+        if md.gamd.is_some()
+            || !md.cv_restraints.is_empty()
+            || md.steering.is_some()
+            || !md.position_restraints.is_empty()
+            || md.frozen.iter().any(|f| *f)
+            || !md.external_fields.is_empty()
+            || md.barostat.is_some()
+        {
+            return Err(ParamError::new(
+                "GaMD, restraints, frozen atoms, steering, external fields, and the barostat aren't \
+                supported on the GPU",
+            ));
+        }
+
+        let atoms = &md.atoms;
+        let statics = &md.atoms_static;
+        let per_atom = |atoms: &[AtomDynamics], f: fn(&AtomDynamics) -> f64| -> Vec<f32> {
+            atoms.iter().map(|a| f(a) as f32).collect()
+        };
+
+        let ff = &md.force_field_params;
+
+        let mut bond_atoms = Vec::new();
+        let (mut bond_k, mut bond_r_0) = (Vec::new(), Vec::new());
+        for (&(i, j), params) in &ff.bond_stretching {
+            bond_atoms.extend([i as u32, j as u32]);
+            bond_k.push(params.k_b);
+            bond_r_0.push(params.r_0);
+        }
+
+        let mut angle_atoms = Vec::new();
+        let (mut angle_k, mut angle_θ_0) = (Vec::new(), Vec::new());
+        for (&(i, j, k), params) in &ff.angle {
+            angle_atoms.extend([i as u32, j as u32, k as u32]);
+            angle_k.push(params.k);
+            angle_θ_0.push(params.theta_0);
+        }
+
+        let mut dihedral_atoms = Vec::new();
+        let (mut dihedral_barrier, mut dihedral_phase, mut dihedral_periodicity) =
+            (Vec::new(), Vec::new(), Vec::new());
+        // One entry per Fourier term; a multi-term dihedral repeats its atoms.
+        for (&(i, j, k, l), terms) in &ff.dihedral {
+            for params in terms {
+                dihedral_atoms.extend([i as u32, j as u32, k as u32, l as u32]);
+                dihedral_barrier.push(params.barrier_height);
+                dihedral_phase.push(params.phase);
+                dihedral_periodicity.push(params.periodicity as f32);
+            }
+        }
+
+        let mut cmap_atoms = Vec::new();
+        let mut cmap_grids = Vec::new();
+        for term in &md.cmap_terms {
+            cmap_atoms.extend(term.atoms.map(|i| i as u32));
+            cmap_grids.push(term.grid as u32);
+        }
+
+        let (mut cmap_grid_offsets, mut cmap_grid_res) = (Vec::new(), Vec::new());
+        let mut cmap_coeffs = Vec::new();
+        for grid in &md.cmap_grids {
+            cmap_grid_offsets.push((cmap_coeffs.len() / 16) as u32);
+            cmap_grid_res.push(grid.resolution as u32);
+            for cell in grid.cell_coeffs() {
+                cmap_coeffs.extend(cell.iter().flatten().map(|&c| c as f32));
+            }
+        }
+
+        let mut result = Self {
+            stream: stream.clone(),
+            kernels: Kernels::new(module),
+            n_atoms: atoms.len(),
+            posits: vec3s_to_dev_f64(stream, atoms.iter().map(|a| a.posit)),
+            posits_at_build: vec3s_to_dev_f64(stream, atoms.iter().map(|a| a.posit)),
+            vels: vec3s_to_dev_f64(stream, atoms.iter().map(|a| a.vel)),
+            accels: vec3s_to_dev_f64(stream, atoms.iter().map(|a| a.accel)),
+            masses: to_dev(stream, &per_atom(atoms, |a| a.mass)),
+            charges: to_dev(stream, &per_atom(atoms, |a| a.partial_charge)),
+            sigmas: to_dev(stream, &per_atom(atoms, |a| a.lj_sigma)),
+            epss: to_dev(stream, &per_atom(atoms, |a| a.lj_eps)),
+            n_bonds: bond_k.len(),
+            bond_atoms: to_dev(stream, &bond_atoms),
+            bond_k: to_dev(stream, &bond_k),
+            bond_r_0: to_dev(stream, &bond_r_0),
+            n_angles: angle_k.len(),
+            angle_atoms: to_dev(stream, &angle_atoms),
+            angle_k: to_dev(stream, &angle_k),
+            angle_θ_0: to_dev(stream, &angle_θ_0),
+            n_dihedrals: dihedral_barrier.len(),
+            dihedral_atoms: to_dev(stream, &dihedral_atoms),
+            dihedral_barrier: to_dev(stream, &dihedral_barrier),
+            dihedral_phase: to_dev(stream, &dihedral_phase),
+            dihedral_periodicity: to_dev(stream, &dihedral_periodicity),
+            n_cmap: cmap_grids.len(),
+            cmap_atoms: to_dev(stream, &cmap_atoms),
+            cmap_grids: to_dev(stream, &cmap_grids),
+            cmap_grid_offsets: to_dev(stream, &cmap_grid_offsets),
+            cmap_grid_res: to_dev(stream, &cmap_grid_res),
+            cmap_coeffs: to_dev(stream, &cmap_coeffs),
+            posits_static: vec3s_to_dev_f64(stream, statics.iter().map(|a| a.posit)),
+            charges_static: to_dev(stream, &per_atom(statics, |a| a.partial_charge)),
+            sigmas_static: to_dev(stream, &per_atom(statics, |a| a.lj_sigma)),
+            epss_static: to_dev(stream, &per_atom(statics, |a| a.lj_eps)),
+            grid_static: None,
+            exclusions: Exclusions::new(stream, md),
+            nb: NbList::new(stream, atoms.len()),
+            nb_static: NbList::new(stream, atoms.len()),
+            terms: stream.alloc_zeros(4).unwrap(),
+            scratch: stream.alloc_zeros(1).unwrap(),
+        };
+
+        if !statics.is_empty() {
+            result.grid_static = Some(result.kernels.build_grid(
+                stream,
+                &result.posits_static,
+                statics.len(),
+                &md.cell,
+                CUTOFF + SKIN,
+            ));
+        }
+        result.build_neighbours(&md.cell);
+
+        Ok(result)
+    }
+
+    /// Build neighbor lists, and record the positions they were built at.
+    fn build_neighbours(&mut self, cell: &SimBox) {
+        let grid = self.kernels.build_grid(
+            &self.stream,
+            &self.posits,
+            self.n_atoms,
+            cell,
+            CUTOFF + SKIN,
+        );
+
+        self.kernels.build_list(
+            &self.stream,
+            &mut self.nb,
+            &self.posits,
+            &self.posits,
+            &grid,
+            Some(&self.exclusions),
+        );
+
+        if let Some(grid_static) = &self.grid_static {
+            self.kernels.build_list(
+                &self.stream,
+                &mut self.nb_static,
+                &self.posits,
+                &self.posits_static,
+                grid_static,
+                None,
+            );
+        }
+
+        self.stream
+            .memcpy_dtod(&self.posits, &mut self.posits_at_build)
+            .unwrap();
+    }
+
+    /// Copy positions and velocities back to the host, and the most recent potential energy.
+    pub fn download(&self, md: &mut MdState) {
+        let _span = Span::new("GPU download");
+
+        let posits = vec3s_from_dev(&self.stream, &self.posits);
+        let vels = vec3s_from_dev(&self.stream, &self.vels);
+
+        for ((a, p), v) in md.atoms.iter_mut().zip(posits).zip(vels) {
+            a.posit = p.into();
+            a.vel = v.into();
+        }
+
+        self.download_terms(md);
+    }
+
+    /// Copy the most recent step's energy terms and virial to the host, and set the potential
+    /// energy from them.
+    fn download_terms(&self, md: &mut MdState) -> ReportTerms {
+        let t = self.stream.memcpy_dtov(&self.terms).unwrap();
+
+        let result = ReportTerms {
+            bonded: t[0] as f64,
+            lj: t[1] as f64,
+            coulomb: t[2] as f64,
+            virial: t[3] as f64,
+        };
+        md.energy_potential = result.bonded + result.lj + result.coulomb;
+
+        result
+    }
+
+    /// Kinetic energy, in kcal/mol.
+    fn kinetic_energy(&mut self) -> f64 {
+        self.stream.memset_zeros(&mut self.scratch).unwrap();
+
+        let mut args = self.stream.launch_builder(&self.kernels.mv_sq);
+        args.arg(&mut self.scratch);
+        args.arg(&self.vels);
+        args.arg(&self.masses);
+        args.arg(&self.n_atoms);
+        unsafe { args.launch(launch_cfg(self.n_atoms)) }.unwrap();
+
+        let mv_sq = self.stream.memcpy_dtov(&self.scratch).unwrap()[0] as f64;
+        0.5 * mv_sq / ACCEL_CONV
+    }
+
+    fn scale_vels(&mut self, scale: f64) {
+        let scale = scale as f32;
+
+        let mut args = self.stream.launch_builder(&self.kernels.scale_vels);
+        args.arg(&mut self.vels);
+        args.arg(&scale);
+        args.arg(&self.n_atoms);
+        unsafe { args.launch(launch_cfg(self.n_atoms)) }.unwrap();
+    }
+
+    /// See `MdState::remove_com_motion`. This round-trips velocities through the host, but it's
+    /// infrequent.
+    fn remove_com_motion(&mut self, md: &mut MdState) {
+        self.download(md);
+        md.remove_com_motion();
+        self.vels = vec3s_to_dev_f64(&self.stream, md.atoms.iter().map(|a| a.vel));
+    }
+
+    /// See `MdState::apply_nhc`.
+    fn apply_nhc(&mut self, md: &mut MdState, dt: f64) {
+        if md.nhc.is_none() {
+            return;
+        }
+        let ke = self.kinetic_energy();
+        let Some(nhc) = &mut md.nhc else {
+            return;
+        };
+
+        let scale = nhc.propagate(ke, dt);
+        self.scale_vels(scale);
+    }
+
+    /// Rebuild neighbor lists if any atom has moved more than half the skin since they were built.
+    /// See `MdState::update_neighbours`.
+    fn update_neighbours(&mut self, md: &MdState) {
+        let ext: Vec3F32 = md.cell.extent().into();
+
+        self.stream.memset_zeros(&mut self.scratch).unwrap();
+
+        let mut args = self.stream.launch_builder(&self.kernels.max_disp_sq);
+        args.arg(&mut self.scratch);
+        args.arg(&self.posits);
+        args.arg(&self.posits_at_build);
+        args.arg(&ext.x);
+        args.arg(&ext.y);
+        args.arg(&ext.z);
+        args.arg(&self.n_atoms);
+        unsafe { args.launch(launch_cfg(self.n_atoms)) }.unwrap();
+
+        let disp_sq = self.stream.memcpy_dtov(&self.scratch).unwrap()[0] as f64;
+
+        if disp_sq > (0.5 * SKIN).powi(2) {
+            self.build_neighbours(&md.cell);
+        }
+    }
+
+    /// One velocity Verlet step of `dt` fs, on the GPU. Matches `MdState::step`.
+    pub fn step(&mut self, md: &mut MdState, dt: f64) {
+        let n = self.n_atoms;
+        let report_due = md.report_due();
+        let cfg = launch_cfg(n);
+
+        let dt_half = 0.5 * dt;
+        let dt_f32 = dt as f32;
+
+        let lo: Vec3F32 = md.cell.lo.into();
+        let ext: Vec3F32 = md.cell.extent().into();
+
+        self.apply_nhc(md, dt_half);
+
+        {
+            let mut args = self.stream.launch_builder(&self.kernels.kick_drift);
+            args.arg(&mut self.posits);
+            args.arg(&mut self.vels);
+            args.arg(&mut self.accels);
+            args.arg(&dt_f32);
+            args.arg(&lo.x);
+            args.arg(&lo.y);
+            args.arg(&lo.z);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&n);
+            unsafe { args.launch(cfg) }.unwrap();
+        }
+
+        self.update_neighbours(md);
+
+        self.stream.memset_zeros(&mut self.terms).unwrap();
+
+        if self.n_bonds > 0 {
+            let mut args = self.stream.launch_builder(&self.kernels.bond_stretching);
+            args.arg(&mut self.accels);
+            args.arg(&mut self.terms);
+            args.arg(&self.posits);
+            args.arg(&self.masses);
+            args.arg(&self.bond_atoms);
+            args.arg(&self.bond_k);
+            args.arg(&self.bond_r_0);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&self.n_bonds);
+            unsafe { args.launch(launch_cfg(self.n_bonds)) }.unwrap();
+        }
+
+        if self.n_angles > 0 {
+            let mut args = self.stream.launch_builder(&self.kernels.angle_bending);
+            args.arg(&mut self.accels);
+            args.arg(&mut self.terms);
+            args.arg(&self.posits);
+            args.arg(&self.masses);
+            args.arg(&self.angle_atoms);
+            args.arg(&self.angle_k);
+            args.arg(&self.angle_θ_0);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&self.n_angles);
+            unsafe { args.launch(launch_cfg(self.n_angles)) }.unwrap();
+        }
+
+        if self.n_dihedrals > 0 {
+            let mut args = self.stream.launch_builder(&self.kernels.dihedral);
+            args.arg(&mut self.accels);
+            args.arg(&mut self.terms);
+            args.arg(&self.posits);
+            args.arg(&self.masses);
+            args.arg(&self.dihedral_atoms);
+            args.arg(&self.dihedral_barrier);
+            args.arg(&self.dihedral_phase);
+            args.arg(&self.dihedral_periodicity);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&self.n_dihedrals);
+            unsafe { args.launch(launch_cfg(self.n_dihedrals)) }.unwrap();
+        }
+
+        if self.n_cmap > 0 {
+            let mut args = self.stream.launch_builder(&self.kernels.cmap);
+            args.arg(&mut self.accels);
+            args.arg(&mut self.terms);
+            args.arg(&self.posits);
+            args.arg(&self.masses);
+            args.arg(&self.cmap_atoms);
+            args.arg(&self.cmap_grids);
+            args.arg(&self.cmap_grid_offsets);
+            args.arg(&self.cmap_grid_res);
+            args.arg(&self.cmap_coeffs);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&self.n_cmap);
+            unsafe { args.launch(launch_cfg(self.n_cmap)) }.unwrap();
+        }
+
+        let cutoff_sq = (CUTOFF * CUTOFF) as f32;
+        let (scale_lj_14, scale_coul_14) = md.nonbonded_scaling.factors_14();
+        let (scale_lj_14, scale_coul_14) = (scale_lj_14 as f32, scale_coul_14 as f32);
+        {
+            let mut args = self.stream.launch_builder(&self.kernels.nonbonded);
+            args.arg(&mut self.accels);
+            args.arg(&mut self.terms);
+            args.arg(&self.posits);
+            args.arg(&self.masses);
+            args.arg(&self.charges);
+            args.arg(&self.sigmas);
+            args.arg(&self.epss);
+            args.arg(&self.nb.offsets);
+            args.arg(&self.nb.indices);
+            args.arg(&self.nb.flags);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&cutoff_sq);
+            args.arg(&scale_lj_14);
+            args.arg(&scale_coul_14);
+            args.arg(&n);
+            unsafe { args.launch(cfg) }.unwrap();
+        }
+
+        if !md.atoms_static.is_empty() {
+            let mut args = self.stream.launch_builder(&self.kernels.nonbonded_static);
+            args.arg(&mut self.accels);
+            args.arg(&mut self.terms);
+            args.arg(&self.posits);
+            args.arg(&self.masses);
+            args.arg(&self.charges);
+            args.arg(&self.sigmas);
+            args.arg(&self.epss);
+            args.arg(&self.posits_static);
+            args.arg(&self.charges_static);
+            args.arg(&self.sigmas_static);
+            args.arg(&self.epss_static);
+            args.arg(&self.nb_static.offsets);
+            args.arg(&self.nb_static.indices);
+            args.arg(&ext.x);
+            args.arg(&ext.y);
+            args.arg(&ext.z);
+            args.arg(&cutoff_sq);
+            args.arg(&n);
+            unsafe { args.launch(cfg) }.unwrap();
+        }
+
+        {
+            let mut args = self.stream.launch_builder(&self.kernels.kick);
+            args.arg(&mut self.vels);
+            args.arg(&mut self.accels);
+            args.arg(&dt_f32);
+            args.arg(&n);
+            unsafe { args.launch(cfg) }.unwrap();
+        }
+
+        if let Some(Thermostat::Berendsen { tau: tau_ps }) = md.thermostat {
+            let tau = tau_ps * FS_PER_PS;
+            let curr_t = temperature(self.kinetic_energy(), 3 * n);
+            let λ = (1.0 + dt / tau * (md.target_temp - curr_t) / curr_t).sqrt();
+            self.scale_vels(λ);
+        }
+
+        self.apply_nhc(md, dt_half);
+
+        md.time += dt;
+        md.step_count += 1;
+
+        if md.com_removal_due() {
+            self.remove_com_motion(md);
+        }
+
+        if md.step_count % SNAPSHOT_RATIO == 0 {
+            self.download(md);
+            if !md.check_stability() {
+                return;
+            }
+            md.take_snapshot();
+        }
+        if report_due {
+            let terms = self.download_terms(md);
+            let kinetic = self.kinetic_energy();
+            md.reports.push(md.energy_report(kinetic, &terms));
+        }
+    }
+}
+
+impl MdState {
+    /// Run `n_steps` of `dt` fs on the GPU, if this system's features are supported there; otherwise,
+    /// on the CPU.
+    pub fn run_md(
+        &mut self,
+        stream: &Arc<CudaStream>,
+        module: &Arc<CudaModule>,
+        n_steps: usize,
+        dt: f64,
+    ) {
+        match MdGpu::new(stream, module, self) {
+            Ok(mut gpu) => {
+                self.progress.set_total(n_steps);
+
+                for _ in 0..n_steps {
+                    gpu.step(self, dt);
+                    if self.blow_up.is_some() {
+                        // `check_stability` restored the host's atoms; don't overwrite them.
+                        return;
+                    }
+                    if !self.progress.advance() {
+                        break;
+                    }
+                }
+                gpu.download(self);
+                // The CPU lists are stale; bring them up to date in case we continue on the CPU.
+                self.build_neighbours();
+            }
+            Err(e) => {
+                warn!("{}; running MD on the CPU.", e.descrip);
+                self.progress.set_total(n_steps);
+
+                for _ in 0..n_steps {
+                    self.step(dt);
+                    if self.blow_up.is_some() || !self.progress.advance() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Interactive MD: the simulation runs a few steps each rendered frame, and the user can pull a
+//! group of atoms toward the mouse cursor with a spring. This is useful for hands-on exploration of
+//! flexibility, and for pulling a ligand into, or out of, a pocket.
+//!
+//! The spring acts on the group's centroid, toward the point on the cursor's ray closest to it, so
+//! dragging moves atoms across the screen without pulling them toward or away from the camera.
+
+use lin_alg::f64::Vec3;
+
+use crate::dynamics::MdState;
+
+/// kcal/(mol·Å²)
+pub const PULL_K_DEFAULT: f64 = 5.;
+/// fs
+pub const LIVE_DT: f64 = 1.;
+/// Simulation steps per rendered frame.
+pub const LIVE_STEPS_PER_FRAME: usize = 10;
+
+/// A spring between the centroid of a group of mobile atoms and a point.
+#[derive(Clone, Debug)]
+pub struct UserPull {
+    /// Mobile atom indices.
+    pub atoms: Vec<usize>,
+    pub target: Vec3,
+    /// kcal/(mol·Å²)
+    pub k: f64,
+}
+
+impl MdState {
+    /// The centroid of a group of mobile atoms.
+    pub fn group_centroid(&self, atoms: &[usize]) -> Vec3 {
+        let mut sum = Vec3::new_zero();
+        for i in atoms {
+            sum += self.atoms[*i].posit;
+        }
+        sum / atoms.len().max(1) as f64
+    }
+
+    /// Returns the spring energy, in kcal/mol. The force is split evenly among the group's atoms.
+    pub(super) fn apply_user_pull(&mut self) -> f64 {
+        let Some(pull) = &self.user_pull else {
+            return 0.;
+        };
+        if pull.atoms.is_empty() {
+            return 0.;
+        }
+
+        let diff = pull.target - self.group_centroid(&pull.atoms);
+        let f_per_atom = diff * pull.k / pull.atoms.len() as f64;
+
+        for i in &pull.atoms {
+            let atom = &mut self.atoms[*i];
+            atom.accel += f_per_atom / atom.mass;
+        }
+
+        0.5 * pull.k * diff.magnitude_squared()
+    }
+}
+
+/// The point on the ray from `origin` through `toward` that's closest to `p`.
+pub fn closest_on_ray(origin: Vec3, toward: Vec3, p: Vec3) -> Vec3 {
+    let dir = toward - origin;
+    let len_sq = dir.magnitude_squared();
+    if len_sq < 1e-12 {
+        return origin;
+    }
+
+    let t = ((p - origin).dot(dir) / len_sq).max(0.);
+    origin + dir * t
+}
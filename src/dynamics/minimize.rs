@@ -0,0 +1,315 @@
+//! Energy minimization, using the same force field terms as `MdState::step`. We use this to relax
+//! clashes and strained geometry, e.g. of a ligand placed in a pocket, before running dynamics.
+//!
+//! Steepest descent is robust far from a minimum; L-BFGS converges much faster near one.
+//! Both limit how far any atom moves in a step.
+
+use std::collections::VecDeque;
+
+use lin_alg::f64::Vec3;
+
+use crate::dynamics::MdState;
+
+/// Number of previous steps L-BFGS uses to approximate the inverse Hessian.
+const LBFGS_MEMORY: usize = 8;
+/// Armijo sufficient-decrease constant for the L-BFGS line search.
+const ARMIJO_C: f64 = 1e-4;
+const LINE_SEARCH_MAX: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum MinimizeAlgorithm {
+    SteepestDescent,
+    #[default]
+    Lbfgs,
+}
+
+impl MinimizeAlgorithm {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::SteepestDescent => "Steepest descent",
+            Self::Lbfgs => "L-BFGS",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MinimizeConfig {
+    pub algorithm: MinimizeAlgorithm,
+    pub max_steps: usize,
+    /// kcal/(mol·Å). Converged once the largest force on any atom is below this.
+    pub force_tol: f64,
+    /// kcal/mol. Converged once an accepted step lowers the energy by less than this.
+    pub energy_tol: f64,
+    /// Å. No atom moves more than this in a single step.
+    pub step_max: f64,
+}
+
+impl Default for MinimizeConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Default::default(),
+            max_steps: 1_000,
+            force_tol: 0.5,
+            energy_tol: 1e-5,
+            step_max: 0.2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MinimizeResult {
+    pub steps: usize,
+    /// kcal/mol
+    pub energy_initial: f64,
+    /// kcal/mol
+    pub energy: f64,
+    /// kcal/(mol·Å)
+    pub force_max: f64,
+    pub converged: bool,
+}
+
+impl MinimizeResult {
+    pub fn descrip(&self) -> String {
+        format!(
+            "{} after {} steps. Energy: {:.2} → {:.2} kcal/mol. Max force: {:.3} kcal/(mol·Å)",
+            if self.converged {
+                "Converged"
+            } else {
+                "Not converged"
+            },
+            self.steps,
+            self.energy_initial,
+            self.energy,
+            self.force_max,
+        )
+    }
+}
+
+fn dot(a: &[Vec3], b: &[Vec3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a.dot(*b)).sum()
+}
+
+fn magnitude_max(v: &[Vec3]) -> f64 {
+    v.iter().map(|v| v.magnitude()).fold(0., f64::max)
+}
+
+impl MdState {
+    /// Potential energy, in kcal/mol, and the force on each atom, in kcal/(mol·Å), at the current
+    /// positions. Uses the same terms as `step`, without GaMD boosts. The steering anchor stays
+    /// where it is.
+    pub fn potential_and_forces(&mut self) -> (f64, Vec<Vec3>) {
+        for a in &mut self.atoms {
+            a.accel = Vec3::new_zero();
+        }
+
+        let mut energy = self.apply_bond_stretching_forces();
+        energy += self.apply_angle_bending_forces();
+        energy += self.apply_dihedral_forces();
+        energy += self.apply_cmap_forces();
+        energy += self.apply_nonbonded_forces();
+        energy += self.apply_cv_restraints();
+        energy += self.apply_position_restraints();
+        energy += self.apply_external_fields();
+        energy += self.apply_steering_spring();
+        energy += self.apply_user_pull();
+        // Frozen atoms have no force, so they don't move.
+        self.zero_frozen();
+
+        // These accumulate as force / mass.
+        let forces = self.atoms.iter().map(|a| a.accel * a.mass).collect();
+
+        for a in &mut self.atoms {
+            a.accel = Vec3::new_zero();
+        }
+
+        (energy, forces)
+    }
+
+    /// Move atoms, and rebuild the neighbor list if any have moved far enough since it was built
+    /// for it to be missing pairs.
+    fn set_posits_min(&mut self, posits: &[Vec3]) {
+        for (a, p) in self.atoms.iter_mut().zip(posits) {
+            a.posit = *p;
+        }
+        self.update_neighbours();
+    }
+
+    /// Minimize potential energy, moving atoms in place. Static and frozen atoms don't move.
+    /// Velocities are zeroed.
+    pub fn minimize(&mut self, cfg: &MinimizeConfig) -> MinimizeResult {
+        if self.atoms.is_empty() {
+            return MinimizeResult {
+                steps: 0,
+                energy_initial: 0.,
+                energy: 0.,
+                force_max: 0.,
+                converged: true,
+            };
+        }
+
+        self.build_neighbours();
+        for a in &mut self.atoms {
+            a.vel = Vec3::new_zero();
+        }
+
+        let mut posits: Vec<_> = self.atoms.iter().map(|a| a.posit).collect();
+
+        let (energy_initial, mut forces) = self.potential_and_forces();
+        let mut energy = energy_initial;
+        let mut force_max = magnitude_max(&forces);
+
+        // Steepest descent: the largest displacement this step, adapted to how steps go.
+        let mut step_size = cfg.step_max;
+        // L-BFGS: Position and gradient changes from recent steps, oldest first.
+        let mut history: VecDeque<(Vec<Vec3>, Vec<Vec3>)> = VecDeque::new();
+
+        let mut steps = 0;
+        let mut converged = force_max < cfg.force_tol;
+
+        while !converged && steps < cfg.max_steps {
+            steps += 1;
+
+            let (posits_new, energy_new, forces_new) = match cfg.algorithm {
+                MinimizeAlgorithm::SteepestDescent => {
+                    let scale = step_size / force_max;
+                    let trial: Vec<_> = posits
+                        .iter()
+                        .zip(&forces)
+                        .map(|(p, f)| *p + *f * scale)
+                        .collect();
+
+                    self.set_posits_min(&trial);
+                    let (e, f) = self.potential_and_forces();
+
+                    if e < energy {
+                        step_size = (step_size * 1.2).min(cfg.step_max);
+                        (trial, e, f)
+                    } else {
+                        // Rejected; try again with a smaller step.
+                        step_size *= 0.2;
+                        if step_size < 1e-6 {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                MinimizeAlgorithm::Lbfgs => {
+                    let Some(accepted) = self.lbfgs_step(&posits, energy, &forces, &history, cfg)
+                    else {
+                        if history.is_empty() {
+                            // Even a short step along the forces didn't lower the energy.
+                            break;
+                        }
+                        // The curvature history is leading us astray; start over from the gradient.
+                        history.clear();
+                        continue;
+                    };
+
+                    let (trial, e, f) = accepted;
+
+                    // The gradient is the negative of the force.
+                    let s: Vec<_> = trial.iter().zip(&posits).map(|(a, b)| *a - *b).collect();
+                    let y: Vec<_> = forces.iter().zip(&f).map(|(a, b)| *a - *b).collect();
+                    if dot(&s, &y) > 1e-10 {
+                        history.push_back((s, y));
+                        if history.len() > LBFGS_MEMORY {
+                            history.pop_front();
+                        }
+                    }
+
+                    (trial, e, f)
+                }
+            };
+
+            let energy_change = energy - energy_new;
+
+            posits = posits_new;
+            energy = energy_new;
+            forces = forces_new;
+            force_max = magnitude_max(&forces);
+
+            converged = force_max < cfg.force_tol || energy_change < cfg.energy_tol;
+        }
+
+        // Rejected trial steps may have left atoms elsewhere.
+        self.set_posits_min(&posits);
+
+        MinimizeResult {
+            steps,
+            energy_initial,
+            energy,
+            force_max,
+            converged,
+        }
+    }
+
+    /// One L-BFGS step, with a backtracking line search. Returns the new positions, energy, and
+    /// forces, or `None` if no step along the search direction lowers the energy.
+    fn lbfgs_step(
+        &mut self,
+        posits: &[Vec3],
+        energy: f64,
+        forces: &[Vec3],
+        history: &VecDeque<(Vec<Vec3>, Vec<Vec3>)>,
+        cfg: &MinimizeConfig,
+    ) -> Option<(Vec<Vec3>, f64, Vec<Vec3>)> {
+        // Two-loop recursion, giving the search direction -H·∇E = H·F.
+        let mut q = forces.to_vec();
+        let mut alphas = Vec::with_capacity(history.len());
+
+        for (s, y) in history.iter().rev() {
+            let ρ = 1. / dot(y, s);
+            let α = ρ * dot(s, &q);
+            for (q, y) in q.iter_mut().zip(y) {
+                *q -= *y * α;
+            }
+            alphas.push((ρ, α));
+        }
+
+        if let Some((s, y)) = history.back() {
+            let γ = dot(s, y) / dot(y, y);
+            for q in &mut q {
+                *q *= γ;
+            }
+        }
+
+        for ((s, y), (ρ, α)) in history.iter().zip(alphas.iter().rev()) {
+            let β = ρ * dot(y, &q);
+            for (q, s) in q.iter_mut().zip(s) {
+                *q += *s * (α - β);
+            }
+        }
+
+        let mut dir = q;
+
+        // Without history, this is a steepest descent step; set a sensible initial length.
+        let disp_max = magnitude_max(&dir);
+        if history.is_empty() || disp_max > cfg.step_max {
+            let scale = cfg.step_max / disp_max;
+            for d in &mut dir {
+                *d *= scale;
+            }
+        }
+
+        // Directional derivative of the energy; negative for a descent direction.
+        let slope = -dot(forces, &dir);
+        if slope >= 0. {
+            return None;
+        }
+
+        let mut α = 1.;
+        for _ in 0..LINE_SEARCH_MAX {
+            let trial: Vec<_> = posits.iter().zip(&dir).map(|(p, d)| *p + *d * α).collect();
+
+            self.set_posits_min(&trial);
+            let (e, f) = self.potential_and_forces();
+
+            if e <= energy + ARMIJO_C * α * slope {
+                return Some((trial, e, f));
+            }
+            α *= 0.5;
+        }
+
+        None
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(non_snake_case)]
+
 //! This module contains a traditional molecular dynamics approach
 //!
 //! [Good article](https://www.owlposting.com/p/a-primer-on-molecular-dynamics)
@@ -29,35 +31,77 @@
 // Note on timescale: Generally femtosecond (-15)
 
 mod ambient;
+pub mod barostat;
+mod cell_list;
+pub mod cmap;
+pub mod colvar;
+pub mod external_fields;
+pub mod flexible;
+pub mod gamd;
+pub mod gb;
+#[cfg(feature = "cuda")]
+pub mod gpu;
+pub mod interactive;
+pub mod minimize;
+pub mod postprocess;
 pub mod prep;
+pub mod protein;
+pub mod protocol;
+pub mod report;
+pub mod restraints;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub mod simd;
+pub mod solvent;
+pub mod stability;
+pub mod steered;
+pub mod templates;
+pub mod thermostat;
+pub mod torsion_scan;
+pub mod validation;
 mod water_opc;
 
-use std::{
-    collections::{HashMap, HashSet},
-    f64::consts::TAU,
-};
+use std::collections::{HashMap, HashSet};
 
 use ambient::SimBox;
+use barostat::{BarostatParams, BarostatState};
+use bincode::{Decode, Encode};
 use bio_files::amber_params::{
     AngleBendingParams, BondStretchingParams, DihedralParams, MassParams, VdwParams,
 };
-use lin_alg::f64::{Vec3, calc_dihedral_angle_v2};
+use cmap::{CmapGrid, CmapTerm};
+use colvar::CvRestraint;
+use external_fields::ExternalFields;
+use gamd::{GamdParams, GamdState};
+use interactive::UserPull;
+use lin_alg::f64::Vec3;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use lin_alg::f64::{Vec3x4, f64x4};
+use log::Level;
+use minimize::MinimizeConfig;
 use na_seq::Element;
+use protocol::TempProtocol;
+use rand::{SeedableRng, rngs::StdRng};
 use rand_distr::Distribution;
+use rayon::prelude::*;
+use report::{EnergyReport, ReportTerms};
+use restraints::{PositionRestraint, RESTRAINT_K_DEFAULT};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use simd::PairChunk;
+use solvent::SolvationConfig;
+use stability::{BlowUp, StabilityConfig};
+use steered::{SteeringParams, SteeringState};
+use thermostat::{NhcState, Thermostat};
+use thiserror::Error;
+use validation::ParamReport;
 
 use crate::{
     forces::{force_coulomb, force_lj},
-    molecule::{Atom, Bond},
+    logging::Span,
+    molecule::{Atom, Bond, Ligand},
+    progress::Progress,
+    units::{ACCEL_CONV, COULOMB_CONST, FS_PER_PS, K_B, kinetic_energy, temperature},
 };
 
-// vacuum permittivity constant   (k_e = 1/(4π ε0))
-// SI, matched with charge in e₀ & Å → kcal mol // todo: QC
-const ε_0: f64 = 8.8541878128e-1;
-const k_e: f32 = 332.0636; // kcal·Å /(mol·e²)
-// const ε_0: f64 = 1.;
-
 // Verlet list parameters
 const CUTOFF: f64 = 12.0; // Å
 const SKIN: f64 = 2.0; // Å – rebuild list if an atom moved >½·SKIN
@@ -67,11 +111,22 @@ const R_OH: f64 = 0.9572; // Å
 const ANG_HOH: f64 = 104.52_f64.to_radians();
 
 // See Amber RM, sectcion 15, "1-4 Non-Bonded Interaction Scaling"
-const SCALE_LJ_14: f64 = 0.5; // AMBER default
-const SCALE_COUL_14: f64 = 1.0 / 1.2; // 0.833̅
+const SCEE_AMBER: f64 = 1.2;
+const SCNB_AMBER: f64 = 2.0;
 
 const SOFTENING_FACTOR_SQ: f64 = 1e-6;
 
+/// The fewest atoms a thread handles when computing nonbonded forces. Each thread allocates a
+/// force buffer for all atoms, so we don't want to split too finely.
+const NONBONDED_CHUNK_MIN: usize = 256;
+
+/// Steps between removing center-of-mass motion. Amber's default. (`nscm`)
+const COM_REMOVAL_INTERVAL: usize = 1_000;
+
+/// Steps between energy reports, by default. Amber's default is 50 (`ntpr`); we're sparser, as
+/// these are for checking equilibration, not analysis.
+const REPORT_INTERVAL_DEFAULT: usize = 500;
+
 // Conversion factor
 // 2^(5/6); no powf in consts.
 const SIGMA_FROM_R_MIN: f64 = 1.7817974362806785;
@@ -81,7 +136,8 @@ const SNAPSHOT_RATIO: usize = 10;
 
 const EPS: f64 = 1.0e-8;
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
+#[error("{descrip}")]
 pub struct ParamError {
     pub descrip: String,
 }
@@ -106,27 +162,27 @@ pub struct ForceFieldParamsIndexed {
     pub mass: HashMap<usize, MassParams>,
     pub bond_stretching: HashMap<(usize, usize), BondStretchingParams>,
     pub angle: HashMap<(usize, usize, usize), AngleBendingParams>,
-    /// This includes both normal, and improper dihedrals.
-    pub dihedral: HashMap<(usize, usize, usize, usize), DihedralParams>,
-    // pub dihedral: HashMap<(usize, usize, usize, usize), Option<DihedralData>>,
-
-    // Dihedrals are represented in Amber params as a fourier series; this Vec indlues all matches.
-    // e.g. X-ca-ca-X may be present multiple times in gaff2.dat. (Although seems to be uncommon)
-    //
-    // X -nh-sx-X    4    3.000         0.000          -2.000
-    // X -nh-sx-X    4    0.400       180.000           3.000
-
-    // pub dihedral: HashMap<(usize, usize, usize, usize), Vec<DihedralData>>,
-    // pub improper: HashMap<(usize, usize, usize, usize), DihedralData>,
+    /// This includes both normal, and improper dihedrals. Dihedrals are represented in Amber params
+    /// as a Fourier series, so each may have several terms, e.g. in `gaff2.dat`:
+    ///
+    /// ```text
+    /// X -nh-sx-X    4    3.000         0.000          -2.000
+    /// X -nh-sx-X    4    0.400       180.000           3.000
+    /// ```
+    pub dihedral: HashMap<(usize, usize, usize, usize), Vec<DihedralParams>>,
     pub van_der_waals: HashMap<usize, VdwParams>,
     // pub partial_charge: HashMap<usize, f32>, // todo: A/r
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Encode, Decode)]
 pub struct SnapshotDynamics {
     pub time: f64,
     pub atom_posits: Vec<Vec3>,
     pub atom_velocities: Vec<Vec3>,
+    /// kcal/mol. See `MdState::energy_conserved`.
+    pub energy_conserved: f64,
+    /// The periodic box at this time. It changes with the barostat.
+    pub cell: SimBox,
 }
 
 #[derive(Clone, Debug)]
@@ -175,6 +231,7 @@ impl AtomDynamics {
             mass: ff_params.mass.get(&i).unwrap().mass as f64,
             // We get partial charge for ligands from (e.g. Amber-provided) Mol files, so we load it from the atom, vice
             // the loaded FF params. They are not in the dat or frcmod files that angle, bond-length etc params are from.
+            // `MdState::new` fills in Gasteiger charges for atoms that don't have one.
             partial_charge: atom.partial_charge.unwrap_or_default() as f64,
             lj_sigma: ff_params.van_der_waals.get(&i).unwrap().sigma as f64,
             lj_eps: ff_params.van_der_waals.get(&i).unwrap().eps as f64,
@@ -183,48 +240,155 @@ impl AtomDynamics {
     }
 }
 
+/// 4 atoms, packed lane-wise for SIMD. (AoSoA)
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[derive(Clone, Debug)]
-pub(crate) struct AtomDynamicsx4 {
-    // pub posit: Vec3x8,
-    // pub vel: Vec3x8,
-    // pub accel: Vec3x8,
-    // pub mass: f32x8,
+pub struct AtomDynamicsx4 {
     pub posit: Vec3x4,
     pub vel: Vec3x4,
     pub accel: Vec3x4,
     pub mass: f64x4,
+    pub partial_charge: f64x4,
+    pub lj_sigma: f64x4,
+    pub lj_eps: f64x4,
     pub element: [Element; 4],
 }
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 impl AtomDynamicsx4 {
-    pub fn from_array(bodies: [AtomDynamics; 4]) -> Self {
-        let mut posits = [Vec3::new_zero(); 4];
-        let mut vels = [Vec3::new_zero(); 4];
-        let mut accels = [Vec3::new_zero(); 4];
-        let mut masses = [0.0; 4];
-        // Replace `Element::H` (for example) with some valid default for your `Element` type:
-        let mut elements = [Element::Hydrogen; 4];
-
-        for (i, body) in bodies.into_iter().enumerate() {
-            posits[i] = body.posit;
-            vels[i] = body.vel;
-            accels[i] = body.accel;
-            masses[i] = body.mass;
-            elements[i] = body.element;
+    pub fn from_array(bodies: [&AtomDynamics; 4]) -> Self {
+        let pack_vec = |f: fn(&AtomDynamics) -> Vec3| Vec3x4::from_array(bodies.map(f));
+        let pack = |f: fn(&AtomDynamics) -> f64| f64x4::from_array(bodies.map(f));
+
+        Self {
+            posit: pack_vec(|a| a.posit),
+            vel: pack_vec(|a| a.vel),
+            accel: pack_vec(|a| a.accel),
+            mass: pack(|a| a.mass),
+            partial_charge: pack(|a| a.partial_charge),
+            lj_sigma: pack(|a| a.lj_sigma),
+            lj_eps: pack(|a| a.lj_eps),
+            element: bodies.map(|a| a.element),
+        }
+    }
+}
+
+/// User-configurable simulation settings.
+/// How nonbonded interactions are treated between atoms a few bonds apart. 1-4 pairs, which share a
+/// dihedral, are scaled down; pairs closer than that are excluded. Force fields differ on this; it
+/// must match the one the parameters come from.
+#[derive(Clone, Debug)]
+pub struct NonbondedScaling {
+    /// 1-4 Coulomb interactions are divided by this.
+    pub scee: f64,
+    /// 1-4 Lennard-Jones interactions are divided by this.
+    pub scnb: f64,
+    /// Pairs separated by this many bonds or fewer are excluded. 2 excludes 1-2 and 1-3 pairs; 3
+    /// excludes 1-4 pairs too, vice scaling them.
+    pub exclusion_depth: u8,
+}
+
+impl Default for NonbondedScaling {
+    fn default() -> Self {
+        Self::amber()
+    }
+}
+
+impl NonbondedScaling {
+    /// Amber force fields, e.g. ff19SB, and GAFF2.
+    pub fn amber() -> Self {
+        Self {
+            scee: SCEE_AMBER,
+            scnb: SCNB_AMBER,
+            exclusion_depth: 2,
         }
+    }
+
+    /// Factors to scale 1-4 Lennard-Jones and Coulomb terms by.
+    pub fn factors_14(&self) -> (f64, f64) {
+        (1. / self.scnb, 1. / self.scee)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MdConfig {
+    /// If present, run Gaussian accelerated MD.
+    pub gamd: Option<GamdParams>,
+    pub external_fields: ExternalFields,
+    pub minimize: MinimizeConfig,
+    /// If `None`, we run at constant energy. (NVE)
+    pub thermostat: Option<Thermostat>,
+    /// K
+    pub target_temp: f64,
+    /// If present, we couple pressure with a Monte Carlo barostat. (NPT)
+    pub barostat: Option<BarostatParams>,
+    /// If present, surround the system with explicit water.
+    pub solvation: Option<SolvationConfig>,
+    /// Record energies, temperature, and pressure every this many steps. 0 to disable.
+    pub report_interval: usize,
+    /// If present, ramp the target temperature through these stages, vice holding it at
+    /// `target_temp`. Requires a thermostat.
+    pub protocol: Option<TempProtocol>,
+    /// If present, pull a group of atoms with a moving harmonic spring. (Steered MD)
+    pub steering: Option<SteeringParams>,
+    /// Mobile atoms tethered to their starting positions, with `restraint_k`.
+    pub restrained: Vec<usize>,
+    /// kcal/mol/Å²
+    pub restraint_k: f64,
+    /// Mobile atoms held fixed.
+    pub frozen: Vec<usize>,
+    /// Receptor residues whose sidechains are mobile, vice static.
+    pub flexible_residues: Vec<usize>,
+    pub nonbonded_scaling: NonbondedScaling,
+    /// Run with fallbacks for missing force field parameters, vice refusing to. See
+    /// `MdState::check_params`.
+    pub allow_missing_params: bool,
+    /// K. Draw initial velocities from the Maxwell-Boltzmann distribution at this temperature. If
+    /// `None`, we use `target_temp` with a thermostat, and start at rest without one.
+    pub init_temp: Option<f64>,
+    /// If present, seed initial velocities with this, so runs are reproducible.
+    pub velocity_seed: Option<u64>,
+    /// Remove center-of-mass motion every this many steps. 0 to disable.
+    pub com_removal_interval: usize,
+    /// Limits for detecting blow-ups.
+    pub stability: StabilityConfig,
+}
 
+impl Default for MdConfig {
+    fn default() -> Self {
         Self {
-            posit: Vec3x4::from_array(posits),
-            vel: Vec3x4::from_array(vels),
-            accel: Vec3x4::from_array(accels),
-            mass: f64x4::from_array(masses),
-            element: elements,
+            gamd: None,
+            external_fields: Default::default(),
+            minimize: Default::default(),
+            thermostat: None,
+            target_temp: 300.,
+            barostat: None,
+            solvation: None,
+            report_interval: REPORT_INTERVAL_DEFAULT,
+            protocol: None,
+            steering: None,
+            restrained: Vec::new(),
+            restraint_k: RESTRAINT_K_DEFAULT,
+            frozen: Vec::new(),
+            flexible_residues: Vec::new(),
+            nonbonded_scaling: Default::default(),
+            allow_missing_params: false,
+            init_temp: None,
+            velocity_seed: None,
+            com_removal_interval: COM_REMOVAL_INTERVAL,
+            stability: Default::default(),
         }
     }
 }
 
+/// The result of an MD run in a thread. See `protein::start_protein_md`, and
+/// `docking::dynamics::start_dock_md`.
+pub struct MdRun {
+    pub md: MdState,
+    /// For docking runs: the ligand, with the run's final positions.
+    pub ligand: Option<Ligand>,
+}
+
 #[derive(Default)]
 pub struct MdState {
     pub atoms: Vec<AtomDynamics>,
@@ -240,53 +404,142 @@ pub struct MdState {
     ///     pub lj_lut: LjTable,
     pub lj_sigma: Vec<f64>,
     pub lj_eps: Vec<f64>,
-    // todo: Implment these SIMD variants A/R, bearing in mind the caveat about our built-in ones vs
-    // todo ones loaded from [e.g. Amber] files.
-    // #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    // pub lj_sigma_x8: Vec<f64x4>,
-    // #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    // pub lj_eps_x8: Vec<f64x4>,
     /// In femtoseconds,
     pub time: f64,
     pub step_count: usize, // increments.
     pub snapshots: Vec<SnapshotDynamics>,
     pub cell: SimBox,
-    neighbour: Vec<Vec<usize>>,    // Verlet list
-    max_disp_sq: f64,              // track atom displacements²
-    pub kb_berendsen: Option<f64>, // coupling constant (ps⁻¹) if you want a thermostat
-    pub target_temp: f64,
+    neighbour: Vec<Vec<usize>>, // Verlet list
+    /// Static atoms near each mobile atom. Rebuilt with `neighbour`.
+    neighbour_static: Vec<Vec<usize>>,
+    /// `neighbour` and `neighbour_static`, packed for SIMD. See `pack_neighbours`.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    neighbour_x4: Vec<Vec<PairChunk>>,
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    neighbour_static_x4: Vec<Vec<PairChunk>>,
+    /// Atom positions when the Verlet lists were last built. We rebuild them once any atom
+    /// moves more than half the skin from here.
+    posits_at_build: Vec<Vec3>,
+    /// Set with `set_thermostat`. If `None`, no thermostat.
+    thermostat: Option<Thermostat>,
+    /// Present when using a Nosé-Hoover chain thermostat.
+    nhc: Option<NhcState>,
+    /// K
+    target_temp: f64,
+    /// kcal/mol. From the most recent step.
+    pub energy_potential: f64,
+    /// If present, we scale the cell, and molecule positions, to maintain a target pressure.
+    pub barostat: Option<BarostatState>,
+    /// Energies, temperature, and pressure over time. See `report_interval`.
+    pub reports: Vec<EnergyReport>,
+    /// Steps between reports. 0 to disable.
+    pub report_interval: usize,
+    /// Present during steps that record a report; the force loops accumulate into it.
+    report_terms: Option<ReportTerms>,
     /// Exclusions / masks optimization.
     excluded_pairs: HashSet<(usize, usize)>, // 1-2 and 1-3
     /// See Amber RM, sectcion 15, "1-4 Non-Bonded Interaction Scaling"
     scaled14_pairs: HashSet<(usize, usize)>, // 1-4
+    /// Gaussian accelerated MD. If present, we boost the potential after an initial cMD stage.
+    pub gamd: Option<GamdState>,
+    /// Harmonic biases on collective variables. Not boosted by GaMD.
+    pub cv_restraints: Vec<CvRestraint>,
+    /// Electric field, walls, and containment. Not boosted by GaMD.
+    pub external_fields: ExternalFields,
+    /// A moving spring pulling a group of atoms. Not boosted by GaMD.
+    pub steering: Option<SteeringState>,
+    /// A spring the user drags with the mouse, in interactive MD. Not boosted by GaMD.
+    pub user_pull: Option<UserPull>,
+    /// Harmonic tethers to reference positions. Not boosted by GaMD.
+    pub position_restraints: Vec<PositionRestraint>,
+    /// Indexed by atom; atoms past its end aren't frozen. Set with `freeze_atoms`.
+    frozen: Vec<bool>,
+    /// (Mobile atom index, receptor atom index) of receptor atoms made mobile. See `add_flexible`,
+    /// and `new_protein`.
+    pub flexible: Vec<(usize, usize)>,
+    /// Waters added by `solvate`.
+    pub num_waters: usize,
+    /// Backbone φ/ψ correction maps, e.g. from ff19SB.
+    pub cmap_grids: Vec<CmapGrid>,
+    pub cmap_terms: Vec<CmapTerm>,
+    /// Set with `set_nonbonded_scaling`; this determines the masks.
+    nonbonded_scaling: NonbondedScaling,
+    /// Parameters missing from the force fields, for atoms added so far. See `check_params`.
+    pub param_report: ParamReport,
+    /// See `MdConfig::velocity_seed`.
+    pub velocity_seed: Option<u64>,
+    /// Steps between removing center-of-mass motion. 0 to disable. See `remove_com_motion`.
+    pub com_removal_interval: usize,
+    pub stability: StabilityConfig,
+    /// Set if the run halted due to instability; see `check_stability`.
+    pub blow_up: Option<BlowUp>,
+    /// Counts steps of the current run. If cancelled, the run stops at the next step, keeping
+    /// the snapshots so far.
+    pub progress: Progress,
+    /// Set once snapshots are superposed with `align_snapshots`. Their boxes no longer match their
+    /// positions, so PBC fixes can't be applied after.
+    pub snapshots_aligned: bool,
+    /// kcal/mol. The conserved energy at the first stability check, for measuring drift.
+    energy_ref: Option<f64>,
+    /// Consecutive stability checks with excessive drift.
+    drift_count: usize,
 }
 
 impl MdState {
     /// One **Velocity-Verlet** step (leap-frog style) of length `dt_fs` femtoseconds.
     pub fn step(&mut self, dt: f64) {
+        let _span = Span::with_level(Level::Trace, "MD step");
         let dt_half = 0.5 * dt;
 
+        // Nosé-Hoover chains are split around the velocity Verlet step; half here, and half at
+        // the end.
+        self.apply_nhc(dt_half);
+
         // 1) First half-kick (v += a dt/2) and drift (x += v dt)
         // todo: Do we want traditional verlet instead?
         for a in &mut self.atoms {
             a.vel += a.accel * dt_half; // Half-kick
             a.posit += a.vel * dt; // Drift
             a.posit = self.cell.wrap(a.posit);
-
-            // track the largest squared displacement to know when to rebuild the list
-            self.max_disp_sq = self.max_disp_sq.max((a.vel * dt).magnitude_squared());
         }
 
+        // Rebuild the Verlet lists before computing forces, if atoms have moved far enough.
+        self.update_neighbours();
+
+        self.report_terms = self.report_due().then(ReportTerms::default);
+
         // Reset acceleration.
         for a in &mut self.atoms {
             a.accel = Vec3::new_zero();
         }
 
-        self.apply_bond_stretching_forces();
-        self.apply_angle_bending_forces();
-        // todo: Dihedral not working. Skipping for now. Our measured and expected angles aren't lining up.
-        // self.apply_dihedral_forces();
-        self.apply_nonbonded_forces();
+        let mut v_total = self.apply_bond_stretching_forces();
+        v_total += self.apply_angle_bending_forces();
+        v_total += self.apply_cmap_forces();
+        // With a GaMD dihedral boost, these are computed separately, so they can be scaled.
+        if !self
+            .gamd
+            .as_ref()
+            .is_some_and(|g| g.params.mode.boosts_dihedral())
+        {
+            v_total += self.apply_dihedral_forces();
+        }
+        v_total += self.apply_nonbonded_forces();
+
+        self.apply_gamd_boost(v_total);
+        let v_bias = self.apply_cv_restraints()
+            + self.apply_position_restraints()
+            + self.apply_external_fields()
+            + self.apply_steering(dt)
+            + self.apply_user_pull();
+
+        self.energy_potential = v_total + v_bias;
+
+        // Forces are accumulated as force / mass; convert to Å/fs².
+        for a in &mut self.atoms {
+            a.accel *= ACCEL_CONV;
+        }
+        self.zero_frozen();
 
         // Second half-kick using new accelerations
         for a in &mut self.atoms {
@@ -294,41 +547,140 @@ impl MdState {
         }
 
         // Berendsen thermostat (T coupling to target every step)
-        if let Some(tau_ps) = self.kb_berendsen {
-            let tau = tau_ps * 1e-12;
+        if let Some(Thermostat::Berendsen { tau: tau_ps }) = self.thermostat {
+            let tau = tau_ps * FS_PER_PS;
             let curr_ke = self.current_kinetic_energy();
-            let curr_t = 2.0 * curr_ke / (3.0 * self.atoms.len() as f64 * 0.0019872041); // k_B in kcal/mol
+            let curr_t = temperature(curr_ke, self.dof());
             let λ = (1.0 + dt / tau * (self.target_temp - curr_t) / curr_t).sqrt();
             for a in &mut self.atoms {
                 a.vel *= λ;
             }
         }
 
+        self.apply_nhc(dt_half);
+
         self.time += dt;
         self.step_count += 1;
 
-        // Rebuild Verlet if needed
-        if self.max_disp_sq > 0.25 * SKIN * SKIN {
-            self.build_neighbours();
+        if self.com_removal_due() {
+            self.remove_com_motion();
         }
+        self.apply_barostat();
 
         if self.step_count % SNAPSHOT_RATIO == 0 {
+            if !self.check_stability() {
+                return;
+            }
             self.take_snapshot();
+            self.record_steering();
+        }
+        if let Some(terms) = self.report_terms.take() {
+            let kinetic = self.current_kinetic_energy();
+            self.reports.push(self.energy_report(kinetic, &terms));
+        }
+    }
+
+    /// Scales the accelerations computed this step by the GaMD boost factors, if GaMD is enabled.
+    /// For dihedral boosts, we compute dihedral forces here, separately from the others, so we can
+    /// scale them independently. `v_total` is the unboosted potential; in that case, it excludes
+    /// dihedrals.
+    fn apply_gamd_boost(&mut self, mut v_total: f64) {
+        let Some(gamd) = &self.gamd else {
+            return;
+        };
+
+        let mut v_dihedral = 0.;
+        let mut accel_other = Vec::new();
+
+        if gamd.params.mode.boosts_dihedral() {
+            accel_other = self.atoms.iter().map(|a| a.accel).collect();
+            for a in &mut self.atoms {
+                a.accel = Vec3::new_zero();
+            }
+
+            v_dihedral = self.apply_dihedral_forces();
+            v_total += v_dihedral;
         }
+
+        let Some(gamd) = &mut self.gamd else {
+            return;
+        };
+        let (factor_total, factor_dihedral) =
+            gamd.update(self.step_count, self.time, v_total, v_dihedral);
+
+        if accel_other.is_empty() {
+            for a in &mut self.atoms {
+                a.accel *= factor_total;
+            }
+        } else {
+            for (a, accel_other) in self.atoms.iter_mut().zip(accel_other) {
+                a.accel = (accel_other + a.accel * factor_dihedral) * factor_total;
+            }
+        }
+    }
+
+    /// Returns potential energy, in kcal/mol.
+    fn apply_external_fields(&mut self) -> f64 {
+        if self.external_fields.is_empty() {
+            return 0.;
+        }
+
+        let mut energy = 0.;
+        for a in &mut self.atoms {
+            let (e, f) = self.external_fields.forces(a, &self.cell);
+            energy += e;
+            a.accel += f / a.mass;
+        }
+
+        energy
+    }
+
+    /// Returns bias energy, in kcal/mol.
+    fn apply_cv_restraints(&mut self) -> f64 {
+        if self.cv_restraints.is_empty() {
+            return 0.;
+        }
+
+        let posits: Vec<_> = self.atoms.iter().map(|a| a.posit).collect();
+        let mut energy = 0.;
+
+        for restraint in &self.cv_restraints {
+            let (e, forces) = restraint.forces(&posits);
+            energy += e;
+
+            for (i, f) in forces {
+                let atom = &mut self.atoms[i];
+                atom.accel += f / atom.mass;
+            }
+        }
+
+        energy
     }
 
-    fn apply_bond_stretching_forces(&mut self) {
+    /// Returns potential energy, in kcal/mol.
+    fn apply_bond_stretching_forces(&mut self) -> f64 {
+        let mut energy = 0.;
+        let mut virial = 0.;
+
         for (indices, params) in &self.force_field_params.bond_stretching {
             let (a_0, a_1) = split2_mut(&mut self.atoms, indices.0, indices.1);
 
-            let f = f_bond_stretching(a_0.posit, a_1.posit, params);
+            // Bonded atoms may be wrapped to opposite sides of the cell.
+            let posit_1 = a_0.posit + self.cell.min_image(a_1.posit - a_0.posit);
+
+            let f = f_bond_stretching(a_0.posit, posit_1, params);
 
-            const KCALMOL_A_TO_A_FS2_PER_AMU: f64 = 4.184e-4;
-            // todo: Multiply accels by this?? Or are our units self-consistent.
+            // Amber convention: V = k(r - r₀)²; no factor of ½.
+            let r_delta = (posit_1 - a_0.posit).magnitude() - params.r_0 as f64;
+            energy += params.k_b as f64 * r_delta * r_delta;
+            virial -= (posit_1 - a_0.posit).dot(f);
 
             a_0.accel += f / a_0.mass;
             a_1.accel -= f / a_1.mass;
         }
+
+        self.report_bonded(energy, virial);
+        energy
     }
 
     /// This maintains bond angles between sets of three atoms as they should be from hybridization.
@@ -339,101 +691,93 @@ impl MdState {
     /// Valence angles, which are the angle formed by two adjacent bonds ba et bc
     /// in a same molecule; a valence angle tends to maintain constant the anglê
     /// abc. A valence angle is thus concerned by the positions of three atoms.
-    fn apply_angle_bending_forces(&mut self) {
+    ///
+    /// Returns potential energy, in kcal/mol.
+    fn apply_angle_bending_forces(&mut self) -> f64 {
+        let mut energy = 0.;
+        let mut virial = 0.;
+
         for (indices, params) in &self.force_field_params.angle {
             let (a_0, a_1, a_2) = split3_mut(&mut self.atoms, indices.0, indices.1, indices.2);
 
-            let (f_0, f_1, f_2) = f_angle_bending(a_0.posit, a_1.posit, a_2.posit, params);
+            let bond_vec_01 = self.cell.min_image(a_0.posit - a_1.posit);
+            let bond_vec_21 = self.cell.min_image(a_2.posit - a_1.posit);
+
+            let (f_0, f_1, f_2) = f_angle_bending(
+                a_1.posit + bond_vec_01,
+                a_1.posit,
+                a_1.posit + bond_vec_21,
+                params,
+            );
+
+            let cos_θ =
+                (bond_vec_01.to_normalized().dot(bond_vec_21.to_normalized())).clamp(-1.0, 1.0);
+            let Δθ = params.theta_0 as f64 - cos_θ.acos();
+            energy += params.k as f64 * Δθ * Δθ;
+            virial += bond_vec_01.dot(f_0) + bond_vec_21.dot(f_2);
 
             a_0.accel += f_0 / a_0.mass;
             a_1.accel += f_1 / a_1.mass;
             a_2.accel += f_2 / a_2.mass;
         }
+
+        self.report_bonded(energy, virial);
+        energy
     }
 
     /// This maintains dihedral angles. (i.e. the angle between four atoms in a sequence). This models
     /// effects such as σ-bond overlap (e.g. staggered conformations), π-conjugation, which locks certain
     /// dihedrals near 0 or τ, and steric hindrance. (Bulky groups clashing).
-    fn apply_dihedral_forces(&mut self) {
-        for (indices, dihe) in &self.force_field_params.dihedral {
-            // let Some(dihe) = dihe_ else { continue };
-
-            if &dihe.atom_types.0 == "X" || &dihe.atom_types.3 == "X" {
-                continue; // todo temp, until we sum.
-            }
+    ///
+    /// Returns potential energy, in kcal/mol.
+    fn apply_dihedral_forces(&mut self) -> f64 {
+        let mut energy = 0.;
+        let mut virial = 0.;
 
+        for (indices, terms) in &self.force_field_params.dihedral {
             // Split the four atoms mutably without aliasing
             let (a_0, a_1, a_2, a_3) =
                 split4_mut(&mut self.atoms, indices.0, indices.1, indices.2, indices.3);
 
-            // Convenience aliases for the positions
+            // Bonded atoms may be wrapped to opposite sides of the cell.
             let r_0 = a_0.posit;
-            let r_1 = a_1.posit;
-            let r_2 = a_2.posit;
-            let r_3 = a_3.posit;
-
-            // Bond vectors (see Allen & Tildesley, chap. 4)
-            let b1 = r_1 - r_0; // r_ij
-            let b2 = r_2 - r_1; // r_kj
-            let b3 = r_3 - r_2; // r_lk
-
-            // Normal vectors to the two planes
-            let n1 = b1.cross(b2);
-            let n2 = b3.cross(b2);
-
-            let n1_sq = n1.magnitude_squared();
-            let n2_sq = n2.magnitude_squared();
-            let b2_len = b2.magnitude();
-
-            // Bail out if the four atoms are (nearly) colinear
-            if n1_sq < EPS || n2_sq < EPS || b2_len < EPS {
-                continue;
-            }
-
-            let dihe_measured = calc_dihedral_angle_v2(&(r_0, r_1, r_2, r_3));
-
-            // Note: We assume that vn has already been divided by the integer divisor.
-            // let dV_dφ = -0.5
-            // todo: Precompute this barrier height when loading to the indexed variant.
-            // todo: Do that once this all owrks.
-            // let dV_dφ =  -(dihe.barrier_height_vn as f64) / (dihe.integer_divisor as f64)
-
-            let k = -dihe.barrier_height as f64;
-            let per = dihe.periodicity as f64;
-            let arg = per * dihe_measured - dihe.phase as f64;
-
-            let dV_dφ = k * per * arg.sin();
-
-            if self.step_count == 0 {
-                println!(
-                    "{:?} - Ms: {:.2}, exp: {:.2}/{} dV_dφ: {:.2}",
-                    &dihe.atom_types,
-                    dihe_measured / TAU,
-                    dihe.phase / TAU as f32,
-                    dihe.periodicity,
-                    dV_dφ,
-                );
+            let r_1 = r_0 + self.cell.min_image(a_1.posit - r_0);
+            let r_2 = r_1 + self.cell.min_image(a_2.posit - r_1);
+            let r_3 = r_2 + self.cell.min_image(a_3.posit - r_2);
+
+            for dihe in terms {
+                let Some((e, f)) = f_dihedral(
+                    [r_0, r_1, r_2, r_3],
+                    dihe.barrier_height as f64,
+                    dihe.periodicity as f64,
+                    dihe.phase as f64,
+                ) else {
+                    continue;
+                };
+                energy += e;
+                virial += (r_1 - r_0).dot(f[1]) + (r_2 - r_0).dot(f[2]) + (r_3 - r_0).dot(f[3]);
+
+                // Convert to accelerations
+                a_0.accel += f[0] / a_0.mass;
+                a_1.accel += f[1] / a_1.mass;
+                a_2.accel += f[2] / a_2.mass;
+                a_3.accel += f[3] / a_3.mass;
             }
+        }
 
-            // ∂φ/∂r   (see e.g. DOI 10.1016/S0021-9991(97)00040-8)
-            let dφ_dr1 = -n1 * (b2_len / n1_sq);
-            let dφ_dr4 = n2 * (b2_len / n2_sq);
-
-            let dφ_dr2 =
-                -n1 * (b1.dot(b2) / (b2_len * n1_sq)) + n2 * (b3.dot(b2) / (b2_len * n2_sq));
-            let dφ_dr3 = -dφ_dr1 - dφ_dr2 - dφ_dr4; // Newton’s third law
-
-            // F_i = −dV/dφ · ∂φ/∂r_i
-            let f1 = -dφ_dr1 * dV_dφ;
-            let f2 = -dφ_dr2 * dV_dφ;
-            let f3 = -dφ_dr3 * dV_dφ;
-            let f4 = -dφ_dr4 * dV_dφ;
+        self.report_bonded(energy, virial);
+        energy
+    }
 
-            // Convert to accelerations
-            a_0.accel += f1 / a_0.mass;
-            a_1.accel += f2 / a_1.mass;
-            a_2.accel += f3 / a_2.mass;
-            a_3.accel += f4 / a_3.mass;
+    /// Factors to scale the Lennard-Jones and Coulomb terms between mobile atoms `i` and `j` by,
+    /// with `i < j`. `None` if the pair is excluded.
+    pub(super) fn pair_scale(&self, i: usize, j: usize) -> Option<(f64, f64)> {
+        if self.excluded_pairs.contains(&(i, j)) {
+            None
+        } else if self.scaled14_pairs.contains(&(i, j)) {
+            Some(self.nonbonded_scaling.factors_14())
+        } else {
+            Some((1., 1.))
         }
     }
 
@@ -443,124 +787,223 @@ impl MdState {
     /// todo you're having trouble with LJ. Applies to Coulomb as well.
     /// todo: Are these already applied in the params, or do you need to scale? Likely; see that RM section.
     ///
-    /// todo: If required, build a neighbors list for interactions with external atoms.
-    fn apply_nonbonded_forces(&mut self) {
-        let cutoff_sq = CUTOFF * CUTOFF;
-
-        const EPS: f64 = 1e-6;
-
-        for i in 0..self.atoms.len() {
-            // todo: Can you unify this with your neighbor code used for bonds?
-            for &j in &self.neighbour[i] {
-                if j < i {
-                    // Prevents duplication of the pair in the other order.
-                    continue;
-                }
-
-                // Handle masks.
-                let key = if i < j { (i, j) } else { (j, i) };
+    /// We split atoms across threads. Each thread accumulates forces into its own buffer, which we
+    /// sum at the end, so each pair is computed once.
+    ///
+    /// Returns potential energy, in kcal/mol.
+    pub fn apply_nonbonded_forces(&mut self) -> f64 {
+        let n = self.atoms.len();
+
+        // Use AVX, if available.
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        let simd = is_x86_feature_detected!("avx");
+
+        // Σ r·f is cheap next to the forces, so we always accumulate it with the energies.
+        let (forces, mut terms) = (0..n)
+            .into_par_iter()
+            .with_min_len(NONBONDED_CHUNK_MIN)
+            .fold(
+                || (vec![Vec3::new_zero(); n], ReportTerms::default()),
+                |(mut forces, mut terms), i| {
+                    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                    if simd {
+                        terms += self.nonbonded_atom_x4(i, &mut forces);
+                        return (forces, terms);
+                    }
+
+                    for &j in &self.neighbour[i] {
+                        // Each pair is in both atoms' lists; handle it once.
+                        if j < i {
+                            continue;
+                        }
+
+                        // Handle masks.
+                        let Some((scale_lj, scale_coul)) = self.pair_scale(i, j) else {
+                            continue;
+                        };
+
+                        let dv = self
+                            .cell
+                            .min_image(self.atoms[j].posit - self.atoms[i].posit);
+
+                        if let Some((f, e_lj, e_coul)) =
+                            f_nonbonded(dv, &self.atoms[i], &self.atoms[j], scale_lj, scale_coul)
+                        {
+                            forces[i] += f;
+                            forces[j] -= f;
+                            terms.lj += e_lj;
+                            terms.coulomb += e_coul;
+                            terms.virial -= dv.dot(f);
+                        }
+                    }
+                    (forces, terms)
+                },
+            )
+            .reduce(
+                || (vec![Vec3::new_zero(); n], ReportTerms::default()),
+                |(mut forces, terms), (forces_other, terms_other)| {
+                    for (f, f_other) in forces.iter_mut().zip(forces_other) {
+                        *f += f_other;
+                    }
+                    (forces, terms + terms_other)
+                },
+            );
+
+        for (a, f) in self.atoms.iter_mut().zip(forces) {
+            a.accel += f / a.mass;
+        }
 
-                if self.excluded_pairs.contains(&key) {
-                    continue;
+        // Second pass: Static atoms. These don't accumulate forces, so each atom is independent.
+        let static_results: Vec<(Vec3, ReportTerms)> = self
+            .atoms
+            .par_iter()
+            .enumerate()
+            .map(|(i, a_lig)| {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if simd {
+                    return self.nonbonded_static_x4(i);
                 }
 
-                let scale14 = self.scaled14_pairs.contains(&key);
+                let mut force = Vec3::new_zero();
+                let mut terms = ReportTerms::default();
 
-                let diff = self.atoms[j].posit - self.atoms[i].posit;
+                for &j in &self.neighbour_static[i] {
+                    let a_static = &self.atoms_static[j];
+                    let dv = self.cell.min_image(a_static.posit - a_lig.posit);
 
-                let dv = self.cell.min_image(diff);
-                let r_sq = dv.magnitude_squared();
-                if r_sq > cutoff_sq {
-                    continue;
+                    if let Some((f, e_lj, e_coul)) = f_nonbonded(dv, a_lig, a_static, 1., 1.) {
+                        force += f;
+                        terms.lj += e_lj;
+                        terms.coulomb += e_coul;
+                        terms.virial -= dv.dot(f);
+                    }
                 }
+                (force, terms)
+            })
+            .collect();
 
-                let dist = r_sq.sqrt();
-                let dir = diff / dist;
-
-                // Note: Amber params are loaded using R_min instead of σ, but we address
-                // this when parsing them.
-                let σ = 0.5 * (self.atoms[i].lj_sigma + self.atoms[j].lj_sigma);
-                let ε = (self.atoms[i].lj_eps * self.atoms[j].lj_eps).sqrt();
+        for (a_lig, (f, t)) in self.atoms.iter_mut().zip(static_results) {
+            a_lig.accel += f / a_lig.mass;
+            terms += t;
+        }
 
-                let mut f_lj = force_lj(dir, dist, σ, ε);
+        if let Some(report) = &mut self.report_terms {
+            *report += terms;
+        }
+        terms.lj + terms.coulomb
+    }
 
-                let mut f_coulomb = force_coulomb(
-                    dir,
-                    dist,
-                    self.atoms[i].partial_charge,
-                    self.atoms[j].partial_charge,
-                    SOFTENING_FACTOR_SQ,
-                );
+    /// Set the thermostat, and its target temperature, in K. Resets Nosé-Hoover chain state.
+    pub fn set_thermostat(&mut self, thermostat: Option<Thermostat>, target_temp: f64) {
+        self.thermostat = thermostat;
+        self.target_temp = target_temp;
 
-                if scale14 {
-                    f_lj *= SCALE_LJ_14;
-                    f_coulomb *= SCALE_COUL_14;
-                }
+        self.nhc = match thermostat {
+            Some(Thermostat::NoseHooverChain { tau, chain_len }) => {
+                Some(NhcState::new(chain_len, tau, self.dof(), target_temp))
+            }
+            _ => None,
+        };
+    }
 
-                let f = f_lj + f_coulomb;
+    /// Draw atom velocities from the Maxwell-Boltzmann distribution at `temp`, in K, seeded with
+    /// `velocity_seed` if present. We then remove center-of-mass motion, and rescale, so the
+    /// system starts at exactly `temp`.
+    pub fn init_velocities(&mut self, temp: f64) {
+        let mut rng = match self.velocity_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
 
-                let accel_0 = f / self.atoms[i].mass;
-                let accel_1 = f / self.atoms[j].mass;
+        for a in &mut self.atoms {
+            let σ = (K_B * temp * ACCEL_CONV / a.mass).sqrt();
+            let normal = rand_distr::Normal::new(0., σ).unwrap();
+
+            a.vel = Vec3::new(
+                normal.sample(&mut rng),
+                normal.sample(&mut rng),
+                normal.sample(&mut rng),
+            );
+        }
+        self.zero_frozen();
+        self.remove_com_motion();
 
-                self.atoms[i].accel += accel_0;
-                self.atoms[j].accel -= accel_1;
+        let temp_drawn = temperature(self.current_kinetic_energy(), self.dof());
+        if temp_drawn > 0. {
+            let scale = (temp / temp_drawn).sqrt();
+            for a in &mut self.atoms {
+                a.vel *= scale;
             }
         }
+    }
 
-        // Second pass: Static atoms.
-        for a_lig in &mut self.atoms {
-            for a_static in &self.atoms_static {
-                let dv = self.cell.min_image(a_static.posit - a_lig.posit);
-
-                // todo: This section DRY with non-external interactions.
-
-                let r_sq = dv.magnitude_squared();
-                if r_sq > cutoff_sq {
-                    continue;
-                }
-
-                // println!(
-                //     "Lig σ: {:.3} lig ε: {:.3} ext σ: {:.3} ext ε: {:.3} lig q: {:.3} ext q: {:.3}",
-                //     a_lig.lj_sigma,
-                //     a_lig.lj_eps,
-                //     a_static.lj_sigma,
-                //     a_static.lj_eps,
-                //     a_lig.partial_charge,
-                //     a_static.partial_charge,
-                // );
-
-                let σ = 0.5 * (a_lig.lj_sigma + a_static.lj_sigma);
-                let ε = (a_lig.lj_eps * a_static.lj_eps).sqrt();
-
-                let dist = r_sq.sqrt();
-                let dir = dv / dist;
+    /// Subtract the center-of-mass velocity from mobile atoms that aren't frozen, so the system
+    /// doesn't drift, e.g. from accumulated integration and cutoff errors.
+    pub fn remove_com_motion(&mut self) {
+        let mut momentum = Vec3::new_zero();
+        let mut mass = 0.;
 
-                let f_lj = force_lj(dir, dist, σ, ε);
+        for (i, a) in self.atoms.iter().enumerate() {
+            if !self.frozen.get(i).copied().unwrap_or_default() {
+                momentum += a.vel * a.mass;
+                mass += a.mass;
+            }
+        }
+        if mass == 0. {
+            return;
+        }
 
-                let f_coulomb = force_coulomb(
-                    dir,
-                    dist,
-                    a_lig.partial_charge,
-                    a_static.partial_charge,
-                    SOFTENING_FACTOR_SQ,
-                );
+        let v_com = momentum / mass;
+        for (i, a) in self.atoms.iter_mut().enumerate() {
+            if !self.frozen.get(i).copied().unwrap_or_default() {
+                a.vel -= v_com;
+            }
+        }
+    }
 
-                let f = f_lj + f_coulomb;
+    /// We only remove center-of-mass motion periodically when momentum would otherwise be
+    /// conserved. Static atoms (e.g. a rigid receptor), frozen atoms, restraints, and steering
+    /// exert net forces, so removing it would suppress real motion relative to them.
+    pub(super) fn com_removal_due(&self) -> bool {
+        self.com_removal_interval > 0
+            && self.step_count % self.com_removal_interval == 0
+            && self.atoms_static.is_empty()
+            && self.position_restraints.is_empty()
+            && self.steering.is_none()
+            && !self.frozen.iter().any(|f| *f)
+    }
 
-                // todo: Experimenting with a scaler for docking trial+error.
-                let scaler = 1.;
+    /// Propagate the Nosé-Hoover chain, if present, for `dt` fs, and scale velocities to match.
+    fn apply_nhc(&mut self, dt: f64) {
+        let ke = self.current_kinetic_energy();
+        let Some(nhc) = &mut self.nhc else {
+            return;
+        };
 
-                a_lig.accel += f / a_lig.mass * scaler;
-            }
+        let scale = nhc.propagate(ke, dt);
+        for a in &mut self.atoms {
+            a.vel *= scale;
         }
     }
 
-    /// A helper for the thermostat
+    /// The extended system's energy, in kcal/mol: kinetic and potential, and for Nosé-Hoover
+    /// chains, the thermostat's. This is conserved by NVE and Nosé-Hoover runs; drift indicates
+    /// too large a time step. It isn't conserved with Berendsen, or GaMD.
+    pub fn energy_conserved(&self) -> f64 {
+        let thermostat = self
+            .nhc
+            .as_ref()
+            .map(|nhc| nhc.energy())
+            .unwrap_or_default();
+        self.current_kinetic_energy() + self.energy_potential + thermostat
+    }
+
+    /// A helper for the thermostat. kcal/mol
     #[inline]
     fn current_kinetic_energy(&self) -> f64 {
         self.atoms
             .iter()
-            .map(|a| 0.5 * a.mass * a.vel.magnitude_squared())
+            .map(|a| kinetic_energy(a.mass, a.vel.magnitude_squared()))
             .sum()
     }
 
@@ -569,6 +1012,8 @@ impl MdState {
             time: self.time,
             atom_posits: self.atoms.iter().map(|a| a.posit).collect(),
             atom_velocities: self.atoms.iter().map(|a| a.vel).collect(),
+            energy_conserved: self.energy_conserved(),
+            cell: self.cell,
         })
     }
 }
@@ -633,6 +1078,67 @@ pub fn split4_mut<T>(
     }
 }
 
+/// Lennard-Jones potential, in kcal/mol. σ is in Å. ε is in kcal/mol. An f64 variant of
+/// `forces::V_lj`.
+pub fn V_lj(dist: f64, σ: f64, ε: f64) -> f64 {
+    if dist < EPS {
+        return 0.;
+    }
+
+    let sr_6 = (σ / dist).powi(6);
+    4. * ε * (sr_6 * sr_6 - sr_6)
+}
+
+/// Coulomb potential, in kcal/mol. Charges are in e; distance in Å.
+pub fn V_coulomb(dist: f64, q0: f64, q1: f64, softening_factor_sq: f64) -> f64 {
+    COULOMB_CONST * q0 * q1 / (dist.powi(2) + softening_factor_sq).sqrt()
+}
+
+/// Coulomb and Lennard-Jones force on atom 0 from atom 1, in kcal/(mol·Å), and the Lennard-Jones
+/// and Coulomb potential energies, in kcal/mol. `dv` points from atom 0 to atom 1. `None` if beyond
+/// the cutoff. The terms are scaled by `scale_lj` and `scale_coul`; e.g. for 1-4 pairs.
+pub fn f_nonbonded(
+    dv: Vec3,
+    a_0: &AtomDynamics,
+    a_1: &AtomDynamics,
+    scale_lj: f64,
+    scale_coul: f64,
+) -> Option<(Vec3, f64, f64)> {
+    let r_sq = dv.magnitude_squared();
+    if r_sq > CUTOFF * CUTOFF {
+        return None;
+    }
+
+    let dist = r_sq.sqrt();
+    let dir = dv / dist;
+
+    // Note: Amber params are loaded using R_min instead of σ, but we address
+    // this when parsing them.
+    let σ = 0.5 * (a_0.lj_sigma + a_1.lj_sigma);
+    let ε = (a_0.lj_eps * a_1.lj_eps).sqrt();
+
+    let f_lj = force_lj(dir, dist, σ, ε) * scale_lj;
+
+    // `force_coulomb` takes the direction from the source; here, atom 1.
+    let f_coulomb = force_coulomb(
+        -dir,
+        dist,
+        a_0.partial_charge,
+        a_1.partial_charge,
+        SOFTENING_FACTOR_SQ,
+    ) * (COULOMB_CONST * scale_coul);
+
+    let v_lj = V_lj(dist, σ, ε) * scale_lj;
+    let v_coulomb = V_coulomb(
+        dist,
+        a_0.partial_charge,
+        a_1.partial_charge,
+        SOFTENING_FACTOR_SQ,
+    ) * scale_coul;
+
+    Some((f_lj + f_coulomb, v_lj, v_coulomb))
+}
+
 /// Returns the force on the atom at position 0. Negate this for the force on posit 1.
 pub fn f_bond_stretching(posit_0: Vec3, posit_1: Vec3, params: &BondStretchingParams) -> Vec3 {
     let diff = posit_1 - posit_0;
@@ -640,9 +1146,8 @@ pub fn f_bond_stretching(posit_0: Vec3, posit_1: Vec3, params: &BondStretchingPa
 
     let r_delta = dist_measured - params.r_0 as f64;
 
-    // todo: Do I need to multiply by 2?
-    // Unit check: kcal/mol/Å² * Å² = kcal/mol. (Energy).
-    let f_mag = params.k_b as f64 * r_delta / dist_measured.max(1e-12);
+    // V = k(r - r₀)², so |F| = 2k(r - r₀). Unit check: kcal/mol/Å² * Å = kcal/mol/Å.
+    let f_mag = 2. * params.k_b as f64 * r_delta / dist_measured.max(1e-12);
     diff * f_mag
 }
 
@@ -695,3 +1200,50 @@ pub fn f_angle_bending(
 
     (f_0, f_1, f_2)
 }
+
+/// The dihedral angle, IUPAC convention, in radians, and its gradient with respect to each atom's
+/// position. `None` if either plane is degenerate.
+/// [Blondel and Karplus, 1996](https://doi.org/10.1002/(SICI)1096-987X(199607)17:9%3C1132::AID-JCC5%3E3.0.CO;2-T)
+pub fn dihedral_grad(r: [Vec3; 4]) -> Option<(f64, [Vec3; 4])> {
+    let f = r[0] - r[1];
+    let g = r[1] - r[2];
+    let h = r[3] - r[2];
+
+    let a = f.cross(g);
+    let b = h.cross(g);
+    let (a_sq, b_sq) = (a.magnitude_squared(), b.magnitude_squared());
+    let g_len = g.magnitude();
+
+    if a_sq < 1e-10 || b_sq < 1e-10 || g_len < 1e-10 {
+        return None;
+    }
+
+    let angle = (b.cross(a).dot(g) / g_len).atan2(a.dot(b));
+
+    let d_0 = -a * (g_len / a_sq);
+    let d_3 = b * (g_len / b_sq);
+    let (fg, hg) = (f.dot(g) / (a_sq * g_len), h.dot(g) / (b_sq * g_len));
+
+    let d_1 = -d_0 + a * fg - b * hg;
+    let d_2 = -d_3 - a * fg + b * hg;
+
+    Some((angle, [d_0, d_1, d_2, d_3]))
+}
+
+/// Energy, in kcal/mol, and the force on each atom, in kcal/(mol·Å), of a proper or improper
+/// dihedral term: V = V_n (1 + cos(nφ - γ)). `barrier_height` (V_n) is pre-divided by the Amber
+/// divider. `None` if the atoms are (nearly) colinear.
+pub fn f_dihedral(
+    posits: [Vec3; 4],
+    barrier_height: f64,
+    periodicity: f64,
+    phase: f64,
+) -> Option<(f64, [Vec3; 4])> {
+    let (φ, dφ) = dihedral_grad(posits)?;
+
+    let arg = periodicity * φ - phase;
+    let dV_dφ = -barrier_height * periodicity * arg.sin();
+
+    // F_i = −dV/dφ · ∂φ/∂r_i
+    Some((barrier_height * (1. + arg.cos()), dφ.map(|d| -d * dV_dφ)))
+}
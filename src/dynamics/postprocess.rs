@@ -0,0 +1,166 @@
+//! Trajectory post-processing, for playback and analysis. Atoms are wrapped into the periodic box
+//! each step, so molecules can be split across its faces, and jump by a box length when they
+//! cross one. We make molecules whole, then either unwrap them, so their paths are continuous, or
+//! reimage them around the largest molecule, e.g. the protein. Separately, we can superpose frames
+//! onto the first, removing global rotation and translation.
+//!
+//! Fix PBC artifacts before aligning; rotated frames no longer line up with the box.
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    dynamics::{MdState, ParamError},
+    superpose::kabsch,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum PbcMode {
+    /// Each molecule's path is continuous, even if it leaves the box.
+    #[default]
+    Unwrap,
+    /// Each molecule is placed at the periodic image closest to the largest molecule.
+    Reimage,
+}
+
+impl PbcMode {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Unwrap => "Unwrap",
+            Self::Reimage => "Reimage",
+        }
+    }
+}
+
+fn centroid(posits: &[Vec3], atoms: &[(usize, Option<usize>)]) -> Vec3 {
+    let mut sum = Vec3::new_zero();
+    for (i, _) in atoms {
+        sum += posits[*i];
+    }
+    sum / atoms.len().max(1) as f64
+}
+
+impl MdState {
+    /// Molecules: connected components of the mobile atoms' bond graph. Each is in breadth-first
+    /// order, with each atom's parent in the traversal.
+    fn molecules_bfs(&self) -> Vec<Vec<(usize, Option<usize>)>> {
+        let n = self.atoms.len();
+        let mut visited = vec![false; n];
+        let mut result = Vec::new();
+
+        for root in 0..n {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+
+            let mut mol = vec![(root, None)];
+            let mut head = 0;
+            while head < mol.len() {
+                let i = mol[head].0;
+                head += 1;
+
+                for &j in self.adjacency_list.get(i).into_iter().flatten() {
+                    if j < n && !visited[j] {
+                        visited[j] = true;
+                        mol.push((j, Some(i)));
+                    }
+                }
+            }
+            result.push(mol);
+        }
+
+        result
+    }
+
+    /// Make molecules whole in each snapshot, then unwrap or reimage them.
+    pub fn fix_pbc(&mut self, mode: PbcMode) -> Result<(), ParamError> {
+        if self.snapshots_aligned {
+            return Err(ParamError::new(
+                "Snapshots are already aligned; fix PBC before aligning",
+            ));
+        }
+
+        let mols = self.molecules_bfs();
+        let largest = (0..mols.len()).max_by_key(|m| mols[*m].len());
+        // For unwrapping: each molecule's centroid in the previous frame.
+        let mut centers_prev: Vec<Option<Vec3>> = vec![None; mols.len()];
+
+        for snap in &mut self.snapshots {
+            let cell = snap.cell;
+            if cell.volume() <= 0. || snap.atom_posits.len() < self.atoms.len() {
+                continue;
+            }
+            let posits = &mut snap.atom_posits;
+
+            // Bonded atoms are placed at each other's nearest image.
+            for mol in &mols {
+                for (i, parent) in mol {
+                    if let Some(p) = parent {
+                        posits[*i] = posits[*p] + cell.min_image(posits[*i] - posits[*p]);
+                    }
+                }
+            }
+
+            let centers: Vec<_> = mols.iter().map(|m| centroid(posits, m)).collect();
+
+            for (m, mol) in mols.iter().enumerate() {
+                let target = match mode {
+                    PbcMode::Unwrap => centers_prev[m],
+                    PbcMode::Reimage => largest.filter(|l| *l != m).map(|l| centers[l]),
+                };
+                let Some(target) = target else {
+                    continue;
+                };
+
+                let shift = target + cell.min_image(centers[m] - target) - centers[m];
+                for (i, _) in mol {
+                    posits[*i] += shift;
+                }
+                centers_prev[m] = Some(centers[m] + shift);
+            }
+
+            // The first frame, or the largest molecule when reimaging, stays as is.
+            for (m, c) in centers.iter().enumerate() {
+                if centers_prev[m].is_none() {
+                    centers_prev[m] = Some(*c);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Superpose each snapshot onto the first, over `fit_atoms`, which index mobile atoms.
+    /// Velocities are rotated to match.
+    pub fn align_snapshots(&mut self, fit_atoms: &[usize]) -> Result<(), ParamError> {
+        let Some(first) = self.snapshots.first() else {
+            return Err(ParamError::new("No snapshots to align"));
+        };
+        if fit_atoms.len() < 3 || fit_atoms.iter().any(|i| *i >= first.atom_posits.len()) {
+            return Err(ParamError::new(
+                "Alignment requires at least 3 simulated atoms",
+            ));
+        }
+
+        let reference: Vec<_> = fit_atoms.iter().map(|i| first.atom_posits[*i]).collect();
+
+        for snap in &mut self.snapshots {
+            let mobile: Vec<_> = fit_atoms.iter().map(|i| snap.atom_posits[*i]).collect();
+            let Some(xform) = kabsch(&mobile, &reference) else {
+                continue;
+            };
+
+            for p in &mut snap.atom_posits {
+                *p = xform.apply(*p);
+            }
+            // Rotation only.
+            let origin = xform.apply(Vec3::new_zero());
+            for v in &mut snap.atom_velocities {
+                *v = xform.apply(*v) - origin;
+            }
+        }
+
+        self.snapshots_aligned = true;
+        Ok(())
+    }
+}
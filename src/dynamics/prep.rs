@@ -29,16 +29,134 @@ use bio_files::{
 };
 use itertools::Itertools;
 use lin_alg::f64::Vec3;
+use log::{debug, info, trace, warn};
 use na_seq::{AminoAcid, AminoAcidGeneral, AminoAcidProtenationVariant, element::LjTable};
 
 use crate::{
     FfParamSet,
+    docking::partial_charge::gasteiger_charges,
     dynamics::{
-        AtomDynamics, CUTOFF, ForceFieldParamsIndexed, MdState, ParamError, SKIN, ambient::SimBox,
+        AtomDynamics, CUTOFF, ForceFieldParamsIndexed, MdConfig, MdState, NonbondedScaling,
+        ParamError, SKIN,
+        ambient::SimBox,
+        barostat::BarostatState,
+        cell_list::CellGrid,
+        gamd::GamdState,
+        templates::ResidueTemplate,
+        validation::{MissingParam, ParamReport},
     },
+    error::{DaedalusError, ResultExt},
     molecule::{Atom, Bond, Residue},
 };
 
+/// Å. Space between the simulated atoms and the edges of the sim box.
+pub(super) const CELL_PAD: f64 = 15.;
+
+/// Multi-term torsions, by atom types. Amber lists these on consecutive lines, with a negative
+/// periodicity on all but the last; `ForceFieldParamsKeyed` keeps one line per type quad, so we
+/// collect the full series from the parameter text. See `parse_dihedral_series`.
+pub type DihedralSeries = HashMap<(String, String, String, String), Vec<FourierTerm>>;
+
+/// One term of a torsion's Fourier series.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FourierTerm {
+    /// kcal/mol, divided by the line's divider (IDIVF).
+    pub barrier_height: f32,
+    /// Radians.
+    pub phase: f32,
+    pub periodicity: u8,
+}
+
+/// Parse multi-term proper dihedrals from the text of an Amber `dat` or `frcmod` file. Series
+/// with a single term are omitted; the keyed parameters cover those.
+pub fn parse_dihedral_series(text: &str) -> DihedralSeries {
+    let mut result = HashMap::new();
+    let mut pending: Option<((String, String, String, String), Vec<FourierTerm>)> = None;
+
+    for line in text.lines() {
+        let Some((types, term, last)) = parse_dihedral_line(line) else {
+            pending = None;
+            continue;
+        };
+
+        match &mut pending {
+            Some((key, terms)) if *key == types => terms.push(term),
+            _ => pending = Some((types, vec![term])),
+        }
+
+        if last {
+            if let Some((key, terms)) = pending.take() {
+                if terms.len() > 1 {
+                    result.insert(key, terms);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Parse a proper dihedral line, e.g. `CT-CT-OS-CT   1    0.383    0.0   -3.`: Types, the divider,
+/// barrier height, phase in degrees, and periodicity, which is negative if more terms follow.
+/// Returns the types, the term, and whether it ends its series. Improper lines have no divider;
+/// their 4th field is a comment, so they don't match.
+fn parse_dihedral_line(
+    line: &str,
+) -> Option<((String, String, String, String), FourierTerm, bool)> {
+    let types: Vec<_> = line.get(..11)?.split('-').map(str::trim).collect();
+    if types.len() != 4
+        || types
+            .iter()
+            .any(|t| t.is_empty() || t.contains(char::is_whitespace))
+    {
+        return None;
+    }
+
+    let mut fields = line[11..].split_whitespace().map(|f| f.parse::<f32>().ok());
+    let divider = fields.next()??;
+    let barrier_height = fields.next()??;
+    let phase = fields.next()??;
+    let periodicity = fields.next()??;
+
+    if periodicity == 0. {
+        return None;
+    }
+
+    let term = FourierTerm {
+        barrier_height: barrier_height / divider.max(1.),
+        phase: phase.to_radians(),
+        periodicity: periodicity.abs().round() as u8,
+    };
+    let types = (
+        types[0].to_owned(),
+        types[1].to_owned(),
+        types[2].to_owned(),
+        types[3].to_owned(),
+    );
+
+    Some((types, term, periodicity > 0.))
+}
+
+/// Find the Fourier series for a proper dihedral, in either direction, falling back to the
+/// `X-j-k-X` wildcard, as Amber does.
+fn find_series<'a>(
+    series: &'a DihedralSeries,
+    (ti, tj, tk, tl): (&str, &str, &str, &str),
+) -> Option<&'a Vec<FourierTerm>> {
+    let key = |a: &str, b: &str, c: &str, d: &str| {
+        (a.to_owned(), b.to_owned(), c.to_owned(), d.to_owned())
+    };
+
+    [
+        key(ti, tj, tk, tl),
+        key(tl, tk, tj, ti),
+        key("X", tj, tk, "X"),
+        key("X", tk, tj, "X"),
+    ]
+    .iter()
+    .find_map(|k| series.get(k))
+}
+
 /// Build a single lookup table in which ligand-specific parameters
 /// (when given) replace or add to the generic ones.
 pub fn merge_params(
@@ -71,9 +189,11 @@ impl ForceFieldParamsIndexed {
     pub fn new(
         params_general: &ForceFieldParamsKeyed,
         params_specific: Option<&ForceFieldParamsKeyed>,
+        dihedral_series: &DihedralSeries,
         atoms: &[Atom],
         bonds: &[Bond],
         adjacency_list: &[Vec<usize>],
+        report: &mut ParamReport,
     ) -> Result<Self, ParamError> {
         let mut result = Self::default();
 
@@ -91,15 +211,14 @@ impl ForceFieldParamsIndexed {
             if let Some(mass) = params.mass.get(ff_type) {
                 result.mass.insert(i, mass.clone());
             } else {
+                report.add(MissingParam::Mass(ff_type.clone()));
+
                 if ff_type.starts_with("C") {
                     result.mass.insert(i, params.mass.get("C").unwrap().clone());
-                    println!("Using C fallback mass for {ff_type}");
                 } else if ff_type.starts_with("N") {
                     result.mass.insert(i, params.mass.get("N").unwrap().clone());
-                    println!("Using N fallback mass for {ff_type}");
                 } else if ff_type.starts_with("O") {
                     result.mass.insert(i, params.mass.get("O").unwrap().clone());
-                    println!("Using O fallback mass for {ff_type}");
                 } else {
                     // todo: This is not a good way to do it. Fall back to element-derived etc.
                     result.mass.insert(
@@ -110,12 +229,6 @@ impl ForceFieldParamsIndexed {
                             comment: None,
                         },
                     );
-
-                    println!("Missing mass params for {ff_type}");
-
-                    // return Err(ParamError::new(&format!(
-                    //     "Missing mass params for {ff_type}"
-                    // )));
                 }
             }
 
@@ -134,23 +247,21 @@ impl ForceFieldParamsIndexed {
             if let Some(vdw) = params.van_der_waals.get(ff_type) {
                 result.van_der_waals.insert(i, vdw.clone());
             } else {
+                report.add(MissingParam::Vdw(ff_type.clone()));
+
                 if ff_type.starts_with("C") {
                     result
                         .van_der_waals
                         .insert(i, params.van_der_waals.get("C*").unwrap().clone());
-                    println!("Using C* fallback VdW for {ff_type}");
                 } else if ff_type.starts_with("N") {
                     result
                         .van_der_waals
                         .insert(i, params.van_der_waals.get("N").unwrap().clone());
-                    println!("Using N fallback VdW for {ff_type}");
                 } else if ff_type.starts_with("O") {
                     result
                         .van_der_waals
                         .insert(i, params.van_der_waals.get("O").unwrap().clone());
-                    println!("Using O fallback VdW for {ff_type}");
                 } else {
-                    println!("Missing Van der Waals params for {ff_type}");
                     // 0. no interaction.
                     // todo: If this is "CG" etc, fall back to other carbon params instead.
                     result.van_der_waals.insert(
@@ -162,10 +273,6 @@ impl ForceFieldParamsIndexed {
                         },
                     );
                 }
-
-                // return Err(ParamError::new(&format!(
-                //     "Missing Van der Waals params for {ff_type}"
-                // )));
             }
         }
 
@@ -180,13 +287,16 @@ impl ForceFieldParamsIndexed {
             let data = params
                 .bond
                 .get(&(type_i.clone(), type_j.clone()))
-                .or_else(|| params.bond.get(&(type_j.clone(), type_i.clone())))
-                .cloned()
-                .ok_or_else(|| {
-                    ParamError::new(&format!("Missing bond parameters for {type_i}-{type_j}"))
-                })?;
+                .or_else(|| params.bond.get(&(type_j.clone(), type_i.clone())));
 
-            result.bond_stretching.insert((i.min(j), i.max(j)), data);
+            match data {
+                Some(d) => {
+                    result
+                        .bond_stretching
+                        .insert((i.min(j), i.max(j)), d.clone());
+                }
+                None => report.add(MissingParam::bond(type_i, type_j)),
+            }
         }
 
         // Angles. (Between 3 atoms)
@@ -212,15 +322,14 @@ impl ForceFieldParamsIndexed {
                         params
                             .angle
                             .get(&(type_2.clone(), type_1.clone(), type_0.clone()))
-                    })
-                    .cloned()
-                    .ok_or_else(|| {
-                        ParamError::new(&format!(
-                            "Missing valence angle parameters for {type_0}-{type_1}-{type_2}"
-                        ))
-                    })?;
-
-                result.angle.insert((i, center, k), data);
+                    });
+
+                match data {
+                    Some(d) => {
+                        result.angle.insert((i, center, k), d.clone());
+                    }
+                    None => report.add(MissingParam::angle(type_0, type_1, type_2)),
+                }
             }
         }
 
@@ -326,14 +435,41 @@ impl ForceFieldParamsIndexed {
                             .get_dihedral(&(ti.clone(), tj.clone(), tk.clone(), tl.clone()), true)
                         {
                             let mut dihe = dihe.clone();
-                            // I believe this may be pri-divided.
-                            // dihe.barrier_height /= dihe.divider as f32; // pre-divide
+                            // Amber lists V_n × the divider (IDIVF), e.g. 4 for `X-C-N-X`, which
+                            // spreads a bond's barrier over its torsions. Pre-divide, so the
+                            // force loops don't need it.
+                            dihe.barrier_height /= dihe.divider.max(1) as f32;
                             dihe.divider = 1;
-                            result.dihedral.insert(idx_key, dihe);
+
+                            // The keyed parameters hold one term of the series. Use the full
+                            // series only if it contains that term; otherwise, a more specific
+                            // parameter set replaced it.
+                            let terms = match find_series(
+                                dihedral_series,
+                                (ti.as_str(), tj.as_str(), tk.as_str(), tl.as_str()),
+                            ) {
+                                Some(series)
+                                    if series.iter().any(|t| {
+                                        t.periodicity as f32 == dihe.periodicity as f32
+                                            && (t.barrier_height - dihe.barrier_height).abs() < 1e-4
+                                    }) =>
+                                {
+                                    series
+                                        .iter()
+                                        .map(|t| {
+                                            let mut term = dihe.clone();
+                                            term.barrier_height = t.barrier_height;
+                                            term.phase = t.phase;
+                                            term.periodicity = t.periodicity as _;
+                                            term
+                                        })
+                                        .collect()
+                                }
+                                _ => vec![dihe],
+                            };
+                            result.dihedral.insert(idx_key, terms);
                         } else {
-                            // return Err(ParamError::new(&format!(
-                            //     "Missing dihedral parameters for {ti}-{tj}-{tk}-{tl}"
-                            // )));
+                            report.add(MissingParam::dihedral(ti, tj, tk, tl));
                         }
                     }
                 }
@@ -371,15 +507,14 @@ impl ForceFieldParamsIndexed {
                         {
                             let mut dihe = dihe.clone();
 
-                            // todo: I believe it's already divided ?
-                            // dihe.barrier_height /= dihe.divider as f32;
+                            // Impropers normally have no divider listed, but handle it if
+                            // present, as for propers.
+                            dihe.barrier_height /= dihe.divider.max(1) as f32;
                             dihe.divider = 1;
-                            result.dihedral.insert(idx_key, dihe);
-                        } else {
-                            // return Err(ParamError::new(&format!(
-                            //     "Missing improper parameters for {ti}-{tc}-{tk}-{tl}"
-                            // )));
+                            result.dihedral.insert(idx_key, vec![dihe]);
                         }
+                        // We don't report missing impropers: Most centers with 3 or more
+                        // neighbours, e.g. sp3 carbons, don't have them.
                     }
                 }
             }
@@ -399,12 +534,12 @@ impl MdState {
         // lj_table: &LjTable,
         ff_params: &FfParamSet,
         residues: &[Residue], // For protein charge LU
-    ) -> Result<Self, ParamError> {
+    ) -> Result<Self, DaedalusError> {
         let Some(ff_params_lig_keyed) = &ff_params.lig_general else {
-            return Err(ParamError::new("Missing lig general params"));
+            return Err(ParamError::new("Missing lig general params").into());
         };
         let Some(ff_params_prot_keyed) = &ff_params.prot_general else {
-            return Err(ParamError::new("Missing prot params general params"));
+            return Err(ParamError::new("Missing prot params general params").into());
         };
 
         // Assign FF type and charge to protein atoms; FF type must be assigned prior to initializing `ForceFieldParamsIndexed`.
@@ -413,14 +548,19 @@ impl MdState {
         // todo temp!
         let ff_params_keyed_lig_specific = ff_params.lig_specific.get("CPB");
 
+        let mut param_report = ParamReport::default();
+
         // Convert FF params from keyed to index-based.
         let ff_params_lig = ForceFieldParamsIndexed::new(
             ff_params_lig_keyed,
             ff_params_keyed_lig_specific,
+            &ff_params.dihedral_series,
             atoms,
             bonds,
             adjacency_list,
-        )?;
+            &mut param_report,
+        )
+        .context("Ligand force field parameters")?;
 
         // This assumes nonbonded interactions only with external atoms; this is fine for
         // rigid protein models, and is how this is currently structured.
@@ -430,10 +570,31 @@ impl MdState {
         let ff_params_prot = ForceFieldParamsIndexed::new(
             ff_params_prot_keyed,
             None,
+            &ff_params.dihedral_series,
             atoms_static,
             &bonds_static,
             &adj_list_static,
-        )?;
+            &mut param_report,
+        )
+        .context("Receptor force field parameters")?;
+
+        // Ligands loaded from bare structures, e.g. PDB or XYZ, have no partial charges; fill in
+        // any missing ones with Gasteiger charges, vice running with neutral atoms.
+        let atoms_charged: Vec<Atom>;
+        let atoms = if atoms.iter().any(|a| a.partial_charge.is_none()) {
+            let q = gasteiger_charges(atoms, bonds);
+            atoms_charged = atoms
+                .iter()
+                .zip(q)
+                .map(|(a, q)| Atom {
+                    partial_charge: Some(a.partial_charge.unwrap_or(q)),
+                    ..a.clone()
+                })
+                .collect();
+            &atoms_charged
+        } else {
+            atoms
+        };
 
         // We are using this approach instead of `.into`, so we can use the atom_posits from
         // the positioned ligand. (its atom coords are relative; we need absolute)
@@ -455,20 +616,8 @@ impl MdState {
             )?);
         }
 
-        let cell = {
-            let (mut min, mut max) = (Vec3::splat(f64::INFINITY), Vec3::splat(f64::NEG_INFINITY));
-            for a in &atoms_dy {
-                min = min.min(a.posit);
-                max = max.max(a.posit);
-            }
-            let pad = 15.0; // Å
-            let lo = min - Vec3::splat(pad);
-            let hi = max + Vec3::splat(pad);
-
-            println!("Initizing sim box. L: {lo} H: {hi}");
-
-            SimBox { lo, hi }
-        };
+        let cell = SimBox::around(atoms_dy.iter().map(|a| a.posit), CELL_PAD);
+        debug!("Initizing sim box. L: {} H: {}", cell.lo, cell.hi);
 
         let mut result = Self {
             atoms: atoms_dy,
@@ -480,6 +629,7 @@ impl MdState {
             excluded_pairs: HashSet::new(),
             scaled14_pairs: HashSet::new(),
             force_field_params: ff_params_lig,
+            param_report,
             ..Default::default()
         };
 
@@ -489,8 +639,59 @@ impl MdState {
         Ok(result)
     }
 
-    // todo: Evaluate whtaq this does, and if you keep it, document.
-    fn build_masks(&mut self) {
+    /// Apply run settings from `cfg`: GaMD, reporting, external fields, nonbonded scaling,
+    /// solvation, the thermostat, initial velocities and COM motion removal, and the barostat. Call this after adding or freezing atoms, since
+    /// the thermostat's degrees of freedom depend on them.
+    pub fn apply_config(
+        &mut self,
+        cfg: &MdConfig,
+        ff_params: &FfParamSet,
+    ) -> Result<(), ParamError> {
+        self.gamd = cfg.gamd.clone().map(GamdState::new);
+        self.report_interval = cfg.report_interval;
+        self.external_fields = cfg.external_fields.clone();
+        self.set_nonbonded_scaling(cfg.nonbonded_scaling.clone());
+        self.velocity_seed = cfg.velocity_seed;
+        self.com_removal_interval = cfg.com_removal_interval;
+        self.stability = cfg.stability.clone();
+
+        if let (Some(solvation), Some(ff_params_lig)) = (&cfg.solvation, &ff_params.lig_general) {
+            let num_waters = self.solvate(solvation, ff_params_lig)?;
+            info!(
+                "Added {num_waters} {} waters. Box: {} to {}",
+                solvation.model.to_str(),
+                self.cell.lo,
+                self.cell.hi
+            );
+        }
+
+        self.set_thermostat(cfg.thermostat, cfg.target_temp);
+        let init_temp = cfg.init_temp.or(cfg.thermostat.map(|_| cfg.target_temp));
+        if let Some(temp) = init_temp {
+            self.init_velocities(temp);
+        }
+        self.barostat = cfg
+            .barostat
+            .clone()
+            .map(|params| BarostatState::new(params, self));
+
+        Ok(())
+    }
+
+    /// Set how 1-4 pairs are scaled, and which close pairs are excluded, and rebuild the masks. Call
+    /// this before solvating; it resets water exclusions.
+    pub fn set_nonbonded_scaling(&mut self, scaling: NonbondedScaling) {
+        self.nonbonded_scaling = scaling;
+        self.build_masks();
+    }
+
+    /// Build the sets of mobile atom pairs excluded from nonbonded interactions, and of 1-4 pairs,
+    /// which are scaled, from the bonded parameters. See `NonbondedScaling`.
+    pub(super) fn build_masks(&mut self) {
+        self.excluded_pairs.clear();
+        self.scaled14_pairs.clear();
+        let depth = self.nonbonded_scaling.exclusion_depth;
+
         // Helper to store pairs in canonical (low,high) order
         let mut push = |set: &mut HashSet<(usize, usize)>, i: usize, j: usize| {
             if i < j {
@@ -501,46 +702,105 @@ impl MdState {
         };
 
         // 1-2
-        for (indices, _) in &self.force_field_params.bond_stretching {
-            push(&mut self.excluded_pairs, indices.0, indices.1);
+        if depth >= 1 {
+            for (indices, _) in &self.force_field_params.bond_stretching {
+                push(&mut self.excluded_pairs, indices.0, indices.1);
+            }
         }
 
         // 1-3
-        for (indices, _) in &self.force_field_params.angle {
-            push(&mut self.excluded_pairs, indices.0, indices.2);
+        if depth >= 2 {
+            for (indices, _) in &self.force_field_params.angle {
+                push(&mut self.excluded_pairs, indices.0, indices.2);
+            }
         }
 
         // 1-4
+        let set_14 = if depth >= 3 {
+            &mut self.excluded_pairs
+        } else {
+            &mut self.scaled14_pairs
+        };
         for (indices, _) in &self.force_field_params.dihedral {
-            push(&mut self.scaled14_pairs, indices.0, indices.3);
+            push(set_14, indices.0, indices.3);
         }
 
-        // Make sure no 1-4 pair is also in the excluded set
-        for p in &self.scaled14_pairs {
-            self.excluded_pairs.remove(p);
-        }
+        // In small rings, a pair may be both 1-3 and 1-4; the closer relation wins.
+        self.scaled14_pairs
+            .retain(|p| !self.excluded_pairs.contains(p));
+
+        // The packed neighbor lists carry these masks.
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        self.pack_neighbours();
     }
 
-    /// Build / rebuild Verlet list
+    /// Build / rebuild Verlet lists: of mobile atom pairs, and of static atoms near each mobile one.
+    /// Uses a cell grid, so this is O(N).
     pub fn build_neighbours(&mut self) {
-        let cutoff2 = (CUTOFF + SKIN).powi(2);
+        let radius = CUTOFF + SKIN;
+        let cutoff2 = radius.powi(2);
+
+        let grid = CellGrid::new(&self.cell, self.atoms.iter().map(|a| a.posit), radius);
+        // Static atoms don't move, but we rebuild this along with the mobile list, since which are
+        // in range depends on the mobile atoms' positions.
+        let grid_static = CellGrid::new(
+            &self.cell,
+            self.atoms_static.iter().map(|a| a.posit),
+            radius,
+        );
+
         self.neighbour = vec![Vec::new(); self.atoms.len()];
-        for i in 0..self.atoms.len() - 1 {
-            for j in i + 1..self.atoms.len() {
-                let dv = self
-                    .cell
-                    .min_image(self.atoms[j].posit - self.atoms[i].posit);
+        self.neighbour_static = vec![Vec::new(); self.atoms.len()];
+
+        for (i, a) in self.atoms.iter().enumerate() {
+            let idx = grid.index(&self.cell, a.posit);
+
+            for j in grid.neighbors(idx) {
+                if j <= i {
+                    continue;
+                }
+                let dv = self.cell.min_image(self.atoms[j].posit - a.posit);
                 if dv.magnitude_squared() < cutoff2 {
                     self.neighbour[i].push(j);
                     self.neighbour[j].push(i);
                 }
             }
+
+            // Frozen atoms and static ones never move relative to each other; their interactions
+            // are constant, so we skip them.
+            if self.frozen.get(i) == Some(&true) {
+                continue;
+            }
+
+            for j in grid_static.neighbors(idx) {
+                let dv = self.cell.min_image(self.atoms_static[j].posit - a.posit);
+                if dv.magnitude_squared() < cutoff2 {
+                    self.neighbour_static[i].push(j);
+                }
+            }
         }
-        // reset displacement tracker
-        for a in &mut self.atoms {
-            a.vel /* nothing */;
+
+        self.posits_at_build = self.atoms.iter().map(|a| a.posit).collect();
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        self.pack_neighbours();
+    }
+
+    /// Rebuild the Verlet lists if any atom has moved more than half the skin since they were
+    /// built; past this, two atoms may have closed from outside the list radius to within the
+    /// cutoff.
+    pub fn update_neighbours(&mut self) {
+        let thresh_sq = (0.5 * SKIN).powi(2);
+
+        let stale =
+            self.posits_at_build.len() != self.atoms.len()
+                || self.atoms.iter().zip(&self.posits_at_build).any(|(a, p)| {
+                    self.cell.min_image(a.posit - *p).magnitude_squared() > thresh_sq
+                });
+
+        if stale {
+            self.build_neighbours();
         }
-        self.max_disp_sq = 0.0;
     }
 }
 
@@ -550,20 +810,67 @@ pub fn populate_ff_and_q(
     atoms: &mut [Atom],
     residues: &[Residue],
     prot_charge: &HashMap<AminoAcidGeneral, Vec<ChargeParams>>,
-) -> Result<(), ParamError> {
+    templates: &HashMap<String, ResidueTemplate>,
+) -> Result<(), DaedalusError> {
+    // Plain "HIS" is absent from amino19.lib; His residues need a tautomer. These are normally
+    // set by `optimize_h_bond_network`; otherwise, infer them from the ring hydrogens present.
+    let his_variants: Vec<Option<AminoAcidProtenationVariant>> = residues
+        .iter()
+        .map(|res| {
+            if res.variant.is_some()
+                || !matches!(res.res_type, ResidueType::AminoAcid(AminoAcid::His))
+            {
+                return res.variant;
+            }
+            let has = |name: &str| {
+                res.atoms.iter().any(|i| {
+                    atoms[*i]
+                        .type_in_res
+                        .as_ref()
+                        .is_some_and(|t| t.to_string() == name)
+                })
+            };
+            match (has("HD1"), has("HE2")) {
+                (true, false) => Some(AminoAcidProtenationVariant::Hid),
+                (false, true) => Some(AminoAcidProtenationVariant::Hie),
+                (true, true) => Some(AminoAcidProtenationVariant::Hip),
+                (false, false) => None,
+            }
+        })
+        .collect();
+
     for atom in atoms {
+        // Non-standard residues, e.g. caps and phosphorylated residues, from loaded library files.
+        // These are often hetero atoms in structure files.
+        if let Some(res_i) = atom.residue {
+            if let ResidueType::Other(name) = &residues[res_i].res_type {
+                if let Some(template) = templates.get(name) {
+                    let name_in_res = atom.type_in_res.as_ref().map(|t| t.to_string());
+                    match name_in_res.and_then(|n| template.atom(&n)) {
+                        Some(t) => {
+                            atom.force_field_type = Some(t.ff_type.clone());
+                            atom.partial_charge = Some(t.charge);
+                        }
+                        None => warn!("Can't find {atom} in residue template {name}"),
+                    }
+                    continue;
+                }
+            }
+        }
+
         if atom.hetero {
             continue;
         }
         let Some(res_i) = atom.residue else {
-            return Err(ParamError::new(&format!("Missing residue: {:?}", atom)));
+            return Err(ParamError::new(&format!("Missing residue: {:?}", atom)).into());
         };
 
         let Some(type_in_res) = &atom.type_in_res else {
             return Err(ParamError::new(&format!(
                 "Missing type in residue for SN: {}, {}, {:?}",
                 atom.serial_number, atom.posit, atom.element
-            )));
+            ))
+            .into());
         };
 
         let atom_res_type = &residues[res_i].res_type;
@@ -573,18 +880,15 @@ pub fn populate_ff_and_q(
             continue;
         };
 
-        // todo: Eventually, determine how to load non-standard AA variants from files; set up your
-        // todo state to use those labels. They are available in the params.
-        let aa_gen = AminoAcidGeneral::Standard(*aa);
-
-        let charges = match prot_charge.get(&aa_gen) {
-            Some(c) => c,
-            // A specific workaround to plain "HIS" being absent from amino19.lib (2025.
-            // Choose one of "HID", "HIE", "HIP arbitrarily.
-            None if aa_gen == AminoAcidGeneral::Standard(AminoAcid::His) => prot_charge
-                .get(&AminoAcidGeneral::Variant(AminoAcidProtenationVariant::Hid))
-                .ok_or_else(|| ParamError::new("Unable to find AA mapping"))?,
-            None => return Err(ParamError::new("Unable to find AA mapping")),
+        // Variants, e.g. HIE or ASH, are set when assigning protonation states.
+        // todo: Eventually, determine how to load non-standard AA variants from files.
+        let aa_gen = match his_variants[res_i] {
+            Some(v) => AminoAcidGeneral::Variant(v),
+            None => AminoAcidGeneral::Standard(*aa),
+        };
+
+        let Some(charges) = prot_charge.get(&aa_gen) else {
+            return Err(ParamError::new(&format!("Unable to find AA mapping for {aa}")).into());
         };
 
         let mut found = false;
@@ -602,24 +906,13 @@ pub fn populate_ff_and_q(
         }
 
         if atom.serial_number == 2212 {
-            println!("Charge data for {atom}");
+            trace!("Charge data for {atom}");
         }
 
         if !found {
-            // todo: This is a workaround for having trouble with H types. LIkely
-            // todo when we create them. For now, this meets the intent.
-
-            // eprintln!("Failed to match H type {ff_type}. Falling back to a generic H");
-            // if ff_type.starts_with("H") {
-            //     for charge in charges {
-            //         if &charge.type_in_res == "H" || &charge.type_in_res == "HA" {
-            //             atom.partial_charge = Some(charge.charge);
-            //             found = true;
-            //         }
-            //     }
-            // }
-
-            eprintln!("Can't find charge for protein atom: {}", atom);
+            // Hydrogens we add are named to match the templates; see `add_hydrogens::name_hydrogens`.
+            // Misses here are generally terminal residues, and non-standard names from files.
+            warn!("Can't find charge for protein atom: {}", atom);
             //  todo temp?
             // return Err(ParamError::new(&format!(
             //     "Can't find charge for protein atom: {:?}",
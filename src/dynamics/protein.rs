@@ -0,0 +1,212 @@
+//! MD of a whole protein. In docking, the receptor is static, and only exerts nonbonded forces
+//! on the ligand. Here, all of the protein's atoms are mobile, with bonds, valence angles,
+//! dihedrals, and CMAP terms from ff19SB, so we can relax structures: e.g. after adding hydrogens, mutating
+//! residues, or building loops.
+//!
+//! Nonbonded pairs use the same cell-grid Verlet lists as other systems, so building them, and each
+//! step, scale linearly with atom count. Hetero atoms, e.g. crystal waters and bound ligands, are
+//! omitted, unless they have force field types from residue templates.
+
+use std::sync::Arc;
+
+use graphics::{EngineUpdates, Scene};
+
+use crate::{
+    ComputationDevice, FfParamSet, State,
+    dynamics::{
+        AtomDynamics, ForceFieldParamsIndexed, MdConfig, MdRun, MdState, ParamError,
+        ambient::SimBox, cmap::find_cmap_terms, prep::CELL_PAD, validation::ParamReport,
+    },
+    error::DaedalusError,
+    mol_drawing::draw_molecule,
+    molecule::{Bond, Molecule},
+    progress::{Progress, Task},
+    units::FS_PER_PS,
+};
+
+/// fs. Bonds to hydrogen aren't constrained, so this can't be much longer.
+pub const PROTEIN_MD_DT: f64 = 1.;
+
+impl MdState {
+    /// Set up a protein's non-hetero atoms as the mobile set, with no static atoms. These must have
+    /// force field types and partial charges assigned, e.g. by `populate_ff_and_q`. Positions map
+    /// back to the molecule's atoms with `flexible_posits`.
+    pub fn new_protein(mol: &Molecule, ff_params: &FfParamSet) -> Result<Self, DaedalusError> {
+        let Some(ff_params_prot) = &ff_params.prot_general else {
+            return Err(ParamError::new("Missing prot params general params").into());
+        };
+
+        // Molecule atom index, to index in the simulation.
+        let mut mol_to_md = vec![None; mol.atoms.len()];
+        let mut atoms = Vec::new();
+
+        for (i, atom) in mol.atoms.iter().enumerate() {
+            // Hetero atoms are included if typed by residue templates, e.g. caps.
+            if atom.hetero && atom.force_field_type.is_none() {
+                continue;
+            }
+            if atom.force_field_type.is_none() {
+                return Err(ParamError::new(&format!(
+                    "Protein atom missing FF type: {atom}. Assign protonation states first."
+                ))
+                .into());
+            }
+
+            mol_to_md[i] = Some(atoms.len());
+            atoms.push(atom.clone());
+        }
+
+        if atoms.is_empty() {
+            return Err(ParamError::new("The molecule has no protein atoms").into());
+        }
+
+        let bonds: Vec<_> = mol
+            .bonds
+            .iter()
+            .filter_map(
+                |bond| match (mol_to_md[bond.atom_0], mol_to_md[bond.atom_1]) {
+                    (Some(i), Some(j)) => Some(Bond {
+                        atom_0: i,
+                        atom_1: j,
+                        ..bond.clone()
+                    }),
+                    _ => None,
+                },
+            )
+            .collect();
+
+        let mut adjacency_list = vec![Vec::new(); atoms.len()];
+        for bond in &bonds {
+            adjacency_list[bond.atom_0].push(bond.atom_1);
+            adjacency_list[bond.atom_1].push(bond.atom_0);
+        }
+
+        let cmap_terms =
+            find_cmap_terms(&atoms, &adjacency_list, &mol.residues, &ff_params.prot_cmap);
+
+        let mut param_report = ParamReport::default();
+        let params = ForceFieldParamsIndexed::new(
+            ff_params_prot,
+            None,
+            &ff_params.dihedral_series,
+            &atoms,
+            &bonds,
+            &adjacency_list,
+            &mut param_report,
+        )?;
+
+        let posits: Vec<_> = atoms.iter().map(|a| a.posit).collect();
+        let mut atoms_dy = Vec::with_capacity(atoms.len());
+        for (i, atom) in atoms.iter().enumerate() {
+            atoms_dy.push(AtomDynamics::new(atom, &posits, &params, i)?);
+        }
+
+        let flexible = mol_to_md
+            .iter()
+            .enumerate()
+            .filter_map(|(i_mol, i)| i.map(|i| (i, i_mol)))
+            .collect();
+
+        let mut result = Self {
+            atoms: atoms_dy,
+            adjacency_list,
+            cell: SimBox::around(posits.into_iter(), CELL_PAD),
+            force_field_params: params,
+            flexible,
+            cmap_grids: ff_params.prot_cmap.clone(),
+            cmap_terms,
+            param_report,
+            ..Default::default()
+        };
+
+        result.build_masks();
+        result.build_neighbours();
+
+        Ok(result)
+    }
+}
+
+/// Run MD on a protein, with settings from `cfg`. Runs `n_steps` of `dt` fs, or `cfg`'s
+/// temperature protocol, if present, counting steps in `progress`.
+pub fn build_protein_dynamics(
+    dev: &ComputationDevice,
+    mol: &Molecule,
+    ff_params: &FfParamSet,
+    n_steps: usize,
+    dt: f64,
+    cfg: &MdConfig,
+    progress: &Progress,
+) -> Result<MdState, DaedalusError> {
+    let mut md_state = MdState::new_protein(mol, ff_params)?;
+    md_state.check_params(cfg.allow_missing_params)?;
+    md_state.apply_config(cfg, ff_params)?;
+
+    md_state.progress = progress.clone();
+    md_state.run_on(dev, cfg.protocol.as_ref(), n_steps, dt)?;
+
+    Ok(md_state)
+}
+
+/// As `build_protein_dynamics`, on the CPU, in a new thread. Used by the protein MD Run button.
+pub fn start_protein_md(
+    mol: Molecule,
+    ff_params: Arc<FfParamSet>,
+    n_steps: usize,
+    dt: f64,
+    cfg: MdConfig,
+) -> Task<Result<MdRun, DaedalusError>> {
+    Task::spawn(move |progress| {
+        let md = build_protein_dynamics(
+            &ComputationDevice::Cpu,
+            &mol,
+            &ff_params,
+            n_steps,
+            dt,
+            &cfg,
+            progress,
+        )?;
+
+        Ok(MdRun { md, ligand: None })
+    })
+}
+
+/// Run MD on the whole protein, for `protein_md_steps` steps, blocking until done. Used by the
+/// `md` command, and Python. Returns a summary.
+pub fn run_protein_md(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) -> Result<String, DaedalusError> {
+    state.load_ffs_general();
+    let Some(mol) = &mut state.molecule else {
+        return Err(ParamError::new("Protein MD requires a molecule").into());
+    };
+
+    let md = build_protein_dynamics(
+        &ComputationDevice::Cpu,
+        mol,
+        &state.ff_params,
+        state.ui.protein_md_steps,
+        PROTEIN_MD_DT,
+        &state.ui.md_config,
+        &Progress::default(),
+    )?;
+
+    for (i, posit) in md.flexible_posits() {
+        mol.atoms[i].posit = posit;
+    }
+
+    let msg = format!(
+        "Ran protein MD over {} atoms, to {:.1} ps",
+        md.atoms.len(),
+        md.time / FS_PER_PS
+    );
+
+    state.mol_dynamics = Some(md);
+    state.ui.current_snapshot = 0;
+
+    draw_molecule(state, scene);
+    engine_updates.entities = true;
+
+    Ok(msg)
+}
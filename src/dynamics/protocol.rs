@@ -0,0 +1,270 @@
+//! Scripted temperature schedules: stages that heat, hold, or cool the system by ramping the
+//! thermostat's target temperature. Simulated annealing heats the system to cross barriers, then
+//! cools it slowly to settle into a low-energy state. Use this to refine docked poses.
+
+#[cfg(feature = "cuda")]
+use std::sync::Arc;
+
+#[cfg(feature = "cuda")]
+use cudarc::driver::{CudaModule, CudaStream};
+use log::warn;
+
+#[cfg(feature = "cuda")]
+use crate::dynamics::gpu::MdGpu;
+use crate::{
+    ComputationDevice,
+    dynamics::{MdState, ParamError, solvent::DT_MAX_SOLVATED},
+    logging::Span,
+};
+
+/// K
+pub const ANNEAL_TEMP_HIGH_DEFAULT: f64 = 600.;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StageKind {
+    Heat,
+    Hold,
+    Cool,
+}
+
+impl StageKind {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Heat => "Heat",
+            Self::Hold => "Hold",
+            Self::Cool => "Cool",
+        }
+    }
+}
+
+/// A linear ramp of the target temperature.
+#[derive(Clone, Debug)]
+pub struct TempStage {
+    /// K
+    pub temp_start: f64,
+    /// K
+    pub temp_end: f64,
+    pub n_steps: usize,
+}
+
+impl TempStage {
+    pub fn kind(&self) -> StageKind {
+        if self.temp_end > self.temp_start {
+            StageKind::Heat
+        } else if self.temp_end < self.temp_start {
+            StageKind::Cool
+        } else {
+            StageKind::Hold
+        }
+    }
+
+    /// The target temperature at step `i` of this stage. K
+    pub fn temp_at(&self, i: usize) -> f64 {
+        if self.n_steps == 0 {
+            return self.temp_end;
+        }
+        let frac = i as f64 / self.n_steps as f64;
+        self.temp_start + (self.temp_end - self.temp_start) * frac
+    }
+}
+
+/// Stages, run in order.
+#[derive(Clone, Debug)]
+pub struct TempProtocol {
+    pub stages: Vec<TempStage>,
+}
+
+impl Default for TempProtocol {
+    fn default() -> Self {
+        Self::anneal(300., ANNEAL_TEMP_HIGH_DEFAULT, 10_000, 10_000, 30_000)
+    }
+}
+
+impl TempProtocol {
+    /// Heat from `temp_low` to `temp_high`, hold, then cool back. Cooling is usually the longest
+    /// stage; cooling too fast traps the system in whichever state it's in at the time.
+    pub fn anneal(
+        temp_low: f64,
+        temp_high: f64,
+        steps_heat: usize,
+        steps_hold: usize,
+        steps_cool: usize,
+    ) -> Self {
+        Self {
+            stages: vec![
+                TempStage {
+                    temp_start: temp_low,
+                    temp_end: temp_high,
+                    n_steps: steps_heat,
+                },
+                TempStage {
+                    temp_start: temp_high,
+                    temp_end: temp_high,
+                    n_steps: steps_hold,
+                },
+                TempStage {
+                    temp_start: temp_high,
+                    temp_end: temp_low,
+                    n_steps: steps_cool,
+                },
+            ],
+        }
+    }
+
+    pub fn n_steps(&self) -> usize {
+        self.stages.iter().map(|s| s.n_steps).sum()
+    }
+
+    /// Target temperatures for each step, in order. K
+    pub fn temps(&self) -> impl Iterator<Item = f64> + '_ {
+        self.stages
+            .iter()
+            .flat_map(|s| (0..s.n_steps).map(move |i| s.temp_at(i)))
+    }
+
+    pub fn descrip(&self) -> String {
+        self.stages
+            .iter()
+            .map(|s| {
+                format!(
+                    "{} {:.0}→{:.0} K, {} steps",
+                    s.kind().to_str(),
+                    s.temp_start,
+                    s.temp_end,
+                    s.n_steps
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl MdState {
+    /// Set the thermostat's target temperature, in K, keeping its other state.
+    pub fn set_target_temp(&mut self, temp: f64) {
+        // The Nosé-Hoover chain's energy depends on its target; measure drift from here.
+        if temp != self.target_temp {
+            self.energy_ref = None;
+        }
+        self.target_temp = temp;
+        if let Some(nhc) = &mut self.nhc {
+            nhc.set_target_temp(temp);
+        }
+    }
+
+    /// Velocities start at the first stage's temperature; the thermostat follows the schedule.
+    fn start_protocol(&mut self, protocol: &TempProtocol) -> Result<(), ParamError> {
+        if self.thermostat.is_none() {
+            return Err(ParamError::new(
+                "A temperature protocol requires a thermostat",
+            ));
+        }
+        let Some(first) = protocol.stages.first() else {
+            return Err(ParamError::new("The temperature protocol has no stages"));
+        };
+
+        self.init_velocities(first.temp_start);
+        self.set_target_temp(first.temp_start);
+
+        Ok(())
+    }
+
+    /// Run each stage of `protocol` in order, with time step `dt`, in fs.
+    pub fn run_protocol(&mut self, protocol: &TempProtocol, dt: f64) -> Result<(), ParamError> {
+        self.start_protocol(protocol)?;
+        self.progress.set_total(protocol.n_steps());
+
+        for temp in protocol.temps() {
+            self.set_target_temp(temp);
+            self.step(dt);
+            if self.blow_up.is_some() || !self.progress.advance() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// As `run_protocol`, on the GPU if this system's features are supported there. See `run_md`.
+    #[cfg(feature = "cuda")]
+    pub fn run_protocol_gpu(
+        &mut self,
+        stream: &Arc<CudaStream>,
+        module: &Arc<CudaModule>,
+        protocol: &TempProtocol,
+        dt: f64,
+    ) -> Result<(), ParamError> {
+        self.start_protocol(protocol)?;
+
+        let mut gpu = match MdGpu::new(stream, module, self) {
+            Ok(gpu) => gpu,
+            Err(e) => {
+                warn!("{}; running MD on the CPU.", e.descrip);
+                return self.run_protocol(protocol, dt);
+            }
+        };
+
+        self.progress.set_total(protocol.n_steps());
+
+        for temp in protocol.temps() {
+            self.set_target_temp(temp);
+            gpu.step(self, dt);
+            if self.blow_up.is_some() {
+                // `check_stability` restored the host's atoms; don't overwrite them.
+                return Ok(());
+            }
+            if !self.progress.advance() {
+                break;
+            }
+        }
+        gpu.download(self);
+        self.build_neighbours();
+
+        Ok(())
+    }
+
+    /// Run `n_steps` of `dt` fs on `dev`, or if present, `protocol`'s stages instead. Returns an
+    /// error with diagnostics if the simulation blows up; see `check_stability`.
+    pub fn run_on(
+        &mut self,
+        dev: &ComputationDevice,
+        protocol: Option<&TempProtocol>,
+        n_steps: usize,
+        dt: f64,
+    ) -> Result<(), ParamError> {
+        let _span = Span::new(format!("MD run over {} atoms", self.atoms.len()));
+
+        if self.num_waters > 0 && dt > DT_MAX_SOLVATED {
+            return Err(ParamError::new(&format!(
+                "A time step of {dt} fs is too long for flexible water; use {DT_MAX_SOLVATED} fs or less"
+            )));
+        }
+
+        match (dev, protocol) {
+            #[cfg(feature = "cuda")]
+            (ComputationDevice::Gpu((stream, module)), Some(protocol)) => {
+                self.run_protocol_gpu(stream, module, protocol, dt)?
+            }
+            #[cfg(feature = "cuda")]
+            (ComputationDevice::Gpu((stream, module)), None) => {
+                self.run_md(stream, module, n_steps, dt)
+            }
+            (ComputationDevice::Cpu, Some(protocol)) => self.run_protocol(protocol, dt)?,
+            (ComputationDevice::Cpu, None) => {
+                self.progress.set_total(n_steps);
+
+                for _ in 0..n_steps {
+                    self.step(dt);
+                    if self.blow_up.is_some() || !self.progress.advance() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        match &self.blow_up {
+            Some(blow_up) => Err(ParamError::new(&blow_up.descrip())),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Energy terms, temperature, and pressure, recorded at intervals during MD. Use these to check
+//! equilibration and stability. The force loops already compute the energy terms and the virial;
+//! on steps that record a report, they keep them in `MdState::report_terms`, so reporting doesn't
+//! re-evaluate the system.
+
+use std::{
+    fs::File,
+    io,
+    io::Write,
+    ops::{Add, AddAssign},
+    path::Path,
+};
+
+use bincode::{Decode, Encode};
+
+use crate::{
+    dynamics::MdState,
+    units::{BAR_A3_TO_KCAL_MOL, temperature},
+};
+
+/// Observables at one step.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct EnergyReport {
+    /// fs
+    pub time: f64,
+    pub step: usize,
+    /// kcal/mol
+    pub kinetic: f64,
+    /// kcal/mol. Bond stretching, angle bending, dihedrals, and CMAP.
+    pub bonded: f64,
+    /// kcal/mol
+    pub lj: f64,
+    /// kcal/mol
+    pub coulomb: f64,
+    /// kcal/mol. The potential the integrator used, including any GaMD boost, restraints, and
+    /// external fields.
+    pub potential: f64,
+    /// K
+    pub temperature: f64,
+    /// bar. From the virial. Interactions with static atoms are included as pair terms.
+    pub pressure: f64,
+}
+
+/// Energy terms, and the virial, accumulated by the force loops of a step that records a report.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReportTerms {
+    /// kcal/mol. Bond stretching, angle bending, dihedrals, and CMAP.
+    pub bonded: f64,
+    /// kcal/mol
+    pub lj: f64,
+    /// kcal/mol
+    pub coulomb: f64,
+    /// kcal/mol. Σ r·f over interactions, for the pressure.
+    pub virial: f64,
+}
+
+impl Add for ReportTerms {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            bonded: self.bonded + rhs.bonded,
+            lj: self.lj + rhs.lj,
+            coulomb: self.coulomb + rhs.coulomb,
+            virial: self.virial + rhs.virial,
+        }
+    }
+}
+
+impl AddAssign for ReportTerms {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+const CSV_HEADER: &str = "time_fs,step,kinetic_kcal_mol,bonded_kcal_mol,lj_kcal_mol,\
+coulomb_kcal_mol,potential_kcal_mol,temperature_k,pressure_bar";
+
+/// Write reports to a CSV file, one row per report.
+pub fn save_reports_csv(reports: &[EnergyReport], path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+
+    writeln!(file, "{CSV_HEADER}")?;
+    for r in reports {
+        writeln!(
+            file,
+            "{:.3},{},{:.6},{:.6},{:.6},{:.6},{:.6},{:.3},{:.3}",
+            r.time,
+            r.step,
+            r.kinetic,
+            r.bonded,
+            r.lj,
+            r.coulomb,
+            r.potential,
+            r.temperature,
+            r.pressure
+        )?;
+    }
+
+    Ok(())
+}
+
+impl MdState {
+    /// If the step about to run records a report. The force loops fill `report_terms` on these.
+    pub(super) fn report_due(&self) -> bool {
+        self.report_interval > 0 && (self.step_count + 1) % self.report_interval == 0
+    }
+
+    /// Add bonded energy, and its virial, to this step's report, if one is due.
+    pub(super) fn report_bonded(&mut self, energy: f64, virial: f64) {
+        if let Some(terms) = &mut self.report_terms {
+            terms.bonded += energy;
+            terms.virial += virial;
+        }
+    }
+
+    /// Build a report from the terms the force loops accumulated, and the kinetic energy, in
+    /// kcal/mol.
+    pub fn energy_report(&self, kinetic: f64, terms: &ReportTerms) -> EnergyReport {
+        let volume = self.cell.volume();
+        let pressure = if volume > 0. {
+            (2. * kinetic + terms.virial) / (3. * volume) / BAR_A3_TO_KCAL_MOL
+        } else {
+            0.
+        };
+
+        EnergyReport {
+            time: self.time,
+            step: self.step_count,
+            kinetic,
+            bonded: terms.bonded,
+            lj: terms.lj,
+            coulomb: terms.coulomb,
+            potential: self.energy_potential,
+            temperature: temperature(kinetic, self.dof()),
+            pressure,
+        }
+    }
+}
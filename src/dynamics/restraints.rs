@@ -0,0 +1,88 @@
+//! Harmonic positional restraints, and frozen atoms. Restraints tether atoms to reference positions,
+//! e.g. heavy atoms during staged equilibration, with springs relaxed over successive stages.
+//! Frozen atoms don't move at all: they're skipped in integration, but still exert forces on the
+//! others, e.g. a fixed backbone while sidechains relax.
+//!
+//! The barostat still scales frozen atoms with the box; don't combine the two.
+
+use lin_alg::f64::Vec3;
+
+use crate::dynamics::MdState;
+
+/// kcal/mol/Å². Typical for heavy-atom restraints early in equilibration.
+pub const RESTRAINT_K_DEFAULT: f64 = 10.;
+
+/// V = ½k|r - r₀|²
+#[derive(Clone, Debug)]
+pub struct PositionRestraint {
+    /// Index of a mobile atom.
+    pub atom: usize,
+    pub reference: Vec3,
+    /// kcal/mol/Å²
+    pub k: f64,
+}
+
+impl MdState {
+    /// Restrain atoms to their current positions, with spring constant `k`, in kcal/mol/Å².
+    /// Replaces existing restraints on these atoms.
+    pub fn restrain_atoms(&mut self, atoms: &[usize], k: f64) {
+        self.position_restraints
+            .retain(|r| !atoms.contains(&r.atom));
+
+        for &atom in atoms {
+            let Some(a) = self.atoms.get(atom) else {
+                continue;
+            };
+            self.position_restraints.push(PositionRestraint {
+                atom,
+                reference: a.posit,
+                k,
+            });
+        }
+    }
+
+    /// Hold atoms fixed in place. Their velocities are zeroed.
+    pub fn freeze_atoms(&mut self, atoms: &[usize]) {
+        if self.frozen.len() < self.atoms.len() {
+            self.frozen.resize(self.atoms.len(), false);
+        }
+
+        for &i in atoms {
+            if i < self.atoms.len() {
+                self.frozen[i] = true;
+            }
+        }
+        self.zero_frozen();
+    }
+
+    /// Degrees of freedom, for the temperature: 3 per atom that isn't frozen.
+    pub(super) fn dof(&self) -> usize {
+        let n_frozen = self.frozen.iter().filter(|f| **f).count();
+        3 * (self.atoms.len() - n_frozen)
+    }
+
+    /// Zero the velocities and accelerations of frozen atoms, so integration doesn't move them.
+    pub(super) fn zero_frozen(&mut self) {
+        for (a, frozen) in self.atoms.iter_mut().zip(&self.frozen) {
+            if *frozen {
+                a.vel = Vec3::new_zero();
+                a.accel = Vec3::new_zero();
+            }
+        }
+    }
+
+    /// Returns the restraint energy, in kcal/mol.
+    pub(super) fn apply_position_restraints(&mut self) -> f64 {
+        let mut energy = 0.;
+
+        for r in &self.position_restraints {
+            let atom = &mut self.atoms[r.atom];
+            let diff = self.cell.min_image(atom.posit - r.reference);
+
+            energy += 0.5 * r.k * diff.magnitude_squared();
+            atom.accel -= diff * (r.k / atom.mass);
+        }
+
+        energy
+    }
+}
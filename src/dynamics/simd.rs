@@ -0,0 +1,219 @@
+//! Nonbonded forces with SIMD, for CPUs with AVX. When we build the neighbor lists, we split each
+//! atom's into chunks of 4, and pack the neighbors' parameters and mask scales into a `PairChunk`.
+//! Each step, we gather positions for a chunk, and compute forces and energies across lanes at
+//! once, in f64. This matches `f_nonbonded`, to rounding.
+//!
+//! Minimum-image displacements and the cutoff are handled per lane while gathering; these are cheap
+//! compared to the Lennard-Jones and Coulomb terms.
+
+use lin_alg::f64::{Vec3, Vec3x4, f64x4};
+use rayon::prelude::*;
+
+use crate::{
+    dynamics::{
+        AtomDynamics, AtomDynamicsx4, CUTOFF, MdState, SOFTENING_FACTOR_SQ, ambient::SimBox,
+        report::ReportTerms,
+    },
+    forces::{force_coulomb_x4, force_lj_x4},
+    units::COULOMB_CONST,
+};
+
+const LANES: usize = 4;
+
+/// A neighbor's index, and factors to scale its Lennard-Jones and Coulomb terms by.
+type Pair = (usize, f64, f64);
+
+/// Up to 4 neighbors of a mobile atom, with their nonbonded parameters packed lane-wise. Built with
+/// the neighbor lists; see `MdState::pack_neighbours`.
+pub(super) struct PairChunk {
+    indices: [usize; LANES],
+    /// Lanes in use. The rest are padded with the mobile atom, and scaled by 0.
+    len: usize,
+    /// Positions in here are stale; we only use the parameters.
+    atoms: AtomDynamicsx4,
+    scale_lj: [f64; LANES],
+    scale_coul: [f64; LANES],
+}
+
+/// Split `pairs` of `a_0` with `atoms` into chunks.
+fn pack_chunks(a_0: &AtomDynamics, atoms: &[AtomDynamics], pairs: &[Pair]) -> Vec<PairChunk> {
+    pairs
+        .chunks(LANES)
+        .map(|chunk| {
+            let mut indices = [0; LANES];
+            let mut others = [a_0; LANES];
+            let mut scale_lj = [0.; LANES];
+            let mut scale_coul = [0.; LANES];
+
+            for (lane, &(j, s_lj, s_coul)) in chunk.iter().enumerate() {
+                indices[lane] = j;
+                others[lane] = &atoms[j];
+                scale_lj[lane] = s_lj;
+                scale_coul[lane] = s_coul;
+            }
+
+            PairChunk {
+                indices,
+                len: chunk.len(),
+                atoms: AtomDynamicsx4::from_array(others),
+                scale_lj,
+                scale_coul,
+            }
+        })
+        .collect()
+}
+
+/// See `V_lj`.
+pub fn V_lj_x4(dist: f64x4, σ: f64x4, ε: f64x4) -> f64x4 {
+    let sr_6 = (σ / dist).powi(6);
+    f64x4::splat(4.) * ε * (sr_6 * sr_6 - sr_6)
+}
+
+/// See `V_coulomb`.
+pub fn V_coulomb_x4(dist: f64x4, q0: f64x4, q1: f64x4, softening_factor_sq: f64x4) -> f64x4 {
+    f64x4::splat(COULOMB_CONST) * q0 * q1 / (dist.powi(2) + softening_factor_sq).sqrt()
+}
+
+/// Coulomb and Lennard-Jones forces on atom 0 from 4 others, and their Lennard-Jones and Coulomb
+/// potential energies. See `f_nonbonded`. In place of its cutoff and 1-4 checks, we scale each lane's terms; lanes scaled
+/// by 0 contribute nothing.
+pub fn f_nonbonded_x4(
+    dv: Vec3x4,
+    a_0: &AtomDynamics,
+    a_1: &AtomDynamicsx4,
+    scale_lj: f64x4,
+    scale_coul: f64x4,
+) -> (Vec3x4, f64x4, f64x4) {
+    let dist = dv.magnitude();
+    let dir = dv / dist;
+
+    let σ = (f64x4::splat(a_0.lj_sigma) + a_1.lj_sigma) * f64x4::splat(0.5);
+    let ε = (f64x4::splat(a_0.lj_eps) * a_1.lj_eps).sqrt();
+    let q_0 = f64x4::splat(a_0.partial_charge);
+    let softening = f64x4::splat(SOFTENING_FACTOR_SQ);
+
+    let f_lj = force_lj_x4(dir, dist, σ, ε) * scale_lj;
+    // `force_coulomb_x4` takes the direction from the source; here, atom 1.
+    let f_coulomb = force_coulomb_x4(-dir, dist, q_0, a_1.partial_charge, softening)
+        * (f64x4::splat(COULOMB_CONST) * scale_coul);
+
+    let v_lj = V_lj_x4(dist, σ, ε) * scale_lj;
+    let v_coulomb = V_coulomb_x4(dist, q_0, a_1.partial_charge, softening) * scale_coul;
+
+    (f_lj + f_coulomb, v_lj, v_coulomb)
+}
+
+/// Forces on `a_0` from a chunk of atoms in `atoms`, and their energies and virial. Lanes unused,
+/// or past the cutoff, are placed at a distance, and scaled by 0.
+fn nonbonded_chunk(
+    cell: &SimBox,
+    a_0: &AtomDynamics,
+    atoms: &[AtomDynamics],
+    chunk: &PairChunk,
+) -> ([Vec3; LANES], ReportTerms) {
+    let mut dv = [Vec3::new(CUTOFF, 0., 0.); LANES];
+    let mut scale_lj = [0.; LANES];
+    let mut scale_coul = [0.; LANES];
+
+    for lane in 0..chunk.len {
+        let d = cell.min_image(atoms[chunk.indices[lane]].posit - a_0.posit);
+        if d.magnitude_squared() > CUTOFF * CUTOFF {
+            continue;
+        }
+
+        dv[lane] = d;
+        scale_lj[lane] = chunk.scale_lj[lane];
+        scale_coul[lane] = chunk.scale_coul[lane];
+    }
+
+    let (f, e_lj, e_coul) = f_nonbonded_x4(
+        Vec3x4::from_array(dv),
+        a_0,
+        &chunk.atoms,
+        f64x4::from_array(scale_lj),
+        f64x4::from_array(scale_coul),
+    );
+    let f = f.to_array();
+
+    let terms = ReportTerms {
+        lj: e_lj.to_array().iter().sum(),
+        coulomb: e_coul.to_array().iter().sum(),
+        virial: -dv.iter().zip(&f).map(|(dv, f)| dv.dot(*f)).sum::<f64>(),
+        ..Default::default()
+    };
+
+    (f, terms)
+}
+
+impl MdState {
+    /// Pack the neighbor lists, with masks and parameters, into chunks for `nonbonded_atom_x4` and
+    /// `nonbonded_static_x4`. Call this when the lists or masks change. Without AVX, we don't use
+    /// these, and leave them empty.
+    pub(super) fn pack_neighbours(&mut self) {
+        if !is_x86_feature_detected!("avx") {
+            return;
+        }
+
+        self.neighbour_x4 = self
+            .neighbour
+            .par_iter()
+            .enumerate()
+            .map(|(i, neighbours)| {
+                // Each pair is in both atoms' lists; handle it once.
+                let pairs: Vec<Pair> = neighbours
+                    .iter()
+                    .filter(|&&j| j > i)
+                    .filter_map(|&j| {
+                        let (s_lj, s_coul) = self.pair_scale(i, j)?;
+                        Some((j, s_lj, s_coul))
+                    })
+                    .collect();
+
+                pack_chunks(&self.atoms[i], &self.atoms, &pairs)
+            })
+            .collect();
+
+        self.neighbour_static_x4 = self
+            .neighbour_static
+            .par_iter()
+            .enumerate()
+            .map(|(i, neighbours)| {
+                let pairs: Vec<Pair> = neighbours.iter().map(|&j| (j, 1., 1.)).collect();
+                pack_chunks(&self.atoms[i], &self.atoms_static, &pairs)
+            })
+            .collect();
+    }
+
+    /// Forces between mobile atom `i` and its neighbors, accumulated into `forces`. Returns the
+    /// energies and virial. See `apply_nonbonded_forces`.
+    pub(super) fn nonbonded_atom_x4(&self, i: usize, forces: &mut [Vec3]) -> ReportTerms {
+        let mut terms = ReportTerms::default();
+        for chunk in &self.neighbour_x4[i] {
+            let (f, t) = nonbonded_chunk(&self.cell, &self.atoms[i], &self.atoms, chunk);
+
+            for (&j, f) in chunk.indices[..chunk.len].iter().zip(f) {
+                forces[i] += f;
+                forces[j] -= f;
+            }
+            terms += t;
+        }
+
+        terms
+    }
+
+    /// The force on mobile atom `i` from static atoms near it, and the energies and virial.
+    pub(super) fn nonbonded_static_x4(&self, i: usize) -> (Vec3, ReportTerms) {
+        let mut force = Vec3::new_zero();
+        let mut terms = ReportTerms::default();
+        for chunk in &self.neighbour_static_x4[i] {
+            let (f, t) = nonbonded_chunk(&self.cell, &self.atoms[i], &self.atoms_static, chunk);
+
+            for f in &f[..chunk.len] {
+                force += *f;
+            }
+            terms += t;
+        }
+
+        (force, terms)
+    }
+}
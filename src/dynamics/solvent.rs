@@ -0,0 +1,330 @@
+//! Explicit solvent. We fill the simulation box with water, remove waters that overlap the solute,
+//! and add the rest to the system as ordinary molecules, with bonded parameters from the force field,
+//! and charges and Lennard-Jones parameters from the water model.
+//!
+//! The water template is a cubic lattice at the model's density, with random orientations, tiled to
+//! fit the box exactly so it's periodic. It isn't equilibrated: minimize, then equilibrate (e.g. NPT
+//! with the barostat) before production runs.
+//!
+//! The models are parameterized as rigid molecules, but we don't constrain them (e.g. with SETTLE);
+//! we take their charges, Lennard-Jones parameters, and geometry, and hold that geometry with
+//! GAFF2's flexible `ow`/`hw` bond and angle terms. These are flexible variants of the models, and
+//! their bulk properties differ from the rigid ones'. The O-H stretch limits the time step to
+//! `DT_MAX_SOLVATED`; `run_on` rejects longer ones.
+
+use std::collections::HashMap;
+
+use bio_files::amber_params::{AngleBendingParams, BondStretchingParams, ForceFieldParamsKeyed};
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::Element;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::dynamics::{
+    ANG_HOH, AtomDynamics, M_H, M_O, MdState, ParamError, R_OH, ambient::SimBox,
+};
+
+/// Molecules per Å³, at 298 K and 1 bar. (0.997 g/cm³)
+const WATER_DENSITY: f64 = 0.033_43;
+/// Seeds water orientations, so solvating the same system twice gives the same result.
+const ORIENTATION_SEED: u64 = 0;
+
+/// fs. The longest time step we run solvated systems with; flexible O-H bonds are unstable past it.
+pub const DT_MAX_SOLVATED: f64 = 1.;
+
+/// GAFF2 atom types for water; we take bonded parameters from these.
+const FF_TYPE_O: &str = "ow";
+const FF_TYPE_H: &str = "hw";
+
+/// 3-point water models. All have a single Lennard-Jones site, on the oxygen. These are fit as rigid
+/// molecules; we run flexible variants of them. See the module docs.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum WaterModel {
+    #[default]
+    Tip3p,
+    Spce,
+    Opc3,
+}
+
+/// Geometry and nonbonded parameters of a water model.
+struct WaterParams {
+    /// Å
+    r_oh: f64,
+    /// Radians
+    θ_hoh: f64,
+    /// e. Hydrogens each carry half of this, with the opposite sign.
+    q_o: f64,
+    /// Å
+    σ_o: f64,
+    /// kcal/mol
+    ε_o: f64,
+}
+
+impl WaterModel {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Tip3p => "TIP3P (flex)",
+            Self::Spce => "SPC/E (flex)",
+            Self::Opc3 => "OPC3 (flex)",
+        }
+    }
+
+    fn params(self) -> WaterParams {
+        match self {
+            // [Jorgensen et al, 1983](https://doi.org/10.1063/1.445869), with Amber's parameters.
+            Self::Tip3p => WaterParams {
+                r_oh: R_OH,
+                θ_hoh: ANG_HOH,
+                q_o: -0.834,
+                σ_o: 3.150_61,
+                ε_o: 0.152_1,
+            },
+            // [Berendsen et al, 1987](https://doi.org/10.1021/j100308a038)
+            Self::Spce => WaterParams {
+                r_oh: 1.,
+                θ_hoh: 109.47_f64.to_radians(),
+                q_o: -0.847_6,
+                σ_o: 3.165_56,
+                ε_o: 0.155_3,
+            },
+            // [Izadi and Onufriev, 2016](https://doi.org/10.1063/1.4960175)
+            Self::Opc3 => WaterParams {
+                r_oh: 0.978_88,
+                θ_hoh: 109.47_f64.to_radians(),
+                q_o: -0.895_17,
+                σ_o: 3.174_27,
+                ε_o: 0.163_406,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SolvationConfig {
+    pub model: WaterModel,
+    /// Å. The minimum distance from the solute to each face of the box.
+    pub padding: f64,
+    /// Å. We remove waters with any atom closer than this to a solute atom.
+    pub clearance: f64,
+}
+
+impl Default for SolvationConfig {
+    fn default() -> Self {
+        Self {
+            model: Default::default(),
+            padding: 10.,
+            clearance: 2.4,
+        }
+    }
+}
+
+/// Positions of a water's O, and two H atoms.
+fn water_posits(o: Vec3, orientation: Quaternion, params: &WaterParams) -> [Vec3; 3] {
+    let half_angle = params.θ_hoh / 2.;
+    let h = |sign: f64| {
+        let dir = Vec3::new(sign * half_angle.sin(), 0., half_angle.cos());
+        o + orientation.rotate_vec(dir) * params.r_oh
+    };
+
+    [o, h(1.), h(-1.)]
+}
+
+/// A uniformly-distributed random rotation. [Shoemake, 1992]
+pub(crate) fn random_orientation(rng: &mut StdRng) -> Quaternion {
+    let (u0, u1, u2): (f64, f64, f64) = (rng.random(), rng.random(), rng.random());
+    let (a, b) = ((1. - u0).sqrt(), u0.sqrt());
+    let (θ1, θ2) = (u1 * std::f64::consts::TAU, u2 * std::f64::consts::TAU);
+
+    Quaternion::new(b * θ2.cos(), a * θ1.sin(), a * θ1.cos(), b * θ2.sin())
+}
+
+/// Finds atoms near a point, by binning them into cubes with sides of the search radius.
+struct Grid {
+    cell: f64,
+    bins: HashMap<(i32, i32, i32), Vec<Vec3>>,
+}
+
+impl Grid {
+    fn new(posits: impl Iterator<Item = Vec3>, cell: f64) -> Self {
+        let mut bins: HashMap<_, Vec<_>> = HashMap::new();
+        for p in posits {
+            bins.entry(Self::key(p, cell)).or_default().push(p);
+        }
+        Self { cell, bins }
+    }
+
+    fn key(p: Vec3, cell: f64) -> (i32, i32, i32) {
+        (
+            (p.x / cell).floor() as i32,
+            (p.y / cell).floor() as i32,
+            (p.z / cell).floor() as i32,
+        )
+    }
+
+    /// If any atom is within the cell size of `p`.
+    fn any_near(&self, p: Vec3) -> bool {
+        let (x, y, z) = Self::key(p, self.cell);
+        let cell_sq = self.cell.powi(2);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(bin) = self.bins.get(&(x + dx, y + dy, z + dz)) {
+                        if bin.iter().any(|q| (*q - p).magnitude_squared() < cell_sq) {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+impl MdState {
+    /// Surround the atoms with water, and size the simulation box to fit. Waters are appended after
+    /// existing atoms, so their indices don't change. Call this before setting up the thermostat
+    /// and barostat, as these depend on the atom count. Returns the number of waters added.
+    pub fn solvate(
+        &mut self,
+        cfg: &SolvationConfig,
+        ff_params: &ForceFieldParamsKeyed,
+    ) -> Result<usize, ParamError> {
+        let bond = ff_params
+            .bond
+            .get(&(FF_TYPE_O.to_owned(), FF_TYPE_H.to_owned()))
+            .or_else(|| {
+                ff_params
+                    .bond
+                    .get(&(FF_TYPE_H.to_owned(), FF_TYPE_O.to_owned()))
+            })
+            .ok_or_else(|| ParamError::new("Missing water bond parameters"))?;
+
+        let angle = ff_params
+            .angle
+            .get(&(
+                FF_TYPE_H.to_owned(),
+                FF_TYPE_O.to_owned(),
+                FF_TYPE_H.to_owned(),
+            ))
+            .ok_or_else(|| ParamError::new("Missing water angle parameters"))?;
+
+        let params = cfg.model.params();
+
+        let mut bond = bond.clone();
+        bond.r_0 = params.r_oh as f32;
+        let mut angle = angle.clone();
+        angle.theta_0 = params.θ_hoh as f32;
+
+        let (mut min, mut max) = (Vec3::splat(f64::INFINITY), Vec3::splat(f64::NEG_INFINITY));
+        for a in &self.atoms {
+            min = min.min(a.posit);
+            max = max.max(a.posit);
+        }
+        if self.atoms.is_empty() {
+            (min, max) = (Vec3::new_zero(), Vec3::new_zero());
+        }
+
+        self.cell = SimBox {
+            lo: min - Vec3::splat(cfg.padding),
+            hi: max + Vec3::splat(cfg.padding),
+        };
+
+        // Round the lattice to fit the box, so it's continuous across periodic boundaries.
+        let ext = self.cell.extent();
+        let per_side = WATER_DENSITY.cbrt();
+        let counts = [ext.x, ext.y, ext.z].map(|len| ((len * per_side).round() as usize).max(1));
+        let spacing = Vec3::new(
+            ext.x / counts[0] as f64,
+            ext.y / counts[1] as f64,
+            ext.z / counts[2] as f64,
+        );
+
+        let solute = Grid::new(
+            self.atoms.iter().chain(&self.atoms_static).map(|a| a.posit),
+            cfg.clearance,
+        );
+
+        let mut rng = StdRng::seed_from_u64(ORIENTATION_SEED);
+        let mut num_added = 0;
+
+        for i in 0..counts[0] {
+            for j in 0..counts[1] {
+                for k in 0..counts[2] {
+                    let o = self.cell.lo
+                        + Vec3::new(
+                            (i as f64 + 0.5) * spacing.x,
+                            (j as f64 + 0.5) * spacing.y,
+                            (k as f64 + 0.5) * spacing.z,
+                        );
+                    // Draw this for every site, so removing waters doesn't change others' orientations.
+                    let posits = water_posits(o, random_orientation(&mut rng), &params);
+
+                    if posits.iter().any(|p| solute.any_near(*p)) {
+                        continue;
+                    }
+
+                    self.add_water(posits, &params, &bond, &angle);
+                    num_added += 1;
+                }
+            }
+        }
+
+        self.num_waters += num_added;
+        self.build_neighbours();
+
+        Ok(num_added)
+    }
+
+    fn add_water(
+        &mut self,
+        posits: [Vec3; 3],
+        params: &WaterParams,
+        bond: &BondStretchingParams,
+        angle: &AngleBendingParams,
+    ) {
+        let i_o = self.atoms.len();
+        let (i_h0, i_h1) = (i_o + 1, i_o + 2);
+
+        let atom =
+            |element, ff_type: &str, posit, mass, partial_charge, lj_sigma, lj_eps| AtomDynamics {
+                force_field_type: ff_type.to_owned(),
+                element,
+                posit,
+                vel: Vec3::new_zero(),
+                accel: Vec3::new_zero(),
+                mass,
+                partial_charge,
+                lj_sigma,
+                lj_eps,
+            };
+
+        let q_h = -params.q_o / 2.;
+        self.atoms.push(atom(
+            Element::Oxygen,
+            FF_TYPE_O,
+            posits[0],
+            M_O,
+            params.q_o,
+            params.σ_o,
+            params.ε_o,
+        ));
+        for posit in &posits[1..] {
+            self.atoms
+                .push(atom(Element::Hydrogen, FF_TYPE_H, *posit, M_H, q_h, 0., 0.));
+        }
+
+        self.adjacency_list.push(vec![i_h0, i_h1]);
+        self.adjacency_list.push(vec![i_o]);
+        self.adjacency_list.push(vec![i_o]);
+
+        let ff = &mut self.force_field_params;
+        ff.bond_stretching.insert((i_o, i_h0), bond.clone());
+        ff.bond_stretching.insert((i_o, i_h1), bond.clone());
+        ff.angle.insert((i_h0, i_o, i_h1), angle.clone());
+
+        // All pairs within a water are 1-2, or 1-3.
+        self.excluded_pairs.insert((i_o, i_h0));
+        self.excluded_pairs.insert((i_o, i_h1));
+        self.excluded_pairs.insert((i_h0, i_h1));
+    }
+}
@@ -0,0 +1,246 @@
+//! Detects simulations that have blown up: non-finite positions or velocities, atoms moving
+//! implausibly fast, or sustained drift of the conserved energy. These usually come from a time
+//! step too large for the system, overlapping atoms, or missing parameters. When we detect one, we
+//! halt the run, restore the last stable snapshot, and report diagnostics, vice continuing to
+//! produce garbage coordinates.
+//!
+//! We check on snapshot steps, so the GPU path can check on data it already downloads.
+
+use std::fmt::Write;
+
+use lin_alg::f64::Vec3;
+
+use crate::dynamics::{MdState, thermostat::Thermostat};
+
+/// Å/fs. About 100× the RMS speed of a hydrogen at 300K.
+pub const VEL_MAX_DEFAULT: f64 = 0.2;
+/// kcal/mol per atom.
+pub const DRIFT_MAX_DEFAULT: f64 = 1.;
+/// Drift must exceed its limit at this many consecutive checks; single spikes are ignored.
+const DRIFT_CHECKS: usize = 3;
+/// The number of closest atom pairs to report.
+const NUM_PAIRS: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct StabilityConfig {
+    /// Å/fs. Halt if any atom moves faster than this.
+    pub vel_max: f64,
+    /// kcal/mol per atom. Halt if the conserved energy drifts this far from its starting value.
+    /// Only checked when the dynamics conserve it; see `MdState::energy_conserved`.
+    pub drift_max: f64,
+}
+
+impl Default for StabilityConfig {
+    fn default() -> Self {
+        Self {
+            vel_max: VEL_MAX_DEFAULT,
+            drift_max: DRIFT_MAX_DEFAULT,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Instability {
+    /// A mobile atom's position or velocity is NaN or infinite.
+    NonFinite { atom: usize },
+    /// Å/fs
+    Velocity { atom: usize, speed: f64 },
+    /// kcal/mol per atom.
+    EnergyDrift { drift: f64 },
+}
+
+impl Instability {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::NonFinite { atom } => format!("Non-finite position or velocity at atom {atom}"),
+            Self::Velocity { atom, speed } => {
+                format!("Atom {atom} moving at {speed:.3} Å/fs")
+            }
+            Self::EnergyDrift { drift } => {
+                format!("Conserved energy drifted by {drift:.3} kcal/mol per atom")
+            }
+        }
+    }
+}
+
+/// Two atoms close together, e.g. overlapping. `atom_1` indexes static atoms if `static_1` is set;
+/// otherwise, mobile ones.
+#[derive(Clone, Debug)]
+pub struct ClosePair {
+    pub atom_0: usize,
+    pub atom_1: usize,
+    pub static_1: bool,
+    /// Å
+    pub dist: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlowUp {
+    pub cause: Instability,
+    pub step: usize,
+    /// fs
+    pub time: f64,
+    /// The closest nonbonded pairs, from the last stable frame.
+    pub close_pairs: Vec<ClosePair>,
+    /// fs. The time of the last stable frame, which the atoms are restored to. `None` if the first
+    /// check failed.
+    pub last_stable: Option<f64>,
+}
+
+impl BlowUp {
+    pub fn descrip(&self) -> String {
+        let mut result = format!(
+            "MD halted at step {} ({:.1} fs): {}.",
+            self.step,
+            self.time,
+            self.cause.to_str()
+        );
+
+        match self.last_stable {
+            Some(t) => {
+                let _ = write!(result, " Restored the last stable frame, at {t:.1} fs.");
+            }
+            None => result.push_str(" There's no stable frame to restore."),
+        }
+
+        if !self.close_pairs.is_empty() {
+            result.push_str(" Closest pairs:");
+            for p in &self.close_pairs {
+                let kind = if p.static_1 { "static " } else { "" };
+                let _ = write!(result, " {}-{kind}{} ({:.2} Å)", p.atom_0, p.atom_1, p.dist);
+            }
+        }
+
+        result.push_str(" Try a smaller time step, minimizing first, or checking parameters.");
+        result
+    }
+}
+
+impl MdState {
+    /// Check for blow-ups, e.g. on snapshot steps. If we find one, set `blow_up`, and restore atoms
+    /// to the last snapshot. Returns `true` if stable.
+    pub(super) fn check_stability(&mut self) -> bool {
+        let Some(cause) = self.find_instability() else {
+            return true;
+        };
+
+        let last_stable = self.snapshots.last().map(|s| s.time);
+        if let Some(snap) = self.snapshots.last() {
+            for ((a, p), v) in self
+                .atoms
+                .iter_mut()
+                .zip(&snap.atom_posits)
+                .zip(&snap.atom_velocities)
+            {
+                a.posit = *p;
+                a.vel = *v;
+            }
+        }
+
+        self.blow_up = Some(BlowUp {
+            cause,
+            step: self.step_count,
+            time: self.time,
+            close_pairs: self.close_pairs(NUM_PAIRS),
+            last_stable,
+        });
+
+        false
+    }
+
+    fn find_instability(&mut self) -> Option<Instability> {
+        let mut fastest = (0, 0.);
+
+        for (i, a) in self.atoms.iter().enumerate() {
+            let finite = |v: Vec3| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+            if !finite(a.posit) || !finite(a.vel) {
+                return Some(Instability::NonFinite { atom: i });
+            }
+
+            let speed = a.vel.magnitude();
+            if speed > fastest.1 {
+                fastest = (i, speed);
+            }
+        }
+
+        if fastest.1 > self.stability.vel_max {
+            return Some(Instability::Velocity {
+                atom: fastest.0,
+                speed: fastest.1,
+            });
+        }
+
+        if !self.energy_is_conserved() || self.atoms.is_empty() {
+            return None;
+        }
+
+        let energy = self.energy_conserved();
+        let Some(energy_ref) = self.energy_ref else {
+            self.energy_ref = Some(energy);
+            return None;
+        };
+
+        let drift = (energy - energy_ref).abs() / self.atoms.len() as f64;
+        if drift > self.stability.drift_max {
+            self.drift_count += 1;
+            if self.drift_count >= DRIFT_CHECKS {
+                return Some(Instability::EnergyDrift { drift });
+            }
+        } else {
+            self.drift_count = 0;
+        }
+
+        None
+    }
+
+    /// If we expect `energy_conserved` to be constant. GaMD, the barostat, steering, and Berendsen
+    /// thermostats all change it.
+    fn energy_is_conserved(&self) -> bool {
+        self.gamd.is_none()
+            && self.barostat.is_none()
+            && self.steering.is_none()
+            && !matches!(self.thermostat, Some(Thermostat::Berendsen { .. }))
+    }
+
+    /// The closest pairs of atoms that interact through nonbonded forces, by brute force. Only run
+    /// this for diagnostics.
+    fn close_pairs(&self, n: usize) -> Vec<ClosePair> {
+        // Sorted by distance; we only keep the closest `n`.
+        let mut result: Vec<ClosePair> = Vec::with_capacity(n + 1);
+        let mut insert = |pair: ClosePair| {
+            if !pair.dist.is_finite() {
+                return;
+            }
+            if result.len() == n && result.last().is_some_and(|p| p.dist <= pair.dist) {
+                return;
+            }
+            let i = result.partition_point(|p| p.dist <= pair.dist);
+            result.insert(i, pair);
+            result.truncate(n);
+        };
+
+        for (i, a) in self.atoms.iter().enumerate() {
+            for (j, b) in self.atoms.iter().enumerate().skip(i + 1) {
+                if self.pair_scale(i, j).is_some() {
+                    insert(ClosePair {
+                        atom_0: i,
+                        atom_1: j,
+                        static_1: false,
+                        dist: self.cell.min_image(b.posit - a.posit).magnitude(),
+                    });
+                }
+            }
+
+            for (j, b) in self.atoms_static.iter().enumerate() {
+                insert(ClosePair {
+                    atom_0: i,
+                    atom_1: j,
+                    static_1: true,
+                    dist: self.cell.min_image(b.posit - a.posit).magnitude(),
+                });
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,184 @@
+//! Steered MD (SMD): A harmonic spring attached to a group's center of mass, with its other end
+//! moving at constant speed along a direction. E.g. pulling a ligand out of its pocket. We record
+//! the spring force against the distance pulled, and the work done; the peak force, and work
+//! profiles averaged over several pulls, give crude unbinding profiles.
+//!
+//! [Izrailev et al, 1997](https://doi.org/10.1016/S0006-3495(97)78677-2)
+
+use std::{fs::File, io, io::Write, path::Path};
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    dynamics::{
+        MdState,
+        colvar::{ColVar, ComProjection, CvRestraint, center_of_mass},
+    },
+    units::FS_PER_PS,
+};
+
+/// kcal/mol/Å². Stiff springs follow the anchor closely; soft ones resolve force better.
+pub const PULL_K_DEFAULT: f64 = 5.;
+/// Å/ps. Much faster than experiment, as is usual for SMD.
+pub const PULL_SPEED_DEFAULT: f64 = 1.;
+
+#[derive(Clone, Debug)]
+pub struct SteeringParams {
+    /// Indices of the mobile atoms to pull.
+    pub atoms: Vec<usize>,
+    /// Unit length.
+    pub direction: Vec3,
+    /// kcal/mol/Å²
+    pub k: f64,
+    /// Å/ps
+    pub speed: f64,
+}
+
+impl Default for SteeringParams {
+    fn default() -> Self {
+        Self {
+            atoms: Vec::new(),
+            direction: Vec3::new(1., 0., 0.),
+            k: PULL_K_DEFAULT,
+            speed: PULL_SPEED_DEFAULT,
+        }
+    }
+}
+
+/// One point of the force-distance profile.
+#[derive(Clone, Debug)]
+pub struct PullSample {
+    /// fs
+    pub time: f64,
+    /// Å. The anchor's displacement from the start.
+    pub anchor: f64,
+    /// Å. The group's center of mass displacement along the pull direction.
+    pub dist: f64,
+    /// kcal/mol/Å. The spring force along the pull direction.
+    pub force: f64,
+    /// kcal/mol. Accumulated work done by the spring.
+    pub work: f64,
+}
+
+pub struct SteeringState {
+    pub params: SteeringParams,
+    /// Its target is the anchor's position along the pull direction.
+    restraint: CvRestraint,
+    /// kcal/mol
+    pub work: f64,
+    /// kcal/mol/Å. From the last step.
+    force: f64,
+    pub log: Vec<PullSample>,
+}
+
+impl SteeringState {
+    /// The spring starts at the group's current center of mass, unstretched.
+    pub fn new(params: SteeringParams, md: &MdState) -> Self {
+        let group: Vec<_> = params
+            .atoms
+            .iter()
+            .filter(|&&i| i < md.atoms.len())
+            .map(|&i| (i, md.atoms[i].mass))
+            .collect();
+
+        let posits: Vec<_> = md.atoms.iter().map(|a| a.posit).collect();
+        let (origin, _) = center_of_mass(&group, &posits);
+
+        let restraint = CvRestraint {
+            cv: Box::new(ComProjection {
+                group,
+                origin,
+                direction: params.direction.to_normalized(),
+            }),
+            k: params.k,
+            target: 0.,
+        };
+
+        Self {
+            params,
+            restraint,
+            work: 0.,
+            force: 0.,
+            log: Vec::new(),
+        }
+    }
+
+    /// Write the force-distance profile as CSV.
+    pub fn save_log(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(
+            file,
+            "time_fs,anchor_a,dist_a,force_kcal_mol_a,work_kcal_mol"
+        )?;
+        for s in &self.log {
+            writeln!(
+                file,
+                "{:.3},{:.4},{:.4},{:.4},{:.4}",
+                s.time, s.anchor, s.dist, s.force, s.work
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The largest force recorded, e.g. at rupture. kcal/mol/Å
+    pub fn force_max(&self) -> f64 {
+        self.log.iter().map(|s| s.force).fold(0., f64::max)
+    }
+}
+
+impl MdState {
+    /// Advance the anchor by `dt`, in fs, and apply the spring force. Returns the bias energy, in
+    /// kcal/mol.
+    pub(super) fn apply_steering(&mut self, dt: f64) -> f64 {
+        let Some(steering) = &mut self.steering else {
+            return 0.;
+        };
+
+        let step = steering.params.speed / FS_PER_PS * dt;
+        steering.restraint.target += step;
+        // W = ∫F·dx, over the anchor's motion.
+        steering.work += steering.force * step;
+
+        let posits: Vec<_> = self.atoms.iter().map(|a| a.posit).collect();
+        let dist = steering.restraint.cv.value(&posits);
+        steering.force = steering.params.k * (steering.restraint.target - dist);
+
+        self.apply_steering_spring()
+    }
+
+    /// Apply the spring force with the anchor where it is, e.g. to re-evaluate forces after a
+    /// barostat move. Returns the bias energy, in kcal/mol.
+    pub(super) fn apply_steering_spring(&mut self) -> f64 {
+        let Some(steering) = &self.steering else {
+            return 0.;
+        };
+
+        let posits: Vec<_> = self.atoms.iter().map(|a| a.posit).collect();
+        let (energy, forces) = steering.restraint.forces(&posits);
+
+        for (i, f) in forces {
+            let atom = &mut self.atoms[i];
+            atom.accel += f / atom.mass;
+        }
+
+        energy
+    }
+
+    /// Log a point of the force-distance profile.
+    pub(super) fn record_steering(&mut self) {
+        let Some(steering) = &mut self.steering else {
+            return;
+        };
+
+        let posits: Vec<_> = self.atoms.iter().map(|a| a.posit).collect();
+        steering.log.push(PullSample {
+            time: self.time,
+            anchor: steering.restraint.target,
+            dist: steering.restraint.cv.value(&posits),
+            force: steering.force,
+            work: steering.work,
+        });
+    }
+}
@@ -0,0 +1,102 @@
+//! Residue templates from Amber library (`.lib`, `.off`) files. These give force field types and
+//! partial charges, by atom name, for residues `amino19.lib` doesn't cover: e.g. caps (ACE, NME),
+//! phosphorylated residues (SEP, TPO, PTR), and other modified residues.
+//!
+//! We only parse the `!entry.NAME.unit.atoms` tables; the remaining sections (connectivity,
+//! coordinates etc) aren't required, as we take those from the structure.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::error::DaedalusError;
+
+#[derive(Clone, Debug)]
+pub struct TemplateAtom {
+    /// E.g. "CA", "P", "O1P". Matches `type_in_res`.
+    pub name: String,
+    /// E.g. "CX", "P", "O2".
+    pub ff_type: String,
+    /// Elementary charge units.
+    pub charge: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResidueTemplate {
+    /// E.g. "SEP"; matches the residue name in the structure.
+    pub name: String,
+    pub atoms: Vec<TemplateAtom>,
+}
+
+impl ResidueTemplate {
+    pub fn atom(&self, name: &str) -> Option<&TemplateAtom> {
+        self.atoms.iter().find(|a| a.name == name)
+    }
+}
+
+/// Parse the text of an Amber library (OFF) file. Atom lines look like this:
+/// ` "CA" "CX" 0 1 131072 2 6 0.033700`; the fields are name, type, typex, resx, flags, seq,
+/// element, and charge.
+pub fn parse_lib(text: &str) -> Result<Vec<ResidueTemplate>, DaedalusError> {
+    let mut result = Vec::new();
+    let mut current: Option<ResidueTemplate> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.starts_with('!') {
+            if let Some(t) = current.take() {
+                result.push(t);
+            }
+
+            if let Some(rest) = line.strip_prefix("!entry.") {
+                if let Some((name, _)) = rest.split_once(".unit.atoms table") {
+                    current = Some(ResidueTemplate {
+                        name: name.to_owned(),
+                        atoms: Vec::new(),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let Some(template) = &mut current else {
+            continue;
+        };
+
+        let cols: Vec<_> = line.split_whitespace().collect();
+        if cols.len() < 8 {
+            return Err(DaedalusError::Parse(format!(
+                "Invalid atom line in residue template {}: {line}",
+                template.name
+            )));
+        }
+
+        let Ok(charge) = cols[7].parse() else {
+            return Err(DaedalusError::Parse(format!(
+                "Invalid charge in residue template {}: {line}",
+                template.name
+            )));
+        };
+
+        template.atoms.push(TemplateAtom {
+            name: cols[0].trim_matches('"').to_owned(),
+            ff_type: cols[1].trim_matches('"').to_owned(),
+            charge,
+        });
+    }
+
+    if let Some(t) = current {
+        result.push(t);
+    }
+
+    Ok(result)
+}
+
+/// Load templates from a `.lib` or `.off` file, keyed by residue name.
+pub fn load_lib(path: &Path) -> io::Result<HashMap<String, ResidueTemplate>> {
+    let text = fs::read_to_string(path)?;
+
+    let templates =
+        parse_lib(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    Ok(templates.into_iter().map(|t| (t.name.clone(), t)).collect())
+}
@@ -0,0 +1,126 @@
+//! Thermostats, for sampling at a constant temperature (NVT).
+//!
+//! Berendsen rescales velocities towards the target temperature. It equilibrates quickly, but
+//! doesn't produce a canonical ensemble. Nosé-Hoover chains couple the system to a chain of
+//! thermostat variables, and are deterministic, and canonical. We integrate the chain with the
+//! Trotter splitting of [Martyna, Tuckerman, Tobias, Klein, 1996](https://doi.org/10.1080/00268979600100761),
+//! and track the extended system's conserved quantity, for validation.
+
+use crate::units::{FS_PER_PS, K_B};
+
+/// ps
+pub const BERENDSEN_TAU_DEFAULT: f64 = 0.1;
+/// ps
+pub const NHC_TAU_DEFAULT: f64 = 0.5;
+/// Thermostat variables per chain. Longer chains better damp oscillations of the first.
+pub const NHC_CHAIN_LEN_DEFAULT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Thermostat {
+    /// `tau` is the coupling time constant, in ps.
+    Berendsen { tau: f64 },
+    /// `tau` is the period of the thermostat's oscillations, in ps.
+    NoseHooverChain { tau: f64, chain_len: usize },
+}
+
+impl Thermostat {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Berendsen { .. } => "Berendsen",
+            Self::NoseHooverChain { .. } => "Nosé-Hoover chain",
+        }
+    }
+}
+
+/// The state of a Nosé-Hoover chain: each thermostat variable's position, velocity, and mass.
+#[derive(Clone, Debug, Default)]
+pub struct NhcState {
+    /// Dimensionless.
+    pub ξ: Vec<f64>,
+    /// 1/fs
+    pub v_ξ: Vec<f64>,
+    /// kcal·fs²/mol
+    q: Vec<f64>,
+    dof: usize,
+    /// kcal/mol
+    kt: f64,
+}
+
+impl NhcState {
+    pub fn new(chain_len: usize, tau_ps: f64, dof: usize, target_temp: f64) -> Self {
+        let kt = K_B * target_temp;
+        let tau = tau_ps * FS_PER_PS;
+
+        // The first variable couples to all degrees of freedom; the others, to one each.
+        let mut q = vec![kt * tau.powi(2); chain_len.max(1)];
+        q[0] *= dof as f64;
+
+        Self {
+            ξ: vec![0.; q.len()],
+            v_ξ: vec![0.; q.len()],
+            q,
+            dof,
+            kt,
+        }
+    }
+
+    /// Force on chain variable `j`. `ke` is the system's kinetic energy, in kcal/mol.
+    fn g(&self, j: usize, ke: f64) -> f64 {
+        if j == 0 {
+            (2. * ke - self.dof as f64 * self.kt) / self.q[0]
+        } else {
+            (self.q[j - 1] * self.v_ξ[j - 1].powi(2) - self.kt) / self.q[j]
+        }
+    }
+
+    /// Propagate the chain for `dt`, in fs; half a time step. `ke` is the system's kinetic energy,
+    /// in kcal/mol. Returns the factor to scale atom velocities by.
+    pub fn propagate(&mut self, ke: f64, dt: f64) -> f64 {
+        let last = self.q.len() - 1;
+        let aa = |v_next: f64| (-dt / 4. * v_next).exp();
+
+        // From the end of the chain, to the system.
+        self.v_ξ[last] += self.g(last, ke) * dt / 2.;
+        for j in (0..last).rev() {
+            let a = aa(self.v_ξ[j + 1]);
+            self.v_ξ[j] = self.v_ξ[j] * a * a + self.g(j, ke) * a * dt / 2.;
+        }
+
+        let scale = (-dt * self.v_ξ[0]).exp();
+        let ke = ke * scale * scale;
+
+        for (ξ, v) in self.ξ.iter_mut().zip(&self.v_ξ) {
+            *ξ += v * dt;
+        }
+
+        // And back.
+        for j in 0..last {
+            let a = aa(self.v_ξ[j + 1]);
+            self.v_ξ[j] = self.v_ξ[j] * a * a + self.g(j, ke) * a * dt / 2.;
+        }
+        self.v_ξ[last] += self.g(last, ke) * dt / 2.;
+
+        scale
+    }
+
+    /// Change the target temperature, e.g. during an annealing schedule. We keep the chain's masses,
+    /// so its period drifts slightly from `tau` away from the initial temperature.
+    pub fn set_target_temp(&mut self, target_temp: f64) {
+        self.kt = K_B * target_temp;
+    }
+
+    /// The chain's contribution to the extended system's conserved quantity. kcal/mol
+    pub fn energy(&self) -> f64 {
+        let kinetic: f64 = self
+            .v_ξ
+            .iter()
+            .zip(&self.q)
+            .map(|(v, q)| 0.5 * q * v * v)
+            .sum();
+
+        let potential = self.dof as f64 * self.kt * self.ξ[0]
+            + self.ξ[1..].iter().map(|ξ| self.kt * ξ).sum::<f64>();
+
+        kinetic + potential
+    }
+}
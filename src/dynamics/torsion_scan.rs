@@ -0,0 +1,242 @@
+//! Torsion scans: Rotate one dihedral through a full turn in fixed steps, and evaluate the force
+//! field's energy at each. The resulting profile shows whether a ligand's torsion parameters
+//! (e.g. GAFF2's) put minima and barriers where expected. Each step is either a single-point
+//! evaluation with the rest of the molecule rigid, or a minimization with the scanned dihedral
+//! restrained.
+
+use std::{
+    f64::consts::TAU,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use lin_alg::f64::{Quaternion, Vec3};
+use na_seq::Element;
+
+use crate::{
+    FfParamSet,
+    dynamics::{
+        MdConfig, MdState, ParamError,
+        colvar::{ColVar, CvRestraint, Dihedral},
+    },
+    error::DaedalusError,
+    molecule::Molecule,
+};
+
+#[derive(Clone, Debug)]
+pub struct TorsionScanParams {
+    /// Degrees.
+    pub step: f64,
+    /// Minimize the rest of the molecule at each step. Otherwise, energies are single-point.
+    pub minimize: bool,
+    /// kcal/(mol·rad²). Holds the scanned dihedral at its target while minimizing.
+    pub restraint_k: f64,
+}
+
+impl Default for TorsionScanParams {
+    fn default() -> Self {
+        Self {
+            step: 10.,
+            minimize: false,
+            restraint_k: 500.,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TorsionScanPoint {
+    /// Radians, from -τ/2 to τ/2.
+    pub angle: f64,
+    /// kcal/mol, relative to the scan's lowest.
+    pub energy: f64,
+    pub atom_posits: Vec<Vec3>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TorsionScan {
+    /// The bond scanned, by index.
+    pub bond: usize,
+    /// The four atoms defining the dihedral.
+    pub atoms: (usize, usize, usize, usize),
+    /// In order of increasing angle.
+    pub points: Vec<TorsionScanPoint>,
+}
+
+impl TorsionScan {
+    /// kcal/mol. The highest energy in the scan, relative to the lowest.
+    pub fn barrier(&self) -> f64 {
+        self.points.iter().map(|p| p.energy).fold(0., f64::max)
+    }
+
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "angle_deg,energy_kcal_mol")?;
+        for p in &self.points {
+            writeln!(file, "{:.1},{:.4}", p.angle.to_degrees(), p.energy)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MdState {
+    /// kcal/mol. The force field energy, including dihedrals.
+    /// Excludes restraints and external fields.
+    fn scan_energy(&mut self) -> f64 {
+        self.build_neighbours();
+
+        let energy = self.apply_bond_stretching_forces()
+            + self.apply_angle_bending_forces()
+            + self.apply_dihedral_forces()
+            + self.apply_nonbonded_forces();
+
+        for a in &mut self.atoms {
+            a.accel = Vec3::new_zero();
+        }
+
+        energy
+    }
+}
+
+/// The first heavy atom bonded to `atom`, other than `exclude`, or any atom if there are none.
+fn dihedral_neighbor(mol: &Molecule, atom: usize, exclude: usize) -> Option<usize> {
+    let neighbors: Vec<_> = mol
+        .adjacency_list
+        .get(atom)?
+        .iter()
+        .copied()
+        .filter(|n| *n != exclude)
+        .collect();
+
+    neighbors
+        .iter()
+        .copied()
+        .find(|n| mol.atoms[*n].element != Element::Hydrogen)
+        .or(neighbors.first().copied())
+}
+
+/// Atoms on `pivot`'s side of the bond between `fixed` and `pivot`, including `pivot`. `None` if
+/// the bond is in a ring, in which case rotating it would move both sides.
+fn moving_atoms(mol: &Molecule, fixed: usize, pivot: usize) -> Option<Vec<usize>> {
+    let mut visited = vec![false; mol.atoms.len()];
+    visited[pivot] = true;
+    let mut stack = vec![pivot];
+    let mut result = Vec::new();
+
+    while let Some(i) = stack.pop() {
+        result.push(i);
+        for &n in mol.adjacency_list.get(i).into_iter().flatten() {
+            if i == pivot && n == fixed {
+                continue;
+            }
+            if n == fixed {
+                return None;
+            }
+            if !visited[n] {
+                visited[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+
+    Some(result)
+}
+
+/// Scan the dihedral about bond `bond`, starting from `posits`. The molecule is evaluated alone,
+/// in vacuum.
+pub fn torsion_scan(
+    mol: &Molecule,
+    posits: &[Vec3],
+    bond: usize,
+    ff_params: &FfParamSet,
+    cfg: &MdConfig,
+    params: &TorsionScanParams,
+) -> Result<TorsionScan, DaedalusError> {
+    let Some(b) = mol.bonds.get(bond) else {
+        return Err(ParamError::new("No bond selected to scan").into());
+    };
+    let (j, k) = (b.atom_0, b.atom_1);
+
+    let (Some(i), Some(l)) = (dihedral_neighbor(mol, j, k), dihedral_neighbor(mol, k, j)) else {
+        return Err(
+            ParamError::new("Both atoms of the scanned bond must have another neighbor").into(),
+        );
+    };
+
+    let Some(moving) = moving_atoms(mol, j, k) else {
+        return Err(ParamError::new("Can't scan a bond in a ring").into());
+    };
+
+    if params.step <= 0. {
+        return Err(ParamError::new("The scan step must be positive").into());
+    }
+
+    let mut md = MdState::new(
+        &mol.atoms,
+        posits,
+        &mol.adjacency_list,
+        &mol.bonds,
+        &[],
+        ff_params,
+        &[],
+    )?;
+    md.check_params(cfg.allow_missing_params)?;
+
+    let dihedral = Dihedral {
+        atoms: (i, j, k, l),
+    };
+    let num_steps = (360. / params.step).round().max(1.) as usize;
+
+    let mut points = Vec::with_capacity(num_steps);
+    for step in 0..num_steps {
+        let target = -TAU / 2. + step as f64 * TAU / num_steps as f64;
+
+        // Rotate the pivot's side about the bond axis, from the original positions, so errors
+        // don't accumulate.
+        let mut step_posits = posits.to_vec();
+        let current = dihedral.value(&step_posits);
+        let axis = (step_posits[k] - step_posits[j]).to_normalized();
+        let rotator = Quaternion::from_axis_angle(axis, dihedral.diff(target, current));
+        let pivot_posit = step_posits[k];
+        for &a in &moving {
+            step_posits[a] = pivot_posit + rotator.rotate_vec(step_posits[a] - pivot_posit);
+        }
+
+        for (a, p) in md.atoms.iter_mut().zip(&step_posits) {
+            a.posit = *p;
+        }
+
+        if params.minimize {
+            md.cv_restraints = vec![CvRestraint {
+                cv: Box::new(dihedral.clone()),
+                k: params.restraint_k,
+                target,
+            }];
+            md.minimize(&cfg.minimize);
+            md.cv_restraints.clear();
+        }
+
+        let atom_posits: Vec<_> = md.atoms.iter().map(|a| a.posit).collect();
+        points.push(TorsionScanPoint {
+            angle: dihedral.value(&atom_posits),
+            energy: md.scan_energy(),
+            atom_posits,
+        });
+    }
+
+    let e_min = points
+        .iter()
+        .map(|p| p.energy)
+        .fold(f64::INFINITY, f64::min);
+    for p in &mut points {
+        p.energy -= e_min;
+    }
+
+    Ok(TorsionScan {
+        bond,
+        atoms: (i, j, k, l),
+        points,
+    })
+}
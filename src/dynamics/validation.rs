@@ -0,0 +1,102 @@
+//! Checks for force field parameters missing from the loaded sets, for the atoms in a simulation.
+//! When a mass or VdW parameter is missing, we fall back to one of a similar type, or to a
+//! placeholder; missing bonded terms are omitted. These make the simulation run, but not
+//! accurately, so we collect them into a report the user must accept before running.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::dynamics::{MdState, ParamError};
+
+/// Identified by force field types.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MissingParam {
+    Mass(String),
+    Vdw(String),
+    Bond([String; 2]),
+    Angle([String; 3]),
+    Dihedral([String; 4]),
+}
+
+impl MissingParam {
+    /// Bonded terms apply in either direction; use one order, so each is only listed once.
+    pub fn bond(t0: &str, t1: &str) -> Self {
+        let mut types = [t0.to_owned(), t1.to_owned()];
+        types.sort();
+        Self::Bond(types)
+    }
+
+    pub fn angle(t0: &str, t1: &str, t2: &str) -> Self {
+        let mut types = [t0.to_owned(), t1.to_owned(), t2.to_owned()];
+        if types[2] < types[0] {
+            types.reverse();
+        }
+        Self::Angle(types)
+    }
+
+    pub fn dihedral(t0: &str, t1: &str, t2: &str, t3: &str) -> Self {
+        let mut types = [t0.to_owned(), t1.to_owned(), t2.to_owned(), t3.to_owned()];
+        let mut rev = types.clone();
+        rev.reverse();
+        if rev < types {
+            types = rev;
+        }
+        Self::Dihedral(types)
+    }
+
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Mass(t) => format!("Mass: {t}"),
+            Self::Vdw(t) => format!("VdW: {t}"),
+            Self::Bond(t) => format!("Bond: {}", t.join("-")),
+            Self::Angle(t) => format!("Angle: {}", t.join("-")),
+            Self::Dihedral(t) => format!("Dihedral: {}", t.join("-")),
+        }
+    }
+}
+
+/// Missing parameters, with the number of atoms or terms affected by each.
+#[derive(Clone, Debug, Default)]
+pub struct ParamReport {
+    pub missing: BTreeMap<MissingParam, usize>,
+}
+
+impl ParamReport {
+    pub fn add(&mut self, param: MissingParam) {
+        *self.missing.entry(param).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: ParamReport) {
+        for (param, count) in other.missing {
+            *self.missing.entry(param).or_default() += count;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for ParamReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (param, count) in &self.missing {
+            writeln!(f, "{} (×{count})", param.to_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl MdState {
+    /// Call this after setting up atoms, and before running. Returns an error listing missing
+    /// parameters, unless `allow_missing` is set; if so, we run with the fallbacks.
+    pub fn check_params(&self, allow_missing: bool) -> Result<(), ParamError> {
+        if self.param_report.is_empty() || allow_missing {
+            return Ok(());
+        }
+
+        Err(ParamError::new(&format!(
+            "Missing force field parameters. Load them (e.g. a frcmod file), or allow missing \
+            parameters to run with fallbacks:\n{}",
+            self.param_report
+        )))
+    }
+}
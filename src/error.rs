@@ -0,0 +1,40 @@
+//! A crate-wide error type. Lower-level code reports problems with `io::Error` (files), or
+//! `ParamError` (force field parameters and simulation setup); operations that combine these,
+//! such as setting up MD or docking, return `DaedalusError`, with context describing which step
+//! failed. Its `Display` output is suitable for showing in the UI.
+
+use std::io;
+
+use thiserror::Error;
+
+use crate::dynamics::ParamError;
+
+#[derive(Debug, Error)]
+pub enum DaedalusError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Param(#[from] ParamError),
+    /// A file or text we can't interpret, e.g. a force field file with an invalid line.
+    #[error("{0}")]
+    Parse(String),
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source: Box<DaedalusError>,
+    },
+}
+
+/// Adds context to errors, e.g. `setup.prepare().context("Preparing the docking site")?`.
+pub trait ResultExt<T> {
+    fn context(self, context: &str) -> Result<T, DaedalusError>;
+}
+
+impl<T, E: Into<DaedalusError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: &str) -> Result<T, DaedalusError> {
+        self.map_err(|e| DaedalusError::Context {
+            context: context.to_owned(),
+            source: Box::new(e.into()),
+        })
+    }
+}
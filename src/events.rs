@@ -0,0 +1,143 @@
+//! An event bus, so code embedding the viewer can react to changes in its state without polling
+//! `State`. Subscribe with a callback, or a channel for use from other threads. Events are queued
+//! as they happen, and dispatched once per frame, after the UI and scene updates.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::{
+    Selection,
+    docking::{BindingEnergy, Pose},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    SelectionChanged,
+    MoleculeLoaded,
+    DockingPoseUpdated,
+    MdFrameAdvanced,
+}
+
+#[derive(Clone, Debug)]
+pub enum ViewerEvent {
+    SelectionChanged(Selection),
+    /// A protein or other molecule, or a ligand, finished loading.
+    MoleculeLoaded {
+        ident: String,
+        ligand: bool,
+    },
+    DockingPoseUpdated {
+        pose: Pose,
+        binding_energy: BindingEnergy,
+    },
+    /// The MD snapshot displayed changed.
+    MdFrameAdvanced {
+        snapshot: usize,
+    },
+}
+
+impl ViewerEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::SelectionChanged(_) => EventKind::SelectionChanged,
+            Self::MoleculeLoaded { .. } => EventKind::MoleculeLoaded,
+            Self::DockingPoseUpdated { .. } => EventKind::DockingPoseUpdated,
+            Self::MdFrameAdvanced { .. } => EventKind::MdFrameAdvanced,
+        }
+    }
+}
+
+pub type SubscriptionId = usize;
+
+enum Subscriber {
+    Callback(Box<dyn FnMut(&ViewerEvent)>),
+    Channel(Sender<ViewerEvent>),
+}
+
+struct Subscription {
+    id: SubscriptionId,
+    /// Empty means all kinds.
+    kinds: Vec<EventKind>,
+    subscriber: Subscriber,
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    subscriptions: Vec<Subscription>,
+    next_id: SubscriptionId,
+    queue: Vec<ViewerEvent>,
+    /// For detecting selection changes; these are made from many places.
+    selection_prev: Selection,
+}
+
+impl EventBus {
+    fn add(&mut self, kinds: &[EventKind], subscriber: Subscriber) -> SubscriptionId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.subscriptions.push(Subscription {
+            id,
+            kinds: kinds.to_vec(),
+            subscriber,
+        });
+        id
+    }
+
+    /// Call `f` on each event of the given kinds, or all events if `kinds` is empty. It runs on
+    /// the UI thread, so should return quickly.
+    pub fn subscribe(
+        &mut self,
+        kinds: &[EventKind],
+        f: impl FnMut(&ViewerEvent) + 'static,
+    ) -> SubscriptionId {
+        self.add(kinds, Subscriber::Callback(Box::new(f)))
+    }
+
+    /// Receive events of the given kinds, or all events if `kinds` is empty, on a channel. The
+    /// subscription is removed once the receiver is dropped.
+    pub fn subscribe_channel(
+        &mut self,
+        kinds: &[EventKind],
+    ) -> (SubscriptionId, Receiver<ViewerEvent>) {
+        let (tx, rx) = mpsc::channel();
+        (self.add(kinds, Subscriber::Channel(tx)), rx)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscriptions.retain(|s| s.id != id);
+    }
+
+    /// Queue an event; it's sent to subscribers on the next `dispatch`.
+    pub fn emit(&mut self, event: ViewerEvent) {
+        if !self.subscriptions.is_empty() {
+            self.queue.push(event);
+        }
+    }
+
+    /// Queue a `SelectionChanged` event, if the selection is different from when last checked.
+    pub fn check_selection(&mut self, selection: &Selection) {
+        if *selection != self.selection_prev {
+            self.selection_prev = selection.clone();
+            self.emit(ViewerEvent::SelectionChanged(selection.clone()));
+        }
+    }
+
+    /// Send queued events to subscribers. Run once per frame.
+    pub fn dispatch(&mut self) {
+        for event in std::mem::take(&mut self.queue) {
+            let kind = event.kind();
+
+            self.subscriptions.retain_mut(|sub| {
+                if !sub.kinds.is_empty() && !sub.kinds.contains(&kind) {
+                    return true;
+                }
+                match &mut sub.subscriber {
+                    Subscriber::Callback(f) => {
+                        f(&event);
+                        true
+                    }
+                    Subscriber::Channel(tx) => tx.send(event.clone()).is_ok(),
+                }
+            });
+        }
+    }
+}
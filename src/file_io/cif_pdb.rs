@@ -8,6 +8,7 @@ use std::{
 
 use bio_files::{Chain, ResidueType};
 use lin_alg::f64::Vec3;
+use log::warn;
 use na_seq::{
     AtomTypeInRes,
     Element::{self, *},
@@ -16,9 +17,10 @@ use pdbtbx::{Format, PDB, ReadOptions, StrictnessLevel};
 use rayon::prelude::*;
 
 use crate::{
+    assembly::load_assemblies,
     docking::prep::DockType,
     file_io::cif_aux::load_data,
-    molecule::{Atom, AtomRole, Molecule, Residue},
+    molecule::{AltLocPolicy, Atom, AtomRole, Molecule, Residue},
 };
 
 impl Atom {
@@ -28,6 +30,7 @@ impl Atom {
         // aa_map: &HashMap<usize, ResidueType>,
         aa_map: &HashMap<usize, usize>, // atom_i: res_i
         residues: &[Residue],
+        alt_loc: Option<char>,
     ) -> Self {
         // let mut residue_type = ResidueType::Other("".to_owned());
         let mut residue = None;
@@ -49,6 +52,11 @@ impl Atom {
 
         let name = atom_pdb.name().to_owned();
 
+        // pdbtbx stores the full, symmetric tensor.
+        let anisou = atom_pdb
+            .anisotropic_temperature_factors()
+            .map(|u| [u[0][0], u[1][1], u[2][2], u[0][1], u[0][2], u[1][2]].map(|v| v as f32));
+
         Self {
             serial_number: atom_pdb.serial_number() + 1,
             posit: Vec3::new(atom_pdb.x(), atom_pdb.y(), atom_pdb.z()),
@@ -59,32 +67,56 @@ impl Atom {
             residue,
             // residue_type,
             hetero: atom_pdb.hetero(),
-            occupancy: None,
-            temperature_factor: None,
+            occupancy: Some(atom_pdb.occupancy() as f32),
+            temperature_factor: Some(atom_pdb.b_factor() as f32),
+            alt_loc,
+            anisou,
             partial_charge: None,
             dock_type: Some(DockType::from_str(atom_pdb.name())), // Updated later with Donor/Acceptor
+            props: Default::default(),
+            in_ring: false,
+            aromatic: false,
         }
     }
 }
 
 impl Molecule {
     /// From `pdbtbx`'s format. Uses raw data too to add secondary structure, which pdbtbx doesn't handle.
-    pub fn from_cif_pdb<R: Read + Seek>(pdb: &PDB, raw: R) -> io::Result<Self> {
+    pub fn from_cif_pdb<R: Read + Seek>(pdb: &PDB, mut raw: R) -> io::Result<Self> {
         // todo: Maybe return the PDB type here, and store that. Also have a way to
         // todo get molecules from it
 
         // todo: Pdbtbx doesn't implm this yet for CIF.
         // for remark in pdb.remarks() {}
 
-        let atoms_pdb: Vec<&pdbtbx::Atom> = pdb.par_atoms().collect();
+        let (alt_locs, alt_of) = pick_alt_locs(pdb, AltLocPolicy::default());
+
+        // Alternate locations not picked are kept aside, and added to the molecule at the end.
+        let (atoms_pdb, atoms_alt): (Vec<&pdbtbx::Atom>, Vec<_>) = pdb
+            .par_atoms()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .partition(|a| !alt_of.contains_key(&a.serial_number()));
+
+        // Built once, so matching atoms to residues and chains scales linearly. { atom sn: atom_i }
+        let mut atom_indices = HashMap::with_capacity(atoms_pdb.len());
+        for (i, atom) in atoms_pdb.iter().enumerate() {
+            atom_indices.entry(atom.serial_number()).or_insert(i);
+        }
 
         let mut residues: Vec<Residue> = pdb
             .par_residues()
-            .map(|res| Residue::from_pdb(res, &atoms_pdb))
+            .map(|res| Residue::from_pdb(res, &atom_indices))
             .collect();
 
         residues.sort_by_key(|r| r.serial_number);
 
+        // { res sn: res_i }. Serial numbers repeat across chains.
+        let mut res_indices: HashMap<_, Vec<usize>> = HashMap::new();
+        for (i, res) in residues.iter().enumerate() {
+            res_indices.entry(res.serial_number).or_default().push(i);
+        }
+
         let mut chains = Vec::with_capacity(pdb.chain_count());
         for chain_pdb in pdb.chains() {
             let mut chain = Chain {
@@ -95,31 +127,27 @@ impl Molecule {
             };
 
             for atom_c in chain_pdb.atoms() {
-                let atom_pdb = atoms_pdb
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, a)| a.serial_number() == atom_c.serial_number());
-                if let Some((i, _atom)) = atom_pdb {
-                    chain.atoms.push(i);
+                if let Some(i) = atom_indices.get(&atom_c.serial_number()) {
+                    chain.atoms.push(*i);
                 }
             }
 
             // We don't have a way to, using serial numbers alone, using PDBTBX, find which residues are associated with
             // which chain. This method is a bit more indirect, using both serial number, and atom indexes.
             for res_c in chain_pdb.residues() {
-                for (i, res) in residues.iter().enumerate() {
-                    if res.serial_number == res_c.serial_number() {
-                        let atom_sns_chain: Vec<usize> =
-                            res_c.atoms().map(|a| a.serial_number()).collect();
-                        // let atom_sns_res: Vec<usize> = res.atoms.iter().map(|a| a.serial_number).collect();
-                        let mut atom_sns_res = Vec::with_capacity(res.atoms.len());
-                        for atom_i in &res.atoms {
-                            atom_sns_res.push(atoms_pdb[*atom_i].serial_number());
-                        }
-
-                        if atom_sns_chain == atom_sns_res {
-                            chain.residues.push(i);
-                        }
+                let Some(candidates) = res_indices.get(&res_c.serial_number()) else {
+                    continue;
+                };
+                let atom_sns_chain: Vec<usize> = res_c.atoms().map(|a| a.serial_number()).collect();
+
+                for &i in candidates {
+                    let atom_sns_res = residues[i]
+                        .atoms
+                        .iter()
+                        .map(|atom_i| atoms_pdb[*atom_i].serial_number());
+
+                    if atom_sns_res.eq(atom_sns_chain.iter().copied()) {
+                        chain.residues.push(i);
                     }
                 }
             }
@@ -136,11 +164,25 @@ impl Molecule {
             }
         }
 
+        let alt_loc = |atom: &pdbtbx::Atom| alt_locs.get(&atom.serial_number()).copied();
+
         // todo: This is taking a while.
         let atoms: Vec<Atom> = atoms_pdb
             .into_iter()
             .enumerate()
-            .map(|(i, atom)| Atom::from_cif_pdb(atom, i, &aa_map, &residues))
+            .map(|(i, atom)| Atom::from_cif_pdb(atom, i, &aa_map, &residues, alt_loc(atom)))
+            .collect();
+
+        // Keyed by the serial number of the atom picked; we match these to indices once the
+        // molecule is built, since building may add or remove hydrogens.
+        let atoms_alt: Vec<(usize, Atom)> = atoms_alt
+            .into_iter()
+            .filter_map(|atom| {
+                let sn_picked = alt_of[&atom.serial_number()];
+                let i = *atom_indices.get(&sn_picked)?;
+                let atom = Atom::from_cif_pdb(atom, i, &aa_map, &residues, alt_loc(atom));
+                Some((atoms[i].serial_number, atom))
+            })
             .collect();
 
         // todo: We use our own bond inference, since most PDBs seem to lack bond information.
@@ -160,14 +202,30 @@ impl Molecule {
             None,
         );
 
-        (result.secondary_structure, result.method) = load_data(raw)?;
+        (result.secondary_structure, result.method) = load_data(&mut raw)?;
+        result.assemblies = load_assemblies(&mut raw)?;
+
+        if !atoms_alt.is_empty() {
+            let indices: HashMap<_, _> = result
+                .atoms
+                .iter()
+                .enumerate()
+                .map(|(i, a)| (a.serial_number, i))
+                .collect();
+
+            result.alt_loc_atoms = atoms_alt
+                .into_iter()
+                .filter_map(|(sn, atom)| Some((*indices.get(&sn)?, atom)))
+                .collect();
+        }
 
         Ok(result)
     }
 }
 
 impl Residue {
-    pub fn from_pdb(res_pdb: &pdbtbx::Residue, atoms_pdb: &[&pdbtbx::Atom]) -> Self {
+    /// `atom_indices` maps atom serial numbers to their indices in the molecule.
+    pub fn from_pdb(res_pdb: &pdbtbx::Residue, atom_indices: &HashMap<usize, usize>) -> Self {
         let res_name = res_pdb.name().unwrap_or_default();
 
         let res_type = ResidueType::from_str(res_name);
@@ -177,15 +235,13 @@ impl Residue {
             res_type,
             atoms: Vec::new(),
             dihedral: None,
+            props: Default::default(),
+            variant: None,
         };
 
         for atom_c in res_pdb.atoms() {
-            let atom_pdb = atoms_pdb
-                .iter()
-                .enumerate()
-                .find(|(_i, a)| a.serial_number() == atom_c.serial_number());
-            if let Some((i, _atom)) = atom_pdb {
-                res.atoms.push(i);
+            if let Some(i) = atom_indices.get(&atom_c.serial_number()) {
+                res.atoms.push(*i);
             }
         }
 
@@ -193,6 +249,51 @@ impl Residue {
     }
 }
 
+/// Find atoms modeled in several alternate locations, and pick one of each to make active.
+/// Returns { atom sn: alt loc } for all atoms with one, and { atom sn: sn of the atom picked in its
+/// place } for those not picked. Alternates are matched by atom name, within each residue.
+fn pick_alt_locs(pdb: &PDB, policy: AltLocPolicy) -> (HashMap<usize, char>, HashMap<usize, usize>) {
+    let mut alt_locs = HashMap::new();
+    let mut alt_of = HashMap::new();
+
+    for res in pdb.residues() {
+        let mut by_name: HashMap<&str, Vec<(&pdbtbx::Atom, char)>> = HashMap::new();
+
+        for conformer in res.conformers() {
+            let Some(alt_loc) = conformer
+                .alternative_location()
+                .and_then(|a| a.chars().next())
+            else {
+                continue;
+            };
+
+            for atom in conformer.atoms() {
+                alt_locs.insert(atom.serial_number(), alt_loc);
+                by_name
+                    .entry(atom.name())
+                    .or_default()
+                    .push((atom, alt_loc));
+            }
+        }
+
+        for alts in by_name.values() {
+            let candidates: Vec<_> = alts
+                .iter()
+                .map(|(a, alt_loc)| (Some(*alt_loc), Some(a.occupancy() as f32)))
+                .collect();
+            let sn_picked = alts[policy.pick(&candidates)].0.serial_number();
+
+            for (atom, _) in alts {
+                if atom.serial_number() != sn_picked {
+                    alt_of.insert(atom.serial_number(), sn_picked);
+                }
+            }
+        }
+    }
+
+    (alt_locs, alt_of)
+}
+
 /// From a string of a CIF or PDB text file.
 pub fn read_pdb(pdb_text: &str) -> io::Result<PDB> {
     let reader = BufReader::new(pdb_text.as_bytes());
@@ -280,7 +381,7 @@ pub fn el_from_pdb(el: Option<&pdbtbx::Element>) -> Element {
             pdbtbx::Element::Ru => Rubidium,
 
             _ => {
-                eprintln!("Unknown element: {e:?}");
+                warn!("Unknown element: {e:?}");
                 Element::Other
             }
         }
@@ -0,0 +1,664 @@
+//! Format conversion without the GUI, e.g. for use in pipelines. Transcodes between PDB, mmCIF,
+//! Mol2, SDF, PDBQT, and XYZ, optionally keeping only part of the structure.
+//!
+//! From the command line: `daedalus convert <input> <output> [--sel all|protein|ligand|no-water] [--no-h]`,
+//! or for a batch: `daedalus convert <inputs>... --to <ext> [--out-dir <dir>] [options]`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    fs::File,
+    io,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use bio_files::{Chain, Mol2, ResidueType, sdf::Sdf};
+use lin_alg::f64::Vec3;
+use na_seq::{AaIdent, Element};
+
+use crate::{
+    file_io::{cif_pdb::load_cif_pdb, pdbqt::load_pdbqt},
+    molecule::{Atom, AtomRole, Bond, Molecule, Residue},
+};
+
+pub const CONVERT_FORMATS: [&str; 7] = ["pdb", "cif", "mol2", "sdf", "pdbqt", "xyz", "mmcif"];
+
+/// Which atoms to write.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum ConvertSel {
+    #[default]
+    All,
+    /// Non-hetero atoms; e.g. no waters, ions, or ligands.
+    Protein,
+    /// Hetero residues large enough to be ligands. For small-molecule files, all atoms.
+    Ligand,
+    /// Everything except waters.
+    NoWater,
+}
+
+impl FromStr for ConvertSel {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "all" => Self::All,
+            "protein" => Self::Protein,
+            "ligand" => Self::Ligand,
+            "no-water" | "nowater" => Self::NoWater,
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid selection: {s}. Use all, protein, ligand, or no-water"),
+                ));
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    pub sel: ConvertSel,
+    pub hydrogens: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            sel: Default::default(),
+            hydrogens: true,
+        }
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+        .to_str()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn unsupported(path: &Path) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+            "Unsupported format: {}. Supported: {}",
+            path.display(),
+            CONVERT_FORMATS.join(", ")
+        ),
+    )
+}
+
+/// Load a molecule from any supported format, without touching application state.
+pub fn load_molecule(path: &Path) -> io::Result<Molecule> {
+    Ok(match extension(path).as_ref() {
+        "sdf" => Sdf::load(path)?.into(),
+        "mol2" => Mol2::load(path)?.into(),
+        "pdbqt" => load_pdbqt(path)?.0,
+        "pdb" | "cif" | "mmcif" => {
+            let pdb = load_cif_pdb(path)?;
+            let file = File::open(path)?;
+            Molecule::from_cif_pdb(&pdb, &file)?
+        }
+        "xyz" => load_xyz(path)?,
+        _ => return Err(unsupported(path)),
+    })
+}
+
+/// Save a molecule to any supported format.
+pub fn save_molecule(mol: &Molecule, path: &Path) -> io::Result<()> {
+    match extension(path).as_ref() {
+        "sdf" => mol.to_sdf().save(path),
+        "mol2" => mol.to_mol2().save(path),
+        // Note: This skips waters.
+        "pdbqt" => mol.save_pdbqt(path, None),
+        "pdb" => save_pdb_atoms(mol, path),
+        "cif" | "mmcif" => save_cif_atoms(mol, path),
+        "xyz" => save_xyz(mol, path),
+        _ => Err(unsupported(path)),
+    }
+}
+
+/// Indices of the atoms to keep.
+fn select(mol: &Molecule, options: &ConvertOptions) -> Vec<usize> {
+    let small_mol = mol.residues.is_empty();
+
+    let het_atoms: Vec<usize> = mol
+        .het_residues
+        .iter()
+        .flat_map(|r| r.atoms.iter().copied())
+        .collect();
+
+    mol.atoms
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| options.hydrogens || a.element != Element::Hydrogen)
+        .filter(|(i, a)| match options.sel {
+            ConvertSel::All => true,
+            ConvertSel::Protein => !a.hetero && !small_mol,
+            ConvertSel::Ligand => small_mol || het_atoms.contains(i),
+            ConvertSel::NoWater => a.role != Some(AtomRole::Water),
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// A copy of the molecule with only the given atoms, and the bonds, residues, and chains between
+/// them. Indices are remapped.
+fn subset(mol: &Molecule, atoms: &[usize]) -> Molecule {
+    let atom_map: HashMap<usize, usize> = atoms
+        .iter()
+        .enumerate()
+        .map(|(new, old)| (*old, new))
+        .collect();
+
+    let mut res_map = HashMap::new();
+    let mut residues = Vec::new();
+    for (i, res) in mol.residues.iter().enumerate() {
+        let res_atoms: Vec<_> = res
+            .atoms
+            .iter()
+            .filter_map(|a| atom_map.get(a).copied())
+            .collect();
+        if res_atoms.is_empty() {
+            continue;
+        }
+
+        res_map.insert(i, residues.len());
+        residues.push(Residue {
+            atoms: res_atoms,
+            ..res.clone()
+        });
+    }
+
+    let atoms_new: Vec<Atom> = atoms
+        .iter()
+        .map(|&i| {
+            let atom = &mol.atoms[i];
+            Atom {
+                residue: atom.residue.and_then(|r| res_map.get(&r).copied()),
+                ..atom.clone()
+            }
+        })
+        .collect();
+
+    let bonds = mol
+        .bonds
+        .iter()
+        .filter_map(|b| {
+            let atom_0 = *atom_map.get(&b.atom_0)?;
+            let atom_1 = *atom_map.get(&b.atom_1)?;
+            Some(Bond {
+                atom_0,
+                atom_1,
+                ..b.clone()
+            })
+        })
+        .collect();
+
+    let chains = mol
+        .chains
+        .iter()
+        .map(|c| Chain {
+            atoms: c
+                .atoms
+                .iter()
+                .filter_map(|a| atom_map.get(a).copied())
+                .collect(),
+            residues: c
+                .residues
+                .iter()
+                .filter_map(|r| res_map.get(r).copied())
+                .collect(),
+            ..c.clone()
+        })
+        .filter(|c| !c.atoms.is_empty())
+        .collect();
+
+    let mut result = Molecule {
+        ident: mol.ident.clone(),
+        atoms: atoms_new,
+        bonds,
+        chains,
+        residues,
+        pubchem_cid: mol.pubchem_cid,
+        drugbank_id: mol.drugbank_id.clone(),
+        ..Default::default()
+    };
+    result.adjacency_list = result.build_adjacency_list();
+
+    result
+}
+
+/// Convert a molecule file to another format, with formats inferred from the extensions.
+pub fn convert(input: &Path, output: &Path, options: &ConvertOptions) -> io::Result<()> {
+    let mol = load_molecule(input)?;
+
+    let atoms = select(&mol, options);
+    if atoms.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("No atoms in {} match the selection", input.display()),
+        ));
+    }
+
+    if atoms.len() == mol.atoms.len() {
+        save_molecule(&mol, output)
+    } else {
+        save_molecule(&subset(&mol, &atoms), output)
+    }
+}
+
+/// Convert each input to the format of `ext`, in `out_dir` if set; otherwise alongside the input.
+/// Returns the output path, or error, for each input; one failure doesn't stop the others.
+pub fn convert_batch(
+    inputs: &[PathBuf],
+    ext: &str,
+    out_dir: Option<&Path>,
+    options: &ConvertOptions,
+) -> Vec<io::Result<PathBuf>> {
+    inputs
+        .iter()
+        .map(|input| {
+            let mut output = match out_dir {
+                Some(dir) => dir.join(input.file_name().unwrap_or_default()),
+                None => input.clone(),
+            };
+            output.set_extension(ext);
+
+            if output == *input {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} is already in this format", input.display()),
+                ));
+            }
+
+            convert(input, &output, options).map(|_| output)
+        })
+        .collect()
+}
+
+/// Handle `convert` command line arguments; those following `convert`.
+pub fn run_cli(args: &[String]) -> io::Result<()> {
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidInput, msg.to_owned());
+
+    let mut options = ConvertOptions::default();
+    let mut paths = Vec::new();
+    let mut ext = None;
+    let mut out_dir = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--sel" => {
+                let val = args
+                    .next()
+                    .ok_or_else(|| invalid("Missing value for --sel"))?;
+                options.sel = val.parse()?;
+            }
+            "--no-h" => options.hydrogens = false,
+            "--to" => {
+                ext = Some(
+                    args.next()
+                        .ok_or_else(|| invalid("Missing value for --to"))?,
+                )
+            }
+            "--out-dir" => {
+                let val = args
+                    .next()
+                    .ok_or_else(|| invalid("Missing value for --out-dir"))?;
+                out_dir = Some(PathBuf::from(val));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    match ext {
+        Some(ext) => {
+            if paths.is_empty() {
+                return Err(invalid("No input files"));
+            }
+            if let Some(dir) = &out_dir {
+                fs::create_dir_all(dir)?;
+            }
+
+            let results = convert_batch(&paths, ext, out_dir.as_deref(), &options);
+            let mut failed = 0;
+            for (input, result) in paths.iter().zip(results) {
+                match result {
+                    Ok(output) => println!("{} -> {}", input.display(), output.display()),
+                    Err(e) => {
+                        eprintln!("{}: {e}", input.display());
+                        failed += 1;
+                    }
+                }
+            }
+
+            if failed > 0 {
+                return Err(io::Error::other(format!(
+                    "{failed} of {} conversions failed",
+                    paths.len()
+                )));
+            }
+        }
+        None => {
+            let [input, output] = paths.as_slice() else {
+                return Err(invalid(
+                    "Usage: convert <input> <output> [--sel all|protein|ligand|no-water] [--no-h], \
+                    or convert <inputs>... --to <ext> [--out-dir <dir>]",
+                ));
+            };
+            convert(input, output, &options)?;
+            println!("{} -> {}", input.display(), output.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Per-atom (residue name, residue serial number, chain ID), for PDB and CIF records.
+fn atom_res_info(mol: &Molecule) -> Vec<(String, isize, String)> {
+    let mut chain_ids = vec!["A".to_owned(); mol.atoms.len()];
+    for chain in &mol.chains {
+        for &i in &chain.atoms {
+            chain_ids[i] = chain.id.clone();
+        }
+    }
+
+    mol.atoms
+        .iter()
+        .zip(chain_ids)
+        .map(|(atom, chain_id)| {
+            let (name, sn) = match atom.residue.map(|i| &mol.residues[i]) {
+                Some(res) => {
+                    let name = match &res.res_type {
+                        ResidueType::AminoAcid(aa) => {
+                            aa.to_str(AaIdent::ThreeLetters).to_uppercase()
+                        }
+                        ResidueType::Water => "HOH".to_owned(),
+                        ResidueType::Other(n) => n.clone(),
+                    };
+                    (name, res.serial_number)
+                }
+                None => ("UNL".to_owned(), 1),
+            };
+            (name, sn, chain_id)
+        })
+        .collect()
+}
+
+fn atom_name(atom: &Atom) -> String {
+    match &atom.type_in_res {
+        Some(name) => name.to_string(),
+        None => atom.element.to_letter(),
+    }
+}
+
+/// Each atom, followed by its alternate locations that aren't active, if any.
+fn atoms_with_alts(mol: &Molecule) -> Vec<(usize, &Atom)> {
+    let mut alts: HashMap<usize, Vec<&Atom>> = HashMap::new();
+    for (i, atom) in &mol.alt_loc_atoms {
+        alts.entry(*i).or_default().push(atom);
+    }
+
+    let mut result = Vec::with_capacity(mol.atoms.len() + mol.alt_loc_atoms.len());
+    for (i, atom) in mol.atoms.iter().enumerate() {
+        result.push((i, atom));
+        if let Some(a) = alts.get(&i) {
+            result.extend(a.iter().map(|alt| (i, *alt)));
+        }
+    }
+    result
+}
+
+/// Write atom coordinates as PDB ATOM and HETATM records, with ANISOU records where present, and
+/// bonds of hetero atoms as CONECT records.
+pub fn save_pdb_atoms(mol: &Molecule, path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+
+    if !mol.ident.is_empty() {
+        writeln!(file, "HEADER    {}", mol.ident)?;
+    }
+
+    let res_info = atom_res_info(mol);
+
+    for (i, atom) in atoms_with_alts(mol) {
+        let (res_name, res_sn, chain_id) = &res_info[i];
+        let record = if atom.hetero { "HETATM" } else { "ATOM" };
+
+        let el = atom.element.to_letter().to_uppercase();
+        let name = atom_name(atom);
+        // Atom names start in column 14 when the element symbol is one letter, so it aligns.
+        let name = if name.len() < 4 && el.len() == 1 {
+            format!(" {name:<3}")
+        } else {
+            format!("{name:<4}")
+        };
+
+        // Columns 7-27 are shared by ATOM and ANISOU records.
+        let id = format!(
+            "{:>5} {name}{:1}{:>3} {:1}{:>4}",
+            atom.serial_number,
+            atom.alt_loc.unwrap_or(' '),
+            res_name,
+            chain_id.chars().next().unwrap_or('A'),
+            res_sn,
+        );
+
+        writeln!(
+            file,
+            "{record:<6}{id}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}          {el:>2}",
+            atom.posit.x,
+            atom.posit.y,
+            atom.posit.z,
+            atom.occupancy.unwrap_or(1.),
+            atom.temperature_factor.unwrap_or_default(),
+        )?;
+
+        if let Some(u) = atom.anisou {
+            // In units of 1e-4 Å².
+            let u = u.map(|v| (v * 1e4).round() as i32);
+            writeln!(
+                file,
+                "ANISOU{id}  {:>7}{:>7}{:>7}{:>7}{:>7}{:>7}      {el:>2}",
+                u[0], u[1], u[2], u[3], u[4], u[5],
+            )?;
+        }
+    }
+
+    for (i, adj) in mol.adjacency_list.iter().enumerate() {
+        if !mol.atoms[i].hetero || adj.is_empty() {
+            continue;
+        }
+        write!(file, "CONECT{:>5}", mol.atoms[i].serial_number)?;
+        for &j in adj {
+            write!(file, "{:>5}", mol.atoms[j].serial_number)?;
+        }
+        writeln!(file)?;
+    }
+
+    writeln!(file, "END")?;
+    Ok(())
+}
+
+/// Write atom coordinates as an mmCIF `atom_site` loop.
+pub fn save_cif_atoms(mol: &Molecule, path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+
+    let ident = if mol.ident.is_empty() {
+        "unnamed"
+    } else {
+        &mol.ident
+    };
+    writeln!(file, "data_{}", ident.replace(' ', "_"))?;
+    writeln!(file, "#\nloop_")?;
+    for field in [
+        "group_PDB",
+        "id",
+        "type_symbol",
+        "label_atom_id",
+        "label_alt_id",
+        "label_comp_id",
+        "label_asym_id",
+        "label_seq_id",
+        "Cartn_x",
+        "Cartn_y",
+        "Cartn_z",
+        "occupancy",
+        "B_iso_or_equiv",
+        "auth_seq_id",
+        "auth_asym_id",
+        "pdbx_PDB_model_num",
+    ] {
+        writeln!(file, "_atom_site.{field}")?;
+    }
+
+    let res_info = atom_res_info(mol);
+    let atoms = atoms_with_alts(mol);
+
+    for (i, atom) in &atoms {
+        let (res_name, res_sn, chain_id) = &res_info[*i];
+        let record = if atom.hetero { "HETATM" } else { "ATOM" };
+        let name = atom_name(atom);
+        // Names with primes, e.g. nucleotides' "C1'", must be quoted.
+        let name = if name.contains('\'') {
+            format!("\"{name}\"")
+        } else {
+            name
+        };
+        let alt_loc = atom.alt_loc.unwrap_or('.');
+
+        writeln!(
+            file,
+            "{record} {} {} {name} {alt_loc} {res_name} {chain_id} {res_sn} {:.3} {:.3} {:.3} {:.2} {:.2} {res_sn} {chain_id} 1",
+            atom.serial_number,
+            atom.element.to_letter(),
+            atom.posit.x,
+            atom.posit.y,
+            atom.posit.z,
+            atom.occupancy.unwrap_or(1.),
+            atom.temperature_factor.unwrap_or_default(),
+        )?;
+    }
+
+    if atoms.iter().any(|(_, a)| a.anisou.is_some()) {
+        writeln!(file, "#\nloop_")?;
+        for field in [
+            "id",
+            "type_symbol",
+            "U[1][1]",
+            "U[2][2]",
+            "U[3][3]",
+            "U[1][2]",
+            "U[1][3]",
+            "U[2][3]",
+        ] {
+            writeln!(file, "_atom_site_anisotrop.{field}")?;
+        }
+
+        for (_, atom) in &atoms {
+            let Some(u) = atom.anisou else {
+                continue;
+            };
+            writeln!(
+                file,
+                "{} {} {:.4} {:.4} {:.4} {:.4} {:.4} {:.4}",
+                atom.serial_number,
+                atom.element.to_letter(),
+                u[0],
+                u[1],
+                u[2],
+                u[3],
+                u[4],
+                u[5],
+            )?;
+        }
+    }
+
+    writeln!(file, "#")?;
+    Ok(())
+}
+
+pub fn save_xyz(mol: &Molecule, path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+
+    writeln!(file, "{}", mol.atoms.len())?;
+    writeln!(file, "{}", mol.ident)?;
+    for atom in &mol.atoms {
+        writeln!(
+            file,
+            "{:<2} {:>12.6} {:>12.6} {:>12.6}",
+            atom.element.to_letter(),
+            atom.posit.x,
+            atom.posit.y,
+            atom.posit.z
+        )?;
+    }
+
+    Ok(())
+}
+
+/// XYZ files have elements and coordinates only; bonds are inferred.
+pub fn load_xyz(path: &Path) -> io::Result<Molecule> {
+    let text = fs::read_to_string(path)?;
+    let mut lines = text.lines();
+
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_owned());
+
+    let count: usize = lines
+        .next()
+        .and_then(|l| l.trim().parse().ok())
+        .ok_or_else(|| invalid("Missing atom count on the first XYZ line"))?;
+    let ident = lines.next().unwrap_or_default().trim().to_owned();
+
+    let mut atoms = Vec::with_capacity(count);
+    for (i, line) in lines.take(count).enumerate() {
+        let cols: Vec<_> = line.split_whitespace().collect();
+        if cols.len() < 4 {
+            return Err(invalid(&format!("Invalid XYZ atom line: {line}")));
+        }
+
+        let coord = |s: &str| {
+            s.parse::<f64>()
+                .map_err(|_| invalid(&format!("Invalid XYZ coordinate: {s}")))
+        };
+
+        atoms.push(Atom {
+            serial_number: i + 1,
+            posit: Vec3::new(coord(cols[1])?, coord(cols[2])?, coord(cols[3])?),
+            element: Element::from_letter(cols[0])?,
+            type_in_res: None,
+            force_field_type: None,
+            dock_type: None,
+            role: None,
+            residue: None,
+            hetero: true,
+            occupancy: None,
+            partial_charge: None,
+            temperature_factor: None,
+            alt_loc: None,
+            anisou: None,
+            props: Default::default(),
+            in_ring: false,
+            aromatic: false,
+        });
+    }
+
+    if atoms.len() != count {
+        return Err(invalid(
+            "Fewer atoms in the XYZ file than its count line states",
+        ));
+    }
+
+    Ok(Molecule::new(
+        ident,
+        atoms,
+        Vec::new(),
+        Vec::new(),
+        None,
+        None,
+    ))
+}
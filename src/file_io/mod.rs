@@ -4,22 +4,34 @@ use std::{
     io,
     io::{ErrorKind, Read},
     path::Path,
+    sync::Arc,
     time::Instant,
 };
 
 use bio_files::{DensityMap, gemmi_cif_to_map};
+use graphics::{Camera, EngineUpdates};
 use lin_alg::f64::Vec3;
+use log::{debug, info, trace};
 use na_seq::{AaIdent, AminoAcid, Element};
+use pdbtbx::PDB;
 
 use crate::{
-    AMINO_19, FRCMOD_FF19SB, GAFF2, PARM_19, State,
-    file_io::{cif_pdb::load_cif_pdb, pdbqt::load_pdbqt},
+    AMINO_19, FRCMOD_FF19SB, GAFF2, PARM_19, Selection, State,
+    file_io::{cif_pdb::load_cif_pdb, convert::save_molecule, pdbqt::load_pdbqt},
     molecule::{Ligand, Molecule},
+    objects::{MolObject, OBJECT_PALETTE},
+    plugins::PluginFile,
+    progress::TaskStatus,
+    progressive_load::{
+        LoadStage, LoadedStructure, PROGRESSIVE_LOAD_MIN_SIZE, ca_trace, start_load,
+    },
+    save_load::SESSION_EXT,
 };
 
 pub mod cif_aux;
 pub mod cif_pdb;
 pub mod cif_sf;
+pub mod convert;
 pub mod mtz;
 pub mod pdbqt;
 
@@ -30,8 +42,13 @@ use bio_files::{
 };
 
 use crate::{
-    docking::prep::DockingSetup,
-    dynamics::prep::{merge_params, populate_ff_and_q},
+    docking::{cleanup::clean_up_geometry, prep::DockingSetup},
+    dynamics::{
+        cmap::parse_cmaps,
+        prep::{merge_params, parse_dihedral_series, populate_ff_and_q},
+        templates::load_lib,
+    },
+    events::ViewerEvent,
     reflection::{DENSITY_CELL_MARGIN, DENSITY_MAX_DIST, DensityRect, ElectronDensity},
     util::handle_err,
 };
@@ -47,10 +64,11 @@ impl State {
             .unwrap_or_default()
         {
             "sdf" | "mol2" | "pdbqt" | "pdb" | "cif" => self.open_molecule(path)?,
+            SESSION_EXT => self.open_session(path)?,
             "map" => self.open_map(path)?,
-            // todo: lib, .dat etc as required. Using Amber force fields and its format
-            // todo to start. We assume it'll be generalizable later.
-            "frcmod" | "dat" => self.open_force_field(path)?,
+            // Using Amber force fields and its format to start. We assume it'll be generalizable later.
+            "frcmod" | "dat" | "lib" | "off" => self.open_force_field(path)?,
+            ext if self.plugins.opens(ext) => self.open_molecule(path)?,
             _ => {
                 return Err(io::Error::new(
                     ErrorKind::InvalidData,
@@ -62,11 +80,56 @@ impl State {
         Ok(())
     }
 
+    /// As `open`, but PDB and mmCIF files are parsed in a thread, so the UI doesn't block. Poll
+    /// with `poll_pending_load`. Other file types open immediately.
+    pub fn open_async(&mut self, path: &Path) -> io::Result<()> {
+        let extension = path.extension().unwrap_or_default().to_ascii_lowercase();
+        // 2fo-fc CIFs are density maps; see `open_molecule`.
+        let is_map = path
+            .file_name()
+            .and_then(|os| os.to_str())
+            .is_some_and(|name| name.contains("2fo") && name.contains("fc"));
+
+        match extension.to_str().unwrap_or_default() {
+            "pdb" | "cif" if !is_map => self.start_molecule_load(path),
+            _ => self.open(path),
+        }
+    }
+
+    /// Load a molecule as an additional object, e.g. for comparing with the primary one, or a
+    /// ligand analog for overlaying onto the open ligand.
+    pub fn open_object(&mut self, path: &Path) -> io::Result<()> {
+        let extension = path.extension().unwrap_or_default().to_ascii_lowercase();
+
+        let mol = match extension.to_str().unwrap_or_default() {
+            "sdf" => Sdf::load(path)?.into(),
+            "mol2" => Mol2::load(path)?.into(),
+            "pdb" | "cif" => {
+                let pdb = load_cif_pdb(path)?;
+                let file = File::open(path)?;
+                Molecule::from_cif_pdb(&pdb, &file)?
+            }
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Unsupported file extension for an object",
+                ));
+            }
+        };
+
+        let color = OBJECT_PALETTE[self.objects.len() % OBJECT_PALETTE.len()];
+        self.objects.push(MolObject::new(mol, color));
+        self.volatile.object_active = Some(self.objects.len() - 1);
+        self.volatile.superpose_result = None;
+
+        Ok(())
+    }
+
     pub fn open_molecule(&mut self, path: &Path) -> io::Result<()> {
         let binding = path.extension().unwrap_or_default().to_ascii_lowercase();
         let extension = binding;
 
-        let is_ligand = matches!(extension.to_str().unwrap(), "sdf" | "mol2");
+        let mut is_ligand = matches!(extension.to_str().unwrap(), "sdf" | "mol2");
 
         let mut ligand = None;
         let molecule = match extension.to_str().unwrap() {
@@ -93,41 +156,31 @@ impl State {
                     }
                 }
 
+                // Very large structures are parsed in a thread; they're set up once that completes.
+                if fs::metadata(path)?.len() >= PROGRESSIVE_LOAD_MIN_SIZE {
+                    return self.start_molecule_load(path);
+                }
+
                 let pdb = load_cif_pdb(path)?;
                 let mut file = File::open(path)?;
 
                 let mut mol = Molecule::from_cif_pdb(&pdb, &file)?;
-                self.pdb = Some(pdb);
 
                 let mut data_str = String::new();
                 file.read_to_string(&mut data_str)?;
-                self.cif_pdb_raw = Some(data_str);
-
-                // If we've loaded general FF params, apply them to get FF type and charge.
-                if let Some(charge_ff_data) = &self.ff_params.prot_charge_general {
-                    if let Err(e) =
-                        populate_ff_and_q(&mut mol.atoms, &mol.residues, &charge_ff_data)
-                    {
-                        eprintln!(
-                            "Unable to populate FF charge and FF type for protein atoms: {:?}",
-                            e
-                        );
-                    } else {
-                        // Run this to update the ff name and charge data on the set of receptor
-                        // atoms near the docking site.
-                        if let Some(lig) = &mut self.ligand {
-                            self.volatile.docking_setup = Some(DockingSetup::new(
-                                &mol,
-                                lig,
-                                &self.volatile.lj_lookup_table,
-                                &self.bh_config,
-                            ));
-                        }
-                    }
-                }
+                self.set_source(pdb, data_str);
+
+                self.populate_ff_protein(&mut mol);
 
                 Ok(mol)
             }
+            ext if self.plugins.opens(ext) => match self.plugins.open(path)? {
+                PluginFile::Molecule(mol) => Ok(mol),
+                PluginFile::Ligand(mol) => {
+                    is_ligand = true;
+                    Ok(mol)
+                }
+            },
             _ => Err(io::Error::new(
                 ErrorKind::InvalidData,
                 "Invalid file extension",
@@ -135,13 +188,25 @@ impl State {
         };
 
         match molecule {
-            Ok(mol) => {
+            Ok(mut mol) => {
                 if is_ligand {
                     let het_residues = mol.het_residues.clone();
                     let mol_atoms = mol.atoms.clone();
 
                     let mut init_posit = Vec3::new_zero();
 
+                    let cleanup = clean_up_geometry(
+                        &mut mol,
+                        self.ff_params.lig_general.as_ref(),
+                        self.ff_params.lig_specific.get(&mol.ident),
+                    );
+                    if let Some(c) = &cleanup {
+                        info!(
+                            "Cleaned up ligand geometry. {}",
+                            c.descrip().replace('\n', ". ")
+                        );
+                    }
+
                     let lig = Ligand::new(mol);
 
                     // Align to a hetero residue in the open molecule, if there is a match.
@@ -152,52 +217,223 @@ impl State {
                         }
                     }
 
+                    self.events.emit(ViewerEvent::MoleculeLoaded {
+                        ident: lig.molecule.ident.clone(),
+                        ligand: true,
+                    });
                     self.ligand = Some(lig);
                     self.to_save.last_ligand_opened = Some(path.to_owned());
+                    self.volatile.mcs_alignment = None;
+                    self.volatile.plif = None;
+                    self.volatile.plif_snapshots = Vec::new();
+                    self.volatile.plif_clusters = Vec::new();
+                    self.volatile.lig_bsa = None;
+                    self.volatile.residue_energy = None;
+                    self.volatile.am1bcc_pending = None;
+                    self.volatile.lig_cleanup = cleanup;
 
                     self.update_docking_site(init_posit);
                 } else {
-                    self.to_save.last_opened = Some(path.to_owned());
+                    self.set_molecule(mol, Some(path));
+                }
 
-                    self.volatile.aa_seq_text = String::with_capacity(mol.atoms.len());
-                    for aa in &mol.aa_seq {
-                        self.volatile
-                            .aa_seq_text
-                            .push_str(&aa.to_str(AaIdent::OneLetter));
-                    }
+                self.finish_open_molecule();
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        }
 
-                    self.volatile.flags.ss_mesh_created = false;
-                    self.volatile.flags.sas_mesh_created = false;
+        Ok(())
+    }
 
-                    self.volatile.flags.clear_density_drawing = true;
-                    self.molecule = Some(mol);
+    /// Keep the parsed file, and its text, for saving it unmodified. Skipped in memory budget mode;
+    /// together, these can take more memory than the molecule itself.
+    pub fn set_source(&mut self, pdb: PDB, raw: String) {
+        if self.to_save.memory_budget {
+            self.pdb = None;
+            self.cif_pdb_raw = None;
+        } else {
+            self.pdb = Some(pdb);
+            self.cif_pdb_raw = Some(raw);
+        }
+    }
 
-                    // Only updating if not loading a ligand.
-                    // Update from prefs based on the molecule-specific items.
-                    self.update_from_prefs();
-                }
+    /// Assign protonation states at the pH from prefs. Then, if we've loaded general FF params, apply
+    /// them to a protein to get FF type and charge.
+    pub fn populate_ff_protein(&mut self, mol: &mut Molecule) {
+        mol.assign_protonation(self.to_save.ph, &self.to_save.h_bond_cfg);
+        self.populate_ff_types(mol);
+    }
 
-                if let Some(mol) = &mut self.molecule {
-                    // Only after updating from prefs (to prevent unecesasary loading) do we update data avail.
-                    mol.updates_rcsb_data(&mut self.volatile.mol_pending_data_avail);
-                }
+    /// If we've loaded general FF params, apply them to a protein to get FF type and charge, using
+    /// its current protonation states.
+    pub fn populate_ff_types(&mut self, mol: &mut Molecule) {
+        let Some(charge_ff_data) = &self.ff_params.prot_charge_general else {
+            return;
+        };
+
+        if let Err(e) = populate_ff_and_q(
+            &mut mol.atoms,
+            &mol.residues,
+            &charge_ff_data,
+            &self.ff_params.residue_templates,
+        ) {
+            handle_err(
+                &mut self.ui,
+                format!("Unable to populate FF charge and FF type for protein atoms: {e}"),
+            );
+        } else {
+            // Run this to update the ff name and charge data on the set of receptor
+            // atoms near the docking site.
+            if let Some(lig) = &mut self.ligand {
+                self.volatile.docking_setup = Some(Arc::new(DockingSetup::new(
+                    mol,
+                    lig,
+                    &self.volatile.lj_lookup_table,
+                    &self.bh_config,
+                )));
+            }
+        }
+    }
 
-                // Now, save prefs: This is to save last opened. Note that anomolies happen
-                // if we update the molecule here, e.g. with docking site posit.
-                self.update_save_prefs_no_mol();
+    /// Set a newly-opened molecule (not ligand) as the primary one.
+    /// `path` is `None` for molecules not from a file, e.g. built peptides.
+    pub fn set_molecule(&mut self, mut mol: Molecule, path: Option<&Path>) {
+        self.to_save.last_opened = path.map(|p| p.to_owned());
 
-                if self.get_make_docking_setup().is_none() {
-                    eprintln!("Problem making or getting docking setup.");
-                }
+        self.volatile.aa_seq_text = String::with_capacity(mol.atoms.len());
+        for aa in &mol.aa_seq {
+            self.volatile
+                .aa_seq_text
+                .push_str(&aa.to_str(AaIdent::OneLetter));
+        }
+
+        self.volatile.flags.ss_mesh_created = false;
+        self.volatile.flags.sas_mesh_created = false;
+
+        self.volatile.flags.clear_density_drawing = true;
+        // Indices into the previous molecule's assemblies.
+        self.ui.assembly = None;
+
+        if self.to_save.h_bond_cfg != Default::default() {
+            mol.update_h_bonds(&self.to_save.h_bond_cfg);
+        }
+        self.events.emit(ViewerEvent::MoleculeLoaded {
+            ident: mol.ident.clone(),
+            ligand: false,
+        });
+        self.molecule = Some(mol);
+
+        // Only updating if not loading a ligand.
+        // Update from prefs based on the molecule-specific items.
+        self.update_from_prefs();
+    }
+
+    /// Run after opening a molecule or ligand.
+    fn finish_open_molecule(&mut self) {
+        if let Some(mol) = &mut self.molecule {
+            // Only after updating from prefs (to prevent unecesasary loading) do we update data avail.
+            mol.updates_rcsb_data(&mut self.volatile.mol_pending_data_avail);
+        }
+
+        // Now, save prefs: This is to save last opened. Note that anomolies happen
+        // if we update the molecule here, e.g. with docking site posit.
+        self.update_save_prefs_no_mol();
+
+        if self.get_make_docking_setup().is_none() {
+            debug!("Problem making or getting docking setup.");
+        }
+
+        self.volatile.flags.new_mol_loaded = true;
+    }
+
+    /// Open a molecule built in the app, e.g. a peptide from its sequence, vice from a file.
+    pub fn open_built_molecule(&mut self, mut mol: Molecule) {
+        self.pdb = None;
+        self.cif_pdb_raw = None;
+
+        self.populate_ff_protein(&mut mol);
+        self.set_molecule(mol, None);
+        self.ui.selection = Selection::None;
+
+        // Not calling `finish_open_molecule`; there's no RCSB data to fetch.
+        self.update_save_prefs_no_mol();
+        if self.get_make_docking_setup().is_none() {
+            debug!("Problem making or getting docking setup.");
+        }
+
+        self.volatile.flags.new_mol_loaded = true;
+    }
+
+    /// Start loading a PDB or mmCIF file in a thread. If it's large, show a Cα trace of it in the
+    /// meantime. See `progressive_load`.
+    fn start_molecule_load(&mut self, path: &Path) -> io::Result<()> {
+        if fs::metadata(path)?.len() >= PROGRESSIVE_LOAD_MIN_SIZE {
+            let start = Instant::now();
+            self.volatile.load_preview = ca_trace(path)?;
+            info!(
+                "Cα trace of {} ready in {}ms; loading the full structure...",
+                path.display(),
+                start.elapsed().as_millis()
+            );
+
+            // The new molecule replaces this one once loaded; don't redraw it in the meantime.
+            self.molecule = None;
+            self.volatile.flags.new_preview_loaded = true;
+        }
+
+        self.volatile.load_stage = None;
+        self.volatile.pending_load = Some(start_load(path));
+
+        Ok(())
+    }
+
+    /// Poll a load in progress; non-blocking. Returns `true` if the molecule is ready,
+    /// and has been set up.
+    pub fn poll_pending_load(&mut self) -> bool {
+        let Some(pending) = &self.volatile.pending_load else {
+            return false;
+        };
 
-                self.volatile.flags.new_mol_loaded = true;
+        let loaded = match pending.task.poll() {
+            TaskStatus::Done(loaded) => loaded,
+            TaskStatus::Running => return false,
+            TaskStatus::Failed => Err(io::Error::other(
+                "Loading thread died before sending a result",
+            )),
+        };
+        let pending = self.volatile.pending_load.take().unwrap();
+        // Large molecules, shown with a preview while loading, are drawn in stages.
+        let staged = !self.volatile.load_preview.is_empty();
+        self.volatile.load_preview = Vec::new();
+
+        match loaded {
+            Ok(LoadedStructure { pdb, mut mol, raw }) => {
+                info!(
+                    "Loaded {} in {:.1}s",
+                    pending.path.display(),
+                    pending.start.elapsed().as_secs_f32()
+                );
+
+                self.set_source(pdb, raw);
+                self.populate_ff_protein(&mut mol);
+                self.set_molecule(mol, Some(&pending.path));
+                self.finish_open_molecule();
+
+                if staged {
+                    self.volatile.load_stage = Some(LoadStage::Backbone);
+                }
+                true
             }
             Err(e) => {
-                return Err(e);
+                handle_err(
+                    &mut self.ui,
+                    format!("Problem loading {}: {e}", pending.path.display()),
+                );
+                false
             }
         }
-
-        Ok(())
     }
 
     pub fn load_density(&mut self, dm: DensityMap) {
@@ -252,59 +488,94 @@ impl State {
 
         match extension.to_str().unwrap() {
             "dat" => {
-                self.ff_params.lig_general = Some(ForceFieldParamsKeyed::new(
+                let ff_params = Arc::make_mut(&mut self.ff_params);
+                ff_params.lig_general = Some(ForceFieldParamsKeyed::new(
                     &ForceFieldParams::load_dat(path)?,
                 ));
+                ff_params
+                    .dihedral_series
+                    .extend(parse_dihedral_series(&fs::read_to_string(path)?));
 
-                println!("\nLoaded forcefields:");
+                trace!("Loaded forcefields:");
                 let v = &self.ff_params.lig_general.as_ref().unwrap();
-                println!("Lin");
+                trace!("Lin");
                 for di in v.bond.values().take(20) {
-                    println!("Lin: {:?}, {}, {}", di.atom_types, di.k_b, di.r_0);
+                    trace!("Lin: {:?}, {}, {}", di.atom_types, di.k_b, di.r_0);
                 }
 
-                println!("Angle");
+                trace!("Angle");
                 for di in v.angle.values().take(20) {
-                    println!("Angle: {:?}, {}, {}", di.atom_types, di.k, di.theta_0);
+                    trace!("Angle: {:?}, {}, {}", di.atom_types, di.k, di.theta_0);
                 }
 
-                println!("Dihe:");
+                trace!("Dihe:");
                 for di in v.dihedral.values().take(20) {
-                    println!(
+                    trace!(
                         "DH: {:?}, {}, {}",
                         di.atom_types, di.barrier_height, di.phase
                     );
                 }
 
-                println!("Dihedral, improper:");
+                trace!("Dihedral, improper:");
                 for di in v.dihedral_improper.values().take(20) {
-                    println!(
+                    trace!(
                         "Imp: {:?}, {}, {}",
                         di.atom_types, di.barrier_height, di.phase
                     );
                 }
 
                 // todo: Get VDW loading working.
-                println!("Vdw");
+                trace!("Vdw");
                 for di in v.van_der_waals.values().take(20) {
-                    println!("Vdw: {:?}, {}, {}", di.atom_type, di.sigma, di.eps);
+                    trace!("Vdw: {:?}, {}, {}", di.atom_type, di.sigma, di.eps);
                 }
 
-                println!("Loaded general Ligand force fields.");
+                info!("Loaded general Ligand force fields.");
             }
             "frcmod" => {
                 let mol_name = "CPB".to_owned(); // todo temp.
 
-                self.ff_params.lig_specific.insert(
+                let ff_params = Arc::make_mut(&mut self.ff_params);
+                ff_params.lig_specific.insert(
                     mol_name,
                     ForceFieldParamsKeyed::new(&ForceFieldParams::load_frcmod(path)?),
                 );
-                println!("Loaded molecule-specific force fields.");
+                ff_params
+                    .dihedral_series
+                    .extend(parse_dihedral_series(&fs::read_to_string(path)?));
+                info!("Loaded molecule-specific force fields.");
+            }
+            "lib" | "off" => {
+                let templates = load_lib(path)?;
+                info!(
+                    "Loaded residue templates: {}",
+                    templates.keys().cloned().collect::<Vec<_>>().join(", ")
+                );
+                Arc::make_mut(&mut self.ff_params)
+                    .residue_templates
+                    .extend(templates);
+
+                // Apply these to the open molecule, e.g. for caps or modified residues it contains.
+                if let (Some(mol), Some(charge_ff_data)) =
+                    (&mut self.molecule, &self.ff_params.prot_charge_general)
+                {
+                    if let Err(e) = populate_ff_and_q(
+                        &mut mol.atoms,
+                        &mol.residues,
+                        charge_ff_data,
+                        &self.ff_params.residue_templates,
+                    ) {
+                        handle_err(
+                            &mut self.ui,
+                            format!("Unable to populate FF charge and FF type: {e}"),
+                        );
+                    }
+                }
             }
             _ => {
                 return Err(io::Error::new(
                     ErrorKind::InvalidFilename,
-                    "Attempting to parse non-dat or frcmod mod file as a force field.",
+                    "Attempting to parse non-dat, frcmod, or lib file as a force field.",
                 ));
             }
         };
@@ -312,12 +583,13 @@ impl State {
         Ok(())
     }
 
-    /// A single endpoint to save a number of file types
-    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+    /// A single endpoint to save a number of file types. `cam` is saved with sessions.
+    pub fn save(&mut self, path: &Path, cam: &Camera) -> io::Result<()> {
         let binding = path.extension().unwrap_or_default().to_ascii_lowercase();
         let extension = binding;
 
         match extension.to_str().unwrap_or_default() {
+            SESSION_EXT => self.save_session(path, cam)?,
             "pdb" | "cif" => {
                 // todo: Eval how you want to handle this. For now, the raw CIF or PDB.
                 // if let Some(pdb) = &mut self.pdb {
@@ -329,6 +601,11 @@ impl State {
                     fs::write(path, data)?;
                     self.to_save.last_opened = Some(path.to_owned());
                     self.update_save_prefs()
+                } else if let Some(mol) = &self.molecule {
+                    // E.g. in memory budget mode, where we don't keep the file's text.
+                    save_molecule(mol, path)?;
+                    self.to_save.last_opened = Some(path.to_owned());
+                    self.update_save_prefs()
                 }
             }
             "sdf" => match &self.ligand {
@@ -378,27 +655,32 @@ impl State {
         match parse_amino_charges(AMINO_19) {
             Ok(charge_ff_data) => {
                 if let Some(mol) = &mut self.molecule {
-                    if let Err(e) =
-                        populate_ff_and_q(&mut mol.atoms, &mol.residues, &charge_ff_data)
-                    {
-                        eprintln!(
-                            "Unable to populate FF charge and FF type for protein atoms: {:?}",
-                            e
+                    if let Err(e) = populate_ff_and_q(
+                        &mut mol.atoms,
+                        &mol.residues,
+                        &charge_ff_data,
+                        &self.ff_params.residue_templates,
+                    ) {
+                        handle_err(
+                            &mut self.ui,
+                            format!(
+                                "Unable to populate FF charge and FF type for protein atoms: {e}"
+                            ),
                         );
                     } else {
                         // Update ff and charges in the receptor atoms.
                         if let Some(lig) = &mut self.ligand {
-                            self.volatile.docking_setup = Some(DockingSetup::new(
+                            self.volatile.docking_setup = Some(Arc::new(DockingSetup::new(
                                 &mol,
                                 lig,
                                 &self.volatile.lj_lookup_table,
                                 &self.bh_config,
-                            ));
+                            )));
                         }
                     }
                 }
 
-                self.ff_params.prot_charge_general = Some(charge_ff_data);
+                Arc::make_mut(&mut self.ff_params).prot_charge_general = Some(charge_ff_data);
             }
             Err(e) => handle_err(
                 &mut self.ui,
@@ -414,7 +696,11 @@ impl State {
             // Load general parameters for proteins and AAs.
             match ForceFieldParams::from_dat(PARM_19) {
                 Ok(ff) => {
-                    self.ff_params.prot_general = Some(ForceFieldParamsKeyed::new(&ff));
+                    let ff_params = Arc::make_mut(&mut self.ff_params);
+                    ff_params.prot_general = Some(ForceFieldParamsKeyed::new(&ff));
+                    ff_params
+                        .dihedral_series
+                        .extend(parse_dihedral_series(PARM_19));
                 }
                 Err(e) => handle_err(
                     &mut self.ui,
@@ -428,16 +714,28 @@ impl State {
                     let ff_keyed = ForceFieldParamsKeyed::new(&ff);
 
                     // We just loaded this above.
-                    if let Some(ffs) = &mut self.ff_params.prot_general {
+                    let ff_params = Arc::make_mut(&mut self.ff_params);
+                    if let Some(ffs) = &ff_params.prot_general {
                         let params_updated = merge_params(ffs, Some(&ff_keyed));
-                        self.ff_params.prot_general = Some(params_updated);
+                        ff_params.prot_general = Some(params_updated);
                     }
+                    ff_params
+                        .dihedral_series
+                        .extend(parse_dihedral_series(FRCMOD_FF19SB));
                 }
                 Err(e) => handle_err(
                     &mut self.ui,
                     format!("Unable to load protein FF params (static): {e}"),
                 ),
             }
+
+            match parse_cmaps(FRCMOD_FF19SB) {
+                Ok(cmaps) => Arc::make_mut(&mut self.ff_params).prot_cmap = cmaps,
+                Err(e) => handle_err(
+                    &mut self.ui,
+                    format!("Unable to load protein CMAP params: {e}"),
+                ),
+            }
         }
 
         // Note: We may load this at program init
@@ -450,7 +748,11 @@ impl State {
         if self.ff_params.lig_general.is_none() {
             match ForceFieldParams::from_dat(GAFF2) {
                 Ok(ff) => {
-                    self.ff_params.lig_general = Some(ForceFieldParamsKeyed::new(&ff));
+                    let ff_params = Arc::make_mut(&mut self.ff_params);
+                    ff_params.lig_general = Some(ForceFieldParamsKeyed::new(&ff));
+                    ff_params
+                        .dihedral_series
+                        .extend(parse_dihedral_series(GAFF2));
                 }
                 Err(e) => handle_err(
                     &mut self.ui,
@@ -460,3 +762,30 @@ impl State {
         }
     }
 }
+
+/// If `in_background`, PDB and mmCIF files are parsed in a thread; see `State::open_async`.
+pub fn load_file(
+    path: &Path,
+    state: &mut State,
+    in_background: bool,
+    redraw: &mut bool,
+    reset_cam: &mut bool,
+    engine_updates: &mut EngineUpdates,
+) -> io::Result<()> {
+    if in_background {
+        state.open_async(path)?;
+    } else {
+        state.open(path)?;
+    }
+
+    // Clear last map opened here, vice in `open_molecule`, to prevent it clearing the map
+    // on init.
+
+    state.to_save.last_map_opened = None;
+
+    *redraw = true;
+    *reset_cam = true;
+    engine_updates.entities = true;
+
+    Ok(())
+}
@@ -12,6 +12,8 @@ use std::{
     path::Path,
 };
 
+use log::trace;
+
 use crate::reflection::{MapStatus, Reflection, ReflectionsData};
 
 const HEADER_BLOCK: usize = 80;
@@ -107,7 +109,7 @@ pub fn read_main_headers(buf: &[u8], save_headers: Option<&mut Vec<String>>) ->
         // let mut args = skip_word_and_space(&line);
 
         // todo temp
-        println!("HEADER Type: {:?}", &line[..4]);
+        trace!("HEADER Type: {:?}", &line[..4]);
 
         i += HEADER_SIZE;
         //     // Dispatch on the 4-character record code
@@ -256,7 +258,7 @@ impl ReflectionsData {
         }
 
         let header_addr = parse_le!(buf, u32, 4..8) as usize;
-        println!("Header addr: {:?}", header_addr);
+        trace!("Header addr: {:?}", header_addr);
 
         // This encodes the number formats of the architecture the file was written on. (In fact,
         // the machine stamp is positioned 2 words from the start, where a word is sizeof(float), i.e.
@@ -279,7 +281,7 @@ impl ReflectionsData {
         // read_main_headers(&buf[80..], None);
         read_main_headers(header_data, None)?;
 
-        println!("HEader data: {:x?}", &header_data[..100]);
+        trace!("HEader data: {:x?}", &header_data[..100]);
 
         let points = Vec::new();
         Ok(Self {
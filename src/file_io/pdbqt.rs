@@ -18,6 +18,7 @@ use regex::Regex;
 use crate::{
     docking::{
         ConformationType,
+        partial_charge::gasteiger_charges,
         prep::{DockType, UnitCellDims},
     },
     molecule::{Atom, AtomRole, Ligand, Molecule, Residue},
@@ -99,6 +100,7 @@ impl Molecule {
                 let atom_id = atoms.len(); // index for assigning residues and chains.
 
                 let name = line[12..16].trim();
+                let alt_loc = line[16..17].chars().next().filter(|c| *c != ' ');
 
                 let element = Element::from_letter(&name[..1]).unwrap_or(Element::Carbon);
 
@@ -145,6 +147,8 @@ impl Molecule {
                         res_type: residue_type.clone(),
                         atoms: vec![atom_id],
                         dihedral: None,
+                        props: Default::default(),
+                        variant: None,
                     });
                 }
 
@@ -177,9 +181,14 @@ impl Molecule {
                     hetero,
                     occupancy,
                     temperature_factor,
+                    alt_loc,
+                    anisou: None,
                     partial_charge,
                     force_field_type: None,
                     dock_type,
+                    props: Default::default(),
+                    in_ring: false,
+                    aromatic: false,
                 });
             } else if record_type == "CRYST1" {
                 let unit_cell_dims = UnitCellDims {
@@ -255,6 +264,13 @@ impl Molecule {
             "REMARK                         _______ _______ _______ _____ _____    ______ ____"
         )?;
 
+        // AutoDock expects Gasteiger charges; compute them for atoms loaded without charges.
+        let charges_gasteiger = if self.atoms.iter().any(|a| a.partial_charge.is_none()) {
+            Some(gasteiger_charges(&self.atoms, &self.bonds))
+        } else {
+            None
+        };
+
         // Optionally write remarks, ROOT/ENDROOT, etc. here if needed.
         // For each atom:
         for (i, atom) in self.atoms.iter().enumerate() {
@@ -315,6 +331,11 @@ impl Molecule {
                 None => atom.element.to_letter(),
             };
 
+            let charge = atom
+                .partial_charge
+                .or_else(|| charges_gasteiger.as_ref().map(|q| q[i]))
+                .unwrap_or_default();
+
             writeln!(
                 file,
                 "{:<6}{:>5}  {:<3} {:<3} {:>1}{:>4}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}    {:>+6.3} {:<2}",
@@ -329,7 +350,7 @@ impl Molecule {
                 atom.posit.z,                                // columns 47-54
                 atom.occupancy.unwrap_or_default(),          // columns 55-60
                 atom.temperature_factor.unwrap_or_default(), // columns 61-66
-                atom.partial_charge.unwrap_or_default(),     // columns 71-76
+                charge,                                      // columns 71-76
                 dock_type                                    // columns 77-78
             )?;
         }
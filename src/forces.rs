@@ -7,15 +7,15 @@ cfg_if::cfg_if! {
         use std::sync::Arc;
         use cudarc::driver::{CudaStream, CudaModule, LaunchConfig, PushKernelArg};
         use lin_alg::f32::{vec3s_to_dev, vec3s_from_dev};
+        use crate::logging::Span;
     }
 }
-use std::time::Instant;
 
 use lin_alg::{f32::Vec3 as Vec3F32, f64::Vec3};
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use lin_alg::{
     f32::{Vec3x8, f32x8},
-    f64::f64x4,
+    f64::{Vec3x4, f64x4},
 };
 use na_seq::{Element, element::LjTable};
 use rayon::prelude::*;
@@ -34,7 +34,7 @@ pub fn force_coulomb_gpu_outer(
     posits_tgt: &[Vec3F32],
     charges: &[f64], // Corresponds 1:1 with `posit_charges`.
 ) -> Vec<f64> {
-    let start = Instant::now();
+    let _span = Span::new("GPU Coulomb");
 
     // allocate buffers
     let n_sources = posits_src.len();
@@ -83,9 +83,6 @@ pub fn force_coulomb_gpu_outer(
     let result = stream.memcpy_dtov(&V_per_sample).unwrap();
     // stream.memcpy_dtoh(&V_per_sample, &mut result_buf).unwrap();
 
-    let time_diff = Instant::now() - start;
-    println!("GPU coulomb data collected. Time: {:?}", time_diff);
-
     // This step is not required when using f64.
     result.iter().map(|v| *v as f64).collect()
     // result
@@ -101,7 +98,7 @@ pub fn force_lj_gpu(
     epss: &[f32],
 ) -> Vec<Vec3F32> {
     // Out is per target.
-    let start = Instant::now();
+    let _span = Span::new("GPU LJ force");
 
     // allocate buffers
     let n_sources = posits_src.len();
@@ -138,9 +135,6 @@ pub fn force_lj_gpu(
     // todo: Consider dtoh; passing to an existing vec instead of re-allocating
     let result = vec3s_from_dev(stream, &result_buf);
 
-    // let time_diff = Instant::now() - start;
-    // println!("GPU LJ force data collected. Time: {:?}", time_diff);
-
     // This step is not required when using f64.
     result
 }
@@ -160,7 +154,7 @@ pub fn setup_sigma_eps_x8(
     let lanes_src = if i_src == chunks_src - 1 {
         valid_lanes_src_last
     } else {
-        8
+        4
     };
 
     let valid_lanes = lanes_src.min(lanes_tgt);
@@ -184,7 +178,8 @@ pub fn setup_sigma_eps_x8(
 }
 
 /// The most fundamental part of Newtonian acceleration calculation.
-/// `acc_dir` is a unit vector.
+/// `dir` is a unit vector from the source charge to the target, which the force acts on. The result
+/// is in e²/Å²; multiply by `units::COULOMB_CONST` for kcal/(mol·Å).
 pub fn force_coulomb_f32(
     dir: Vec3F32,
     dist: f32,
@@ -210,6 +205,17 @@ pub fn force_coulomb_x8(
     dir * q0 * q1 / (dist.powi(2) + softening_factor_sq)
 }
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn force_coulomb_x4(
+    dir: Vec3x4,
+    dist: f64x4,
+    q0: f64x4,
+    q1: f64x4,
+    softening_factor_sq: f64x4,
+) -> Vec3x4 {
+    dir * q0 * q1 / (dist.powi(2) + softening_factor_sq)
+}
+
 /// Calculate the Lennard-Jones potential between two atoms.
 /// σ is in Å. ε is in kcal/mol.
 ///
@@ -247,17 +253,18 @@ pub fn force_lj_f32(dir: Vec3F32, dist: f32, sigma: f32, eps: f32) -> Vec3F32 {
     let s_r_6 = s_r.powi(6);
     let s_r_12 = s_r_6.powi(2);
 
-    let mag = 24. * eps * (2. * s_r_12 - s_r_6) / dist.powi(2);
+    let mag = 24. * eps * (2. * s_r_12 - s_r_6) / dist;
     -dir * mag
 }
 
-/// See notes on `V_lj()`.
+/// See notes on `V_lj()`. `dir` is a unit vector from the atom the force acts on, to the other.
+/// The result is in kcal/(mol·Å).
 pub fn force_lj(dir: Vec3, dist: f64, sigma: f64, eps: f64) -> Vec3 {
     let s_r = sigma / dist;
     let s_r_6 = s_r.powi(6);
     let s_r_12 = s_r_6.powi(2);
 
-    let mag = 24. * eps * (2. * s_r_12 - s_r_6) / dist.powi(2);
+    let mag = 24. * eps * (2. * s_r_12 - s_r_6) / dist;
     -dir * mag
 }
 
@@ -268,7 +275,19 @@ pub fn force_lj_x8(dir: Vec3x8, dist: f32x8, sigma: f32x8, eps: f32x8) -> Vec3x8
     let s_r_6 = s_r.powi(6);
     let s_r_12 = s_r_6.powi(2);
 
-    let mag = f32x8::splat(24.) * eps * (f32x8::splat(2.) * s_r_12 - s_r_6) / dist.powi(2);
+    let mag = f32x8::splat(24.) * eps * (f32x8::splat(2.) * s_r_12 - s_r_6) / dist;
+
+    -dir * mag
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// See notes on `force_lj()`.
+pub fn force_lj_x4(dir: Vec3x4, dist: f64x4, sigma: f64x4, eps: f64x4) -> Vec3x4 {
+    let s_r = sigma / dist;
+    let s_r_6 = s_r.powi(6);
+    let s_r_12 = s_r_6.powi(2);
+
+    let mag = f64x4::splat(24.) * eps * (f64x4::splat(2.) * s_r_12 - s_r_6) / dist;
 
     -dir * mag
 }
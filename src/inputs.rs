@@ -15,13 +15,10 @@ use crate::{
     Selection, State, mol_drawing,
     mol_drawing::MoleculeView,
     molecule::Atom,
-    render::{BODY_SHINYNESS, MESH_BOND, set_flashlight},
+    scene::{BODY_SHINYNESS, MESH_BOND, set_flashlight},
     util::{cycle_res_selected, find_selected_atom, orbit_center, points_along_ray},
 };
 
-// These are defaults; overridden by the user A/R, and saved to prefs.
-pub const MOVEMENT_SENS: f32 = 12.;
-pub const ROTATE_SENS: f32 = 0.45;
 pub const RUN_FACTOR: f32 = 6.; // i.e. shift key multiplier
 
 pub const SCROLL_MOVE_AMT: f32 = 4.;
@@ -35,6 +32,31 @@ const SELECTION_DIST_THRESH_LARGE: f32 = 0.1; // e.g. VDW views.
 
 const SEL_NEAR_PAD: f32 = 4.;
 
+/// The ray from the camera through the cursor, in world coordinates, with its near end clipped.
+pub fn cursor_ray(state: &State, scene: &Scene) -> Option<(Vec3, Vec3)> {
+    let mut cursor = state.ui.cursor_pos?;
+
+    // Due to a quirk of some combination of our graphics engine and the egui
+    // integration lib in it, we need this vertical offset for the UI; otherwise,
+    // the higher up we click, the more the projected ray will be below the one
+    // indicated by the cursor. (Rays will only be accurate if clicked at the bottom of the screen).
+    // todo: It may be worth addressing upstream.
+    cursor.1 -= map_linear(
+        cursor.1,
+        (scene.window_size.1, state.volatile.ui_height),
+        (0., state.volatile.ui_height),
+    );
+
+    let mut ray = scene.screen_to_render(cursor);
+
+    // Clip the near end of this to prevent false selections that seem to the user
+    // to be behind the camera.
+    let diff = ray.1 - ray.0;
+    ray.0 += diff.to_normalized() * SEL_NEAR_PAD;
+
+    Some(ray)
+}
+
 pub fn event_dev_handler(
     state_: &mut State,
     event: DeviceEvent,
@@ -80,26 +102,9 @@ pub fn event_dev_handler(
                 // Right click
                 match state {
                     ElementState::Pressed => {
-                        if let Some(mut cursor) = state_.ui.cursor_pos {
-                            // Due to a quirk of some combination of our graphics engine and the egui
-                            // integration lib in it, we need this vertical offset for the UI; otherwise,
-                            // the higher up we click, the more the projected ray will be below the one
-                            // indicated by the cursor. (Rays will only be accurate if clicked at the bottom of the screen).
-                            // todo: It may be worth addressing upstream.
-                            cursor.1 -= map_linear(
-                                cursor.1,
-                                (scene.window_size.1, state_.volatile.ui_height),
-                                (0., state_.volatile.ui_height),
-                            );
-
-                            let mut selected_ray = scene.screen_to_render(cursor);
-
-                            // Clip the near end of this to prevent false selections that seem to the user
-                            // to be behind the camera.
-                            let diff = selected_ray.1 - selected_ray.0;
-
-                            selected_ray.0 += diff.to_normalized() * SEL_NEAR_PAD;
+                        state_.ui.right_click_down = true;
 
+                        if let Some(selected_ray) = cursor_ray(state_, scene) {
                             if let Some(mol) = &state_.molecule {
                                 // If we don't scale the selection distance appropriately, an atom etc
                                 // behind the desired one, but closer to the ray, may be selected; likely
@@ -144,7 +149,11 @@ pub fn event_dev_handler(
                                     &mol.chains,
                                 );
 
-                                if selection == state_.ui.selection {
+                                // While pulling atoms in interactive MD, clicking the selection
+                                // again grabs it, vice deselecting it.
+                                let pulling = state_.ui.md_live && state_.ui.md_pull;
+
+                                if selection == state_.ui.selection && !pulling {
                                     // Toggle.
                                     state_.ui.selection = Selection::None;
                                 } else {
@@ -162,7 +171,7 @@ pub fn event_dev_handler(
                             }
                         }
                     }
-                    ElementState::Released => (),
+                    ElementState::Released => state_.ui.right_click_down = false,
                 }
             }
             if button == 2 {
@@ -177,11 +186,17 @@ pub fn event_dev_handler(
             match key.state {
                 ElementState::Pressed => match key.physical_key {
                     Code(KeyCode::ArrowLeft) => {
-                        cycle_res_selected(state_, scene, true);
+                        if cycle_res_selected(state_, scene, true) {
+                            updates.camera = true;
+                            updates.lighting = true;
+                        }
                         redraw_protein = true;
                     }
                     Code(KeyCode::ArrowRight) => {
-                        cycle_res_selected(state_, scene, false);
+                        if cycle_res_selected(state_, scene, false) {
+                            updates.camera = true;
+                            updates.lighting = true;
+                        }
                         redraw_protein = true;
                     }
                     Code(KeyCode::Escape) => {
@@ -0,0 +1,1109 @@
+//! Daedalus: molecule viewing, docking, and molecular dynamics. The binary is a thin wrapper
+//! around `run`; everything else is usable as a library, without opening a window.
+//!
+//! The core modules, useful without the GUI:
+//! - `file_io`: Reading and writing PDB, mmCIF, SDF, Mol2, PDBQT, density maps, and force fields
+//! - `molecule`: Molecules, ligands, atoms, bonds, and residues
+//! - `dynamics`: Molecular dynamics, with Amber force fields
+//! - `forces`: Force and energy calculations
+//! - `docking`: Ligand docking and scoring
+//! - `aa_coords`: Building and editing peptide geometry
+//! - `analysis`: Pockets, contacts, interfaces, trajectories, and validation
+//!
+//! Most operations are methods on `State`, which holds the open molecule, ligand, and settings.
+//! Create one with `new_state`, e.g.:
+//!
+//! ```ignore
+//! use daedalus_core::{ComputationDevice, new_state};
+//!
+//! let mut state = new_state(ComputationDevice::Cpu);
+//! state.load_aa_charges_ff();
+//! state.open(Path::new("1c8k.cif"))?;
+//! ```
+//!
+//! `render`, `ui`, `ui_aux`, `ui_plots`, and `inputs` make up the GUI, and are only built with the
+//! `gui` feature, which is on by default. `scene` holds the drawing setup they share with the
+//! command line and Python bindings. `State` carries UI settings, such as what's visible, since the
+//! command line and prefs use them too.
+
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::needless_range_loop)]
+
+// Note: To test if it compiles on ARM:
+// `rustup target add aarch64-pc-windows-msvc`
+// `cargo check --target aarch64-pc-windows-msvc`
+// note: Currently getting Clang errors when I attempt htis.
+
+// todo: Features to add:
+// - qvina2/qvina-w/gpuvina implementations too along with stuff like haddock for affinity-based
+// protein-protein.
+// - CLI interface or scripting, like PyMol
+// - mol2 support
+// - Better color scheme for residues?
+
+pub mod aa_coords;
+pub mod add_hydrogens;
+pub mod amino_acid_coords;
+pub mod analysis;
+pub mod assembly;
+pub mod blink;
+pub mod bond_inference;
+pub mod docking;
+pub mod download_mols;
+pub mod drug_like;
+pub mod error;
+pub mod events;
+pub mod file_io;
+pub mod forces;
+#[cfg(feature = "gui")]
+pub mod inputs;
+pub mod logging;
+pub mod mcs;
+pub mod mol_drawing;
+pub mod molecule;
+pub mod navigation;
+pub mod objects;
+pub mod plugins;
+pub mod prefs;
+pub mod progress;
+pub mod progressive_load;
+pub mod protonation;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "gui")]
+pub mod render;
+pub mod ribbon_mesh;
+pub mod sa_surface;
+pub mod save_load;
+pub mod scene;
+pub mod superpose;
+#[cfg(feature = "gui")]
+pub mod ui;
+pub mod units;
+pub mod util;
+
+pub mod cli;
+pub mod compact_atoms;
+pub mod dynamics;
+pub mod integrate;
+pub mod reflection;
+#[cfg(test)]
+mod tests;
+#[cfg(feature = "gui")]
+pub mod ui_aux;
+#[cfg(feature = "gui")]
+pub mod ui_plots;
+
+use std::{
+    collections::HashMap,
+    env, fmt, io,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, mpsc::Receiver},
+    time::Instant,
+};
+
+use barnes_hut::BhConfig;
+use bincode::{Decode, Encode};
+use bio_apis::{
+    ReqError, rcsb,
+    rcsb::{FilesAvailable, PdbDataResults},
+};
+use bio_files::amber_params::{ChargeParams, ForceFieldParams, ForceFieldParamsKeyed};
+// #[cfg(feature = "cuda")]
+// use cuda_setup::ComputationDevice;
+#[cfg(feature = "cuda")]
+use cudarc::{
+    driver::{CudaContext, CudaModule, CudaStream},
+    nvrtc::Ptx,
+};
+#[cfg(feature = "gui")]
+use egui::TextureHandle;
+#[cfg(feature = "gui")]
+use egui_file_dialog::{FileDialog, FileDialogConfig};
+use file_io::cif_pdb::load_cif_pdb;
+use graphics::{Camera, InputsCommanded};
+use lin_alg::{
+    f32::{Quaternion, Vec3},
+    f64::Vec3 as Vec3F64,
+};
+use log::{info, warn};
+use mol_drawing::{MoleculeView, SurfaceColoring, ViewTransition};
+use molecule::Molecule;
+use na_seq::{
+    AminoAcid, AminoAcidGeneral, Element,
+    element::{LjTable, init_lj_lut},
+};
+use pdbtbx::{self, PDB};
+
+use crate::{
+    aa_coords::build_peptide::BackbonePreset,
+    analysis::{
+        clashes::Clash,
+        contact_map::{ContactMap, ContactMode},
+        gnm::GnmResult,
+        interface::{Interface, LigandBsa},
+        mmgbsa::Mmgbsa,
+        plif::Plif,
+        pockets::Pocket,
+        ramachandran::RamaPoint,
+        residue_energy::EnergyDecomp,
+        trajectory::TrajAnalysis,
+        validation::ValidationReport,
+    },
+    blink::Blink,
+    docking::{
+        BindingEnergy, ConformationType, THETA_BH, am1bcc::Am1BccPending, cleanup::CleanupReport,
+        conformers::Conformer, dynamics::Snapshot, external::check_adv_avail,
+        pharmacophore::PharmConstraints, prep::DockingSetup, search::RankedPose, waters::SiteWater,
+    },
+    dynamics::{
+        MdConfig, MdRun, MdState,
+        cmap::CmapGrid,
+        postprocess::PbcMode,
+        prep::DihedralSeries,
+        templates::ResidueTemplate,
+        torsion_scan::{TorsionScan, TorsionScanParams},
+    },
+    error::DaedalusError,
+    events::EventBus,
+    file_io::{cif_pdb::save_pdb, convert, mtz::load_mtz, pdbqt::load_pdbqt},
+    logging::LogLevel,
+    mcs::McsAlignment,
+    molecule::Ligand,
+    navigation::Tab,
+    objects::MolObject,
+    plugins::PluginRegistry,
+    prefs::ToSave,
+    progress::Task,
+    progressive_load::{LoadStage, PendingLoad},
+    sa_surface::SasMeshPending,
+    save_load::SESSION_EXT,
+    scene::{Color, VIEW_DEPTH_FAR_MAX, VIEW_DEPTH_NEAR_MIN},
+    superpose::PairMode,
+    util::handle_err,
+};
+
+// Include general Amber forcefield params with our program. See the Reference Manual, section ]
+// 3.1.1 for details on which we include. (The recommended ones for Proteins, and ligands).
+
+// Proteins and amino acids:
+const PARM_19: &str = include_str!("../resources/parm19.dat"); // Bonded, and Van der Waals.
+const FRCMOD_FF19SB: &str = include_str!("../resources/frcmod.ff19SB"); // Bonded, and Van der Waals: overrides and new types
+const AMINO_19: &str = include_str!("../resources/amino19.lib"); // Charge; internal residues
+const AMINONT12: &str = include_str!("../resources/aminont12.lib"); // Charge; protonated N-terminus residues
+const AMINOCT12: &str = include_str!("../resources/aminoct12.lib"); // Charge; protonated C-terminus residues
+
+// Ligands/small organic molecules: *General Amber Force Fields*.
+const GAFF2: &str = include_str!("../resources/gaff2.dat");
+
+// Note: Water parameters are concise; we store them directly.
+
+// todo: Eventually, implement a system that automatically checks for changes, and don't
+// todo save to disk if there are no changes.
+const PREFS_SAVE_INTERVAL: u64 = 60; // Save user preferences this often, in seconds.
+
+#[derive(Debug, Clone, Default)]
+pub enum ComputationDevice {
+    #[default]
+    Cpu,
+    #[cfg(feature = "cuda")]
+    Gpu((Arc<CudaStream>, Arc<CudaModule>)),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum ViewSelLevel {
+    #[default]
+    Atom,
+    Residue,
+}
+
+impl fmt::Display for ViewSelLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Atom => write!(f, "Atom"),
+            Self::Residue => write!(f, "Residue"),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+pub struct FileDialogs {
+    load: FileDialog,
+    /// Additional structures, e.g. for comparison with the primary one.
+    load_object: FileDialog,
+    save: FileDialog,
+    autodock_path: FileDialog,
+    /// Command scripts, e.g. recorded macros.
+    load_script: FileDialog,
+    save_macro: FileDialog,
+    /// E.g. MD energy reports.
+    save_csv: FileDialog,
+}
+
+#[cfg(feature = "gui")]
+impl Default for FileDialogs {
+    fn default() -> Self {
+        let cfg_all = FileDialogConfig::default()
+            .add_file_filter_extensions(
+                "All",
+                vec![
+                    "pdb",
+                    "cif",
+                    "sdf",
+                    "mol2",
+                    "pdbqt",
+                    "map",
+                    "mtz",
+                    "frcmod",
+                    "dat",
+                    "lib",
+                    "off",
+                    SESSION_EXT,
+                ],
+            )
+            .add_file_filter_extensions("Molecule", vec!["pdb", "cif", "sdf", "mol2", "pdbqt"])
+            .add_file_filter_extensions("Protein", vec!["pdb", "cif"])
+            .add_file_filter_extensions("Small mol", vec!["sdf", "mol2", "pdbqt"])
+            .add_file_filter_extensions("Density", vec!["map", "mtz", "cif"])
+            .add_file_filter_extensions("Mol dynamics", vec!["frcmod", "dat", "lib", "off"])
+            .add_file_filter_extensions("Session", vec![SESSION_EXT])
+            .add_save_extension("CIF", "cif")
+            .add_save_extension("SDF", "sdf")
+            .add_save_extension("Mol2", "mol2")
+            .add_save_extension("Pdbqt", "pdbqt")
+            .add_save_extension("Map", "map")
+            .add_save_extension("Session", SESSION_EXT);
+
+        let cfg_vina = FileDialogConfig {
+            ..Default::default()
+        }
+        .add_file_filter_extensions("Executables", vec!["", "exe"]);
+
+        //
+        // let cfg_protein = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_file_filter_extensions("PDB/CIF", vec!["pdb", "cif"]);
+        //
+        // let cfg_small_mol = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_file_filter_extensions("SDF/MOL2/PDBQT", vec!["sdf", "mol2", "pdbqt"]);
+        //
+        // let cfg_save_small_mol = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_save_extension("SDF", "sdf")
+        // .add_save_extension("Mol2", "mol2");
+        //
+        // let cfg_crystallography = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_file_filter_extensions("Map/MTZ", vec!["map", "mtz"]);
+        //
+        // let cfg_save_crystallography = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_save_extension("Map", "map")
+        // .add_save_extension("MTZ", "mtz");
+
+        //
+        // let cfg_save_pdbqt = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_save_extension("PDBQT", "pdbqt");
+        //
+        // let cfg_load_mdx = FileDialogConfig {
+        //     ..Default::default()
+        // }
+        // .add_file_filter_extensions("MDX", vec!["mdx"]);
+        //
+        // let load = FileDialog::with_config(cfg_protein.clone()).default_file_filter("PDB/CIF");
+        //
+        // let load_ligand =
+        //     FileDialog::with_config(cfg_small_mol.clone()).default_file_filter("SDF/MOL2/PDBQT");
+        //
+        // let load_crystallography =
+        //     FileDialog::with_config(cfg_crystallography).default_file_filter("Map/MTZ");
+        //
+        // let save = FileDialog::with_config(cfg_protein)
+        //     .add_save_extension("CIF", "cif")
+        //     .default_save_extension("CIF");
+        //
+        // let save_ligand = FileDialog::with_config(cfg_save_small_mol).default_save_extension("SDF");
+        // let save_pdbqt = FileDialog::with_config(cfg_save_pdbqt).default_save_extension("PDBQT");
+        //
+        // // todo: What is this?
+        // let load_mdx = FileDialog::with_config(cfg_load_mdx).default_file_filter("MDX");
+
+        let autodock_path = FileDialog::with_config(cfg_vina).default_file_filter("Executables");
+
+        let load = FileDialog::with_config(cfg_all.clone()).default_file_filter("All");
+        let load_object = FileDialog::with_config(cfg_all.clone()).default_file_filter("Molecule");
+
+        let save = FileDialog::with_config(cfg_all).default_save_extension("Protein");
+
+        let cfg_script = FileDialogConfig::default()
+            .add_file_filter_extensions("Script", vec!["pml", "txt"])
+            .add_save_extension("Script", "pml");
+
+        let load_script = FileDialog::with_config(cfg_script.clone()).default_file_filter("Script");
+        let save_macro = FileDialog::with_config(cfg_script).default_save_extension("Script");
+
+        let cfg_csv = FileDialogConfig::default().add_save_extension("CSV", "csv");
+        let save_csv = FileDialog::with_config(cfg_csv).default_save_extension("CSV");
+
+        Self {
+            load,
+            load_object,
+            // load_ligand,
+            save,
+            // save_ligand,
+            autodock_path,
+            load_script,
+            save_macro,
+            save_csv,
+            // save_pdbqt,
+            // load_mdx,
+            // load_crystallography,
+        }
+    }
+}
+
+/// Flags to accomplish things that must be done somewhere with access to `Scene`.
+#[derive(Default)]
+pub struct SceneFlags {
+    /// Secondary structure
+    pub update_ss_mesh: bool,
+    /// Solvent-accessible surface.
+    pub update_sas_mesh: bool,
+    pub ss_mesh_created: bool,
+    pub sas_mesh_created: bool,
+    pub make_density_mesh: bool,
+    pub clear_density_drawing: bool,
+    pub new_density_loaded: bool,
+    pub new_mol_loaded: bool,
+    /// A Cα trace is ready, while a large molecule loads.
+    pub new_preview_loaded: bool,
+    pub update_sel_sfc_mesh: bool,
+    pub update_chain_sfc_mesh: bool,
+    pub update_pocket_mesh: bool,
+}
+
+/// Surfaces over part of the molecule, e.g. binding-site residues, or a single chain. These are
+/// independent of the whole-molecule surface view.
+#[derive(Default)]
+pub struct PartialSurfaces {
+    /// The atoms the selection surface is built from. Set when requested; the selection may
+    /// change afterwards.
+    sel_atoms: Vec<usize>,
+    /// Index into the molecule's chains.
+    chain: Option<usize>,
+    sel_mesh_created: bool,
+    chain_mesh_created: bool,
+}
+/// Temprary, and generated state.
+pub struct StateVolatile {
+    #[cfg(feature = "gui")]
+    dialogs: FileDialogs,
+    /// We use this for offsetting our cursor selection.
+    ui_height: f32,
+    // /// Center and size are used for setting the camera. Dependent on the molecule atom positions.
+    // mol_center: Vec3,
+    // mol_size: f32, // Dimension-agnostic
+    /// We Use this to keep track of key press state for the camera movement, so we can continuously
+    /// update the flashlight when moving.
+    inputs_commanded: InputsCommanded,
+    /// (Sigma, Epsilon). Initialize once at startup. Not-quite-static.
+    lj_lookup_table: LjTable,
+    snapshots: Vec<Snapshot>,
+    /// Shared with pose searches running in a thread.
+    docking_setup: Option<Arc<DockingSetup>>,
+    /// A docking pose search running in a thread.
+    pose_search: Option<Task<Vec<RankedPose>>>,
+    /// Protein or docking MD running in a thread.
+    md_run: Option<Task<Result<MdRun, DaedalusError>>>,
+    /// e.g. waiting for the data avail thread to return
+    mol_pending_data_avail: Option<
+        Receiver<(
+            Result<PdbDataResults, ReqError>,
+            Result<FilesAvailable, ReqError>,
+        )>,
+    >,
+    /// We may change CWD during CLI navigation; keep prefs directory constant.
+    prefs_dir: PathBuf,
+    /// Entered by the user, for this session.
+    cli_input_history: Vec<String>,
+    cli_input_selected: usize,
+    /// Commands recorded so far, if recording a macro.
+    macro_recording: Option<Vec<String>>,
+    /// The most recently completed recording.
+    macro_last: Vec<String>,
+    /// The camera from a session just opened; applied on the next frame.
+    session_cam: Option<CamSnapshot>,
+    /// Pre-computed from the molecule
+    aa_seq_text: String,
+    flags: SceneFlags,
+    /// The view last used by `draw_molecule`; lets us detect view changes, for transitions.
+    mol_view_drawn: Option<MoleculeView>,
+    view_transition: Option<ViewTransition>,
+    /// Seconds until the residue spotlight fades out.
+    spotlight_remaining: f32,
+    partial_surfaces: PartialSurfaces,
+    blink: Blink,
+    /// Per-vertex colors of the solvent-accessible surface mesh, when not colored uniformly.
+    sas_vertex_colors: Vec<Color>,
+    /// (Mesh index, color) of the solvent-accessible surface, split by color.
+    sas_color_meshes: Vec<(usize, Color)>,
+    /// A solvent-accessible surface mesh being built in a thread.
+    sas_mesh_pending: Option<SasMeshPending>,
+    /// Detected binding pockets, ranked by druggability.
+    pockets: Vec<Pocket>,
+    /// Index into `pockets`.
+    pocket_selected: Option<usize>,
+    /// Indices into the molecule's chains, for interface analysis.
+    interface_chains: (Option<usize>, Option<usize>),
+    interface: Option<Interface>,
+    contact_map_mode: ContactMode,
+    contact_map: Option<ContactMap>,
+    /// The contact map, rendered; built on demand.
+    #[cfg(feature = "gui")]
+    contact_map_tex: Option<TextureHandle>,
+    ramachandran: Option<Vec<RamaPoint>>,
+    validation: Option<ValidationReport>,
+    gnm: Option<GnmResult>,
+    /// Å. After the last backbone edit with the C terminus pinned.
+    backbone_edit_gap: Option<f64>,
+    /// Sorted by descending overlap.
+    clashes: Vec<Clash>,
+    /// Index into `clashes`.
+    clash_selected: Option<usize>,
+    /// Index into `State::objects`. The object that object-specific actions, e.g. superposition,
+    /// apply to.
+    object_active: Option<usize>,
+    superpose_mode: PairMode,
+    /// Pair residues by sequence alignment, vice chain ID and serial number.
+    superpose_align_seq: bool,
+    /// RMSD in Å, and the number of atom pairs, from the last superposition.
+    superpose_result: Option<(f64, usize)>,
+    /// From the last MCS alignment of an object onto the ligand.
+    mcs_alignment: Option<McsAlignment>,
+    /// Color atoms of the ligand and aligned object by their MCS correspondence.
+    show_mcs_mapping: bool,
+    /// Interaction fingerprint of the ligand's current pose.
+    plif: Option<Plif>,
+    /// Fingerprints of each MD snapshot, and the cluster each is in.
+    plif_snapshots: Vec<Plif>,
+    plif_clusters: Vec<usize>,
+    /// The fraction of screened ligands contacting each receptor residue, and the number of
+    /// ligands aggregated.
+    contact_occupancy: Option<(Vec<f32>, usize)>,
+    /// Surface area buried by the ligand's pose, when last computed.
+    lig_bsa: Option<LigandBsa>,
+    /// Ligand-receptor interaction energy by residue, at the ligand's pose when last computed.
+    residue_energy: Option<EnergyDecomp>,
+    /// Binding free energy estimate, over MD snapshots or the current pose.
+    mmgbsa: Option<Mmgbsa>,
+    /// Force field energy against a ligand dihedral.
+    torsion_scan: Option<TorsionScan>,
+    /// RMSD, RMSF, and other metrics over the MD trajectory.
+    traj_analysis: Option<TrajAnalysis>,
+    /// From the geometry cleanup when the ligand was opened.
+    lig_cleanup: Option<CleanupReport>,
+    /// An AM1-BCC charge run for the ligand, in progress, and when it started.
+    am1bcc_pending: Option<(Am1BccPending, Instant)>,
+    /// Checked when first needed.
+    antechamber_avail: Option<bool>,
+    /// A large molecule being parsed in a thread.
+    pending_load: Option<PendingLoad>,
+    /// Cα positions by chain, displayed while `pending_load` is in progress.
+    load_preview: Vec<Vec<Vec3F64>>,
+    /// Set while drawing a newly-loaded large molecule in stages.
+    load_stage: Option<LoadStage>,
+}
+
+impl Default for StateVolatile {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "gui")]
+            dialogs: Default::default(),
+            ui_height: Default::default(),
+            inputs_commanded: Default::default(),
+            lj_lookup_table: init_lj_lut(),
+            snapshots: Default::default(),
+            docking_setup: Default::default(),
+            pose_search: None,
+            md_run: None,
+            mol_pending_data_avail: Default::default(),
+            prefs_dir: env::current_dir().unwrap(),
+            cli_input_history: Default::default(),
+            cli_input_selected: Default::default(),
+            macro_recording: None,
+            macro_last: Vec::new(),
+            session_cam: None,
+            aa_seq_text: Default::default(),
+            flags: Default::default(),
+            mol_view_drawn: Default::default(),
+            view_transition: Default::default(),
+            spotlight_remaining: Default::default(),
+            partial_surfaces: Default::default(),
+            blink: Default::default(),
+            sas_vertex_colors: Default::default(),
+            sas_color_meshes: Default::default(),
+            sas_mesh_pending: None,
+            pockets: Default::default(),
+            pocket_selected: Default::default(),
+            interface_chains: Default::default(),
+            interface: Default::default(),
+            contact_map_mode: Default::default(),
+            contact_map: None,
+            #[cfg(feature = "gui")]
+            contact_map_tex: None,
+            ramachandran: None,
+            validation: None,
+            gnm: None,
+            backbone_edit_gap: None,
+            clashes: Vec::new(),
+            clash_selected: None,
+            object_active: None,
+            superpose_mode: Default::default(),
+            superpose_align_seq: true,
+            superpose_result: Default::default(),
+            mcs_alignment: None,
+            show_mcs_mapping: true,
+            plif: None,
+            plif_snapshots: Vec::new(),
+            plif_clusters: Vec::new(),
+            contact_occupancy: None,
+            lig_bsa: None,
+            residue_energy: None,
+            mmgbsa: None,
+            torsion_scan: None,
+            traj_analysis: None,
+            lig_cleanup: None,
+            am1bcc_pending: None,
+            antechamber_avail: None,
+            pending_load: None,
+            load_preview: Vec::new(),
+            load_stage: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct Visibility {
+    hide_sidechains: bool,
+    hide_water: bool,
+    /// Hide hetero atoms: i.e. ones not part of a polypeptide.
+    hide_hetero: bool,
+    hide_non_hetero: bool,
+    /// The primary molecule. Set from the object list.
+    hide_molecule: bool,
+    hide_ligand: bool,
+    hide_hydrogen: bool,
+    hide_h_bonds: bool,
+    hide_salt_bridges: bool,
+    hide_pi_stacks: bool,
+    hide_cation_pi: bool,
+    /// Between the ligand and receptor.
+    hide_halogen_bonds: bool,
+    hide_chalcogen: bool,
+    dim_peptide: bool,
+    hide_density: bool,
+    hide_density_surface: bool,
+    /// Surfaces over the selection, or a single chain.
+    hide_sel_surface: bool,
+    hide_chain_surface: bool,
+    hide_pockets: bool,
+    hide_objects: bool,
+    hide_clashes: bool,
+    // todo: Seq here, or not?
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            hide_sidechains: false,
+            hide_water: false,
+            hide_hetero: false,
+            hide_non_hetero: false,
+            hide_molecule: false,
+            hide_ligand: false,
+            hide_hydrogen: true,
+            hide_h_bonds: false,
+            hide_salt_bridges: false,
+            hide_pi_stacks: false,
+            hide_cation_pi: false,
+            hide_halogen_bonds: false,
+            hide_chalcogen: false,
+            dim_peptide: false,
+            hide_density: false,
+            hide_density_surface: false,
+            hide_sel_surface: false,
+            hide_chain_surface: false,
+            hide_pockets: false,
+            hide_objects: false,
+            hide_clashes: false,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Encode, Decode)]
+pub enum MsaaSetting {
+    None = 1,
+    // Two = 2, // todo: Not supported on this depth texture, but we could switch to a different one.
+    #[default]
+    Four = 4,
+}
+
+impl MsaaSetting {
+    pub fn to_str(self) -> String {
+        match self {
+            Self::None => "None",
+            // Self::Two => "2×",
+            Self::Four => "4×",
+        }
+        .to_owned()
+    }
+}
+
+/// Ui text fields and similar.
+#[derive(Default)]
+pub struct StateUi {
+    mol_view: MoleculeView,
+    view_sel_level: ViewSelLevel,
+    /// Mouse cursor
+    cursor_pos: Option<(f32, f32)>,
+    db_input: String,
+    cam_snapshot_name: String,
+    /// For saving the current selection under a name.
+    named_sel_name: String,
+    /// Index into `State::named_selections`.
+    named_sel: Option<usize>,
+    residue_search: String,
+    /// To selection.
+    show_near_sel_only: bool,
+    show_near_lig_only: bool,
+    /// Angstrom. For selections, or ligand.
+    nearby_dist_thresh: u16,
+    view_depth: (u16, u16), // angstrom. min, max.
+    cam_snapshot: Option<usize>,
+    dt: f32, // seconds.
+    // For selecting residues from the GUI.
+    chain_to_pick_res: Option<usize>,
+    /// Index into the open molecule's biological assemblies. Its copies of chains are drawn.
+    assembly: Option<usize>,
+    /// Workaround for a bug or limitation in EGUI's `is_pointer_button_down_on`.
+    // inputs_commanded: InputsCommanded,
+    visibility: Visibility,
+    selection: Selection,
+    left_click_down: bool,
+    middle_click_down: bool,
+    autodock_path_valid: bool,
+    mouse_in_window: bool,
+    docking_site_x: String,
+    docking_site_y: String,
+    docking_site_z: String,
+    docking_site_size: String,
+    /// For the arc/orbit cam only.
+    orbit_around_selection: bool,
+    /// Move the camera with the selection when stepping through residues.
+    follow_res_sel: bool,
+    binding_energy_disp: Option<BindingEnergy>,
+    /// Cluster representatives from the global pose search; best first.
+    docked_poses: Vec<RankedPose>,
+    docked_pose_i: usize,
+    show_dock_results: bool,
+    /// Minimize poses with the MD force field after searching.
+    dock_minimize: bool,
+    /// Interactions docked poses must make.
+    pharm_constraints: PharmConstraints,
+    /// Of the open ligand, lowest energy first. These seed the docking search.
+    conformers: Vec<Conformer>,
+    /// Crystallographic waters in the docking site.
+    site_waters: Vec<SiteWater>,
+    current_snapshot: usize,
+    /// A flag so we know to update the flashlight upon loading a new model; this should be done within
+    /// a callback.
+    show_docking_tools: bool,
+    show_settings: bool,
+    show_peptide_builder: bool,
+    /// One-letter amino acid sequence.
+    peptide_seq: String,
+    peptide_backbone: BackbonePreset,
+    show_loop_builder: bool,
+    /// Index into the open molecule's chain breaks. `None` to select the first, with a default
+    /// sequence.
+    loop_break: Option<usize>,
+    /// One-letter amino acid sequence of the residues to build into the break.
+    loop_seq: String,
+    movement_speed_input: String,
+    rotation_sens_input: String,
+    cmd_line_input: String,
+    cmd_line_output: String,
+    /// Indicates CLI, or errors more broadly by changing its displayed color.
+    cmd_line_out_is_err: bool,
+    show_aa_seq: bool,
+    show_contact_map: bool,
+    show_ramachandran: bool,
+    show_validation: bool,
+    show_gnm: bool,
+    show_md_energy: bool,
+    show_residue_energy: bool,
+    show_torsion_scan: bool,
+    show_trajectory: bool,
+    /// PBC fix to apply to MD snapshots when processing. None to skip.
+    md_pbc_mode: Option<PbcMode>,
+    /// Superpose MD snapshots when processing.
+    md_align: bool,
+    /// Interactive MD: step the simulation each rendered frame.
+    md_live: bool,
+    /// Interactive MD: pull the selection toward the cursor while the right button is held.
+    md_pull: bool,
+    right_click_down: bool,
+    #[cfg(feature = "gui")]
+    traj_plot_kind: ui_plots::TrajPlotKind,
+    /// Index into the ligand's bonds.
+    torsion_scan_bond: Option<usize>,
+    torsion_scan_params: TorsionScanParams,
+    protein_md_steps: usize,
+    #[cfg(feature = "gui")]
+    md_plot_kind: ui_plots::MdPlotKind,
+    /// When editing backbone torsions, keep the chain after a short window fixed.
+    rama_pin_c_term: bool,
+    /// Re-run clash detection when atoms are added or moved, e.g. after adding hydrogens.
+    clash_auto: bool,
+    /// The number of screened ligands, in load order, to aggregate contact occupancy over. 0 for all.
+    screen_top_n: usize,
+    /// For AM1-BCC charges.
+    lig_net_charge: i32,
+    /// Use a viridis or simialar colr scheme to color residues gradually based on their
+    /// position in the sequence.
+    res_color_by_index: bool,
+    atom_color_by_charge: bool,
+    /// Color atoms by a numerical value in the atom or residue property store, if present.
+    color_by_prop: Option<String>,
+    /// Affects the electron density mesh.
+    density_iso_level: f32,
+    surface_coloring: SurfaceColoring,
+    md_config: MdConfig,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Encode, Decode)]
+pub enum Selection {
+    #[default]
+    None,
+    /// Of the protein
+    Atom(usize),
+    /// Of the protein
+    Residue(usize),
+    /// Of the protein
+    Atoms(Vec<usize>),
+    AtomLigand(usize),
+}
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct CamSnapshot {
+    // We don't use camera directly, so we don't have to store the projection matrix, and so we can impl
+    // Encode/Decode
+    pub position: Vec3,
+    pub orientation: Quaternion,
+    pub far: f32,
+    pub name: String,
+}
+
+impl CamSnapshot {
+    pub fn from_cam(cam: &Camera, name: String) -> Self {
+        Self {
+            position: cam.position,
+            orientation: cam.orientation,
+            far: cam.far,
+            name,
+        }
+    }
+}
+
+/// A selection saved by the user, e.g. a binding pocket, to recall or refer to in commands.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct NamedSelection {
+    pub name: String,
+    pub selection: Selection,
+}
+
+#[derive(Clone, Default)]
+/// Force field parameters (e.g. Amber) for molecular dynamics.
+pub struct FfParamSet {
+    /// E.g. parsed from Amber `gaff2.dat`.
+    pub lig_general: Option<ForceFieldParamsKeyed>,
+    /// E.g. ff19SB. Loaded at init.
+    pub prot_general: Option<ForceFieldParamsKeyed>,
+    /// In addition to charge, this also contains the mapping of res type to FF type; required to map
+    /// other parameters to protein atoms.
+    pub prot_charge_general: Option<HashMap<AminoAcidGeneral, Vec<ChargeParams>>>,
+    /// ff19SB backbone corrections. Loaded with `prot_general`.
+    pub prot_cmap: Vec<CmapGrid>,
+    /// Multi-term torsions from all loaded `dat` and `frcmod` files, which the keyed sets above
+    /// hold one term of.
+    pub dihedral_series: DihedralSeries,
+    /// Non-standard residues, e.g. caps and modified residues, keyed by residue name. Loaded from
+    /// Amber `.lib` or `.off` files.
+    pub residue_templates: HashMap<String, ResidueTemplate>,
+    /// Key: A unique identifier for the molecule. (e.g. ligand)
+    pub lig_specific: HashMap<String, ForceFieldParamsKeyed>,
+}
+
+#[derive(Default)]
+pub struct State {
+    pub ui: StateUi,
+    pub volatile: StateVolatile,
+    pub pdb: Option<PDB>,
+    pub cif_pdb_raw: Option<String>,
+    pub molecule: Option<Molecule>,
+    pub ligand: Option<Ligand>,
+    /// Additional structures, e.g. mutants or homologs for superposing onto `molecule`.
+    pub objects: Vec<MolObject>,
+    pub cam_snapshots: Vec<CamSnapshot>,
+    pub named_selections: Vec<NamedSelection>,
+    /// This allows us to keep in-memory data for other molecules.
+    pub to_save: ToSave,
+    pub tabs_open: Vec<Tab>,
+    pub babel_avail: bool,
+    pub docking_ready: bool,
+    pub bh_config: BhConfig,
+    pub dev: ComputationDevice,
+    pub mol_dynamics: Option<MdState>,
+    // todo: Combine these params in a single struct.
+    /// Shared with MD runs in a thread. Modify with `Arc::make_mut`.
+    pub ff_params: Arc<FfParamSet>,
+    /// Subscriptions to viewer events, e.g. from code embedding the viewer.
+    pub events: EventBus,
+    /// Extensions adding file formats, commands, and drawn entities.
+    pub plugins: PluginRegistry,
+}
+
+impl State {
+    /// E.g. when loading a new molecule.
+    pub fn reset_selections(&mut self) {
+        self.ui.selection = Selection::None;
+        self.cam_snapshots = Vec::new();
+        self.named_selections = Vec::new();
+        self.ui.named_sel = None;
+        self.ui.cam_snapshot = None;
+        self.ui.chain_to_pick_res = None;
+    }
+
+    /// A saved selection, by name. Case-insensitive.
+    pub fn named_selection(&self, name: &str) -> Option<&Selection> {
+        self.named_selections
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name.trim()))
+            .map(|s| &s.selection)
+    }
+
+    /// Save a selection under a name, replacing any with the same name.
+    pub fn save_named_selection(&mut self, name: &str, selection: Selection) {
+        let name = name.trim();
+        self.named_selections
+            .retain(|s| !s.name.eq_ignore_ascii_case(name));
+        self.named_selections.push(NamedSelection {
+            name: name.to_owned(),
+            selection,
+        });
+        self.ui.named_sel = Some(self.named_selections.len() - 1);
+
+        self.update_save_prefs();
+    }
+
+    /// Gets the docking setup, creating it if it doesn't exist. Returns `None` if molecule
+    /// or ligand are absent.
+    pub fn get_make_docking_setup(&mut self) -> Option<&DockingSetup> {
+        let (Some(mol), Some(lig)) = (&self.molecule, &mut self.ligand) else {
+            return None;
+        };
+
+        let setup = self.volatile.docking_setup.get_or_insert_with(|| {
+            Arc::new(DockingSetup::new(
+                mol,
+                lig,
+                &self.volatile.lj_lookup_table,
+                &self.bh_config,
+            ))
+        });
+        Some(setup.as_ref())
+    }
+
+    pub fn update_docking_site(&mut self, posit: Vec3F64) {
+        if let Some(lig) = &mut self.ligand {
+            lig.docking_site.site_center = posit;
+            lig.pose.anchor_posit = lig.docking_site.site_center;
+            lig.position_atoms(None);
+
+            self.ui.docking_site_x = posit.x.to_string();
+            self.ui.docking_site_y = posit.y.to_string();
+            self.ui.docking_site_z = posit.z.to_string();
+
+            // todo: Make sure this isn't too computationally intensive to put here.
+            if let Some(mol) = &self.molecule {
+                self.volatile.docking_setup = Some(Arc::new(DockingSetup::new(
+                    mol,
+                    lig,
+                    &self.volatile.lj_lookup_table,
+                    &self.bh_config,
+                )));
+            }
+        }
+    }
+}
+
+// todo: Consider a custom default impl. This is a substitute.
+pub fn new_state(dev: ComputationDevice) -> State {
+    State {
+        dev,
+        bh_config: BhConfig {
+            θ: THETA_BH,
+            ..Default::default()
+        },
+        ui: StateUi {
+            view_depth: (VIEW_DEPTH_NEAR_MIN, VIEW_DEPTH_FAR_MAX),
+            nearby_dist_thresh: 15,
+            density_iso_level: 1.8,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Run the application: the GUI, or a headless subcommand such as `convert`, depending on the
+/// command line arguments.
+#[cfg(feature = "gui")]
+pub fn run() {
+    // The level from prefs is applied once they're loaded.
+    logging::init(LogLevel::default());
+
+    #[cfg(feature = "cuda")]
+    let dev = {
+        let runtime_v = cudarc::runtime::result::version::get_runtime_version();
+        let driver_v = cudarc::runtime::result::version::get_driver_version();
+        info!("CUDA runtime: {runtime_v:?}. Driver: {driver_v:?}");
+
+        if runtime_v.is_ok() && driver_v.is_ok() {
+            // This is compiled in `build_`.
+            let ctx = CudaContext::new(0).unwrap();
+            let stream = ctx.default_stream();
+
+            let ptx_file = "./cuda.ptx";
+            let module = ctx.load_module(Ptx::from_file(ptx_file));
+
+            match module {
+                Ok(m) => {
+                    // todo: Store/cache these, likely.
+                    // let func_coulomb = module.load_function("coulomb_kernel").unwrap();
+                    // let func_lj_V = module.load_function("lj_V_kernel").unwrap();
+                    // let func_lj_force = module.load_function("lj_force_kernel").unwrap();
+
+                    ComputationDevice::Gpu((stream, m))
+                }
+                Err(e) => {
+                    warn!("Error loading CUDA module: {ptx_file}; not using CUDA. Error: {e}");
+                    ComputationDevice::Cpu
+                }
+            }
+        } else {
+            ComputationDevice::Cpu
+        }
+
+        // println!("Using the GPU for computations.");
+    };
+
+    #[cfg(not(feature = "cuda"))]
+    let dev = ComputationDevice::Cpu;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            info!("AVX-512 is available");
+        } else if is_x86_feature_detected!("avx") {
+            info!("AVX (256-bit) is available");
+        } else {
+            info!("AVX is not available.");
+        }
+    }
+
+    // Headless format conversion, e.g. for pipelines; no GUI. See the `convert` module.
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("convert") {
+        if let Err(e) = convert::run_cli(&args[2..]) {
+            eprintln!("Conversion failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Run a Python script against a headless session. See the `python` module.
+    #[cfg(feature = "python")]
+    if args.get(1).map(String::as_str) == Some("script") {
+        let Some(path) = args.get(2) else {
+            eprintln!("Usage: daedalus script <file.py>");
+            std::process::exit(1);
+        };
+        if let Err(e) = python::run_script(Path::new(path)) {
+            eprintln!("Script failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut state = new_state(dev);
+
+    state.load_prefs();
+    logging::set_level(state.to_save.log_level);
+
+    let last_opened = state.to_save.last_opened.clone();
+    if let Some(path) = &last_opened {
+        if let Err(e) = state.open_molecule(path) {
+            handle_err(&mut state.ui, e.to_string());
+        }
+    }
+
+    // Load map after molecule, so it knows the coordinates.
+    let last_map_opened = state.to_save.last_map_opened.clone();
+    if let Some(path) = &last_map_opened {
+        if let Err(e) = state.open(path) {
+            handle_err(&mut state.ui, e.to_string());
+        }
+    }
+
+    let last_ligand_opened = state.to_save.last_ligand_opened.clone();
+    if let Some(path) = &last_ligand_opened {
+        if let Err(e) = state.open_molecule(path) {
+            handle_err(&mut state.ui, e.to_string());
+        }
+    }
+
+    // Update ligand positions, e.g. from the docking position site center loaded from prefs.
+    if let Some(lig) = &mut state.ligand {
+        lig.pose.anchor_posit = lig.docking_site.site_center;
+        lig.position_atoms(None);
+    }
+
+    if let Some(mol) = &state.molecule {
+        let posit = state.to_save.per_mol[&mol.ident].docking_site.site_center;
+        state.update_docking_site(posit);
+    }
+
+    state.load_aa_charges_ff();
+
+    // todo temp
+    state
+        .open(&PathBuf::from_str("molecules/CPB.frcmod").unwrap())
+        .unwrap();
+
+    // todo temp
+    // let mtz = load_mtz(&PathBuf::from_str("../../../Desktop/1fat_2fo.mtz").unwrap());
+    // println!("MTZ: {:?}", mtz);
+
+    render::render(state);
+}
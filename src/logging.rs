@@ -0,0 +1,123 @@
+//! Logging, through the `log` facade. Messages go to stderr, with the time since startup. The
+//! level is set in the settings, and can be changed while running.
+//!
+//! `Span` logs how long a scope takes, e.g. `let _span = Span::new("Surface mesh");`.
+
+use std::{borrow::Cow, io::Write, sync::OnceLock, time::Instant};
+
+use bincode::{Decode, Encode};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Error => "Errors",
+            Self::Warn => "Warnings",
+            Self::Info => "Info",
+            Self::Debug => "Debug",
+            Self::Trace => "Trace",
+        }
+    }
+
+    fn filter(self) -> LevelFilter {
+        match self {
+            Self::Off => LevelFilter::Off,
+            Self::Error => LevelFilter::Error,
+            Self::Warn => LevelFilter::Warn,
+            Self::Info => LevelFilter::Info,
+            Self::Debug => LevelFilter::Debug,
+            Self::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+struct Logger {
+    start: Instant,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let _ = writeln!(
+            std::io::stderr(),
+            "[{:>9.3}s {:<5} {}] {}",
+            self.start.elapsed().as_secs_f32(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Install the logger. Call once, at startup; later calls only change the level.
+pub fn init(level: LogLevel) {
+    let logger = LOGGER.get_or_init(|| Logger {
+        start: Instant::now(),
+    });
+    // Fails if a logger is already set, e.g. by code embedding this crate; that's fine.
+    let _ = log::set_logger(logger);
+
+    set_level(level);
+}
+
+pub fn set_level(level: LogLevel) {
+    log::set_max_level(level.filter());
+}
+
+/// Logs the time between its creation and drop, at debug level unless set otherwise.
+pub struct Span {
+    name: Cow<'static, str>,
+    level: Level,
+    start: Instant,
+}
+
+impl Span {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        Self::with_level(Level::Debug, name)
+    }
+
+    /// E.g. `Level::Trace` for ones that run often, like MD steps.
+    pub fn with_level(level: Level, name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: name.into(),
+            level,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if log::log_enabled!(self.level) {
+            log::log!(
+                self.level,
+                "{} took {:.2?}",
+                self.name,
+                self.start.elapsed()
+            );
+        }
+    }
+}
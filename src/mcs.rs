@@ -0,0 +1,232 @@
+//! Maximum common substructure (MCS) between two small molecules, and rigid alignment over it.
+//! Used to overlay a ligand analog onto a reference, e.g. one from a crystal structure, without
+//! involving docking.
+//!
+//! Matching is over heavy atoms, by element and connectivity. Bond orders are ignored, so
+//! differences in aromaticity perception, or Kekulé forms, between files don't break the match.
+//! The search is exhaustive for typical ligand sizes, and returns the best mapping found within
+//! a step budget otherwise.
+
+use lin_alg::f64::Vec3;
+use na_seq::Element;
+
+use crate::{
+    molecule::Molecule,
+    superpose::{kabsch, rmsd},
+};
+
+/// Limits search time for large or highly symmetric molecules.
+const MCS_MAX_STEPS: usize = 200_000;
+
+/// Heavy atoms, and the bonds between them.
+struct Graph {
+    /// Indices of heavy atoms in the molecule.
+    atoms: Vec<usize>,
+    elements: Vec<Element>,
+    /// Indices into `atoms`.
+    adj: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn new(mol: &Molecule) -> Self {
+        let atoms: Vec<_> = mol
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.element != Element::Hydrogen)
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut local = vec![None; mol.atoms.len()];
+        for (k, &i) in atoms.iter().enumerate() {
+            local[i] = Some(k);
+        }
+
+        let mut adj = vec![Vec::new(); atoms.len()];
+        for bond in &mol.bonds {
+            if let (Some(a), Some(b)) = (local[bond.atom_0], local[bond.atom_1]) {
+                adj[a].push(b);
+                adj[b].push(a);
+            }
+        }
+
+        Self {
+            elements: atoms.iter().map(|&i| mol.atoms[i].element).collect(),
+            atoms,
+            adj,
+        }
+    }
+
+    fn bonded(&self, i: usize, j: usize) -> bool {
+        self.adj[i].contains(&j)
+    }
+}
+
+/// Grows connected mappings by depth-first search. At each step, an unmapped atom of `a` adjacent to
+/// the mapped set is either mapped to a compatible atom of `b`, or excluded.
+struct Search<'a> {
+    a: &'a Graph,
+    b: &'a Graph,
+    /// Indices into `b`, by index into `a`.
+    map_a: Vec<Option<usize>>,
+    mapped_b: Vec<bool>,
+    excluded_a: Vec<bool>,
+    /// (a, b) pairs, in the order mapped.
+    pairs: Vec<(usize, usize)>,
+    best: Vec<(usize, usize)>,
+    steps: usize,
+}
+
+impl Search<'_> {
+    /// An atom of `b` is compatible with one of `a` if the elements match, and bonds to all
+    /// mapped atoms match, i.e. the mapped substructures are identical.
+    fn compatible(&self, i_a: usize, i_b: usize) -> bool {
+        if self.mapped_b[i_b] || self.a.elements[i_a] != self.b.elements[i_b] {
+            return false;
+        }
+
+        self.pairs
+            .iter()
+            .all(|&(m_a, m_b)| self.a.bonded(i_a, m_a) == self.b.bonded(i_b, m_b))
+    }
+
+    fn push(&mut self, i_a: usize, i_b: usize) {
+        self.map_a[i_a] = Some(i_b);
+        self.mapped_b[i_b] = true;
+        self.pairs.push((i_a, i_b));
+    }
+
+    fn pop(&mut self) {
+        if let Some((i_a, i_b)) = self.pairs.pop() {
+            self.map_a[i_a] = None;
+            self.mapped_b[i_b] = false;
+        }
+    }
+
+    fn extend(&mut self) {
+        self.steps += 1;
+
+        if self.pairs.len() > self.best.len() {
+            self.best = self.pairs.clone();
+        }
+        if self.steps > MCS_MAX_STEPS {
+            return;
+        }
+
+        // Bound: even mapping every remaining atom can't beat the best.
+        let avail_a = (0..self.a.atoms.len())
+            .filter(|&i| self.map_a[i].is_none() && !self.excluded_a[i])
+            .count();
+        let avail_b = self.mapped_b.iter().filter(|m| !**m).count();
+        if self.pairs.len() + avail_a.min(avail_b) <= self.best.len() {
+            return;
+        }
+
+        let frontier = self.pairs.iter().find_map(|&(m_a, _)| {
+            self.a.adj[m_a]
+                .iter()
+                .copied()
+                .find(|&n| self.map_a[n].is_none() && !self.excluded_a[n])
+        });
+        let Some(i_a) = frontier else {
+            return;
+        };
+
+        // Candidates must be bonded to the image of a mapped neighbor.
+        let anchor_b = self.a.adj[i_a].iter().find_map(|&n| self.map_a[n]).unwrap();
+
+        let candidates: Vec<_> = self.b.adj[anchor_b]
+            .iter()
+            .copied()
+            .filter(|&i_b| self.compatible(i_a, i_b))
+            .collect();
+
+        for i_b in candidates {
+            self.push(i_a, i_b);
+            self.extend();
+            self.pop();
+        }
+
+        self.excluded_a[i_a] = true;
+        self.extend();
+        self.excluded_a[i_a] = false;
+    }
+}
+
+/// Find the maximum common connected substructure of two molecules' heavy atoms. Returns
+/// (atom index in `a`, atom index in `b`) pairs.
+pub fn find_mcs(a: &Molecule, b: &Molecule) -> Vec<(usize, usize)> {
+    let (graph_a, graph_b) = (Graph::new(a), Graph::new(b));
+
+    let mut search = Search {
+        a: &graph_a,
+        b: &graph_b,
+        map_a: vec![None; graph_a.atoms.len()],
+        mapped_b: vec![false; graph_b.atoms.len()],
+        excluded_a: vec![false; graph_a.atoms.len()],
+        pairs: Vec::new(),
+        best: Vec::new(),
+        steps: 0,
+    };
+
+    // Seed with each atom of `a` in turn. Once all mappings containing a seed are explored, it's
+    // excluded from later ones.
+    for seed_a in 0..graph_a.atoms.len() {
+        for seed_b in 0..graph_b.atoms.len() {
+            if graph_a.elements[seed_a] != graph_b.elements[seed_b] {
+                continue;
+            }
+
+            search.push(seed_a, seed_b);
+            search.extend();
+            search.pop();
+        }
+        search.excluded_a[seed_a] = true;
+
+        if search.steps > MCS_MAX_STEPS {
+            break;
+        }
+    }
+
+    search
+        .best
+        .into_iter()
+        .map(|(i_a, i_b)| (graph_a.atoms[i_a], graph_b.atoms[i_b]))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct McsAlignment {
+    /// Index into `State::objects`; the molecule that was moved.
+    pub object: usize,
+    /// (Reference atom index, mobile atom index).
+    pub pairs: Vec<(usize, usize)>,
+    /// Å, over the matched atoms.
+    pub rmsd: f64,
+}
+
+/// Align `mobile` onto a reference molecule, over their MCS, moving all of its atoms. `reference_posits`
+/// are the reference's atom positions; e.g. a ligand's posed positions. Returns the atom
+/// pairs, and the RMSD over them after alignment, or `None` if fewer than 3 atoms match.
+pub fn align_by_mcs(
+    reference: &Molecule,
+    reference_posits: &[Vec3],
+    mobile: &mut Molecule,
+) -> Option<(Vec<(usize, usize)>, f64)> {
+    let pairs = find_mcs(reference, mobile);
+
+    let posits_ref: Vec<_> = pairs.iter().map(|(r, _)| reference_posits[*r]).collect();
+    let posits_mobile: Vec<_> = pairs.iter().map(|(_, m)| mobile.atoms[*m].posit).collect();
+
+    let transform = kabsch(&posits_mobile, &posits_ref)?;
+
+    for atom in &mut mobile.atoms {
+        atom.posit = transform.apply(atom.posit);
+    }
+    mobile.center = transform.apply(mobile.center);
+
+    let posits_mobile: Vec<_> = pairs.iter().map(|(_, m)| mobile.atoms[*m].posit).collect();
+    let rmsd = rmsd(&posits_mobile, &posits_ref);
+
+    Some((pairs, rmsd))
+}
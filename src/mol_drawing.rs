@@ -1,26 +1,33 @@
 //! Handles drawing molecules, bonds etc.
 
-use std::{fmt, io, io::ErrorKind, str::FromStr};
+use std::{collections::HashMap, fmt, io, io::ErrorKind, str::FromStr};
 
 use bincode::{Decode, Encode};
 use bio_files::{Chain, ResidueType};
-use graphics::{ControlScheme, Entity, FWD_VEC, Scene, UP_VEC};
+use graphics::{ControlScheme, Entity, FWD_VEC, Scene, UP_VEC, Vertex};
 use lin_alg::{
     f32::{Quaternion, Vec3},
+    f64::Vec3 as Vec3F64,
     map_linear,
 };
+use log::warn;
 use na_seq::Element;
 
 use crate::{
     Selection, State, ViewSelLevel,
-    molecule::{Atom, AtomRole, BondCount, BondType, Residue, aa_color},
+    molecule::{Atom, AtomRole, BondCount, BondType, Residue, aa_color, hydropathy},
+    objects::{OBJECT_PALETTE, ObjColorScheme, ObjectId},
+    plugins::draw_plugins,
     reflection::ElectronDensity,
-    render::{
+    sa_surface::{atoms_near_points, calc_sasa, nearest_atoms},
+    scene::{
         ATOM_SHININESS, BACKGROUND_COLOR, BALL_RADIUS_WATER, BALL_STICK_RADIUS,
-        BALL_STICK_RADIUS_H, BODY_SHINYNESS, Color, MESH_BOND, MESH_CUBE, MESH_DENSITY_SURFACE,
-        MESH_DOCKING_BOX, MESH_SECONDARY_STRUCTURE, MESH_SOLVENT_SURFACE, MESH_SPHERE_HIGHRES,
+        BALL_STICK_RADIUS_H, BODY_SHINYNESS, Color, MESH_BOND, MESH_CHAIN_SURFACE, MESH_CUBE,
+        MESH_DENSITY_SURFACE, MESH_DOCKING_BOX, MESH_POCKET_SEL, MESH_POCKETS,
+        MESH_SECONDARY_STRUCTURE, MESH_SEL_SURFACE, MESH_SOLVENT_SURFACE, MESH_SPHERE_HIGHRES,
         MESH_SPHERE_LOWRES, MESH_SPHERE_MEDRES, set_docking_light,
     },
+    units,
     util::orbit_center,
 };
 
@@ -33,6 +40,16 @@ const COLOR_AA_NON_RESIDUE: Color = (0., 0.8, 1.0);
 const COLOR_SELECTED: Color = (1., 0., 0.);
 const COLOR_H_BOND: Color = (1., 0.5, 0.1);
 const RADIUS_H_BOND: f32 = 0.2; // A scaler relative to covalent sticks.
+const COLOR_SALT_BRIDGE: Color = (1., 0.85, 0.1);
+const COLOR_PI_STACK: Color = (0.2, 0.85, 0.4);
+const COLOR_CATION_PI: Color = (0.3, 0.6, 1.);
+const COLOR_HALOGEN_BOND: Color = (0.4, 1., 0.9);
+const COLOR_CHALCOGEN: Color = (1., 0.55, 0.75);
+/// Relative to covalent sticks.
+const RADIUS_INTERACTION: f32 = 0.25;
+/// Å. Dashes for non-covalent interactions.
+const DASH_LEN: f32 = 0.25;
+const DASH_GAP: f32 = 0.15;
 
 const COLOR_SFC_DOT: Color = (0.7, 0.7, 0.7);
 const COLOR_DOCKING_BOX: Color = (0.3, 0.3, 0.9);
@@ -40,6 +57,28 @@ pub const COLOR_DOCKING_SITE_MESH: Color = (0.5, 0.5, 0.9);
 
 const COLOR_SA_SURFACE: Color = (0.3, 0.2, 1.);
 
+// For coloring surfaces by electrostatic potential.
+/// Å. We ignore charges farther than this from a surface vertex.
+const POTENTIAL_CUTOFF: f32 = 10.;
+/// kcal/mol · Å / e². Converts q₁q₂/r to kcal/mol.
+const COULOMB_CONST: f32 = units::COULOMB_CONST as f32;
+/// kcal/(mol·e). Potentials at or beyond this are fully saturated in color.
+const POTENTIAL_MAP_RANGE: f32 = 10.;
+const COLOR_SEL_SURFACE: Color = (1., 0.6, 0.2);
+const COLOR_CHAIN_SURFACE: Color = (0.2, 0.9, 0.5);
+const COLOR_POCKET: Color = (0.9, 0.8, 0.1);
+const COLOR_POCKET_SEL: Color = (1., 0.3, 0.6);
+const POCKET_OPACITY: f32 = 0.5;
+const COLOR_CLASH: Color = (1., 0.1, 0.1);
+const CLASH_OPACITY: f32 = 0.6;
+/// Clash highlight spheres are this portion of the atom's VdW radius.
+const CLASH_SPHERE_SCALE: f32 = 0.6;
+
+/// Å.
+const LOAD_PREVIEW_RADIUS: f32 = 1.2;
+/// Past this, Cα atoms in the load preview are skipped at even intervals.
+const LOAD_PREVIEW_MAX_ENTITIES: usize = 100_000;
+
 pub const BOND_RADIUS: f32 = 0.10;
 pub const BOND_RADIUS_LIGAND_RATIO: f32 = 1.3; // Of bond radius.
 // const BOND_CAP_RADIUS: f32 = 1./BOND_RADIUS;
@@ -71,6 +110,7 @@ const MESH_BALL_STICK_SPHERE: usize = MESH_SPHERE_MEDRES;
 const MESH_SPACEFILL_SPHERE: usize = MESH_SPHERE_HIGHRES;
 const MESH_WATER_SPHERE: usize = MESH_SPHERE_MEDRES;
 const MESH_BOND_CAP: usize = MESH_SPHERE_LOWRES;
+const MESH_LOAD_PREVIEW: usize = MESH_SPHERE_LOWRES;
 // This should ideally be high res, but we experience anomolies on viewing items inside it, while
 // the cam is outside.
 // const MESH_DOCKING_SITE: usize = MESH_SPHERE_HIGHRES;
@@ -90,7 +130,85 @@ pub enum EntityType {
     SecondaryStructure = 4,
     SaSurface = 5,
     DockingSite = 6,
+    /// Entities from the previous molecule view, fading out.
+    ViewTransition = 7,
+    /// Surfaces over the selection, or a single chain.
+    PartialSurface = 8,
+    Pocket = 9,
     Other = 10,
+    /// A second molecule, for comparison.
+    Object = 11,
+    Clash = 12,
+    /// Generated by plugins.
+    Plugin = 13,
+    /// Copies of chains making up a biological assembly.
+    Assembly = 14,
+}
+
+/// Duration of the fade when switching molecule views, in seconds.
+const VIEW_TRANSITION_TIME: f32 = 0.35;
+/// Entities fading in start at this portion of their final size; ones fading out shrink to it.
+const VIEW_TRANSITION_SCALE_MIN: f32 = 0.5;
+
+/// Animates a switch between molecule views (e.g. Sticks to SpaceFill) with a fade and scale
+/// interpolation, instead of popping. We own copies of the affected entities at their final
+/// values, and each frame replace the scene's copies with interpolated ones.
+pub struct ViewTransition {
+    /// Seconds.
+    elapsed: f32,
+    outgoing: Vec<Entity>,
+    incoming: Vec<Entity>,
+}
+
+impl ViewTransition {
+    /// Advances the animation, and updates scene entities. Returns `true` when complete, at which
+    /// point the outgoing entities are removed, and the incoming ones are at their final values.
+    pub fn step(&mut self, entities: &mut Vec<Entity>, dt: f32) -> bool {
+        self.elapsed += dt;
+        let portion = (self.elapsed / VIEW_TRANSITION_TIME).min(1.);
+
+        entities.retain(|ent| {
+            ent.class != EntityType::ViewTransition as u32 && !is_mol_view_entity(ent)
+        });
+
+        if portion >= 1. {
+            entities.extend(self.incoming.iter().cloned());
+            return true;
+        }
+
+        // Ease in and out, so the start and end aren't abrupt.
+        let t = portion * portion * (3. - 2. * portion);
+
+        for ent in &self.outgoing {
+            entities.push(transition_entity(ent, 1. - t));
+        }
+        for ent in &self.incoming {
+            entities.push(transition_entity(ent, t));
+        }
+
+        false
+    }
+}
+
+/// Entities that `draw_molecule` rebuilds, and which change with the molecule view.
+fn is_mol_view_entity(ent: &Entity) -> bool {
+    ent.class == EntityType::Protein as u32 || ent.class == EntityType::SaSurface as u32
+}
+
+/// A copy of an entity, partway through fading in. `portion` is 0. for invisible, and 1. for
+/// its final state.
+fn transition_entity(ent: &Entity, portion: f32) -> Entity {
+    let scale_factor = map_linear(portion, (0., 1.), (VIEW_TRANSITION_SCALE_MIN, 1.));
+
+    let mut result = ent.clone();
+    result.opacity = ent.opacity * portion;
+    result.scale = ent.scale * scale_factor;
+    // For bonds, only scale thickness; not length.
+    if let Some(s) = ent.scale_partial {
+        result.scale_partial = Some(Vec3::new(s.x * scale_factor, s.y, s.z * scale_factor));
+    }
+
+    result
 }
 
 // todo: For ligands that are flexible, highlight the fleixble bonds in a bright color.
@@ -143,6 +261,21 @@ impl FromStr for MoleculeView {
     }
 }
 
+impl MoleculeView {
+    /// The name used by the `show_as` command; parses back with `from_str`.
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            Self::Backbone => "backbone",
+            Self::Sticks => "sticks",
+            Self::BallAndStick => "ball_and_stick",
+            Self::Ribbon => "cartoon",
+            Self::SpaceFill => "spheres",
+            Self::Surface => "surface",
+            Self::Dots => "dots",
+        }
+    }
+}
+
 impl fmt::Display for MoleculeView {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let val = match self {
@@ -159,6 +292,139 @@ impl fmt::Display for MoleculeView {
     }
 }
 
+/// What we color solvent-accessible surfaces (and dots) by. Each vertex takes its value from the
+/// atom, or atoms under it.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SurfaceColoring {
+    #[default]
+    Uniform,
+    /// Of the nearest atom.
+    Element,
+    /// Of the nearest atom's residue; Kyte-Doolittle scale.
+    Hydrophobicity,
+    /// Of the nearest atom.
+    BFactor,
+    /// Coulomb potential from partial charges of nearby atoms.
+    Potential,
+    /// Solvent-accessible surface area of the nearest atom's residue.
+    Sasa,
+}
+
+impl fmt::Display for SurfaceColoring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::Uniform => "Uniform",
+            Self::Element => "Element",
+            Self::Hydrophobicity => "Hydrophobicity",
+            Self::BFactor => "B-factor",
+            Self::Potential => "Elec potential",
+            Self::Sasa => "SASA",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+/// Red for negative, white for neutral, blue for positive.
+fn color_diverging(val: f32, range: f32) -> Color {
+    let portion = (val / range).clamp(-1., 1.);
+    if portion < 0. {
+        blend_color((1., 1., 1.), (0.9, 0.1, 0.1), -portion)
+    } else {
+        blend_color((1., 1., 1.), (0.1, 0.2, 0.9), portion)
+    }
+}
+
+/// A color for each vertex of a surface mesh, based on properties of the atoms beneath it.
+pub fn surface_vertex_colors(
+    vertices: &[Vertex],
+    atoms: &[&Atom],
+    residues: &[Residue],
+    coloring: SurfaceColoring,
+) -> Vec<Color> {
+    let points: Vec<Vec3> = vertices
+        .iter()
+        .map(|v| Vec3::from_slice(&v.position).unwrap())
+        .collect();
+
+    if coloring == SurfaceColoring::Potential {
+        return atoms_near_points(&points, atoms, POTENTIAL_CUTOFF)
+            .iter()
+            .zip(&points)
+            .map(|(near, p)| {
+                let mut v = 0.;
+                for &i in near {
+                    let Some(q) = atoms[i].partial_charge else {
+                        continue;
+                    };
+                    let posit: Vec3 = atoms[i].posit.into();
+                    // Distance-dependent dielectric (ε = 4r), a common approximation for implicit solvent.
+                    let r = (posit - *p).magnitude().max(0.5);
+                    v += COULOMB_CONST * q / (4. * r * r);
+                }
+                color_diverging(v, POTENTIAL_MAP_RANGE)
+            })
+            .collect();
+    }
+
+    let res_sasa = if coloring == SurfaceColoring::Sasa {
+        calc_sasa(atoms, residues.len()).per_residue
+    } else {
+        Vec::new()
+    };
+    let sasa_max = res_sasa.iter().copied().fold(0., f32::max);
+
+    let (b_min, b_max) = atoms
+        .iter()
+        .filter_map(|a| a.temperature_factor)
+        .fold((f32::MAX, f32::MIN), |(lo, hi), b| (lo.min(b), hi.max(b)));
+
+    nearest_atoms(&points, atoms)
+        .into_iter()
+        .map(|nearest| {
+            let Some(i) = nearest else {
+                return COLOR_SA_SURFACE;
+            };
+            let atom = atoms[i];
+
+            match coloring {
+                SurfaceColoring::Element => atom.element.color(),
+                SurfaceColoring::Hydrophobicity => {
+                    let aa =
+                        atom.residue
+                            .and_then(|r| residues.get(r))
+                            .and_then(|r| match r.res_type {
+                                ResidueType::AminoAcid(aa) => Some(aa),
+                                _ => None,
+                            });
+                    match aa {
+                        // Hydrophobic residues are orange; hydrophilic ones are blue.
+                        Some(aa) => {
+                            let v = hydropathy(aa);
+                            if v > 0. {
+                                blend_color((1., 1., 1.), (1., 0.5, 0.), v / 4.5)
+                            } else {
+                                blend_color((1., 1., 1.), (0.1, 0.4, 1.), -v / 4.5)
+                            }
+                        }
+                        None => (0.5, 0.5, 0.5),
+                    }
+                }
+                SurfaceColoring::BFactor => match atom.temperature_factor {
+                    Some(b) => color_viridis_float(b, b_min, b_max),
+                    None => (0.5, 0.5, 0.5),
+                },
+                SurfaceColoring::Sasa => match atom.residue.and_then(|r| res_sasa.get(r)) {
+                    Some(area) => color_viridis_float(*area, 0., sasa_max),
+                    None => (0.5, 0.5, 0.5),
+                },
+                // Handled above.
+                SurfaceColoring::Uniform | SurfaceColoring::Potential => COLOR_SA_SURFACE,
+            }
+        })
+        .collect()
+}
+
 /// A linear color map using the viridis scheme.
 fn color_viridis(i: usize, min: usize, max: usize) -> Color {
     // Normalize i to [0.0, 1.0]
@@ -222,49 +488,66 @@ fn atom_color(
     dimmed: bool,
     res_color_by_index: bool,
     atom_color_by_q: bool,
+    // Property key, and its (min, max) value.
+    color_by_prop: Option<(&str, (f32, f32))>,
     is_ligand: bool,
 ) -> Color {
-    let mut result = match view_sel_level {
-        ViewSelLevel::Atom => {
-            if atom_color_by_q {
-                if let Some(q) = atom.partial_charge {
-                    color_viridis_float(q, CHARGE_MAP_MIN, CHARGE_MAP_MAX)
+    let mut result = if let Some((key, (min, max))) = color_by_prop {
+        let val = atom.field_prop(key).or_else(|| {
+            atom.props
+                .get(key)
+                .or_else(|| residues.get(atom.residue?)?.props.get(key))
+                .and_then(|v| v.as_f32())
+        });
+
+        match val {
+            Some(v) => color_viridis_float(v, min, max),
+            // As with charge, don't revert to another scheme; it could be misinterpreted.
+            None => (0.5, 0.5, 0.5),
+        }
+    } else {
+        match view_sel_level {
+            ViewSelLevel::Atom => {
+                if atom_color_by_q {
+                    if let Some(q) = atom.partial_charge {
+                        color_viridis_float(q, CHARGE_MAP_MIN, CHARGE_MAP_MAX)
+                    } else {
+                        // Don't revert to atom color, as that could be misinterpreted.
+                        (0.5, 0.5, 0.5)
+                    }
                 } else {
-                    // Don't revert to atom color, as that could be misinterpreted.
-                    (0.5, 0.5, 0.5)
+                    atom.element.color()
                 }
-            } else {
-                atom.element.color()
             }
-        }
-        ViewSelLevel::Residue => {
-            let mut color = Element::Hydrogen.color(); // todo temp workaround for a bug we haven't tracked down.
-
-            if let Some(res_i) = &atom.residue {
-                let res = &residues[*res_i];
-                color = match &res.res_type {
-                    ResidueType::AminoAcid(aa) => {
-                        if res_color_by_index {
-                            match atom.residue {
-                                Some(res_i) => color_viridis(res_i, 0, aa_count),
-                                None => aa_color(*aa),
+            ViewSelLevel::Residue => {
+                let mut color = Element::Hydrogen.color(); // todo temp workaround for a bug we haven't tracked down.
+
+                if let Some(res_i) = &atom.residue {
+                    let res = &residues[*res_i];
+                    color = match &res.res_type {
+                        ResidueType::AminoAcid(aa) => {
+                            if res_color_by_index {
+                                match atom.residue {
+                                    Some(res_i) => color_viridis(res_i, 0, aa_count),
+                                    None => aa_color(*aa),
+                                }
+                            } else {
+                                aa_color(*aa)
                             }
-                        } else {
-                            aa_color(*aa)
                         }
+                        _ => COLOR_AA_NON_RESIDUE,
+                    };
+
+                    // Todo: WOrkaround for a problem we're having with Hydrogen's showing like hetero atoms
+                    // todo in residue mode. Likely due to them not having their AA set.
+                    // our workaround of setting to 1, 1, 1 is taking effect instead...
+                    // This sets white vice the residue color, but this may be OK.
+                    if atom.element == Element::Hydrogen {
+                        color = atom.element.color();
                     }
-                    _ => COLOR_AA_NON_RESIDUE,
-                };
-
-                // Todo: WOrkaround for a problem we're having with Hydrogen's showing like hetero atoms
-                // todo in residue mode. Likely due to them not having their AA set.
-                // our workaround of setting to 1, 1, 1 is taking effect instead...
-                // This sets white vice the residue color, but this may be OK.
-                if atom.element == Element::Hydrogen {
-                    color = atom.element.color();
                 }
+                color
             }
-            color
         }
     };
 
@@ -382,6 +665,45 @@ fn add_bond(
     entities.push(entity_1);
 }
 
+/// A dashed line between two points, e.g. a salt bridge or π-stack pseudo-bond.
+fn dashed_bond_entities(
+    entities: &mut Vec<Entity>,
+    posit_0: Vec3,
+    posit_1: Vec3,
+    color: Color,
+    ligand: bool,
+) {
+    let diff = posit_1 - posit_0;
+    let len = diff.magnitude();
+    let dir = diff.to_normalized();
+    let orientation = Quaternion::from_unit_vecs(UP_VEC, dir);
+
+    let dash_count = ((len + DASH_GAP) / (DASH_LEN + DASH_GAP)).floor().max(1.) as usize;
+    // Center the dashes, so both ends look the same.
+    let margin = (len - dash_count as f32 * (DASH_LEN + DASH_GAP) + DASH_GAP).max(0.) / 2.;
+
+    for k in 0..dash_count {
+        let start = margin + k as f32 * (DASH_LEN + DASH_GAP);
+        let dash_len = DASH_LEN.min(len);
+
+        let mut ent = Entity::new(
+            MESH_BOND,
+            posit_0 + dir * (start + dash_len / 2.),
+            orientation,
+            1.,
+            color,
+            BODY_SHINYNESS,
+        );
+        ent.class = if ligand {
+            EntityType::Ligand
+        } else {
+            EntityType::Protein
+        } as u32;
+        ent.scale_partial = Some(Vec3::new(RADIUS_INTERACTION, dash_len, RADIUS_INTERACTION));
+        entities.push(ent);
+    }
+}
+
 fn bond_entities(
     entities: &mut Vec<Entity>,
     posit_0: Vec3,
@@ -554,13 +876,40 @@ fn bond_entities(
 }
 
 // todo: DRY with/subset of draw_molecule?
+/// Colors showing the MCS correspondence between the ligand and an aligned object: each matched
+/// pair of atoms shares a color. Keyed by ligand atom index if `ligand`, and object atom index
+/// otherwise. Empty if there's no alignment, or the mapping is hidden.
+fn mcs_colors(state: &State, ligand: bool) -> HashMap<usize, Color> {
+    let Some(aln) = &state.volatile.mcs_alignment else {
+        return HashMap::new();
+    };
+    if !state.volatile.show_mcs_mapping {
+        return HashMap::new();
+    }
+
+    let n = aln.pairs.len();
+    aln.pairs
+        .iter()
+        .enumerate()
+        .map(|(k, (i_ref, i_mobile))| {
+            let i = if ligand { *i_ref } else { *i_mobile };
+            (i, color_viridis(k, 0, n))
+        })
+        .collect()
+}
+
 pub fn draw_ligand(state: &mut State, scene: &mut Scene) {
     // Hard-coded for sticks for now.
+    draw_plugins(state, scene);
 
     scene.entities.retain(|ent| {
         ent.class != EntityType::Ligand as u32 && ent.class != EntityType::DockingSite as u32
     });
 
+    if let (Some(lig), Some(rec)) = (state.ligand.as_mut(), state.molecule.as_ref()) {
+        lig.update_interactions(rec);
+    }
+
     let Some(lig) = state.ligand.as_ref() else {
         set_docking_light(scene, None);
         return;
@@ -591,6 +940,7 @@ pub fn draw_ligand(state: &mut State, scene: &mut Scene) {
     }
 
     let mut atoms_positioned = mol.atoms.clone();
+    let mcs_colors = mcs_colors(state, true);
 
     // for (i, atom) in mol.atoms.iter().enumerate() {
     //         let posit = lig.atom_posits[i].into();
@@ -631,6 +981,7 @@ pub fn draw_ligand(state: &mut State, scene: &mut Scene) {
             false,
             false,
             false,
+            None,
             true,
         );
         let mut color_1 = atom_color(
@@ -643,6 +994,7 @@ pub fn draw_ligand(state: &mut State, scene: &mut Scene) {
             false,
             false,
             false,
+            None,
             true,
         );
 
@@ -663,6 +1015,13 @@ pub fn draw_ligand(state: &mut State, scene: &mut Scene) {
             if bond.atom_1 == lig.anchor_atom {
                 color_1 = LIGAND_COLOR_ANCHOR;
             }
+
+            if let Some(c) = mcs_colors.get(&bond.atom_0) {
+                color_0 = *c;
+            }
+            if let Some(c) = mcs_colors.get(&bond.atom_1) {
+                color_1 = *c;
+            }
         }
 
         bond_entities(
@@ -697,6 +1056,34 @@ pub fn draw_ligand(state: &mut State, scene: &mut Scene) {
         }
     }
 
+    if let Some(rec) = &state.molecule {
+        let vis = &state.ui.visibility;
+        let mut pseudo_bonds = Vec::new();
+
+        if !vis.hide_halogen_bonds {
+            for hb in &lig.halogen_bonds {
+                let posits = (lig.atom_posits[hb.lig_atom], rec.atoms[hb.rec_atom].posit);
+                pseudo_bonds.push((posits, COLOR_HALOGEN_BOND));
+            }
+        }
+        if !vis.hide_chalcogen {
+            for cc in &lig.chalcogen_contacts {
+                let posits = (lig.atom_posits[cc.lig_atom], rec.atoms[cc.rec_atom].posit);
+                pseudo_bonds.push((posits, COLOR_CHALCOGEN));
+            }
+        }
+
+        for ((posit_0, posit_1), color) in pseudo_bonds {
+            dashed_bond_entities(
+                &mut scene.entities,
+                posit_0.into(),
+                posit_1.into(),
+                color,
+                true,
+            );
+        }
+    }
+
     set_docking_light(scene, Some(&state.ligand.as_ref().unwrap().docking_site));
 }
 
@@ -749,8 +1136,9 @@ pub fn draw_density_surface(entities: &mut Vec<Entity>) {
     entities.push(ent);
 }
 
-/// The dots view of solvent-accessible-surface
-fn draw_dots(update_mesh: &mut bool, mesh_created: bool, scene: &mut Scene) {
+/// The dots view of solvent-accessible-surface. `colors` is per-vertex; if empty, we use a
+/// uniform color.
+fn draw_dots(update_mesh: &mut bool, mesh_created: bool, colors: &[Color], scene: &mut Scene) {
     // If the mesh is the default cube, build it. (On demand.)
     if !mesh_created {
         *update_mesh = true;
@@ -758,17 +1146,23 @@ fn draw_dots(update_mesh: &mut bool, mesh_created: bool, scene: &mut Scene) {
     }
 
     if scene.meshes[MESH_SOLVENT_SURFACE].vertices.len() > 1_000_000 {
-        eprintln!("Not drawing dots due to a large-mol rendering problem.");
+        warn!("Not drawing dots due to a large-mol rendering problem.");
         return;
     }
 
-    for vertex in &scene.meshes[MESH_SOLVENT_SURFACE].vertices {
+    for (i, vertex) in scene.meshes[MESH_SOLVENT_SURFACE]
+        .vertices
+        .iter()
+        .enumerate()
+    {
+        let color = colors.get(i).copied().unwrap_or(COLOR_SFC_DOT);
+
         let mut entity = Entity::new(
             MESH_SURFACE_DOT,
             Vec3::from_slice(&vertex.position).unwrap(),
             Quaternion::new_identity(),
             SIZE_SFC_DOT,
-            COLOR_SFC_DOT,
+            color,
             ATOM_SHININESS,
         );
         entity.class = EntityType::Protein as u32;
@@ -776,25 +1170,315 @@ fn draw_dots(update_mesh: &mut bool, mesh_created: bool, scene: &mut Scene) {
     }
 }
 
-/// The mesh view of solvent-accessible-surface
-fn draw_sa_surface(update_mesh: &mut bool, mesh_created: bool, scene: &mut Scene) {
+/// The mesh view of solvent-accessible-surface. If `color_meshes` is populated, it contains
+/// (mesh index, color) for a surface split by color; we draw those instead of the uniform mesh.
+fn draw_sa_surface(
+    update_mesh: &mut bool,
+    mesh_created: bool,
+    color_meshes: &[(usize, Color)],
+    scene: &mut Scene,
+) {
     // If the mesh is the default cube, build it. (On demand.)
     if !mesh_created {
         *update_mesh = true;
         return;
     }
 
-    let mut ent = Entity::new(
-        MESH_SOLVENT_SURFACE,
-        Vec3::new_zero(),
-        Quaternion::new_identity(),
-        1.,
-        COLOR_SA_SURFACE,
-        ATOM_SHININESS,
-    );
-    ent.class = EntityType::SaSurface as u32;
-    ent.opacity = SAS_ISO_OPACITY;
-    scene.entities.push(ent);
+    let meshes = if color_meshes.is_empty() {
+        &[(MESH_SOLVENT_SURFACE, COLOR_SA_SURFACE)][..]
+    } else {
+        color_meshes
+    };
+
+    for (mesh, color) in meshes {
+        let mut ent = Entity::new(
+            *mesh,
+            Vec3::new_zero(),
+            Quaternion::new_identity(),
+            1.,
+            *color,
+            ATOM_SHININESS,
+        );
+        ent.class = EntityType::SaSurface as u32;
+        ent.opacity = SAS_ISO_OPACITY;
+        scene.entities.push(ent);
+    }
+}
+
+/// Surfaces over the selection, and a single chain, if created. These are drawn independently
+/// of the molecule view.
+pub fn draw_partial_surfaces(state: &State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::PartialSurface as u32);
+
+    if state.molecule.is_none() {
+        return;
+    }
+
+    let sfcs = &state.volatile.partial_surfaces;
+    let vis = &state.ui.visibility;
+
+    for (mesh, color, created, hidden) in [
+        (
+            MESH_SEL_SURFACE,
+            COLOR_SEL_SURFACE,
+            sfcs.sel_mesh_created,
+            vis.hide_sel_surface,
+        ),
+        (
+            MESH_CHAIN_SURFACE,
+            COLOR_CHAIN_SURFACE,
+            sfcs.chain_mesh_created,
+            vis.hide_chain_surface,
+        ),
+    ] {
+        if !created || hidden {
+            continue;
+        }
+
+        let mut ent = Entity::new(
+            mesh,
+            Vec3::new_zero(),
+            Quaternion::new_identity(),
+            1.,
+            color,
+            ATOM_SHININESS,
+        );
+        ent.class = EntityType::PartialSurface as u32;
+        ent.opacity = SAS_ISO_OPACITY;
+        scene.entities.push(ent);
+    }
+}
+
+/// Detected binding pockets. The selected one is drawn in a separate color.
+pub fn draw_pockets(state: &State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Pocket as u32);
+
+    if state.ui.visibility.hide_pockets || state.volatile.pockets.is_empty() {
+        return;
+    }
+
+    let mut meshes = vec![(MESH_POCKETS, COLOR_POCKET)];
+    if state.volatile.pocket_selected.is_some() {
+        meshes.push((MESH_POCKET_SEL, COLOR_POCKET_SEL));
+    }
+
+    for (mesh, color) in meshes {
+        let mut ent = Entity::new(
+            mesh,
+            Vec3::new_zero(),
+            Quaternion::new_identity(),
+            1.,
+            color,
+            ATOM_SHININESS,
+        );
+        ent.class = EntityType::Pocket as u32;
+        ent.opacity = POCKET_OPACITY;
+        scene.entities.push(ent);
+    }
+}
+
+/// While loading a large molecule, a coarse Cα trace from a quick scan of its file, colored by
+/// chain.
+pub fn draw_load_preview(trace: &[Vec<Vec3F64>], scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Protein as u32);
+
+    let count: usize = trace.iter().map(|c| c.len()).sum();
+    let stride = count.div_ceil(LOAD_PREVIEW_MAX_ENTITIES).max(1);
+
+    for (i, chain) in trace.iter().enumerate() {
+        let color = OBJECT_PALETTE[i % OBJECT_PALETTE.len()];
+
+        for posit in chain.iter().step_by(stride) {
+            let mut ent = Entity::new(
+                MESH_LOAD_PREVIEW,
+                (*posit).into(),
+                Quaternion::new_identity(),
+                LOAD_PREVIEW_RADIUS,
+                color,
+                ATOM_SHININESS,
+            );
+            ent.class = EntityType::Protein as u32;
+            scene.entities.push(ent);
+        }
+    }
+}
+
+/// Translucent red spheres over atoms involved in steric clashes.
+pub fn draw_clashes(state: &State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Clash as u32);
+
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+    if state.ui.visibility.hide_clashes {
+        return;
+    }
+
+    let mut atoms: Vec<_> = state
+        .volatile
+        .clashes
+        .iter()
+        .flat_map(|c| [c.atom_0, c.atom_1])
+        .collect();
+    atoms.sort_unstable();
+    atoms.dedup();
+
+    for i in atoms {
+        let Some(atom) = mol.atoms.get(i) else {
+            continue;
+        };
+
+        let mut ent = Entity::new(
+            MESH_SPHERE_MEDRES,
+            atom.posit.into(),
+            Quaternion::new_identity(),
+            atom.element.vdw_radius() * CLASH_SPHERE_SCALE,
+            COLOR_CLASH,
+            ATOM_SHININESS,
+        );
+        ent.class = EntityType::Clash as u32;
+        ent.opacity = CLASH_OPACITY;
+        scene.entities.push(ent);
+    }
+}
+
+/// Additional loaded structures, as sticks. By default, each is a single color, so it's easy to
+/// distinguish when overlaid on the primary one.
+pub fn draw_objects(state: &State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Object as u32);
+
+    for i_obj in 0..state.objects.len() {
+        draw_object(state, i_obj, scene);
+    }
+}
+
+/// A single additional structure. Doesn't remove previously-drawn ones; see `draw_objects`.
+fn draw_object(state: &State, i_obj: usize, scene: &mut Scene) {
+    let obj = &state.objects[i_obj];
+    if state.ui.visibility.hide_objects || !obj.visible {
+        return;
+    }
+
+    let mut entities = Vec::new();
+    let mol = &obj.mol;
+
+    let mcs_colors = match &state.volatile.mcs_alignment {
+        Some(aln) if aln.object == i_obj => mcs_colors(state, false),
+        _ => HashMap::new(),
+    };
+
+    for bond in &mol.bonds {
+        let atom_0 = &mol.atoms[bond.atom_0];
+        let atom_1 = &mol.atoms[bond.atom_1];
+
+        if state.ui.visibility.hide_hydrogen
+            && (atom_0.element == Element::Hydrogen || atom_1.element == Element::Hydrogen)
+        {
+            continue;
+        }
+        if state.ui.visibility.hide_water
+            && (atom_0.role == Some(AtomRole::Water) || atom_1.role == Some(AtomRole::Water))
+        {
+            continue;
+        }
+
+        let (mut color_0, mut color_1) = match obj.color_scheme {
+            ObjColorScheme::Uniform => (obj.color, obj.color),
+            ObjColorScheme::Element => (atom_0.element.color(), atom_1.element.color()),
+        };
+        if let Some(c) = mcs_colors.get(&bond.atom_0) {
+            color_0 = *c;
+        }
+        if let Some(c) = mcs_colors.get(&bond.atom_1) {
+            color_1 = *c;
+        }
+
+        bond_entities(
+            &mut entities,
+            obj.transform(atom_0.posit).into(),
+            obj.transform(atom_1.posit).into(),
+            color_0,
+            color_1,
+            bond.bond_type,
+            false,
+        );
+    }
+
+    for mut ent in entities {
+        ent.class = EntityType::Object as u32;
+        scene.entities.push(ent);
+    }
+}
+
+/// Copies of the molecule's chains making up the selected biological assembly, if any. Copies
+/// whose operator is the identity are the molecule itself, so aren't drawn again.
+pub fn draw_assembly(state: &State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Assembly as u32);
+
+    let (Some(mol), Some(assembly)) = (&state.molecule, state.ui.assembly) else {
+        return;
+    };
+
+    let mut entities = Vec::new();
+    let copies = mol
+        .assembly_copies(assembly)
+        .into_iter()
+        .filter(|(_, op)| !op.is_identity());
+
+    for (i_copy, (atoms, op)) in copies.enumerate() {
+        let color = OBJECT_PALETTE[i_copy % OBJECT_PALETTE.len()];
+
+        let mut in_copy = vec![false; mol.atoms.len()];
+        for i in atoms {
+            in_copy[i] = true;
+        }
+
+        for bond in &mol.bonds {
+            if !in_copy[bond.atom_0] || !in_copy[bond.atom_1] {
+                continue;
+            }
+            let atom_0 = &mol.atoms[bond.atom_0];
+            let atom_1 = &mol.atoms[bond.atom_1];
+
+            if state.ui.visibility.hide_hydrogen
+                && (atom_0.element == Element::Hydrogen || atom_1.element == Element::Hydrogen)
+            {
+                continue;
+            }
+            if state.ui.visibility.hide_water
+                && (atom_0.role == Some(AtomRole::Water) || atom_1.role == Some(AtomRole::Water))
+            {
+                continue;
+            }
+
+            bond_entities(
+                &mut entities,
+                op.apply(atom_0.posit).into(),
+                op.apply(atom_1.posit).into(),
+                color,
+                color,
+                bond.bond_type,
+                false,
+            );
+        }
+    }
+
+    for mut ent in entities {
+        ent.class = EntityType::Assembly as u32;
+        scene.entities.push(ent);
+    }
 }
 
 /// Secondary structure, e.g. cartoon.
@@ -821,9 +1505,29 @@ pub fn draw_secondary_structure(update_mesh: &mut bool, mesh_created: bool, scen
     scene.entities.push(ent);
 }
 
-/// Refreshes entities with the model passed.
-/// Sensitive to various view configuration parameters.
+/// Refreshes entities for each loaded structure: the primary molecule, the ligand, and additional
+/// objects. Sensitive to various view configuration parameters.
 pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Object as u32);
+
+    // Additional objects share view settings, e.g. hydrogen and water visibility, with the primary.
+    for id in state.object_ids() {
+        match id {
+            ObjectId::Molecule => draw_primary_molecule(state, scene),
+            ObjectId::Ligand => draw_ligand(state, scene),
+            ObjectId::Extra(i) => draw_object(state, i, scene),
+        }
+    }
+
+    draw_assembly(state, scene);
+    draw_clashes(state, scene);
+    draw_plugins(state, scene);
+}
+
+/// The primary molecule, in the selected view.
+fn draw_primary_molecule(state: &mut State, scene: &mut Scene) {
     let Some(mol) = state.molecule.as_mut() else {
         return;
     };
@@ -841,6 +1545,38 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
         })
         .count();
 
+    let color_by_prop = state
+        .ui
+        .color_by_prop
+        .as_deref()
+        .and_then(|k| Some((k, mol.prop_range(k)?)));
+
+    let view_changed = state
+        .volatile
+        .mol_view_drawn
+        .is_some_and(|v| v != state.ui.mol_view);
+
+    // When switching views, keep the previous view's entities around so they can fade out. If
+    // we're already mid-transition, use the final state of that transition's incoming entities.
+    let outgoing = if view_changed {
+        let outgoing = match state.volatile.view_transition.take() {
+            Some(tr) => tr.incoming,
+            None => scene
+                .entities
+                .iter()
+                .filter(|ent| is_mol_view_entity(ent))
+                .cloned()
+                .collect(),
+        };
+        scene
+            .entities
+            .retain(|ent| ent.class != EntityType::ViewTransition as u32);
+
+        Some(outgoing)
+    } else {
+        None
+    };
+
     // todo: You may wish to integrate Cartoon into this workflow.
     scene.entities.retain(|ent| {
         ent.class != EntityType::Protein as u32 && ent.class != EntityType::SaSurface as u32
@@ -848,6 +1584,13 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
         // ent.class != EntityType::Density as u32
     });
 
+    if state.ui.visibility.hide_molecule {
+        scene
+            .entities
+            .retain(|ent| ent.class != EntityType::SecondaryStructure as u32);
+        return;
+    }
+
     let ui = &state.ui;
 
     if ui.mol_view == MoleculeView::Ribbon {
@@ -863,6 +1606,7 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
         draw_dots(
             &mut state.volatile.flags.update_sas_mesh,
             state.volatile.flags.sas_mesh_created,
+            &state.volatile.sas_vertex_colors,
             scene,
         );
     }
@@ -872,14 +1616,21 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
         draw_sa_surface(
             &mut state.volatile.flags.update_sas_mesh,
             state.volatile.flags.sas_mesh_created,
+            &state.volatile.sas_color_meshes,
             scene,
         );
     }
 
     let chains_invis: Vec<&Chain> = mol.chains.iter().filter(|c| !c.visible).collect();
 
+    // Set while drawing a large molecule in stages, after loading it.
+    let load_stage = state.volatile.load_stage;
+
     // If sticks view, draw water molecules as balls.
-    if ui.mol_view == MoleculeView::Sticks && !state.ui.visibility.hide_water {
+    if ui.mol_view == MoleculeView::Sticks
+        && !state.ui.visibility.hide_water
+        && load_stage.is_none()
+    {
         for (i, atom) in mol.atoms.iter().enumerate() {
             if atom.hetero {
                 // todo: Excessive nesting.
@@ -895,6 +1646,7 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
                             false,
                             false,
                             false,
+                            None,
                             false,
                         );
 
@@ -928,6 +1680,12 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
                 }
             }
 
+            if let Some(stage) = load_stage {
+                if !stage.shows(atom) {
+                    continue;
+                }
+            }
+
             let mut chain_not_sel = false;
             for chain in &chains_invis {
                 if chain.atoms.contains(&i) {
@@ -1012,6 +1770,7 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
                 dim_peptide,
                 state.ui.res_color_by_index,
                 state.ui.atom_color_by_charge,
+                color_by_prop,
                 false,
             );
 
@@ -1042,6 +1801,12 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
         let atom_0 = &mol.atoms[bond.atom_0];
         let atom_1 = &mol.atoms[bond.atom_1];
 
+        if let Some(stage) = load_stage {
+            if !stage.shows(atom_0) || !stage.shows(atom_1) {
+                continue;
+            }
+        }
+
         // Don't draw bonds if on the spacefill view, and the atoms aren't hetero.
         if ui.mol_view == MoleculeView::SpaceFill && !atom_0.hetero && !atom_1.hetero {
             continue;
@@ -1117,6 +1882,7 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
             dim_peptide,
             state.ui.res_color_by_index,
             state.ui.atom_color_by_charge,
+            color_by_prop,
             false,
         );
         let color_1 = atom_color(
@@ -1129,6 +1895,7 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
             dim_peptide,
             state.ui.res_color_by_index,
             state.ui.atom_color_by_charge,
+            color_by_prop,
             false,
         );
 
@@ -1223,6 +1990,105 @@ pub fn draw_molecule(state: &mut State, scene: &mut Scene) {
         }
     }
 
+    // Draw salt bridges, π-stacking, and cation-π interactions. These all involve sidechains, or
+    // hetero atoms.
+    if !state.ui.visibility.hide_non_hetero
+        && !state.ui.visibility.hide_sidechains
+        && ![MoleculeView::SpaceFill, MoleculeView::Backbone].contains(&state.ui.mol_view)
+    {
+        let lig_posit = state
+            .ligand
+            .as_ref()
+            .filter(|_| ui.show_near_lig_only)
+            .map(|lig| lig.atom_posits[lig.anchor_atom]);
+        let sel_posit = ui
+            .show_near_sel_only
+            .then(|| mol.get_sel_atom(&state.ui.selection).map(|a| a.posit))
+            .flatten();
+
+        let visible = |i: usize| {
+            let atom = &mol.atoms[i];
+            let near = |p: Option<Vec3F64>| {
+                p.is_none_or(|p| {
+                    (atom.posit - p).magnitude() as f32 <= ui.nearby_dist_thresh as f32
+                })
+            };
+            near(sel_posit)
+                && near(lig_posit)
+                && !(ui.visibility.hide_hetero && atom.hetero)
+                && !chains_invis.iter().any(|c| c.atoms.contains(&i))
+        };
+
+        let mut pseudo_bonds = Vec::new();
+        let vis = &ui.visibility;
+
+        if !vis.hide_salt_bridges {
+            for sb in &mol.salt_bridges {
+                if visible(sb.cation) && visible(sb.anion) {
+                    let posits = (mol.atoms[sb.cation].posit, mol.atoms[sb.anion].posit);
+                    pseudo_bonds.push((posits, COLOR_SALT_BRIDGE));
+                }
+            }
+        }
+        if !vis.hide_pi_stacks {
+            for stack in &mol.pi_stacks {
+                let (ring_0, ring_1) = (
+                    &mol.rings_aromatic[stack.ring_0],
+                    &mol.rings_aromatic[stack.ring_1],
+                );
+                if visible(ring_0.atoms[0]) && visible(ring_1.atoms[0]) {
+                    pseudo_bonds.push(((ring_0.center, ring_1.center), COLOR_PI_STACK));
+                }
+            }
+        }
+        if !vis.hide_cation_pi {
+            for cp in &mol.cation_pi {
+                let ring = &mol.rings_aromatic[cp.ring];
+                if visible(cp.cation) && visible(ring.atoms[0]) {
+                    let posits = (mol.atoms[cp.cation].posit, ring.center);
+                    pseudo_bonds.push((posits, COLOR_CATION_PI));
+                }
+            }
+        }
+
+        for ((posit_0, posit_1), color) in pseudo_bonds {
+            dashed_bond_entities(
+                &mut scene.entities,
+                posit_0.into(),
+                posit_1.into(),
+                color,
+                false,
+            );
+        }
+    }
+
+    state.volatile.mol_view_drawn = Some(state.ui.mol_view);
+
+    // Hand the freshly-drawn entities to the transition, which positions them at the start of
+    // the fade. If we redraw mid-transition without a view change (e.g. selection), we update
+    // its incoming entities, and keep the animation going.
+    let incoming = || {
+        scene
+            .entities
+            .iter()
+            .filter(|ent| is_mol_view_entity(ent))
+            .cloned()
+            .collect()
+    };
+
+    if let Some(outgoing) = outgoing {
+        let mut transition = ViewTransition {
+            elapsed: 0.,
+            outgoing,
+            incoming: incoming(),
+        };
+        transition.step(&mut scene.entities, 0.);
+        state.volatile.view_transition = Some(transition);
+    } else if let Some(transition) = &mut state.volatile.view_transition {
+        transition.incoming = incoming();
+        transition.step(&mut scene.entities, 0.);
+    }
+
     if let ControlScheme::Arc { center } = &mut scene.input_settings.control_scheme {
         *center = orbit_center(state);
     }
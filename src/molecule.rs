@@ -2,16 +2,19 @@
 
 //! Contains data structures and related code for molecules, atoms, residues, chains, etc.
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fmt,
     fmt::{Display, Formatter},
     io,
     io::ErrorKind,
+    mem,
     str::FromStr,
     sync::mpsc::{self, Receiver},
     thread,
 };
 
+use bincode::{Decode, Encode};
 use bio_apis::{
     ReqError, rcsb,
     rcsb::{FilesAvailable, PdbDataResults, PdbMetaData},
@@ -24,25 +27,129 @@ use lin_alg::{
     f32::Vec3 as Vec3F32,
     f64::{Quaternion, Vec3},
 };
-use na_seq::{AminoAcid, AtomTypeInRes, Element};
+use log::{debug, error, info, warn};
+use na_seq::{AminoAcid, AminoAcidProtenationVariant, AtomTypeInRes, Element};
+use nalgebra::Matrix3;
 use rayon::prelude::*;
 
 use crate::{
     Selection,
-    aa_coords::Dihedral,
-    bond_inference::{create_bonds, create_hydrogen_bonds},
+    aa_coords::{
+        Dihedral, calc_sidechain_dihedrals,
+        flips::{SidechainFlip, optimize_h_bond_network},
+    },
+    analysis::{
+        interactions::{
+            CationPi, ChalcogenContact, HalogenBond, PiStack, SaltBridge, find_cation_pi,
+            find_chalcogen_contacts, find_halogen_bonds, find_pi_stacks, find_salt_bridges,
+        },
+        rings::{Ring, find_aromatic_rings},
+    },
+    assembly::Assembly,
+    bond_inference::{
+        HBondConfig, assign_bond_orders, create_bonds, create_hydrogen_bonds, perceive_rings,
+    },
+    compact_atoms::{CompactAtoms, atoms_heap_size},
     docking::{
         ConformationType, DockingSite, Pose,
-        prep::{DockType, Torsion, UnitCellDims, setup_flexibility},
+        prep::{DockType, Torsion, TorsionTree, UnitCellDims, setup_flexibility},
     },
     dynamics::ForceFieldParamsIndexed,
+    protonation::Protonation,
     reflection::{DensityRect, ElectronDensity, ReflectionsData},
     ribbon_mesh::BackboneSS,
+    sa_surface::Sasa,
+    units::AngleUnit,
     util::mol_center_size,
 };
 
 pub const ATOM_NEIGHBOR_DIST_THRESH: f64 = 5.; // todo: Adjust A/R.
 
+/// A value in an atom or residue's property store.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub enum PropVal {
+    Float(f32),
+    Int(i64),
+    Bool(bool),
+    Text(String),
+}
+
+impl PropVal {
+    /// A numerical value, e.g. for color mapping. `None` for text.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Self::Float(v) => Some(*v),
+            Self::Int(v) => Some(*v as f32),
+            Self::Bool(v) => Some(if *v { 1. } else { 0. }),
+            Self::Text(_) => None,
+        }
+    }
+}
+
+impl Display for PropVal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Float(v) => write!(f, "{v:.3}"),
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Bool(v) => write!(f, "{v}"),
+            Self::Text(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Arbitrary, named per-atom or per-residue data, e.g. conservation scores, analysis results, or
+/// lab annotations. Lets these attach data without changing the `Atom` or `Residue` structs.
+pub type Properties = HashMap<String, PropVal>;
+
+// Atom fields that are available as numerical properties, e.g. for coloring. See `Atom::field_prop`.
+pub const PROP_B_FACTOR: &str = "b_factor";
+pub const PROP_OCCUPANCY: &str = "occupancy";
+/// The ratio of the smallest to largest principal axis of the anisotropic displacement; 1 for isotropic.
+pub const PROP_ANISOTROPY: &str = "anisotropy";
+
+const FIELD_PROPS: [&str; 3] = [PROP_B_FACTOR, PROP_OCCUPANCY, PROP_ANISOTROPY];
+
+/// For atoms modeled in several alternate locations, which to make active. The others are
+/// kept in `Molecule::alt_loc_atoms`.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum AltLocPolicy {
+    /// Ties go to the first ID alphabetically.
+    #[default]
+    HighestOccupancy,
+    /// This ID, e.g. 'A', where present; highest occupancy otherwise.
+    Id(char),
+}
+
+impl AltLocPolicy {
+    /// Index of the candidate to make active, from (alt loc, occupancy) for each.
+    pub fn pick(self, candidates: &[(Option<char>, Option<f32>)]) -> usize {
+        if let Self::Id(id) = self {
+            if let Some(i) = candidates.iter().position(|c| c.0 == Some(id)) {
+                return i;
+            }
+        }
+
+        candidates
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let occ = |c: &(Option<char>, Option<f32>)| c.1.unwrap_or(1.);
+                occ(b).total_cmp(&occ(a)).then(a.0.cmp(&b.0))
+            })
+            .map(|(i, _)| i)
+            .unwrap_or_default()
+    }
+}
+
+impl Display for AltLocPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HighestOccupancy => write!(f, "Highest occ"),
+            Self::Id(id) => write!(f, "Alt loc {id}"),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Molecule {
     pub ident: String,
@@ -51,6 +158,14 @@ pub struct Molecule {
     /// Relating covalent bonds. For each atom, a list of atoms bonded to it.
     pub adjacency_list: Vec<Vec<usize>>,
     pub bonds_hydrogen: Vec<HydrogenBond>,
+    /// The smallest set of smallest rings. Atom indices, in order around each ring.
+    pub rings: Vec<Vec<usize>>,
+    pub rings_aromatic: Vec<Ring>,
+    pub salt_bridges: Vec<SaltBridge>,
+    pub pi_stacks: Vec<PiStack>,
+    pub cation_pi: Vec<CationPi>,
+    /// Asn, Gln, and His flips, and hydroxyl rotations, applied to optimize the H bond network.
+    pub flips: Vec<SidechainFlip>,
     pub chains: Vec<Chain>,
     pub residues: Vec<Residue>,
     pub metadata: Option<PdbMetaData>,
@@ -82,6 +197,20 @@ pub struct Molecule {
     pub aa_seq: Vec<AminoAcid>,
     pub method: Option<ExperimentalMethod>,
     pub ff_params: Option<ForceFieldParamsIndexed>,
+    /// Computed on request, over `sasa_atoms()`.
+    pub sasa: Option<Sasa>,
+    /// Set in memory budget mode, for molecules not in use. `atoms` and `adjacency_list` are empty
+    /// while this is; read atoms with `atom`, `atom_posit`, and `atom_count`, which work in either
+    /// form. See `compact`, and `expand`.
+    pub atoms_compact: Option<CompactAtoms>,
+    /// Estimated pKas, and the pH protonation states were assigned at.
+    pub protonation: Option<Protonation>,
+    /// Alternate locations not currently active, each with the index of the atom in `atoms` it's
+    /// an alternate of. See `set_alt_loc`.
+    pub alt_loc_atoms: Vec<(usize, Atom)>,
+    pub alt_loc_policy: AltLocPolicy,
+    /// Biological assemblies, from the file's symmetry operators. See the `assembly` module.
+    pub assemblies: Vec<Assembly>,
 }
 
 impl Molecule {
@@ -112,25 +241,27 @@ impl Molecule {
 
         result.aa_seq = result.get_seq();
 
-        // todo: Perhaps you still want to calculate dihedral angles if hydrogens are populated already.
-        // todo; For now, you are skipping both. Example when this comes up: Ligands.
-        // Attempt to only populate Hydrogens if there aren't many.
-        if result
-            .atoms
-            .iter()
-            .filter(|a| a.element == Element::Hydrogen)
-            .count()
-            < 4
-        {
-            result.populate_hydrogens_angles();
-        }
+        // Hydrogens are only added to residues missing them; dihedral angles are calculated for all.
+        result.populate_hydrogens_angles();
 
         let bonds = create_bonds(&result.atoms);
         result.bonds = bonds;
+        result.adjacency_list = result.build_adjacency_list();
+        result.rings = perceive_rings(&mut result.atoms, &mut result.bonds, &result.adjacency_list);
+        assign_bond_orders(&mut result.bonds, &result.atoms);
+
+        // Do this prior to inferring H bonds, since it moves polar groups, and removes hydrogens
+        // of His tautomers not chosen.
+        result.flips = optimize_h_bond_network(&mut result);
+        if !result.flips.is_empty() {
+            info!("Applied {} sidechain flips", result.flips.len());
+        }
 
-        result.bonds_hydrogen = create_hydrogen_bonds(&result.atoms, &result.bonds);
+        // After flips, since these change some χ angles.
+        calc_sidechain_dihedrals(&mut result);
 
-        result.adjacency_list = result.build_adjacency_list();
+        result.update_h_bonds(&Default::default());
+        result.infer_noncovalent();
 
         for res in &result.residues {
             if let ResidueType::Other(_) = &res.res_type {
@@ -149,6 +280,141 @@ impl Molecule {
         result
     }
 
+    /// Find aromatic rings, and non-covalent interactions other than hydrogen bonds. Run after
+    /// bonds and the adjacency list are set up.
+    pub fn infer_noncovalent(&mut self) {
+        self.rings_aromatic = find_aromatic_rings(self);
+        self.salt_bridges = find_salt_bridges(self);
+        self.pi_stacks = find_pi_stacks(self, &self.rings_aromatic);
+        self.cation_pi = find_cation_pi(self, &self.rings_aromatic);
+    }
+
+    /// Move atoms to the compact representation, freeing the full one. The molecule can't be
+    /// drawn or used in calculations until expanded. We only do this for extra objects that are
+    /// hidden and inactive; the primary molecule is drawn, selected, and analyzed directly from
+    /// `atoms`, so it always stays expanded.
+    pub fn compact(&mut self) {
+        if self.atoms_compact.is_some() {
+            return;
+        }
+        let compact = CompactAtoms::from_atoms(&self.atoms);
+        info!(
+            "Compacted {}: {:.1} MB -> {:.1} MB",
+            self.ident,
+            atoms_heap_size(&self.atoms) as f32 / 1e6,
+            compact.heap_size() as f32 / 1e6
+        );
+
+        self.atoms_compact = Some(compact);
+        self.atoms = Vec::new();
+        self.adjacency_list = Vec::new();
+    }
+
+    /// Restore atoms from the compact representation, if compacted.
+    pub fn expand(&mut self) {
+        if let Some(compact) = self.atoms_compact.take() {
+            self.atoms = compact.to_atoms();
+            self.adjacency_list = self.build_adjacency_list();
+        }
+    }
+
+    pub fn is_compact(&self) -> bool {
+        self.atoms_compact.is_some()
+    }
+
+    /// Whether or not compacted.
+    pub fn atom_count(&self) -> usize {
+        match &self.atoms_compact {
+            Some(c) => c.len(),
+            None => self.atoms.len(),
+        }
+    }
+
+    /// Whether or not compacted. Borrowed if not; built from the compact form if so.
+    pub fn atom(&self, i: usize) -> Cow<'_, Atom> {
+        match &self.atoms_compact {
+            Some(c) => Cow::Owned(c.atom(i)),
+            None => Cow::Borrowed(&self.atoms[i]),
+        }
+    }
+
+    /// Whether or not compacted. Cheaper than `atom` for compacted molecules.
+    pub fn atom_posit(&self, i: usize) -> Vec3 {
+        match &self.atoms_compact {
+            Some(c) => c.posit(i),
+            None => self.atoms[i].posit,
+        }
+    }
+
+    /// Whether or not compacted.
+    pub fn atom_posits(&self) -> Vec<Vec3> {
+        (0..self.atom_count()).map(|i| self.atom_posit(i)).collect()
+    }
+
+    /// Remove atoms, and bonds to them, and update atom indices elsewhere in the molecule. Returns
+    /// each old index's new one. Derived data, e.g. hydrogen bonds, must be re-computed after.
+    pub fn remove_atoms(&mut self, remove: &[usize]) -> Vec<Option<usize>> {
+        let mut removed = vec![false; self.atoms.len()];
+        for i in remove {
+            removed[*i] = true;
+        }
+
+        let mut map = vec![None; self.atoms.len()];
+        let mut next = 0;
+        for (i, r) in removed.iter().enumerate() {
+            if !r {
+                map[i] = Some(next);
+                next += 1;
+            }
+        }
+
+        let remap = |v: &mut Vec<usize>| *v = v.iter().filter_map(|i| map[*i]).collect();
+
+        let mut i = 0;
+        self.atoms.retain(|_| {
+            i += 1;
+            !removed[i - 1]
+        });
+
+        self.bonds
+            .retain_mut(|b| match (map[b.atom_0], map[b.atom_1]) {
+                (Some(atom_0), Some(atom_1)) => {
+                    b.atom_0 = atom_0;
+                    b.atom_1 = atom_1;
+                    true
+                }
+                _ => false,
+            });
+
+        for res in self.residues.iter_mut().chain(self.het_residues.iter_mut()) {
+            remap(&mut res.atoms);
+        }
+        for chain in &mut self.chains {
+            remap(&mut chain.atoms);
+        }
+        for ring in &mut self.rings {
+            remap(ring);
+        }
+        self.alt_loc_atoms.retain_mut(|(i, _)| match map[*i] {
+            Some(i_new) => {
+                *i = i_new;
+                true
+            }
+            None => false,
+        });
+        for ss in &mut self.secondary_structure {
+            if let (Some(Some(start)), Some(Some(end))) = (map.get(ss.start), map.get(ss.end)) {
+                ss.start = *start;
+                ss.end = *end;
+            }
+        }
+
+        self.adjacency_list = self.build_adjacency_list();
+        self.sasa = None;
+
+        map
+    }
+
     /// Build a list of, for each atom, all atoms bonded to it.
     /// We use this as part of our flexible-bond conformation algorithm, and in setting up
     /// angles and dihedrals for molecular docking.
@@ -194,6 +460,141 @@ impl Molecule {
         }
     }
 
+    /// Indices of all atoms in a selection. Empty for ligand selections.
+    pub fn sel_atom_indices(&self, selection: &Selection) -> Vec<usize> {
+        match selection {
+            Selection::Atom(i) => vec![*i],
+            Selection::Residue(i) => match self.residues.get(*i) {
+                Some(res) => res.atoms.clone(),
+                None => Vec::new(),
+            },
+            Selection::Atoms(is) => is.clone(),
+            Selection::AtomLigand(_) | Selection::None => Vec::new(),
+        }
+    }
+
+    /// A residue's atom, by its PDB name, e.g. "CB".
+    pub fn atom_in_res(&self, res: usize, name: &str) -> Option<usize> {
+        self.residues.get(res)?.atoms.iter().copied().find(|&i| {
+            self.atoms[i]
+                .type_in_res
+                .as_ref()
+                .is_some_and(|t| t.to_string() == name)
+        })
+    }
+
+    /// A property of an atom. Falls back to its residue's, if the atom doesn't have it.
+    pub fn atom_prop(&self, atom_i: usize, key: &str) -> Option<&PropVal> {
+        let atom = self.atoms.get(atom_i)?;
+        atom.props.get(key).or_else(|| {
+            let res = self.residues.get(atom.residue?)?;
+            res.props.get(key)
+        })
+    }
+
+    /// A numerical property of an atom, from its fields, its property store, or its residue's.
+    pub fn atom_prop_f32(&self, atom_i: usize, key: &str) -> Option<f32> {
+        self.atoms
+            .get(atom_i)?
+            .field_prop(key)
+            .or_else(|| self.atom_prop(atom_i, key)?.as_f32())
+    }
+
+    /// Alternate location IDs present, e.g. `['A', 'B']`. Sorted.
+    pub fn alt_loc_ids(&self) -> Vec<char> {
+        let mut result: Vec<_> = self
+            .atoms
+            .iter()
+            .chain(self.alt_loc_atoms.iter().map(|(_, a)| a))
+            .filter_map(|a| a.alt_loc)
+            .collect();
+
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    /// Make one alternate location active for each atom that has several, by swapping
+    /// positions, occupancies, and B-factors with those in `alt_loc_atoms`. Atom indices don't
+    /// change. Hydrogens aren't re-placed, and derived data, e.g. hydrogen bonds, must be
+    /// re-computed after.
+    pub fn set_alt_loc(&mut self, policy: AltLocPolicy) {
+        self.alt_loc_policy = policy;
+
+        let mut alts_by_atom: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (k, (atom_i, _)) in self.alt_loc_atoms.iter().enumerate() {
+            alts_by_atom.entry(*atom_i).or_default().push(k);
+        }
+
+        for (atom_i, alts) in alts_by_atom {
+            let Some(atom) = self.atoms.get_mut(atom_i) else {
+                continue;
+            };
+
+            let mut candidates = vec![(atom.alt_loc, atom.occupancy)];
+            for &k in &alts {
+                let alt = &self.alt_loc_atoms[k].1;
+                candidates.push((alt.alt_loc, alt.occupancy));
+            }
+
+            let picked = policy.pick(&candidates);
+            if picked > 0 {
+                atom.swap_alt_loc(&mut self.alt_loc_atoms[alts[picked - 1]].1);
+            }
+        }
+
+        self.sasa = None;
+    }
+
+    /// Infer hydrogen bonds, e.g. again after the criteria change.
+    pub fn update_h_bonds(&mut self, cfg: &HBondConfig) {
+        self.bonds_hydrogen = create_hydrogen_bonds(&self.atoms, &self.bonds, cfg);
+    }
+
+    /// All property keys present on atoms or residues, including atom fields. Sorted.
+    pub fn prop_keys(&self) -> Vec<String> {
+        let mut result: Vec<_> = self
+            .atoms
+            .iter()
+            .flat_map(|a| a.props.keys())
+            .chain(self.residues.iter().flat_map(|r| r.props.keys()))
+            .cloned()
+            .collect();
+
+        for key in FIELD_PROPS {
+            if self.atoms.iter().any(|a| a.field_prop(key).is_some()) {
+                result.push(key.to_owned());
+            }
+        }
+
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    /// The (min, max) numerical value of a property, across atoms; used for color mapping.
+    pub fn prop_range(&self, key: &str) -> Option<(f32, f32)> {
+        let mut result: Option<(f32, f32)> = None;
+        for i in 0..self.atoms.len() {
+            let Some(v) = self.atom_prop_f32(i, key) else {
+                continue;
+            };
+            result = Some(match result {
+                Some((min, max)) => (min.min(v), max.max(v)),
+                None => (v, v),
+            });
+        }
+        result
+    }
+
+    /// Atoms included in SASA calculations: All except water.
+    pub fn sasa_atoms(&self) -> Vec<&Atom> {
+        self.atoms
+            .iter()
+            .filter(|a| a.role != Some(AtomRole::Water))
+            .collect()
+    }
+
     /// Load RCSB data, and the list of (non-coordinate) files available from the PDB. We do this
     /// in a new thread, to prevent blocking the UI, or delaying a molecule's loading.
     pub fn updates_rcsb_data(
@@ -209,13 +610,13 @@ impl Molecule {
             return;
         }
 
-        println!("Existing data: {:?}", self.rcsb_data);
-        println!("Existing files: {:?}", self.rcsb_files_avail);
+        debug!("Existing data: {:?}", self.rcsb_data);
+        debug!("Existing files: {:?}", self.rcsb_files_avail);
 
         let ident = self.ident.clone(); // data the worker needs
         let (tx, rx) = mpsc::channel(); // one-shot channel
 
-        println!("Getting RCSB data...");
+        info!("Getting RCSB data...");
 
         thread::spawn(move || {
             let data = rcsb::get_all_data(&ident);
@@ -244,7 +645,7 @@ impl Molecule {
             match rx.try_recv() {
                 // both fetches succeeded:
                 Ok((Ok(pdb_data), Ok(files_avail))) => {
-                    println!("RCSB data ready for {}", self.ident);
+                    info!("RCSB data ready for {}", self.ident);
                     self.rcsb_data = Some(pdb_data);
                     self.rcsb_files_avail = Some(files_avail);
 
@@ -256,13 +657,13 @@ impl Molecule {
 
                 // PdbDataResults failed, but FilesAvailable might not have been sent:
                 Ok((Err(e), _)) => {
-                    eprintln!("Failed to fetch PDB data for {}: {e:?}", self.ident);
+                    error!("Failed to fetch PDB data for {}: {e:?}", self.ident);
                     *pending_data_avail = None;
                 }
 
                 // FilesAvailable failed (even if PdbDataResults succeeded):
                 Ok((_, Err(e))) => {
-                    eprintln!("Failed to fetch file‐list for {}: {e:?}", self.ident);
+                    error!("Failed to fetch file‐list for {}: {e:?}", self.ident);
                     *pending_data_avail = None;
                 }
 
@@ -273,7 +674,7 @@ impl Molecule {
 
                 // the sender hung up before sending:
                 Err(mpsc::TryRecvError::Disconnected) => {
-                    eprintln!("Worker thread died before sending result");
+                    error!("Worker thread died before sending result");
                     *pending_data_avail = None;
                 }
             }
@@ -282,7 +683,7 @@ impl Molecule {
     }
 
     /// Get the amino acid sequence from the currently opened molecule, if applicable.
-    fn get_seq(&self) -> Vec<AminoAcid> {
+    pub fn get_seq(&self) -> Vec<AminoAcid> {
         // todo: If not a polypeptide, should we return an error, or empty vec?
         let mut result = Vec::new();
 
@@ -297,7 +698,7 @@ impl Molecule {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Encode, Decode)]
 pub enum AtomRole {
     C_Alpha,
     C_Prime,
@@ -349,9 +750,16 @@ pub struct Ligand {
     // pub offset: Vec3,
     pub anchor_atom: usize,         // Index.
     pub flexible_bonds: Vec<usize>, // Index
+    /// Built from `flexible_bonds`; sets `anchor_atom`, and which atoms each torsion moves.
+    pub torsion_tree: TorsionTree,
+    /// Oxygen indices of receptor waters to keep in the docking site, as part of the receptor.
+    pub site_waters: Vec<usize>,
     pub pose: Pose,
     pub docking_site: DockingSite,
     pub unit_cell_dims: UnitCellDims, // todo: Unused
+    /// With the receptor, at the current pose. Updated when the ligand is drawn.
+    pub halogen_bonds: Vec<HalogenBond>,
+    pub chalcogen_contacts: Vec<ChalcogenContact>,
 }
 
 impl Ligand {
@@ -361,8 +769,8 @@ impl Ligand {
             ..Default::default()
         };
 
-        result.set_anchor();
         result.flexible_bonds = setup_flexibility(&result.molecule);
+        result.set_anchor();
 
         result.pose.conformation_type = ConformationType::Flexible {
             torsions: result
@@ -396,26 +804,23 @@ impl Ligand {
         result
     }
 
-    /// Separate from constructor; run when the pose changes, for now.
-    pub fn set_anchor(&mut self) {
-        let mut center = Vec3::new_zero();
-        for atom in &self.molecule.atoms {
-            center += atom.posit;
+    /// Find halogen bonds and chalcogen contacts with the receptor, at the current pose.
+    pub fn update_interactions(&mut self, rec: &Molecule) {
+        if self.atom_posits.len() != self.molecule.atoms.len() {
+            self.halogen_bonds.clear();
+            self.chalcogen_contacts.clear();
+            return;
         }
-        center /= self.molecule.atoms.len() as f64;
 
-        let mut anchor_atom = 0;
-        let mut best_dist = 999999.;
-
-        for (i, atom) in self.molecule.atoms.iter().enumerate() {
-            let dist = (atom.posit - center).magnitude();
-            if dist < best_dist {
-                best_dist = dist;
-                anchor_atom = i;
-            }
-        }
+        self.halogen_bonds = find_halogen_bonds(rec, &self.molecule, &self.atom_posits);
+        self.chalcogen_contacts = find_chalcogen_contacts(rec, &self.molecule, &self.atom_posits);
+    }
 
-        self.anchor_atom = anchor_atom;
+    /// Build the torsion tree from `flexible_bonds`, and set the anchor to its root. Run this
+    /// when the flexible bonds change.
+    pub fn set_anchor(&mut self) {
+        self.torsion_tree = TorsionTree::new(&self.molecule, &self.flexible_bonds);
+        self.anchor_atom = self.torsion_tree.anchor;
     }
 
     /// Creates global positions for all atoms. This takes into account position, orientation, and if applicable,
@@ -433,7 +838,7 @@ impl Ligand {
         match &pose_.conformation_type {
             ConformationType::Flexible { torsions } => {
                 if self.anchor_atom >= self.molecule.atoms.len() {
-                    eprintln!(
+                    error!(
                         "Error positioning ligand atoms: Anchor outside atom count. Atom cound: {:?}",
                         self.molecule.atoms.len()
                     );
@@ -457,6 +862,30 @@ impl Ligand {
                 for torsion in torsions {
                     let bond = &self.molecule.bonds[torsion.bond];
 
+                    // Rotate the side away from the anchor, per the torsion tree.
+                    if let Some(branch) = self
+                        .torsion_tree
+                        .branches
+                        .iter()
+                        .find(|b| b.bond == torsion.bond)
+                    {
+                        let pivot_pos = result[branch.pivot];
+                        let side = if bond.atom_0 == branch.pivot {
+                            bond.atom_1
+                        } else {
+                            bond.atom_0
+                        };
+                        let axis_vec = (result[side] - pivot_pos).to_normalized();
+                        let rotator =
+                            Quaternion::from_axis_angle(axis_vec, torsion.dihedral_angle as f64);
+
+                        for &atom_idx in &branch.moving {
+                            let relative = result[atom_idx] - pivot_pos;
+                            result[atom_idx] = pivot_pos + rotator.rotate_vec(relative);
+                        }
+                        continue;
+                    }
+
                     // -- Step 1: measure how many atoms would be "downstream" from each side
                     let side0_downstream = self.find_downstream_atoms(bond.atom_1, bond.atom_0);
                     let side1_downstream = self.find_downstream_atoms(bond.atom_0, bond.atom_1);
@@ -528,7 +957,7 @@ impl Ligand {
 }
 
 #[allow(unused)]
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Encode, Decode)]
 pub enum BondType {
     // C+P from pdbtbx for now
     Covalent {
@@ -546,7 +975,7 @@ pub enum BondType {
     CovalentModificationNucleotidePhosphate,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
 pub enum BondCount {
     #[default]
     Single,
@@ -571,7 +1000,7 @@ impl BondCount {
             2 => Self::Double,
             3 => Self::Triple,
             _ => {
-                eprintln!("Error: Invalid count value: {}", count);
+                warn!("Error: Invalid count value: {}", count);
                 Self::Single
             }
         }
@@ -591,21 +1020,33 @@ impl BondCount {
             "1" => Self::Single,
             "2" => Self::Double,
             "3" => Self::Triple,
+            // Aromatic, in SDF.
+            "4" => Self::SingleDoubleHybrid,
             // todo: How should we handle these? New types in the enum?
             "am" => Self::SingleDoubleHybrid,
-            "ar" => Self::Triple,
+            "ar" => Self::SingleDoubleHybrid,
             "du" => Self::Single,
             "un" => Self::Single,
             "nc" => Self::Single,
             _ => {
-                eprintln!("Error: Invalid count value: {}", val);
+                warn!("Error: Invalid count value: {}", val);
                 Self::Single
             }
         }
     }
+
+    /// E.g. the Mol2 format. Hybrid bonds are written as aromatic.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Single => "1",
+            Self::SingleDoubleHybrid => "ar",
+            Self::Double => "2",
+            Self::Triple => "3",
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Bond {
     pub bond_type: BondType,
     /// Index
@@ -613,12 +1054,17 @@ pub struct Bond {
     /// Index
     pub atom_1: usize,
     pub is_backbone: bool,
+    pub in_ring: bool,
+    pub aromatic: bool,
 }
 
 impl Bond {
     pub fn to_generic(&self) -> BondGeneric {
         BondGeneric {
-            bond_type: "1".to_owned(), // todo!
+            bond_type: match self.bond_type {
+                BondType::Covalent { count } => count.to_str().to_owned(),
+                _ => "1".to_owned(),
+            },
             // todo: Map serial num to index incase these don't ascend by one.
             atom_0: self.atom_0 + 1,
             atom_1: self.atom_1 + 1,
@@ -637,6 +1083,8 @@ impl From<&BondGeneric> for Bond {
             atom_0: bond.atom_0 - 1,
             atom_1: bond.atom_1 - 1,
             is_backbone: false,
+            in_ring: false,
+            aromatic: false,
         }
     }
 }
@@ -657,6 +1105,10 @@ pub struct Residue {
     pub res_type: ResidueType,
     pub atoms: Vec<usize>, // Atom index
     pub dihedral: Option<Dihedral>,
+    pub props: Properties,
+    /// The Amber variant for the residue's protonation state, e.g. HIE, if not the standard
+    /// residue. Set by `assign_protonation`.
+    pub variant: Option<AminoAcidProtenationVariant>,
 }
 
 impl Residue {
@@ -676,12 +1128,15 @@ impl From<&ResidueGeneric> for Residue {
             res_type: res.res_type.clone(),
             atoms: res.atoms.clone(),
             dihedral: None,
+            props: Default::default(),
+            variant: None,
         }
     }
 }
 
 impl Residue {
-    pub fn descrip(&self) -> String {
+    /// Includes backbone dihedral angles, if available, in the display unit.
+    pub fn descrip(&self, angle_unit: AngleUnit) -> String {
         let name = match &self.res_type {
             ResidueType::AminoAcid(aa) => aa.to_string(),
             ResidueType::Water => "Water".to_owned(),
@@ -690,7 +1145,7 @@ impl Residue {
 
         let mut result = format!("Res: {}: {name}", self.serial_number);
         if let Some(dihedral) = &self.dihedral {
-            result += &format!("   {dihedral}");
+            result += &format!("   {}", dihedral.descrip(angle_unit));
         }
         result
     }
@@ -723,6 +1178,15 @@ pub struct Atom {
     pub occupancy: Option<f32>,
     pub partial_charge: Option<f32>,
     pub temperature_factor: Option<f32>,
+    /// E.g. 'A' or 'B', for atoms modeled in several locations.
+    pub alt_loc: Option<char>,
+    /// Anisotropic displacement parameters U11, U22, U33, U12, U13, U23, in Å², from PDB `ANISOU`
+    /// records or the mmCIF `atom_site_anisotrop` loop.
+    pub anisou: Option<[f32; 6]>,
+    pub props: Properties,
+    /// Set by ring perception. See `bond_inference::perceive_rings`.
+    pub in_ring: bool,
+    pub aromatic: bool,
     // todo: Impl this, for various calculations
     // /// Atoms relatively close to this; simplifies  certain calculations.
     // pub neighbors: Vec<usize>,
@@ -743,6 +1207,31 @@ impl Atom {
         }
     }
 
+    /// Numerical fields available as properties, e.g. B-factor. See `PROP_B_FACTOR` etc.
+    pub fn field_prop(&self, key: &str) -> Option<f32> {
+        match key {
+            PROP_B_FACTOR => self.temperature_factor,
+            PROP_OCCUPANCY => self.occupancy,
+            PROP_ANISOTROPY => {
+                let [u11, u22, u33, u12, u13, u23] = self.anisou?;
+                let eigs = Matrix3::new(u11, u12, u13, u12, u22, u23, u13, u23, u33)
+                    .symmetric_eigenvalues();
+                let max = eigs.max();
+                (max > 0.).then(|| eigs.min().max(0.) / max)
+            }
+            _ => None,
+        }
+    }
+
+    /// Swap the fields that differ between alternate locations of the same atom.
+    fn swap_alt_loc(&mut self, other: &mut Self) {
+        mem::swap(&mut self.posit, &mut other.posit);
+        mem::swap(&mut self.alt_loc, &mut other.alt_loc);
+        mem::swap(&mut self.occupancy, &mut other.occupancy);
+        mem::swap(&mut self.temperature_factor, &mut other.temperature_factor);
+        mem::swap(&mut self.anisou, &mut other.anisou);
+    }
+
     pub fn to_generic(&self) -> AtomGeneric {
         AtomGeneric {
             serial_number: self.serial_number,
@@ -813,6 +1302,34 @@ pub const fn aa_color(aa: AminoAcid) -> (f32, f32, f32) {
     }
 }
 
+/// Kyte-Doolittle hydropathy index. Positive values are hydrophobic; negative are hydrophilic.
+pub const fn hydropathy(aa: AminoAcid) -> f32 {
+    match aa {
+        AminoAcid::Ile => 4.5,
+        AminoAcid::Val => 4.2,
+        AminoAcid::Leu => 3.8,
+        AminoAcid::Phe => 2.8,
+        AminoAcid::Cys => 2.5,
+        AminoAcid::Met => 1.9,
+        AminoAcid::Ala => 1.8,
+        AminoAcid::Gly => -0.4,
+        AminoAcid::Thr => -0.7,
+        AminoAcid::Ser => -0.8,
+        AminoAcid::Trp => -0.9,
+        AminoAcid::Tyr => -1.3,
+        AminoAcid::Pro => -1.6,
+        AminoAcid::His => -3.2,
+        AminoAcid::Glu => -3.5,
+        AminoAcid::Gln => -3.5,
+        AminoAcid::Asp => -3.5,
+        AminoAcid::Asn => -3.5,
+        AminoAcid::Lys => -3.9,
+        AminoAcid::Arg => -4.5,
+        // Not in the original scale; treat similar to Cys.
+        AminoAcid::Sec => 2.5,
+    }
+}
+
 // todo: A/R.
 
 // #[derive(Debug, Clone, PartialEq)]
@@ -847,6 +1364,9 @@ impl From<Mol2> for Molecule {
         result.bonds = bonds;
         result.bonds_hydrogen = Vec::new();
         result.adjacency_list = result.build_adjacency_list();
+        // Keep bond orders from the file.
+        result.rings = perceive_rings(&mut result.atoms, &mut result.bonds, &result.adjacency_list);
+        result.infer_noncovalent();
 
         result
     }
@@ -865,6 +1385,9 @@ impl From<Sdf> for Molecule {
         result.bonds = bonds;
         result.bonds_hydrogen = Vec::new();
         result.adjacency_list = result.build_adjacency_list();
+        // Keep bond orders from the file.
+        result.rings = perceive_rings(&mut result.atoms, &mut result.bonds, &result.adjacency_list);
+        result.infer_noncovalent();
 
         result
     }
@@ -888,7 +1411,18 @@ impl Molecule {
     // todo: DRY!
     pub fn to_sdf(&self) -> Sdf {
         let atoms = self.atoms.iter().map(|a| a.to_generic()).collect();
-        let bonds = self.bonds.iter().map(|b| b.to_generic()).collect();
+        // SDF's bond type for aromatic is 4, vice Mol2's "ar".
+        let bonds = self
+            .bonds
+            .iter()
+            .map(|b| {
+                let mut bond = b.to_generic();
+                if bond.bond_type == "ar" {
+                    bond.bond_type = "4".to_owned();
+                }
+                bond
+            })
+            .collect();
         let residues = self.residues.iter().map(|r| r.to_generic()).collect();
 
         Sdf {
@@ -905,7 +1439,7 @@ impl Molecule {
 }
 
 // todo: Move to na_seq?
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Encode, Decode)]
 /// The method used to find a given molecular structure. This data is present in mmCIF files
 /// as the `_exptl.method` field.
 pub enum ExperimentalMethod {
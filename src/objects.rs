@@ -0,0 +1,171 @@
+//! Structures loaded alongside the primary molecule: e.g. mutants or homologs for comparison,
+//! partners in a complex, or a set of screening hits. Each has its own visibility, color scheme,
+//! and rigid-body transform. Analysis, selection, and docking act on the primary molecule.
+//!
+//! The object list also has entries for the primary molecule and the ligand; see `ObjectId`.
+//! Drawing iterates over it. These two define the frame analysis works in, so have visibility, but
+//! no transform.
+
+use std::fmt;
+
+use bincode::{Decode, Encode};
+use lin_alg::f64::{Quaternion, Vec3};
+
+use crate::{State, molecule::Molecule, scene::Color};
+
+/// Uniform object colors are assigned from this, in order of loading.
+pub const OBJECT_PALETTE: [Color; 6] = [
+    (0.9, 0.5, 0.9),
+    (0.4, 0.9, 0.6),
+    (1.0, 0.7, 0.3),
+    (0.5, 0.8, 1.0),
+    (1.0, 1.0, 0.5),
+    (0.9, 0.4, 0.4),
+];
+
+/// In memory budget mode, objects with fewer atoms than this aren't compacted; the savings would
+/// be small. This also keeps small molecules, e.g. screening hits, available for analysis.
+const COMPACT_MIN_ATOMS: usize = 10_000;
+
+/// An entry in the object list.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ObjectId {
+    /// `State::molecule`
+    Molecule,
+    /// `State::ligand`
+    Ligand,
+    /// An index into `State::objects`.
+    Extra(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum ObjColorScheme {
+    /// A single color for the whole object; makes it easy to distinguish when overlaid on others.
+    #[default]
+    Uniform,
+    Element,
+}
+
+impl fmt::Display for ObjColorScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::Uniform => "Uniform",
+            Self::Element => "Element",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+#[derive(Debug)]
+pub struct MolObject {
+    pub mol: Molecule,
+    pub visible: bool,
+    pub color_scheme: ObjColorScheme,
+    /// Used by the `Uniform` scheme.
+    pub color: Color,
+    /// Rotation about the molecule's center, then translation. Applied when drawing; atom positions
+    /// are unchanged until `bake_transform` is called.
+    pub orientation: Quaternion,
+    /// Å
+    pub offset: Vec3,
+}
+
+impl MolObject {
+    pub fn new(mol: Molecule, color: Color) -> Self {
+        Self {
+            mol,
+            visible: true,
+            color_scheme: Default::default(),
+            color,
+            orientation: Quaternion::new_identity(),
+            offset: Vec3::new_zero(),
+        }
+    }
+
+    /// A position in the molecule's frame, with the object's transform applied.
+    pub fn transform(&self, posit: Vec3) -> Vec3 {
+        let center = self.mol.center;
+        self.orientation.rotate_vec(posit - center) + center + self.offset
+    }
+
+    /// Atom positions, with the object's transform applied. Works on compacted objects.
+    pub fn atom_posits(&self) -> Vec<Vec3> {
+        self.mol
+            .atom_posits()
+            .into_iter()
+            .map(|p| self.transform(p))
+            .collect()
+    }
+
+    /// Apply the transform to atom positions, then reset it. Do this before operations that use
+    /// atom positions directly, e.g. superposition. Expands the object if compacted.
+    pub fn bake_transform(&mut self) {
+        self.mol.expand();
+
+        let posits = self.atom_posits();
+        for (atom, posit) in self.mol.atoms.iter_mut().zip(posits) {
+            atom.posit = posit;
+        }
+        self.mol.center += self.offset;
+
+        self.orientation = Quaternion::new_identity();
+        self.offset = Vec3::new_zero();
+    }
+
+    /// In memory budget mode, compact large objects that are hidden, and not active. Otherwise,
+    /// expand them.
+    pub fn update_compact(&mut self, memory_budget: bool, active: bool) {
+        if memory_budget && !self.visible && !active && self.mol.atom_count() >= COMPACT_MIN_ATOMS {
+            self.mol.compact();
+        } else {
+            self.mol.expand();
+        }
+    }
+}
+
+impl State {
+    /// Each loaded structure, in drawing order: the primary molecule, the ligand, then additional
+    /// objects.
+    pub fn object_ids(&self) -> Vec<ObjectId> {
+        let mut result = Vec::with_capacity(self.objects.len() + 2);
+        if self.molecule.is_some() {
+            result.push(ObjectId::Molecule);
+        }
+        if self.ligand.is_some() {
+            result.push(ObjectId::Ligand);
+        }
+        result.extend((0..self.objects.len()).map(ObjectId::Extra));
+
+        result
+    }
+
+    pub fn object_name(&self, id: ObjectId) -> &str {
+        let name = match id {
+            ObjectId::Molecule => self.molecule.as_ref().map(|m| &m.ident),
+            ObjectId::Ligand => self.ligand.as_ref().map(|l| &l.molecule.ident),
+            ObjectId::Extra(i) => self.objects.get(i).map(|o| &o.mol.ident),
+        };
+        name.map(|n| n.as_str()).unwrap_or_default()
+    }
+
+    pub fn object_visible(&self, id: ObjectId) -> bool {
+        match id {
+            ObjectId::Molecule => !self.ui.visibility.hide_molecule,
+            ObjectId::Ligand => !self.ui.visibility.hide_ligand,
+            ObjectId::Extra(i) => self.objects.get(i).is_some_and(|o| o.visible),
+        }
+    }
+
+    pub fn set_object_visible(&mut self, id: ObjectId, visible: bool) {
+        match id {
+            ObjectId::Molecule => self.ui.visibility.hide_molecule = !visible,
+            ObjectId::Ligand => self.ui.visibility.hide_ligand = !visible,
+            ObjectId::Extra(i) => {
+                if let Some(obj) = self.objects.get_mut(i) {
+                    obj.visible = visible;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Plugins: extensions that add file formats, command-line commands, and drawn entities, without
+//! changing the drawing and state code. Implement `Plugin`, overriding the hooks you need, and
+//! register it at startup, e.g. in `new_state`:
+//!
+//! ```ignore
+//! struct AtomCount;
+//!
+//! impl Plugin for AtomCount {
+//!     fn name(&self) -> &str {
+//!         "Atom count"
+//!     }
+//!
+//!     fn commands(&self) -> &[&str] {
+//!         &["count"]
+//!     }
+//!
+//!     fn run_cmd(&mut self, _cmd: &str, _args: &str, state: &mut State) -> io::Result<String> {
+//!         let Some(mol) = &state.molecule else {
+//!             return Ok("No molecule is open".to_owned());
+//!         };
+//!         Ok(format!("{} atoms", mol.atoms.len()))
+//!     }
+//! }
+//!
+//! state.plugins.register(Box::new(AtomCount));
+//! ```
+//!
+//! Hooks run on the UI thread, so should return quickly.
+
+use std::{io, io::ErrorKind, path::Path};
+
+use graphics::{Entity, Scene};
+
+use crate::{State, mol_drawing::EntityType, molecule::Molecule};
+
+/// A molecule parsed by a plugin, and whether to open it as the ligand or the primary molecule.
+pub enum PluginFile {
+    Molecule(Molecule),
+    Ligand(Molecule),
+}
+
+pub trait Plugin {
+    /// Shown in errors.
+    fn name(&self) -> &str;
+
+    /// Lowercase file extensions, without the dot, this plugin opens.
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Parse a file with one of `extensions`.
+    fn open(&mut self, _path: &Path) -> io::Result<PluginFile> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "This plugin doesn't open files",
+        ))
+    }
+
+    /// Command-line commands this plugin handles, e.g. an analysis.
+    fn commands(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Run one of `commands`. `args` is the rest of the input, trimmed. Returns the text to show.
+    fn run_cmd(&mut self, _cmd: &str, _args: &str, _state: &mut State) -> io::Result<String> {
+        Ok(String::new())
+    }
+
+    /// Entities to draw, e.g. annotations or generated geometry. Called when the molecule or
+    /// ligand is redrawn.
+    fn entities(&self, _state: &State) -> Vec<Entity> {
+        Vec::new()
+    }
+}
+
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl PluginRegistry {
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn commands(&self) -> Vec<&str> {
+        self.plugins
+            .iter()
+            .flat_map(|p| p.commands().iter().copied())
+            .collect()
+    }
+
+    pub fn opens(&self, extension: &str) -> bool {
+        self.plugins
+            .iter()
+            .any(|p| p.extensions().contains(&extension))
+    }
+
+    /// Open a file with the first plugin that handles its extension.
+    pub fn open(&mut self, path: &Path) -> io::Result<PluginFile> {
+        let extension = path.extension().unwrap_or_default().to_ascii_lowercase();
+        let extension = extension.to_str().unwrap_or_default();
+
+        match self
+            .plugins
+            .iter_mut()
+            .find(|p| p.extensions().contains(&extension))
+        {
+            Some(p) => p
+                .open(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", p.name()))),
+            None => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Unsupported file extension",
+            )),
+        }
+    }
+}
+
+impl State {
+    /// Run a plugin's command, if `input` starts with one. Plugins can modify state, so the
+    /// registry is taken out of it while the command runs.
+    pub fn run_plugin_cmd(&mut self, input: &str) -> Option<io::Result<String>> {
+        let (cmd, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let cmd = cmd.to_lowercase();
+
+        let i = self
+            .plugins
+            .plugins
+            .iter()
+            .position(|p| p.commands().contains(&cmd.as_str()))?;
+
+        let mut plugins = std::mem::take(&mut self.plugins);
+        let result = plugins.plugins[i].run_cmd(&cmd, args.trim(), self);
+
+        // Keep any registered while the command ran.
+        plugins.plugins.append(&mut self.plugins.plugins);
+        self.plugins = plugins;
+
+        Some(result)
+    }
+}
+
+/// Replace plugin-generated entities with current ones.
+pub fn draw_plugins(state: &State, scene: &mut Scene) {
+    scene
+        .entities
+        .retain(|ent| ent.class != EntityType::Plugin as u32);
+
+    for plugin in &state.plugins.plugins {
+        for mut ent in plugin.entities(state) {
+            ent.class = EntityType::Plugin as u32;
+            scene.entities.push(ent);
+        }
+    }
+}
@@ -9,12 +9,17 @@ use graphics::{
     app_utils::{load, save},
 };
 use lin_alg::f64::Vec3;
+use log::{debug, error, info, warn};
 
 use crate::{
-    CamSnapshot, MsaaSetting, Selection, State, ViewSelLevel, Visibility,
+    CamSnapshot, MsaaSetting, NamedSelection, Selection, State, ViewSelLevel, Visibility,
+    bond_inference::HBondConfig,
     docking::DockingSite,
-    inputs::{MOVEMENT_SENS, ROTATE_SENS},
+    logging::LogLevel,
     mol_drawing::MoleculeView,
+    protonation::PH_DEFAULT,
+    scene::{MOVEMENT_SENS, ROTATE_SENS},
+    units::{AngleUnit, EnergyUnit},
 };
 
 pub const DEFAULT_PREFS_FILE: &str = "daedalus_prefs.dae";
@@ -35,6 +40,17 @@ pub struct ToSave {
     /// Solvent-accessible surface (and dots) precion. Lower is higher precision. A value of 0.5 - 0.6
     /// is a good default. Too low will cause crashes and very poor performance. Higher is too coarse.
     pub sa_surface_precision: f32,
+    /// Display units. Values are stored in radians and kcal/mol regardless.
+    pub angle_unit: AngleUnit,
+    pub energy_unit: EnergyUnit,
+    pub h_bond_cfg: HBondConfig,
+    /// Reduce memory use, for very large structures: Don't keep the opened file's text or parsed
+    /// structure, and store hidden objects compactly.
+    pub memory_budget: bool,
+    /// For assigning protonation states of titratable residues.
+    pub ph: f32,
+    /// Console log verbosity.
+    pub log_level: LogLevel,
 }
 
 impl Default for ToSave {
@@ -50,6 +66,12 @@ impl Default for ToSave {
             movement_speed: MOVEMENT_SENS as u8,
             rotation_sens: (ROTATE_SENS * 100.) as u8,
             sa_surface_precision: 0.55,
+            angle_unit: Default::default(),
+            energy_unit: Default::default(),
+            h_bond_cfg: Default::default(),
+            memory_budget: false,
+            ph: PH_DEFAULT,
+            log_level: Default::default(),
         }
     }
 }
@@ -63,6 +85,7 @@ pub struct MolMetaData {
 pub struct PerMolToSave {
     selection: Selection,
     cam_snapshots: Vec<CamSnapshot>,
+    named_selections: Vec<NamedSelection>,
     mol_view: MoleculeView,
     view_sel_level: ViewSelLevel,
     near_sel_only: bool,
@@ -113,6 +136,7 @@ impl PerMolToSave {
         Self {
             selection: state.ui.selection.clone(),
             cam_snapshots: state.cam_snapshots.clone(),
+            named_selections: state.named_selections.clone(),
             mol_view: state.ui.mol_view,
             view_sel_level: state.ui.view_sel_level,
             near_sel_only: state.ui.show_near_sel_only,
@@ -141,7 +165,7 @@ impl State {
             &self.volatile.prefs_dir.join(DEFAULT_PREFS_FILE),
             &self.to_save,
         ) {
-            eprintln!("Error saving state: {e:?}");
+            error!("Error saving state: {e:?}");
         }
     }
 
@@ -157,7 +181,7 @@ impl State {
             &self.volatile.prefs_dir.join(DEFAULT_PREFS_FILE),
             &self.to_save,
         ) {
-            eprintln!("Error saving state: {e:?}");
+            error!("Error saving state: {e:?}");
         }
     }
 
@@ -173,6 +197,7 @@ impl State {
 
                 self.ui.selection = data.selection.clone();
                 self.cam_snapshots = data.cam_snapshots.clone();
+                self.named_selections = data.named_selections.clone();
                 self.ui.mol_view = data.mol_view;
                 self.ui.view_sel_level = data.view_sel_level;
                 self.ui.show_near_sel_only = data.near_sel_only;
@@ -209,10 +234,10 @@ impl State {
 
             // If loaded from file or not.
             if mol.metadata.is_none() {
-                println!("Getting MD");
+                debug!("Getting MD");
                 match load_metadata(&mol.ident) {
                     Ok(md) => mol.metadata = Some(md),
-                    Err(_) => eprintln!("Error loading metadata for: {}", mol.ident),
+                    Err(_) => warn!("Error loading metadata for: {}", mol.ident),
                 }
             }
         }
@@ -226,7 +251,7 @@ impl State {
     pub fn load_prefs(&mut self) {
         match load(&PathBuf::from(DEFAULT_PREFS_FILE)) {
             Ok(p) => self.to_save = p,
-            Err(_) => eprintln!("Unable to load save file; possibly the first time running."),
+            Err(_) => info!("Unable to load save file; possibly the first time running."),
         }
 
         self.update_from_prefs();
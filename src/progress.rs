@@ -0,0 +1,112 @@
+//! Progress reporting and cancellation for long operations, e.g. loading large files, building
+//! surface meshes, docking searches, and MD runs. The worker advances a `Progress`, and checks
+//! whether it's been cancelled; the UI holds a clone, and shows a progress bar with a Cancel
+//! button. Cancelled work stops at its next check, and returns what it has so far, or an error.
+//!
+//! `Task` runs a function in a thread, with a `Progress`, and receives its result.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+};
+
+#[derive(Debug, Default)]
+struct ProgressInner {
+    done: AtomicUsize,
+    total: AtomicUsize,
+    cancelled: AtomicBool,
+    /// A description of the current step, e.g. "Parsing".
+    stage: Mutex<&'static str>,
+}
+
+/// Shared between the worker, and the code displaying it; clones refer to the same progress.
+#[derive(Clone, Debug, Default)]
+pub struct Progress(Arc<ProgressInner>);
+
+impl Progress {
+    /// Resets the completed count.
+    pub fn set_total(&self, total: usize) {
+        self.0.total.store(total, Ordering::Relaxed);
+        self.0.done.store(0, Ordering::Relaxed);
+    }
+
+    pub fn set_done(&self, done: usize) {
+        self.0.done.store(done, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.0.done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Mark one unit done. Returns `false` if cancelled; use as the condition for continuing a
+    /// loop.
+    pub fn advance(&self) -> bool {
+        self.inc();
+        !self.is_cancelled()
+    }
+
+    pub fn set_stage(&self, stage: &'static str) {
+        *self.0.stage.lock().unwrap() = stage;
+    }
+
+    pub fn stage(&self) -> &'static str {
+        *self.0.stage.lock().unwrap()
+    }
+
+    /// 0. to 1. 0. if the total isn't known yet.
+    pub fn fraction(&self) -> f32 {
+        let total = self.0.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.;
+        }
+        (self.0.done.load(Ordering::Relaxed) as f32 / total as f32).min(1.)
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub enum TaskStatus<T> {
+    Running,
+    Done(T),
+    /// The thread panicked.
+    Failed,
+}
+
+/// Work running in a thread. Poll it from the render loop, so the UI doesn't block.
+pub struct Task<T> {
+    rx: Receiver<T>,
+    pub progress: Progress,
+}
+
+impl<T: Send + 'static> Task<T> {
+    pub fn spawn(f: impl FnOnce(&Progress) -> T + Send + 'static) -> Self {
+        let progress = Progress::default();
+        let progress_ = progress.clone();
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(f(&progress_));
+        });
+
+        Self { rx, progress }
+    }
+
+    /// Non-blocking.
+    pub fn poll(&self) -> TaskStatus<T> {
+        match self.rx.try_recv() {
+            Ok(v) => TaskStatus::Done(v),
+            Err(TryRecvError::Empty) => TaskStatus::Running,
+            Err(TryRecvError::Disconnected) => TaskStatus::Failed,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Loading PDB and mmCIF files in a thread, so the UI doesn't block while parsing them, and setting
+//! up bonds etc. The molecule is committed to `State` once ready; until then, the previous one
+//! stays open. The UI shows the loading thread's progress, and can cancel it.
+//!
+//! Very large structures, e.g. multi-million-atom cryo-EM assemblies, are loaded progressively.
+//! While parsing, we display a Cα trace from a quick scan of the file. Once the molecule is ready,
+//! we draw it in stages over consecutive frames: backbone, then sidechains, then hetero atoms and
+//! waters.
+
+use std::{
+    fs::File,
+    io,
+    io::{BufRead, BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use lin_alg::f64::Vec3;
+use pdbtbx::PDB;
+
+use crate::{
+    file_io::cif_pdb::load_cif_pdb,
+    molecule::{Atom, AtomRole, Molecule},
+    progress::{Progress, Task},
+};
+
+/// Bytes. PDB and mmCIF files at least this large are loaded progressively, with a preview.
+pub const PROGRESSIVE_LOAD_MIN_SIZE: u64 = 20_000_000;
+
+/// From the background thread.
+pub struct LoadedStructure {
+    pub pdb: PDB,
+    pub mol: Molecule,
+    pub raw: String,
+}
+
+pub struct PendingLoad {
+    pub path: PathBuf,
+    pub task: Task<io::Result<LoadedStructure>>,
+    pub start: Instant,
+}
+
+/// Which atoms to draw, while drawing a newly-loaded large molecule in stages. `None` once
+/// complete.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoadStage {
+    Backbone,
+    Sidechains,
+}
+
+impl LoadStage {
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Backbone => Some(Self::Sidechains),
+            Self::Sidechains => None,
+        }
+    }
+
+    pub fn shows(self, atom: &Atom) -> bool {
+        if atom.hetero {
+            return false;
+        }
+        match self {
+            Self::Backbone => {
+                !matches!(atom.role, Some(AtomRole::Sidechain | AtomRole::H_Sidechain))
+            }
+            Self::Sidechains => true,
+        }
+    }
+}
+
+fn parse_coord(s: &str) -> Option<f64> {
+    s.trim().parse().ok()
+}
+
+/// Cα positions by chain, from a fast line scan of a PDB or mmCIF file; no full parse. Breaks in
+/// the order of atoms, e.g. between chains, start a new segment.
+pub fn ca_trace(path: &Path) -> io::Result<Vec<Vec<Vec3>>> {
+    let reader = BufReader::new(File::open(path)?);
+    let cif = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("cif"));
+
+    let mut result: Vec<Vec<Vec3>> = Vec::new();
+    let mut chain_prev = String::new();
+
+    // mmCIF: Column indices in the `atom_site` loop, once its header is read.
+    let mut fields: Vec<String> = Vec::new();
+    let mut in_atom_site = false;
+    let mut cols = None;
+
+    for line in reader.lines() {
+        let line = line?;
+
+        let (chain, posit) = if cif {
+            if line.starts_with("_atom_site.") {
+                in_atom_site = true;
+                fields.push(line["_atom_site.".len()..].trim().to_owned());
+                continue;
+            }
+            if !in_atom_site {
+                continue;
+            }
+            if line.starts_with('#') || line.starts_with("loop_") {
+                if cols.is_some() {
+                    break;
+                }
+                continue;
+            }
+
+            let (name_i, chain_i, x_i) = *cols.get_or_insert_with(|| {
+                let i = |name: &str| fields.iter().position(|f| f == name);
+                (
+                    i("label_atom_id").or(i("auth_atom_id")),
+                    i("auth_asym_id").or(i("label_asym_id")),
+                    i("Cartn_x"),
+                )
+            });
+            let (Some(name_i), Some(chain_i), Some(x_i)) = (name_i, chain_i, x_i) else {
+                break;
+            };
+
+            let vals: Vec<_> = line.split_whitespace().collect();
+            if vals.get(name_i) != Some(&"CA") || vals.len() < x_i + 3 {
+                continue;
+            }
+            let (Some(x), Some(y), Some(z)) = (
+                parse_coord(vals[x_i]),
+                parse_coord(vals[x_i + 1]),
+                parse_coord(vals[x_i + 2]),
+            ) else {
+                continue;
+            };
+            (vals[chain_i].to_owned(), Vec3::new(x, y, z))
+        } else {
+            if !line.starts_with("ATOM") || line.len() < 54 || line[12..16].trim() != "CA" {
+                continue;
+            }
+            let (Some(x), Some(y), Some(z)) = (
+                parse_coord(&line[30..38]),
+                parse_coord(&line[38..46]),
+                parse_coord(&line[46..54]),
+            ) else {
+                continue;
+            };
+            (line[21..22].to_owned(), Vec3::new(x, y, z))
+        };
+
+        if chain != chain_prev || result.is_empty() {
+            result.push(Vec::new());
+            chain_prev = chain;
+        }
+        result.last_mut().unwrap().push(posit);
+    }
+
+    Ok(result)
+}
+
+/// Returns an `Interrupted` error if `progress` has been cancelled.
+fn check_cancelled(progress: &Progress) -> io::Result<()> {
+    if progress.is_cancelled() {
+        return Err(io::Error::new(ErrorKind::Interrupted, "Loading cancelled"));
+    }
+    Ok(())
+}
+
+/// Parse the file, and build the molecule, in a new thread. Poll the result from the UI loop.
+pub fn start_load(path: &Path) -> PendingLoad {
+    let path_ = path.to_owned();
+
+    let task = Task::spawn(move |progress| -> io::Result<LoadedStructure> {
+        progress.set_total(3);
+
+        progress.set_stage("Parsing");
+        let pdb = load_cif_pdb(&path_)?;
+        progress.inc();
+        check_cancelled(progress)?;
+
+        progress.set_stage("Building the molecule");
+        let mut file = File::open(&path_)?;
+        let mol = Molecule::from_cif_pdb(&pdb, &file)?;
+        progress.inc();
+        check_cancelled(progress)?;
+
+        progress.set_stage("Reading the file text");
+        let mut raw = String::new();
+        file.read_to_string(&mut raw)?;
+        progress.inc();
+
+        Ok(LoadedStructure { pdb, mol, raw })
+    });
+
+    PendingLoad {
+        path: path.to_owned(),
+        task,
+        start: Instant::now(),
+    }
+}
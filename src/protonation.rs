@@ -0,0 +1,621 @@
+//! pH-dependent protonation states of titratable residues: Asp, Glu, His, Lys, Cys, and Tyr. We
+//! estimate each group's pKa empirically, similar to PROPKA: its model pKa in water, shifted by
+//! desolvation, hydrogen bonds, and Coulomb interactions with other charged groups. Then, at a
+//! given pH, we rebuild each group's hydrogens, and record its Amber residue variant (e.g. ASH,
+//! HIE), so force field types and charges match the state.
+//!
+//! Coulomb terms use each group's average charge at the pH, so estimates depend on it slightly.
+//! Expect errors around 1 pH unit; more for buried, or strongly coupled groups. Termini aren't
+//! handled, and Arg is treated as always protonated.
+
+use std::f64::consts::{PI, TAU};
+
+use bio_files::ResidueType;
+use lin_alg::f64::Vec3;
+use log::info;
+use na_seq::{AminoAcid, AminoAcidProtenationVariant, AtomTypeInRes, Element::*};
+
+use crate::{
+    aa_coords::{
+        bond_vecs::{TETRA_A, residue_geometry},
+        flips::optimize_h_bond_network,
+    },
+    add_hydrogens::{align_template, len_h},
+    bond_inference::HBondConfig,
+    docking::prep::DockType,
+    molecule::{Atom, AtomRole, Bond, BondCount, BondType, Molecule, PropVal},
+    units::COULOMB_CONST,
+};
+
+pub const PH_DEFAULT: f32 = 7.;
+/// The residue property estimated pKas are stored under, e.g. for coloring.
+pub const PROP_PKA: &str = "pka";
+
+/// Å. Heavy atoms within this of a group count towards its burial.
+const BURIAL_DIST: f64 = 10.;
+/// Heavy atom counts within `BURIAL_DIST` of a fully exposed, and a fully buried group.
+const BURIAL_COUNT_MIN: f64 = 100.;
+const BURIAL_COUNT_MAX: f64 = 200.;
+/// pH units. Shift of a fully buried group, favoring its neutral form.
+const DESOLV_SHIFT_MAX: f64 = 2.5;
+/// Å. Hydrogen bond partners within the first distance shift a group's pKa fully; the shift falls
+/// linearly to 0 at the second.
+const HB_DIST: (f64, f64) = (3.0, 4.0);
+/// pH units, per partner.
+const HB_SHIFT: f64 = 0.8;
+const HB_SHIFT_MAX: f64 = 2.4;
+/// Å
+const COULOMB_CUTOFF: f64 = 10.;
+/// Å. Closer pairs are treated as this far apart.
+const COULOMB_DIST_MIN: f64 = 3.;
+/// Dielectric constants for pairs of exposed, and buried groups.
+const EPS_EXPOSED: f64 = 80.;
+const EPS_BURIED: f64 = 20.;
+/// kcal/mol per pH unit at 298K; RT ln 10.
+const KCAL_PER_PH: f64 = 1.364;
+const MAX_ITERS: usize = 20;
+/// pH units. Iteration stops once no pKa changes by more than this.
+const CONVERGENCE: f64 = 0.01;
+/// Å. Cys SG atoms closer than this are in a disulfide bond, and not titratable.
+const DISULFIDE_DIST: f64 = 2.5;
+
+/// Radians. Bond angles to the hydrogens we add.
+const ANGLE_C_O_H: f64 = 110. * PI / 180.;
+const ANGLE_C_S_H: f64 = 96. * PI / 180.;
+
+/// Model pKa in water, and the charge of the ionized form.
+fn model_pka(aa: AminoAcid) -> Option<(f64, f64)> {
+    match aa {
+        AminoAcid::Asp => Some((3.8, -1.)),
+        AminoAcid::Glu => Some((4.5, -1.)),
+        AminoAcid::His => Some((6.5, 1.)),
+        AminoAcid::Cys => Some((9.0, -1.)),
+        AminoAcid::Tyr => Some((10.0, -1.)),
+        AminoAcid::Lys => Some((10.5, 1.)),
+        // Not titrated; see `Group::titratable`.
+        AminoAcid::Arg => Some((12.5, 1.)),
+        _ => None,
+    }
+}
+
+/// Names of the atoms that carry the group's charge.
+fn charge_atoms(aa: AminoAcid) -> &'static [&'static str] {
+    match aa {
+        AminoAcid::Asp => &["OD1", "OD2"],
+        AminoAcid::Glu => &["OE1", "OE2"],
+        AminoAcid::His => &["ND1", "NE2"],
+        AminoAcid::Cys => &["SG"],
+        AminoAcid::Tyr => &["OH"],
+        AminoAcid::Lys => &["NZ"],
+        AminoAcid::Arg => &["NE", "NH1", "NH2"],
+        _ => &[],
+    }
+}
+
+/// The average charge of a group at a pH.
+fn charge_at(pka: f64, z: f64, ph: f64) -> f64 {
+    if z < 0. {
+        z / (1. + 10_f64.powf(pka - ph))
+    } else {
+        z / (1. + 10_f64.powf(ph - pka))
+    }
+}
+
+/// A ramp from 1 at `HB_DIST.0` to 0 at `HB_DIST.1`.
+fn h_bond_factor(dist: f64) -> f64 {
+    ((HB_DIST.1 - dist) / (HB_DIST.1 - HB_DIST.0)).clamp(0., 1.)
+}
+
+#[derive(Clone, Debug)]
+pub struct PkaSite {
+    /// Residue index.
+    pub res: usize,
+    pub aa: AminoAcid,
+    pub pka_model: f32,
+    pub pka: f32,
+    /// At the pH states were assigned at. For acids, this is the neutral form; for bases, the
+    /// charged one.
+    pub protonated: bool,
+}
+
+impl PkaSite {
+    /// E.g. "Asp 25: 3.1 (model 3.8), deprotonated".
+    pub fn descrip(&self, mol: &Molecule) -> String {
+        let sn = mol
+            .residues
+            .get(self.res)
+            .map(|r| r.serial_number)
+            .unwrap_or_default();
+        let state = if self.protonated {
+            "protonated"
+        } else {
+            "deprotonated"
+        };
+
+        format!(
+            "{} {sn}: {:.1} (model {:.1}), {state}",
+            self.aa, self.pka, self.pka_model
+        )
+    }
+}
+
+/// The result of assigning protonation states.
+#[derive(Clone, Debug)]
+pub struct Protonation {
+    pub ph: f32,
+    pub sites: Vec<PkaSite>,
+}
+
+struct Group {
+    res: usize,
+    aa: AminoAcid,
+    /// Atom indices.
+    atoms: Vec<usize>,
+    /// The atoms' centroid.
+    center: Vec3,
+    pka_model: f64,
+    /// Charge of the ionized form.
+    z: f64,
+    /// 0 for fully exposed, to 1 for fully buried.
+    burial: f64,
+    /// Arg is included for its charge only.
+    titratable: bool,
+}
+
+fn find_groups(mol: &Molecule) -> Vec<Group> {
+    let heavy: Vec<Vec3> = mol
+        .atoms
+        .iter()
+        .filter(|a| a.element != Hydrogen && a.role != Some(AtomRole::Water))
+        .map(|a| a.posit)
+        .collect();
+
+    let sg_posits: Vec<Vec3> = mol
+        .residues
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| matches!(r.res_type, ResidueType::AminoAcid(AminoAcid::Cys)))
+        .filter_map(|(i, _)| mol.atom_in_res(i, "SG"))
+        .map(|i| mol.atoms[i].posit)
+        .collect();
+
+    let mut result = Vec::new();
+
+    for (res_i, res) in mol.residues.iter().enumerate() {
+        let ResidueType::AminoAcid(aa) = res.res_type else {
+            continue;
+        };
+        let Some((pka_model, z)) = model_pka(aa) else {
+            continue;
+        };
+
+        let Some(atoms) = charge_atoms(aa)
+            .iter()
+            .map(|name| mol.atom_in_res(res_i, name))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+
+        let center = atoms
+            .iter()
+            .fold(Vec3::new_zero(), |acc, i| acc + mol.atoms[*i].posit)
+            / atoms.len() as f64;
+
+        if aa == AminoAcid::Cys {
+            let bonded = sg_posits.iter().any(|p| {
+                let dist = (*p - center).magnitude();
+                dist > 0.01 && dist < DISULFIDE_DIST
+            });
+            if bonded {
+                continue;
+            }
+        }
+
+        let count = heavy
+            .iter()
+            .filter(|p| (**p - center).magnitude() < BURIAL_DIST)
+            .count() as f64;
+        let burial =
+            ((count - BURIAL_COUNT_MIN) / (BURIAL_COUNT_MAX - BURIAL_COUNT_MIN)).clamp(0., 1.);
+
+        result.push(Group {
+            res: res_i,
+            aa,
+            atoms,
+            center,
+            pka_model,
+            z,
+            burial,
+            titratable: aa != AminoAcid::Arg,
+        });
+    }
+
+    result
+}
+
+/// pKa shift from hydrogen bonds to polar atoms of other residues: Donors stabilize an anion, and
+/// acceptors a cation. Charged groups' atoms are left to the Coulomb term.
+fn h_bond_shift(mol: &Molecule, group: &Group, charged_atoms: &[bool]) -> f64 {
+    let res_atoms = &mol.residues[group.res].atoms;
+
+    let has_h = |i: usize| {
+        mol.adjacency_list
+            .get(i)
+            .is_some_and(|adj| adj.iter().any(|j| mol.atoms[*j].element == Hydrogen))
+    };
+
+    let mut result = 0.;
+    for (i, atom) in mol.atoms.iter().enumerate() {
+        if !matches!(atom.element, Nitrogen | Oxygen)
+            || atom.role == Some(AtomRole::Water)
+            || charged_atoms[i]
+            || res_atoms.contains(&i)
+            || (atom.posit - group.center).magnitude() > HB_DIST.1 + 2.
+        {
+            continue;
+        }
+
+        let partner = if group.z < 0. {
+            has_h(i)
+        } else {
+            atom.element == Oxygen
+        };
+        if !partner {
+            continue;
+        }
+
+        let dist = group
+            .atoms
+            .iter()
+            .map(|j| (mol.atoms[*j].posit - atom.posit).magnitude())
+            .fold(f64::MAX, f64::min);
+
+        result += HB_SHIFT * h_bond_factor(dist);
+    }
+
+    // Negative for acids, and positive for bases.
+    result.min(HB_SHIFT_MAX) * group.z.signum()
+}
+
+/// Estimate pKas of titratable residues, and whether each is protonated at the given pH.
+pub fn estimate_pkas(mol: &Molecule, ph: f32) -> Vec<PkaSite> {
+    let ph = ph as f64;
+    let groups = find_groups(mol);
+
+    let mut charged_atoms = vec![false; mol.atoms.len()];
+    for g in &groups {
+        for i in &g.atoms {
+            charged_atoms[*i] = true;
+        }
+    }
+
+    // Terms that don't depend on other groups' states.
+    let pka_intrinsic: Vec<f64> = groups
+        .iter()
+        .map(|g| {
+            let desolv = -g.z * DESOLV_SHIFT_MAX * g.burial;
+            g.pka_model + desolv + h_bond_shift(mol, g, &charged_atoms)
+        })
+        .collect();
+
+    // Add Coulomb interactions, using each group's average charge at the pH; iterate to
+    // self-consistency.
+    let mut pkas = pka_intrinsic.clone();
+    for _ in 0..MAX_ITERS {
+        let charges: Vec<f64> = groups
+            .iter()
+            .zip(&pkas)
+            .map(|(g, pka)| {
+                if g.titratable {
+                    charge_at(*pka, g.z, ph)
+                } else {
+                    g.z
+                }
+            })
+            .collect();
+
+        let mut change_max: f64 = 0.;
+
+        for (i, g) in groups.iter().enumerate() {
+            if !g.titratable {
+                continue;
+            }
+
+            let mut coulomb = 0.;
+            for (j, other) in groups.iter().enumerate() {
+                if other.res == g.res {
+                    continue;
+                }
+                let dist = (other.center - g.center).magnitude();
+                if dist > COULOMB_CUTOFF {
+                    continue;
+                }
+
+                let burial = (g.burial + other.burial) / 2.;
+                let eps = EPS_EXPOSED - (EPS_EXPOSED - EPS_BURIED) * burial;
+                // Energy of the ionized form, from the other group.
+                let e = COULOMB_CONST * g.z * charges[j] / (eps * dist.max(COULOMB_DIST_MIN));
+                // Stabilizing the ionized form lowers an acid's pKa, and raises a base's.
+                coulomb += -g.z * e / KCAL_PER_PH;
+            }
+
+            let pka = pka_intrinsic[i] + coulomb;
+            change_max = change_max.max((pka - pkas[i]).abs());
+            pkas[i] = pka;
+        }
+
+        if change_max < CONVERGENCE {
+            break;
+        }
+    }
+
+    groups
+        .iter()
+        .zip(&pkas)
+        .filter(|(g, _)| g.titratable)
+        .map(|(g, pka)| PkaSite {
+            res: g.res,
+            aa: g.aa,
+            pka_model: g.pka_model as f32,
+            pka: *pka as f32,
+            protonated: ph < *pka,
+        })
+        .collect()
+}
+
+/// Position of a hydrogen on `parent`, which is bonded to `neighbor`. The hydrogen is in the plane of
+/// the three atoms: on the same side of the bond as `reference` if `syn`, or the opposite side.
+fn h_in_plane(
+    parent: Vec3,
+    neighbor: Vec3,
+    reference: Vec3,
+    bond_angle: f64,
+    syn: bool,
+    len: f64,
+) -> Vec3 {
+    let axis = (parent - neighbor).to_normalized();
+    let to_ref = reference - neighbor;
+    let mut perp = (to_ref - axis * to_ref.dot(axis)).to_normalized();
+    if !syn {
+        perp = -perp;
+    }
+
+    let angle = PI - bond_angle;
+    parent + (axis * angle.cos() + perp * angle.sin()) * len
+}
+
+/// Position of a hydrogen on an aromatic ring N, bisecting the exterior angle.
+fn h_on_ring_n(n: Vec3, neighbor_0: Vec3, neighbor_1: Vec3) -> Vec3 {
+    let dir = (n - neighbor_0).to_normalized() + (n - neighbor_1).to_normalized();
+    n + dir.to_normalized() * len_h(Nitrogen)
+}
+
+/// Hydrogens for a group in a given state: (parent atom index, name, posit). Also returns the
+/// Amber variant, if not the standard residue.
+fn group_hydrogens(
+    mol: &Molecule,
+    site: &PkaSite,
+) -> Option<(
+    Vec<(usize, &'static str, Vec3)>,
+    Option<AminoAcidProtenationVariant>,
+)> {
+    let atom = |name: &str| mol.atom_in_res(site.res, name);
+    let posit = |i: usize| mol.atoms[i].posit;
+
+    let result = match (site.aa, site.protonated) {
+        (AminoAcid::Asp, true) => {
+            let (o1, o2, c) = (atom("OD1")?, atom("OD2")?, atom("CG")?);
+            let h = h_in_plane(
+                posit(o2),
+                posit(c),
+                posit(o1),
+                ANGLE_C_O_H,
+                true,
+                len_h(Oxygen),
+            );
+            (vec![(o2, "HD2", h)], Some(AminoAcidProtenationVariant::Ash))
+        }
+        (AminoAcid::Glu, true) => {
+            let (o1, o2, c) = (atom("OE1")?, atom("OE2")?, atom("CD")?);
+            let h = h_in_plane(
+                posit(o2),
+                posit(c),
+                posit(o1),
+                ANGLE_C_O_H,
+                true,
+                len_h(Oxygen),
+            );
+            (vec![(o2, "HE2", h)], Some(AminoAcidProtenationVariant::Glh))
+        }
+        (AminoAcid::Asp | AminoAcid::Glu, false) => (Vec::new(), None),
+        (AminoAcid::His, _) => {
+            let (nd1, ne2) = (atom("ND1")?, atom("NE2")?);
+            let (cg, ce1, cd2) = (atom("CG")?, atom("CE1")?, atom("CD2")?);
+            let hd1 = (nd1, "HD1", h_on_ring_n(posit(nd1), posit(cg), posit(ce1)));
+            let he2 = (ne2, "HE2", h_on_ring_n(posit(ne2), posit(cd2), posit(ce1)));
+
+            // Neutral His gets both hydrogens for now. `optimize_h_bond_network` chooses between
+            // HID and HIE, and removes the other.
+            let variant = if site.protonated {
+                AminoAcidProtenationVariant::Hip
+            } else {
+                AminoAcidProtenationVariant::Hie
+            };
+            (vec![hd1, he2], Some(variant))
+        }
+        (AminoAcid::Lys, _) => {
+            let (nz, ce, cd) = (atom("NZ")?, atom("CE")?, atom("CD")?);
+            let bond = (posit(ce) - posit(nz)).to_normalized();
+            let bond_back = (posit(ce) - posit(cd)).to_normalized();
+            // Staggered.
+            let geom = residue_geometry();
+            let rotator = align_template(TETRA_A, geom.tetra_b, bond, bond_back, TAU / 6.);
+            let dirs = [geom.tetra_b, geom.tetra_c, geom.tetra_d];
+            let h = |i: usize| posit(nz) + rotator.rotate_vec(dirs[i]) * len_h(Nitrogen);
+
+            if site.protonated {
+                (
+                    vec![(nz, "HZ1", h(0)), (nz, "HZ2", h(1)), (nz, "HZ3", h(2))],
+                    None,
+                )
+            } else {
+                // Amber's LYN template names these HZ2 and HZ3.
+                (
+                    vec![(nz, "HZ2", h(0)), (nz, "HZ3", h(1))],
+                    Some(AminoAcidProtenationVariant::Lyn),
+                )
+            }
+        }
+        (AminoAcid::Cys, true) => {
+            let (sg, cb, ca) = (atom("SG")?, atom("CB")?, atom("CA")?);
+            let h = h_in_plane(
+                posit(sg),
+                posit(cb),
+                posit(ca),
+                ANGLE_C_S_H,
+                false,
+                len_h(Sulfur),
+            );
+            (vec![(sg, "HG", h)], None)
+        }
+        (AminoAcid::Cys, false) => (Vec::new(), Some(AminoAcidProtenationVariant::Cym)),
+        (AminoAcid::Tyr, true) => {
+            let (oh, cz, ce1) = (atom("OH")?, atom("CZ")?, atom("CE1")?);
+            let h = h_in_plane(
+                posit(oh),
+                posit(cz),
+                posit(ce1),
+                ANGLE_C_O_H,
+                true,
+                len_h(Oxygen),
+            );
+            (vec![(oh, "HH", h)], None)
+        }
+        // Amber has no tyrosinate template; its charges are Tyr's, less HH's.
+        (AminoAcid::Tyr, false) => (Vec::new(), None),
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+impl Molecule {
+    /// Estimate pKas of titratable residues, and set their protonation states for the given pH:
+    /// Their hydrogens are rebuilt, and each residue's Amber variant is set, for force field
+    /// assignment. Atom indices change; anything holding them, e.g. selections, should be reset.
+    pub fn assign_protonation(&mut self, ph: f32, h_bond_cfg: &HBondConfig) {
+        if self.is_compact() || self.residues.is_empty() {
+            return;
+        }
+
+        let sites = estimate_pkas(self, ph);
+
+        let mut remove = Vec::new();
+        // (Parent atom index, name, posit)
+        let mut added = Vec::new();
+
+        for site in &sites {
+            let Some((hydrogens, variant)) = group_hydrogens(self, site) else {
+                continue;
+            };
+
+            for name in charge_atoms(site.aa) {
+                if let Some(i) = self.atom_in_res(site.res, name) {
+                    remove.extend(
+                        self.adjacency_list[i]
+                            .iter()
+                            .filter(|j| self.atoms[**j].element == Hydrogen),
+                    );
+                }
+            }
+            added.extend(hydrogens);
+
+            let res = &mut self.residues[site.res];
+            res.variant = variant;
+            res.props
+                .insert(PROP_PKA.to_owned(), PropVal::Float(site.pka));
+        }
+
+        let map = self.remove_atoms(&remove);
+
+        let mut sn = self
+            .atoms
+            .iter()
+            .map(|a| a.serial_number)
+            .max()
+            .unwrap_or(0);
+
+        for (parent, name, posit) in added {
+            let Some(parent) = map[parent] else {
+                continue;
+            };
+            let res_i = self.atoms[parent].residue;
+            sn += 1;
+
+            let h_i = self.atoms.len();
+            self.atoms.push(Atom {
+                serial_number: sn,
+                posit,
+                element: Hydrogen,
+                type_in_res: Some(AtomTypeInRes::H(name.to_owned())),
+                force_field_type: None,
+                dock_type: None,
+                role: Some(AtomRole::H_Sidechain),
+                residue: res_i,
+                hetero: false,
+                occupancy: None,
+                partial_charge: None,
+                temperature_factor: None,
+                alt_loc: None,
+                anisou: None,
+                props: Default::default(),
+                in_ring: false,
+                aromatic: false,
+            });
+
+            if let Some(res) = res_i.and_then(|r| self.residues.get_mut(r)) {
+                res.atoms.push(h_i);
+            }
+
+            self.bonds.push(Bond {
+                bond_type: BondType::Covalent {
+                    count: BondCount::Single,
+                },
+                atom_0: parent,
+                atom_1: h_i,
+                is_backbone: false,
+                in_ring: false,
+                aromatic: false,
+            });
+        }
+
+        self.adjacency_list = self.build_adjacency_list();
+
+        // Choose neutral His tautomers, and re-optimize the network around the changed groups.
+        for flip in optimize_h_bond_network(self) {
+            self.flips.retain(|f| f.res != flip.res);
+            self.flips.push(flip);
+        }
+
+        // todo: Don't like this clone.
+        let atoms_clone = self.atoms.clone();
+        for atom in &mut self.atoms {
+            atom.dock_type = Some(DockType::infer(atom, &self.bonds, &atoms_clone));
+        }
+
+        self.update_h_bonds(h_bond_cfg);
+        self.infer_noncovalent();
+
+        let charged = sites
+            .iter()
+            .filter(|s| s.protonated == model_pka(s.aa).is_some_and(|(_, z)| z > 0.))
+            .count();
+        info!(
+            "Assigned protonation states at pH {ph:.1}: {} titratable residues, {charged} charged",
+            sites.len()
+        );
+
+        self.protonation = Some(Protonation { ph, sites });
+    }
+}
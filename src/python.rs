@@ -0,0 +1,341 @@
+//! Python bindings, so users can script batch analyses, similar to PyMol's API. A `Session` holds
+//! a headless viewer state: a receptor, ligand, selections, docking setup, and MD run. Anything
+//! the command line supports is available through `Session.cmd`, e.g. `s.cmd("show surface, pocket")`;
+//! common operations also have typed methods returning plain Python values.
+//!
+//! Requires the `python` feature. Run a script with the module available using
+//! `daedalus script <file.py>`:
+//!
+//! ```python
+//! import daedalus
+//!
+//! s = daedalus.Session()
+//! s.load("1c8k.cif")
+//! s.load("ligand.sdf")
+//! s.cmd("select pocket, resi 45")
+//! s.dock()
+//! print(s.run_md(5_000))
+//! for time, rmsd, rg in s.trajectory():
+//!     print(time, rmsd, rg)
+//!
+//! mol = s.molecule()
+//! print(mol.ident, len(mol), mol.sel_atoms(s.selection()))
+//! md = s.md()
+//! print(md.time, md.snapshot_count())
+//! ```
+//!
+//! `Molecule` and `MdState` are copies, as of when they were taken; changing the session doesn't
+//! change them. To import the module from an existing Python environment, build it with
+//! `maturin develop`; see `pyproject.toml`.
+
+use std::{ffi::CString, fs, path::Path};
+
+use graphics::{EngineUpdates, Scene};
+use lin_alg::f64::Vec3;
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    ComputationDevice, Selection, State,
+    analysis::trajectory::analyze_trajectory,
+    cli::run_cmd,
+    docking::dock_ligand,
+    dynamics::{SnapshotDynamics, protein::run_protein_md, report::EnergyReport},
+    error::DaedalusError,
+    molecule::Molecule,
+    new_state,
+    util::load_atom_coords_rcsb,
+};
+
+type Posit = (f64, f64, f64);
+
+fn to_py_err(e: DaedalusError) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+fn to_posits(posits: impl Iterator<Item = Vec3>) -> Vec<Posit> {
+    posits.map(|p| (p.x, p.y, p.z)).collect()
+}
+
+/// Which atoms, or residue, are selected.
+#[pyclass(name = "Selection")]
+#[derive(Clone)]
+pub struct PySelection {
+    inner: Selection,
+}
+
+#[pymethods]
+impl PySelection {
+    /// Atoms of the molecule, by index.
+    #[staticmethod]
+    fn atoms(indices: Vec<usize>) -> Self {
+        Self {
+            inner: Selection::Atoms(indices),
+        }
+    }
+
+    /// A residue of the molecule, by index.
+    #[staticmethod]
+    fn residue(i: usize) -> Self {
+        Self {
+            inner: Selection::Residue(i),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+/// A copy of a molecule. Positions are in Å.
+#[pyclass(name = "Molecule", unsendable)]
+pub struct PyMolecule {
+    inner: Molecule,
+}
+
+#[pymethods]
+impl PyMolecule {
+    #[getter]
+    fn ident(&self) -> String {
+        self.inner.ident.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.atom_count()
+    }
+
+    fn atom_posits(&self) -> Vec<Posit> {
+        to_posits(self.inner.atom_posits().into_iter())
+    }
+
+    /// E.g. "C", or "Fe".
+    fn elements(&self) -> Vec<String> {
+        (0..self.inner.atom_count())
+            .map(|i| self.inner.atom(i).element.to_letter())
+            .collect()
+    }
+
+    /// `None` for atoms without one assigned.
+    fn partial_charges(&self) -> Vec<Option<f32>> {
+        (0..self.inner.atom_count())
+            .map(|i| self.inner.atom(i).partial_charge)
+            .collect()
+    }
+
+    /// Covalent and other bonds, as pairs of atom indices.
+    fn bonds(&self) -> Vec<(usize, usize)> {
+        self.inner
+            .bonds
+            .iter()
+            .map(|b| (b.atom_0, b.atom_1))
+            .collect()
+    }
+
+    /// Indices of the atoms in `selection`.
+    fn sel_atoms(&self, selection: PySelection) -> Vec<usize> {
+        self.inner.sel_atom_indices(&selection.inner)
+    }
+}
+
+/// A copy of an MD run's results: its snapshots, and energy reports.
+#[pyclass(name = "MdState")]
+pub struct PyMdState {
+    /// fs
+    #[pyo3(get)]
+    time: f64,
+    #[pyo3(get)]
+    step_count: usize,
+    snapshots: Vec<SnapshotDynamics>,
+    reports: Vec<EnergyReport>,
+}
+
+#[pymethods]
+impl PyMdState {
+    fn snapshot_count(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Positions of the simulated atoms in a snapshot.
+    fn snapshot(&self, i: usize) -> PyResult<Vec<Posit>> {
+        let snap = self
+            .snapshots
+            .get(i)
+            .ok_or_else(|| PyValueError::new_err("No MD snapshot at this index"))?;
+        Ok(to_posits(snap.atom_posits.iter().copied()))
+    }
+
+    /// (time in fs, kinetic and potential energy in kcal/mol, temperature in K) for each energy
+    /// report.
+    fn energies(&self) -> Vec<(f64, f64, f64, f64)> {
+        self.reports
+            .iter()
+            .map(|r| (r.time, r.kinetic, r.potential, r.temperature))
+            .collect()
+    }
+}
+
+#[pyclass(unsendable)]
+pub struct Session {
+    state: State,
+    /// Drawing commands update this, as they would the window's.
+    scene: Scene,
+    engine_updates: EngineUpdates,
+}
+
+impl Session {
+    fn no_molecule() -> PyErr {
+        PyValueError::new_err("No molecule is loaded")
+    }
+}
+
+#[pymethods]
+impl Session {
+    #[new]
+    fn new() -> Self {
+        let mut state = new_state(ComputationDevice::Cpu);
+        state.load_aa_charges_ff();
+
+        Self {
+            state,
+            scene: Scene::default(),
+            engine_updates: EngineUpdates::default(),
+        }
+    }
+
+    /// Open a molecule, ligand, map, or parameter file.
+    fn load(&mut self, path: &str) -> PyResult<()> {
+        self.state.open(Path::new(path))?;
+        Ok(())
+    }
+
+    /// Download a structure from RCSB PDB, by its identifier.
+    fn fetch(&mut self, ident: &str) {
+        load_atom_coords_rcsb(
+            ident,
+            &mut self.state,
+            &mut self.scene,
+            &mut self.engine_updates,
+            &mut false,
+            &mut false,
+        );
+    }
+
+    fn save(&mut self, path: &str) -> PyResult<()> {
+        self.state.save(Path::new(path), &self.scene.camera)?;
+        Ok(())
+    }
+
+    /// Run a command, as from the command line. Returns its output.
+    fn cmd(&mut self, command: &str) -> PyResult<String> {
+        Ok(run_cmd(
+            command.trim(),
+            &mut self.state,
+            &mut self.scene,
+            &mut self.engine_updates,
+            &mut false,
+            &mut false,
+        )?)
+    }
+
+    fn molecule(&self) -> PyResult<PyMolecule> {
+        let mol = self.state.molecule.as_ref().ok_or_else(Self::no_molecule)?;
+        Ok(PyMolecule { inner: mol.clone() })
+    }
+
+    /// The ligand, with atoms at its current pose.
+    fn ligand(&self) -> PyResult<PyMolecule> {
+        let lig = self
+            .state
+            .ligand
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("No ligand is loaded"))?;
+
+        let mut inner = lig.molecule.clone();
+        inner.expand();
+        for (atom, posit) in inner.atoms.iter_mut().zip(&lig.atom_posits) {
+            atom.posit = *posit;
+        }
+        Ok(PyMolecule { inner })
+    }
+
+    fn selection(&self) -> PySelection {
+        PySelection {
+            inner: self.state.ui.selection.clone(),
+        }
+    }
+
+    fn select(&mut self, selection: PySelection) {
+        self.state.ui.selection = selection.inner;
+    }
+
+    /// Save the current selection under a name, for use in commands.
+    fn save_selection(&mut self, name: &str) {
+        let selection = self.state.ui.selection.clone();
+        self.state.save_named_selection(name, selection);
+    }
+
+    /// Find the ligand's optimal pose in the docking site.
+    fn dock(&mut self) -> PyResult<()> {
+        dock_ligand(&mut self.state, &mut self.scene, &mut self.engine_updates).map_err(to_py_err)
+    }
+
+    /// Run MD on the protein, with the current MD settings. Returns a summary.
+    fn run_md(&mut self, steps: usize) -> PyResult<String> {
+        self.state.ui.protein_md_steps = steps;
+        run_protein_md(&mut self.state, &mut self.scene, &mut self.engine_updates)
+            .map_err(to_py_err)
+    }
+
+    fn md(&self) -> PyResult<PyMdState> {
+        let md = self
+            .state
+            .mol_dynamics
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("No MD run"))?;
+
+        Ok(PyMdState {
+            time: md.time,
+            step_count: md.step_count,
+            snapshots: md.snapshots.clone(),
+            reports: md.reports.clone(),
+        })
+    }
+
+    /// (time in fs, RMSD in Å, radius of gyration in Å) for each MD snapshot.
+    fn trajectory(&self) -> PyResult<Vec<Posit>> {
+        let mol = self.state.molecule.as_ref().ok_or_else(Self::no_molecule)?;
+        let md = self
+            .state
+            .mol_dynamics
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("No MD run"))?;
+
+        let lig = self.state.ligand.as_ref().map(|l| &l.molecule);
+        let analysis = analyze_trajectory(mol, lig, md).unwrap_or_default();
+
+        Ok(analysis
+            .frames
+            .iter()
+            .map(|f| (f.time, f.rmsd, f.rg))
+            .collect())
+    }
+}
+
+#[pymodule]
+fn daedalus(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Session>()?;
+    m.add_class::<PyMolecule>()?;
+    m.add_class::<PySelection>()?;
+    m.add_class::<PyMdState>()?;
+    Ok(())
+}
+
+/// Run a Python script, with the `daedalus` module available to import.
+pub fn run_script(path: &Path) -> PyResult<()> {
+    let code = CString::new(fs::read_to_string(path)?)
+        .map_err(|_| PyValueError::new_err("The script contains a null byte"))?;
+
+    pyo3::append_to_inittab!(daedalus);
+    pyo3::prepare_freethreaded_python();
+
+    Python::with_gil(|py| py.run(&code, None, None))
+}
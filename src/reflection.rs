@@ -6,6 +6,7 @@ use std::{f64::consts::TAU, time::Instant};
 use bio_apis::{ReqError, rcsb};
 use bio_files::{DensityMap, MapHeader, UnitCell};
 use lin_alg::f64::Vec3;
+use log::{error, info, warn};
 use mcubes::GridPoint;
 use rayon::prelude::*;
 
@@ -42,7 +43,7 @@ impl MapStatus {
             "l" => Some(MapStatus::LowerThanResCutoff),
             "x" => Some(MapStatus::UnreliableMeasurement),
             _ => {
-                eprintln!("Fallthrough on map type: {val}");
+                warn!("Fallthrough on map type: {val}");
                 None
             }
         }
@@ -94,12 +95,12 @@ pub struct ReflectionsData {
 impl ReflectionsData {
     /// Load reflections data from RCSB, then parse. (SF, 2fo_fc, and fo_fc)
     pub fn load_from_rcsb(ident: &str) -> Result<Self, ReqError> {
-        println!("Downloading structure factors and Map data for {ident}...");
+        info!("Downloading structure factors and Map data for {ident}...");
 
         let sf = match rcsb::load_structure_factors_cif(ident) {
             Ok(m) => Some(m),
             Err(_) => {
-                eprintln!("Error loading structure factors CIF");
+                error!("Error loading structure factors CIF");
                 None
             }
         };
@@ -107,7 +108,7 @@ impl ReflectionsData {
         let map_2fo_fc = match rcsb::load_validation_2fo_fc_cif(ident) {
             Ok(m) => Some(m),
             Err(_) => {
-                eprintln!("Error loading 2fo_fc map");
+                error!("Error loading 2fo_fc map");
                 None
             }
         };
@@ -115,12 +116,12 @@ impl ReflectionsData {
         let map_fo_fc = match rcsb::load_validation_fo_fc_cif(ident) {
             Ok(m) => Some(m),
             Err(_) => {
-                eprintln!("Error loading fo_fc map");
+                error!("Error loading fo_fc map");
                 None
             }
         };
 
-        println!("Download complete. Parsing...");
+        info!("Download complete. Parsing...");
         Ok(Self::from_cifs(
             sf.as_deref(),
             map_2fo_fc.as_deref(),
@@ -231,7 +232,7 @@ pub fn compute_density_grid(data: &ReflectionsData) -> Vec<ElectronDensity> {
     let grid = data.regular_fractional_grid(90);
     let unit_cell_vol = data.cell_len_a * data.cell_len_b * data.cell_len_c;
 
-    println!(
+    info!(
         "Computing electron density from refletions onver {} points...",
         grid.len()
     );
@@ -268,7 +269,7 @@ pub fn compute_density_grid(data: &ReflectionsData) -> Vec<ElectronDensity> {
 
     let elapsed = start.elapsed().as_millis();
 
-    println!("Complete. Time: {:?}ms", elapsed);
+    info!("Complete. Time: {:?}ms", elapsed);
     result
 }
 
@@ -3,107 +3,183 @@
 use std::f32::consts::TAU;
 
 use graphics::{
-    Camera, ControlScheme, EngineUpdates, FWD_VEC, GraphicsSettings, InputSettings, LightType,
-    Lighting, Mesh, PointLight, RIGHT_VEC, Scene, ScrollBehavior, UiLayout, UiSettings,
+    Camera, ControlScheme, EngineUpdates, GraphicsSettings, InputSettings, LightType, Lighting,
+    Mesh, PointLight, RIGHT_VEC, Scene, ScrollBehavior, UiLayout, UiSettings,
+};
+use lin_alg::{
+    f32::{Quaternion, Vec3},
+    f64::Vec3 as Vec3F64,
 };
-use lin_alg::f32::{Quaternion, Vec3};
 
 use crate::{
-    State,
-    docking::DockingSite,
+    Selection, State,
+    blink::blink_step,
+    docking::ConformationType,
+    dynamics::interactive::{
+        LIVE_DT, LIVE_STEPS_PER_FRAME, PULL_K_DEFAULT, UserPull, closest_on_ray,
+    },
     inputs,
-    inputs::{MOVEMENT_SENS, RUN_FACTOR, SCROLL_MOVE_AMT, SCROLL_ROTATE_AMT},
+    inputs::{RUN_FACTOR, SCROLL_MOVE_AMT, SCROLL_ROTATE_AMT, cursor_ray},
     mol_drawing,
-    mol_drawing::BOND_RADIUS,
+    mol_drawing::{BOND_RADIUS, draw_ligand, draw_molecule},
+    scene::{
+        BACKGROUND_COLOR, RENDER_DIST_FAR, RENDER_DIST_NEAR, RES_SPOTLIGHT_INTENSITY,
+        RES_SPOTLIGHT_TIME, set_flashlight, set_res_spotlight,
+    },
     ui::ui_handler,
+    util::handle_err,
 };
 
-pub type Color = (f32, f32, f32);
-
 const WINDOW_TITLE: &str = "Daedalus";
 const WINDOW_SIZE_X: f32 = 1_600.;
 const WINDOW_SIZE_Y: f32 = 1_000.;
-pub const BACKGROUND_COLOR: Color = (0., 0., 0.);
-
-pub const RENDER_DIST_NEAR: f32 = 0.2;
-pub const RENDER_DIST_FAR: f32 = 1_000.;
-
-// todo: Shinyness broken?
-pub const ATOM_SHININESS: f32 = 0.9;
-pub const BODY_SHINYNESS: f32 = 0.9;
-
-// Keep this in sync with mesh init.
-pub const MESH_SPHERE_HIGHRES: usize = 0;
-pub const MESH_CUBE: usize = 1;
-pub const MESH_BOND: usize = 2;
-pub const MESH_SPHERE_LOWRES: usize = 3;
-pub const MESH_SPHERE_MEDRES: usize = 4;
-pub const MESH_DOCKING_BOX: usize = 5;
-pub const MESH_SOLVENT_SURFACE: usize = 6; // Van Der Waals surface.
-pub const MESH_DOCKING_SURFACE: usize = 7;
-pub const MESH_DENSITY_SURFACE: usize = 8;
-pub const MESH_SECONDARY_STRUCTURE: usize = 9;
-
-pub const BALL_STICK_RADIUS: f32 = 0.3;
-pub const BALL_STICK_RADIUS_H: f32 = 0.2;
-pub const BALL_RADIUS_WATER: f32 = 0.15;
-
-pub const SHELL_OPACITY: f32 = 0.01;
-
-// From the farthest molecule.
-pub const CAM_INIT_OFFSET: f32 = 10.;
-
-// A higher value will result in a less-dramatic brightness change with distance.
-const FLASHLIGHT_OFFSET: f32 = 10.;
-const FLASHLIGHT_FOV: f32 = TAU / 16.;
-pub const OUTSIDE_LIGHTING_OFFSET: f32 = 900.;
-pub const DOCKING_LIGHT_INTENSITY: f32 = 0.3;
-
-/// Set the flashlight to be a little bit behind the camera; prevents too dramatic of an intensity
-/// scaling on the object looked at, WRT distance.
-pub fn set_flashlight(scene: &mut Scene) {
-    let light = &mut scene.lighting.point_lights[0];
-    light.position = scene.camera.position
-        + scene
-            .camera
-            .orientation
-            .rotate_vec(Vec3::new(0., 0., -FLASHLIGHT_OFFSET));
-
-    // todo: Put back. Problem with di
-    // light.type_ = LightType::Directional {
-    //     direction: scene.camera.orientation.rotate_vec(FWD_VEC),
-    //     fov: FLASHLIGHT_FOV,
-    // };
-}
 
-/// Set lighting based on the center and size of the molecule.
-pub fn set_static_light(scene: &mut Scene, center: Vec3, size: f32) {
-    scene.lighting.point_lights[1].position =
-        center + Vec3::new(40., size + OUTSIDE_LIGHTING_OFFSET, 0.);
-}
+/// This runs each frame. Steps view-transition fades, blinking, live MD, and the residue
+/// spotlight's fade-out.
+fn render_handler(state: &mut State, scene: &mut Scene, dt: f32) -> EngineUpdates {
+    let mut updates = EngineUpdates::default();
 
-/// Set lighting based on the docking location.
-pub fn set_docking_light(scene: &mut Scene, docking_init: Option<&DockingSite>) {
-    let mut light = &mut scene.lighting.point_lights[2];
+    // Animate switching between molecule views.
+    if let Some(transition) = &mut state.volatile.view_transition {
+        if transition.step(&mut scene.entities, dt) {
+            state.volatile.view_transition = None;
+        }
+        updates.entities = true;
+    }
 
-    match docking_init {
-        Some(docking_init) => {
-            let intensity = DOCKING_LIGHT_INTENSITY * docking_init.site_radius as f32;
+    if blink_step(state, scene, dt) {
+        updates.entities = true;
+    }
 
-            light.position = docking_init.site_center.into();
+    if state.ui.md_live && md_live_frame(state, scene) {
+        updates.entities = true;
+    }
+
+    if state.volatile.spotlight_remaining > 0. {
+        state.volatile.spotlight_remaining -= dt;
+
+        if state.volatile.spotlight_remaining <= 0. {
+            set_res_spotlight(scene, None);
+        } else {
+            let light = &mut scene.lighting.point_lights[3];
+            let intensity =
+                RES_SPOTLIGHT_INTENSITY * state.volatile.spotlight_remaining / RES_SPOTLIGHT_TIME;
             light.diffuse_intensity = intensity;
             light.specular_intensity = intensity;
         }
-        None => {
-            light.diffuse_intensity = 0.;
-            light.specular_intensity = 0.;
-        }
+        updates.lighting = true;
     }
+
+    updates
 }
 
-/// This runs each frame. Currently, no updates.
-fn render_handler(_state: &mut State, _scene: &mut Scene, _dt: f32) -> EngineUpdates {
-    EngineUpdates::default()
+/// Mobile atom indices of the selected atoms, for pulling in interactive MD.
+fn pull_atoms(state: &State) -> Vec<usize> {
+    let Some(md) = &state.mol_dynamics else {
+        return Vec::new();
+    };
+
+    let rec_atoms = match &state.ui.selection {
+        Selection::AtomLigand(i) => {
+            return if md.has_ligand() {
+                vec![*i]
+            } else {
+                Vec::new()
+            };
+        }
+        Selection::Atom(i) => vec![*i],
+        Selection::Atoms(atoms) => atoms.clone(),
+        Selection::Residue(i) => match &state.molecule {
+            Some(mol) => mol
+                .residues
+                .get(*i)
+                .map(|r| r.atoms.clone())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        },
+        Selection::None => Vec::new(),
+    };
+
+    md.flexible
+        .iter()
+        .filter(|(_, i_rec)| rec_atoms.contains(i_rec))
+        .map(|(i_md, _)| *i_md)
+        .collect()
+}
+
+/// Interactive MD: run a few steps, pulling the selection toward the cursor if the user is
+/// dragging it, and update the molecules' positions. Returns `true` if they changed.
+fn md_live_frame(state: &mut State, scene: &mut Scene) -> bool {
+    let pull_atoms = if state.ui.md_pull && state.ui.right_click_down {
+        pull_atoms(state)
+    } else {
+        Vec::new()
+    };
+    let ray = cursor_ray(state, scene);
+
+    let Some(md) = &mut state.mol_dynamics else {
+        state.ui.md_live = false;
+        return false;
+    };
+
+    md.user_pull = match ray {
+        Some((origin, toward)) if !pull_atoms.is_empty() => {
+            let center = md.group_centroid(&pull_atoms);
+            let to_f64 = |v: Vec3| Vec3F64::new(v.x as f64, v.y as f64, v.z as f64);
+
+            Some(UserPull {
+                target: closest_on_ray(to_f64(origin), to_f64(toward), center),
+                atoms: pull_atoms,
+                k: PULL_K_DEFAULT,
+            })
+        }
+        _ => None,
+    };
+
+    for _ in 0..LIVE_STEPS_PER_FRAME {
+        md.step(LIVE_DT);
+        if md.blow_up.is_some() {
+            break;
+        }
+    }
+    md.user_pull = None;
+
+    if let Some(blow_up) = &md.blow_up {
+        let descrip = blow_up.descrip();
+        state.ui.md_live = false;
+        handle_err(&mut state.ui, descrip);
+        return false;
+    }
+
+    let has_lig = md.has_ligand();
+    let rec_posits = md.flexible_posits();
+
+    if has_lig {
+        if let Some(lig) = &mut state.ligand {
+            lig.pose.conformation_type = ConformationType::AbsolutePosits;
+            lig.atom_posits = md
+                .atoms
+                .iter()
+                .take(lig.molecule.atoms.len())
+                .map(|a| a.posit)
+                .collect();
+        }
+    }
+
+    if let Some(mol) = &mut state.molecule {
+        for (i, posit) in &rec_posits {
+            mol.atoms[*i].posit = *posit;
+        }
+    }
+
+    if has_lig {
+        draw_ligand(state, scene);
+    }
+    if !rec_posits.is_empty() {
+        draw_molecule(state, scene);
+    }
+
+    true
 }
 
 /// Entry point to our render and event loop.
@@ -123,6 +199,10 @@ pub fn render(mut state: State) {
             Mesh::new_box(1., 1., 1.), // Placeholder for docking site sufrace; populated later.
             Mesh::new_box(1., 1., 1.), // Placeholder for density sufrace; populated later.
             Mesh::new_box(1., 1., 1.), // Placeholder for secondary structure surface; populated later.
+            Mesh::new_box(1., 1., 1.), // Placeholder for the selection's surface; populated later.
+            Mesh::new_box(1., 1., 1.), // Placeholder for a single chain's surface; populated later.
+            Mesh::new_box(1., 1., 1.), // Placeholder for binding pockets; populated later.
+            Mesh::new_box(1., 1., 1.), // Placeholder for the selected pocket; populated later.
         ],
         entities: Vec::new(),
         gaussians: Vec::new(),
@@ -164,6 +244,14 @@ pub fn render(mut state: State) {
                     specular_intensity: 0.,
                     ..Default::default()
                 },
+                // Residue spotlight.
+                PointLight {
+                    diffuse_color: white,
+                    specular_color: white,
+                    diffuse_intensity: 0.,
+                    specular_intensity: 0.,
+                    ..Default::default()
+                },
             ],
         },
         input_settings: InputSettings {
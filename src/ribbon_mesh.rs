@@ -2,9 +2,11 @@
 
 use std::{f32::consts::TAU, f64::consts::PI};
 
+use bincode::{Decode, Encode};
 use bio_files::ResidueType;
 use graphics::{Mesh, Vertex};
 use lin_alg::{f32::Vec3 as Vec3F32, f64::Vec3};
+use log::error;
 use na_seq::{AminoAcid, Element};
 
 use crate::molecule::{Atom, AtomRole, Residue};
@@ -19,14 +21,14 @@ const CYLINDER_SEGMENTS: usize = 12;
 
 // todo: Eval if you want a second cyilnder mesh of different parameters.
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Encode, Decode)]
 pub enum SecondaryStructure {
     Helix,
     Sheet,
     Coil,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Encode, Decode)]
 pub struct BackboneSS {
     // pub start: Vec3,
     // pub end: Vec3,
@@ -93,7 +95,7 @@ fn cylinder(a: Vec3F32, b: Vec3F32, radius: f32, segments: usize) -> (Vec<Vertex
 ///   to make a smooth, curved strip with thickness.
 fn sheet_ribbon(backbone_posits: &[Vec3F32], half_w: f32, thick: f32) -> (Vec<Vertex>, Vec<usize>) {
     if backbone_posits.len() < 2 {
-        eprintln!("Error loading backbone positions for cartoon mesh");
+        error!("Error loading backbone positions for cartoon mesh");
         return (Vec::new(), Vec::new());
     }
 
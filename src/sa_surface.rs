@@ -2,27 +2,59 @@
 //! to a molecule. Used for drawing *surface*, *dots*, and related meshes. Related to the van der Waals
 //! radius.
 //!
-//! Uses the Shrake-Rupley, or similar "rolling ball" methods.
+//! Uses the Shrake-Rupley, or similar "rolling ball" methods. For surface meshes, the distance field
+//! and its marching cubes mesh can be built on the GPU, if available. Both run in a thread.
 //! [This Rust lib](https://github.com/maxall41/RustSASA) appearse to be unsuitable to our purpose;
 //! it provides a single 'total SASA value', vice a set of points defining a surface.
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "cuda")] {
+        use std::sync::Arc;
+        use cudarc::driver::{CudaModule, CudaSlice, CudaStream, LaunchConfig, PushKernelArg};
+        use lin_alg::f32::{vec3s_from_dev, vec3s_to_dev};
+    }
+}
+use std::{array, collections::HashMap, f32::consts::TAU, fs::File, io, io::Write, path::Path};
+
+use bio_files::ResidueType;
 use graphics::{Mesh, Vertex};
 use lin_alg::f32::Vec3;
 use mcubes::{MarchingCubes, MeshSide};
 
-use crate::molecule::Atom;
+use crate::{
+    ComputationDevice,
+    logging::Span,
+    molecule::{Atom, Residue},
+    progress::{Progress, Task},
+    scene::Color,
+};
 
 const SOLVENT_RAD: f32 = 1.4; // water probe
+/// Test points on each atom's sphere, for Shrake-Rupley SASA.
+const SASA_SPHERE_POINTS: usize = 100;
 // const GRID_H: f32 = 0.5; // voxel edge length
 
-/// Create a mesh of the solvent-accessible surface. We do this using the ball-rolling method
-/// based on Van-der-Waals radius, then use the Marching Cubes algorithm to generate an iso mesh with
-/// iso value = 0.
-pub fn make_sas_mesh(atoms: &[&Atom], mut precision: f32) -> Mesh {
-    if atoms.is_empty() {
-        return Mesh::default();
-    }
+/// Marching cubes edges, as pairs of corners. Corner `i` is offset from the cube's first voxel by
+/// `(i & 1, (i >> 1) & 1, (i >> 2) & 1)`. Matches `MC_EDGE_CORNERS` in the CUDA kernels.
+pub(crate) const CUBE_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+/// Entries per case in `mc_tri_table`: up to 5 triangles, and a terminator.
+pub(crate) const MC_TABLE_ROW: usize = 16;
 
+/// Spheres for the solvent-accessible surface, and the voxel edge length to use.
+fn sas_spheres(atoms: &[&Atom], mut precision: f32) -> (Vec<(Vec3, f32)>, f32) {
     // todo: Experimenting avoiding problems on large mols. We have problems with both surface
     // todo: And dots; this mitigates surface. The dots one is re Instance Buffer max size;
     // todo: This one addresses Vertex buffer being maximum size.
@@ -34,50 +66,104 @@ pub fn make_sas_mesh(atoms: &[&Atom], mut precision: f32) -> Mesh {
         precision = 0.75;
     }
 
-    // Bounding box and grid
-    let mut bb_min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
-    let mut bb_max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
-    let mut r_max: f32 = 0.0;
-    for a in atoms {
-        let r = a.element.vdw_radius() + SOLVENT_RAD;
-        r_max = r_max.max(r);
+    let spheres = atoms
+        .iter()
+        .map(|a| (a.posit.into(), a.element.vdw_radius() + SOLVENT_RAD))
+        .collect();
 
-        bb_min = Vec3::new(
-            bb_min.x.min(a.posit.x as f32),
-            bb_min.y.min(a.posit.y as f32),
-            bb_min.z.min(a.posit.z as f32),
-        );
+    (spheres, precision)
+}
 
-        bb_max = Vec3::new(
-            bb_max.x.max(a.posit.x as f32),
-            bb_max.y.max(a.posit.y as f32),
-            bb_max.z.max(a.posit.z as f32),
-        );
+/// Create a mesh of the solvent-accessible surface. We do this using the ball-rolling method
+/// based on Van-der-Waals radius, then use the Marching Cubes algorithm to generate an iso mesh with
+/// iso value = 0. Evaluates the field on the GPU, if available.
+pub fn make_sas_mesh(dev: &ComputationDevice, atoms: &[&Atom], precision: f32) -> Mesh {
+    if atoms.is_empty() {
+        return Mesh::default();
     }
-    bb_min -= Vec3::splat(r_max + precision);
-    bb_max += Vec3::splat(r_max + precision);
 
-    let dim_v = (bb_max - bb_min) / precision;
-    let grid_dim = (
-        dim_v.x.ceil() as usize + 1,
-        dim_v.y.ceil() as usize + 1,
-        dim_v.z.ceil() as usize + 1,
-    );
+    let (spheres, precision) = sas_spheres(atoms, precision);
+    sphere_union_mesh(dev, &spheres, precision, &Progress::default())
+}
+
+/// A surface mesh build in a thread.
+pub type SasMeshPending = Task<Mesh>;
+
+/// As `make_sas_mesh`, in a new thread. This takes a while for large molecules; poll the returned
+/// task from the render loop, so the UI doesn't block. If cancelled, the mesh is empty.
+pub fn start_sas_mesh(dev: &ComputationDevice, atoms: &[&Atom], precision: f32) -> SasMeshPending {
+    let (spheres, precision) = sas_spheres(atoms, precision);
+    let dev = dev.clone();
+
+    Task::spawn(move |progress| {
+        let _span = Span::new(format!("Surface mesh for {} atoms", spheres.len()));
+        sphere_union_mesh(&dev, &spheres, precision, progress)
+    })
+}
 
-    let nvox = grid_dim.0 * grid_dim.1 * grid_dim.2;
+/// The grid a sphere union's field is sampled on.
+struct FieldGrid {
+    bb_min: Vec3,
+    dim: (usize, usize, usize),
+    /// Å. Voxel edge length.
+    precision: f32,
+    /// This can be any that is guaranteed to be well outside the surface. It prevents holes from
+    /// appearing in the mesh due to not having a value outside to compare to.
+    far_val: f32,
+}
+
+impl FieldGrid {
+    fn new(spheres: &[(Vec3, f32)], precision: f32) -> Self {
+        // Bounding box and grid
+        let mut bb_min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut bb_max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+        let mut r_max: f32 = 0.0;
+        for (center, r) in spheres {
+            r_max = r_max.max(*r);
+
+            bb_min = Vec3::new(
+                bb_min.x.min(center.x),
+                bb_min.y.min(center.y),
+                bb_min.z.min(center.z),
+            );
+
+            bb_max = Vec3::new(
+                bb_max.x.max(center.x),
+                bb_max.y.max(center.y),
+                bb_max.z.max(center.z),
+            );
+        }
+        bb_min -= Vec3::splat(r_max + precision);
+        bb_max += Vec3::splat(r_max + precision);
+
+        let dim_v = (bb_max - bb_min) / precision;
+
+        Self {
+            bb_min,
+            dim: (
+                dim_v.x.ceil() as usize + 1,
+                dim_v.y.ceil() as usize + 1,
+                dim_v.z.ceil() as usize + 1,
+            ),
+            precision,
+            far_val: (r_max + precision).powi(2) + 1.0,
+        }
+    }
+
+    fn num_voxels(&self) -> usize {
+        self.dim.0 * self.dim.1 * self.dim.2
+    }
+}
 
-    // This can be any that is guaranteed to be well outside the SAS surface.
-    // It prevents holes from appearing in the mesh due to not having a value outside to compare to.
-    let far_val = (r_max + precision).powi(2) + 1.0;
-    let mut field = vec![far_val; nvox];
+/// Fill the signed-squared-distance field on the CPU.
+fn sphere_union_field(spheres: &[(Vec3, f32)], grid: &FieldGrid, progress: &Progress) -> Vec<f32> {
+    let (grid_dim, precision, bb_min) = (grid.dim, grid.precision, grid.bb_min);
+    let mut field = vec![grid.far_val; grid.num_voxels()];
 
     // Helper to flatten (x, y, z)
     let idx = |x: usize, y: usize, z: usize| -> usize { (z * grid_dim.1 + y) * grid_dim.0 + x };
 
-    // Fill signed-squared-distance field
-    for a in atoms {
-        let center: Vec3 = a.posit.into();
-        let rad = a.element.vdw_radius() + SOLVENT_RAD;
+    for &(center, rad) in spheres {
         let rad2 = rad * rad;
 
         let lo = ((center - Vec3::splat(rad)) - bb_min) / precision;
@@ -107,9 +193,277 @@ pub fn make_sas_mesh(atoms: &[&Atom], mut precision: f32) -> Mesh {
                 }
             }
         }
+
+        if !progress.advance() {
+            break;
+        }
+    }
+
+    field
+}
+
+/// Fill the signed-squared-distance field on the GPU. One block per sphere; its threads split the
+/// voxels in the sphere's bounding box, and combine with the field using an atomic min.
+#[cfg(feature = "cuda")]
+fn sphere_union_field_gpu(
+    stream: &Arc<CudaStream>,
+    module: &Arc<CudaModule>,
+    spheres: &[(Vec3, f32)],
+    grid: &FieldGrid,
+) -> CudaSlice<f32> {
+    const NUM_THREADS: u32 = 256;
+
+    let centers: Vec<Vec3> = spheres.iter().map(|s| s.0).collect();
+    let radii: Vec<f32> = spheres.iter().map(|s| s.1).collect();
+
+    let centers_gpu = vec3s_to_dev(stream, &centers);
+    let radii_gpu = stream.memcpy_stod(&radii).unwrap();
+    let mut field_gpu = stream
+        .memcpy_stod(&vec![grid.far_val; grid.num_voxels()])
+        .unwrap();
+
+    let func = module.load_function("sas_field_kernel").unwrap();
+
+    let cfg = LaunchConfig {
+        grid_dim: (spheres.len() as u32, 1, 1),
+        block_dim: (NUM_THREADS, 1, 1),
+        shared_mem_bytes: 0,
+    };
+
+    let n_spheres = spheres.len();
+    let mut launch_args = stream.launch_builder(&func);
+
+    launch_args.arg(&mut field_gpu);
+    launch_args.arg(&centers_gpu);
+    launch_args.arg(&radii_gpu);
+    launch_args.arg(&n_spheres);
+    launch_args.arg(&grid.bb_min.x);
+    launch_args.arg(&grid.bb_min.y);
+    launch_args.arg(&grid.bb_min.z);
+    launch_args.arg(&grid.precision);
+    launch_args.arg(&grid.dim.0);
+    launch_args.arg(&grid.dim.1);
+    launch_args.arg(&grid.dim.2);
+
+    unsafe { launch_args.launch(cfg) }.unwrap();
+
+    field_gpu
+}
+
+/// Triangles for each marching cubes case, as indices into `CUBE_EDGES`, ending with -1. Bit `i`
+/// of the case is set if corner `i` is inside the surface. We build these from each face's cut
+/// edges: A face with two joins them; one with four (inside corners on a diagonal) cuts off each
+/// inside corner. Cubes sharing a face agree on this, so the surface has no cracks. The joins form
+/// closed loops in each cube, which we triangulate as fans.
+pub(crate) fn mc_tri_table() -> [[i8; MC_TABLE_ROW]; 256] {
+    let edge = |a: usize, b: usize| {
+        CUBE_EDGES
+            .iter()
+            .position(|&e| e == (a.min(b), a.max(b)))
+            .unwrap()
+    };
+
+    let mut result = [[-1; MC_TABLE_ROW]; 256];
+
+    for (case, row) in result.iter_mut().enumerate() {
+        let inside = |corner: usize| case & (1 << corner) != 0;
+
+        // The (two, if cut) edges each edge is joined to.
+        let mut links: [Vec<usize>; 12] = Default::default();
+
+        for axis in 0..3 {
+            let (u, w) = ((axis + 1) % 3, (axis + 2) % 3);
+
+            for side in 0..2 {
+                // The face's corners, in order around it.
+                let corners =
+                    [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(a, b)| side << axis | a << u | b << w);
+                let edges: [usize; 4] = array::from_fn(|k| edge(corners[k], corners[(k + 1) % 4]));
+
+                let mut join = |e0: usize, e1: usize| {
+                    links[e0].push(e1);
+                    links[e1].push(e0);
+                };
+
+                let cut: Vec<usize> = (0..4)
+                    .filter(|&k| inside(corners[k]) != inside(corners[(k + 1) % 4]))
+                    .collect();
+
+                if cut.len() == 2 {
+                    join(edges[cut[0]], edges[cut[1]]);
+                } else if cut.len() == 4 {
+                    for k in 0..4 {
+                        if inside(corners[k]) {
+                            join(edges[(k + 3) % 4], edges[k]);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut visited = [false; 12];
+        let mut i_row = 0;
+
+        for start in 0..12 {
+            if visited[start] || links[start].is_empty() {
+                continue;
+            }
+
+            let mut ring = vec![start];
+            visited[start] = true;
+
+            let (mut prev, mut current) = (start, links[start][0]);
+            while current != start {
+                ring.push(current);
+                visited[current] = true;
+
+                let next = if links[current][0] == prev {
+                    links[current][1]
+                } else {
+                    links[current][0]
+                };
+                prev = current;
+                current = next;
+            }
+
+            for i in 1..ring.len() - 1 {
+                for e in [ring[0], ring[i], ring[i + 1]] {
+                    row[i_row] = e as i8;
+                    i_row += 1;
+                }
+            }
+        }
     }
 
-    // Convert to a mesh using Marchine Cubes.
+    result
+}
+
+/// Convert the field to a mesh using Marching Cubes, on the GPU. Like the CPU version, vertices on
+/// each grid edge are shared by the triangles around it. Uses `mc_tri_table`; see the kernels for
+/// the passes.
+#[cfg(feature = "cuda")]
+fn mesh_from_field_gpu(
+    stream: &Arc<CudaStream>,
+    module: &Arc<CudaModule>,
+    grid: &FieldGrid,
+    field_gpu: &CudaSlice<f32>,
+) -> Mesh {
+    const SCAN_THREADS: u32 = 1_024;
+
+    let (dim_x, dim_y, dim_z) = grid.dim;
+    let n_edges = 3 * grid.num_voxels();
+    let n_cubes = (dim_x - 1) * (dim_y - 1) * (dim_z - 1);
+
+    let scan = module.load_function("nb_scan_kernel").unwrap();
+    let scan_cfg = LaunchConfig {
+        grid_dim: (1, 1, 1),
+        block_dim: (SCAN_THREADS, 1, 1),
+        shared_mem_bytes: 0,
+    };
+
+    let table: Vec<i8> = mc_tri_table().concat();
+    let table_gpu = stream.memcpy_stod(&table).unwrap();
+
+    // Vertices: Flag edges the surface crosses, and scan the flags into vertex indices.
+    let mut vert_offsets = stream.alloc_zeros::<u32>(n_edges + 1).unwrap();
+
+    let func = module.load_function("mc_edge_flags_kernel").unwrap();
+    let mut args = stream.launch_builder(&func);
+    args.arg(&mut vert_offsets);
+    args.arg(field_gpu);
+    args.arg(&dim_x);
+    args.arg(&dim_y);
+    args.arg(&dim_z);
+    unsafe { args.launch(LaunchConfig::for_num_elems(n_edges as u32)) }.unwrap();
+
+    let mut args = stream.launch_builder(&scan);
+    args.arg(&mut vert_offsets);
+    args.arg(&n_edges);
+    unsafe { args.launch(scan_cfg) }.unwrap();
+
+    let n_verts = stream.memcpy_dtov(&vert_offsets.slice(n_edges..)).unwrap()[0] as usize;
+
+    if n_verts == 0 {
+        return Mesh::default();
+    }
+
+    let mut posits_gpu = stream.alloc_zeros::<f32>(3 * n_verts).unwrap();
+    let mut normals_gpu = stream.alloc_zeros::<f32>(3 * n_verts).unwrap();
+
+    let func = module.load_function("mc_vertices_kernel").unwrap();
+    let mut args = stream.launch_builder(&func);
+    args.arg(&mut posits_gpu);
+    args.arg(&mut normals_gpu);
+    args.arg(&vert_offsets);
+    args.arg(field_gpu);
+    args.arg(&grid.bb_min.x);
+    args.arg(&grid.bb_min.y);
+    args.arg(&grid.bb_min.z);
+    args.arg(&grid.precision);
+    args.arg(&dim_x);
+    args.arg(&dim_y);
+    args.arg(&dim_z);
+    unsafe { args.launch(LaunchConfig::for_num_elems(n_edges as u32)) }.unwrap();
+
+    // Triangles: Count them per cube, scan the counts, and write them.
+    let mut tri_offsets = stream.alloc_zeros::<u32>(n_cubes + 1).unwrap();
+
+    let func = module.load_function("mc_tri_counts_kernel").unwrap();
+    let mut args = stream.launch_builder(&func);
+    args.arg(&mut tri_offsets);
+    args.arg(field_gpu);
+    args.arg(&table_gpu);
+    args.arg(&dim_x);
+    args.arg(&dim_y);
+    args.arg(&dim_z);
+    unsafe { args.launch(LaunchConfig::for_num_elems(n_cubes as u32)) }.unwrap();
+
+    let mut args = stream.launch_builder(&scan);
+    args.arg(&mut tri_offsets);
+    args.arg(&n_cubes);
+    unsafe { args.launch(scan_cfg) }.unwrap();
+
+    let n_tris = stream.memcpy_dtov(&tri_offsets.slice(n_cubes..)).unwrap()[0] as usize;
+
+    if n_tris == 0 {
+        return Mesh::default();
+    }
+
+    let mut indices_gpu = stream.alloc_zeros::<u32>(3 * n_tris).unwrap();
+
+    let func = module.load_function("mc_triangles_kernel").unwrap();
+    let mut args = stream.launch_builder(&func);
+    args.arg(&mut indices_gpu);
+    args.arg(&tri_offsets);
+    args.arg(&vert_offsets);
+    args.arg(&posits_gpu);
+    args.arg(&normals_gpu);
+    args.arg(field_gpu);
+    args.arg(&table_gpu);
+    args.arg(&dim_x);
+    args.arg(&dim_y);
+    args.arg(&dim_z);
+    unsafe { args.launch(LaunchConfig::for_num_elems(n_cubes as u32)) }.unwrap();
+
+    let posits = vec3s_from_dev(stream, &posits_gpu);
+    let normals = vec3s_from_dev(stream, &normals_gpu);
+    let indices: Vec<u32> = stream.memcpy_dtov(&indices_gpu).unwrap();
+
+    Mesh {
+        vertices: posits
+            .iter()
+            .zip(&normals)
+            .map(|(p, n)| Vertex::new([p.x, p.y, p.z], *n))
+            .collect(),
+        indices: indices.into_iter().map(|i| i as usize).collect(),
+        material: 0,
+    }
+}
+
+/// Convert the field to a mesh using Marching Cubes.
+fn mesh_from_field(grid: &FieldGrid, field: Vec<f32>) -> Mesh {
+    let (grid_dim, precision) = (grid.dim, grid.precision);
+
     //  scale = precision because size / sampling_interval = precision
     let size = (
         (grid_dim.0 as f32 - 1.0) * precision,
@@ -123,8 +477,8 @@ pub fn make_sas_mesh(atoms: &[&Atom], mut precision: f32) -> Mesh {
     );
 
     // todo: The holes in our mesh seem related to the iso level chosen.
-    let mc =
-        MarchingCubes::new(grid_dim, size, samp, bb_min, field, 0.).expect("marching cubes init");
+    let mc = MarchingCubes::new(grid_dim, size, samp, grid.bb_min, field, 0.)
+        .expect("marching cubes init");
 
     // Note: We're experiencing the opposite behavior than we expect here; we really want to draw outside.
     let mc_mesh = mc.generate(MeshSide::InsideOnly);
@@ -143,3 +497,305 @@ pub fn make_sas_mesh(atoms: &[&Atom], mut precision: f32) -> Mesh {
         material: 0,
     }
 }
+
+/// Progress counts one unit per sphere, and one for the mesh.
+fn sphere_union_mesh(
+    dev: &ComputationDevice,
+    spheres: &[(Vec3, f32)],
+    precision: f32,
+    progress: &Progress,
+) -> Mesh {
+    if spheres.is_empty() {
+        return Mesh::default();
+    }
+
+    progress.set_total(spheres.len() + 1);
+    progress.set_stage("Distance field");
+
+    let grid = FieldGrid::new(spheres, precision);
+
+    let mesh = match dev {
+        ComputationDevice::Cpu => {
+            let field = sphere_union_field(spheres, &grid, progress);
+            if progress.is_cancelled() {
+                return Mesh::default();
+            }
+
+            progress.set_stage("Mesh");
+            mesh_from_field(&grid, field)
+        }
+        // The field stays on the device for marching cubes.
+        #[cfg(feature = "cuda")]
+        ComputationDevice::Gpu((stream, module)) => {
+            let field = sphere_union_field_gpu(stream, module, spheres, &grid);
+            progress.set_done(spheres.len());
+
+            progress.set_stage("Mesh");
+            mesh_from_field_gpu(stream, module, &grid, &field)
+        }
+    };
+    progress.inc();
+
+    mesh
+}
+
+/// Create a mesh of the surface of a union of spheres, each a (center, radius). Uses a
+/// signed-squared-distance field, and Marching Cubes. `precision` is the voxel edge length, in Å.
+pub fn make_sphere_union_mesh(spheres: &[(Vec3, f32)], precision: f32) -> Mesh {
+    sphere_union_mesh(
+        &ComputationDevice::Cpu,
+        spheres,
+        precision,
+        &Progress::default(),
+    )
+}
+
+/// For each point, the indices (into `atoms`) of atoms within `dist` of it. Uses a hash grid
+/// with cell size `dist`, so we only check atoms in neighboring cells.
+pub fn atoms_near_points(points: &[Vec3], atoms: &[&Atom], dist: f32) -> Vec<Vec<usize>> {
+    let cell = |p: Vec3| -> (i32, i32, i32) {
+        (
+            (p.x / dist).floor() as i32,
+            (p.y / dist).floor() as i32,
+            (p.z / dist).floor() as i32,
+        )
+    };
+
+    let mut grid: HashMap<(i32, i32, i32), Vec<usize>> = HashMap::new();
+    for (i, atom) in atoms.iter().enumerate() {
+        grid.entry(cell(atom.posit.into())).or_default().push(i);
+    }
+
+    let dist_sq = dist * dist;
+
+    points
+        .iter()
+        .map(|p| {
+            let (cx, cy, cz) = cell(*p);
+            let mut result = Vec::new();
+
+            for x in cx - 1..=cx + 1 {
+                for y in cy - 1..=cy + 1 {
+                    for z in cz - 1..=cz + 1 {
+                        let Some(cell_atoms) = grid.get(&(x, y, z)) else {
+                            continue;
+                        };
+                        for &i in cell_atoms {
+                            let posit: Vec3 = atoms[i].posit.into();
+                            if (posit - *p).magnitude_squared() < dist_sq {
+                                result.push(i);
+                            }
+                        }
+                    }
+                }
+            }
+            result
+        })
+        .collect()
+}
+
+/// For each point, the index (into `atoms`) of the closest atom, if any are within range of a
+/// solvent-accessible surface.
+pub fn nearest_atoms(points: &[Vec3], atoms: &[&Atom]) -> Vec<Option<usize>> {
+    let r_max = atoms
+        .iter()
+        .map(|a| a.element.vdw_radius())
+        .fold(0., f32::max);
+
+    // A bit of margin for the marching cubes grid.
+    let near = atoms_near_points(points, atoms, r_max + SOLVENT_RAD + 1.);
+
+    points
+        .iter()
+        .zip(near)
+        .map(|(p, near)| {
+            near.into_iter().min_by(|&a, &b| {
+                let posit_a: Vec3 = atoms[a].posit.into();
+                let posit_b: Vec3 = atoms[b].posit.into();
+                (posit_a - *p)
+                    .magnitude_squared()
+                    .total_cmp(&(posit_b - *p).magnitude_squared())
+            })
+        })
+        .collect()
+}
+
+/// Solvent-accessible surface area, per atom, and per residue.
+#[derive(Clone, Debug, Default)]
+pub struct Sasa {
+    /// Å². Same order as the atoms passed.
+    pub per_atom: Vec<f32>,
+    /// Å². Indexed by the molecule's residues.
+    pub per_residue: Vec<f32>,
+    /// Å²
+    pub total: f32,
+    /// The exposed test points of each atom. Same order as the atoms passed.
+    pub points: Vec<Vec<Vec3>>,
+}
+
+impl Sasa {
+    /// Save per-residue areas as CSV.
+    pub fn save_csv_residues(&self, residues: &[Residue], path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "res_index,res_sn,res_type,sasa")?;
+        for (i, (res, area)) in residues.iter().zip(&self.per_residue).enumerate() {
+            let res_type = match &res.res_type {
+                ResidueType::AminoAcid(aa) => aa.to_string(),
+                ResidueType::Water => "Water".to_owned(),
+                ResidueType::Other(name) => name.clone(),
+            };
+            writeln!(file, "{i},{},{res_type},{area:.3}", res.serial_number)?;
+        }
+        writeln!(file, "total,,,{:.3}", self.total)?;
+
+        Ok(())
+    }
+
+    /// Save per-atom areas as CSV. `atoms` must be the ones passed to `calc_sasa`.
+    pub fn save_csv_atoms(&self, atoms: &[&Atom], path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "atom_sn,element,res_index,sasa")?;
+        for (atom, area) in atoms.iter().zip(&self.per_atom) {
+            let res = match atom.residue {
+                Some(r) => r.to_string(),
+                None => String::new(),
+            };
+            writeln!(
+                file,
+                "{},{},{res},{area:.3}",
+                atom.serial_number,
+                atom.element.to_letter()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Evenly-distributed points on a unit sphere, using the golden spiral.
+fn unit_sphere_points(n: usize) -> Vec<Vec3> {
+    let golden_angle = TAU * (1. - 1. / 1.618_034);
+
+    (0..n)
+        .map(|i| {
+            let y = 1. - 2. * (i as f32 + 0.5) / n as f32;
+            let r = (1. - y * y).sqrt();
+            let θ = golden_angle * i as f32;
+            Vec3::new(r * θ.cos(), y, r * θ.sin())
+        })
+        .collect()
+}
+
+/// Compute SASA using the Shrake-Rupley algorithm: Place test points on a sphere of radius
+/// VDW + probe around each atom. The fraction of points not inside another atom's sphere, times
+/// the sphere's area, is the atom's SASA. `n_residues` sizes the per-residue result.
+pub fn calc_sasa(atoms: &[&Atom], n_residues: usize) -> Sasa {
+    let mut result = Sasa {
+        per_atom: vec![0.; atoms.len()],
+        per_residue: vec![0.; n_residues],
+        total: 0.,
+        points: vec![Vec::new(); atoms.len()],
+    };
+
+    if atoms.is_empty() {
+        return result;
+    }
+
+    let unit_pts = unit_sphere_points(SASA_SPHERE_POINTS);
+
+    let radii: Vec<f32> = atoms
+        .iter()
+        .map(|a| a.element.vdw_radius() + SOLVENT_RAD)
+        .collect();
+    let r_max = radii.iter().copied().fold(0., f32::max);
+
+    let centers: Vec<Vec3> = atoms.iter().map(|a| a.posit.into()).collect();
+    // Any atom whose sphere can overlap this one's.
+    let neighbors = atoms_near_points(&centers, atoms, 2. * r_max);
+
+    for (i, neighbors) in neighbors.iter().enumerate() {
+        let r = radii[i];
+
+        // Only check neighbors that actually overlap.
+        let overlapping: Vec<_> = neighbors
+            .iter()
+            .copied()
+            .filter(|&j| {
+                j != i && (centers[j] - centers[i]).magnitude_squared() < (r + radii[j]).powi(2)
+            })
+            .collect();
+
+        for unit in &unit_pts {
+            let p = centers[i] + *unit * r;
+            let buried = overlapping
+                .iter()
+                .any(|&j| (p - centers[j]).magnitude_squared() < radii[j] * radii[j]);
+
+            if !buried {
+                result.points[i].push(p);
+            }
+        }
+
+        let area = 2. * TAU * r * r * result.points[i].len() as f32 / SASA_SPHERE_POINTS as f32;
+
+        result.per_atom[i] = area;
+        result.total += area;
+        if let Some(res) = atoms[i].residue.and_then(|r| result.per_residue.get_mut(r)) {
+            *res += area;
+        }
+    }
+
+    result
+}
+
+/// Our meshes have a single color per entity. To color a surface per-vertex, we split it into
+/// one mesh per (quantized) color. Each triangle takes the color of its first vertex.
+pub fn split_mesh_by_color(mesh: &Mesh, colors: &[Color]) -> Vec<(Mesh, Color)> {
+    // Color channel steps; keeps the number of meshes reasonable for continuous color maps.
+    const LEVELS: f32 = 15.;
+
+    let key = |c: Color| -> (u8, u8, u8) {
+        (
+            (c.0.clamp(0., 1.) * LEVELS).round() as u8,
+            (c.1.clamp(0., 1.) * LEVELS).round() as u8,
+            (c.2.clamp(0., 1.) * LEVELS).round() as u8,
+        )
+    };
+
+    // Color key: (Vertices, indices, and a map of original vertex index to new.)
+    let mut buckets: HashMap<(u8, u8, u8), (Vec<Vertex>, Vec<usize>, HashMap<usize, usize>)> =
+        HashMap::new();
+
+    for tri in mesh.indices.chunks_exact(3) {
+        let (verts, indices, index_map) = buckets.entry(key(colors[tri[0]])).or_default();
+
+        for &i in tri {
+            let new_i = *index_map.entry(i).or_insert_with(|| {
+                verts.push(mesh.vertices[i].clone());
+                verts.len() - 1
+            });
+            indices.push(new_i);
+        }
+    }
+
+    buckets
+        .into_iter()
+        .map(|(k, (vertices, indices, _))| {
+            let color = (
+                k.0 as f32 / LEVELS,
+                k.1 as f32 / LEVELS,
+                k.2 as f32 / LEVELS,
+            );
+            (
+                Mesh {
+                    vertices,
+                    indices,
+                    material: 0,
+                },
+                color,
+            )
+        })
+        .collect()
+}
@@ -1,7 +1,585 @@
-use std::{fs::File, path::Path};
+//! Sessions: the receptor, ligand and its pose, additional objects, MD trajectory, docking site,
+//! selections, view settings, scenes, and camera, saved together in a `.daedalus` file, and
+//! restored as they were.
+//!
+//! Molecules are encoded directly from their current state, so edits such as added hydrogens, MD
+//! positions, per-atom and per-residue properties, force field types, partial charges, and
+//! protonation variants are kept. On restore, we don't re-run protonation or ligand geometry
+//! cleanup; atom indices, and so selections, stay valid. Data derived from positions and bonds,
+//! e.g. rings, hydrogen bonds, and residue dihedral angles, is re-computed. Other settings use the
+//! same per-molecule data as prefs.
+//!
+//! MD is stored as its snapshots and energy reports. The simulation is rebuilt from the restored
+//! molecule or ligand on load, so playback and plots work, and runs can continue.
 
-use crate::molecule::Molecule;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    str::FromStr,
+};
 
-struct SaveData {
-    molecule: Molecule,
+use bincode::{Decode, Encode, config};
+use bio_files::{Chain, ResidueType};
+use graphics::Camera;
+use lin_alg::f64::{Quaternion, Vec3};
+use log::warn;
+use na_seq::{AaIdent, AminoAcidProtenationVariant, AtomTypeInRes, Element};
+
+use crate::{
+    CamSnapshot, ComputationDevice, State,
+    aa_coords::calc_sidechain_dihedrals,
+    analysis::ramachandran::calc_backbone_dihedrals,
+    assembly::Assembly,
+    bond_inference::perceive_rings,
+    docking::{ConformationType, DockingSite, dynamics::build_dock_dynamics, prep::DockType},
+    dynamics::{
+        MdState, ParamError, SnapshotDynamics,
+        protein::{PROTEIN_MD_DT, build_protein_dynamics},
+        report::EnergyReport,
+    },
+    error::DaedalusError,
+    molecule::{
+        AltLocPolicy, Atom, AtomRole, Bond, ExperimentalMethod, Ligand, Molecule, Properties,
+        Residue,
+    },
+    objects::{MolObject, ObjColorScheme},
+    prefs::PerMolToSave,
+    progress::Progress,
+    ribbon_mesh::BackboneSS,
+    scene::Color,
+    util::mol_center_size,
+};
+
+pub const SESSION_EXT: &str = "daedalus";
+/// Increment when the format changes; we reject sessions from other versions.
+const SESSION_VERSION: u16 = 2;
+
+/// Protonation variants we assign; see the `protonation` module, and sidechain flips. Stored by
+/// name, since `na_seq` types aren't encodable.
+const VARIANTS: [AminoAcidProtenationVariant; 7] = [
+    AminoAcidProtenationVariant::Ash,
+    AminoAcidProtenationVariant::Cym,
+    AminoAcidProtenationVariant::Glh,
+    AminoAcidProtenationVariant::Hid,
+    AminoAcidProtenationVariant::Hie,
+    AminoAcidProtenationVariant::Hip,
+    AminoAcidProtenationVariant::Lyn,
+];
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// As `Atom`, with `na_seq` types stored as text.
+#[derive(Encode, Decode)]
+struct SessionAtom {
+    serial_number: usize,
+    posit: Vec3,
+    /// E.g. "C", or "Fe".
+    element: String,
+    type_in_res: Option<String>,
+    force_field_type: Option<String>,
+    dock_type: Option<DockType>,
+    role: Option<AtomRole>,
+    residue: Option<usize>,
+    hetero: bool,
+    occupancy: Option<f32>,
+    partial_charge: Option<f32>,
+    temperature_factor: Option<f32>,
+    alt_loc: Option<char>,
+    anisou: Option<[f32; 6]>,
+    props: Properties,
+    in_ring: bool,
+    aromatic: bool,
+}
+
+impl From<&Atom> for SessionAtom {
+    fn from(atom: &Atom) -> Self {
+        Self {
+            serial_number: atom.serial_number,
+            posit: atom.posit,
+            element: atom.element.to_letter(),
+            type_in_res: atom.type_in_res.as_ref().map(|t| t.to_string()),
+            force_field_type: atom.force_field_type.clone(),
+            dock_type: atom.dock_type,
+            role: atom.role,
+            residue: atom.residue,
+            hetero: atom.hetero,
+            occupancy: atom.occupancy,
+            partial_charge: atom.partial_charge,
+            temperature_factor: atom.temperature_factor,
+            alt_loc: atom.alt_loc,
+            anisou: atom.anisou,
+            props: atom.props.clone(),
+            in_ring: atom.in_ring,
+            aromatic: atom.aromatic,
+        }
+    }
+}
+
+impl SessionAtom {
+    fn to_atom(&self) -> io::Result<Atom> {
+        Ok(Atom {
+            serial_number: self.serial_number,
+            posit: self.posit,
+            element: Element::from_letter(&self.element)?,
+            type_in_res: self
+                .type_in_res
+                .as_ref()
+                .and_then(|t| AtomTypeInRes::from_str(t).ok()),
+            force_field_type: self.force_field_type.clone(),
+            dock_type: self.dock_type,
+            role: self.role,
+            residue: self.residue,
+            hetero: self.hetero,
+            occupancy: self.occupancy,
+            partial_charge: self.partial_charge,
+            temperature_factor: self.temperature_factor,
+            alt_loc: self.alt_loc,
+            anisou: self.anisou,
+            props: self.props.clone(),
+            in_ring: self.in_ring,
+            aromatic: self.aromatic,
+        })
+    }
+}
+
+/// As `Residue`. Dihedral angles are re-computed from atom positions on load.
+#[derive(Encode, Decode)]
+struct SessionResidue {
+    serial_number: isize,
+    /// E.g. "ALA", "HOH", or a hetero residue's name.
+    res_type: String,
+    atoms: Vec<usize>,
+    props: Properties,
+    /// E.g. "Hie".
+    variant: Option<String>,
+}
+
+impl From<&Residue> for SessionResidue {
+    fn from(res: &Residue) -> Self {
+        let res_type = match &res.res_type {
+            ResidueType::AminoAcid(aa) => aa.to_str(AaIdent::ThreeLetters).to_uppercase(),
+            ResidueType::Water => "HOH".to_owned(),
+            ResidueType::Other(name) => name.clone(),
+        };
+
+        Self {
+            serial_number: res.serial_number,
+            res_type,
+            atoms: res.atoms.clone(),
+            props: res.props.clone(),
+            variant: res.variant.map(|v| format!("{v:?}")),
+        }
+    }
+}
+
+impl SessionResidue {
+    fn to_residue(&self) -> Residue {
+        Residue {
+            serial_number: self.serial_number,
+            res_type: ResidueType::from_str(&self.res_type),
+            atoms: self.atoms.clone(),
+            dihedral: None,
+            props: self.props.clone(),
+            variant: self
+                .variant
+                .as_ref()
+                .and_then(|name| VARIANTS.into_iter().find(|v| format!("{v:?}") == *name)),
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+struct SessionChain {
+    id: String,
+    atoms: Vec<usize>,
+    residues: Vec<usize>,
+    visible: bool,
+}
+
+/// A molecule's own data. What's derived from it, e.g. rings, and non-covalent interactions, is
+/// re-computed on load.
+#[derive(Encode, Decode)]
+struct SessionMolecule {
+    ident: String,
+    atoms: Vec<SessionAtom>,
+    bonds: Vec<Bond>,
+    residues: Vec<SessionResidue>,
+    chains: Vec<SessionChain>,
+    secondary_structure: Vec<BackboneSS>,
+    method: Option<ExperimentalMethod>,
+    assemblies: Vec<Assembly>,
+    alt_loc_atoms: Vec<(usize, SessionAtom)>,
+    alt_loc_policy: AltLocPolicy,
+    pubchem_cid: Option<u32>,
+    drugbank_id: Option<String>,
+}
+
+impl SessionMolecule {
+    /// Works on compacted molecules too.
+    fn new(mol: &Molecule) -> Self {
+        Self {
+            ident: mol.ident.clone(),
+            atoms: (0..mol.atom_count())
+                .map(|i| SessionAtom::from(&*mol.atom(i)))
+                .collect(),
+            bonds: mol.bonds.clone(),
+            residues: mol.residues.iter().map(Into::into).collect(),
+            chains: mol
+                .chains
+                .iter()
+                .map(|c| SessionChain {
+                    id: c.id.clone(),
+                    atoms: c.atoms.clone(),
+                    residues: c.residues.clone(),
+                    visible: c.visible,
+                })
+                .collect(),
+            secondary_structure: mol.secondary_structure.clone(),
+            method: mol.method,
+            assemblies: mol.assemblies.clone(),
+            alt_loc_atoms: mol
+                .alt_loc_atoms
+                .iter()
+                .map(|(i, atom)| (*i, atom.into()))
+                .collect(),
+            alt_loc_policy: mol.alt_loc_policy,
+            pubchem_cid: mol.pubchem_cid,
+            drugbank_id: mol.drugbank_id.clone(),
+        }
+    }
+
+    fn to_molecule(&self) -> io::Result<Molecule> {
+        let atoms = self
+            .atoms
+            .iter()
+            .map(SessionAtom::to_atom)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let alt_loc_atoms = self
+            .alt_loc_atoms
+            .iter()
+            .map(|(i, atom)| Ok((*i, atom.to_atom()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let n = atoms.len();
+        if self.bonds.iter().any(|b| b.atom_0 >= n || b.atom_1 >= n)
+            || self
+                .residues
+                .iter()
+                .any(|r| r.atoms.iter().any(|&i| i >= n))
+        {
+            return Err(invalid("Reference to a missing atom"));
+        }
+
+        let (center, size) = mol_center_size(&atoms);
+
+        let mut result = Molecule {
+            ident: self.ident.clone(),
+            atoms,
+            bonds: self.bonds.clone(),
+            residues: self
+                .residues
+                .iter()
+                .map(SessionResidue::to_residue)
+                .collect(),
+            chains: self
+                .chains
+                .iter()
+                .map(|c| Chain {
+                    id: c.id.clone(),
+                    atoms: c.atoms.clone(),
+                    residues: c.residues.clone(),
+                    visible: c.visible,
+                })
+                .collect(),
+            center,
+            size,
+            secondary_structure: self.secondary_structure.clone(),
+            method: self.method,
+            assemblies: self.assemblies.clone(),
+            alt_loc_atoms,
+            alt_loc_policy: self.alt_loc_policy,
+            pubchem_cid: self.pubchem_cid,
+            drugbank_id: self.drugbank_id.clone(),
+            ..Default::default()
+        };
+
+        result.aa_seq = result.get_seq();
+        result.adjacency_list = result.build_adjacency_list();
+        result.rings = perceive_rings(&mut result.atoms, &mut result.bonds, &result.adjacency_list);
+
+        result.update_h_bonds(&Default::default());
+        result.infer_noncovalent();
+
+        result.het_residues = result
+            .residues
+            .iter()
+            .filter(|r| matches!(r.res_type, ResidueType::Other(_)) && r.atoms.len() >= 10)
+            .cloned()
+            .collect();
+
+        calc_backbone_dihedrals(&mut result);
+        calc_sidechain_dihedrals(&mut result);
+
+        Ok(result)
+    }
+}
+
+#[derive(Encode, Decode)]
+struct SessionLigand {
+    molecule: SessionMolecule,
+    atom_posits: Vec<Vec3>,
+    anchor_posit: Vec3,
+    orientation: Quaternion,
+    docking_site: DockingSite,
+    site_waters: Vec<usize>,
+}
+
+#[derive(Encode, Decode)]
+struct SessionObject {
+    molecule: SessionMolecule,
+    visible: bool,
+    color_scheme: ObjColorScheme,
+    color: Color,
+    orientation: Quaternion,
+    offset: Vec3,
+}
+
+/// Which system an MD run simulated; we rebuild it from this on load.
+#[derive(Clone, Copy, Encode, Decode)]
+enum SessionMdKind {
+    /// The whole primary molecule. See `build_protein_dynamics`.
+    Protein,
+    /// The ligand in its docking site. See `build_dock_dynamics`.
+    Docking,
+}
+
+#[derive(Encode, Decode)]
+struct SessionMd {
+    kind: SessionMdKind,
+    /// fs
+    time: f64,
+    step_count: usize,
+    snapshots: Vec<SnapshotDynamics>,
+    reports: Vec<EnergyReport>,
+}
+
+impl SessionMd {
+    fn new(md: &MdState) -> Self {
+        // Docking MD has the receptor as static atoms; protein MD has none.
+        let kind = if md.atoms_static.is_empty() {
+            SessionMdKind::Protein
+        } else {
+            SessionMdKind::Docking
+        };
+
+        Self {
+            kind,
+            time: md.time,
+            step_count: md.step_count,
+            snapshots: md.snapshots.clone(),
+            reports: md.reports.clone(),
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+struct SessionFile {
+    version: u16,
+    molecule: Option<SessionMolecule>,
+    ligand: Option<SessionLigand>,
+    objects: Vec<SessionObject>,
+    md: Option<SessionMd>,
+    /// Selections, named selections, scenes, view and visibility settings, and the docking site.
+    view: Option<PerMolToSave>,
+    camera: CamSnapshot,
+}
+
+impl State {
+    pub fn save_session(&self, path: &Path, cam: &Camera) -> io::Result<()> {
+        let ligand = self.ligand.as_ref().map(|lig| SessionLigand {
+            molecule: SessionMolecule::new(&lig.molecule),
+            atom_posits: lig.atom_posits.clone(),
+            anchor_posit: lig.pose.anchor_posit,
+            orientation: lig.pose.orientation,
+            docking_site: lig.docking_site.clone(),
+            site_waters: lig.site_waters.clone(),
+        });
+
+        let objects = self
+            .objects
+            .iter()
+            .map(|obj| SessionObject {
+                molecule: SessionMolecule::new(&obj.mol),
+                visible: obj.visible,
+                color_scheme: obj.color_scheme,
+                color: obj.color,
+                orientation: obj.orientation,
+                offset: obj.offset,
+            })
+            .collect();
+
+        let session = SessionFile {
+            version: SESSION_VERSION,
+            molecule: self.molecule.as_ref().map(SessionMolecule::new),
+            ligand,
+            objects,
+            md: self.mol_dynamics.as_ref().map(SessionMd::new),
+            view: self
+                .molecule
+                .as_ref()
+                .map(|_| PerMolToSave::from_state(self)),
+            camera: CamSnapshot::from_cam(cam, "Session".to_owned()),
+        };
+
+        let data = bincode::encode_to_vec(&session, config::standard())
+            .map_err(|e| invalid(&e.to_string()))?;
+        fs::write(path, data)
+    }
+
+    /// Replace the open molecule, ligand, and objects with a session's. The camera is applied on
+    /// the next frame.
+    pub fn open_session(&mut self, path: &Path) -> io::Result<()> {
+        let data = fs::read(path)?;
+        let (session, _): (SessionFile, _) = bincode::decode_from_slice(&data, config::standard())
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        if session.version != SESSION_VERSION {
+            return Err(invalid("This session was saved by a different version"));
+        }
+
+        // Build everything before changing state, so a bad file leaves the current one intact.
+        let molecule = match &session.molecule {
+            Some(mol) => Some(mol.to_molecule()?),
+            None => None,
+        };
+        let lig_mol = match &session.ligand {
+            Some(lig) => Some(lig.molecule.to_molecule()?),
+            None => None,
+        };
+        let objects = session
+            .objects
+            .iter()
+            .map(|obj| {
+                Ok(MolObject {
+                    mol: obj.molecule.to_molecule()?,
+                    visible: obj.visible,
+                    color_scheme: obj.color_scheme,
+                    color: obj.color,
+                    orientation: obj.orientation,
+                    offset: obj.offset,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        self.ligand = None;
+        self.molecule = None;
+        self.pdb = None;
+        self.cif_pdb_raw = None;
+        self.mol_dynamics = None;
+        self.volatile.docking_setup = None;
+        self.volatile.pose_search = None;
+        // The run is for the previous molecule.
+        if let Some(task) = self.volatile.md_run.take() {
+            task.progress.cancel();
+        }
+        self.volatile.object_active = None;
+        self.volatile.superpose_result = None;
+        self.volatile.mcs_alignment = None;
+
+        self.objects = objects;
+
+        if let Some(mol) = molecule {
+            if let Some(view) = session.view {
+                self.to_save.per_mol.insert(mol.ident.clone(), view);
+            }
+            // This applies the view settings above.
+            self.set_molecule(mol, None);
+        }
+
+        // After the molecule, since restoring its docking site re-positions the ligand.
+        if let (Some(mol), Some(saved)) = (lig_mol, session.ligand) {
+            let mut lig = Ligand::new(mol);
+
+            lig.docking_site = saved.docking_site;
+            lig.site_waters = saved.site_waters;
+            lig.pose.anchor_posit = saved.anchor_posit;
+            lig.pose.orientation = saved.orientation;
+            lig.pose.conformation_type = ConformationType::AbsolutePosits;
+            lig.atom_posits = saved.atom_posits;
+
+            self.ligand = Some(lig);
+            self.get_make_docking_setup();
+        }
+
+        if let Some(saved) = session.md {
+            // A session is still usable without its trajectory, e.g. if force field parameters
+            // are missing.
+            match self.rebuild_md(saved.kind) {
+                Ok(mut md) => {
+                    md.time = saved.time;
+                    md.step_count = saved.step_count;
+                    md.snapshots = saved.snapshots;
+                    md.reports = saved.reports;
+
+                    self.mol_dynamics = Some(md);
+                    self.ui.current_snapshot = 0;
+                }
+                Err(e) => warn!("Unable to restore MD from the session: {e}"),
+            }
+        }
+
+        self.volatile.session_cam = Some(session.camera);
+
+        Ok(())
+    }
+
+    /// Set up, without running, a simulation of the same system as a saved one.
+    fn rebuild_md(&mut self, kind: SessionMdKind) -> Result<MdState, DaedalusError> {
+        self.load_ffs_general();
+        let dev = ComputationDevice::Cpu;
+
+        match kind {
+            SessionMdKind::Protein => {
+                let Some(mol) = &self.molecule else {
+                    return Err(ParamError::new("Protein MD requires a molecule").into());
+                };
+                build_protein_dynamics(
+                    &dev,
+                    mol,
+                    &self.ff_params,
+                    0,
+                    PROTEIN_MD_DT,
+                    &self.ui.md_config,
+                    &Progress::default(),
+                )
+            }
+            SessionMdKind::Docking => {
+                self.get_make_docking_setup();
+                let (Some(mol), Some(lig), Some(setup)) = (
+                    &self.molecule,
+                    &mut self.ligand,
+                    &self.volatile.docking_setup,
+                ) else {
+                    return Err(ParamError::new("Docking MD requires a molecule and ligand").into());
+                };
+
+                // No steps are run, so the time step is unused.
+                build_dock_dynamics(
+                    &dev,
+                    lig,
+                    setup,
+                    &self.ff_params,
+                    &mol.residues,
+                    0,
+                    PROTEIN_MD_DT,
+                    &self.ui.md_config,
+                    &Progress::default(),
+                )
+            }
+        }
+    }
 }
@@ -0,0 +1,133 @@
+//! Scene setup shared by drawing code, the command line, and the GUI: colors, mesh indices, sizes,
+//! lighting, and camera limits. These act on `graphics` types, but don't need a window; the event
+//! loop is in `render`.
+
+use std::f32::consts::TAU;
+
+use graphics::Scene;
+use lin_alg::f32::Vec3;
+
+use crate::docking::DockingSite;
+
+pub type Color = (f32, f32, f32);
+
+pub const BACKGROUND_COLOR: Color = (0., 0., 0.);
+
+pub const RENDER_DIST_NEAR: f32 = 0.2;
+pub const RENDER_DIST_FAR: f32 = 1_000.;
+
+// todo: Shinyness broken?
+pub const ATOM_SHININESS: f32 = 0.9;
+pub const BODY_SHINYNESS: f32 = 0.9;
+
+// Keep this in sync with mesh init.
+pub const MESH_SPHERE_HIGHRES: usize = 0;
+pub const MESH_CUBE: usize = 1;
+pub const MESH_BOND: usize = 2;
+pub const MESH_SPHERE_LOWRES: usize = 3;
+pub const MESH_SPHERE_MEDRES: usize = 4;
+pub const MESH_DOCKING_BOX: usize = 5;
+pub const MESH_SOLVENT_SURFACE: usize = 6; // Van Der Waals surface.
+pub const MESH_DOCKING_SURFACE: usize = 7;
+pub const MESH_DENSITY_SURFACE: usize = 8;
+pub const MESH_SECONDARY_STRUCTURE: usize = 9;
+pub const MESH_SEL_SURFACE: usize = 10;
+pub const MESH_CHAIN_SURFACE: usize = 11;
+pub const MESH_POCKETS: usize = 12;
+pub const MESH_POCKET_SEL: usize = 13;
+// Meshes at and above this index are created at runtime, e.g. the solvent-accessible surface split
+// by color. Keep this after the fixed indices above.
+pub const MESH_DYNAMIC_START: usize = 14;
+
+pub const BALL_STICK_RADIUS: f32 = 0.3;
+pub const BALL_STICK_RADIUS_H: f32 = 0.2;
+pub const BALL_RADIUS_WATER: f32 = 0.15;
+
+pub const SHELL_OPACITY: f32 = 0.01;
+
+// From the farthest molecule.
+pub const CAM_INIT_OFFSET: f32 = 10.;
+
+// A higher value will result in a less-dramatic brightness change with distance.
+const FLASHLIGHT_OFFSET: f32 = 10.;
+const FLASHLIGHT_FOV: f32 = TAU / 16.;
+pub const OUTSIDE_LIGHTING_OFFSET: f32 = 900.;
+pub const DOCKING_LIGHT_INTENSITY: f32 = 0.3;
+
+// A temporary highlight on a residue, when stepping through residues with the keyboard.
+pub const RES_SPOTLIGHT_INTENSITY: f32 = 4.;
+/// Seconds. The spotlight fades out over this duration.
+pub const RES_SPOTLIGHT_TIME: f32 = 1.5;
+const RES_SPOTLIGHT_OFFSET: f32 = 3.;
+
+/// Set the flashlight to be a little bit behind the camera; prevents too dramatic of an intensity
+/// scaling on the object looked at, WRT distance.
+pub fn set_flashlight(scene: &mut Scene) {
+    let light = &mut scene.lighting.point_lights[0];
+    light.position = scene.camera.position
+        + scene
+            .camera
+            .orientation
+            .rotate_vec(Vec3::new(0., 0., -FLASHLIGHT_OFFSET));
+
+    // todo: Put back. Problem with di
+    // light.type_ = LightType::Directional {
+    //     direction: scene.camera.orientation.rotate_vec(FWD_VEC),
+    //     fov: FLASHLIGHT_FOV,
+    // };
+}
+
+/// Set lighting based on the center and size of the molecule.
+pub fn set_static_light(scene: &mut Scene, center: Vec3, size: f32) {
+    scene.lighting.point_lights[1].position =
+        center + Vec3::new(40., size + OUTSIDE_LIGHTING_OFFSET, 0.);
+}
+
+/// Set lighting based on the docking location.
+pub fn set_docking_light(scene: &mut Scene, docking_init: Option<&DockingSite>) {
+    let mut light = &mut scene.lighting.point_lights[2];
+
+    match docking_init {
+        Some(docking_init) => {
+            let intensity = DOCKING_LIGHT_INTENSITY * docking_init.site_radius as f32;
+
+            light.position = docking_init.site_center.into();
+            light.diffuse_intensity = intensity;
+            light.specular_intensity = intensity;
+        }
+        None => {
+            light.diffuse_intensity = 0.;
+            light.specular_intensity = 0.;
+        }
+    }
+}
+
+/// Sets the residue spotlight's position, at full intensity; `None` turns it off.
+pub fn set_res_spotlight(scene: &mut Scene, posit: Option<Vec3>) {
+    let light = &mut scene.lighting.point_lights[3];
+
+    match posit {
+        Some(p) => {
+            // Offset towards the camera, so the light hits the side of the residue we're looking at.
+            let to_cam = (scene.camera.position - p).to_normalized();
+            light.position = p + to_cam * RES_SPOTLIGHT_OFFSET;
+            light.diffuse_intensity = RES_SPOTLIGHT_INTENSITY;
+            light.specular_intensity = RES_SPOTLIGHT_INTENSITY;
+        }
+        None => {
+            light.diffuse_intensity = 0.;
+            light.specular_intensity = 0.;
+        }
+    }
+}
+
+// View depth slider limits. These are divided by 10.
+pub const VIEW_DEPTH_NEAR_MIN: u16 = 2;
+pub const VIEW_DEPTH_NEAR_MAX: u16 = 300;
+
+pub const VIEW_DEPTH_FAR_MIN: u16 = 10;
+pub const VIEW_DEPTH_FAR_MAX: u16 = 60;
+
+// Camera control sensitivities. These are defaults; overridden by the user A/R, and saved to prefs.
+pub const MOVEMENT_SENS: f32 = 12.;
+pub const ROTATE_SENS: f32 = 0.45;
@@ -0,0 +1,296 @@
+//! Structural superposition: Align a second molecule onto the primary one using the Kabsch
+//! algorithm over corresponding atom pairs. Useful for visually comparing mutants, homologs, or
+//! apo and holo structures.
+//!
+//! Residues are paired either by chain ID and serial number, or, for structures with different
+//! numbering or gaps, by a global sequence alignment of their chains.
+
+use std::{collections::HashMap, fmt};
+
+use bio_files::{Chain, ResidueType};
+use lin_alg::f64::Vec3;
+use na_seq::AminoAcid;
+use nalgebra::{Matrix3, Vector3};
+
+use crate::molecule::{AtomRole, Molecule};
+
+// Sequence alignment scores. Identity-based; adequate for establishing correspondence between
+// close homologs and mutants.
+const ALIGN_MATCH: i32 = 2;
+const ALIGN_MISMATCH: i32 = -1;
+const ALIGN_GAP: i32 = -2;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum PairMode {
+    /// Alpha carbons of all residues present in both.
+    #[default]
+    CAlpha,
+    /// The atoms selected in the primary molecule, and their counterparts.
+    Selection,
+}
+
+impl fmt::Display for PairMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::CAlpha => "Cα",
+            Self::Selection => "Selection",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+/// A rotation about the mobile points' centroid, then a translation onto the target's.
+#[derive(Clone, Debug)]
+pub struct RigidTransform {
+    rotation: Matrix3<f64>,
+    center_mobile: Vec3,
+    center_target: Vec3,
+}
+
+impl RigidTransform {
+    pub fn apply(&self, p: Vec3) -> Vec3 {
+        let v = p - self.center_mobile;
+        let r = self.rotation * Vector3::new(v.x, v.y, v.z);
+        Vec3::new(r.x, r.y, r.z) + self.center_target
+    }
+}
+
+fn centroid(points: &[Vec3]) -> Vec3 {
+    let mut sum = Vec3::new_zero();
+    for p in points {
+        sum += *p;
+    }
+    sum / points.len() as f64
+}
+
+/// Find the rigid transform that minimizes RMSD of `mobile` onto `target`. Points correspond by
+/// index. Returns `None` if there are fewer than 3 pairs, or the lengths don't match.
+pub fn kabsch(mobile: &[Vec3], target: &[Vec3]) -> Option<RigidTransform> {
+    if mobile.len() != target.len() || mobile.len() < 3 {
+        return None;
+    }
+
+    let center_mobile = centroid(mobile);
+    let center_target = centroid(target);
+
+    // Covariance matrix.
+    let mut h = Matrix3::zeros();
+    for (m, t) in mobile.iter().zip(target) {
+        let a = *m - center_mobile;
+        let b = *t - center_target;
+        h += Vector3::new(a.x, a.y, a.z) * Vector3::new(b.x, b.y, b.z).transpose();
+    }
+
+    let svd = h.svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+
+    // Correct for a reflection, if present.
+    let d = (v_t.transpose() * u.transpose()).determinant().signum();
+    let correction = Matrix3::from_diagonal(&Vector3::new(1., 1., d));
+
+    Some(RigidTransform {
+        rotation: v_t.transpose() * correction * u.transpose(),
+        center_mobile,
+        center_target,
+    })
+}
+
+/// Å. Points correspond by index.
+pub fn rmsd(a: &[Vec3], b: &[Vec3]) -> f64 {
+    if a.is_empty() {
+        return 0.;
+    }
+
+    let sum_sq: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (*a - *b).magnitude_squared())
+        .sum();
+    (sum_sq / a.len() as f64).sqrt()
+}
+
+/// Maps (chain ID, residue serial number) to residue index.
+fn residue_ids(mol: &Molecule) -> HashMap<(String, isize), usize> {
+    let mut result = HashMap::new();
+    for chain in &mol.chains {
+        for &r in &chain.residues {
+            if let Some(res) = mol.residues.get(r) {
+                result.insert((chain.id.clone(), res.serial_number), r);
+            }
+        }
+    }
+    result
+}
+
+/// Pairs residues of the target with those of the mobile molecule that share a chain ID and
+/// serial number. Keys are target residue indices; values are mobile ones.
+pub fn residue_map_by_numbering(target: &Molecule, mobile: &Molecule) -> HashMap<usize, usize> {
+    let mobile_ids = residue_ids(mobile);
+
+    residue_ids(target)
+        .into_iter()
+        .filter_map(|(id, r_target)| mobile_ids.get(&id).map(|r_mobile| (r_target, *r_mobile)))
+        .collect()
+}
+
+/// Global sequence alignment (Needleman-Wunsch), with linear gap penalties. Returns the aligned
+/// (non-gap) index pairs, and the alignment score.
+pub fn needleman_wunsch(a: &[AminoAcid], b: &[AminoAcid]) -> (Vec<(usize, usize)>, i32) {
+    let (n, m) = (a.len(), b.len());
+    let w = m + 1;
+    let idx = |i: usize, j: usize| i * w + j;
+
+    let mut score = vec![0; (n + 1) * w];
+    for i in 1..=n {
+        score[idx(i, 0)] = i as i32 * ALIGN_GAP;
+    }
+    for j in 1..=m {
+        score[idx(0, j)] = j as i32 * ALIGN_GAP;
+    }
+
+    let sub = |i: usize, j: usize| {
+        if a[i - 1] == b[j - 1] {
+            ALIGN_MATCH
+        } else {
+            ALIGN_MISMATCH
+        }
+    };
+
+    for i in 1..=n {
+        for j in 1..=m {
+            score[idx(i, j)] = (score[idx(i - 1, j - 1)] + sub(i, j))
+                .max(score[idx(i - 1, j)] + ALIGN_GAP)
+                .max(score[idx(i, j - 1)] + ALIGN_GAP);
+        }
+    }
+
+    // Traceback.
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        if score[idx(i, j)] == score[idx(i - 1, j - 1)] + sub(i, j) {
+            pairs.push((i - 1, j - 1));
+            i -= 1;
+            j -= 1;
+        } else if score[idx(i, j)] == score[idx(i - 1, j)] + ALIGN_GAP {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+    pairs.reverse();
+
+    (pairs, score[idx(n, m)])
+}
+
+/// (Residue index, amino acid) for each amino acid residue in a chain.
+fn chain_seq(mol: &Molecule, chain: &Chain) -> Vec<(usize, AminoAcid)> {
+    chain
+        .residues
+        .iter()
+        .filter_map(|&r| match mol.residues.get(r)?.res_type {
+            ResidueType::AminoAcid(aa) => Some((r, aa)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Pairs residues by aligning chain sequences. Each target chain is aligned to the unused mobile
+/// chain that scores highest against it. Keys are target residue indices; values are mobile ones.
+pub fn residue_map_by_alignment(target: &Molecule, mobile: &Molecule) -> HashMap<usize, usize> {
+    let seqs_mobile: Vec<_> = mobile.chains.iter().map(|c| chain_seq(mobile, c)).collect();
+    let mut used = vec![false; seqs_mobile.len()];
+
+    let mut result = HashMap::new();
+
+    for chain in &target.chains {
+        let seq_target = chain_seq(target, chain);
+        if seq_target.is_empty() {
+            continue;
+        }
+        let aas_target: Vec<_> = seq_target.iter().map(|(_, aa)| *aa).collect();
+
+        let best = seqs_mobile
+            .iter()
+            .enumerate()
+            .filter(|(i, seq)| !used[*i] && !seq.is_empty())
+            .map(|(i, seq)| {
+                let aas: Vec<_> = seq.iter().map(|(_, aa)| *aa).collect();
+                (i, needleman_wunsch(&aas_target, &aas))
+            })
+            .max_by_key(|(_, (_, score))| *score);
+
+        let Some((i_mobile, (pairs, _))) = best else {
+            continue;
+        };
+        used[i_mobile] = true;
+
+        for (a, b) in pairs {
+            result.insert(seq_target[a].0, seqs_mobile[i_mobile][b].0);
+        }
+    }
+
+    result
+}
+
+/// Pairs target atoms with mobile ones, using a residue correspondence, and atom names within
+/// each residue. Returns (target atom index, mobile atom index).
+pub fn pair_atoms(
+    target: &Molecule,
+    mobile: &Molecule,
+    target_atoms: &[usize],
+    residue_map: &HashMap<usize, usize>,
+) -> Vec<(usize, usize)> {
+    target_atoms
+        .iter()
+        .filter_map(|&i| {
+            let atom = &target.atoms[i];
+            atom.type_in_res.as_ref()?;
+
+            let res_mobile = residue_map.get(&atom.residue?)?;
+            let i_mobile = mobile.residues[*res_mobile]
+                .atoms
+                .iter()
+                .copied()
+                .find(|&j| mobile.atoms[j].type_in_res == atom.type_in_res)?;
+
+            Some((i, i_mobile))
+        })
+        .collect()
+}
+
+/// The target atoms to superpose over, for a given mode.
+pub fn atoms_for_mode(target: &Molecule, mode: PairMode, sel_atoms: &[usize]) -> Vec<usize> {
+    match mode {
+        PairMode::CAlpha => target
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.role == Some(AtomRole::C_Alpha))
+            .map(|(i, _)| i)
+            .collect(),
+        PairMode::Selection => sel_atoms.to_vec(),
+    }
+}
+
+/// Align `mobile` onto `target` over the atom pairs, moving all of its atoms. Returns the RMSD
+/// over the pairs after alignment, or `None` if there are too few pairs.
+pub fn superpose(
+    mobile: &mut Molecule,
+    target: &Molecule,
+    pairs: &[(usize, usize)],
+) -> Option<f64> {
+    let posits_target: Vec<_> = pairs.iter().map(|(t, _)| target.atoms[*t].posit).collect();
+    let posits_mobile: Vec<_> = pairs.iter().map(|(_, m)| mobile.atoms[*m].posit).collect();
+
+    let transform = kabsch(&posits_mobile, &posits_target)?;
+
+    for atom in &mut mobile.atoms {
+        atom.posit = transform.apply(atom.posit);
+    }
+    mobile.center = transform.apply(mobile.center);
+
+    let posits_mobile: Vec<_> = pairs.iter().map(|(_, m)| mobile.atoms[*m].posit).collect();
+    Some(rmsd(&posits_mobile, &posits_target))
+}
@@ -93,3 +93,988 @@ fn test_docking_setup() {
     // todo:  Youros answers are coming out similar in mangnute, but sometimes very large?
     assert!((vdw - vdw_x8).abs() < 0.00001);
 }
+
+#[test]
+fn test_units() {
+    use lin_alg::f64::Vec3;
+
+    use crate::{
+        dynamics::{V_coulomb, V_lj as V_lj_f64},
+        forces::{force_coulomb, force_lj},
+        units::*,
+    };
+
+    // SI values.
+    const N_A: f64 = 6.022_140_76e23;
+    const E_CHARGE: f64 = 1.602_176_634e-19; // C
+    const EPS_0: f64 = 8.854_187_8128e-12; // F/m
+    const K_B_SI: f64 = 1.380_649e-23; // J/K
+    const J_PER_KCAL: f64 = 4_184.;
+    const KG_PER_AMU: f64 = 1.660_539_066_6e-27;
+
+    let rel = |a: f64, b: f64| ((a - b) / b).abs();
+
+    // J/mol -> kcal/mol, m -> Å.
+    let k_e = E_CHARGE.powi(2) / (4. * std::f64::consts::PI * EPS_0) * N_A / J_PER_KCAL * 1e10;
+    assert!(rel(k_e, COULOMB_CONST) < 1e-5);
+
+    assert!(rel(K_B_SI * N_A / J_PER_KCAL, K_B) < 1e-6);
+    assert!(rel(E_CHARGE * N_A / J_PER_KCAL, EV_TO_KCAL_MOL) < 1e-6);
+
+    // kcal/(mol·Å·amu) -> m/s² -> Å/fs².
+    let accel = J_PER_KCAL / N_A / 1e-10 / KG_PER_AMU * 1e10 / 1e30;
+    assert!(rel(accel, ACCEL_CONV) < 1e-5);
+
+    // Equipartition: ½kT per degree of freedom, with velocities in Å/fs.
+    let t = 300.;
+    let mass = 12.;
+    let speed_sq = 3. * K_B * t / mass * ACCEL_CONV;
+    let ke = kinetic_energy(mass, speed_sq);
+    assert!(rel(temperature(ke, 3), t) < 1e-9);
+
+    // Forces, in kcal/(mol·Å), are the negative gradients of the energies, in kcal/mol.
+    let (q0, q1, σ, ε) = (0.4, -0.6, 3.4, 0.1);
+    let dir = Vec3::new(1., 0., 0.);
+    let h = 1e-5;
+    for r in [2.5, 3.5, 6.] {
+        let dv_coulomb = (V_coulomb(r + h, q0, q1, 0.) - V_coulomb(r - h, q0, q1, 0.)) / (2. * h);
+        let f = force_coulomb(dir, r, q0, q1, 0.) * COULOMB_CONST;
+        assert!(rel(-f.x, dv_coulomb) < 1e-6);
+
+        let dv_lj = (V_lj_f64(r + h, σ, ε) - V_lj_f64(r - h, σ, ε)) / (2. * h);
+        // `force_lj` returns the force on the atom at the origin, where `dir` points to the other.
+        let f = force_lj(dir, r, σ, ε);
+        assert!(rel(f.x, dv_lj) < 1e-6);
+    }
+}
+
+#[test]
+fn test_ring_closure() {
+    use crate::aa_coords::{
+        bond_vecs::residue_geometry,
+        build_peptide::{BackbonePreset, build_peptide},
+        ring_closure::{RING_HIS, RING_PHE, RING_PRO, RING_TRP, RING_TYR},
+    };
+
+    // Deviations from the templates remaining, where their bond lengths and angles aren't
+    // quite consistent.
+    const TOL_LEN: f64 = 0.02; // Å
+    const TOL_ANGLE: f64 = 2.; // degrees
+    const TOL_PLANAR: f64 = 0.01; // Å
+
+    let geom = residue_geometry();
+    let mol = build_peptide("FYHWP", BackbonePreset::Sheet).unwrap();
+
+    for (res_i, template) in [&RING_PHE, &RING_TYR, &RING_HIS, &RING_TRP, &RING_PRO]
+        .into_iter()
+        .enumerate()
+    {
+        let posit = |name: &str| mol.atoms[mol.atom_in_res(res_i, name).unwrap()].posit;
+
+        for &(a, b) in template.bonds {
+            let dist = (posit(a) - posit(b)).magnitude();
+            let expected = geom.bond_len(template.aa, a, b);
+            assert!(
+                (dist - expected).abs() < TOL_LEN,
+                "{:?}: {a}-{b} is {dist:.3} Å; expected {expected:.3}",
+                template.aa
+            );
+        }
+
+        for &(a, vertex, b, expected) in template.angles {
+            let bond_0 = (posit(a) - posit(vertex)).to_normalized();
+            let bond_1 = (posit(b) - posit(vertex)).to_normalized();
+            let angle = bond_0.dot(bond_1).acos().to_degrees();
+            assert!(
+                (angle - expected).abs() < TOL_ANGLE,
+                "{:?}: {a}-{vertex}-{b} is {angle:.1}°; expected {expected:.1}",
+                template.aa
+            );
+        }
+
+        if template.planar {
+            let (c_beta, c_gamma) = (posit("CB"), posit("CG"));
+            let normal = (c_gamma - c_beta)
+                .cross(posit(template.atoms[template.num_fixed]) - c_gamma)
+                .to_normalized();
+
+            for name in template.atoms {
+                assert!((posit(name) - c_gamma).dot(normal).abs() < TOL_PLANAR);
+            }
+        }
+    }
+}
+
+/// Harmonic oscillators coupled to a Nosé-Hoover chain: the extended system's energy is conserved,
+/// and the temperature averages to the target.
+#[test]
+fn test_nose_hoover_chain() {
+    use crate::{
+        dynamics::thermostat::NhcState,
+        units::{ACCEL_CONV, kinetic_energy, temperature},
+    };
+
+    const N: usize = 50;
+    const K: f64 = 100.; // kcal/(mol·Å²)
+    const MASS: f64 = 12.; // amu
+    const TEMP: f64 = 300.; // K
+    const DT: f64 = 1.; // fs
+    const N_STEPS: usize = 50_000;
+    const N_EQUIL: usize = 10_000;
+
+    let ke = |vels: &[Vec3]| -> f64 {
+        vels.iter()
+            .map(|v| kinetic_energy(MASS, v.magnitude_squared()))
+            .sum()
+    };
+    let pe =
+        |posits: &[Vec3]| -> f64 { posits.iter().map(|p| 0.5 * K * p.magnitude_squared()).sum() };
+
+    let mut posits: Vec<_> = (0..N)
+        .map(|i| {
+            let i = i as f64;
+            Vec3::new(0.1 * (i * 0.7).sin(), 0.1 * (i * 1.3).cos(), 0.05)
+        })
+        .collect();
+    let mut vels = vec![Vec3::new_zero(); N];
+
+    let mut nhc = NhcState::new(3, 0.1, 3 * N, TEMP);
+    let energy_0 = ke(&vels) + pe(&posits) + nhc.energy();
+
+    let mut temp_sum = 0.;
+    for step in 0..N_STEPS {
+        let scale = nhc.propagate(ke(&vels), DT / 2.);
+        for v in &mut vels {
+            *v *= scale;
+        }
+
+        for (p, v) in posits.iter_mut().zip(&mut vels) {
+            *v -= *p * (K / MASS * ACCEL_CONV * DT / 2.);
+            *p += *v * DT;
+        }
+        for (p, v) in posits.iter().zip(&mut vels) {
+            *v -= *p * (K / MASS * ACCEL_CONV * DT / 2.);
+        }
+
+        let scale = nhc.propagate(ke(&vels), DT / 2.);
+        for v in &mut vels {
+            *v *= scale;
+        }
+
+        let energy = ke(&vels) + pe(&posits) + nhc.energy();
+        assert!((energy - energy_0).abs() < 0.2);
+
+        if step >= N_EQUIL {
+            temp_sum += temperature(ke(&vels), 3 * N);
+        }
+    }
+
+    let temp_mean = temp_sum / (N_STEPS - N_EQUIL) as f64;
+    assert!((temp_mean - TEMP).abs() < 0.05 * TEMP);
+}
+
+/// The SIMD nonbonded path matches the scalar one. For timings, see `benches/nonbonded.rs`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[test]
+fn test_nonbonded_x4() {
+    use lin_alg::f64::{Vec3x4, f64x4};
+    use na_seq::Element;
+
+    use crate::dynamics::{AtomDynamics, AtomDynamicsx4, f_nonbonded, simd::f_nonbonded_x4};
+
+    if !is_x86_feature_detected!("avx") {
+        return;
+    }
+
+    let atom = |posit, partial_charge, lj_sigma, lj_eps| AtomDynamics {
+        force_field_type: String::new(),
+        element: Element::Carbon,
+        posit,
+        vel: Vec3::new_zero(),
+        accel: Vec3::new_zero(),
+        mass: 12.,
+        partial_charge,
+        lj_sigma,
+        lj_eps,
+    };
+
+    let a_0 = atom(Vec3::new(1., 2., 3.), -0.3, 3.4, 0.086);
+    let others = [
+        atom(Vec3::new(4.2, 2.1, 3.3), 0.41, 3.1, 0.12),
+        atom(Vec3::new(1.5, -1.8, 2.2), -0.52, 2.9, 0.21),
+        atom(Vec3::new(-3., 4.5, 6.), 0.08, 2.6, 0.015),
+        atom(Vec3::new(1.2, 2.3, 9.1), 0.33, 3.3, 0.1),
+    ];
+    let dv = others.each_ref().map(|a| a.posit - a_0.posit);
+
+    let scalar: Vec<_> = others
+        .iter()
+        .zip(dv)
+        .map(|(a, dv)| f_nonbonded(dv, &a_0, a, 1., 1.).unwrap())
+        .collect();
+
+    let (f, e_lj, e_coul) = f_nonbonded_x4(
+        Vec3x4::from_array(dv),
+        &a_0,
+        &AtomDynamicsx4::from_array(others.each_ref()),
+        f64x4::splat(1.),
+        f64x4::splat(1.),
+    );
+
+    for (i, (f_scalar, e_lj_scalar, e_coul_scalar)) in scalar.iter().enumerate() {
+        assert!((*f_scalar - f.to_array()[i]).magnitude() < 1e-9);
+        assert!((e_lj_scalar - e_lj.to_array()[i]).abs() < 1e-9);
+        assert!((e_coul_scalar - e_coul.to_array()[i]).abs() < 1e-9);
+    }
+}
+
+/// CMAP maps parse from Amber's format, and interpolate through their grid points, with
+/// derivatives matching finite differences.
+#[test]
+fn test_cmap() {
+    use std::f64::consts::{PI, TAU};
+
+    use crate::dynamics::cmap::parse_cmaps;
+
+    const RES: usize = 24;
+
+    let f = |φ: f64, ψ: f64| 2. * φ.sin() * (2. * ψ).cos() + 0.5 * (φ + ψ).cos();
+    let grid_angle = |i: usize| -PI + i as f64 * TAU / RES as f64;
+
+    let values: Vec<_> = (0..RES * RES)
+        .map(|k| format!("{:.6}", f(grid_angle(k / RES), grid_angle(k % RES))))
+        .collect();
+    let text = format!(
+        "CMAP\n%FLAG CMAP_COUNT     1   ! ALA map\n%FLAG CMAP_RESLIST 2\nALA ALA_N\n\
+        %FLAG CMAP_RESOLUTION {RES}\n%FLAG CMAP_PARAMETER\n{}\n",
+        values
+            .chunks(8)
+            .map(|c| c.join(" "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+
+    let maps = parse_cmaps(&text).unwrap();
+    assert_eq!(maps.len(), 1);
+    assert_eq!(maps[0].residues, vec!["ALA".to_owned(), "ALA_N".to_owned()]);
+
+    let map = &maps[0];
+    for (i, j) in [(0, 0), (3, 17), (23, 5)] {
+        let (e, _, _) = map.energy(grid_angle(i), grid_angle(j));
+        assert!((e - f(grid_angle(i), grid_angle(j))).abs() < 1e-5);
+    }
+
+    for (φ, ψ) in [(0.3, -1.2), (2.9, 3.1), (-3.1, 0.01)] {
+        let (e, de_dφ, de_dψ) = map.energy(φ, ψ);
+        assert!((e - f(φ, ψ)).abs() < 0.01);
+
+        let h = 1e-6;
+        assert!((de_dφ - (map.energy(φ + h, ψ).0 - e) / h).abs() < 1e-3);
+        assert!((de_dψ - (map.energy(φ, ψ + h).0 - e) / h).abs() < 1e-3);
+    }
+}
+
+/// Dihedral terms measure the angle with the IUPAC convention, and have forces matching the
+/// negative gradient of their energy.
+#[test]
+fn test_dihedral() {
+    use std::f64::consts::TAU;
+
+    use lin_alg::f64::Vec3;
+
+    use crate::dynamics::{dihedral_grad, f_dihedral};
+
+    // Atom 3 rotated by φ about the 1-2 axis, from cis to atom 0.
+    let posits = |φ: f64| {
+        [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0., 0., 1.5),
+            Vec3::new(φ.cos(), φ.sin(), 1.5),
+        ]
+    };
+
+    for φ in [0.3, 1., -2., 3.] {
+        let (measured, _) = dihedral_grad(posits(φ)).unwrap();
+        assert!((measured - φ).abs() < 1e-9);
+    }
+    // Trans.
+    let (measured, _) = dihedral_grad(posits(TAU / 2.)).unwrap();
+    assert!((measured.abs() - TAU / 2.).abs() < 1e-9);
+
+    // V_n = 2, n = 3, γ = 0: Staggered minima, and eclipsed maxima.
+    let (e, _) = f_dihedral(posits(TAU / 6.), 2., 3., 0.).unwrap();
+    assert!(e.abs() < 1e-9);
+    let (e, _) = f_dihedral(posits(0.), 2., 3., 0.).unwrap();
+    assert!((e - 4.).abs() < 1e-9);
+
+    let (barrier, per, phase) = (1.3, 2., TAU / 2.);
+    for φ in [0.4, 1.9, -2.5] {
+        let r = posits(φ);
+        let (e, f) = f_dihedral(r, barrier, per, phase).unwrap();
+        assert!((e - barrier * (1. + (per * φ - phase).cos())).abs() < 1e-9);
+
+        // No net force.
+        let f_net = f.iter().fold(Vec3::new_zero(), |acc, f| acc + *f);
+        assert!(f_net.magnitude() < 1e-9);
+
+        let h = 1e-6;
+        for i in 0..4 {
+            for dir in [
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                Vec3::new(0., 0., 1.),
+            ] {
+                let mut r_h = r;
+                r_h[i] += dir * h;
+                let (e_h, _) = f_dihedral(r_h, barrier, per, phase).unwrap();
+                assert!((f[i].dot(dir) + (e_h - e) / h).abs() < 1e-4);
+            }
+        }
+    }
+}
+
+/// Multi-term torsions keep every line of their series; single-term ones and impropers are left to
+/// the keyed parameters.
+#[test]
+fn test_dihedral_series() {
+    use crate::dynamics::prep::parse_dihedral_series;
+
+    let text = "\
+DIHE
+X -nh-sx-X    4    3.000         0.000          -2.000
+X -nh-sx-X    4    0.400       180.000           3.000
+X -c -c -X    4   14.500       180.000           2.000      JCC,7,(1986),230
+X -X -c -o          10.5         180.          2.           JCC,7,(1986),230
+";
+    let series = parse_dihedral_series(text);
+    assert_eq!(series.len(), 1);
+
+    let terms = &series[&(
+        "X".to_owned(),
+        "nh".to_owned(),
+        "sx".to_owned(),
+        "X".to_owned(),
+    )];
+    assert_eq!(terms.len(), 2);
+    assert_eq!(terms[0].periodicity, 2);
+    assert!((terms[0].barrier_height - 0.75).abs() < 1e-6);
+    assert_eq!(terms[1].periodicity, 3);
+    assert!((terms[1].barrier_height - 0.1).abs() < 1e-6);
+    assert!((terms[1].phase - std::f32::consts::PI).abs() < 1e-6);
+}
+
+/// Seeded velocities are reproducible, start at exactly the target temperature, and have no
+/// center-of-mass motion.
+#[test]
+fn test_init_velocities() {
+    use lin_alg::f64::Vec3;
+    use na_seq::Element;
+
+    use crate::{
+        dynamics::{AtomDynamics, MdState},
+        units::{kinetic_energy, temperature},
+    };
+
+    const TEMP: f64 = 310.; // K
+
+    let atoms = (0..40)
+        .map(|i| AtomDynamics {
+            force_field_type: String::new(),
+            element: Element::Carbon,
+            posit: Vec3::new(i as f64, 0., 0.),
+            vel: Vec3::new_zero(),
+            accel: Vec3::new_zero(),
+            mass: if i % 3 == 0 { 1.008 } else { 12.01 },
+            partial_charge: 0.,
+            lj_sigma: 0.,
+            lj_eps: 0.,
+        })
+        .collect();
+
+    let mut md = MdState {
+        atoms,
+        velocity_seed: Some(7),
+        ..Default::default()
+    };
+    md.init_velocities(TEMP);
+    let vels: Vec<_> = md.atoms.iter().map(|a| a.vel).collect();
+
+    let ke: f64 = md
+        .atoms
+        .iter()
+        .map(|a| kinetic_energy(a.mass, a.vel.magnitude_squared()))
+        .sum();
+    assert!((temperature(ke, 3 * md.atoms.len()) - TEMP).abs() < 1e-6);
+
+    let momentum = md
+        .atoms
+        .iter()
+        .fold(Vec3::new_zero(), |acc, a| acc + a.vel * a.mass);
+    assert!(momentum.magnitude() < 1e-9);
+
+    md.init_velocities(TEMP);
+    for (a, v) in md.atoms.iter().zip(&vels) {
+        assert!((a.vel - *v).magnitude() < 1e-12);
+    }
+}
+
+/// Vina terms: hydrophobic contact at a favorable distance scores negative, overlap positive, and
+/// donor-acceptor pairs at H bond distance add an H bond term.
+#[test]
+fn test_vina_score() {
+    use na_seq::Element::*;
+
+    use crate::docking::scoring::{XsType, vina_score};
+
+    let c = XsType::new(Carbon, &[Carbon, Hydrogen]);
+    let o_acceptor = XsType::new(Oxygen, &[Carbon]);
+    let n_donor = XsType::new(Nitrogen, &[Carbon, Hydrogen]);
+
+    let contact = vina_score(&[4.0], &[c], &[c], 0);
+    assert!(contact.score < 0.);
+    assert!(contact.hydrophobic > 0.99);
+
+    let overlap = vina_score(&[1.5], &[c], &[c], 0);
+    assert!(overlap.score > 0.);
+    assert!(overlap.repulsion > 0.);
+
+    let h_bond = vina_score(&[2.7], &[n_donor], &[o_acceptor], 0);
+    assert!(h_bond.h_bond > 0.99);
+
+    // Rotatable bonds scale the score toward 0.
+    let flexible = vina_score(&[4.0], &[c], &[c], 5);
+    assert!(flexible.score > contact.score && flexible.score < 0.);
+
+    // Hydrogens are implicit.
+    let h = XsType::new(Hydrogen, &[Carbon]);
+    assert_eq!(vina_score(&[1.0], &[h], &[c], 0).score, 0.);
+}
+
+/// Grid maps hold the Vina pair terms, so interpolated energies match the Vina score: exactly on
+/// grid points, and to within interpolation error between them.
+#[test]
+fn test_grid_maps() {
+    use lin_alg::f32::Vec3;
+    use na_seq::Element::*;
+
+    use crate::docking::{
+        grid::GridMaps,
+        scoring::{XsType, vina_score},
+    };
+
+    let c = XsType::new(Carbon, &[Carbon, Hydrogen]).unwrap();
+    let n_donor = XsType::new(Nitrogen, &[Carbon, Hydrogen]).unwrap();
+    let o_acceptor = XsType::new(Oxygen, &[Carbon]);
+
+    let rec = [
+        (Vec3::new(0., 0., 0.), c),
+        (Vec3::new(1.5, 0., 0.), n_donor),
+    ];
+    let rec_types: Vec<_> = rec.iter().map(|r| Some(r.1)).collect();
+    let lig_types = [Some(c), o_acceptor, XsType::new(Hydrogen, &[Carbon])];
+
+    let maps = GridMaps::from_types(&rec, &lig_types, 2, Vec3::new_zero(), 6., 0.375);
+    assert_eq!(maps.types.len(), 2);
+
+    let score = |lig: &[Vec3]| {
+        let distances: Vec<_> = rec
+            .iter()
+            .flat_map(|(p, _)| lig.iter().map(move |l| (*l - *p).magnitude()))
+            .collect();
+        vina_score(&distances, &rec_types, &lig_types, 2).score
+    };
+
+    // On grid points.
+    let lig = [
+        Vec3::new(4.5, 0., 0.),
+        Vec3::new(0., 3.75, 0.),
+        Vec3::new(0., 0., 0.),
+    ];
+    assert!((maps.energy(&lig) - score(&lig)).abs() < 1e-5);
+
+    // Between them.
+    let lig = [
+        Vec3::new(2.9, 2.7, -0.4),
+        Vec3::new(3.3, -2.2, 0.7),
+        Vec3::new(0.1, 0.2, 0.3),
+    ];
+    assert!((maps.energy(&lig) - score(&lig)).abs() < 0.005);
+}
+
+/// Alternate locations: the highest occupancy is active by default; picking an ID swaps in its
+/// positions without changing atom indices.
+#[test]
+fn test_alt_loc() {
+    use lin_alg::f64::Vec3;
+
+    use crate::molecule::{AltLocPolicy, Atom, Molecule};
+
+    let policy = AltLocPolicy::HighestOccupancy;
+    assert_eq!(
+        policy.pick(&[(Some('A'), Some(0.4)), (Some('B'), Some(0.6))]),
+        1
+    );
+    // Ties go to the first ID.
+    assert_eq!(
+        policy.pick(&[(Some('B'), Some(0.5)), (Some('A'), Some(0.5))]),
+        1
+    );
+    assert_eq!(
+        AltLocPolicy::Id('A').pick(&[(Some('B'), Some(0.6)), (Some('A'), Some(0.4))]),
+        1
+    );
+
+    let atom = |x, alt_loc, occupancy| Atom {
+        posit: Vec3::new(x, 0., 0.),
+        alt_loc: Some(alt_loc),
+        occupancy: Some(occupancy),
+        ..Default::default()
+    };
+
+    let mut mol = Molecule {
+        atoms: vec![Atom::default(), atom(1., 'B', 0.6)],
+        alt_loc_atoms: vec![(1, atom(2., 'A', 0.4))],
+        ..Default::default()
+    };
+    assert_eq!(mol.alt_loc_ids(), vec!['A', 'B']);
+
+    mol.set_alt_loc(AltLocPolicy::Id('A'));
+    assert_eq!(mol.atoms[1].alt_loc, Some('A'));
+    assert_eq!(mol.atoms[1].posit.x, 2.);
+    assert_eq!(mol.alt_loc_atoms[0].1.alt_loc, Some('B'));
+
+    mol.set_alt_loc(AltLocPolicy::HighestOccupancy);
+    assert_eq!(mol.atoms[1].posit.x, 1.);
+    assert_eq!(mol.atoms.len(), 2);
+}
+
+#[test]
+fn test_assemblies() {
+    use std::io::Cursor;
+
+    use lin_alg::f64::Vec3;
+
+    use crate::assembly::load_assemblies;
+
+    let pdb = "\
+HEADER    TRANSFERASE                             01-JAN-00   1ABC
+REMARK 350 BIOMOLECULE: 1
+REMARK 350 AUTHOR DETERMINED BIOLOGICAL UNIT: DIMERIC
+REMARK 350 APPLY THE FOLLOWING TO CHAINS: A,
+REMARK 350                    AND CHAINS: B
+REMARK 350   BIOMT1   1  1.000000  0.000000  0.000000        0.00000
+REMARK 350   BIOMT2   1  0.000000  1.000000  0.000000        0.00000
+REMARK 350   BIOMT3   1  0.000000  0.000000  1.000000        0.00000
+REMARK 350   BIOMT1
+REMARK 350   BIOMT1   2 -1.000000  0.000000  0.000000       10.00000
+REMARK 350   BIOMT2   2  0.000000 -1.000000  0.000000        0.00000
+REMARK 350   BIOMT3   2  0.000000  0.000000  1.000000        0.00000
+";
+    let assemblies = load_assemblies(Cursor::new(pdb)).unwrap();
+    assert_eq!(assemblies.len(), 1);
+    assert_eq!(assemblies[0].details, "dimeric");
+    assert_eq!(assemblies[0].gens[0].chains, vec!["A", "B"]);
+    assert_eq!(assemblies[0].copy_count(), 2);
+
+    let ops = &assemblies[0].gens[0].ops;
+    assert!(ops[0].is_identity());
+    assert_eq!(ops[1].apply(Vec3::new(1., 2., 3.)), Vec3::new(9., -2., 3.));
+
+    let cif = "\
+data_1ABC
+#
+_pdbx_struct_assembly.id                   1
+_pdbx_struct_assembly.oligomeric_details   dimeric
+#
+_pdbx_struct_assembly_gen.assembly_id       1
+_pdbx_struct_assembly_gen.oper_expression   (1-2)
+_pdbx_struct_assembly_gen.asym_id_list      A,B
+#
+loop_
+_pdbx_struct_oper_list.id
+_pdbx_struct_oper_list.matrix[1][1]
+_pdbx_struct_oper_list.matrix[1][2]
+_pdbx_struct_oper_list.matrix[1][3]
+_pdbx_struct_oper_list.vector[1]
+_pdbx_struct_oper_list.matrix[2][1]
+_pdbx_struct_oper_list.matrix[2][2]
+_pdbx_struct_oper_list.matrix[2][3]
+_pdbx_struct_oper_list.vector[2]
+_pdbx_struct_oper_list.matrix[3][1]
+_pdbx_struct_oper_list.matrix[3][2]
+_pdbx_struct_oper_list.matrix[3][3]
+_pdbx_struct_oper_list.vector[3]
+1 1.0 0.0 0.0 0.0 0.0 1.0 0.0 0.0 0.0 0.0 1.0 0.0
+2 -1.0 0.0 0.0 10.0 0.0 -1.0 0.0 0.0 0.0 0.0 1.0 0.0
+#
+";
+    let assemblies = load_assemblies(Cursor::new(cif)).unwrap();
+    assert_eq!(assemblies.len(), 1);
+    assert_eq!(assemblies[0].to_string(), "1: dimeric (2 copies)");
+    assert_eq!(&assemblies[0].gens[0].ops, ops);
+}
+
+#[test]
+fn test_compact_atoms() {
+    use lin_alg::f64::Vec3;
+    use na_seq::AtomTypeInRes;
+
+    use crate::{
+        compact_atoms::CompactAtoms,
+        docking::prep::DockType,
+        molecule::{Atom, AtomRole, Molecule, PropVal},
+    };
+
+    let mut carbon = Atom {
+        serial_number: 7,
+        posit: Vec3::new(12.345, -6.789, 101.25),
+        element: Element::Carbon,
+        type_in_res: AtomTypeInRes::from_str("CA").ok(),
+        force_field_type: Some("CX".to_owned()),
+        dock_type: Some(DockType::C),
+        role: Some(AtomRole::C_Alpha),
+        residue: Some(3),
+        occupancy: Some(0.5),
+        partial_charge: Some(-0.25),
+        temperature_factor: Some(18.),
+        alt_loc: Some('B'),
+        anisou: Some([0.1, 0.2, 0.3, 0.01, 0.02, 0.03]),
+        in_ring: true,
+        aromatic: true,
+        ..Default::default()
+    };
+    carbon.props.insert("pka".to_owned(), PropVal::Float(4.2));
+
+    // Fields left as `None` or false must survive the sentinel packing too.
+    let atoms = vec![
+        carbon,
+        Atom {
+            element: Element::Oxygen,
+            hetero: true,
+            ..Default::default()
+        },
+    ];
+
+    let compact = CompactAtoms::from_atoms(&atoms);
+    assert_eq!(compact.len(), 2);
+
+    for (orig, rt) in atoms.iter().zip(compact.to_atoms()) {
+        assert_eq!(rt.serial_number, orig.serial_number);
+        assert!((rt.posit - orig.posit).magnitude() < 1e-4);
+        assert_eq!(rt.element, orig.element);
+        assert_eq!(rt.type_in_res, orig.type_in_res);
+        assert_eq!(rt.force_field_type, orig.force_field_type);
+        assert_eq!(rt.dock_type, orig.dock_type);
+        assert_eq!(rt.role, orig.role);
+        assert_eq!(rt.residue, orig.residue);
+        assert_eq!(rt.hetero, orig.hetero);
+        assert_eq!(rt.occupancy, orig.occupancy);
+        assert_eq!(rt.partial_charge, orig.partial_charge);
+        assert_eq!(rt.temperature_factor, orig.temperature_factor);
+        assert_eq!(rt.alt_loc, orig.alt_loc);
+        assert_eq!(rt.anisou, orig.anisou);
+        assert_eq!(rt.props, orig.props);
+        assert_eq!(rt.in_ring, orig.in_ring);
+        assert_eq!(rt.aromatic, orig.aromatic);
+    }
+
+    // The accessors give the same atoms whether or not the molecule is compacted.
+    let mut mol = Molecule {
+        atoms: atoms.clone(),
+        ..Default::default()
+    };
+    let posits = mol.atom_posits();
+
+    mol.compact();
+    assert!(mol.is_compact());
+    assert!(mol.atoms.is_empty());
+    assert_eq!(mol.atom_count(), 2);
+    assert_eq!(mol.atom(0).serial_number, 7);
+    assert_eq!(mol.atom(1).element, Element::Oxygen);
+    for (a, b) in mol.atom_posits().iter().zip(&posits) {
+        assert!((*a - *b).magnitude() < 1e-4);
+    }
+
+    mol.expand();
+    assert!(!mol.is_compact());
+    assert_eq!(mol.atoms.len(), 2);
+    assert_eq!(mol.atoms[0].force_field_type.as_deref(), Some("CX"));
+}
+
+#[test]
+fn test_session_round_trip() {
+    use std::{env, fs};
+
+    use bio_files::ResidueType;
+    use lin_alg::f64::{Quaternion as QuaternionF64, Vec3};
+    use na_seq::{AminoAcidProtenationVariant, AtomTypeInRes};
+
+    use crate::{
+        molecule::{Atom, Bond, BondCount, BondType, PropVal, Residue},
+        objects::{MolObject, ObjColorScheme},
+    };
+
+    let mut carbon = Atom {
+        serial_number: 1,
+        posit: Vec3::new(1., 2., 3.),
+        element: Element::Carbon,
+        type_in_res: AtomTypeInRes::from_str("CA").ok(),
+        force_field_type: Some("CX".to_owned()),
+        residue: Some(0),
+        partial_charge: Some(0.0337),
+        ..Default::default()
+    };
+    carbon.props.insert("pka".to_owned(), PropVal::Float(6.1));
+
+    let nitrogen = Atom {
+        serial_number: 2,
+        posit: Vec3::new(2.45, 2., 3.),
+        element: Element::Nitrogen,
+        type_in_res: AtomTypeInRes::from_str("N").ok(),
+        force_field_type: Some("N".to_owned()),
+        residue: Some(0),
+        partial_charge: Some(-0.4157),
+        ..Default::default()
+    };
+
+    let mut residue = Residue {
+        serial_number: 5,
+        res_type: ResidueType::AminoAcid(AminoAcid::His),
+        atoms: vec![0, 1],
+        dihedral: None,
+        props: Default::default(),
+        variant: Some(AminoAcidProtenationVariant::Hie),
+    };
+    residue
+        .props
+        .insert("note".to_owned(), PropVal::Text("site".to_owned()));
+
+    let mol = Molecule {
+        ident: "TEST".to_owned(),
+        atoms: vec![carbon, nitrogen],
+        bonds: vec![Bond {
+            bond_type: BondType::Covalent {
+                count: BondCount::Single,
+            },
+            atom_0: 0,
+            atom_1: 1,
+            is_backbone: true,
+            in_ring: false,
+            aromatic: false,
+        }],
+        residues: vec![residue],
+        ..Default::default()
+    };
+
+    let mut state = State::default();
+    state.molecule = Some(mol.clone());
+
+    let mut obj = MolObject::new(mol.clone(), (0.2, 0.4, 0.6));
+    obj.visible = false;
+    obj.color_scheme = ObjColorScheme::Element;
+    obj.offset = Vec3::new(10., 0., -5.);
+    obj.orientation = QuaternionF64::from_axis_angle(Vec3::new(0., 0., 1.), 1.);
+    state.objects.push(obj);
+
+    let path = env::temp_dir().join(format!(
+        "daedalus_test_{}.{}",
+        std::process::id(),
+        save_load::SESSION_EXT
+    ));
+    state.save_session(&path, &Camera::default()).unwrap();
+
+    let mut loaded = State::default();
+    let result = loaded.open_session(&path);
+    fs::remove_file(&path).ok();
+    result.unwrap();
+
+    let check = |rt: &Molecule| {
+        assert_eq!(rt.ident, mol.ident);
+        assert_eq!(rt.atoms.len(), mol.atoms.len());
+        for (a, b) in rt.atoms.iter().zip(&mol.atoms) {
+            assert_eq!(a.serial_number, b.serial_number);
+            assert!((a.posit - b.posit).magnitude() < 1e-9);
+            assert_eq!(a.element, b.element);
+            assert_eq!(a.type_in_res, b.type_in_res);
+            assert_eq!(a.force_field_type, b.force_field_type);
+            assert_eq!(a.partial_charge, b.partial_charge);
+            assert_eq!(a.residue, b.residue);
+            assert_eq!(a.props, b.props);
+        }
+        assert_eq!(rt.bonds.len(), 1);
+        assert_eq!(rt.adjacency_list[0], vec![1]);
+
+        let res = &rt.residues[0];
+        assert_eq!(res.serial_number, 5);
+        assert!(matches!(
+            res.res_type,
+            ResidueType::AminoAcid(AminoAcid::His)
+        ));
+        assert_eq!(res.variant, Some(AminoAcidProtenationVariant::Hie));
+        assert_eq!(res.props, mol.residues[0].props);
+    };
+
+    check(loaded.molecule.as_ref().unwrap());
+
+    assert_eq!(loaded.objects.len(), 1);
+    let obj = &loaded.objects[0];
+    check(&obj.mol);
+    assert!(!obj.visible);
+    assert_eq!(obj.color_scheme, ObjColorScheme::Element);
+    assert_eq!(obj.color, (0.2, 0.4, 0.6));
+    assert!((obj.offset - Vec3::new(10., 0., -5.)).magnitude() < 1e-9);
+    assert!((obj.orientation.w - state.objects[0].orientation.w).abs() < 1e-9);
+}
+
+#[test]
+fn test_mc_tri_table() {
+    use std::collections::HashMap;
+
+    use crate::sa_surface::{CUBE_EDGES, MC_TABLE_ROW, mc_tri_table};
+
+    let table = mc_tri_table();
+
+    for (case, row) in table.iter().enumerate() {
+        let inside = |corner: usize| case & (1 << corner) != 0;
+
+        let n = row.iter().position(|&e| e < 0).unwrap();
+        assert!(n < MC_TABLE_ROW && n % 3 == 0, "case {case}");
+        let tris: Vec<_> = row[..n]
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+            .collect();
+
+        // Vertices are exactly on the edges the surface crosses.
+        let mut active: Vec<usize> = (0..12)
+            .filter(|&e| inside(CUBE_EDGES[e].0) != inside(CUBE_EDGES[e].1))
+            .collect();
+        let mut used: Vec<usize> = tris.iter().flatten().copied().collect();
+        used.sort();
+        used.dedup();
+        active.sort();
+        assert_eq!(used, active, "case {case}");
+
+        // Sides not shared by two triangles in the cube are on its faces, and close around
+        // each vertex; this is what neighboring cubes join with.
+        let mut sides = HashMap::new();
+        for t in &tris {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *sides.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        let mut boundary_count = [0; 12];
+        for (&(a, b), &count) in &sides {
+            if count % 2 == 0 {
+                continue;
+            }
+            let corners = [
+                CUBE_EDGES[a].0,
+                CUBE_EDGES[a].1,
+                CUBE_EDGES[b].0,
+                CUBE_EDGES[b].1,
+            ];
+            let on_face = (0..3).any(|axis| {
+                corners
+                    .iter()
+                    .all(|c| (c >> axis) & 1 == (corners[0] >> axis) & 1)
+            });
+            assert!(on_face, "case {case}: side {a}-{b}");
+
+            boundary_count[a] += 1;
+            boundary_count[b] += 1;
+        }
+        for e in active {
+            assert_eq!(boundary_count[e], 2, "case {case}: edge {e}");
+        }
+    }
+}
+
+/// Sidechains built from χ angles measure back to the same angles.
+#[test]
+fn test_sidechain_χ_round_trip() {
+    use std::f64::consts::TAU;
+
+    use lin_alg::f64::{Quaternion, Vec3};
+
+    use crate::aa_coords::{
+        PHI_SHEET,
+        bond_vecs::{CALPHA_CP_BOND, LEN_CP_N, LEN_N_CALPHA, N_CALPHA_BOND, residue_geometry},
+        build_peptide::sidechain_atoms,
+        sc_atom_placement::find_atom_placement,
+        sidechain::Sidechain,
+    };
+
+    let geom = residue_geometry();
+    let n = Vec3::new_zero();
+    let n_or = Quaternion::new_identity();
+    let cp_prev = n + n_or.rotate_vec(geom.n_cp) * LEN_CP_N;
+
+    let (c_alpha, c_alpha_or) = find_atom_placement(
+        n_or,
+        geom.calpha_n,
+        CALPHA_CP_BOND,
+        PHI_SHEET,
+        n,
+        cp_prev,
+        N_CALPHA_BOND,
+        LEN_N_CALPHA,
+    );
+
+    let χ_set = [1.1, -2.3, 2.9, -0.6, 0.4];
+
+    for aa in [
+        AminoAcid::Arg,
+        AminoAcid::His,
+        AminoAcid::Lys,
+        AminoAcid::Asp,
+        AminoAcid::Glu,
+        AminoAcid::Ser,
+        AminoAcid::Thr,
+        AminoAcid::Asn,
+        AminoAcid::Gln,
+        AminoAcid::Cys,
+        AminoAcid::Sec,
+        AminoAcid::Val,
+        AminoAcid::Ile,
+        AminoAcid::Leu,
+        AminoAcid::Met,
+        AminoAcid::Phe,
+        AminoAcid::Tyr,
+        AminoAcid::Trp,
+    ] {
+        let mut sc = Sidechain::from_aa_type(aa);
+        for (i, χ) in χ_set.iter().enumerate() {
+            let angle = match i {
+                0 => sc.get_mut_χ1(),
+                1 => sc.get_mut_χ2(),
+                2 => sc.get_mut_χ3(),
+                3 => sc.get_mut_χ4(),
+                _ => sc.get_mut_χ5(),
+            };
+            if let Some(a) = angle {
+                *a = *χ;
+            }
+        }
+
+        let atoms = sidechain_atoms(&sc, c_alpha, c_alpha_or, n, geom);
+        let measured = Sidechain::from_coords(aa, |name| match name {
+            "N" => Some(n),
+            "CA" => Some(c_alpha),
+            _ => atoms.iter().find(|a| a.0 == name).map(|a| a.2),
+        });
+
+        let expected = sc.χ_angles();
+        assert_eq!(measured.χ_angles().len(), expected.len());
+
+        for (m, e) in measured.χ_angles().into_iter().zip(expected) {
+            let diff = (m - e).rem_euclid(TAU);
+            assert!(
+                diff.min(TAU - diff) < 1e-6,
+                "{aa:?}: χ measured {m:.4}; expected {e:.4}"
+            );
+        }
+    }
+}
@@ -1,43 +1,107 @@
 use std::{
     f32::consts::TAU,
+    f64::consts::PI,
     io,
     io::Cursor,
     path::Path,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::TryRecvError,
+    },
     time::Instant,
 };
 
 use bio_apis::{drugbank, pubchem, rcsb};
-use egui::{Color32, ComboBox, Context, Key, RichText, Slider, TextEdit, TopBottomPanel, Ui};
+use egui::{
+    Button, Color32, ComboBox, Context, DragValue, Key, RichText, Slider, TextEdit, TopBottomPanel,
+    Ui,
+};
 use graphics::{ControlScheme, EngineUpdates, RIGHT_VEC, Scene, UP_VEC};
-use lin_alg::f32::{Quaternion, Vec3};
-use na_seq::AaIdent;
+use lin_alg::{
+    f32::{Quaternion, Vec3},
+    f64::{Quaternion as QuaternionF64, Vec3 as Vec3F64},
+};
+use log::{debug, error, info};
+use na_seq::{AaIdent, Element};
+use rayon::prelude::*;
 
 static INIT_COMPLETE: AtomicBool = AtomicBool::new(false);
 
 use bio_files::{DensityMap, ResidueType, density_from_2fo_fc_rcsb_gemmi};
 
 use crate::{
-    CamSnapshot, MsaaSetting, Selection, State, ViewSelLevel, cli,
+    CamSnapshot, MsaaSetting, Selection, State, ViewSelLevel,
+    aa_coords::{
+        build_peptide::{BackbonePreset, build_peptide},
+        loop_model::{build_loop, find_chain_breaks},
+    },
+    analysis::{
+        clashes::{CLASH_OVERLAP_MIN, Clash, atom_label, find_clashes},
+        hydration::{add_waters, place_waters},
+        interface::{analyze_interface, ligand_bsa},
+        mmgbsa::{MMGBSA_FRAMES_MAX, Mmgbsa, mmgbsa_frame, mmgbsa_md},
+        plif::{calc_plif, cluster_plifs, residue_occupancy, residue_union},
+        pockets::{Pocket, find_pockets},
+        residue_energy::{RES_ENERGY_CUTOFF, decompose_energy},
+    },
+    blink::{BLINK_INTERVAL_MAX, BLINK_INTERVAL_MIN, BlinkFrame, blink_start, blink_stop},
+    cli,
     cli::autocomplete_cli,
     docking::{
-        ConformationType, calc_binding_energy,
-        dynamics::{build_dock_dynamics, change_snapshot_md},
+        ConformationType,
+        am1bcc::{CHARGE_CACHE_DIR, antechamber_avail, start_am1bcc},
+        calc_binding_energy,
+        conformers::{ConformerParams, apply_conformer, generate_conformers},
+        dock_ligand,
+        dynamics::{change_snapshot_md, minimize_ligand, start_dock_md},
         external::check_adv_avail,
-        find_optimal_pose,
         find_sites::find_docking_sites,
+        partial_charge::gasteiger_charges,
+        pharmacophore::PharmFeature,
+        search::{RankedPose, SearchParams, minimize_poses, start_pose_search},
+        waters::find_site_waters,
     },
     download_mols::{load_sdf_drugbank, load_sdf_pubchem},
-    inputs::{MOVEMENT_SENS, ROTATE_SENS},
+    dynamics::{
+        barostat::BarostatParams,
+        external_fields::SphereContainment,
+        gamd::GamdParams,
+        minimize::MinimizeAlgorithm,
+        postprocess::PbcMode,
+        protein::{PROTEIN_MD_DT, start_protein_md},
+        protocol::{ANNEAL_TEMP_HIGH_DEFAULT, TempProtocol, TempStage},
+        report::save_reports_csv,
+        solvent::{SolvationConfig, WaterModel},
+        steered::SteeringParams,
+        thermostat::{BERENDSEN_TAU_DEFAULT, NHC_CHAIN_LEN_DEFAULT, NHC_TAU_DEFAULT, Thermostat},
+        torsion_scan::torsion_scan,
+    },
+    events::ViewerEvent,
+    logging,
+    logging::LogLevel,
+    mcs::{McsAlignment, align_by_mcs},
     mol_drawing::{
-        EntityType, MoleculeView, draw_density, draw_density_surface, draw_ligand, draw_molecule,
+        EntityType, MoleculeView, SurfaceColoring, draw_density, draw_density_surface, draw_ligand,
+        draw_molecule, draw_objects, draw_partial_surfaces, draw_pockets,
     },
-    molecule::{Ligand, Molecule},
-    render::{
-        CAM_INIT_OFFSET, RENDER_DIST_FAR, RENDER_DIST_NEAR, set_docking_light, set_flashlight,
-        set_static_light,
+    molecule::{AltLocPolicy, AtomRole, Ligand, Molecule, PropVal},
+    objects::{ObjColorScheme, ObjectId},
+    progress::TaskStatus,
+    protonation::PROP_PKA,
+    sa_surface::calc_sasa,
+    save_load::SESSION_EXT,
+    scene::{
+        CAM_INIT_OFFSET, MOVEMENT_SENS, RENDER_DIST_FAR, RENDER_DIST_NEAR, ROTATE_SENS,
+        VIEW_DEPTH_FAR_MAX, VIEW_DEPTH_FAR_MIN, VIEW_DEPTH_NEAR_MAX, VIEW_DEPTH_NEAR_MIN,
+        set_docking_light, set_flashlight, set_static_light,
     },
-    ui_aux, util,
+    superpose::{
+        PairMode, atoms_for_mode, pair_atoms, residue_map_by_alignment, residue_map_by_numbering,
+        superpose,
+    },
+    ui_aux, ui_plots,
+    units::{AngleUnit, EnergyUnit},
+    util,
     util::{
         cam_look_at, cam_look_at_outside, check_prefs_save, close_lig, close_mol,
         cycle_res_selected, handle_err, handle_scene_flags, load_atom_coords_rcsb, orbit_center,
@@ -46,14 +110,14 @@ use crate::{
 };
 
 pub const ROW_SPACING: f32 = 10.;
-pub const COL_SPACING: f32 = 30.;
 
-// These are divided by 10.
-pub const VIEW_DEPTH_NEAR_MIN: u16 = 2;
-pub const VIEW_DEPTH_NEAR_MAX: u16 = 300;
-
-pub const VIEW_DEPTH_FAR_MIN: u16 = 10;
-pub const VIEW_DEPTH_FAR_MAX: u16 = 60;
+/// kcal/mol/Å². Spring constant for keeping the ligand in the docking site during MD.
+const CONTAINMENT_K: f64 = 10.;
+/// Docking MD runs this many steps of `DOCK_MD_DT`: 50 ps.
+const DOCK_MD_STEPS: usize = 50_000;
+/// fs. As for `dynamics::protein::PROTEIN_MD_DT`.
+const DOCK_MD_DT: f64 = 1.;
+pub const COL_SPACING: f32 = 30.;
 
 const DENS_ISO_MIN: f32 = 1.0;
 const DENS_ISO_MAX: f32 = 3.0;
@@ -72,33 +136,24 @@ const COLOR_OUT_SUCCESS: Color32 = Color32::LIGHT_GREEN; // Unused for now
 // Number of characters to display. E.g. the molecular description. Often long.
 const MAX_TITLE_LEN: usize = 80;
 
+/// Tanimoto similarity for MD snapshots to share an interaction fingerprint cluster.
+const PLIF_CLUSTER_SIM: f64 = 0.7;
+/// Residues contacted by at least this fraction of screened ligands are reported as anchors.
+const ANCHOR_OCCUPANCY_MIN: f32 = 0.5;
+const PROP_CONTACT_OCCUPANCY: &str = "contact_occupancy";
+/// Å². Receptor area buried by the ligand, per residue.
+const PROP_DSASA_LIG: &str = "dsasa_lig";
+/// kcal/mol. Ligand interaction energy, per residue.
+const PROP_LIG_ENERGY: &str = "lig_energy";
+/// kcal/mol. Residues interacting with the ligand at least this favorably are hot spots.
+const HOT_SPOT_THRESH: f64 = -1.;
+
 /// Update the tilebar to reflect the current molecule
 fn set_window_title(title: &str, scene: &mut Scene) {
     scene.window_title = title.to_owned();
     // ui.ctx().send_viewport_cmd(ViewportCommand::Title(title.to_string()));
 }
 
-pub fn load_file(
-    path: &Path,
-    state: &mut State,
-    redraw: &mut bool,
-    reset_cam: &mut bool,
-    engine_updates: &mut EngineUpdates,
-) -> io::Result<()> {
-    state.open(path)?;
-
-    // Clear last map opened here, vice in `open_molecule`, to prevent it clearing the map
-    // on init.
-
-    state.to_save.last_map_opened = None;
-
-    *redraw = true;
-    *reset_cam = true;
-    engine_updates.entities = true;
-
-    Ok(())
-}
-
 fn _int_field(val: &mut usize, label: &str, redraw: &mut bool, ui: &mut Ui) {
     ui.label(label);
     ui.label(label);
@@ -129,7 +184,7 @@ pub fn handle_input(
         // Check for file drop
         if let Some(dropped_files) = ip.raw.dropped_files.first() {
             if let Some(path) = &dropped_files.path {
-                if let Err(e) = load_file(path, state, redraw, reset_cam, engine_updates) {
+                if let Err(e) = load_file(path, state, true, redraw, reset_cam, engine_updates) {
                     handle_err(&mut state.ui, e.to_string());
                 }
             }
@@ -457,6 +512,83 @@ fn chain_selector(state: &mut State, redraw: &mut bool, ui: &mut Ui) {
     });
 }
 
+/// Show the copies of chains making up a biological assembly, or build it as a new molecule.
+fn assemblies(state: &mut State, redraw: &mut bool, ui: &mut Ui) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+    if mol.assemblies.is_empty() {
+        return;
+    }
+
+    let prev = state.ui.assembly;
+    let mut build = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Assembly:");
+
+        let selected = match state.ui.assembly.and_then(|i| mol.assemblies.get(i)) {
+            Some(asm) => asm.to_string(),
+            None => "(None)".to_owned(),
+        };
+
+        ComboBox::from_id_salt(47)
+            .width(160.)
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.ui.assembly, None, "(None)");
+                for (i, asm) in mol.assemblies.iter().enumerate() {
+                    ui.selectable_value(&mut state.ui.assembly, Some(i), asm.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Show the biological assembly: copies of chains, placed using the operators \
+                from the file.",
+            );
+
+        if state.ui.assembly.is_some() {
+            build = ui
+                .button(RichText::new("Build").color(COLOR_HIGHLIGHT))
+                .on_hover_text(
+                    "Create atoms for each copy in the assembly. This replaces the open molecule.",
+                )
+                .clicked();
+        }
+    });
+
+    if state.ui.assembly != prev {
+        let cmd = match state.ui.assembly {
+            Some(i) => format!("assembly {}", mol.assemblies[i].id),
+            None => "assembly off".to_owned(),
+        };
+        cli::record_cmd(state, &cmd);
+        *redraw = true;
+    }
+
+    if build {
+        let Some(i) = state.ui.assembly else {
+            return;
+        };
+        // `state` may have been borrowed mutably to record the selection above.
+        let Some(mol) = &state.molecule else {
+            return;
+        };
+        let cmd = format!("assembly {}, atoms", mol.assemblies[i].id);
+        let Some(mol) = mol.build_assembly(i) else {
+            handle_err(&mut state.ui, "Unable to build this assembly".to_owned());
+            return;
+        };
+        cli::record_cmd(state, &cmd);
+
+        state.ui.cmd_line_output = format!("Built an assembly with {} atoms", mol.atoms.len());
+        state.ui.cmd_line_out_is_err = false;
+
+        state.open_built_molecule(mol);
+        *redraw = true;
+    }
+}
+
 // todo: Update params A/R
 fn draw_cli(
     state: &mut State,
@@ -520,7 +652,7 @@ fn draw_cli(
         }
 
         if dn_pressed {
-            if state.volatile.cli_input_selected < state.volatile.cli_input_history.len() - 1 {
+            if state.volatile.cli_input_selected + 1 < state.volatile.cli_input_history.len() {
                 state.volatile.cli_input_selected += 1;
             }
             if state.volatile.cli_input_history.len() > state.volatile.cli_input_selected {
@@ -538,7 +670,7 @@ fn draw_cli(
                         out
                     }
                     Err(e) => {
-                        eprintln!("Error processing command");
+                        error!("Error processing command");
                         state.ui.cmd_line_out_is_err = true;
                         e.to_string()
                     }
@@ -548,7 +680,57 @@ fn draw_cli(
             // Compensates for the default lose focus behavior; we still want the cursor to remain here.
             edit_resp.request_focus();
         }
+
+        ui.add_space(COL_SPACING);
+
+        let recording = state.volatile.macro_recording.is_some();
+        let (text, color) = if recording {
+            ("⏹ Stop", Color32::LIGHT_RED)
+        } else {
+            ("⏺ Record", COLOR_INACTIVE)
+        };
+        if ui
+            .button(RichText::new(text).color(color))
+            .on_hover_text("Record commands, and some UI actions, as a macro.")
+            .clicked()
+        {
+            if recording {
+                state.volatile.macro_last = state.volatile.macro_recording.take().unwrap();
+            } else {
+                state.volatile.macro_recording = Some(Vec::new());
+            }
+        }
+
+        if let Some(cmds) = &state.volatile.macro_recording {
+            ui.label(RichText::new(format!("{} cmds", cmds.len())).color(Color32::LIGHT_RED));
+        } else if !state.volatile.macro_last.is_empty() && ui.button("Save macro").clicked() {
+            state.volatile.dialogs.save_macro.save_file();
+        }
+
+        if ui
+            .button("Run script")
+            .on_hover_text(
+                "Run a script of commands, e.g. a saved macro, on the current structure.",
+            )
+            .clicked()
+        {
+            state.volatile.dialogs.load_script.pick_file();
+        }
     });
+
+    if let Some(path) = &state.volatile.dialogs.load_script.take_picked() {
+        let result = cli::run_script(path, state, scene, engine_updates, redraw, reset_cam);
+        (state.ui.cmd_line_output, state.ui.cmd_line_out_is_err) = match result {
+            Ok(out) => (out, false),
+            Err(e) => (e.to_string(), true),
+        };
+    }
+
+    if let Some(path) = &state.volatile.dialogs.save_macro.take_picked() {
+        if let Err(e) = cli::save_macro(&state.volatile.macro_last, path) {
+            handle_err(&mut state.ui, format!("Problem saving the macro: {e}"));
+        }
+    }
 }
 
 fn docking(
@@ -570,6 +752,8 @@ fn docking(
     }
 
     let mut docking_posit_update = None;
+    let mut pose_sel = None;
+    let mut dock_clicked = false;
 
     ui.horizontal(|ui| {
         let mol = state.molecule.as_ref().unwrap();
@@ -578,43 +762,77 @@ fn docking(
         if ui.button("Find sites").clicked() {
             let sites = find_docking_sites(mol);
             for site in sites {
-                println!("Docking site: {:?}", site);
+                debug!("Docking site: {:?}", site);
             }
         }
 
         if ui.button("Dock").clicked() {
-            // todo: Ideally move the camera to the docking site prior to docking. You could do this
-            // todo by deferring the docking below to the next frame.
+            // Allow the user to select the autodock executable.
+            // if state.to_save.autodock_vina_path.is_none() {
+            //     state.volatile.autodock_path_dialog.pick_file();
+            // }
+            // dock_with_vina(mol, ligand, &state.to_save.autodock_vina_path);
+            dock_clicked = true;
+        }
 
-            let (pose, binding_energy) = find_optimal_pose(
-                &state.dev,
-                state.volatile.docking_setup.as_ref().unwrap(),
-                lig,
-            );
+        if ui
+            .button("Conformers")
+            .on_hover_text(
+                "Generate low-energy conformers by sampling rotatable bond torsions, and apply the \
+                lowest-energy one. These seed the docking search.",
+            )
+            .clicked()
+        {
+            state.ui.conformers =
+                generate_conformers(lig, &state.lj_lookup_table, &ConformerParams::default());
+
+            if let Some(c) = state.ui.conformers.first() {
+                apply_conformer(lig, c);
+                info!(
+                    "Generated {} conformers. Lowest energy: {}",
+                    state.ui.conformers.len(),
+                    state.to_save.energy_unit.fmt(c.energy as f64)
+                );
+                *redraw_lig = true;
+            }
+        }
 
-            lig.pose = pose;
+        if ui
+            .button("Search")
+            .on_hover_text(
+                "Search for poses with independent Monte Carlo runs, and rank them by Vina score.",
+            )
+            .clicked()
+            && state.volatile.pose_search.is_none()
+        {
+            state.volatile.pose_search = Some(start_pose_search(
+                state.volatile.docking_setup.clone().unwrap(),
+                lig.clone(),
+                SearchParams {
+                    constraints: state.ui.pharm_constraints.clone(),
+                    conformers: state.ui.conformers.clone(),
+                    ..Default::default()
+                },
+            ));
+        }
 
-            lig.position_atoms(None);
+        ui.checkbox(&mut state.ui.dock_minimize, "Min")
+            .on_hover_text(
+                "After searching, minimize each pose in the receptor's field with the MD force \
+                field, including flexible residues, then rescore.",
+            );
 
+        if !state.ui.docked_poses.is_empty() {
+            let color = ui_aux::active_color(state.ui.show_dock_results);
+            if ui
+                .button(
+                    RichText::new(format!("{} poses", state.ui.docked_poses.len())).color(color),
+                )
+                .on_hover_text("Show the pose clusters from the last search, with their scores.")
+                .clicked()
             {
-                lig.position_atoms(None);
-
-                lig.position_atoms(None);
-                let lig_pos: Vec3 = lig.atom_posits[lig.anchor_atom].into();
-                let ctr: Vec3 = mol.center.into();
-
-                cam_look_at_outside(&mut scene.camera, lig_pos, ctr);
-
-                engine_updates.camera = true;
-                state.ui.cam_snapshot = None;
+                state.ui.show_dock_results = !state.ui.show_dock_results;
             }
-
-            // Allow the user to select the autodock executable.
-            // if state.to_save.autodock_vina_path.is_none() {
-            //     state.volatile.autodock_path_dialog.pick_file();
-            // }
-            // dock_with_vina(mol, ligand, &state.to_save.autodock_vina_path);
-            *redraw_lig = true;
         }
 
         if ui.button("Docking energy").clicked() {
@@ -789,527 +1007,3442 @@ fn docking(
         state.update_save_prefs();
     }
 
+    if let Some(search) = &state.volatile.pose_search {
+        // If cancelled, runs not yet started are skipped; we show poses from the others.
+        ui_aux::progress_bar("Pose search", &search.progress, ui);
+
+        match search.poll() {
+            TaskStatus::Running => (),
+            TaskStatus::Done(poses) => {
+                state.volatile.pose_search = None;
+                pose_sel = finish_pose_search(state, poses);
+            }
+            TaskStatus::Failed => {
+                state.volatile.pose_search = None;
+                handle_err(&mut state.ui, "The pose search failed".to_owned());
+            }
+        }
+    }
+
+    if let Some(i) = pose_sel {
+        if select_docked_pose(state, i) {
+            draw_molecule(state, scene);
+            engine_updates.entities = true;
+        }
+        *redraw_lig = true;
+    }
+
+    if dock_clicked {
+        if let Err(e) = dock_ligand(state, scene, engine_updates) {
+            handle_err(&mut state.ui, e.to_string());
+        }
+        *redraw_lig = true;
+    }
+
+    pharm_constraints(state, ui);
+    site_waters(state, ui);
+
     ui.horizontal(|ui| {
         // Workaround for double-borrow.
         let mut run_clicked = false;
 
-        run_clicked = ui.button("Run MD docking").clicked();
-        if run_clicked {
-            // If not already loaded from static string to state, do so now.
-            // We load on demand to save computation.
-            state.load_ffs_general();
-        }
-        if run_clicked {
-            // todo: This nesting is sloppy. if let something, with an early return?
-            // state.volatile.snapshots = build_dock_dynamics(
-            //     &state.dev,
-            //     lig,
-            //     state.volatile.docking_setup.as_ref().unwrap(),
-            //     1_500,
-            // );
-
-            // Set up the atom posits to be IOC the pose.
-            // println!("P before: {}", lig.atom_posits[0]);
-            // lig.position_atoms(None);
+        run_clicked =
+            ui.button("Run MD docking").clicked() && state.volatile.md_run.is_none();
 
-            // // Sync atom posits with pose.
-            // match &lig.pose.conformation_type {
-            //     ConformationType::AbsolutePosits => {
-            //         println!("Abs")
-            //     }
-            //     ConformationType::Flexible { torsions} => {
-            //         println!("Flexible");
-            //     }
-            // }
+        let minimize_clicked = ui
+            .button("Minimize lig")
+            .on_hover_text(
+                "Minimize the ligand's energy in the docking site, with the receptor rigid. Use this to \
+                relax clashes and strain before docking or running dynamics.",
+            )
+            .clicked();
 
-            let mol = state.molecule.as_ref().unwrap();
-            let lig = state.ligand.as_mut().unwrap();
+        let cfg = &mut state.ui.md_config;
 
-            match build_dock_dynamics(
-                &state.dev,
-                lig,
-                state.volatile.docking_setup.as_ref().unwrap(),
-                &state.ff_params,
-                &mol.residues,
-                1_500,
-            ) {
-                Ok(md) => {
-                    state.mol_dynamics = Some(md);
-                    state.ui.current_snapshot = 0;
+        ComboBox::from_id_salt(25)
+            .width(110.)
+            .selected_text(cfg.minimize.algorithm.to_str())
+            .show_ui(ui, |ui| {
+                for alg in [MinimizeAlgorithm::Lbfgs, MinimizeAlgorithm::SteepestDescent] {
+                    ui.selectable_value(&mut cfg.minimize.algorithm, alg, alg.to_str());
                 }
-                Err(e) => handle_err(&mut state.ui, e.descrip),
-            }
+            })
+            .response
+            .on_hover_text("The energy minimization algorithm");
+
+        let mut gamd = cfg.gamd.is_some();
+        if ui
+            .checkbox(&mut gamd, "GaMD")
+            .on_hover_text(
+                "Gaussian accelerated MD: boost the potential after an initial conventional MD stage.",
+            )
+            .changed()
+        {
+            cfg.gamd = gamd.then(GamdParams::default);
         }
 
-        if let Some(md) = &state.mol_dynamics {
-            if !md.snapshots.is_empty() {
-                // if !state.volatile.snapshots.is_empty() {
-                ui.add_space(ROW_SPACING);
+        let mut contain = cfg.external_fields.sphere.is_some();
+        if ui
+            .checkbox(&mut contain, "Contain")
+            .on_hover_text("Keep the ligand within the docking site, using a spherical wall.")
+            .changed()
+        {
+            // The center and radius are synced to the docking site when running.
+            cfg.external_fields.sphere = contain.then(|| SphereContainment {
+                center: Vec3F64::new_zero(),
+                radius: 0.,
+                k: CONTAINMENT_K,
+            });
+        }
 
-                let snapshot_prev = state.ui.current_snapshot;
-                ui.spacing_mut().slider_width = ui.available_width() - 100.;
-                ui.add(Slider::new(
-                    &mut state.ui.current_snapshot,
-                    // 0..=state.volatile.snapshots.len() - 1,
-                    0..=md.snapshots.len() - 1, // todo exper
-                ));
+        ComboBox::from_id_salt(26)
+            .width(130.)
+            .selected_text(cfg.thermostat.map_or("No thermostat", |t| t.to_str()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut cfg.thermostat, None, "No thermostat");
+                for thermostat in [
+                    Thermostat::Berendsen {
+                        tau: BERENDSEN_TAU_DEFAULT,
+                    },
+                    Thermostat::NoseHooverChain {
+                        tau: NHC_TAU_DEFAULT,
+                        chain_len: NHC_CHAIN_LEN_DEFAULT,
+                    },
+                ] {
+                    ui.selectable_value(&mut cfg.thermostat, Some(thermostat), thermostat.to_str());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Berendsen equilibrates quickly. Nosé-Hoover chains sample the canonical (NVT) \
+                ensemble deterministically. Without a thermostat, energy is conserved. (NVE)",
+            );
 
-                if state.ui.current_snapshot != snapshot_prev {
-                    // change_snapshot(
-                    //     &mut scene.entities,
-                    //     lig,
-                    //     &Vec::new(),
-                    //     &mut state.ui.binding_energy_disp,
-                    //     &state.volatile.snapshots[state.ui.current_snapshot],
-                    // );
-
-                    let lig = state.ligand.as_mut().unwrap();
-
-                    change_snapshot_md(
-                        &mut scene.entities,
-                        lig,
-                        &Vec::new(),
-                        &mut state.ui.binding_energy_disp,
-                        &md.snapshots[state.ui.current_snapshot],
-                    );
+        if cfg.thermostat.is_some() {
+            if cfg.protocol.is_none() {
+                ui.label("T (K):");
+                ui.add(
+                    DragValue::new(&mut cfg.target_temp)
+                        .range(0. ..=1_000.)
+                        .speed(1.),
+                );
+            }
 
-                    draw_ligand(state, scene);
+            let mut anneal = cfg.protocol.is_some();
+            if ui
+                .checkbox(&mut anneal, "Anneal")
+                .on_hover_text(
+                    "Run a temperature schedule, vice a constant temperature: e.g. heat, hold, \
+                    then cool slowly, to refine the pose.",
+                )
+                .changed()
+            {
+                cfg.protocol = anneal.then(|| {
+                    TempProtocol::anneal(
+                        cfg.target_temp,
+                        ANNEAL_TEMP_HIGH_DEFAULT,
+                        10_000,
+                        10_000,
+                        30_000,
+                    )
+                });
+            }
+        }
 
-                    engine_updates.entities = true;
-                }
+        if cfg.thermostat.is_none() {
+            let mut init = cfg.init_temp.is_some();
+            if ui
+                .checkbox(&mut init, "Init T")
+                .on_hover_text(
+                    "Start with velocities drawn at this temperature, vice at rest. With a \
+                    thermostat, we start at its target temperature.",
+                )
+                .changed()
+            {
+                cfg.init_temp = init.then_some(cfg.target_temp);
+            }
+            if let Some(temp) = &mut cfg.init_temp {
+                ui.add(DragValue::new(temp).range(0. ..=1_000.).speed(1.));
             }
         }
-    });
-}
 
-fn residue_search(state: &mut State, scene: &mut Scene, redraw: &mut bool, ui: &mut Ui) {
-    ui.horizontal(|ui| {
-        // let sel_prev = &state.ui.selection;
-        ui.label("Find residue:");
+        let mut seeded = cfg.velocity_seed.is_some();
         if ui
-            .add(TextEdit::singleline(&mut state.ui.residue_search).desired_width(60.))
+            .checkbox(&mut seeded, "Seed")
+            .on_hover_text("Draw initial velocities with a fixed random seed, so runs are reproducible.")
             .changed()
         {
-            select_from_search(state);
-            *redraw = true;
+            cfg.velocity_seed = seeded.then_some(0);
+        }
+        if let Some(seed) = &mut cfg.velocity_seed {
+            ui.add(DragValue::new(seed));
         }
 
-        // if sel_prev != &state.ui.selection {
-        //     *redraw = true;
-        // }
+        ui.label("COM").on_hover_text(
+            "Remove center-of-mass motion every this many steps. 0 to disable. Skipped with a \
+            static receptor, frozen atoms, restraints, or pulling.",
+        );
+        ui.add(
+            DragValue::new(&mut cfg.com_removal_interval)
+                .range(0..=100_000)
+                .speed(10),
+        );
 
-        // todo: for UI adccessbility
-        // if key_up_is_down {
-        //     up_button.highlight();
-        // }
-        if state.molecule.is_some() {
+        let mut pull = cfg.steering.is_some();
+        if ui
+            .checkbox(&mut pull, "Pull")
+            .on_hover_text(
+                "Steered MD: pull the selected ligand atom, or the whole ligand, out of the docking \
+                site with a moving spring. Records force against distance.",
+            )
+            .changed()
+        {
+            // The atoms and direction are set from the selection and docking site when running.
+            cfg.steering = pull.then(SteeringParams::default);
+        }
+
+        if let Some(steering) = &mut cfg.steering {
+            ui.label("k:");
+            ui.add(
+                DragValue::new(&mut steering.k)
+                    .range(0.1..=100.)
+                    .speed(0.1),
+            )
+            .on_hover_text("Spring constant, in kcal/mol/Å²");
+
+            ui.label("v (Å/ps):");
+            ui.add(
+                DragValue::new(&mut steering.speed)
+                    .range(0.001..=100.)
+                    .speed(0.01),
+            );
+        }
+
+        if let Some(lig) = &state.ligand {
+            let color = ui_aux::active_color(!cfg.restrained.is_empty());
             if ui
-                .button("Prev AA")
-                .on_hover_text("Hotkey: Left arrow")
+                .button(RichText::new(format!("Restrain ({})", cfg.restrained.len())).color(color))
+                .on_hover_text(
+                    "Tether the selected ligand atom, or if none, all ligand heavy atoms, to their \
+                    starting positions with harmonic springs. Click again to clear.",
+                )
                 .clicked()
             {
-                cycle_res_selected(state, scene, true);
-                *redraw = true;
+                cfg.restrained = if cfg.restrained.is_empty() {
+                    lig_atoms_for_sel(&state.ui.selection, lig)
+                } else {
+                    Vec::new()
+                };
             }
-            // todo: DRY
+
+            if !cfg.restrained.is_empty() {
+                ui.add(
+                    DragValue::new(&mut cfg.restraint_k)
+                        .range(0.1..=1_000.)
+                        .speed(0.5),
+                )
+                .on_hover_text("Restraint spring constant, in kcal/mol/Å²");
+            }
+
+            let color = ui_aux::active_color(!cfg.frozen.is_empty());
             if ui
-                .button("Next AA")
-                .on_hover_text("Hotkey: Right arrow")
+                .button(RichText::new(format!("Freeze ({})", cfg.frozen.len())).color(color))
+                .on_hover_text(
+                    "Hold the selected ligand atom, or if none, all ligand heavy atoms, fixed. \
+                    They still exert forces on other atoms. Click again to clear.",
+                )
                 .clicked()
             {
-                cycle_res_selected(state, scene, false);
-                *redraw = true;
+                cfg.frozen = if cfg.frozen.is_empty() {
+                    lig_atoms_for_sel(&state.ui.selection, lig)
+                } else {
+                    Vec::new()
+                };
             }
+        }
 
-            ui.add_space(COL_SPACING * 2.);
-
-            let dock_tools_text = if state.ui.show_docking_tools {
-                "Hide docking tools"
-            } else {
-                "Show docking tools (Broken/WIP)"
-            };
-
-            if ui.button(RichText::new(dock_tools_text)).clicked() {
-                state.ui.show_docking_tools = !state.ui.show_docking_tools;
+        let color = ui_aux::active_color(!cfg.flexible_residues.is_empty());
+        if ui
+            .button(RichText::new(format!("Flex res ({})", cfg.flexible_residues.len())).color(color))
+            .on_hover_text(
+                "Make the selected receptor residue's sidechain mobile, for induced fit; its \
+                backbone stays fixed. Click again to toggle it back. Click with no residue \
+                selected to clear all.",
+            )
+            .clicked()
+        {
+            match state.ui.selection {
+                Selection::Residue(r) => {
+                    if let Some(i) = cfg.flexible_residues.iter().position(|f| *f == r) {
+                        cfg.flexible_residues.remove(i);
+                    } else {
+                        cfg.flexible_residues.push(r);
+                    }
+                }
+                _ => cfg.flexible_residues.clear(),
             }
+        }
 
-            ui.add_space(COL_SPACING / 2.);
+        let nb = &mut cfg.nonbonded_scaling;
+        ui.label("1-4 SCEE")
+            .on_hover_text("1-4 Coulomb interactions are divided by this. Amber: 1.2. OPLS: 2.");
+        ui.add(DragValue::new(&mut nb.scee).range(1. ..=10.).speed(0.05));
+        ui.label("SCNB").on_hover_text(
+            "1-4 Lennard-Jones interactions are divided by this. Amber and OPLS: 2.",
+        );
+        ui.add(DragValue::new(&mut nb.scnb).range(1. ..=10.).speed(0.05));
+        ui.label("Excl").on_hover_text(
+            "Exclude nonbonded interactions between atoms this many bonds apart, or fewer. 2 \
+            excludes 1-2 and 1-3 pairs, as in Amber and OPLS; 3 excludes 1-4 pairs too.",
+        );
+        ui.add(DragValue::new(&mut nb.exclusion_depth).range(1..=3));
 
-            let dock_seq_text = if state.ui.show_aa_seq {
-                "Hide seq"
-            } else {
-                "Show seq"
-            };
+        ui.checkbox(&mut cfg.allow_missing_params, "Missing params")
+            .on_hover_text(
+                "Run even if force field parameters are missing, using fallbacks, or omitting the \
+                terms. If not set, we list the missing parameters instead of running.",
+            );
 
-            if ui.button(RichText::new(dock_seq_text)).clicked() {
-                state.ui.show_aa_seq = !state.ui.show_aa_seq;
-            }
+        let mut solvate = cfg.solvation.is_some();
+        if ui
+            .checkbox(&mut solvate, "Water")
+            .on_hover_text("Surround the system with explicit water, filling a periodic box.")
+            .changed()
+        {
+            cfg.solvation = solvate.then(SolvationConfig::default);
         }
-    });
-}
 
-fn add_aa_seq(seq_text: &str, ui: &mut Ui) {
-    ui.horizontal_wrapped(|ui| {
-        ui.label(RichText::new(seq_text).color(Color32::LIGHT_BLUE));
-    });
-}
+        if let Some(solvation) = &mut cfg.solvation {
+            ComboBox::from_id_salt(27)
+                .width(100.)
+                .selected_text(solvation.model.to_str())
+                .show_ui(ui, |ui| {
+                    for model in [WaterModel::Tip3p, WaterModel::Spce, WaterModel::Opc3] {
+                        ui.selectable_value(&mut solvation.model, model, model.to_str());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Flexible variants of these rigid models, starting from an unequilibrated \
+                    lattice. Equilibrate before production runs, and use a time step of 1 fs or less.",
+                );
 
-fn selection_section(
-    state: &mut State,
-    scene: &mut Scene,
-    redraw: &mut bool,
-    engine_updates: &mut EngineUpdates,
-    ui: &mut Ui,
-) {
-    // todo: DRY with view.
-    ui.horizontal(|ui| {
-        ui.label("View/Select:");
-        let prev_view = state.ui.view_sel_level;
-        ComboBox::from_id_salt(1)
-            .width(80.)
-            .selected_text(state.ui.view_sel_level.to_string())
-            .show_ui(ui, |ui| {
-                for view in &[ViewSelLevel::Atom, ViewSelLevel::Residue] {
-                    ui.selectable_value(&mut state.ui.view_sel_level, *view, view.to_string());
-                }
-            });
+            ui.label("Pad (Å):");
+            ui.add(
+                DragValue::new(&mut solvation.padding)
+                    .range(3. ..=30.)
+                    .speed(0.5),
+            )
+            .on_hover_text("Minimum distance from the solute to the box edges.");
+        }
 
-        if state.ui.view_sel_level != prev_view {
-            *redraw = true;
-            // Kludge to prevent surprising behavior.
-            state.ui.selection = Selection::None;
+        let mut npt = cfg.barostat.is_some();
+        if ui
+            .checkbox(&mut npt, "NPT")
+            .on_hover_text(
+                "Couple pressure with a Monte Carlo barostat, scaling the box. Use with a thermostat.",
+            )
+            .changed()
+        {
+            cfg.barostat = npt.then(BarostatParams::default);
         }
 
-        // Buttons to alter the color profile, e.g. for res position, or partial charge.
-        ui.add_space(COL_SPACING / 2.);
-        match state.ui.view_sel_level {
-            ViewSelLevel::Atom => {
-                let color = if state.ui.atom_color_by_charge {
-                    COLOR_ACTIVE
-                } else {
-                    COLOR_INACTIVE
-                };
+        if let Some(baro) = &mut cfg.barostat {
+            ui.label("P (bar):");
+            ui.add(
+                DragValue::new(&mut baro.pressure)
+                    .range(0. ..=10_000.)
+                    .speed(0.1),
+            );
+
+            ui.label("Every:");
+            ui.add(DragValue::new(&mut baro.frequency).range(1..=1_000))
+                .on_hover_text("Attempt a volume change every this many steps.");
+        }
+
+        ui.label("Report every:");
+        ui.add(DragValue::new(&mut cfg.report_interval).range(0..=10_000))
+            .on_hover_text("Record energies, temperature, and pressure every this many steps. 0 to disable.");
+
+        let color = ui_aux::active_color(state.ui.show_md_energy);
+        if ui
+            .button(RichText::new("Energies").color(color))
+            .on_hover_text("Plot energies, temperature, and pressure over the run.")
+            .clicked()
+        {
+            state.ui.show_md_energy = !state.ui.show_md_energy;
+        }
+
+        let color = ui_aux::active_color(state.ui.show_trajectory);
+        if ui
+            .button(RichText::new("Trajectory").color(color))
+            .on_hover_text("Plot RMSD, RMSF, radius of gyration, and ligand metrics over the run.")
+            .clicked()
+        {
+            state.ui.show_trajectory = !state.ui.show_trajectory;
+        }
+
+        if let Some(md) = &state.mol_dynamics {
+            if let (Some(first), Some(last)) = (md.snapshots.first(), md.snapshots.last()) {
+                let drift = last.energy_conserved - first.energy_conserved;
+                ui.label(format!(
+                    "Drift: {}",
+                    state.to_save.energy_unit.fmt(drift)
+                ))
+                .on_hover_text(
+                    "Change in the conserved energy over the run. Large values indicate too long \
+                    a time step. Not conserved with Berendsen or GaMD.",
+                );
+            }
+
+            if let Some(steering) = &md.steering {
+                let unit = state.to_save.energy_unit;
+                ui.label(format!(
+                    "Pull max F: {:.2} kcal/mol/Å  Work: {}",
+                    steering.force_max(),
+                    unit.fmt(steering.work)
+                ));
 
                 if ui
-                    .button(RichText::new("Color by q").color(color))
+                    .button("Save pull log")
+                    .on_hover_text(
+                        "Save the force-distance profile and accumulated work, to pull_log.csv",
+                    )
                     .clicked()
                 {
-                    state.ui.atom_color_by_charge = !state.ui.atom_color_by_charge;
-                    state.ui.view_sel_level = ViewSelLevel::Atom;
-                    *redraw = true;
+                    if let Err(e) = steering.save_log(Path::new("pull_log.csv")) {
+                        handle_err(&mut state.ui, format!("Problem saving the pull log: {e}"));
+                    }
                 }
             }
-            ViewSelLevel::Residue => {
-                let color = if state.ui.res_color_by_index {
-                    COLOR_ACTIVE
-                } else {
-                    COLOR_INACTIVE
-                };
+
+            if let Some(gamd) = &md.gamd {
+                let (mean, std_dev, max) = gamd.boost_stats();
+                let unit = state.to_save.energy_unit;
+                ui.label(format!(
+                    "Boost mean: {}  σ: {}  max: {}",
+                    unit.fmt(mean),
+                    unit.fmt(std_dev),
+                    unit.fmt(max)
+                ));
 
                 if ui
-                    .button(RichText::new("Color by res #").color(color))
+                    .button("Save GaMD log")
+                    .on_hover_text("Save boost potentials for reweighting, to gamd_log.csv")
                     .clicked()
                 {
-                    state.ui.res_color_by_index = !state.ui.res_color_by_index;
-                    state.ui.view_sel_level = ViewSelLevel::Residue;
-                    *redraw = true;
+                    if let Err(e) = gamd.save_log(Path::new("gamd_log.csv")) {
+                        handle_err(&mut state.ui, format!("Problem saving the GaMD log: {e}"));
+                    }
                 }
             }
         }
 
-        ui.add_space(COL_SPACING);
+        if run_clicked || minimize_clicked {
+            // If not already loaded from static string to state, do so now.
+            // We load on demand to save computation.
+            state.load_ffs_general();
+        }
 
-        ui.label("Nearby sel only:");
-        if ui.checkbox(&mut state.ui.show_near_sel_only, "").changed() {
-            *redraw = true;
+        if minimize_clicked {
+            if let (Some(mol), Some(lig), Some(setup)) = (
+                &mut state.molecule,
+                &mut state.ligand,
+                &state.volatile.docking_setup,
+            ) {
+                match minimize_ligand(
+                    lig,
+                    setup,
+                    &state.ff_params,
+                    &mol.residues,
+                    &state.ui.md_config,
+                ) {
+                    Ok((result, flex_posits)) => {
+                        state.ui.cmd_line_output = result.descrip();
+                        state.ui.cmd_line_out_is_err = false;
 
-            // todo: For now, only allow one of near sel/lig
-            if state.ui.show_near_sel_only {
-                state.ui.show_near_lig_only = false
+                        for (i, posit) in &flex_posits {
+                            mol.atoms[*i].posit = *posit;
+                        }
+
+                        draw_ligand(state, scene);
+                        if !flex_posits.is_empty() {
+                            draw_molecule(state, scene);
+                        }
+                        engine_updates.entities = true;
+                    }
+                    Err(e) => handle_err(&mut state.ui, e.to_string()),
+                }
+            } else {
+                handle_err(
+                    &mut state.ui,
+                    "Minimizing requires a molecule, ligand, and docking site".to_owned(),
+                );
             }
         }
+        if run_clicked {
+            // todo: This nesting is sloppy. if let something, with an early return?
+            // state.volatile.snapshots = build_dock_dynamics(
+            //     &state.dev,
+            //     lig,
+            //     state.volatile.docking_setup.as_ref().unwrap(),
+            //     1_500,
+            // );
 
-        if state.ligand.is_some() {
-            ui.label("Nearby lig only:");
-            if ui.checkbox(&mut state.ui.show_near_lig_only, "").changed() {
-                *redraw = true;
+            // Set up the atom posits to be IOC the pose.
+            // println!("P before: {}", lig.atom_posits[0]);
+            // lig.position_atoms(None);
 
-                // todo: For now, only allow one of near sel/lig
-                if state.ui.show_near_lig_only {
-                    state.ui.show_near_sel_only = false
-                }
+            // // Sync atom posits with pose.
+            // match &lig.pose.conformation_type {
+            //     ConformationType::AbsolutePosits => {
+            //         println!("Abs")
+            //     }
+            //     ConformationType::Flexible { torsions} => {
+            //         println!("Flexible");
+            //     }
+            // }
+
+            let mol = state.molecule.as_ref().unwrap();
+            let lig = state.ligand.as_ref().unwrap();
+
+            if let Some(sphere) = &mut state.ui.md_config.external_fields.sphere {
+                sphere.center = lig.docking_site.site_center;
+                sphere.radius = lig.docking_site.site_radius;
             }
-        }
 
-        if state.ui.show_near_sel_only || state.ui.show_near_lig_only {
-            ui.label("Dist:");
-            let dist_prev = state.ui.nearby_dist_thresh;
-            ui.add(Slider::new(
-                &mut state.ui.nearby_dist_thresh,
-                NEARBY_THRESH_MIN..=NEARBY_THRESH_MAX,
-            ));
+            if let Some(steering) = &mut state.ui.md_config.steering {
+                steering.atoms = match state.ui.selection {
+                    Selection::AtomLigand(i) => vec![i],
+                    _ => (0..lig.atom_posits.len()).collect(),
+                };
 
-            if state.ui.nearby_dist_thresh != dist_prev {
-                *redraw = true;
+                // Pull from the site center, through the group's centroid.
+                let centroid = steering
+                    .atoms
+                    .iter()
+                    .fold(Vec3F64::new_zero(), |acc, &i| acc + lig.atom_posits[i])
+                    / steering.atoms.len().max(1) as f64;
+                let dir = centroid - lig.docking_site.site_center;
+                steering.direction = if dir.magnitude() > 1e-3 {
+                    dir.to_normalized()
+                } else {
+                    Vec3F64::new(1., 0., 0.)
+                };
             }
+
+            // todo For now. GPU currently is going slower than CPU for VDW.
+            // Applied once done; see `handle_scene_flags`.
+            state.volatile.md_run = Some(start_dock_md(
+                lig.clone(),
+                state.volatile.docking_setup.clone().unwrap(),
+                state.ff_params.clone(),
+                mol.residues.clone(),
+                DOCK_MD_STEPS,
+                DOCK_MD_DT,
+                state.ui.md_config.clone(),
+            ));
         }
 
-        if let Some(mol) = &state.molecule {
-            ui.add_space(COL_SPACING);
+        if state.mol_dynamics.as_ref().is_some_and(|md| md.has_ligand()) {
+            md_snapshot_slider(state, scene, engine_updates, ui);
+        }
+    });
 
-            if state.ui.selection != Selection::None {
-                if ui
-                    .button(RichText::new("Move cam to sel").color(COLOR_HIGHLIGHT))
-                    .clicked()
-                {
-                    if let Selection::AtomLigand(i) = &state.ui.selection {
-                        if let Some(lig) = &state.ligand {
-                            cam_look_at(&mut scene.camera, lig.atom_posits[*i]);
-                            engine_updates.camera = true;
-                            state.ui.cam_snapshot = None;
-                        }
-                    } else {
-                        let atom_sel = mol.get_sel_atom(&state.ui.selection);
+    let cfg = &mut state.ui.md_config;
+    if cfg.thermostat.is_some() {
+        if let Some(protocol) = &mut cfg.protocol {
+            temp_protocol(protocol, ui);
+        }
+    }
+}
 
-                        if let Some(atom) = atom_sel {
-                            cam_look_at(&mut scene.camera, atom.posit);
-                            engine_updates.camera = true;
-                            state.ui.cam_snapshot = None;
-                        }
-                    }
-                }
-            }
+/// Run MD on the whole protein, e.g. to relax it after adding hydrogens, or editing residues.
+/// Add and remove interactions the docking search requires, from the selected receptor atom.
+fn pharm_constraints(state: &mut State, ui: &mut Ui) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
 
-            if let Some(lig) = &mut state.ligand {
-                ui.add_space(COL_SPACING / 2.);
-                if ui
-                    .button(RichText::new("Move cam to lig").color(COLOR_HIGHLIGHT))
-                    .clicked()
-                {
-                    if lig.anchor_atom >= lig.molecule.atoms.len() {
-                        handle_err(
-                            &mut state.ui,
-                            "Problem positioning ligand atoms. Len shorter than anchor.".to_owned(),
-                        );
-                    } else {
-                        lig.position_atoms(None);
+    let sel_atom = match state.ui.selection {
+        Selection::Atom(i) => Some(i),
+        _ => None,
+    };
 
-                        let lig_pos: Vec3 = lig.atom_posits[lig.anchor_atom].into();
-                        let ctr: Vec3 = mol.center.into();
+    let mut remove = None;
 
-                        cam_look_at_outside(&mut scene.camera, lig_pos, ctr);
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Constraints:");
 
-                        engine_updates.camera = true;
-                        state.ui.cam_snapshot = None;
-                    }
+        if let Some(i) = sel_atom {
+            if ui
+                .button("+ H bond")
+                .on_hover_text("Require a hydrogen bond between the ligand and the selected atom.")
+                .clicked()
+            {
+                match PharmFeature::h_bond(mol, i) {
+                    Some(f) => state.ui.pharm_constraints.features.push(f),
+                    None => handle_err(
+                        &mut state.ui,
+                        "The selected atom can't donate or accept a hydrogen bond".to_owned(),
+                    ),
                 }
             }
 
-            ui.add_space(COL_SPACING / 2.);
-            ui_aux::selected_data(mol, &state.ligand, &state.ui.selection, ui);
+            if ui
+                .button("+ Hydrophobic")
+                .on_hover_text(
+                    "Require a hydrophobic ligand atom in contact with the selected atom, e.g. \
+                    one lining a sub-pocket.",
+                )
+                .clicked()
+            {
+                if let Some(f) = PharmFeature::hydrophobic(mol, i) {
+                    state.ui.pharm_constraints.features.push(f);
+                }
+            }
+        } else {
+            ui.label("(Select a receptor atom to add)");
         }
-    });
-}
-
-fn mol_descrip(mol: &Molecule, ui: &mut Ui) {
-    ui.heading(RichText::new(mol.ident.clone()).color(Color32::GOLD));
 
-    ui.label(format!("{} atoms", mol.atoms.len()));
+        let constraints = &mut state.ui.pharm_constraints;
+        if constraints.features.is_empty() {
+            return;
+        }
 
-    if let Some(method) = mol.method {
-        ui.label(method.to_str_short());
-    }
+        ui.checkbox(&mut constraints.required, "Required")
+            .on_hover_text(
+                "Drop poses that miss any constraint. Otherwise, penalize them in the search.",
+            );
 
-    if let Some(metadata) = &mol.metadata {
-        // Limit size to prevent UI problems.
-        let mut title: String = metadata
-            .prim_cit_title
-            .chars()
-            .take(MAX_TITLE_LEN)
-            .collect();
-        if title.len() != metadata.prim_cit_title.len() {
-            title += "...";
+        for (i, feature) in constraints.features.iter().enumerate() {
+            ui.label(feature.to_str());
+            if ui.button("❌").clicked() {
+                remove = Some(i);
+            }
         }
-        ui.label(RichText::new(title).color(Color32::WHITE).size(12.));
-    }
+    });
 
-    if mol.ident.len() <= 5 {
-        // todo: You likely need a better approach.
-        if ui.button("View on RCSB").clicked() {
-            rcsb::open_overview(&mol.ident);
-        }
+    if let Some(i) = remove {
+        state.ui.pharm_constraints.features.remove(i);
     }
+}
 
-    if let Some(id) = &mol.drugbank_id {
-        if ui.button("View on Drugbank").clicked() {
+/// Find waters in the docking site, and choose which to keep as part of the receptor when docking.
+fn site_waters(state: &mut State, ui: &mut Ui) {
+    let (Some(mol), Some(lig)) = (&state.molecule, &mut state.ligand) else {
+        return;
+    };
+
+    let mut changed = false;
+
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Waters:");
+
+        if ui
+            .button("Find")
+            .on_hover_text(
+                "Find crystallographic waters in the docking site, and keep conserved ones: those \
+                with multiple hydrogen bonds to the receptor, and low B factors.",
+            )
+            .clicked()
+        {
+            state.ui.site_waters = find_site_waters(mol, &lig.docking_site);
+            lig.site_waters = state
+                .ui
+                .site_waters
+                .iter()
+                .filter(|w| w.conserved)
+                .map(|w| w.atom)
+                .collect();
+            changed = true;
+
+            if state.ui.site_waters.is_empty() {
+                ui.label("(None in the site)");
+            }
+        }
+
+        for water in &state.ui.site_waters {
+            let mut keep = lig.site_waters.contains(&water.atom);
+            let text = RichText::new(water.descrip(mol)).color(if water.conserved {
+                COLOR_HIGHLIGHT
+            } else {
+                Color32::GRAY
+            });
+
+            if ui
+                .checkbox(&mut keep, text)
+                .on_hover_text("Keep this water as part of the receptor when docking.")
+                .changed()
+            {
+                if keep {
+                    lig.site_waters.push(water.atom);
+                } else {
+                    lig.site_waters.retain(|w| *w != water.atom);
+                }
+                changed = true;
+            }
+        }
+    });
+
+    if changed {
+        state.volatile.docking_setup = None;
+        state.get_make_docking_setup();
+    }
+}
+
+/// Minimize a finished search's poses if set to, and show them. Returns the pose to select.
+fn finish_pose_search(state: &mut State, mut poses: Vec<RankedPose>) -> Option<usize> {
+    if state.ui.dock_minimize {
+        if let (Some(mol), Some(lig), Some(setup)) = (
+            &state.molecule,
+            &state.ligand,
+            &state.volatile.docking_setup,
+        ) {
+            if let Err(e) = minimize_poses(
+                &mut poses,
+                lig,
+                setup,
+                &state.ff_params,
+                &mol.residues,
+                &state.ui.md_config,
+            ) {
+                handle_err(&mut state.ui, e.to_string());
+            }
+        }
+    }
+
+    state.ui.docked_poses = poses;
+
+    if state.ui.docked_poses.is_empty() {
+        handle_err(&mut state.ui, "Pose search found no poses".to_owned());
+        None
+    } else {
+        state.ui.show_dock_results = true;
+        Some(0)
+    }
+}
+
+/// Move the ligand to a pose from the last search, and score it. Returns `true` if this moved
+/// flexible receptor atoms.
+pub fn select_docked_pose(state: &mut State, i: usize) -> bool {
+    let (Some(lig), Some(setup), Some(ranked)) = (
+        &mut state.ligand,
+        &state.volatile.docking_setup,
+        state.ui.docked_poses.get(i),
+    ) else {
+        return false;
+    };
+
+    if let Some(mol) = &mut state.molecule {
+        for (j, posit) in &ranked.flex_posits {
+            mol.atoms[*j].posit = *posit;
+        }
+    }
+
+    state.ui.docked_pose_i = i;
+    lig.pose = ranked.pose.clone();
+    lig.atom_posits = ranked.atom_posits.clone();
+
+    let lig_posits: Vec<_> = lig.atom_posits.iter().map(|p| (*p).into()).collect();
+    let binding_energy = calc_binding_energy(setup, lig, &lig_posits);
+
+    if let Some(binding_energy) = &binding_energy {
+        state.events.emit(ViewerEvent::DockingPoseUpdated {
+            pose: lig.pose.clone(),
+            binding_energy: binding_energy.clone(),
+        });
+    }
+    state.ui.binding_energy_disp = binding_energy;
+
+    !ranked.flex_posits.is_empty()
+}
+
+fn protein_md(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    if state.molecule.is_none() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Protein MD: steps");
+        ui.add(
+            DragValue::new(&mut state.ui.protein_md_steps)
+                .range(0..=1_000_000)
+                .speed(100),
+        );
+
+        if ui
+            .button("Run")
+            .on_hover_text(
+                "Simulate the protein with all of its atoms mobile, and bonded terms from ff19SB. \
+                Uses the thermostat, barostat, solvation, and temperature protocol from the MD \
+                docking settings. Hetero atoms, e.g. waters and ligands, are omitted. Requires \
+                hydrogens, and force field types; assign protonation states first.",
+            )
+            .clicked()
+            && state.volatile.md_run.is_none()
+        {
+            state.load_ffs_general();
+            if let Some(mol) = &state.molecule {
+                // Applied once done; see `handle_scene_flags`.
+                state.volatile.md_run = Some(start_protein_md(
+                    mol.clone(),
+                    state.ff_params.clone(),
+                    state.ui.protein_md_steps,
+                    PROTEIN_MD_DT,
+                    state.ui.md_config.clone(),
+                ));
+            }
+        }
+    });
+
+    // Ligand runs have their slider in the docking section.
+    if state
+        .mol_dynamics
+        .as_ref()
+        .is_some_and(|md| !md.has_ligand())
+    {
+        md_snapshot_slider(state, scene, engine_updates, ui);
+    }
+}
+
+/// Step through MD snapshots, updating the ligand, and any mobile receptor atoms.
+fn md_snapshot_slider(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    if let Some(md) = &state.mol_dynamics {
+        if !md.snapshots.is_empty() {
+            // if !state.volatile.snapshots.is_empty() {
+            ui.add_space(ROW_SPACING);
+
+            let snapshot_prev = state.ui.current_snapshot;
+            ui.spacing_mut().slider_width = ui.available_width() - 100.;
+            ui.add(Slider::new(
+                &mut state.ui.current_snapshot,
+                // 0..=state.volatile.snapshots.len() - 1,
+                0..=md.snapshots.len() - 1, // todo exper
+            ));
+
+            if state.ui.current_snapshot != snapshot_prev {
+                state.events.emit(ViewerEvent::MdFrameAdvanced {
+                    snapshot: state.ui.current_snapshot,
+                });
+
+                // change_snapshot(
+                //     &mut scene.entities,
+                //     lig,
+                //     &Vec::new(),
+                //     &mut state.ui.binding_energy_disp,
+                //     &state.volatile.snapshots[state.ui.current_snapshot],
+                // );
+
+                show_md_snapshot(state, scene, engine_updates);
+            }
+
+            if md_postprocess(state, ui) {
+                show_md_snapshot(state, scene, engine_updates);
+            }
+        }
+    }
+
+    if state.mol_dynamics.is_some() {
+        md_live_controls(state, ui);
+    }
+}
+
+/// Interactive MD: continue the run in real time, and optionally pull atoms with the mouse.
+fn md_live_controls(state: &mut State, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        let color = ui_aux::active_color(state.ui.md_live);
+        if ui
+            .button(RichText::new("Live").color(color))
+            .on_hover_text("Continue this MD run in real time, updating the view each frame.")
+            .clicked()
+        {
+            state.ui.md_live = !state.ui.md_live;
+        }
+
+        let color = ui_aux::active_color(state.ui.md_pull);
+        if ui
+            .button(RichText::new("Pull").color(color))
+            .on_hover_text(
+                "While running live, hold the right mouse button to pull the selected atom, \
+                residue, or atoms toward the cursor with a spring.",
+            )
+            .clicked()
+        {
+            state.ui.md_pull = !state.ui.md_pull;
+        }
+    });
+}
+
+/// Update the ligand, and any mobile receptor atoms, to the current MD snapshot.
+fn show_md_snapshot(state: &mut State, scene: &mut Scene, engine_updates: &mut EngineUpdates) {
+    let Some(md) = &state.mol_dynamics else {
+        return;
+    };
+    let Some(snapshot) = md.snapshots.get(state.ui.current_snapshot) else {
+        return;
+    };
+
+    let rec_posits = md.flexible_posits_at(snapshot);
+    let has_lig = md.has_ligand();
+
+    if has_lig {
+        if let Some(lig) = &mut state.ligand {
+            change_snapshot_md(
+                &mut scene.entities,
+                lig,
+                &Vec::new(),
+                &mut state.ui.binding_energy_disp,
+                snapshot,
+            );
+        }
+    }
+
+    if let Some(mol) = &mut state.molecule {
+        for (i, posit) in &rec_posits {
+            mol.atoms[*i].posit = *posit;
+        }
+    }
+
+    if has_lig {
+        draw_ligand(state, scene);
+    }
+    if !rec_posits.is_empty() {
+        draw_molecule(state, scene);
+    }
+
+    engine_updates.entities = true;
+}
+
+/// Fix periodic boundary artifacts in MD snapshots, and superpose them. Returns `true` if the
+/// snapshots changed.
+fn md_postprocess(state: &mut State, ui: &mut Ui) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("PBC:");
+        ComboBox::from_id_salt(43)
+            .width(90.)
+            .selected_text(state.ui.md_pbc_mode.map_or("None", |m| m.to_str()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.ui.md_pbc_mode, None, "None");
+                for mode in [PbcMode::Unwrap, PbcMode::Reimage] {
+                    ui.selectable_value(&mut state.ui.md_pbc_mode, Some(mode), mode.to_str());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Make molecules whole across the periodic box, then unwrap them so paths are \
+                continuous, or reimage them around the largest molecule.",
+            );
+
+        ui.checkbox(&mut state.ui.md_align, "Align").on_hover_text(
+            "Superpose each snapshot onto the first, over the selected receptor atoms, or if none \
+            are selected, the simulated Cα atoms.",
+        );
+
+        if !ui
+            .button("Process")
+            .on_hover_text("Apply these to all snapshots.")
+            .clicked()
+        {
+            return;
+        }
+
+        let Some(md) = &mut state.mol_dynamics else {
+            return;
+        };
+
+        if let Some(mode) = state.ui.md_pbc_mode {
+            if let Err(e) = md.fix_pbc(mode) {
+                handle_err(&mut state.ui, e.descrip);
+                return;
+            }
+            changed = true;
+        }
+
+        if state.ui.md_align {
+            let Some(mol) = &state.molecule else {
+                return;
+            };
+
+            let selected: Vec<usize> = match &state.ui.selection {
+                Selection::Atom(i) => vec![*i],
+                Selection::Atoms(atoms) => atoms.clone(),
+                Selection::Residue(r) => mol
+                    .residues
+                    .get(*r)
+                    .map(|res| res.atoms.clone())
+                    .unwrap_or_default(),
+                _ => Vec::new(),
+            };
+
+            // Mobile atom indices.
+            let fit_atoms: Vec<_> = md
+                .flexible
+                .iter()
+                .filter(|(_, i_rec)| {
+                    if selected.is_empty() {
+                        mol.atoms[*i_rec].role == Some(AtomRole::C_Alpha)
+                    } else {
+                        selected.contains(i_rec)
+                    }
+                })
+                .map(|(i_md, _)| *i_md)
+                .collect();
+
+            match md.align_snapshots(&fit_atoms) {
+                Ok(()) => changed = true,
+                Err(e) => handle_err(&mut state.ui, e.descrip),
+            }
+        }
+    });
+
+    changed
+}
+
+/// The selected ligand atom, or if none is selected, all of the ligand's heavy atoms.
+fn lig_atoms_for_sel(sel: &Selection, lig: &Ligand) -> Vec<usize> {
+    match sel {
+        Selection::AtomLigand(i) => vec![*i],
+        _ => lig
+            .molecule
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.element != Element::Hydrogen)
+            .map(|(i, _)| i)
+            .collect(),
+    }
+}
+
+/// Edit the stages of a temperature schedule. Each stage ramps linearly between its temperatures.
+fn temp_protocol(protocol: &mut TempProtocol, ui: &mut Ui) {
+    let mut remove = None;
+
+    for (i, stage) in protocol.stages.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(stage.kind().to_str()).color(COLOR_HIGHLIGHT));
+
+            ui.label("T (K):");
+            ui.add(
+                DragValue::new(&mut stage.temp_start)
+                    .range(0. ..=1_000.)
+                    .speed(1.),
+            );
+            ui.label("→");
+            ui.add(
+                DragValue::new(&mut stage.temp_end)
+                    .range(0. ..=1_000.)
+                    .speed(1.),
+            );
+
+            ui.label("Steps:");
+            ui.add(
+                DragValue::new(&mut stage.n_steps)
+                    .range(0..=1_000_000)
+                    .speed(100.),
+            );
+
+            if ui
+                .button(RichText::new("❌").color(Color32::LIGHT_RED))
+                .on_hover_text("Remove this stage")
+                .clicked()
+            {
+                remove = Some(i);
+            }
+        });
+    }
+
+    if let Some(i) = remove {
+        protocol.stages.remove(i);
+    }
+
+    ui.horizontal(|ui| {
+        if ui
+            .button("Add stage")
+            .on_hover_text("Add a stage starting at the last stage's final temperature.")
+            .clicked()
+        {
+            let temp = protocol.stages.last().map_or(300., |s| s.temp_end);
+            protocol.stages.push(TempStage {
+                temp_start: temp,
+                temp_end: temp,
+                n_steps: 10_000,
+            });
+        }
+
+        ui.label(format!("{} steps total", protocol.n_steps()))
+            .on_hover_text(protocol.descrip());
+    });
+}
+
+fn residue_search(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    redraw: &mut bool,
+    ui: &mut Ui,
+) {
+    ui.horizontal(|ui| {
+        // let sel_prev = &state.ui.selection;
+        ui.label("Find residue:");
+        if ui
+            .add(TextEdit::singleline(&mut state.ui.residue_search).desired_width(60.))
+            .changed()
+        {
+            select_from_search(state);
+            *redraw = true;
+        }
+
+        // if sel_prev != &state.ui.selection {
+        //     *redraw = true;
+        // }
+
+        // todo: for UI adccessbility
+        // if key_up_is_down {
+        //     up_button.highlight();
+        // }
+        if state.molecule.is_some() {
+            if ui
+                .button("Prev AA")
+                .on_hover_text("Hotkey: Left arrow")
+                .clicked()
+            {
+                if cycle_res_selected(state, scene, true) {
+                    engine_updates.camera = true;
+                    engine_updates.lighting = true;
+                }
+                *redraw = true;
+            }
+            // todo: DRY
+            if ui
+                .button("Next AA")
+                .on_hover_text("Hotkey: Right arrow")
+                .clicked()
+            {
+                if cycle_res_selected(state, scene, false) {
+                    engine_updates.camera = true;
+                    engine_updates.lighting = true;
+                }
+                *redraw = true;
+            }
+
+            ui.checkbox(&mut state.ui.follow_res_sel, "Follow")
+                .on_hover_text(
+                    "Move the camera with the selection when stepping through residues.",
+                );
+
+            ui.add_space(COL_SPACING * 2.);
+
+            let dock_tools_text = if state.ui.show_docking_tools {
+                "Hide docking tools"
+            } else {
+                "Show docking tools (Broken/WIP)"
+            };
+
+            if ui.button(RichText::new(dock_tools_text)).clicked() {
+                state.ui.show_docking_tools = !state.ui.show_docking_tools;
+            }
+
+            ui.add_space(COL_SPACING / 2.);
+
+            let dock_seq_text = if state.ui.show_aa_seq {
+                "Hide seq"
+            } else {
+                "Show seq"
+            };
+
+            if ui.button(RichText::new(dock_seq_text)).clicked() {
+                state.ui.show_aa_seq = !state.ui.show_aa_seq;
+            }
+        }
+    });
+}
+
+fn add_aa_seq(seq_text: &str, ui: &mut Ui) {
+    ui.horizontal_wrapped(|ui| {
+        ui.label(RichText::new(seq_text).color(Color32::LIGHT_BLUE));
+    });
+}
+
+fn selection_section(
+    state: &mut State,
+    scene: &mut Scene,
+    redraw: &mut bool,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    // todo: DRY with view.
+    ui.horizontal(|ui| {
+        ui.label("View/Select:");
+        let prev_view = state.ui.view_sel_level;
+        ComboBox::from_id_salt(1)
+            .width(80.)
+            .selected_text(state.ui.view_sel_level.to_string())
+            .show_ui(ui, |ui| {
+                for view in &[ViewSelLevel::Atom, ViewSelLevel::Residue] {
+                    ui.selectable_value(&mut state.ui.view_sel_level, *view, view.to_string());
+                }
+            });
+
+        if state.ui.view_sel_level != prev_view {
+            *redraw = true;
+            // Kludge to prevent surprising behavior.
+            state.ui.selection = Selection::None;
+        }
+
+        // Buttons to alter the color profile, e.g. for res position, or partial charge.
+        ui.add_space(COL_SPACING / 2.);
+        match state.ui.view_sel_level {
+            ViewSelLevel::Atom => {
+                let color = if state.ui.atom_color_by_charge {
+                    COLOR_ACTIVE
+                } else {
+                    COLOR_INACTIVE
+                };
+
+                if ui
+                    .button(RichText::new("Color by q").color(color))
+                    .clicked()
+                {
+                    state.ui.atom_color_by_charge = !state.ui.atom_color_by_charge;
+                    state.ui.view_sel_level = ViewSelLevel::Atom;
+                    *redraw = true;
+                }
+            }
+            ViewSelLevel::Residue => {
+                let color = if state.ui.res_color_by_index {
+                    COLOR_ACTIVE
+                } else {
+                    COLOR_INACTIVE
+                };
+
+                if ui
+                    .button(RichText::new("Color by res #").color(color))
+                    .clicked()
+                {
+                    state.ui.res_color_by_index = !state.ui.res_color_by_index;
+                    state.ui.view_sel_level = ViewSelLevel::Residue;
+                    *redraw = true;
+                }
+            }
+        }
+
+        if let Some(mol) = &state.molecule {
+            let prev = state.ui.color_by_prop.clone();
+
+            ComboBox::from_id_salt(17)
+                .width(80.)
+                .selected_text(prev.as_deref().unwrap_or("(Color by prop)"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.ui.color_by_prop, None, "(None)");
+                    // This scans every atom and residue, so only do it while the list is open.
+                    for key in mol.prop_keys() {
+                        let text = key.clone();
+                        ui.selectable_value(&mut state.ui.color_by_prop, Some(key), text);
+                    }
+                })
+                .response
+                .on_hover_text("Color atoms by a stored atom or residue property.");
+
+            if state.ui.color_by_prop != prev {
+                *redraw = true;
+            }
+        }
+
+        if let Some(mol) = &mut state.molecule {
+            let ids = mol.alt_loc_ids();
+            if !ids.is_empty() {
+                let prev = mol.alt_loc_policy;
+                let mut policy = prev;
+
+                ComboBox::from_id_salt(46)
+                    .width(90.)
+                    .selected_text(policy.to_string())
+                    .show_ui(ui, |ui| {
+                        let v = AltLocPolicy::HighestOccupancy;
+                        ui.selectable_value(&mut policy, v, v.to_string());
+                        for id in ids {
+                            let v = AltLocPolicy::Id(id);
+                            ui.selectable_value(&mut policy, v, v.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Which alternate location to show and use, for atoms modeled in several.",
+                    );
+
+                if policy != prev {
+                    mol.set_alt_loc(policy);
+                    mol.update_h_bonds(&state.to_save.h_bond_cfg);
+                    *redraw = true;
+                }
+            }
+        }
+
+        ui.add_space(COL_SPACING);
+
+        ui.label("Nearby sel only:");
+        if ui.checkbox(&mut state.ui.show_near_sel_only, "").changed() {
+            *redraw = true;
+
+            // todo: For now, only allow one of near sel/lig
+            if state.ui.show_near_sel_only {
+                state.ui.show_near_lig_only = false
+            }
+        }
+
+        if state.ligand.is_some() {
+            ui.label("Nearby lig only:");
+            if ui.checkbox(&mut state.ui.show_near_lig_only, "").changed() {
+                *redraw = true;
+
+                // todo: For now, only allow one of near sel/lig
+                if state.ui.show_near_lig_only {
+                    state.ui.show_near_sel_only = false
+                }
+            }
+        }
+
+        if state.ui.show_near_sel_only || state.ui.show_near_lig_only {
+            ui.label("Dist:");
+            let dist_prev = state.ui.nearby_dist_thresh;
+            ui.add(Slider::new(
+                &mut state.ui.nearby_dist_thresh,
+                NEARBY_THRESH_MIN..=NEARBY_THRESH_MAX,
+            ));
+
+            if state.ui.nearby_dist_thresh != dist_prev {
+                *redraw = true;
+            }
+        }
+
+        if let Some(mol) = &state.molecule {
+            ui.add_space(COL_SPACING);
+
+            if state.ui.selection != Selection::None {
+                if ui
+                    .button(RichText::new("Move cam to sel").color(COLOR_HIGHLIGHT))
+                    .clicked()
+                {
+                    if let Selection::AtomLigand(i) = &state.ui.selection {
+                        if let Some(lig) = &state.ligand {
+                            cam_look_at(&mut scene.camera, lig.atom_posits[*i]);
+                            engine_updates.camera = true;
+                            state.ui.cam_snapshot = None;
+                        }
+                    } else {
+                        let atom_sel = mol.get_sel_atom(&state.ui.selection);
+
+                        if let Some(atom) = atom_sel {
+                            cam_look_at(&mut scene.camera, atom.posit);
+                            engine_updates.camera = true;
+                            state.ui.cam_snapshot = None;
+                        }
+                    }
+                }
+            }
+
+            if let Some(lig) = &mut state.ligand {
+                ui.add_space(COL_SPACING / 2.);
+                if ui
+                    .button(RichText::new("Move cam to lig").color(COLOR_HIGHLIGHT))
+                    .clicked()
+                {
+                    if lig.anchor_atom >= lig.molecule.atoms.len() {
+                        handle_err(
+                            &mut state.ui,
+                            "Problem positioning ligand atoms. Len shorter than anchor.".to_owned(),
+                        );
+                    } else {
+                        lig.position_atoms(None);
+
+                        let lig_pos: Vec3 = lig.atom_posits[lig.anchor_atom].into();
+                        let ctr: Vec3 = mol.center.into();
+
+                        cam_look_at_outside(&mut scene.camera, lig_pos, ctr);
+
+                        engine_updates.camera = true;
+                        state.ui.cam_snapshot = None;
+                    }
+                }
+            }
+
+            ui.add_space(COL_SPACING / 2.);
+            ui_aux::selected_data(
+                mol,
+                &state.ligand,
+                &state.ui.selection,
+                state.to_save.angle_unit,
+                ui,
+            );
+        }
+    });
+}
+
+/// Save the current selection under a name, and recall saved ones. These can also be referred to
+/// by name in commands, e.g. `select pocket` or `show surface, pocket`.
+fn named_selections(state: &mut State, redraw: &mut bool, ui: &mut Ui) {
+    if state.molecule.is_none() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Saved selections:");
+        ui.add(TextEdit::singleline(&mut state.ui.named_sel_name).desired_width(60.));
+
+        if state.ui.selection != Selection::None {
+            if ui
+                .button("Save")
+                .on_hover_text("Save the current selection under this name.")
+                .clicked()
+            {
+                let name = if !state.ui.named_sel_name.trim().is_empty() {
+                    state.ui.named_sel_name.clone()
+                } else {
+                    format!("sel{}", state.named_selections.len() + 1)
+                };
+
+                let selection = state.ui.selection.clone();
+                state.save_named_selection(&name, selection);
+                state.ui.named_sel_name = String::new();
+            }
+        }
+
+        if state.named_selections.is_empty() {
+            return;
+        }
+
+        let prev = state.ui.named_sel;
+        let sel_name = match prev.and_then(|i| state.named_selections.get(i)) {
+            Some(s) => s.name.clone(),
+            None => "(None)".to_owned(),
+        };
+
+        ComboBox::from_id_salt(44)
+            .width(80.)
+            .selected_text(sel_name)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.ui.named_sel, None, "(None)");
+                for (i, s) in state.named_selections.iter().enumerate() {
+                    ui.selectable_value(&mut state.ui.named_sel, Some(i), &s.name);
+                }
+            });
+
+        if state.ui.named_sel != prev {
+            if let Some(s) = state
+                .ui
+                .named_sel
+                .and_then(|i| state.named_selections.get(i))
+            {
+                state.ui.selection = s.selection.clone();
+                *redraw = true;
+            }
+        }
+
+        if let Some(i) = state.ui.named_sel {
+            if ui.button(RichText::new("❌").color(Color32::RED)).clicked() {
+                if i < state.named_selections.len() {
+                    state.named_selections.remove(i);
+                }
+                state.ui.named_sel = None;
+                state.update_save_prefs();
+            }
+        }
+    });
+}
+
+fn mol_descrip(mol: &Molecule, ui: &mut Ui) {
+    ui.heading(RichText::new(mol.ident.clone()).color(Color32::GOLD));
+
+    ui.label(format!("{} atoms", mol.atoms.len()));
+
+    if !mol.flips.is_empty() {
+        let flips: Vec<_> = mol.flips.iter().map(|f| f.descrip(mol)).collect();
+        ui.label(format!("{} flips", mol.flips.len()))
+            .on_hover_text(format!(
+                "Sidechain flips, and His tautomers, chosen to optimize the hydrogen bond network:\n{}",
+                flips.join("\n")
+            ));
+    }
+
+    if let Some(method) = mol.method {
+        ui.label(method.to_str_short());
+    }
+
+    if let Some(metadata) = &mol.metadata {
+        // Limit size to prevent UI problems.
+        let mut title: String = metadata
+            .prim_cit_title
+            .chars()
+            .take(MAX_TITLE_LEN)
+            .collect();
+        if title.len() != metadata.prim_cit_title.len() {
+            title += "...";
+        }
+        ui.label(RichText::new(title).color(Color32::WHITE).size(12.));
+    }
+
+    if mol.ident.len() <= 5 {
+        // todo: You likely need a better approach.
+        if ui.button("View on RCSB").clicked() {
+            rcsb::open_overview(&mol.ident);
+        }
+    }
+
+    if let Some(id) = &mol.drugbank_id {
+        if ui.button("View on Drugbank").clicked() {
             drugbank::open_overview(id);
         }
-    }
+    }
+
+    if let Some(id) = mol.pubchem_cid {
+        if ui.button("View on PubChem").clicked() {
+            pubchem::open_overview(id);
+        }
+    }
+}
+
+fn view_settings(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    redraw: &mut bool,
+    ui: &mut Ui,
+) {
+    ui.horizontal(|ui| {
+        ui.label("View:");
+        let prev_view = state.ui.mol_view;
+        ComboBox::from_id_salt(0)
+            .width(80.)
+            .selected_text(state.ui.mol_view.to_string())
+            .show_ui(ui, |ui| {
+                for view in &[
+                    MoleculeView::Backbone,
+                    MoleculeView::Sticks,
+                    MoleculeView::BallAndStick,
+                    // MoleculeView::Cartoon,
+                    MoleculeView::SpaceFill,
+                    MoleculeView::Surface,
+                    MoleculeView::Dots,
+                ] {
+                    ui.selectable_value(&mut state.ui.mol_view, *view, view.to_string());
+                }
+            });
+
+        if state.ui.mol_view != prev_view {
+            let cmd = format!("show_as {}", state.ui.mol_view.cli_name());
+            cli::record_cmd(state, &cmd);
+            *redraw = true;
+        }
+
+        if matches!(
+            state.ui.mol_view,
+            MoleculeView::Surface | MoleculeView::Dots
+        ) {
+            ui.label("Color by:");
+            let coloring_prev = state.ui.surface_coloring;
+            ComboBox::from_id_salt(12)
+                .width(80.)
+                .selected_text(state.ui.surface_coloring.to_string())
+                .show_ui(ui, |ui| {
+                    for coloring in &[
+                        SurfaceColoring::Uniform,
+                        SurfaceColoring::Element,
+                        SurfaceColoring::Hydrophobicity,
+                        SurfaceColoring::BFactor,
+                        SurfaceColoring::Potential,
+                        SurfaceColoring::Sasa,
+                    ] {
+                        ui.selectable_value(
+                            &mut state.ui.surface_coloring,
+                            *coloring,
+                            coloring.to_string(),
+                        );
+                    }
+                });
+
+            if state.ui.surface_coloring != coloring_prev {
+                // Colors are assigned when building the mesh.
+                state.volatile.flags.update_sas_mesh = true;
+            }
+        }
+
+        ui.add_space(COL_SPACING);
+
+        ui.label("Vis:");
+
+        ui_aux::vis_check(
+            &mut state.ui.visibility.hide_non_hetero,
+            "Peptide",
+            ui,
+            redraw,
+        );
+        ui_aux::vis_check(&mut state.ui.visibility.hide_hetero, "Hetero", ui, redraw);
+
+        ui.add_space(COL_SPACING / 2.);
+
+        if !state.ui.visibility.hide_non_hetero {
+            // Subset of peptide.
+            ui_aux::vis_check(
+                &mut state.ui.visibility.hide_sidechains,
+                "Sidechains",
+                ui,
+                redraw,
+            );
+        }
+
+        ui_aux::vis_check(&mut state.ui.visibility.hide_hydrogen, "H", ui, redraw);
+
+        if !state.ui.visibility.hide_hetero {
+            // Subset of hetero.
+            ui_aux::vis_check(&mut state.ui.visibility.hide_water, "Water", ui, redraw);
+        }
+
+        if state.ligand.is_some() {
+            let color = ui_aux::active_color(!state.ui.visibility.hide_ligand);
+            if ui.button(RichText::new("Lig").color(color)).clicked() {
+                state.ui.visibility.hide_ligand = !state.ui.visibility.hide_ligand;
+
+                if state.ui.visibility.hide_ligand {
+                    scene.entities.retain(|ent| {
+                        ent.class != EntityType::Ligand as u32
+                            && ent.class != EntityType::DockingSite as u32
+                    });
+                } else {
+                    draw_ligand(state, scene);
+                }
+
+                engine_updates.entities = true;
+                engine_updates.lighting = true; // docking light.
+            }
+        }
+
+        ui_aux::vis_check(&mut state.ui.visibility.hide_h_bonds, "H bonds", ui, redraw);
+
+        if let Some(mol) = &state.molecule {
+            let vis = &mut state.ui.visibility;
+            if !mol.salt_bridges.is_empty() {
+                ui_aux::vis_check(&mut vis.hide_salt_bridges, "Salt bridges", ui, redraw);
+            }
+            if !mol.pi_stacks.is_empty() {
+                ui_aux::vis_check(&mut vis.hide_pi_stacks, "π-stack", ui, redraw);
+            }
+            if !mol.cation_pi.is_empty() {
+                ui_aux::vis_check(&mut vis.hide_cation_pi, "Cation-π", ui, redraw);
+            }
+        }
+
+        if let Some(lig) = &state.ligand {
+            let vis = &mut state.ui.visibility;
+            if !lig.halogen_bonds.is_empty() {
+                ui_aux::vis_check(&mut vis.hide_halogen_bonds, "Halogen bonds", ui, redraw);
+            }
+            if !lig.chalcogen_contacts.is_empty() {
+                ui_aux::vis_check(&mut vis.hide_chalcogen, "S···O", ui, redraw);
+            }
+        }
+
+        // vis_check(&mut state.ui.visibility.dim_peptide, "Dim peptide", ui, redraw);
+
+        if state.ligand.is_some() {
+            ui.add_space(COL_SPACING / 2.);
+            // Not using `vis_check` for this because its semantics are inverted.
+            let color = ui_aux::active_color(state.ui.visibility.dim_peptide);
+            if ui
+                .button(RichText::new("Dim peptide").color(color))
+                .clicked()
+            {
+                state.ui.visibility.dim_peptide = !state.ui.visibility.dim_peptide;
+                *redraw = true;
+            }
+        }
+
+        if let Some(mol) = &state.molecule {
+            if let Some(dens) = &mol.elec_density {
+                let mut redraw_dens = false;
+                ui_aux::vis_check(
+                    &mut state.ui.visibility.hide_density,
+                    "Density",
+                    ui,
+                    &mut redraw_dens,
+                );
+
+                if redraw_dens {
+                    if state.ui.visibility.hide_density {
+                        scene
+                            .entities
+                            .retain(|ent| ent.class != EntityType::Density as u32);
+                    } else {
+                        draw_density(&mut scene.entities, dens);
+                    }
+                    engine_updates.entities = true;
+                }
+
+                let mut redraw_dens_surface = false;
+                ui_aux::vis_check(
+                    &mut state.ui.visibility.hide_density_surface,
+                    "Density sfc",
+                    ui,
+                    &mut redraw_dens_surface,
+                );
+
+                if !state.ui.visibility.hide_density_surface {
+                    let iso_prev = state.ui.density_iso_level;
+
+                    ui.spacing_mut().slider_width = 300.;
+                    ui.add(Slider::new(
+                        &mut state.ui.density_iso_level,
+                        DENS_ISO_MIN..=DENS_ISO_MAX,
+                    ));
+                    if state.ui.density_iso_level != iso_prev {
+                        state.volatile.flags.make_density_mesh = true;
+                    }
+                }
+
+                // todo
+                if redraw_dens_surface {
+                    if state.ui.visibility.hide_density_surface {
+                        let _ = &mut scene
+                            .entities
+                            .retain(|ent| ent.class != EntityType::DensitySurface as u32);
+                    } else {
+                        draw_density_surface(&mut scene.entities);
+                    }
+                    engine_updates.entities = true;
+                    redraw_dens_surface = false;
+                }
+            }
+        }
+    });
+}
+
+/// Build and toggle surfaces over the selection, or a single chain.
+fn partial_surfaces(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+
+    let mut redraw = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Partial surfaces:");
+
+        if ui
+            .button("Surface selection")
+            .on_hover_text("Build a surface over the atoms currently selected.")
+            .clicked()
+        {
+            state.volatile.partial_surfaces.sel_atoms = mol.sel_atom_indices(&state.ui.selection);
+            state.ui.visibility.hide_sel_surface = false;
+            state.volatile.flags.update_sel_sfc_mesh = true;
+        }
+
+        let chain_prev = state.volatile.partial_surfaces.chain;
+        let chain_text = match chain_prev.and_then(|i| mol.chains.get(i)) {
+            Some(c) => c.id.clone(),
+            None => "(None)".to_owned(),
+        };
+
+        ui.add_space(COL_SPACING / 2.);
+        ui.label("Surface chain:");
+        ComboBox::from_id_salt(11)
+            .width(40.)
+            .selected_text(chain_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.volatile.partial_surfaces.chain, None, "(None)");
+                for (i, chain) in mol.chains.iter().enumerate() {
+                    ui.selectable_value(
+                        &mut state.volatile.partial_surfaces.chain,
+                        Some(i),
+                        chain.id.clone(),
+                    );
+                }
+            });
+
+        if state.volatile.partial_surfaces.chain != chain_prev {
+            state.ui.visibility.hide_chain_surface = false;
+            state.volatile.flags.update_chain_sfc_mesh = true;
+        }
+
+        ui.add_space(COL_SPACING / 2.);
+
+        if state.volatile.partial_surfaces.sel_mesh_created {
+            ui_aux::vis_check(
+                &mut state.ui.visibility.hide_sel_surface,
+                "Sel sfc",
+                ui,
+                &mut redraw,
+            );
+        }
+        if state.volatile.partial_surfaces.chain_mesh_created {
+            ui_aux::vis_check(
+                &mut state.ui.visibility.hide_chain_surface,
+                "Chain sfc",
+                ui,
+                &mut redraw,
+            );
+        }
+    });
+
+    if redraw {
+        draw_partial_surfaces(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+/// Store two sets of atom positions, and alternate between them.
+fn blink_controls(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    if state.molecule.is_none() && state.ligand.is_none() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Blink compare:");
+
+        let running = state.volatile.blink.running();
+
+        // Don't store frames while blinking; positions are the ones we're displaying.
+        if !running {
+            let color = ui_aux::active_color(state.volatile.blink.a.is_some());
+            if ui
+                .button(RichText::new("Store A").color(color))
+                .on_hover_text("Store current atom positions as frame A.")
+                .clicked()
+            {
+                state.volatile.blink.a = Some(BlinkFrame::from_state(state));
+            }
+
+            let color = ui_aux::active_color(state.volatile.blink.b.is_some());
+            if ui
+                .button(RichText::new("Store B").color(color))
+                .on_hover_text("Store current atom positions as frame B.")
+                .clicked()
+            {
+                state.volatile.blink.b = Some(BlinkFrame::from_state(state));
+            }
+        }
+
+        if state.volatile.blink.a.is_some() && state.volatile.blink.b.is_some() {
+            let text = if running { "Stop" } else { "Blink" };
+            if ui.button(text).clicked() {
+                if running {
+                    blink_stop(state, scene);
+                } else {
+                    blink_start(state, scene);
+                }
+                engine_updates.entities = true;
+            }
+
+            ui.label("Interval (s):");
+            ui.add(Slider::new(
+                &mut state.volatile.blink.interval,
+                BLINK_INTERVAL_MIN..=BLINK_INTERVAL_MAX,
+            ));
+
+            if running {
+                let shown = if state.volatile.blink.showing_b {
+                    "B"
+                } else {
+                    "A"
+                };
+                ui.label(RichText::new(shown).color(Color32::GOLD));
+            }
+        }
+    });
+}
+
+/// Detect binding pockets, and optionally use one as the docking site.
+fn pockets(state: &mut State, scene: &mut Scene, engine_updates: &mut EngineUpdates, ui: &mut Ui) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+
+    let mut redraw = false;
+    let mut dock_site = None;
+    let mut waters = None;
+
+    ui.horizontal(|ui| {
+        ui.label("Pockets:");
+
+        if ui
+            .button("Find pockets")
+            .on_hover_text("Detect cavities in the protein, ranked by estimated druggability.")
+            .clicked()
+        {
+            let start = Instant::now();
+            state.volatile.pockets = find_pockets(mol);
+            info!(
+                "Found {} pockets in {}ms",
+                state.volatile.pockets.len(),
+                start.elapsed().as_millis()
+            );
+
+            state.volatile.pocket_selected = if state.volatile.pockets.is_empty() {
+                None
+            } else {
+                Some(0)
+            };
+            state.ui.visibility.hide_pockets = false;
+            state.volatile.flags.update_pocket_mesh = true;
+        }
+
+        if state.volatile.pockets.is_empty() {
+            return;
+        }
+
+        let pocket_text = |i: usize, p: &Pocket| {
+            format!("#{}: {:.0} Å³, drug {:.2}", i + 1, p.volume, p.druggability)
+        };
+
+        let sel_prev = state.volatile.pocket_selected;
+        let sel_text = sel_prev
+            .and_then(|i| state.volatile.pockets.get(i).map(|p| pocket_text(i, p)))
+            .unwrap_or_else(|| "(None)".to_owned());
+
+        ComboBox::from_id_salt(13)
+            .width(160.)
+            .selected_text(sel_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.volatile.pocket_selected, None, "(None)");
+                for (i, pocket) in state.volatile.pockets.iter().enumerate() {
+                    ui.selectable_value(
+                        &mut state.volatile.pocket_selected,
+                        Some(i),
+                        pocket_text(i, pocket),
+                    );
+                }
+            });
+
+        if state.volatile.pocket_selected != sel_prev {
+            state.volatile.flags.update_pocket_mesh = true;
+        }
+
+        if let Some(pocket) = state
+            .volatile
+            .pocket_selected
+            .and_then(|i| state.volatile.pockets.get(i))
+        {
+            ui.label(format!(
+                "Lining res: {}  Hydrophobic: {:.0}%",
+                pocket.lining_residues.len(),
+                pocket.hydrophobic_frac * 100.
+            ));
+
+            if state.ligand.is_some()
+                && ui
+                    .button(RichText::new("Set docking site").color(COLOR_HIGHLIGHT))
+                    .on_hover_text("Center the docking site on this pocket, sized to contain it.")
+                    .clicked()
+            {
+                dock_site = Some(pocket.docking_site());
+            }
+
+            if ui
+                .button("Place waters")
+                .on_hover_text(
+                    "Hydrate the pocket: place waters at energy minima of a water probe, e.g. for \
+                    an apo structure without crystallographic waters. Existing waters are kept.",
+                )
+                .clicked()
+            {
+                waters = Some(place_waters(mol, pocket));
+            }
+        }
+
+        ui.add_space(COL_SPACING / 2.);
+        ui_aux::vis_check(
+            &mut state.ui.visibility.hide_pockets,
+            "Pockets",
+            ui,
+            &mut redraw,
+        );
+    });
+
+    if let Some(site) = dock_site {
+        if let Some(lig) = &mut state.ligand {
+            lig.docking_site.site_radius = site.site_radius;
+        }
+        state.ui.docking_site_size = format!("{:.1}", site.site_radius);
+        state.update_docking_site(site.site_center);
+        state.update_save_prefs();
+
+        if let Some(lig) = &state.ligand {
+            set_docking_light(scene, Some(&lig.docking_site));
+        }
+        draw_ligand(state, scene);
+
+        engine_updates.lighting = true;
+        engine_updates.entities = true;
+    }
+
+    if let Some(waters) = waters {
+        if let Some(mol) = &mut state.molecule {
+            add_waters(mol, &waters);
+        }
+        info!("Placed {} waters in the pocket", waters.len());
+
+        state.ui.visibility.hide_water = false;
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+
+    if redraw {
+        draw_pockets(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+/// Numerical solvent-accessible surface area; total, and for the selected residue.
+fn sasa(state: &mut State, ui: &mut Ui) {
+    let Some(mol) = &mut state.molecule else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("SASA:");
+
+        if ui
+            .button("Calc SASA")
+            .on_hover_text("Compute per-atom, and per-residue solvent-accessible surface area.")
+            .clicked()
+        {
+            let start = Instant::now();
+            let sasa = calc_sasa(&mol.sasa_atoms(), mol.residues.len());
+
+            for (res, area) in mol.residues.iter_mut().zip(&sasa.per_residue) {
+                res.props.insert("sasa".to_owned(), PropVal::Float(*area));
+            }
+            mol.sasa = Some(sasa);
+            info!("SASA computed in {}ms", start.elapsed().as_millis());
+        }
+
+        let Some(sasa) = &mol.sasa else {
+            return;
+        };
+
+        ui.label(format!("Total: {:.0} Å²", sasa.total));
+
+        if let Selection::Residue(i) = state.ui.selection {
+            if let Some(area) = sasa.per_residue.get(i) {
+                ui.label(RichText::new(format!("Sel res: {area:.1} Å²")).color(Color32::GOLD));
+            }
+        }
+
+        if ui
+            .button("Save CSV")
+            .on_hover_text(
+                "Save per-residue, and per-atom areas to sasa_residues.csv and sasa_atoms.csv",
+            )
+            .clicked()
+        {
+            let result = sasa
+                .save_csv_residues(&mol.residues, Path::new("sasa_residues.csv"))
+                .and_then(|_| sasa.save_csv_atoms(&mol.sasa_atoms(), Path::new("sasa_atoms.csv")));
+
+            if let Err(e) = result {
+                handle_err(&mut state.ui, format!("Problem saving SASA: {e}"));
+            }
+        }
+    });
+}
+
+/// Buried surface area, and interface residues between two chains.
+fn interface(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+    if mol.chains.len() < 2 {
+        return;
+    }
+
+    let mut redraw = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Interface:");
+
+        let chain_text = |chain: Option<usize>| match chain.and_then(|i| mol.chains.get(i)) {
+            Some(c) => c.id.clone(),
+            None => "-".to_owned(),
+        };
+
+        let chains = &mut state.volatile.interface_chains;
+        for (id, chain) in [(14, &mut chains.0), (15, &mut chains.1)] {
+            ComboBox::from_id_salt(id)
+                .width(40.)
+                .selected_text(chain_text(*chain))
+                .show_ui(ui, |ui| {
+                    for (i, c) in mol.chains.iter().enumerate() {
+                        ui.selectable_value(chain, Some(i), c.id.clone());
+                    }
+                });
+        }
+
+        if let (Some(a), Some(b)) = state.volatile.interface_chains {
+            if a != b && ui.button("Calc").clicked() {
+                let start = Instant::now();
+                state.volatile.interface = Some(analyze_interface(mol, a, b));
+                info!("Interface computed in {}ms", start.elapsed().as_millis());
+            }
+        }
+
+        let Some(iface) = &state.volatile.interface else {
+            return;
+        };
+
+        ui.label(format!(
+            "{}-{}  BSA: {:.0} Å²  Residues: {}",
+            chain_text(Some(iface.chain_a)),
+            chain_text(Some(iface.chain_b)),
+            iface.bsa,
+            iface.residues.len()
+        ));
+
+        if !iface.residues.is_empty()
+            && ui
+                .button(RichText::new("Select").color(COLOR_HIGHLIGHT))
+                .on_hover_text("Select all atoms of the interface residues.")
+                .clicked()
+        {
+            state.ui.selection = Selection::Atoms(iface.atoms(mol));
+            redraw = true;
+        }
+    });
+
+    if redraw {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+/// Steric clash detection. Selecting a clash selects its two atoms.
+fn clashes(state: &mut State, scene: &mut Scene, engine_updates: &mut EngineUpdates, ui: &mut Ui) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+
+    let mut redraw = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Clashes:");
+
+        if ui
+            .button("Find clashes")
+            .on_hover_text(format!(
+                "Find non-bonded atom pairs whose Van der Waals spheres overlap by at least {CLASH_OVERLAP_MIN} Å."
+            ))
+            .clicked()
+        {
+            let start = Instant::now();
+            state.volatile.clashes = find_clashes(mol);
+            state.volatile.clash_selected = None;
+            info!(
+                "Found {} clashes in {}ms",
+                state.volatile.clashes.len(),
+                start.elapsed().as_millis()
+            );
+
+            state.ui.visibility.hide_clashes = false;
+            redraw = true;
+        }
+
+        ui.checkbox(&mut state.ui.clash_auto, "Auto")
+            .on_hover_text("Re-run clash detection after hydrogens are added, or atoms are moved.");
+
+        if state.volatile.clashes.is_empty() {
+            return;
+        }
+
+        let clash_text = |c: &Clash| {
+            format!(
+                "{} - {}: {:.2} Å",
+                atom_label(mol, c.atom_0),
+                atom_label(mol, c.atom_1),
+                c.overlap
+            )
+        };
+
+        let sel_prev = state.volatile.clash_selected;
+        let sel_text = sel_prev
+            .and_then(|i| state.volatile.clashes.get(i).map(clash_text))
+            .unwrap_or_else(|| format!("{} clashes", state.volatile.clashes.len()));
+
+        ComboBox::from_id_salt(21)
+            .width(220.)
+            .selected_text(sel_text)
+            .show_ui(ui, |ui| {
+                for (i, clash) in state.volatile.clashes.iter().enumerate() {
+                    ui.selectable_value(
+                        &mut state.volatile.clash_selected,
+                        Some(i),
+                        clash_text(clash),
+                    );
+                }
+            });
+
+        if state.volatile.clash_selected != sel_prev {
+            if let Some(c) = state
+                .volatile
+                .clash_selected
+                .and_then(|i| state.volatile.clashes.get(i))
+            {
+                state.ui.selection = Selection::Atoms(vec![c.atom_0, c.atom_1]);
+                redraw = true;
+            }
+        }
+
+        ui.add_space(COL_SPACING / 2.);
+        ui_aux::vis_check(
+            &mut state.ui.visibility.hide_clashes,
+            "Clashes",
+            ui,
+            &mut redraw,
+        );
+    });
+
+    if redraw {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+/// Estimated pKas of titratable residues, and their protonation states at a chosen pH.
+fn protonation(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    if state.molecule.is_none() {
+        return;
+    }
+
+    let mut redraw = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Protonation: pH");
+        ui.add(
+            DragValue::new(&mut state.to_save.ph)
+                .range(0. ..=14.)
+                .speed(0.05)
+                .max_decimals(1),
+        );
+
+        if ui
+            .button("Assign")
+            .on_hover_text(
+                "Estimate pKas of Asp, Glu, His, Lys, Cys, and Tyr residues, and set their \
+                protonation states for this pH: hydrogens, and force field types and charges. \
+                States are assigned at this pH when opening a molecule as well.",
+            )
+            .clicked()
+        {
+            let start = Instant::now();
+            let mut mol = state.molecule.take().unwrap();
+
+            state.volatile.docking_setup = None;
+            state.populate_ff_protein(&mut mol);
+            state.molecule = Some(mol);
+            info!("Protonation assigned in {}ms", start.elapsed().as_millis());
+
+            // Atom indices have changed.
+            state.ui.selection = Selection::None;
+            state.volatile.clashes = Vec::new();
+            state.volatile.clash_selected = None;
+
+            state.update_save_prefs();
+            redraw = true;
+        }
+
+        let Some(mol) = &state.molecule else {
+            return;
+        };
+        let Some(prot) = &mol.protonation else {
+            return;
+        };
+
+        // Largest shifts from the model pKa first.
+        let mut sites: Vec<_> = prot.sites.iter().collect();
+        sites.sort_by(|a, b| {
+            (b.pka - b.pka_model)
+                .abs()
+                .total_cmp(&(a.pka - a.pka_model).abs())
+        });
+        let descrips: Vec<_> = sites.iter().map(|s| s.descrip(mol)).collect();
+
+        ui.label(format!("{} sites at pH {:.1}", prot.sites.len(), prot.ph))
+            .on_hover_text(descrips.join("\n"));
+
+        if ui
+            .button("Color by pKa")
+            .on_hover_text("Color residues by their estimated pKa.")
+            .clicked()
+        {
+            state.ui.color_by_prop = Some(PROP_PKA.to_owned());
+            redraw = true;
+        }
+    });
+
+    if redraw {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+/// Build a peptide from a sequence, replacing the open molecule.
+fn peptide_builder(state: &mut State, redraw: &mut bool, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Peptide sequence:");
+        ui.add(TextEdit::singleline(&mut state.ui.peptide_seq).desired_width(200.))
+            .on_hover_text("One-letter amino acid codes, from the N terminus, e.g. \"ACDEFGHIK\".");
+
+        ui.label("Backbone:");
+        ComboBox::from_id_salt(23)
+            .width(80.)
+            .selected_text(state.ui.peptide_backbone.to_string())
+            .show_ui(ui, |ui| {
+                let (φ, ψ) = state.ui.peptide_backbone.dihedrals();
+                for preset in [
+                    BackbonePreset::AlphaHelix,
+                    BackbonePreset::Sheet,
+                    BackbonePreset::Ppii,
+                    BackbonePreset::Custom(φ, ψ),
+                ] {
+                    ui.selectable_value(&mut state.ui.peptide_backbone, preset, preset.to_string());
+                }
+            });
+
+        let unit = state.to_save.angle_unit;
+        if let BackbonePreset::Custom(φ, ψ) = &mut state.ui.peptide_backbone {
+            let suffix = match unit {
+                AngleUnit::Degrees => "°",
+                AngleUnit::Radians => " rad",
+            };
+            for (name, angle) in [("φ:", φ), ("ψ:", ψ)] {
+                ui.label(name);
+                let mut val = unit.convert(*angle);
+                if ui
+                    .add(
+                        DragValue::new(&mut val)
+                            .range(unit.convert(-PI)..=unit.convert(PI))
+                            .speed(unit.convert(1_f64.to_radians()))
+                            .suffix(suffix),
+                    )
+                    .changed()
+                {
+                    *angle = unit.to_radians(val);
+                }
+            }
+        }
+
+        if ui
+            .button(RichText::new("Build").color(COLOR_HIGHLIGHT))
+            .on_hover_text(
+                "Build the peptide from ideal geometry, with these φ and ψ for each residue, \
+                and trans peptide bonds. This replaces the open molecule.",
+            )
+            .clicked()
+        {
+            match build_peptide(&state.ui.peptide_seq, state.ui.peptide_backbone) {
+                Ok(mol) => {
+                    state.ui.cmd_line_output =
+                        format!("Built a peptide with {} residues", mol.residues.len());
+                    state.ui.cmd_line_out_is_err = false;
+
+                    state.open_built_molecule(mol);
+                    *redraw = true;
+                }
+                Err(e) => handle_err(&mut state.ui, e.to_string()),
+            }
+        }
+    });
+}
+
+/// Build missing residues into a chain break, replacing the open molecule with the result.
+fn loop_builder(state: &mut State, redraw: &mut bool, ui: &mut Ui) {
+    let Some(mol) = &state.molecule else {
+        return;
+    };
+
+    let breaks = find_chain_breaks(mol);
+    if breaks.is_empty() {
+        ui.label("No chain breaks found");
+        return;
+    }
+    // Default to Gly for each missing residue; the model doesn't know their identity.
+    let default_seq = |i: usize| "G".repeat(breaks[i].missing.max(1));
+    let mut break_i = match state.ui.loop_break {
+        Some(i) if i < breaks.len() => i,
+        _ => {
+            state.ui.loop_break = Some(0);
+            state.ui.loop_seq = default_seq(0);
+            0
+        }
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Chain break:");
+        ComboBox::from_id_salt(24)
+            .width(200.)
+            .selected_text(breaks[break_i].descrip())
+            .show_ui(ui, |ui| {
+                for (i, brk) in breaks.iter().enumerate() {
+                    ui.selectable_value(&mut break_i, i, brk.descrip());
+                }
+            });
+
+        if state.ui.loop_break != Some(break_i) {
+            state.ui.loop_break = Some(break_i);
+            state.ui.loop_seq = default_seq(break_i);
+        }
+        let brk = &breaks[break_i];
+
+        ui.label("Sequence:");
+        ui.add(TextEdit::singleline(&mut state.ui.loop_seq).desired_width(120.))
+            .on_hover_text(
+                "One-letter amino acid codes of the residues to build, from the N terminus. \
+                The residue count is inferred from serial numbers, but their identity isn't.",
+            );
+
+        if ui
+            .button(RichText::new("Build loop").color(COLOR_HIGHLIGHT))
+            .on_hover_text(
+                "Build these residues into the break, closing the backbone with cyclic coordinate \
+                descent, and adding sidechains from a rotamer library.",
+            )
+            .clicked()
+        {
+            let Some(mol) = &state.molecule else {
+                return;
+            };
+
+            match build_loop(mol, brk, &state.ui.loop_seq) {
+                Ok((mol, rmsd)) => {
+                    state.ui.cmd_line_output = format!(
+                        "Built a loop into {}. Closure RMSD: {rmsd:.2} Å",
+                        brk.descrip()
+                    );
+                    state.ui.cmd_line_out_is_err = false;
+
+                    state.ui.loop_break = None;
+                    state.open_built_molecule(mol);
+                    *redraw = true;
+                }
+                Err(e) => handle_err(&mut state.ui, e.to_string()),
+            }
+        }
+    });
+}
+
+/// Scan a ligand dihedral with the force field, to check its torsion parameters.
+fn torsion_scan_tool(state: &mut State, ui: &mut Ui) {
+    let Some(lig) = &state.ligand else {
+        return;
+    };
+    let mol = &lig.molecule;
+
+    let bond_label = |i: usize| {
+        let bond = &mol.bonds[i];
+        format!(
+            "{}–{}",
+            atom_label(mol, bond.atom_0),
+            atom_label(mol, bond.atom_1)
+        )
+    };
+
+    let mut scan_clicked = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Torsion scan:");
+
+        if lig.flexible_bonds.is_empty() {
+            ui.label("No rotatable bonds");
+            return;
+        }
+
+        let bond_sel = state
+            .ui
+            .torsion_scan_bond
+            .filter(|b| lig.flexible_bonds.contains(b))
+            .unwrap_or(lig.flexible_bonds[0]);
+
+        ComboBox::from_id_salt(41)
+            .width(140.)
+            .selected_text(bond_label(bond_sel))
+            .show_ui(ui, |ui| {
+                for &b in &lig.flexible_bonds {
+                    if ui.selectable_label(b == bond_sel, bond_label(b)).clicked() {
+                        state.ui.torsion_scan_bond = Some(b);
+                    }
+                }
+            });
+        if state.ui.torsion_scan_bond.is_none() {
+            state.ui.torsion_scan_bond = Some(bond_sel);
+        }
+
+        let params = &mut state.ui.torsion_scan_params;
+        ui.label("Step (°):");
+        ui.add(DragValue::new(&mut params.step).range(1. ..=90.).speed(0.5));
+
+        ui.checkbox(&mut params.minimize, "Min").on_hover_text(
+            "Minimize the rest of the ligand at each step, with the dihedral restrained. \
+            Otherwise, evaluate each step with the rest of the ligand rigid.",
+        );
+
+        if ui
+            .button("Scan")
+            .on_hover_text(
+                "Rotate this bond through 360°, and plot the force field energy at each step.",
+            )
+            .clicked()
+        {
+            scan_clicked = true;
+        }
+
+        if state.volatile.torsion_scan.is_some() {
+            let color = ui_aux::active_color(state.ui.show_torsion_scan);
+            if ui
+                .button(RichText::new("Profile").color(color))
+                .on_hover_text("Show the energy profile from the last scan.")
+                .clicked()
+            {
+                state.ui.show_torsion_scan = !state.ui.show_torsion_scan;
+            }
+
+            if ui
+                .button("Save")
+                .on_hover_text("Save the energy profile to torsion_scan.csv")
+                .clicked()
+            {
+                if let Some(scan) = &state.volatile.torsion_scan {
+                    if let Err(e) = scan.save_csv(Path::new("torsion_scan.csv")) {
+                        handle_err(
+                            &mut state.ui,
+                            format!("Problem saving the torsion scan: {e}"),
+                        );
+                    }
+                }
+            }
+        }
+    });
+
+    if scan_clicked {
+        state.load_ffs_general();
+
+        let Some(lig) = &state.ligand else {
+            return;
+        };
+        let Some(bond) = state.ui.torsion_scan_bond else {
+            return;
+        };
+
+        match torsion_scan(
+            &lig.molecule,
+            &lig.atom_posits,
+            bond,
+            &state.ff_params,
+            &state.ui.md_config,
+            &state.ui.torsion_scan_params,
+        ) {
+            Ok(scan) => {
+                state.volatile.torsion_scan = Some(scan);
+                state.ui.show_torsion_scan = true;
+            }
+            Err(e) => handle_err(&mut state.ui, e.to_string()),
+        }
+    }
+}
+
+/// Partial charges for the ligand: AM1-BCC from AmberTools, if installed, or Gasteiger.
+fn ligand_charges(state: &mut State, ui: &mut Ui) {
+    let Some(lig) = &mut state.ligand else {
+        return;
+    };
+
+    // Poll a run in progress.
+    let result = state
+        .volatile
+        .am1bcc_pending
+        .as_ref()
+        .map(|(rx, _)| rx.try_recv());
+
+    match result {
+        Some(Ok(Ok(charges))) => {
+            for (atom, q) in lig.molecule.atoms.iter_mut().zip(charges) {
+                atom.partial_charge = Some(q);
+            }
+            // Prevents docking setup from replacing these with EEM charges.
+            lig.molecule.eem_charges_assigned = true;
+            state.volatile.am1bcc_pending = None;
+            info!("Assigned AM1-BCC charges to the ligand");
+        }
+        Some(Ok(Err(e))) => {
+            state.volatile.am1bcc_pending = None;
+            handle_err(&mut state.ui, e.to_string());
+        }
+        Some(Err(TryRecvError::Empty)) => ui.ctx().request_repaint(),
+        Some(Err(TryRecvError::Disconnected)) => state.volatile.am1bcc_pending = None,
+        None => (),
+    }
+
+    let avail = *state
+        .volatile
+        .antechamber_avail
+        .get_or_insert_with(antechamber_avail);
+
+    ui.horizontal(|ui| {
+        ui.label("Lig charges:");
+
+        if let Some((_, start)) = &state.volatile.am1bcc_pending {
+            ui.spinner();
+            ui.label(format!("Running AM1-BCC... {}s", start.elapsed().as_secs()));
+            return;
+        }
+
+        ui.label("Net:");
+        ui.add(DragValue::new(&mut state.ui.lig_net_charge).range(-6..=6))
+            .on_hover_text("The ligand's net formal charge. Used for AM1-BCC.");
+
+        let hover = if avail {
+            "Compute AM1-BCC charges with AmberTools (antechamber and sqm). This takes seconds to \
+            minutes; results are cached."
+        } else {
+            "Requires AmberTools, with antechamber on the path."
+        };
+        if ui
+            .add_enabled(avail, Button::new("AM1-BCC"))
+            .on_hover_text(hover)
+            .on_disabled_hover_text(hover)
+            .clicked()
+        {
+            let cache_dir = state.volatile.prefs_dir.join(CHARGE_CACHE_DIR);
+            state.volatile.am1bcc_pending = Some((
+                start_am1bcc(&lig.molecule, state.ui.lig_net_charge, &cache_dir),
+                Instant::now(),
+            ));
+        }
+
+        if ui
+            .button("Gasteiger")
+            .on_hover_text("Assign Gasteiger-Marsili charges. Fast, but rougher than AM1-BCC.")
+            .clicked()
+        {
+            let charges = gasteiger_charges(&lig.molecule.atoms, &lig.molecule.bonds);
+            for (atom, q) in lig.molecule.atoms.iter_mut().zip(charges) {
+                atom.partial_charge = Some(q);
+            }
+            lig.molecule.eem_charges_assigned = true;
+        }
 
-    if let Some(id) = mol.pubchem_cid {
-        if ui.button("View on PubChem").clicked() {
-            pubchem::open_overview(id);
+        let charged = lig
+            .molecule
+            .atoms
+            .iter()
+            .all(|a| a.partial_charge.is_some());
+        if charged {
+            let total: f32 = lig
+                .molecule
+                .atoms
+                .iter()
+                .filter_map(|a| a.partial_charge)
+                .sum();
+            ui.label(format!("Total: {total:+.2}"));
+        } else {
+            ui.label(RichText::new("Missing charges").color(Color32::GOLD));
+        }
+    });
+}
+
+/// Surface area buried on binding, at the ligand's current pose: of the ligand, and of each
+/// receptor residue.
+fn buried_area(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    let (Some(mol), Some(lig)) = (&mut state.molecule, &state.ligand) else {
+        return;
+    };
+
+    let mut redraw = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Buried area:");
+
+        if ui
+            .button("Calc ΔSASA")
+            .on_hover_text(
+                "Surface area buried on binding, at the current pose: SASA alone minus SASA in the \
+                complex, for the ligand, and each receptor residue. Colors the receptor by residue.",
+            )
+            .clicked()
+        {
+            let start = Instant::now();
+            let bsa = ligand_bsa(mol, &lig.molecule, &lig.atom_posits);
+
+            let mut per_res = vec![0.; mol.residues.len()];
+            for (r, area) in &bsa.residues {
+                per_res[*r] = *area;
+            }
+            for (res, area) in mol.residues.iter_mut().zip(per_res) {
+                res.props
+                    .insert(PROP_DSASA_LIG.to_owned(), PropVal::Float(area));
+            }
+
+            info!("ΔSASA computed in {}ms", start.elapsed().as_millis());
+
+            state.volatile.lig_bsa = Some(bsa);
+            state.ui.color_by_prop = Some(PROP_DSASA_LIG.to_owned());
+            redraw = true;
+        }
+
+        let Some(bsa) = &state.volatile.lig_bsa else {
+            return;
+        };
+
+        let lig_pct = if bsa.lig_sasa > 0. {
+            bsa.lig_buried / bsa.lig_sasa * 100.
+        } else {
+            0.
+        };
+        ui.label(format!(
+            "Lig: {:.0} Å² ({lig_pct:.0}%)  Rec: {:.0} Å²",
+            bsa.lig_buried, bsa.rec_buried
+        ));
+
+        let res_text: Vec<_> = bsa
+            .residues
+            .iter()
+            .map(|(r, area)| {
+                let res = &mol.residues[*r];
+                let name = match &res.res_type {
+                    ResidueType::AminoAcid(aa) => aa.to_string(),
+                    ResidueType::Water => "Water".to_owned(),
+                    ResidueType::Other(n) => n.clone(),
+                };
+                format!("{name} {}: {area:.1} Å²", res.serial_number)
+            })
+            .collect();
+
+        ui.label(
+            RichText::new(format!("{} residues", bsa.residues.len())).color(COLOR_HIGHLIGHT),
+        )
+        .on_hover_text(res_text.join("\n"));
+
+        if !bsa.residues.is_empty()
+            && ui
+                .button("Select")
+                .on_hover_text("Select all atoms of the residues the ligand buries.")
+                .clicked()
+        {
+            state.ui.selection = Selection::Atoms(
+                bsa.residues
+                    .iter()
+                    .flat_map(|(r, _)| mol.residues[*r].atoms.iter().copied())
+                    .collect(),
+            );
+            redraw = true;
         }
+    });
+
+    if redraw {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
     }
 }
 
-fn view_settings(
+/// Nonbonded interaction energy between the ligand's current pose and each receptor residue.
+fn residue_energy(
     state: &mut State,
     scene: &mut Scene,
     engine_updates: &mut EngineUpdates,
-    redraw: &mut bool,
     ui: &mut Ui,
 ) {
+    let (Some(mol), Some(lig)) = (&mut state.molecule, &state.ligand) else {
+        return;
+    };
+
+    let mut redraw = false;
+
     ui.horizontal(|ui| {
-        ui.label("View:");
-        let prev_view = state.ui.mol_view;
-        ComboBox::from_id_salt(0)
-            .width(80.)
-            .selected_text(state.ui.mol_view.to_string())
-            .show_ui(ui, |ui| {
-                for view in &[
-                    MoleculeView::Backbone,
-                    MoleculeView::Sticks,
-                    MoleculeView::BallAndStick,
-                    // MoleculeView::Cartoon,
-                    MoleculeView::SpaceFill,
-                    MoleculeView::Surface,
-                    MoleculeView::Dots,
-                ] {
-                    ui.selectable_value(&mut state.ui.mol_view, *view, view.to_string());
+        ui.label("Energy by residue:");
+
+        if ui
+            .button("Decompose")
+            .on_hover_text(format!(
+                "Lennard-Jones and Coulomb energy between the ligand, at its current pose, and \
+                each receptor residue within {RES_ENERGY_CUTOFF} Å. Colors the receptor by \
+                residue, and adds the \"{PROP_LIG_ENERGY}\" residue property."
+            ))
+            .clicked()
+        {
+            let decomp =
+                decompose_energy(mol, &lig.molecule, &lig.atom_posits, &state.lj_lookup_table);
+
+            let per_res = decomp.per_residue(mol.residues.len());
+            for (res, e) in mol.residues.iter_mut().zip(per_res) {
+                res.props
+                    .insert(PROP_LIG_ENERGY.to_owned(), PropVal::Float(e as f32));
+            }
+
+            state.volatile.residue_energy = Some(decomp);
+            state.ui.color_by_prop = Some(PROP_LIG_ENERGY.to_owned());
+            redraw = true;
+        }
+
+        let Some(decomp) = &state.volatile.residue_energy else {
+            return;
+        };
+
+        let unit = state.to_save.energy_unit;
+        ui.label(format!(
+            "LJ: {}  Coulomb: {}  Total: {}",
+            unit.fmt(decomp.lj),
+            unit.fmt(decomp.coulomb),
+            unit.fmt(decomp.total())
+        ));
+
+        let hot_spots: Vec<_> = decomp
+            .hot_spots(HOT_SPOT_THRESH)
+            .map(|r| r.residue)
+            .collect();
+
+        let color = ui_aux::active_color(state.ui.show_residue_energy);
+        if ui
+            .button(RichText::new(format!("{} hot spots", hot_spots.len())).color(color))
+            .on_hover_text(format!(
+                "Show all residues in range, ranked by interaction energy. Hot spots interact at \
+                least as favorably as {}.",
+                unit.fmt(HOT_SPOT_THRESH)
+            ))
+            .clicked()
+        {
+            state.ui.show_residue_energy = !state.ui.show_residue_energy;
+        }
+
+        if !hot_spots.is_empty()
+            && ui
+                .button("Select")
+                .on_hover_text("Select all atoms of the hot spot residues.")
+                .clicked()
+        {
+            state.ui.selection = Selection::Atoms(
+                hot_spots
+                    .iter()
+                    .flat_map(|r| mol.residues[*r].atoms.iter().copied())
+                    .collect(),
+            );
+            redraw = true;
+        }
+    });
+
+    if redraw {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+fn mmgbsa(state: &mut State, ui: &mut Ui) {
+    let (Some(mol), Some(lig)) = (&state.molecule, &state.ligand) else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Binding free energy:");
+
+        if ui
+            .button("MM-GBSA")
+            .on_hover_text(format!(
+                "Estimate binding free energy from molecular mechanics, Generalized Born \
+                solvation, and buried surface area. Averages over up to {MMGBSA_FRAMES_MAX} MD \
+                snapshots if present; otherwise uses the ligand's current pose. Excludes entropy."
+            ))
+            .clicked()
+        {
+            let lj_lut = &state.lj_lookup_table;
+
+            let result = match &state.mol_dynamics {
+                Some(md) if md.has_ligand() && !md.snapshots.is_empty() => {
+                    mmgbsa_md(mol, &lig.molecule, md, lj_lut)
                 }
-            });
+                _ => Mmgbsa {
+                    frames: vec![mmgbsa_frame(
+                        mol,
+                        &lig.molecule,
+                        &lig.atom_posits,
+                        lj_lut,
+                        0.,
+                    )],
+                },
+            };
+            state.volatile.mmgbsa = Some(result);
+        }
 
-        if state.ui.mol_view != prev_view {
-            *redraw = true;
+        let Some(result) = &state.volatile.mmgbsa else {
+            return;
+        };
+
+        let unit = state.to_save.energy_unit;
+        let mean = result.mean();
+        ui.label(format!(
+            "ΔG: {} ± {}",
+            unit.fmt(mean.total()),
+            unit.fmt(result.std_dev())
+        ))
+        .on_hover_text(format!(
+            "Over {} frames. vdW: {}  Elec: {}  GB: {}  SA: {}",
+            result.frames.len(),
+            unit.fmt(mean.vdw),
+            unit.fmt(mean.elec),
+            unit.fmt(mean.gb),
+            unit.fmt(mean.sa)
+        ));
+
+        if ui
+            .button("Save")
+            .on_hover_text("Save each frame's terms to mmgbsa.csv")
+            .clicked()
+        {
+            if let Err(e) = result.save_csv(Path::new("mmgbsa.csv")) {
+                handle_err(
+                    &mut state.ui,
+                    format!("Problem saving MM-GBSA results: {e}"),
+                );
+            }
         }
+    });
+}
 
-        ui.add_space(COL_SPACING);
+/// Protein-ligand interaction fingerprints of the current pose, and of MD snapshots.
+fn interaction_fingerprint(state: &mut State, ui: &mut Ui) {
+    let (Some(mol), Some(lig)) = (&state.molecule, &state.ligand) else {
+        return;
+    };
 
-        ui.label("Vis:");
+    ui.horizontal(|ui| {
+        ui.label("Interactions:");
 
-        ui_aux::vis_check(
-            &mut state.ui.visibility.hide_non_hetero,
-            "Peptide",
-            ui,
-            redraw,
-        );
-        ui_aux::vis_check(&mut state.ui.visibility.hide_hetero, "Hetero", ui, redraw);
+        if ui
+            .button("Fingerprint")
+            .on_hover_text(
+                "Compute which receptor residues the ligand interacts with, and how: H bonds, \
+                hydrophobic contacts, ionic interactions, and π-stacking. If MD snapshots are present, \
+                fingerprint and cluster each of them too.",
+            )
+            .clicked()
+        {
+            let cfg = &state.to_save.h_bond_cfg;
+            state.volatile.plif = Some(calc_plif(mol, &lig.molecule, &lig.atom_posits, cfg));
+
+            state.volatile.plif_snapshots = match &state.mol_dynamics {
+                Some(md) => md
+                    .snapshots
+                    .par_iter()
+                    .map(|snap| calc_plif(mol, &lig.molecule, &snap.atom_posits, cfg))
+                    .collect(),
+                None => Vec::new(),
+            };
+            state.volatile.plif_clusters =
+                cluster_plifs(&state.volatile.plif_snapshots, PLIF_CLUSTER_SIM);
+        }
+
+        let Some(plif) = &state.volatile.plif else {
+            return;
+        };
+
+        let residues = residue_union(&[plif.clone()]);
+        ui.label(
+            RichText::new(format!("{} residues", residues.len())).color(COLOR_HIGHLIGHT),
+        )
+        .on_hover_text(format!(
+            "{}\n\n{}",
+            plif.descrip(mol),
+            plif.to_bitstring(&residues)
+        ));
 
+        let snaps = &state.volatile.plif_snapshots;
+        if snaps.is_empty() {
+            return;
+        }
+
+        let cluster_count = state.volatile.plif_clusters.iter().max().map_or(0, |c| c + 1);
         ui.add_space(COL_SPACING / 2.);
+        ui.label(format!("MD: {cluster_count} clusters"))
+            .on_hover_text(format!(
+                "Snapshots grouped by interaction pattern, at a Tanimoto similarity of at least {PLIF_CLUSTER_SIM}."
+            ));
 
-        if !state.ui.visibility.hide_non_hetero {
-            // Subset of peptide.
+        if let Some(snap_plif) = snaps.get(state.ui.current_snapshot) {
+            ui.label(format!(
+                "Snapshot: cluster {}, similarity to pose {:.2}",
+                state.volatile.plif_clusters[state.ui.current_snapshot] + 1,
+                snap_plif.tanimoto(plif)
+            ))
+            .on_hover_text(snap_plif.descrip(mol));
+        }
+    });
+}
+
+/// Contact occupancy across screening hits loaded as objects: the fraction of top-ranked ligands
+/// that interact with each receptor residue. Stored as a residue property, and used to color
+/// the receptor.
+fn contact_occupancy(
+    state: &mut State,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+    ui: &mut Ui,
+) {
+    let Some(mol) = &mut state.molecule else {
+        return;
+    };
+
+    // Small molecules, e.g. docked poses, vice proteins.
+    let hits: Vec<_> = state
+        .objects
+        .iter()
+        .filter(|o| o.mol.aa_seq.is_empty())
+        .collect();
+    if hits.is_empty() {
+        return;
+    }
+
+    let mut redraw = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Screen contacts: top");
+        ui.add(DragValue::new(&mut state.ui.screen_top_n).range(0..=hits.len()))
+            .on_hover_text(
+                "The number of ligand objects to include, in load order; e.g. the top-ranked \
+                poses from a docking run's sorted output. 0 for all.",
+            );
+
+        if ui
+            .button("Occupancy")
+            .on_hover_text(
+                "Fingerprint each ligand's interactions, and color the receptor by how many of \
+                them contact each residue.",
+            )
+            .clicked()
+        {
+            let n = match state.ui.screen_top_n {
+                0 => hits.len(),
+                n => n.min(hits.len()),
+            };
+
+            let rec: &Molecule = mol;
+            let cfg = &state.to_save.h_bond_cfg;
+            let plifs: Vec<_> = hits[..n]
+                .par_iter()
+                .map(|obj| calc_plif(rec, &obj.mol, &obj.atom_posits(), cfg))
+                .collect();
+            let occupancy = residue_occupancy(&plifs, mol.residues.len());
+
+            for (res, occ) in mol.residues.iter_mut().zip(&occupancy) {
+                res.props
+                    .insert(PROP_CONTACT_OCCUPANCY.to_owned(), PropVal::Float(*occ));
+            }
+
+            state.volatile.contact_occupancy = Some((occupancy, n));
+            state.ui.color_by_prop = Some(PROP_CONTACT_OCCUPANCY.to_owned());
+            redraw = true;
+        }
+
+        let Some((occupancy, n)) = &state.volatile.contact_occupancy else {
+            return;
+        };
+
+        let mut anchors: Vec<_> = occupancy
+            .iter()
+            .enumerate()
+            .filter(|(_, occ)| **occ >= ANCHOR_OCCUPANCY_MIN)
+            .collect();
+        anchors.sort_by(|a, b| b.1.total_cmp(a.1));
+
+        let anchor_text: Vec<_> = anchors
+            .iter()
+            .map(|(i, occ)| {
+                let res = &mol.residues[*i];
+                let name = match &res.res_type {
+                    ResidueType::AminoAcid(aa) => aa.to_string(),
+                    ResidueType::Water => "Water".to_owned(),
+                    ResidueType::Other(n) => n.clone(),
+                };
+                format!("{name} {}: {:.0}%", res.serial_number, *occ * 100.)
+            })
+            .collect();
+
+        ui.label(
+            RichText::new(format!(
+                "{} anchor residues, over {n} ligands",
+                anchors.len()
+            ))
+            .color(COLOR_HIGHLIGHT),
+        )
+        .on_hover_text(format!(
+            "Residues contacted by at least {:.0}% of ligands:\n{}",
+            ANCHOR_OCCUPANCY_MIN * 100.,
+            anchor_text.join("\n")
+        ));
+    });
+
+    if redraw {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+}
+
+/// Buttons to open 2D analysis plot windows.
+fn plot_toggles(state: &mut State, ui: &mut Ui) {
+    if state.molecule.is_none() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Plots:");
+
+        let color = ui_aux::active_color(state.ui.show_contact_map);
+        if ui
+            .button(RichText::new("Contact map").color(color))
+            .on_hover_text("Residue-residue distances. Click a cell to select its residues.")
+            .clicked()
+        {
+            state.ui.show_contact_map = !state.ui.show_contact_map;
+        }
+
+        let color = ui_aux::active_color(state.ui.show_ramachandran);
+        if ui
+            .button(RichText::new("Ramachandran").color(color))
+            .on_hover_text(
+                "Backbone φ/ψ angles, with outliers flagged. Click a point to select its residue.",
+            )
+            .clicked()
+        {
+            state.ui.show_ramachandran = !state.ui.show_ramachandran;
+        }
+
+        let color = ui_aux::active_color(state.ui.show_validation);
+        if ui
+            .button(RichText::new("Health").color(color))
+            .on_hover_text(
+                "Structure quality: clashscore, Ramachandran and rotamer outliers, and backbone \
+                geometry. Click an outlier to select it.",
+            )
+            .clicked()
+        {
+            state.ui.show_validation = !state.ui.show_validation;
+        }
+
+        let color = ui_aux::active_color(state.ui.show_gnm);
+        if ui
+            .button(RichText::new("B-factors").color(color))
+            .on_hover_text(
+                "Per-residue fluctuations predicted by a Gaussian network model, compared to \
+                experimental B-factors. Disagreeing regions are shaded.",
+            )
+            .clicked()
+        {
+            state.ui.show_gnm = !state.ui.show_gnm;
+        }
+    });
+}
+
+/// Loaded structures: Visibility of each, color and transform of additional ones, and
+/// superposition of the active one onto the primary molecule.
+fn objects(state: &mut State, scene: &mut Scene, engine_updates: &mut EngineUpdates, ui: &mut Ui) {
+    let mut redraw = false;
+    let mut redraw_all = false;
+    let mut remove = None;
+
+    ui.horizontal(|ui| {
+        ui.label("Objects:");
+
+        if ui
+            .button("Add")
+            .on_hover_text(
+                "Load an additional molecule: e.g. a mutant or homolog to superpose onto this one, \
+                or a ligand analog to overlay onto the open ligand.",
+            )
+            .clicked()
+        {
+            state.volatile.dialogs.load_object.pick_file();
+        }
+
+        if !state.objects.is_empty() {
             ui_aux::vis_check(
-                &mut state.ui.visibility.hide_sidechains,
-                "Sidechains",
+                &mut state.ui.visibility.hide_objects,
+                "Objects",
                 ui,
-                redraw,
+                &mut redraw,
             );
         }
+    });
 
-        ui_aux::vis_check(&mut state.ui.visibility.hide_hydrogen, "H", ui, redraw);
+    for id in state.object_ids() {
+        // The primary molecule and ligand only have visibility here; their other settings are
+        // in the view and docking sections.
+        let ObjectId::Extra(i) = id else {
+            let name = state.object_name(id).to_owned();
+            let mut visible = state.object_visible(id);
 
-        if !state.ui.visibility.hide_hetero {
-            // Subset of hetero.
-            ui_aux::vis_check(&mut state.ui.visibility.hide_water, "Water", ui, redraw);
-        }
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(name).color(Color32::WHITE));
+                if ui.checkbox(&mut visible, "Show").changed() {
+                    redraw_all = true;
+                }
+            });
 
-        if state.ligand.is_some() {
-            let color = ui_aux::active_color(!state.ui.visibility.hide_ligand);
-            if ui.button(RichText::new("Lig").color(color)).clicked() {
-                state.ui.visibility.hide_ligand = !state.ui.visibility.hide_ligand;
+            state.set_object_visible(id, visible);
+            continue;
+        };
+
+        let obj = &mut state.objects[i];
+        ui.horizontal(|ui| {
+            let active = state.volatile.object_active == Some(i);
+            let color = if active {
+                COLOR_ACTIVE_RADIO
+            } else {
+                Color32::GOLD
+            };
+            if ui
+                .button(RichText::new(&obj.mol.ident).color(color))
+                .on_hover_text("Make this the active object, for superposition and transforms.")
+                .clicked()
+            {
+                state.volatile.object_active = Some(i);
+            }
+
+            if ui.checkbox(&mut obj.visible, "Show").changed() {
+                redraw = true;
+            }
+
+            let prev_scheme = obj.color_scheme;
+            ComboBox::from_id_salt(100 + i)
+                .width(70.)
+                .selected_text(obj.color_scheme.to_string())
+                .show_ui(ui, |ui| {
+                    for scheme in [ObjColorScheme::Uniform, ObjColorScheme::Element] {
+                        ui.selectable_value(&mut obj.color_scheme, scheme, scheme.to_string());
+                    }
+                });
+            if obj.color_scheme != prev_scheme {
+                redraw = true;
+            }
 
-                if state.ui.visibility.hide_ligand {
-                    scene.entities.retain(|ent| {
-                        ent.class != EntityType::Ligand as u32
-                            && ent.class != EntityType::DockingSite as u32
-                    });
-                } else {
-                    draw_ligand(state, scene);
+            if active {
+                ui.add_space(COL_SPACING / 2.);
+                ui.label("Offset:");
+                for v in [&mut obj.offset.x, &mut obj.offset.y, &mut obj.offset.z] {
+                    if ui.add(DragValue::new(v).speed(0.1).suffix(" Å")).changed() {
+                        redraw = true;
+                    }
                 }
 
-                engine_updates.entities = true;
-                engine_updates.lighting = true; // docking light.
+                if ui
+                    .button("Reset")
+                    .on_hover_text("Remove this object's offset and rotation.")
+                    .clicked()
+                {
+                    obj.orientation = QuaternionF64::new_identity();
+                    obj.offset = Vec3F64::new_zero();
+                    redraw = true;
+                }
             }
-        }
 
-        ui_aux::vis_check(&mut state.ui.visibility.hide_h_bonds, "H bonds", ui, redraw);
-        // vis_check(&mut state.ui.visibility.dim_peptide, "Dim peptide", ui, redraw);
-
-        if state.ligand.is_some() {
-            ui.add_space(COL_SPACING / 2.);
-            // Not using `vis_check` for this because its semantics are inverted.
-            let color = ui_aux::active_color(state.ui.visibility.dim_peptide);
             if ui
-                .button(RichText::new("Dim peptide").color(color))
+                .button(RichText::new("❌").color(Color32::LIGHT_RED))
                 .clicked()
             {
-                state.ui.visibility.dim_peptide = !state.ui.visibility.dim_peptide;
-                *redraw = true;
+                remove = Some(i);
             }
-        }
+        });
+    }
 
-        if let Some(mol) = &state.molecule {
-            if let Some(dens) = &mol.elec_density {
-                let mut redraw_dens = false;
-                ui_aux::vis_check(
-                    &mut state.ui.visibility.hide_density,
-                    "Density",
-                    ui,
-                    &mut redraw_dens,
-                );
+    if let Some(i) = remove {
+        state.objects.remove(i);
+        state.volatile.object_active = match state.volatile.object_active {
+            Some(a) if a == i => None,
+            Some(a) if a > i => Some(a - 1),
+            a => a,
+        };
+        state.volatile.superpose_result = None;
+        state.volatile.mcs_alignment = None;
+        redraw = true;
+    }
 
-                if redraw_dens {
-                    if state.ui.visibility.hide_density {
-                        scene
-                            .entities
-                            .retain(|ent| ent.class != EntityType::Density as u32);
+    if let (Some(mol), Some(i)) = (&state.molecule, state.volatile.object_active) {
+        if let Some(obj) = state.objects.get_mut(i) {
+            ui.horizontal(|ui| {
+                ui.label("Superpose:");
+
+                ComboBox::from_id_salt(16)
+                    .width(70.)
+                    .selected_text(state.volatile.superpose_mode.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in [PairMode::CAlpha, PairMode::Selection] {
+                            ui.selectable_value(
+                                &mut state.volatile.superpose_mode,
+                                mode,
+                                mode.to_string(),
+                            );
+                        }
+                    });
+
+                ui.checkbox(&mut state.volatile.superpose_align_seq, "Align seq")
+                    .on_hover_text(
+                        "Pair residues using a sequence alignment of each chain. Use for structures \
+                        with different numbering, or gaps. Otherwise, pair by chain ID and residue \
+                        number.",
+                    );
+
+                if ui
+                    .button(RichText::new("Superpose").color(COLOR_HIGHLIGHT))
+                    .on_hover_text("Align the active object onto this molecule, over the paired atoms.")
+                    .clicked()
+                {
+                    obj.bake_transform();
+
+                    let target_atoms = atoms_for_mode(
+                        mol,
+                        state.volatile.superpose_mode,
+                        &mol.sel_atom_indices(&state.ui.selection),
+                    );
+                    let res_map = if state.volatile.superpose_align_seq {
+                        residue_map_by_alignment(mol, &obj.mol)
                     } else {
-                        draw_density(&mut scene.entities, dens);
+                        residue_map_by_numbering(mol, &obj.mol)
+                    };
+                    let pairs = pair_atoms(mol, &obj.mol, &target_atoms, &res_map);
+
+                    match superpose(&mut obj.mol, mol, &pairs) {
+                        Some(rmsd) => {
+                            state.volatile.superpose_result = Some((rmsd, pairs.len()));
+                            redraw = true;
+                        }
+                        None => handle_err(
+                            &mut state.ui,
+                            format!(
+                                "Unable to superpose; found {} matching atom pairs. At least 3 are required.",
+                                pairs.len()
+                            ),
+                        ),
                     }
-                    engine_updates.entities = true;
                 }
 
-                let mut redraw_dens_surface = false;
-                ui_aux::vis_check(
-                    &mut state.ui.visibility.hide_density_surface,
-                    "Density sfc",
-                    ui,
-                    &mut redraw_dens_surface,
-                );
+                if let Some((rmsd, n)) = state.volatile.superpose_result {
+                    ui.label(format!("RMSD: {rmsd:.2} Å over {n} atoms"));
+                }
+            });
+        }
+    }
 
-                if !state.ui.visibility.hide_density_surface {
-                    let iso_prev = state.ui.density_iso_level;
+    let mut redraw_lig = false;
+    if let (Some(lig), Some(i)) = (&state.ligand, state.volatile.object_active) {
+        if let Some(obj) = state.objects.get_mut(i) {
+            ui.horizontal(|ui| {
+                ui.label("Ligand overlay:");
 
-                    ui.spacing_mut().slider_width = 300.;
-                    ui.add(Slider::new(
-                        &mut state.ui.density_iso_level,
-                        DENS_ISO_MIN..=DENS_ISO_MAX,
-                    ));
-                    if state.ui.density_iso_level != iso_prev {
-                        state.volatile.flags.make_density_mesh = true;
+                if ui
+                    .button(RichText::new("MCS align").color(COLOR_HIGHLIGHT))
+                    .on_hover_text(
+                        "Align the active object onto the ligand, over their maximum common \
+                        substructure. E.g. to overlay an analog onto a crystallized reference.",
+                    )
+                    .clicked()
+                {
+                    obj.bake_transform();
+
+                    match align_by_mcs(&lig.molecule, &lig.atom_posits, &mut obj.mol) {
+                        Some((pairs, rmsd)) => {
+                            state.volatile.mcs_alignment = Some(McsAlignment {
+                                object: i,
+                                pairs,
+                                rmsd,
+                            });
+                            redraw = true;
+                            redraw_lig = true;
+                        }
+                        None => handle_err(
+                            &mut state.ui,
+                            "Unable to align; fewer than 3 atoms of the common substructure match."
+                                .to_owned(),
+                        ),
                     }
                 }
 
-                // todo
-                if redraw_dens_surface {
-                    if state.ui.visibility.hide_density_surface {
-                        let _ = &mut scene
-                            .entities
-                            .retain(|ent| ent.class != EntityType::DensitySurface as u32);
-                    } else {
-                        draw_density_surface(&mut scene.entities);
-                    }
-                    engine_updates.entities = true;
-                    redraw_dens_surface = false;
+                let Some(aln) = &state.volatile.mcs_alignment else {
+                    return;
+                };
+                if aln.object != i {
+                    return;
                 }
-            }
+
+                ui.label(format!(
+                    "MCS: {} atoms. RMSD: {:.2} Å",
+                    aln.pairs.len(),
+                    aln.rmsd
+                ));
+
+                if ui
+                    .checkbox(&mut state.volatile.show_mcs_mapping, "Show mapping")
+                    .on_hover_text("Color matched atoms of the ligand and object the same.")
+                    .changed()
+                {
+                    redraw = true;
+                    redraw_lig = true;
+                }
+            });
         }
-    });
+    }
+
+    for (i, obj) in state.objects.iter_mut().enumerate() {
+        obj.update_compact(
+            state.to_save.memory_budget,
+            state.volatile.object_active == Some(i),
+        );
+    }
+
+    if redraw_all {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+        engine_updates.lighting = true; // docking light.
+    } else if redraw {
+        draw_objects(state, scene);
+        engine_updates.entities = true;
+    }
+    if redraw_lig {
+        draw_ligand(state, scene);
+        engine_updates.entities = true;
+    }
 }
 
-fn settings(state: &mut State, scene: &mut Scene, ui: &mut Ui) {
+fn settings(state: &mut State, scene: &mut Scene, redraw: &mut bool, ui: &mut Ui) {
     if state.ui.show_settings {
         ui.horizontal(|ui| {
             ui.heading("Settings");
@@ -1384,6 +4517,132 @@ fn settings(state: &mut State, scene: &mut Scene, ui: &mut Ui) {
 
                 state.update_save_prefs();
             }
+
+            ui.add_space(COL_SPACING);
+            ui.label("Angles:");
+            let angle_prev = state.to_save.angle_unit;
+            ComboBox::from_id_salt(19)
+                .width(70.)
+                .selected_text(state.to_save.angle_unit.to_string())
+                .show_ui(ui, |ui| {
+                    for unit in [AngleUnit::Degrees, AngleUnit::Radians] {
+                        ui.selectable_value(&mut state.to_save.angle_unit, unit, unit.to_string());
+                    }
+                });
+
+            ui.label("Energy:");
+            let energy_prev = state.to_save.energy_unit;
+            ComboBox::from_id_salt(20)
+                .width(70.)
+                .selected_text(state.to_save.energy_unit.to_string())
+                .show_ui(ui, |ui| {
+                    for unit in [EnergyUnit::KcalMol, EnergyUnit::KjMol] {
+                        ui.selectable_value(&mut state.to_save.energy_unit, unit, unit.to_string());
+                    }
+                });
+
+            if state.to_save.angle_unit != angle_prev || state.to_save.energy_unit != energy_prev {
+                state.update_save_prefs();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("H bonds: Distance tolerance:");
+
+            let unit = state.to_save.angle_unit;
+            let cfg = &mut state.to_save.h_bond_cfg;
+
+            let mut changed = ui
+                .add(
+                    DragValue::new(&mut cfg.dist_tol)
+                        .range(0.05..=1.)
+                        .speed(0.01)
+                        .suffix(" Å"),
+                )
+                .on_hover_text(
+                    "Donor-acceptor distances within this of the typical distance for the pair's \
+                    elements are H bonds.",
+                )
+                .changed();
+
+            ui.label("Min D-H···A angle:");
+            let mut angle = unit.convert(cfg.angle_min);
+            let suffix = match unit {
+                AngleUnit::Degrees => "°",
+                AngleUnit::Radians => " rad",
+            };
+            if ui
+                .add(
+                    DragValue::new(&mut angle)
+                        .range(unit.convert(PI / 2.)..=unit.convert(PI))
+                        .speed(unit.convert(1_f64.to_radians()))
+                        .suffix(suffix),
+                )
+                .changed()
+            {
+                cfg.angle_min = unit.to_radians(angle);
+                changed = true;
+            }
+
+            if ui.button("Reset").clicked() {
+                *cfg = Default::default();
+                changed = true;
+            }
+
+            if changed {
+                if let Some(mol) = &mut state.molecule {
+                    mol.update_h_bonds(&state.to_save.h_bond_cfg);
+                    *redraw = true;
+                }
+                state.update_save_prefs();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .checkbox(&mut state.to_save.memory_budget, "Memory budget")
+                .on_hover_text(
+                    "Reduce memory use, for very large structures. The opened file's text and parsed \
+                    structure aren't kept; saving writes from the molecule instead. Large hidden \
+                    objects are stored compactly.",
+                )
+                .changed()
+            {
+                if state.to_save.memory_budget {
+                    state.pdb = None;
+                    state.cif_pdb_raw = None;
+                }
+                state.update_save_prefs();
+            }
+
+            ui.add_space(COL_SPACING);
+            ui.label("Log level:");
+            let log_prev = state.to_save.log_level;
+            ComboBox::from_id_salt(45)
+                .width(80.)
+                .selected_text(state.to_save.log_level.to_str())
+                .show_ui(ui, |ui| {
+                    for level in [
+                        LogLevel::Off,
+                        LogLevel::Error,
+                        LogLevel::Warn,
+                        LogLevel::Info,
+                        LogLevel::Debug,
+                        LogLevel::Trace,
+                    ] {
+                        ui.selectable_value(&mut state.to_save.log_level, level, level.to_str());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "How much to print to the console. Debug includes timings, e.g. for surfaces, \
+                    MD runs, and GPU transfers.",
+                );
+
+            if state.to_save.log_level != log_prev {
+                logging::set_level(state.to_save.log_level);
+                state.update_save_prefs();
+            }
         });
         ui.add_space(ROW_SPACING * 2.);
     }
@@ -1415,7 +4674,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
             &mut engine_updates,
         );
 
-        settings(state, scene, ui);
+        settings(state, scene, &mut redraw_mol, ui);
 
         ui.horizontal_wrapped(|ui| {
             let color_settings = if state.ui.show_settings {
@@ -1454,23 +4713,35 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
 
             let mut dm_loaded = None; // avoids a double-borrow error.
             if let Some(mol) = &mut state.molecule {
-                if state.pdb.is_some() {
-                    if ui.button("Save").clicked() {
-                        let extension = "cif";
-
-                        let filename = {
-                            let name = if mol.ident.is_empty() {
-                                "molecule".to_string()
-                            } else {
-                                mol.ident.clone()
-                            };
-                            format!("{name}.{extension}")
+                if ui.button("Save").clicked() {
+                    let extension = "cif";
+
+                    let filename = {
+                        let name = if mol.ident.is_empty() {
+                            "molecule".to_string()
+                        } else {
+                            mol.ident.clone()
                         };
+                        format!("{name}.{extension}")
+                    };
 
-                        state.volatile.dialogs.save.config_mut().default_file_name =
-                            filename.to_string();
-                        state.volatile.dialogs.save.save_file();
-                    }
+                    state.volatile.dialogs.save.config_mut().default_file_name =
+                        filename.to_string();
+                    state.volatile.dialogs.save.save_file();
+                }
+
+                if ui
+                    .button("Save session")
+                    .on_hover_text(
+                        "Save the molecule, ligand pose, docking site, other objects, MD \
+                        trajectory, selections, view settings, and camera, to restore later by \
+                        opening the file.",
+                    )
+                    .clicked()
+                {
+                    state.volatile.dialogs.save.config_mut().default_file_name =
+                        format!("{}.{SESSION_EXT}", mol.ident);
+                    state.volatile.dialogs.save.save_file();
                 }
 
                 // todo: Move these A/R. LIkely in a sub menu.
@@ -1506,7 +4777,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                             match density_from_2fo_fc_rcsb_gemmi(&mol.ident) {
                                 Ok(dm) => {
                                     dm_loaded = Some(dm);
-                                    println!(
+                                    info!(
                                         "Succsesfully loaded density data from RSCB using Gemmi."
                                     );
                                 }
@@ -1592,7 +4863,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                                     match DensityMap::new(&mut cursor) {
                                         Ok(dm) => {
                                             dm_loaded = Some(dm);
-                                            println!("Succsesfully loaded Map rom RSCB.");
+                                            info!("Succsesfully loaded Map rom RSCB.");
                                         }
                                         Err(e) => {
                                             let msg = format!(
@@ -1747,8 +5018,76 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                     }
                 }
             }
+
+            ui.add_space(COL_SPACING / 2.);
+            let color_builder = if state.ui.show_peptide_builder {
+                Color32::LIGHT_RED
+            } else {
+                color_open_tools
+            };
+            if ui
+                .button(RichText::new("Build peptide").color(color_builder))
+                .clicked()
+            {
+                state.ui.show_peptide_builder = !state.ui.show_peptide_builder;
+            }
+
+            if state.molecule.is_some() {
+                let color_loops = if state.ui.show_loop_builder {
+                    Color32::LIGHT_RED
+                } else {
+                    color_open_tools
+                };
+                if ui
+                    .button(RichText::new("Model loops").color(color_loops))
+                    .on_hover_text("Build residues missing from the model into chain breaks.")
+                    .clicked()
+                {
+                    state.ui.show_loop_builder = !state.ui.show_loop_builder;
+                }
+            }
         });
 
+        if state.ui.show_peptide_builder {
+            peptide_builder(state, &mut redraw_mol, ui);
+        }
+        if state.ui.show_loop_builder {
+            loop_builder(state, &mut redraw_mol, ui);
+        }
+
+        if let Some(pending) = &state.volatile.pending_load {
+            let label = format!(
+                "Loading {}... {}s",
+                pending
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                pending.start.elapsed().as_secs()
+            );
+
+            // The thread stops at its next check; we discard its result.
+            if ui_aux::progress_bar(&label, &pending.task.progress, ui) {
+                state.volatile.pending_load = None;
+                if !state.volatile.load_preview.is_empty() {
+                    state.volatile.load_preview = Vec::new();
+                    state.volatile.flags.new_preview_loaded = true;
+                }
+            }
+        }
+
+        if let Some(pending) = &state.volatile.sas_mesh_pending {
+            if ui_aux::progress_bar("Surface", &pending.progress, ui) {
+                state.volatile.sas_mesh_pending = None;
+                state.volatile.flags.sas_mesh_created = false;
+            }
+        }
+
+        // If cancelled, the run stops at its next step; we keep the snapshots so far.
+        if let Some(task) = &state.volatile.md_run {
+            ui_aux::progress_bar("MD", &task.progress, ui);
+        }
+
         ui.add_space(ROW_SPACING);
         let mut close_ligand = false; // to avoid borrow error.
         if let Some(ligand) = &mut state.ligand {
@@ -1759,6 +5098,37 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                     close_ligand = true;
                 }
 
+                if ui
+                    .button("Add H")
+                    .on_hover_text("Add hydrogens to ligand atoms missing them, based on valence and bond orders.")
+                    .clicked()
+                {
+                    let count = ligand.molecule.add_hydrogens_ligand();
+                    if count > 0 {
+                        // Atom indices have changed; rebuild, keeping the pose's position.
+                        let docking_site = ligand.docking_site.clone();
+                        let anchor_posit = ligand.pose.anchor_posit;
+                        let orientation = ligand.pose.orientation;
+
+                        *ligand = Ligand::new(ligand.molecule.clone());
+                        ligand.docking_site = docking_site;
+                        ligand.pose.anchor_posit = anchor_posit;
+                        ligand.pose.orientation = orientation;
+                        ligand.position_atoms(None);
+
+                        redraw_lig = true;
+                    }
+                    state.ui.cmd_line_output = format!("Added {count} hydrogens to the ligand");
+                    state.ui.cmd_line_out_is_err = false;
+                }
+
+                if let Some(cleanup) = &state.volatile.lig_cleanup {
+                    ui.label(format!("Cleaned: {:.2} Å", cleanup.posit_rmsd)).on_hover_text(format!(
+                        "Bond lengths and angles were idealized on import, keeping torsions.\n{}",
+                        cleanup.descrip()
+                    ));
+                }
+
                 ui.add_space(COL_SPACING);
 
                 ui.label("Rotate bonds:");
@@ -1780,7 +5150,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                 ui.add_space(COL_SPACING);
 
                 if let Some(energy) = &state.ui.binding_energy_disp {
-                    ui.label(format!("{:.2?}", energy)); // todo placeholder.
+                    ui.label(energy.descrip(state.to_save.energy_unit));
                 }
 
                 // todo: temp, or at least temp here
@@ -1788,7 +5158,10 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                 ui.label(format!("Lig or: {}", ligand.pose.orientation));
                 if let ConformationType::Flexible { torsions } = &ligand.pose.conformation_type {
                     for torsion in torsions {
-                        ui.label(format!("T: {:.3}", torsion.dihedral_angle));
+                        ui.label(format!(
+                            "T: {}",
+                            state.to_save.angle_unit.fmt(torsion.dihedral_angle as f64)
+                        ));
                     }
                 }
             });
@@ -1796,6 +5169,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
 
         ui.add_space(ROW_SPACING);
         selection_section(state, scene, &mut redraw_mol, &mut engine_updates, ui);
+        named_selections(state, &mut redraw_mol, ui);
 
         ui.add_space(ROW_SPACING);
 
@@ -1812,8 +5186,20 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
         ui.horizontal(|ui| {
             ui.vertical(|ui| {
                 view_settings(state, scene, &mut engine_updates, &mut redraw_mol, ui);
+                partial_surfaces(state, scene, &mut engine_updates, ui);
+                blink_controls(state, scene, &mut engine_updates, ui);
+                pockets(state, scene, &mut engine_updates, ui);
+                sasa(state, ui);
+                interface(state, scene, &mut engine_updates, ui);
+                clashes(state, scene, &mut engine_updates, ui);
+                protonation(state, scene, &mut engine_updates, ui);
+                protein_md(state, scene, &mut engine_updates, ui);
+                plot_toggles(state, ui);
+                objects(state, scene, &mut engine_updates, ui);
+                contact_occupancy(state, scene, &mut engine_updates, ui);
                 ui.add_space(ROW_SPACING);
                 chain_selector(state, &mut redraw_mol, ui);
+                assemblies(state, &mut redraw_mol, ui);
 
                 // todo: Show hide based on AaCategory? i.e. residue.amino_acid.category(). Hydrophilic, acidic etc.
 
@@ -1823,7 +5209,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
 
         ui.add_space(ROW_SPACING);
 
-        residue_search(state, scene, &mut redraw_mol, ui);
+        residue_search(state, scene, &mut engine_updates, &mut redraw_mol, ui);
 
         if state.ui.show_docking_tools {
             ui.add_space(ROW_SPACING);
@@ -1837,6 +5223,24 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                 &mut engine_updates,
                 ui,
             );
+
+            ui.add_space(ROW_SPACING / 2.);
+            interaction_fingerprint(state, ui);
+
+            ui.add_space(ROW_SPACING / 2.);
+            buried_area(state, scene, &mut engine_updates, ui);
+
+            ui.add_space(ROW_SPACING / 2.);
+            residue_energy(state, scene, &mut engine_updates, ui);
+
+            ui.add_space(ROW_SPACING / 2.);
+            mmgbsa(state, ui);
+
+            ui.add_space(ROW_SPACING / 2.);
+            ligand_charges(state, ui);
+
+            ui.add_space(ROW_SPACING / 2.);
+            torsion_scan_tool(state, ui);
         }
 
         // todo: Allow switching between chains and secondary-structure features here.
@@ -1899,6 +5303,7 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
             if let Err(e) = load_file(
                 path,
                 state,
+                true,
                 &mut redraw_mol,
                 &mut reset_cam,
                 &mut engine_updates,
@@ -1910,8 +5315,28 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
             engine_updates.lighting = true;
         }
 
+        if let Some(path) = &state.volatile.dialogs.load_object.take_picked() {
+            match state.open_object(path) {
+                Ok(_) => {
+                    draw_objects(state, scene);
+                    engine_updates.entities = true;
+                }
+                Err(e) => handle_err(&mut state.ui, e.to_string()),
+            }
+        }
+
         if let Some(path) = &state.volatile.dialogs.save.take_picked() {
-            state.save(path).ok();
+            if let Err(e) = state.save(path, &scene.camera) {
+                handle_err(&mut state.ui, format!("Problem saving: {e}"));
+            }
+        }
+
+        if let Some(path) = &state.volatile.dialogs.save_csv.take_picked() {
+            if let Some(md) = &state.mol_dynamics {
+                if let Err(e) = save_reports_csv(&md.reports, path) {
+                    handle_err(&mut state.ui, format!("Problem saving MD energies: {e}"));
+                }
+            }
         }
 
         if let Some(path) = &state.volatile.dialogs.autodock_path.take_picked() {
@@ -1982,9 +5407,53 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                 reset_camera(scene, &mut state.ui.view_depth, &mut engine_updates, mol);
             }
         }
+
+        // After resetting the camera, which loading the session requested.
+        if let Some(snap) = state.volatile.session_cam.take() {
+            util::apply_cam_snap(&snap, scene, &mut engine_updates);
+        }
     });
 
+    let sel_changed_contact = ui_plots::contact_map_window(state, ctx);
+    let rama_changed = ui_plots::ramachandran_window(state, ctx);
+    let sel_changed_health = ui_plots::validation_window(state, ctx);
+    let gnm_changed = ui_plots::gnm_window(state, ctx);
+    ui_plots::md_energy_window(state, ctx);
+    let sel_changed_energy = ui_plots::residue_energy_window(state, ctx);
+    let sel_changed_traj = ui_plots::trajectory_window(state, ctx);
+
+    if let Some(i) = ui_plots::torsion_scan_window(state, ctx) {
+        if let (Some(lig), Some(scan)) = (&mut state.ligand, &state.volatile.torsion_scan) {
+            lig.pose.conformation_type = ConformationType::AbsolutePosits;
+            lig.atom_posits = scan.points[i].atom_posits.clone();
+        }
+        draw_ligand(state, scene);
+        engine_updates.entities = true;
+    }
+
+    if let Some(i) = ui_plots::dock_results_window(state, ctx) {
+        if select_docked_pose(state, i) {
+            draw_molecule(state, scene);
+        }
+        draw_ligand(state, scene);
+        engine_updates.entities = true;
+    }
+    if sel_changed_contact
+        || rama_changed
+        || sel_changed_health
+        || gnm_changed
+        || sel_changed_energy
+        || sel_changed_traj
+    {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+
     state.volatile.dialogs.load.update(ctx);
+    state.volatile.dialogs.load_object.update(ctx);
+    state.volatile.dialogs.load_script.update(ctx);
+    state.volatile.dialogs.save_macro.update(ctx);
+    state.volatile.dialogs.save_csv.update(ctx);
     state.volatile.dialogs.save.update(ctx);
     state.volatile.dialogs.autodock_path.update(ctx);
 
@@ -2025,5 +5494,8 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
 
     handle_scene_flags(state, scene, &mut engine_updates);
 
+    state.events.check_selection(&state.ui.selection);
+    state.events.dispatch();
+
     engine_updates
 }
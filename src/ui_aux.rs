@@ -1,17 +1,19 @@
 //! Misc utility-related UI functionality.
 
 use bio_files::ResidueType;
-use egui::{Color32, RichText, Ui};
+use egui::{Color32, ProgressBar, RichText, Ui};
 use na_seq::AaIdent;
 
 use crate::{
     Selection, mol_drawing,
     mol_drawing::{CHARGE_MAP_MAX, CHARGE_MAP_MIN},
-    molecule::{Atom, Ligand, Molecule, Residue},
+    molecule::{Atom, Ligand, Molecule, Properties, Residue},
+    progress::Progress,
     ui::{COLOR_ACTIVE, COLOR_ACTIVE_RADIO, COLOR_INACTIVE},
+    units::AngleUnit,
 };
 
-fn disp_atom_data(atom: &Atom, residues: &[Residue], ui: &mut Ui) {
+fn disp_atom_data(atom: &Atom, residues: &[Residue], angle_unit: AngleUnit, ui: &mut Ui) {
     let mut aa = String::new();
     if let Some(res_i) = atom.residue {
         let res = &residues[res_i];
@@ -41,7 +43,7 @@ fn disp_atom_data(atom: &Atom, residues: &[Residue], ui: &mut Ui) {
 
     if let Some(res_i) = atom.residue {
         let res = &residues[res_i];
-        text_c += &format!("  {}", res.descrip());
+        text_c += &format!("  {}", res.descrip(angle_unit));
     }
 
     ui.label(RichText::new(text_a).color(Color32::GOLD));
@@ -65,10 +67,28 @@ fn disp_atom_data(atom: &Atom, residues: &[Residue], ui: &mut Ui) {
         let color = Color32::from_rgb((r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8);
         ui.label(RichText::new(format!("{plus}q: {q:.2}")).color(color));
     }
+
+    disp_props(&atom.props, ui);
+}
+
+/// Display stored properties, sorted by key.
+fn disp_props(props: &Properties, ui: &mut Ui) {
+    let mut props: Vec<_> = props.iter().collect();
+    props.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (key, val) in props {
+        ui.label(RichText::new(format!("{key}: {val}")).color(Color32::LIGHT_BLUE));
+    }
 }
 
 /// Display text of the selected atom
-pub fn selected_data(mol: &Molecule, ligand: &Option<Ligand>, selection: &Selection, ui: &mut Ui) {
+pub fn selected_data(
+    mol: &Molecule,
+    ligand: &Option<Ligand>,
+    selection: &Selection,
+    angle_unit: AngleUnit,
+    ui: &mut Ui,
+) {
     match selection {
         Selection::Atom(sel_i) => {
             if *sel_i >= mol.atoms.len() {
@@ -76,7 +96,7 @@ pub fn selected_data(mol: &Molecule, ligand: &Option<Ligand>, selection: &Select
             }
 
             let atom = &mol.atoms[*sel_i];
-            disp_atom_data(atom, &mol.residues, ui);
+            disp_atom_data(atom, &mol.residues, angle_unit, ui);
         }
         Selection::AtomLigand(sel_i) => {
             let Some(lig) = ligand else {
@@ -87,7 +107,7 @@ pub fn selected_data(mol: &Molecule, ligand: &Option<Ligand>, selection: &Select
             }
 
             let atom = &lig.molecule.atoms[*sel_i];
-            disp_atom_data(atom, &[], ui);
+            disp_atom_data(atom, &[], angle_unit, ui);
         }
         Selection::Residue(sel_i) => {
             if *sel_i >= mol.residues.len() {
@@ -95,7 +115,8 @@ pub fn selected_data(mol: &Molecule, ligand: &Option<Ligand>, selection: &Select
             }
 
             let res = &mol.residues[*sel_i];
-            ui.label(RichText::new(res.descrip()).color(Color32::GOLD));
+            ui.label(RichText::new(res.descrip(angle_unit)).color(Color32::GOLD));
+            disp_props(&res.props, ui);
         }
         Selection::Atoms(is) => {
             // todo: A/R
@@ -118,6 +139,29 @@ pub fn active_color(val: bool) -> Color32 {
     if val { COLOR_ACTIVE } else { COLOR_INACTIVE }
 }
 
+/// A labeled progress bar, showing the current stage, and a Cancel button. Cancels `progress` if
+/// clicked, and returns `true`.
+pub fn progress_bar(label: &str, progress: &Progress, ui: &mut Ui) -> bool {
+    let mut cancelled = false;
+
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(
+            ProgressBar::new(progress.fraction())
+                .desired_width(200.)
+                .text(progress.stage())
+                .animate(true),
+        );
+
+        if ui.button("Cancel").clicked() {
+            progress.cancel();
+            cancelled = true;
+        }
+    });
+
+    cancelled
+}
+
 /// Visually distinct; fore buttons that operate as radio buttons
 pub fn active_color_sel(val: bool) -> Color32 {
     if val {
@@ -0,0 +1,1442 @@
+//! 2D analysis plots, and the structure health dashboard, shown in their own windows. Clicking a
+//! plot or outlier selects the corresponding atoms or residues in the 3D view.
+
+use std::{f64::consts::PI, path::Path};
+
+use bio_files::ResidueType;
+use egui::{
+    Align2, Color32, ColorImage, ComboBox, Context, FontId, Grid, Image, Pos2, Rect, RichText,
+    ScrollArea, Sense, Slider, Stroke, TextureOptions, Window, pos2, vec2,
+};
+
+use crate::{
+    Selection, State,
+    aa_coords::backbone_edit::{BackboneTorsion, set_backbone_torsion},
+    analysis::{
+        clashes::atom_label,
+        contact_map::{ContactMap, ContactMode},
+        gnm::{DISAGREE_Z_MIN, GNM_CUTOFF, gnm_b_factors},
+        ramachandran::{
+            ALLOWED_GENERAL, FAVORED_GENERAL, RamaRegion, calc_backbone_dihedrals, ramachandran,
+        },
+        trajectory::{TrajFrame, analyze_trajectory},
+        validation::{Grade, validate},
+    },
+    docking::search::rmsd,
+    dynamics::report::EnergyReport,
+    mol_drawing::color_viridis_float,
+    molecule::{Molecule, PropVal},
+    units::FS_PER_PS,
+    util::{handle_err, refresh_clashes},
+};
+
+/// Å. Distances at or above this share the color at the end of the color map.
+const CONTACT_MAP_COLOR_MAX: f32 = 20.;
+/// Pixels, on each side.
+const CONTACT_MAP_SIZE: f32 = 400.;
+const RAMA_SIZE: f32 = 360.;
+/// Pixels. Hovering or clicking within this of a point picks it.
+const RAMA_PICK_DIST: f32 = 6.;
+
+const GNM_PLOT_SIZE: (f32, f32) = (480., 200.);
+
+/// Residue property holding predicted B-factors, for coloring.
+pub const PROP_GNM_B: &str = "gnm_b";
+
+const RAMA_BG: Color32 = Color32::from_gray(24);
+const RAMA_ALLOWED: Color32 = Color32::from_rgb(40, 50, 70);
+const RAMA_FAVORED: Color32 = Color32::from_rgb(55, 80, 120);
+
+/// One pixel per residue pair. Close pairs are dark.
+fn contact_map_image(map: &ContactMap) -> ColorImage {
+    let n = map.len();
+    let mut rgb = Vec::with_capacity(n * n * 3);
+
+    for d in &map.dists {
+        let c = color_viridis_float(d.min(CONTACT_MAP_COLOR_MAX), 0., CONTACT_MAP_COLOR_MAX);
+        rgb.extend([(c.0 * 255.) as u8, (c.1 * 255.) as u8, (c.2 * 255.) as u8]);
+    }
+
+    ColorImage::from_rgb([n, n], &rgb)
+}
+
+/// Residue-residue distances. Clicking a cell selects both residues. Returns `true` if the
+/// selection changed.
+pub fn contact_map_window(state: &mut State, ctx: &Context) -> bool {
+    if !state.ui.show_contact_map {
+        return false;
+    }
+    let Some(mol) = &state.molecule else {
+        return false;
+    };
+
+    let mut open = true;
+    let mut sel_changed = false;
+
+    Window::new("Contact map")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ComboBox::from_id_salt(18)
+                    .width(80.)
+                    .selected_text(state.volatile.contact_map_mode.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in [ContactMode::CAlpha, ContactMode::MinAtom] {
+                            ui.selectable_value(
+                                &mut state.volatile.contact_map_mode,
+                                mode,
+                                mode.to_string(),
+                            );
+                        }
+                    });
+
+                if ui.button("Compute").clicked() {
+                    state.volatile.contact_map =
+                        Some(ContactMap::new(mol, state.volatile.contact_map_mode));
+                    state.volatile.contact_map_tex = None;
+                }
+            });
+
+            let Some(map) = &state.volatile.contact_map else {
+                return;
+            };
+            let n = map.len();
+            if n == 0 {
+                ui.label("No residues found.");
+                return;
+            }
+
+            let tex = state.volatile.contact_map_tex.get_or_insert_with(|| {
+                ctx.load_texture(
+                    "contact_map",
+                    contact_map_image(map),
+                    TextureOptions::NEAREST,
+                )
+            });
+
+            let resp = ui.add(
+                Image::from_texture(&*tex)
+                    .fit_to_exact_size(vec2(CONTACT_MAP_SIZE, CONTACT_MAP_SIZE))
+                    .sense(Sense::click()),
+            );
+
+            let Some(pos) = resp.hover_pos() else {
+                ui.label(format!(
+                    "{} residues. Contact: ≤ {:.1} Å",
+                    n,
+                    map.contact_thresh()
+                ));
+                return;
+            };
+
+            let rel = (pos - resp.rect.min) / resp.rect.size();
+            let i = ((rel.y * n as f32) as usize).min(n - 1);
+            let j = ((rel.x * n as f32) as usize).min(n - 1);
+
+            let (res_i, res_j) = (map.residues[i], map.residues[j]);
+            let contact = if map.in_contact(i, j) {
+                "  Contact"
+            } else {
+                ""
+            };
+            ui.label(
+                RichText::new(format!(
+                    "{}  |  {}:  {:.1} Å{contact}",
+                    mol.residues[res_i].descrip(state.to_save.angle_unit),
+                    mol.residues[res_j].descrip(state.to_save.angle_unit),
+                    map.dist(i, j)
+                ))
+                .color(Color32::GOLD),
+            );
+
+            if resp.clicked() {
+                state.ui.selection = if res_i == res_j {
+                    Selection::Residue(res_i)
+                } else {
+                    let mut atoms = mol.residues[res_i].atoms.clone();
+                    atoms.extend(&mol.residues[res_j].atoms);
+                    Selection::Atoms(atoms)
+                };
+                sel_changed = true;
+            }
+        });
+
+    if !open {
+        state.ui.show_contact_map = false;
+    }
+
+    sel_changed
+}
+
+fn rama_color(region: RamaRegion) -> Color32 {
+    match region {
+        RamaRegion::Favored => Color32::LIGHT_BLUE,
+        RamaRegion::Allowed => Color32::YELLOW,
+        RamaRegion::Outlier => Color32::LIGHT_RED,
+    }
+}
+
+/// φ/ψ scatter plot, over the general-case favored and allowed regions. Clicking a point, or an
+/// outlier in the list, selects its residue. The selected residue's φ and ψ can be edited by
+/// dragging on the plot, or with sliders. Returns `true` if the selection or atom positions
+/// changed.
+pub fn ramachandran_window(state: &mut State, ctx: &Context) -> bool {
+    if !state.ui.show_ramachandran {
+        return false;
+    }
+    let Some(mol) = &mut state.molecule else {
+        return false;
+    };
+
+    let mut open = true;
+    let mut changed = false;
+    // (Residue index, torsion, radians). Applied after drawing, since the plot borrows the points.
+    let mut edits = Vec::new();
+
+    Window::new("Ramachandran")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            if ui
+                .button("Compute")
+                .on_hover_text("Compute backbone dihedrals from the current atom positions.")
+                .clicked()
+            {
+                calc_backbone_dihedrals(mol);
+                state.volatile.ramachandran = Some(ramachandran(mol));
+            }
+
+            let Some(points) = &state.volatile.ramachandran else {
+                return;
+            };
+            if points.is_empty() {
+                ui.label("No residues with both φ and ψ found.");
+                return;
+            }
+
+            let (resp, painter) =
+                ui.allocate_painter(vec2(RAMA_SIZE, RAMA_SIZE), Sense::click_and_drag());
+            let rect = resp.rect;
+
+            // Degrees to screen; ψ increases upwards.
+            let to_screen = |φ: f64, ψ: f64| {
+                pos2(
+                    rect.left() + ((φ + 180.) / 360.) as f32 * rect.width(),
+                    rect.bottom() - ((ψ + 180.) / 360.) as f32 * rect.height(),
+                )
+            };
+            let rama_rect = |(φ_0, φ_1, ψ_0, ψ_1): (f64, f64, f64, f64)| -> Rect {
+                Rect::from_two_pos(to_screen(φ_0, ψ_0), to_screen(φ_1, ψ_1))
+            };
+
+            painter.rect_filled(rect, 0., RAMA_BG);
+            for r in ALLOWED_GENERAL {
+                painter.rect_filled(rama_rect(r), 0., RAMA_ALLOWED);
+            }
+            for r in FAVORED_GENERAL {
+                painter.rect_filled(rama_rect(r), 0., RAMA_FAVORED);
+            }
+
+            let axis = Stroke::new(1., Color32::GRAY);
+            painter.line_segment([to_screen(0., -180.), to_screen(0., 180.)], axis);
+            painter.line_segment([to_screen(-180., 0.), to_screen(180., 0.)], axis);
+
+            let font = FontId::proportional(12.);
+            painter.text(
+                rect.right_bottom() - vec2(4., 4.),
+                Align2::RIGHT_BOTTOM,
+                "φ",
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.left_top() + vec2(4., 4.),
+                Align2::LEFT_TOP,
+                "ψ",
+                font,
+                Color32::GRAY,
+            );
+
+            let mut positions: Vec<Pos2> = Vec::with_capacity(points.len());
+            for p in points {
+                let pos = to_screen(p.φ.to_degrees(), p.ψ.to_degrees());
+                painter.circle_filled(pos, 2., rama_color(p.region));
+                positions.push(pos);
+            }
+
+            let hovered = resp.hover_pos().and_then(|cursor| {
+                positions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, pos)| (i, pos.distance(cursor)))
+                    .filter(|(_, d)| *d <= RAMA_PICK_DIST)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(i, _)| i)
+            });
+
+            let unit = state.to_save.angle_unit;
+
+            match hovered {
+                Some(i) => {
+                    let p = &points[i];
+                    painter.circle_stroke(positions[i], 4., Stroke::new(1., Color32::WHITE));
+
+                    ui.label(
+                        // The description includes φ and ψ.
+                        RichText::new(format!(
+                            "{}  {}",
+                            mol.residues[p.res].descrip(unit),
+                            p.region
+                        ))
+                        .color(Color32::GOLD),
+                    );
+
+                    if resp.clicked() {
+                        state.ui.selection = Selection::Residue(p.res);
+                        changed = true;
+                    }
+                }
+                None => {
+                    let count = |region| points.iter().filter(|p| p.region == region).count();
+                    let pct = |n: usize| n as f32 / points.len() as f32 * 100.;
+                    let (favored, allowed, outliers) = (
+                        count(RamaRegion::Favored),
+                        count(RamaRegion::Allowed),
+                        count(RamaRegion::Outlier),
+                    );
+
+                    ui.label(format!(
+                        "{} residues. Favored: {:.1}%  Allowed: {:.1}%  Outliers: {outliers}",
+                        points.len(),
+                        pct(favored),
+                        pct(allowed),
+                    ));
+                }
+            }
+
+            let sel_point = match &state.ui.selection {
+                Selection::Residue(r) => points.iter().find(|p| p.res == *r),
+                _ => None,
+            };
+
+            if let Some(p) = sel_point {
+                if resp.dragged() {
+                    if let Some(pos) = resp.interact_pointer_pos() {
+                        let rel = (pos - rect.left_top()) / rect.size();
+                        let φ = (rel.x.clamp(0., 1.) as f64 * 360. - 180.).to_radians();
+                        let ψ = ((1. - rel.y.clamp(0., 1.)) as f64 * 360. - 180.).to_radians();
+
+                        edits.push((p.res, BackboneTorsion::Phi, φ));
+                        edits.push((p.res, BackboneTorsion::Psi, ψ));
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("Edit res {}:", mol.residues[p.res].serial_number))
+                        .on_hover_text(
+                            "Drag on the plot, or use the sliders, to set the selected residue's \
+                            φ and ψ. Atoms downstream in the chain move accordingly.",
+                        );
+
+                    let range = unit.convert(-PI)..=unit.convert(PI);
+                    for (name, torsion, val) in [
+                        ("φ", BackboneTorsion::Phi, p.φ),
+                        ("ψ", BackboneTorsion::Psi, p.ψ),
+                    ] {
+                        ui.label(name);
+                        let mut v = unit.convert(val);
+                        if ui.add(Slider::new(&mut v, range.clone())).changed() {
+                            edits.push((p.res, torsion, unit.to_radians(v)));
+                        }
+                    }
+
+                    ui.checkbox(&mut state.ui.rama_pin_c_term, "Pin C-term")
+                        .on_hover_text(
+                            "Keep the rest of the chain in place, closing the gap by adjusting \
+                            the torsions of the next few residues.",
+                        );
+
+                    if let Some(gap) = state.volatile.backbone_edit_gap {
+                        ui.label(format!("Gap: {gap:.2} Å"));
+                    }
+                });
+            }
+
+            let outliers: Vec<_> = points
+                .iter()
+                .filter(|p| p.region == RamaRegion::Outlier)
+                .collect();
+            if outliers.is_empty() {
+                return;
+            }
+
+            ui.label(RichText::new("Outliers:").color(rama_color(RamaRegion::Outlier)));
+            ScrollArea::vertical().max_height(120.).show(ui, |ui| {
+                for p in outliers {
+                    let text = mol.residues[p.res].descrip(unit);
+                    if ui.selectable_label(false, text).clicked() {
+                        state.ui.selection = Selection::Residue(p.res);
+                        changed = true;
+                    }
+                }
+            });
+        });
+
+    if !edits.is_empty() {
+        for (res, torsion, angle) in edits {
+            let gap = set_backbone_torsion(mol, res, torsion, angle, state.ui.rama_pin_c_term);
+            state.volatile.backbone_edit_gap = gap.filter(|_| state.ui.rama_pin_c_term);
+        }
+
+        calc_backbone_dihedrals(mol);
+        state.volatile.ramachandran = Some(ramachandran(mol));
+        refresh_clashes(state);
+        changed = true;
+    }
+
+    if !open {
+        state.ui.show_ramachandran = false;
+    }
+
+    changed
+}
+
+fn grade_color(grade: Grade) -> Color32 {
+    match grade {
+        Grade::Good => Color32::LIGHT_GREEN,
+        Grade::Fair => Color32::YELLOW,
+        Grade::Poor => Color32::LIGHT_RED,
+    }
+}
+
+/// E.g. "45 Leu".
+fn res_label(mol: &Molecule, res: usize) -> String {
+    let res = &mol.residues[res];
+    let name = match &res.res_type {
+        ResidueType::AminoAcid(aa) => aa.to_string(),
+        ResidueType::Water => "Water".to_owned(),
+        ResidueType::Other(n) => n.clone(),
+    };
+    format!("{} {name}", res.serial_number)
+}
+
+/// E.g. "45 Leu C - 46 Gly N".
+fn geom_label(mol: &Molecule, atoms: &[usize]) -> String {
+    atoms
+        .iter()
+        .map(|&i| atom_label(mol, i))
+        .collect::<Vec<_>>()
+        .join(" - ")
+}
+
+/// Structure quality metrics, graded, and lists of outliers. Clicking an outlier selects its atoms
+/// or residue. Returns `true` if the selection changed.
+pub fn validation_window(state: &mut State, ctx: &Context) -> bool {
+    if !state.ui.show_validation {
+        return false;
+    }
+    let Some(mol) = &mut state.molecule else {
+        return false;
+    };
+
+    let mut open = true;
+    let mut sel_changed = false;
+    let unit = state.to_save.angle_unit;
+
+    Window::new("Structure health")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            if ui
+                .button("Compute")
+                .on_hover_text(
+                    "Compute clashscore, Ramachandran and rotamer outliers, and backbone bond \
+                    geometry deviations, from the current atom positions. Lists residues with \
+                    high B-factors or low occupancy.",
+                )
+                .clicked()
+            {
+                state.volatile.validation = Some(validate(mol));
+            }
+
+            let Some(report) = &state.volatile.validation else {
+                return;
+            };
+
+            for (name, val, grade) in report.metrics() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{name}:"));
+                    ui.label(RichText::new(val).color(grade_color(grade)));
+                });
+            }
+
+            // (Heading, (text, selection) for each outlier)
+            let sections: [(&str, Vec<(String, Selection)>); 7] = [
+                (
+                    "Clashes",
+                    report
+                        .clashes
+                        .iter()
+                        .map(|c| {
+                            (
+                                format!(
+                                    "{}: {:.2} Å",
+                                    geom_label(mol, &[c.atom_0, c.atom_1]),
+                                    c.overlap
+                                ),
+                                Selection::Atoms(vec![c.atom_0, c.atom_1]),
+                            )
+                        })
+                        .collect(),
+                ),
+                (
+                    "Ramachandran outliers",
+                    report
+                        .rama_outliers
+                        .iter()
+                        .map(|&r| (mol.residues[r].descrip(unit), Selection::Residue(r)))
+                        .collect(),
+                ),
+                (
+                    "Rotamer outliers",
+                    report
+                        .rotamer_outliers
+                        .iter()
+                        .map(|o| {
+                            let χ: Vec<_> =
+                                o.χ.iter()
+                                    .enumerate()
+                                    .map(|(i, a)| format!("χ{}: {}", i + 1, unit.fmt(*a)))
+                                    .collect();
+                            (
+                                format!("{}  {}", res_label(mol, o.res), χ.join("  ")),
+                                Selection::Residue(o.res),
+                            )
+                        })
+                        .collect(),
+                ),
+                (
+                    "Bond length outliers",
+                    report
+                        .bond_outliers
+                        .iter()
+                        .map(|o| {
+                            (
+                                format!(
+                                    "{}: {:.3} Å ({:+.1}σ)",
+                                    geom_label(mol, &o.atoms),
+                                    o.value,
+                                    o.z
+                                ),
+                                Selection::Atoms(o.atoms.clone()),
+                            )
+                        })
+                        .collect(),
+                ),
+                (
+                    "Bond angle outliers",
+                    report
+                        .angle_outliers
+                        .iter()
+                        .map(|o| {
+                            (
+                                format!(
+                                    "{}: {} ({:+.1}σ)",
+                                    geom_label(mol, &o.atoms),
+                                    unit.fmt(o.value),
+                                    o.z
+                                ),
+                                Selection::Atoms(o.atoms.clone()),
+                            )
+                        })
+                        .collect(),
+                ),
+                (
+                    "High B-factor",
+                    report
+                        .high_b_residues
+                        .iter()
+                        .map(|&(r, b)| {
+                            (
+                                format!("{}  B: {b:.1} Å²", res_label(mol, r)),
+                                Selection::Residue(r),
+                            )
+                        })
+                        .collect(),
+                ),
+                (
+                    "Low occupancy",
+                    report
+                        .low_occupancy_residues
+                        .iter()
+                        .map(|&(r, occ)| {
+                            (
+                                format!("{}  occ: {occ:.2}", res_label(mol, r)),
+                                Selection::Residue(r),
+                            )
+                        })
+                        .collect(),
+                ),
+            ];
+
+            for (heading, items) in sections {
+                if items.is_empty() {
+                    continue;
+                }
+
+                ui.collapsing(format!("{heading} ({})", items.len()), |ui| {
+                    ScrollArea::vertical()
+                        .id_salt(heading)
+                        .max_height(120.)
+                        .show(ui, |ui| {
+                            for (text, sel) in items {
+                                let selected = state.ui.selection == sel;
+                                if ui.selectable_label(selected, text).clicked() {
+                                    state.ui.selection = sel;
+                                    sel_changed = true;
+                                }
+                            }
+                        });
+                });
+            }
+        });
+
+    if !open {
+        state.ui.show_validation = false;
+    }
+
+    sel_changed
+}
+
+/// Predicted B-factors from a Gaussian network model, plotted against experimental ones by residue.
+/// Regions of disagreement are shaded. Clicking the plot selects the residue under the cursor.
+/// Returns `true` if the selection or residue properties changed.
+pub fn gnm_window(state: &mut State, ctx: &Context) -> bool {
+    if !state.ui.show_gnm {
+        return false;
+    }
+    let Some(mol) = &mut state.molecule else {
+        return false;
+    };
+
+    let mut open = true;
+    let mut changed = false;
+
+    Window::new("B-factors (GNM)")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            if ui
+                .button("Compute")
+                .on_hover_text(format!(
+                    "Predict per-residue fluctuations from a Gaussian network model: springs \
+                    between Cα atoms within {GNM_CUTOFF} Å. Predictions are fit to the \
+                    experimental B-factors. Adds the \"{PROP_GNM_B}\" residue property."
+                ))
+                .clicked()
+            {
+                state.volatile.gnm = gnm_b_factors(mol);
+
+                if let Some(gnm) = &state.volatile.gnm {
+                    for (&res, b) in gnm.residues.iter().zip(&gnm.b_pred) {
+                        mol.residues[res]
+                            .props
+                            .insert(PROP_GNM_B.to_owned(), PropVal::Float(*b as f32));
+                    }
+                }
+                changed = true;
+            }
+
+            let Some(gnm) = &state.volatile.gnm else {
+                return;
+            };
+
+            match gnm.correlation {
+                Some(r) => ui.label(format!(
+                    "{} residues. Correlation with experiment: {r:.2}. Disagreeing by ≥{DISAGREE_Z_MIN}σ: {}",
+                    gnm.residues.len(),
+                    gnm.flagged.len(),
+                )),
+                None => ui.label(format!(
+                    "{} residues. No experimental B-factors to compare to; predictions are in \
+                    arbitrary units.",
+                    gnm.residues.len()
+                )),
+            };
+
+            let (resp, painter) =
+                ui.allocate_painter(vec2(GNM_PLOT_SIZE.0, GNM_PLOT_SIZE.1), Sense::click());
+            let rect = resp.rect;
+
+            let n = gnm.residues.len();
+            let b_max = gnm
+                .b_pred
+                .iter()
+                .copied()
+                .chain(gnm.b_exp.iter().flatten().copied())
+                .fold(0., f64::max)
+                .max(1.);
+
+            let x = |i: usize| rect.left() + i as f32 / (n - 1).max(1) as f32 * rect.width();
+            let to_screen =
+                |i: usize, b: f64| pos2(x(i), rect.bottom() - (b / b_max) as f32 * rect.height());
+
+            painter.rect_filled(rect, 0., RAMA_BG);
+
+            let band_width = (rect.width() / n as f32).max(1.);
+            for &i in &gnm.flagged {
+                let band = Rect::from_center_size(
+                    pos2(x(i), rect.center().y),
+                    vec2(band_width, rect.height()),
+                );
+                painter.rect_filled(band, 0., Color32::from_rgb(90, 35, 35));
+            }
+
+            // Break the experimental line where values are missing.
+            let mut segments = vec![Vec::new()];
+            for (i, b) in gnm.b_exp.iter().enumerate() {
+                match b {
+                    Some(b) => segments.last_mut().unwrap().push(to_screen(i, *b)),
+                    None => segments.push(Vec::new()),
+                }
+            }
+            for segment in segments.into_iter().filter(|s| s.len() >= 2) {
+                painter.line(segment, Stroke::new(1., Color32::GRAY));
+            }
+
+            let pred = gnm
+                .b_pred
+                .iter()
+                .enumerate()
+                .map(|(i, b)| to_screen(i, *b))
+                .collect();
+            painter.line(pred, Stroke::new(1., Color32::LIGHT_BLUE));
+
+            let font = FontId::proportional(12.);
+            painter.text(
+                rect.left_top() + vec2(4., 4.),
+                Align2::LEFT_TOP,
+                format!("{b_max:.0} Å²"),
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.right_top() + vec2(-4., 4.),
+                Align2::RIGHT_TOP,
+                "Experimental",
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.right_top() + vec2(-4., 18.),
+                Align2::RIGHT_TOP,
+                "Predicted",
+                font,
+                Color32::LIGHT_BLUE,
+            );
+
+            if let Some(cursor) = resp.hover_pos() {
+                let rel = ((cursor.x - rect.left()) / rect.width()).clamp(0., 1.);
+                let i = (rel * (n - 1) as f32).round() as usize;
+                let res = gnm.residues[i];
+
+                painter.line_segment(
+                    [pos2(x(i), rect.top()), pos2(x(i), rect.bottom())],
+                    Stroke::new(1., Color32::WHITE),
+                );
+
+                let exp = match gnm.b_exp[i] {
+                    Some(b) => format!("{b:.1}"),
+                    None => "-".to_owned(),
+                };
+                ui.label(
+                    RichText::new(format!(
+                        "{}  Predicted: {:.1} Å²  Experimental: {exp}",
+                        res_label(mol, res),
+                        gnm.b_pred[i],
+                    ))
+                    .color(Color32::GOLD),
+                );
+
+                if resp.clicked() {
+                    state.ui.selection = Selection::Residue(res);
+                    changed = true;
+                }
+            }
+        });
+
+    if !open {
+        state.ui.show_gnm = false;
+    }
+
+    changed
+}
+
+/// A plotted series: name, color, and value from a report.
+type MdSeries = (&'static str, Color32, fn(&EnergyReport) -> f64);
+
+/// Observables to plot from MD reports.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum MdPlotKind {
+    #[default]
+    Energy,
+    Temperature,
+    Pressure,
+}
+
+impl MdPlotKind {
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Energy => "Energy",
+            Self::Temperature => "Temperature",
+            Self::Pressure => "Pressure",
+        }
+    }
+
+    /// Name, color, and value of each series.
+    fn series(self) -> &'static [MdSeries] {
+        const ENERGY: [MdSeries; 5] = [
+            ("Kinetic", Color32::LIGHT_RED, |r| r.kinetic),
+            ("Bonded", Color32::LIGHT_GREEN, |r| r.bonded),
+            ("LJ", Color32::YELLOW, |r| r.lj),
+            ("Coulomb", Color32::LIGHT_BLUE, |r| r.coulomb),
+            ("Potential", Color32::WHITE, |r| r.potential),
+        ];
+        const TEMPERATURE: [MdSeries; 1] = [("T (K)", Color32::LIGHT_RED, |r| r.temperature)];
+        const PRESSURE: [MdSeries; 1] = [("P (bar)", Color32::LIGHT_BLUE, |r| r.pressure)];
+
+        match self {
+            Self::Energy => &ENERGY,
+            Self::Temperature => &TEMPERATURE,
+            Self::Pressure => &PRESSURE,
+        }
+    }
+}
+
+/// Energy terms, temperature, or pressure over an MD run. The vertical line marks the snapshot
+/// being viewed.
+pub fn md_energy_window(state: &mut State, ctx: &Context) {
+    if !state.ui.show_md_energy {
+        return;
+    }
+
+    let mut open = true;
+
+    Window::new("MD energies")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let Some(md) = &state.mol_dynamics else {
+                ui.label("Run MD to see energies.");
+                return;
+            };
+
+            ui.horizontal(|ui| {
+                ComboBox::from_id_salt(28)
+                    .width(100.)
+                    .selected_text(state.ui.md_plot_kind.to_str())
+                    .show_ui(ui, |ui| {
+                        for kind in [
+                            MdPlotKind::Energy,
+                            MdPlotKind::Temperature,
+                            MdPlotKind::Pressure,
+                        ] {
+                            ui.selectable_value(&mut state.ui.md_plot_kind, kind, kind.to_str());
+                        }
+                    });
+
+                if !md.reports.is_empty() && ui.button("Save CSV").clicked() {
+                    state
+                        .volatile
+                        .dialogs
+                        .save_csv
+                        .config_mut()
+                        .default_file_name = "md_energies.csv".to_owned();
+                    state.volatile.dialogs.save_csv.save_file();
+                }
+            });
+
+            let reports = &md.reports;
+            if reports.len() < 2 {
+                ui.label("No reports recorded. Set a report interval before running MD.");
+                return;
+            }
+
+            let series = state.ui.md_plot_kind.series();
+
+            let (mut v_min, mut v_max) = (f64::INFINITY, f64::NEG_INFINITY);
+            for (_, _, value) in series {
+                for r in reports {
+                    v_min = v_min.min(value(r));
+                    v_max = v_max.max(value(r));
+                }
+            }
+            if v_max - v_min < 1e-6 {
+                (v_min, v_max) = (v_min - 1., v_max + 1.);
+            }
+
+            let (t_0, t_1) = (reports[0].time, reports[reports.len() - 1].time);
+
+            let (resp, painter) =
+                ui.allocate_painter(vec2(GNM_PLOT_SIZE.0, GNM_PLOT_SIZE.1), Sense::hover());
+            let rect = resp.rect;
+
+            let x = |t: f64| rect.left() + ((t - t_0) / (t_1 - t_0)) as f32 * rect.width();
+            let y = |v: f64| rect.bottom() - ((v - v_min) / (v_max - v_min)) as f32 * rect.height();
+
+            painter.rect_filled(rect, 0., RAMA_BG);
+
+            let font = FontId::proportional(12.);
+            for (i, (name, color, value)) in series.iter().enumerate() {
+                let points = reports
+                    .iter()
+                    .map(|r| pos2(x(r.time), y(value(r))))
+                    .collect();
+                painter.line(points, Stroke::new(1., *color));
+
+                painter.text(
+                    rect.right_top() + vec2(-4., 4. + 14. * i as f32),
+                    Align2::RIGHT_TOP,
+                    *name,
+                    font.clone(),
+                    *color,
+                );
+            }
+
+            painter.text(
+                rect.left_top() + vec2(4., 4.),
+                Align2::LEFT_TOP,
+                format!("{v_max:.1}"),
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.left_bottom() + vec2(4., -4.),
+                Align2::LEFT_BOTTOM,
+                format!("{v_min:.1}"),
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.center_bottom() + vec2(0., -4.),
+                Align2::CENTER_BOTTOM,
+                format!("{:.1} ps", (t_1 - t_0) / FS_PER_PS),
+                font,
+                Color32::GRAY,
+            );
+
+            if let Some(snap) = md.snapshots.get(state.ui.current_snapshot) {
+                if (t_0..=t_1).contains(&snap.time) {
+                    painter.line_segment(
+                        [
+                            pos2(x(snap.time), rect.top()),
+                            pos2(x(snap.time), rect.bottom()),
+                        ],
+                        Stroke::new(1., Color32::GOLD),
+                    );
+                }
+            }
+
+            if let Some(cursor) = resp.hover_pos() {
+                let rel = ((cursor.x - rect.left()) / rect.width()).clamp(0., 1.) as f64;
+                let t = t_0 + rel * (t_1 - t_0);
+                let i = reports
+                    .partition_point(|r: &EnergyReport| r.time < t)
+                    .min(reports.len() - 1);
+                let r = &reports[i];
+
+                let values: Vec<_> = series
+                    .iter()
+                    .map(|(name, _, value)| format!("{name}: {:.2}", value(r)))
+                    .collect();
+                ui.label(
+                    RichText::new(format!(
+                        "{:.3} ps  {}",
+                        r.time / FS_PER_PS,
+                        values.join("  ")
+                    ))
+                    .color(Color32::GOLD),
+                );
+            }
+        });
+
+    if !open {
+        state.ui.show_md_energy = false;
+    }
+}
+
+/// Receptor residues ranked by their interaction energy with the ligand. Clicking a row selects
+/// the residue. Returns `true` if the selection changed.
+pub fn residue_energy_window(state: &mut State, ctx: &Context) -> bool {
+    if !state.ui.show_residue_energy {
+        return false;
+    }
+    let (Some(mol), Some(decomp)) = (&state.molecule, &state.volatile.residue_energy) else {
+        return false;
+    };
+
+    let mut open = true;
+    let mut sel_changed = false;
+    let unit = state.to_save.energy_unit;
+
+    Window::new("Energy by residue")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "{} residues. Total: {}",
+                decomp.residues.len(),
+                unit.fmt(decomp.total())
+            ));
+
+            ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                Grid::new("residue_energy_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for heading in ["#", "Residue", "LJ", "Coulomb", "Total"] {
+                            ui.label(RichText::new(heading).strong());
+                        }
+                        ui.end_row();
+
+                        for (rank, r) in decomp.residues.iter().enumerate() {
+                            let sel = Selection::Residue(r.residue);
+
+                            ui.label((rank + 1).to_string());
+                            let selected = state.ui.selection == sel;
+                            if ui
+                                .selectable_label(selected, res_label(mol, r.residue))
+                                .clicked()
+                            {
+                                state.ui.selection = sel;
+                                sel_changed = true;
+                            }
+                            ui.label(format!("{:.2}", unit.convert(r.lj)));
+                            ui.label(format!("{:.2}", unit.convert(r.coulomb)));
+                            ui.label(format!("{:.2}", unit.convert(r.total())));
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+
+    if !open {
+        state.ui.show_residue_energy = false;
+    }
+
+    sel_changed
+}
+
+/// Pose clusters from the last docking search, best first. Clicking a row, or stepping with the
+/// arrows, moves the ligand to that pose. Returns the pose index to move to, if changed.
+pub fn dock_results_window(state: &mut State, ctx: &Context) -> Option<usize> {
+    if !state.ui.show_dock_results || state.ui.docked_poses.is_empty() {
+        return None;
+    }
+
+    let mut open = true;
+    let mut result = None;
+    let unit = state.to_save.energy_unit;
+    let poses = &state.ui.docked_poses;
+    let n = poses.len();
+    let current = state.ui.docked_pose_i.min(n - 1);
+    let constrained = !state.ui.pharm_constraints.features.is_empty();
+
+    Window::new("Docking results")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("◀").clicked() {
+                    result = Some((current + n - 1) % n);
+                }
+                ui.label(format!("Pose {}/{n}", current + 1));
+                if ui.button("▶").clicked() {
+                    result = Some((current + 1) % n);
+                }
+            });
+
+            ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                Grid::new("dock_results_grid").striped(true).show(ui, |ui| {
+                    for heading in ["#", "Score", "Population", "RMSD (Å)"] {
+                        ui.label(RichText::new(heading).strong());
+                    }
+                    if constrained {
+                        ui.label(RichText::new("Unmet").strong());
+                    }
+                    ui.end_row();
+
+                    for (i, pose) in poses.iter().enumerate() {
+                        if ui
+                            .selectable_label(i == current, (i + 1).to_string())
+                            .on_hover_text(pose.score.descrip(unit))
+                            .clicked()
+                        {
+                            result = Some(i);
+                        }
+                        ui.label(unit.fmt(pose.score.score as f64));
+                        ui.label(pose.population.to_string());
+                        // From the best pose.
+                        ui.label(format!(
+                            "{:.2}",
+                            rmsd(&poses[0].atom_posits, &pose.atom_posits)
+                        ));
+                        if constrained {
+                            ui.label(pose.unsatisfied.to_string());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+
+    if !open {
+        state.ui.show_dock_results = false;
+    }
+
+    result
+}
+
+/// Energy against dihedral angle, from a torsion scan. Clicking a point returns its index, so the
+/// ligand can be set to that step's geometry.
+pub fn torsion_scan_window(state: &mut State, ctx: &Context) -> Option<usize> {
+    if !state.ui.show_torsion_scan {
+        return None;
+    }
+    let Some(scan) = &state.volatile.torsion_scan else {
+        return None;
+    };
+
+    let mut open = true;
+    let mut result = None;
+    let unit = state.to_save.energy_unit;
+
+    Window::new("Torsion scan")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!("Barrier: {}", unit.fmt(scan.barrier())));
+
+            let e_max = scan.barrier().max(1.);
+
+            let (resp, painter) =
+                ui.allocate_painter(vec2(GNM_PLOT_SIZE.0, GNM_PLOT_SIZE.1), Sense::click());
+            let rect = resp.rect;
+
+            let to_screen = |angle: f64, e: f64| {
+                pos2(
+                    rect.left() + ((angle.to_degrees() + 180.) / 360.) as f32 * rect.width(),
+                    rect.bottom() - (e / e_max) as f32 * rect.height(),
+                )
+            };
+
+            painter.rect_filled(rect, 0., RAMA_BG);
+
+            let positions: Vec<_> = scan
+                .points
+                .iter()
+                .map(|p| to_screen(p.angle, p.energy))
+                .collect();
+            painter.line(positions.clone(), Stroke::new(1., Color32::LIGHT_BLUE));
+            for pos in &positions {
+                painter.circle_filled(*pos, 2., Color32::LIGHT_BLUE);
+            }
+
+            let font = FontId::proportional(12.);
+            painter.text(
+                rect.left_top() + vec2(4., 4.),
+                Align2::LEFT_TOP,
+                format!("{e_max:.1} kcal/mol"),
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.left_bottom() + vec2(4., -4.),
+                Align2::LEFT_BOTTOM,
+                "-180°",
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.right_bottom() + vec2(-4., -4.),
+                Align2::RIGHT_BOTTOM,
+                "180°",
+                font,
+                Color32::GRAY,
+            );
+
+            let hovered = resp.hover_pos().and_then(|cursor| {
+                positions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, pos)| (i, pos.distance(cursor)))
+                    .filter(|(_, d)| *d <= RAMA_PICK_DIST)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(i, _)| i)
+            });
+
+            if let Some(i) = hovered {
+                let p = &scan.points[i];
+                painter.circle_stroke(positions[i], 4., Stroke::new(1., Color32::WHITE));
+                ui.label(
+                    RichText::new(format!(
+                        "{:.0}°  {}",
+                        p.angle.to_degrees(),
+                        unit.fmt(p.energy)
+                    ))
+                    .color(Color32::GOLD),
+                );
+
+                if resp.clicked() {
+                    result = Some(i);
+                }
+            }
+        });
+
+    if !open {
+        state.ui.show_torsion_scan = false;
+    }
+
+    result
+}
+
+/// Trajectory metrics to plot.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum TrajPlotKind {
+    #[default]
+    Rmsd,
+    Rg,
+    LigRmsd,
+    PocketDist,
+    Rmsf,
+}
+
+impl TrajPlotKind {
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Rmsd => "RMSD",
+            Self::Rg => "Radius of gyration",
+            Self::LigRmsd => "Ligand RMSD",
+            Self::PocketDist => "Ligand-pocket distance",
+            Self::Rmsf => "RMSF by residue",
+        }
+    }
+
+    fn value(self, f: &TrajFrame) -> Option<f64> {
+        match self {
+            Self::Rmsd => Some(f.rmsd),
+            Self::Rg => Some(f.rg),
+            Self::LigRmsd => f.lig_rmsd,
+            Self::PocketDist => f.pocket_dist,
+            Self::Rmsf => None,
+        }
+    }
+}
+
+/// Time series of structural metrics over an MD run, and per-residue RMSF. Clicking the RMSF plot
+/// selects the residue. Returns `true` if the selection changed.
+pub fn trajectory_window(state: &mut State, ctx: &Context) -> bool {
+    if !state.ui.show_trajectory {
+        return false;
+    }
+
+    let mut open = true;
+    let mut changed = false;
+
+    Window::new("Trajectory")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let (Some(mol), Some(md)) = (&state.molecule, &state.mol_dynamics) else {
+                ui.label("Run MD to analyze its trajectory.");
+                return;
+            };
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Compute")
+                    .on_hover_text(
+                        "Compute RMSD to the first frame, radius of gyration, ligand RMSD and \
+                        distance from the pocket, and per-residue RMSF, over all snapshots.",
+                    )
+                    .clicked()
+                {
+                    let lig = state.ligand.as_ref().map(|l| &l.molecule);
+                    state.volatile.traj_analysis = analyze_trajectory(mol, lig, md);
+                }
+
+                ComboBox::from_id_salt(42)
+                    .width(160.)
+                    .selected_text(state.ui.traj_plot_kind.to_str())
+                    .show_ui(ui, |ui| {
+                        for kind in [
+                            TrajPlotKind::Rmsd,
+                            TrajPlotKind::Rg,
+                            TrajPlotKind::LigRmsd,
+                            TrajPlotKind::PocketDist,
+                            TrajPlotKind::Rmsf,
+                        ] {
+                            ui.selectable_value(&mut state.ui.traj_plot_kind, kind, kind.to_str());
+                        }
+                    });
+
+                if let Some(traj) = &state.volatile.traj_analysis {
+                    if ui
+                        .button("Save CSV")
+                        .on_hover_text("Save metrics to trajectory.csv, and RMSF to rmsf.csv")
+                        .clicked()
+                    {
+                        if let Err(e) = traj
+                            .save_csv(Path::new("trajectory.csv"))
+                            .and_then(|_| traj.save_rmsf_csv(mol, Path::new("rmsf.csv")))
+                        {
+                            handle_err(
+                                &mut state.ui,
+                                format!("Problem saving trajectory analysis: {e}"),
+                            );
+                        }
+                    }
+                }
+            });
+
+            let Some(traj) = &state.volatile.traj_analysis else {
+                return;
+            };
+            if traj.frames.len() < 2 {
+                ui.label("The trajectory needs at least two snapshots.");
+                return;
+            }
+            if !traj.fitted {
+                ui.label("Too few moving Cα atoms to superpose; frames aren't fitted.");
+            }
+
+            let kind = state.ui.traj_plot_kind;
+
+            // (x, y) for each point, and x-axis labels.
+            let (points, x_labels): (Vec<(f64, f64)>, _) = if kind == TrajPlotKind::Rmsf {
+                if traj.rmsf.is_empty() {
+                    ui.label("No receptor atoms moved in this run.");
+                    return;
+                }
+                (
+                    traj.rmsf
+                        .iter()
+                        .enumerate()
+                        .map(|(i, r)| (i as f64, r.rmsf))
+                        .collect(),
+                    ("First residue".to_owned(), "Last residue".to_owned()),
+                )
+            } else {
+                let points: Vec<_> = traj
+                    .frames
+                    .iter()
+                    .filter_map(|f| kind.value(f).map(|v| (f.time, v)))
+                    .collect();
+                if points.len() < 2 {
+                    ui.label("This metric needs a ligand simulated with the receptor.");
+                    return;
+                }
+                let (t_0, t_1) = (points[0].0, points[points.len() - 1].0);
+                (
+                    points,
+                    (
+                        format!("{:.1} ps", t_0 / FS_PER_PS),
+                        format!("{:.1} ps", t_1 / FS_PER_PS),
+                    ),
+                )
+            };
+
+            let (x_0, x_1) = (
+                points[0].0,
+                points[points.len() - 1].0.max(points[0].0 + 1e-6),
+            );
+            let y_max = points.iter().map(|p| p.1).fold(0., f64::max).max(0.1);
+
+            let (resp, painter) =
+                ui.allocate_painter(vec2(GNM_PLOT_SIZE.0, GNM_PLOT_SIZE.1), Sense::click());
+            let rect = resp.rect;
+
+            let x = |v: f64| rect.left() + ((v - x_0) / (x_1 - x_0)) as f32 * rect.width();
+            let y = |v: f64| rect.bottom() - (v / y_max) as f32 * rect.height();
+
+            painter.rect_filled(rect, 0., RAMA_BG);
+            painter.line(
+                points.iter().map(|(px, py)| pos2(x(*px), y(*py))).collect(),
+                Stroke::new(1., Color32::LIGHT_BLUE),
+            );
+
+            let font = FontId::proportional(12.);
+            painter.text(
+                rect.left_top() + vec2(4., 4.),
+                Align2::LEFT_TOP,
+                format!("{y_max:.2} Å"),
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.left_bottom() + vec2(4., -4.),
+                Align2::LEFT_BOTTOM,
+                x_labels.0,
+                font.clone(),
+                Color32::GRAY,
+            );
+            painter.text(
+                rect.right_bottom() + vec2(-4., -4.),
+                Align2::RIGHT_BOTTOM,
+                x_labels.1,
+                font,
+                Color32::GRAY,
+            );
+
+            if kind != TrajPlotKind::Rmsf {
+                if let Some(snap) = md.snapshots.get(state.ui.current_snapshot) {
+                    if (x_0..=x_1).contains(&snap.time) {
+                        painter.line_segment(
+                            [
+                                pos2(x(snap.time), rect.top()),
+                                pos2(x(snap.time), rect.bottom()),
+                            ],
+                            Stroke::new(1., Color32::GOLD),
+                        );
+                    }
+                }
+            }
+
+            let Some(cursor) = resp.hover_pos() else {
+                return;
+            };
+            let rel = ((cursor.x - rect.left()) / rect.width()).clamp(0., 1.) as f64;
+            let v = x_0 + rel * (x_1 - x_0);
+            let i = points.partition_point(|p| p.0 < v).min(points.len() - 1);
+            let (px, py) = points[i];
+
+            painter.line_segment(
+                [pos2(x(px), rect.top()), pos2(x(px), rect.bottom())],
+                Stroke::new(1., Color32::WHITE),
+            );
+
+            if kind == TrajPlotKind::Rmsf {
+                let res = traj.rmsf[i].residue;
+                ui.label(
+                    RichText::new(format!("{}  RMSF: {py:.2} Å", res_label(mol, res)))
+                        .color(Color32::GOLD),
+                );
+
+                if resp.clicked() {
+                    state.ui.selection = Selection::Residue(res);
+                    changed = true;
+                }
+            } else {
+                ui.label(
+                    RichText::new(format!("{:.3} ps  {py:.2} Å", px / FS_PER_PS))
+                        .color(Color32::GOLD),
+                );
+            }
+        });
+
+    if !open {
+        state.ui.show_trajectory = false;
+    }
+
+    changed
+}
@@ -0,0 +1,129 @@
+//! The unit system used in simulation, and conversions to and from it. This matches Amber's:
+//!
+//! - Length: Å
+//! - Time: fs
+//! - Mass: amu (Da)
+//! - Energy: kcal/mol
+//! - Charge: elementary charge (e)
+//! - Temperature: K
+//! - Angles: radians
+//!
+//! Force is then kcal/(mol·Å). Note that force / mass, in these units, is *not* Å/fs²; multiply
+//! by `ACCEL_CONV` before integrating.
+//!
+//! Values are stored and computed in these units throughout. The display units selected by the
+//! user are applied only when formatting values for presentation.
+
+use std::fmt;
+
+use bincode::{Decode, Encode};
+
+/// Coulomb constant, k_e = 1/(4πε₀). kcal·Å/(mol·e²)
+pub const COULOMB_CONST: f64 = 332.0636;
+
+/// Boltzmann constant. kcal/(mol·K)
+pub const K_B: f64 = 0.001_987_204_1;
+
+/// Converts force / mass, in kcal/(mol·Å·amu), to acceleration, in Å/fs².
+pub const ACCEL_CONV: f64 = 4.184e-4;
+
+/// kcal/mol per eV. Also converts qE, with q in e and E in V/Å, to kcal/(mol·Å).
+pub const EV_TO_KCAL_MOL: f64 = 23.060_55;
+
+pub const FS_PER_PS: f64 = 1_000.;
+
+/// kJ per kcal (thermochemical calorie).
+pub const KJ_PER_KCAL: f64 = 4.184;
+
+/// Converts pressure × volume, in bar·Å³, to kcal/mol.
+pub const BAR_A3_TO_KCAL_MOL: f64 = 1.439_326e-5;
+
+/// Kinetic energy, in kcal/mol, from mass in amu and speed² in Å²/fs².
+pub fn kinetic_energy(mass: f64, speed_sq: f64) -> f64 {
+    0.5 * mass * speed_sq / ACCEL_CONV
+}
+
+/// Instantaneous temperature, in K, from kinetic energy in kcal/mol, and degrees of freedom.
+pub fn temperature(kinetic_energy: f64, dof: usize) -> f64 {
+    if dof == 0 {
+        return 0.;
+    }
+    2. * kinetic_energy / (dof as f64 * K_B)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum AngleUnit {
+    #[default]
+    Degrees,
+    Radians,
+}
+
+impl fmt::Display for AngleUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::Degrees => "Degrees",
+            Self::Radians => "Radians",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+impl AngleUnit {
+    /// From radians.
+    pub fn convert(self, rad: f64) -> f64 {
+        match self {
+            Self::Degrees => rad.to_degrees(),
+            Self::Radians => rad,
+        }
+    }
+
+    /// To radians.
+    pub fn to_radians(self, val: f64) -> f64 {
+        match self {
+            Self::Degrees => val.to_radians(),
+            Self::Radians => val,
+        }
+    }
+
+    /// Formats an angle, given in radians, with its unit.
+    pub fn fmt(self, rad: f64) -> String {
+        match self {
+            Self::Degrees => format!("{:.1}°", self.convert(rad)),
+            Self::Radians => format!("{:.3} rad", self.convert(rad)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum EnergyUnit {
+    #[default]
+    KcalMol,
+    KjMol,
+}
+
+impl fmt::Display for EnergyUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            Self::KcalMol => "kcal/mol",
+            Self::KjMol => "kJ/mol",
+        };
+
+        write!(f, "{val}")
+    }
+}
+
+impl EnergyUnit {
+    /// From kcal/mol.
+    pub fn convert(self, kcal_mol: f64) -> f64 {
+        match self {
+            Self::KcalMol => kcal_mol,
+            Self::KjMol => kcal_mol * KJ_PER_KCAL,
+        }
+    }
+
+    /// Formats an energy, given in kcal/mol, with its unit.
+    pub fn fmt(self, kcal_mol: f64) -> String {
+        format!("{:.2} {self}", self.convert(kcal_mol))
+    }
+}
@@ -11,21 +11,32 @@ use lin_alg::{
     f32::{Quaternion, Vec3 as Vec3F32},
     f64::Vec3,
 };
+use log::{error, warn};
 use mcubes::{MarchingCubes, MeshSide};
 use na_seq::{AaIdent, Element};
 
 use crate::{
     CamSnapshot, PREFS_SAVE_INTERVAL, Selection, State, StateUi, ViewSelLevel,
+    analysis::{clashes::find_clashes, pockets::make_pocket_mesh},
     download_mols::load_cif_rcsb,
-    mol_drawing::{EntityType, MoleculeView, draw_density, draw_density_surface, draw_molecule},
-    molecule::{Atom, AtomRole, Bond, Molecule, Residue},
-    render::{
-        CAM_INIT_OFFSET, MESH_DENSITY_SURFACE, MESH_SECONDARY_STRUCTURE, MESH_SOLVENT_SURFACE,
-        RENDER_DIST_FAR, RENDER_DIST_NEAR, set_flashlight, set_static_light,
+    dynamics::MdRun,
+    mol_drawing::{
+        EntityType, MoleculeView, SurfaceColoring, draw_density, draw_density_surface, draw_ligand,
+        draw_load_preview, draw_molecule, draw_partial_surfaces, draw_pockets,
+        surface_vertex_colors,
     },
+    molecule::{Atom, AtomRole, Bond, Molecule, Residue},
+    progress::TaskStatus,
     ribbon_mesh::build_cartoon_mesh,
-    sa_surface::make_sas_mesh,
-    ui::{VIEW_DEPTH_FAR_MAX, VIEW_DEPTH_NEAR_MIN},
+    sa_surface::{make_sas_mesh, split_mesh_by_color, start_sas_mesh},
+    scene::{
+        CAM_INIT_OFFSET, MESH_CHAIN_SURFACE, MESH_DENSITY_SURFACE, MESH_DYNAMIC_START,
+        MESH_POCKET_SEL, MESH_POCKETS, MESH_SECONDARY_STRUCTURE, MESH_SEL_SURFACE,
+        MESH_SOLVENT_SURFACE, RENDER_DIST_FAR, RENDER_DIST_NEAR, RES_SPOTLIGHT_TIME,
+        VIEW_DEPTH_FAR_MAX, VIEW_DEPTH_NEAR_MIN, set_flashlight, set_res_spotlight,
+        set_static_light,
+    },
+    units::FS_PER_PS,
 };
 
 const MOVE_TO_TARGET_DIST: f32 = 15.;
@@ -299,11 +310,36 @@ pub fn select_from_search(state: &mut State) {
     }
 }
 
-pub fn cycle_res_selected(state: &mut State, scene: &mut Scene, reverse: bool) {
-    let Some(mol) = &state.molecule else { return };
+/// The mean position of a residue's atoms.
+pub fn res_center(mol: &Molecule, res_i: usize) -> Option<Vec3F32> {
+    let res = mol.residues.get(res_i)?;
+    if res.atoms.is_empty() {
+        return None;
+    }
+
+    let mut sum = Vec3::new_zero();
+    for i in &res.atoms {
+        sum += mol.atoms[*i].posit;
+    }
+
+    Some((sum / res.atoms.len() as f64).into())
+}
+
+/// Steps the selection to the next or previous residue in the chain. Moves the camera along with
+/// the selection if `follow_res_sel` is set, and briefly spotlights the new residue. Returns `true`
+/// if the camera and lighting need updating.
+pub fn cycle_res_selected(state: &mut State, scene: &mut Scene, reverse: bool) -> bool {
+    let Some(mol) = &state.molecule else {
+        return false;
+    };
 
     state.ui.view_sel_level = ViewSelLevel::Residue;
 
+    let center_prev = match state.ui.selection {
+        Selection::Residue(i) => res_center(mol, i),
+        _ => None,
+    };
+
     match state.ui.selection {
         Selection::Residue(res_i) => {
             for chain in &mol.chains {
@@ -332,9 +368,30 @@ pub fn cycle_res_selected(state: &mut State, scene: &mut Scene, reverse: bool) {
         }
     }
 
+    let Selection::Residue(res_i) = state.ui.selection else {
+        return false;
+    };
+    let Some(center) = res_center(mol, res_i) else {
+        return false;
+    };
+
+    if state.ui.follow_res_sel {
+        match center_prev {
+            // Translate only, so the view direction and distance stay constant while stepping.
+            Some(prev) => scene.camera.position += center - prev,
+            None => cam_look_at(&mut scene.camera, center.into()),
+        }
+        set_flashlight(scene);
+    }
+
+    set_res_spotlight(scene, Some(center));
+    state.volatile.spotlight_remaining = RES_SPOTLIGHT_TIME;
+
     if let ControlScheme::Arc { center } = &mut scene.input_settings.control_scheme {
         *center = orbit_center(state);
     }
+
+    true
 }
 
 pub fn check_prefs_save(state: &mut State) {
@@ -361,7 +418,7 @@ pub fn check_prefs_save(state: &mut State) {
 /// `bond_1`'s atom_0.
 pub fn bond_angle(atoms: &[Atom], bond_0: &Bond, bond_1: &Bond) -> f64 {
     if bond_0.atom_1 != bond_1.atom_0 {
-        eprintln!("Error: bonds do not share an atom.");
+        warn!("Error: bonds do not share an atom.");
         return 0.;
     }
 
@@ -551,11 +608,10 @@ pub fn load_atom_coords_rcsb(
                     state.volatile.flags.clear_density_drawing = true;
                     state.molecule = Some(mol)
                 }
-                Err(e) => eprintln!("Problem loading molecule from CIF: {e:?}"),
+                Err(e) => error!("Problem loading molecule from CIF: {e:?}"),
             }
 
-            state.pdb = Some(pdb);
-            state.cif_pdb_raw = Some(cif_data);
+            state.set_source(pdb, cif_data);
             state.update_from_prefs();
 
             *redraw = true;
@@ -572,7 +628,7 @@ pub fn load_atom_coords_rcsb(
                 .updates_rcsb_data(&mut state.volatile.mol_pending_data_avail);
         }
         Err(_e) => {
-            eprintln!("Error loading CIF file");
+            error!("Error loading CIF file");
         }
     }
 }
@@ -588,23 +644,25 @@ pub fn save_snap(state: &mut State, cam: &Camera, name: &str) {
     state.update_save_prefs();
 }
 
+pub fn apply_cam_snap(snap: &CamSnapshot, scene: &mut Scene, engine_updates: &mut EngineUpdates) {
+    scene.camera.position = snap.position;
+    scene.camera.orientation = snap.orientation;
+    scene.camera.far = snap.far;
+
+    scene.camera.update_proj_mat(); // In case `far` etc changed.
+    engine_updates.camera = true;
+
+    set_flashlight(scene);
+    engine_updates.lighting = true;
+}
+
 // The snap must be set in state.ui.cam_snapshot ahead of calling this.
 pub fn load_snap(state: &mut State, scene: &mut Scene, engine_updates: &mut EngineUpdates) {
     if let Some(snap_i) = state.ui.cam_snapshot {
         match state.cam_snapshots.get(snap_i) {
-            Some(snap) => {
-                scene.camera.position = snap.position;
-                scene.camera.orientation = snap.orientation;
-                scene.camera.far = snap.far;
-
-                scene.camera.update_proj_mat(); // In case `far` etc changed.
-                engine_updates.camera = true;
-
-                set_flashlight(scene);
-                engine_updates.lighting = true;
-            }
+            Some(snap) => apply_cam_snap(snap, scene, engine_updates),
             None => {
-                eprintln!("Error: Could not find snapshot {}", snap_i);
+                warn!("Error: Could not find snapshot {}", snap_i);
             }
         }
     }
@@ -617,16 +675,27 @@ pub fn reset_camera(
     engine_updates: &mut EngineUpdates,
     mol: &Molecule,
 ) {
-    let center: lin_alg::f32::Vec3 = mol.center.into();
+    reset_camera_to(scene, view_depth, engine_updates, mol.center, mol.size);
+}
+
+/// Point the camera at a region of the given center and size, e.g. from `mol_center_size`.
+pub fn reset_camera_to(
+    scene: &mut Scene,
+    view_depth: &mut (u16, u16),
+    engine_updates: &mut EngineUpdates,
+    center: Vec3,
+    size: f32,
+) {
+    let center: lin_alg::f32::Vec3 = center.into();
     scene.camera.position =
-        lin_alg::f32::Vec3::new(center.x, center.y, center.z - (mol.size + CAM_INIT_OFFSET));
+        lin_alg::f32::Vec3::new(center.x, center.y, center.z - (size + CAM_INIT_OFFSET));
     scene.camera.orientation = Quaternion::new_identity();
 
     scene.camera.near = RENDER_DIST_NEAR;
     scene.camera.far = RENDER_DIST_FAR;
     scene.camera.update_proj_mat();
 
-    set_static_light(scene, center, mol.size);
+    set_static_light(scene, center, size);
     set_flashlight(scene);
 
     engine_updates.camera = true;
@@ -637,7 +706,7 @@ pub fn reset_camera(
 
 /// Utility function that prints to stderr, and the CLI output. Sets the out flag.
 pub fn handle_err(ui: &mut StateUi, msg: String) {
-    eprintln!("{msg}");
+    error!("{msg}");
     ui.cmd_line_output = msg;
     ui.cmd_line_out_is_err = true;
 }
@@ -652,8 +721,36 @@ pub fn close_mol(state: &mut State, scene: &mut Scene, engine_updates: &mut Engi
             && ent.class != EntityType::DensitySurface as u32
             && ent.class != EntityType::SecondaryStructure as u32
             && ent.class != EntityType::SaSurface as u32
+            && ent.class != EntityType::PartialSurface as u32
+            && ent.class != EntityType::Pocket as u32
     });
 
+    state.volatile.partial_surfaces = Default::default();
+    state.volatile.pockets = Vec::new();
+    state.volatile.pocket_selected = None;
+    state.volatile.interface_chains = Default::default();
+    state.volatile.interface = None;
+    state.volatile.contact_map = None;
+    #[cfg(feature = "gui")]
+    state.volatile.contact_map_tex = None;
+    state.volatile.ramachandran = None;
+    state.volatile.validation = None;
+    state.volatile.gnm = None;
+    state.volatile.backbone_edit_gap = None;
+    state.volatile.clashes = Vec::new();
+    state.volatile.clash_selected = None;
+    state.volatile.contact_occupancy = None;
+    state.volatile.lig_bsa = None;
+    state.volatile.residue_energy = None;
+    state.volatile.pending_load = None;
+    state.volatile.sas_mesh_pending = None;
+    state.volatile.pose_search = None;
+    // The run is for the previous molecule.
+    if let Some(task) = state.volatile.md_run.take() {
+        task.progress.cancel();
+    }
+    state.volatile.load_preview = Vec::new();
+    state.volatile.load_stage = None;
     state.to_save.last_opened = None;
     state.to_save.last_map_opened = None;
     state.volatile.aa_seq_text = String::new();
@@ -665,6 +762,14 @@ pub fn close_mol(state: &mut State, scene: &mut Scene, engine_updates: &mut Engi
 
 pub fn close_lig(state: &mut State, scene: &mut Scene, engine_updates: &mut EngineUpdates) {
     state.ligand = None;
+    state.volatile.mcs_alignment = None;
+    state.volatile.plif = None;
+    state.volatile.plif_snapshots = Vec::new();
+    state.volatile.plif_clusters = Vec::new();
+    state.volatile.lig_bsa = None;
+    state.volatile.residue_energy = None;
+    state.volatile.lig_cleanup = None;
+    state.volatile.am1bcc_pending = None;
     scene
         .entities
         .retain(|ent| ent.class != EntityType::Ligand as u32);
@@ -733,6 +838,52 @@ pub fn make_density_mesh(state: &mut State, scene: &mut Scene, engine_updates: &
     }
 }
 
+/// Run this after atoms are added or moved, e.g. adding hydrogens, mutations, or backbone edits.
+/// Re-runs clash detection if set to run automatically; otherwise, clears the now-stale result.
+pub fn refresh_clashes(state: &mut State) {
+    state.volatile.clash_selected = None;
+
+    state.volatile.clashes = match &state.molecule {
+        Some(mol) if state.ui.clash_auto => find_clashes(mol),
+        _ => Vec::new(),
+    };
+}
+
+/// Apply a finished MD run: flexible receptor atoms, and the ligand for docking runs, take their
+/// final positions, and the snapshots become available for playback.
+fn finish_md_run(state: &mut State, scene: &mut Scene, run: MdRun) {
+    let MdRun { md, ligand } = run;
+
+    if let Some(mol) = &mut state.molecule {
+        for (i, posit) in md.flexible_posits() {
+            // The molecule may have changed while the run was in progress.
+            if let Some(atom) = mol.atoms.get_mut(i) {
+                atom.posit = posit;
+            }
+        }
+    }
+
+    let docking = ligand.is_some();
+    if ligand.is_some() {
+        state.ligand = ligand;
+    }
+
+    state.ui.cmd_line_output = format!(
+        "Ran MD over {} atoms, to {:.1} ps",
+        md.atoms.len(),
+        md.time / FS_PER_PS
+    );
+    state.ui.cmd_line_out_is_err = false;
+
+    state.mol_dynamics = Some(md);
+    state.ui.current_snapshot = 0;
+
+    draw_molecule(state, scene);
+    if docking {
+        draw_ligand(state, scene);
+    }
+}
+
 /// Code here is ctivated by flags. It's organized here, where we have access to the Scene.
 /// These flags are set in places that don't have access to the scene.
 pub fn handle_scene_flags(
@@ -743,6 +894,31 @@ pub fn handle_scene_flags(
     if state.volatile.flags.new_mol_loaded {
         state.volatile.flags.new_mol_loaded = false;
 
+        // Partial surfaces, pockets, interfaces, and contact maps are specific to the previous molecule's atoms.
+        state.volatile.partial_surfaces = Default::default();
+        state.volatile.pockets = Vec::new();
+        state.volatile.pocket_selected = None;
+        state.volatile.interface_chains = Default::default();
+        state.volatile.interface = None;
+        state.volatile.contact_map = None;
+        #[cfg(feature = "gui")]
+        state.volatile.contact_map_tex = None;
+        state.volatile.ramachandran = None;
+        state.volatile.validation = None;
+        state.volatile.gnm = None;
+        state.volatile.backbone_edit_gap = None;
+        state.volatile.clashes = Vec::new();
+        state.volatile.clash_selected = None;
+        state.volatile.lig_bsa = None;
+        state.volatile.residue_energy = None;
+        scene.entities.retain(|ent| {
+            ent.class != EntityType::PartialSurface as u32 && ent.class != EntityType::Pocket as u32
+        });
+        engine_updates.entities = true;
+
+        // Hydrogens are added on load, if the file doesn't include them.
+        refresh_clashes(state);
+
         if let Some(mol) = &state.molecule {
             reset_camera(scene, &mut state.ui.view_depth, engine_updates, mol);
         }
@@ -797,8 +973,56 @@ pub fn handle_scene_flags(
 
         if let Some(mol) = &state.molecule {
             let atoms: Vec<&_> = mol.atoms.iter().filter(|a| !a.hetero).collect();
-            scene.meshes[MESH_SOLVENT_SURFACE] =
-                make_sas_mesh(&atoms, state.to_save.sa_surface_precision);
+            // Built in a thread; applied below once ready.
+            state.volatile.sas_mesh_pending = Some(start_sas_mesh(
+                &state.dev,
+                &atoms,
+                state.to_save.sa_surface_precision,
+            ));
+        }
+    }
+
+    let sas_mesh = match &state.volatile.sas_mesh_pending {
+        Some(pending) => match pending.poll() {
+            TaskStatus::Done(mesh) => Some(mesh),
+            TaskStatus::Running => None,
+            TaskStatus::Failed => {
+                state.volatile.sas_mesh_pending = None;
+                None
+            }
+        },
+        None => None,
+    };
+
+    if let Some(mesh) = sas_mesh {
+        state.volatile.sas_mesh_pending = None;
+
+        if let Some(mol) = &state.molecule {
+            let atoms: Vec<&_> = mol.atoms.iter().filter(|a| !a.hetero).collect();
+            scene.meshes[MESH_SOLVENT_SURFACE] = mesh;
+
+            scene.meshes.truncate(MESH_DYNAMIC_START);
+            state.volatile.sas_vertex_colors = Vec::new();
+            state.volatile.sas_color_meshes = Vec::new();
+
+            if state.ui.surface_coloring != SurfaceColoring::Uniform {
+                let mesh = &scene.meshes[MESH_SOLVENT_SURFACE];
+                let colors = surface_vertex_colors(
+                    &mesh.vertices,
+                    &atoms,
+                    &mol.residues,
+                    state.ui.surface_coloring,
+                );
+
+                for (mesh, color) in split_mesh_by_color(mesh, &colors) {
+                    state
+                        .volatile
+                        .sas_color_meshes
+                        .push((scene.meshes.len(), color));
+                    scene.meshes.push(mesh);
+                }
+                state.volatile.sas_vertex_colors = colors;
+            }
 
             // We draw the molecule here
             if matches!(
@@ -814,6 +1038,129 @@ pub fn handle_scene_flags(
         }
     }
 
+    if state.volatile.flags.update_sel_sfc_mesh || state.volatile.flags.update_chain_sfc_mesh {
+        if let Some(mol) = &state.molecule {
+            let sfcs = &mut state.volatile.partial_surfaces;
+
+            if state.volatile.flags.update_sel_sfc_mesh {
+                let atoms: Vec<&_> = sfcs.sel_atoms.iter().map(|i| &mol.atoms[*i]).collect();
+                scene.meshes[MESH_SEL_SURFACE] =
+                    make_sas_mesh(&state.dev, &atoms, state.to_save.sa_surface_precision);
+                sfcs.sel_mesh_created = !atoms.is_empty();
+            }
+
+            if state.volatile.flags.update_chain_sfc_mesh {
+                let atoms: Vec<&_> = match sfcs.chain.and_then(|i| mol.chains.get(i)) {
+                    Some(chain) => chain
+                        .atoms
+                        .iter()
+                        .map(|i| &mol.atoms[*i])
+                        .filter(|a| !a.hetero)
+                        .collect(),
+                    None => Vec::new(),
+                };
+                scene.meshes[MESH_CHAIN_SURFACE] =
+                    make_sas_mesh(&state.dev, &atoms, state.to_save.sa_surface_precision);
+                sfcs.chain_mesh_created = !atoms.is_empty();
+            }
+
+            draw_partial_surfaces(state, scene);
+
+            engine_updates.meshes = true;
+            engine_updates.entities = true;
+        }
+
+        state.volatile.flags.update_sel_sfc_mesh = false;
+        state.volatile.flags.update_chain_sfc_mesh = false;
+    }
+
+    if state.volatile.flags.update_pocket_mesh {
+        state.volatile.flags.update_pocket_mesh = false;
+
+        let sel = state.volatile.pocket_selected;
+
+        let (selected, others): (Vec<_>, Vec<_>) = state
+            .volatile
+            .pockets
+            .iter()
+            .enumerate()
+            .partition(|(i, _)| Some(*i) == sel);
+
+        let others: Vec<_> = others.into_iter().map(|(_, p)| p).collect();
+        let selected: Vec<_> = selected.into_iter().map(|(_, p)| p).collect();
+
+        scene.meshes[MESH_POCKETS] = make_pocket_mesh(&others);
+        scene.meshes[MESH_POCKET_SEL] = make_pocket_mesh(&selected);
+
+        draw_pockets(state, scene);
+
+        engine_updates.meshes = true;
+        engine_updates.entities = true;
+    }
+
+    if state.volatile.flags.new_preview_loaded {
+        state.volatile.flags.new_preview_loaded = false;
+
+        let posits: Vec<Vec3> = state
+            .volatile
+            .load_preview
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        if !posits.is_empty() {
+            let center =
+                posits.iter().fold(Vec3::new_zero(), |acc, p| acc + *p) / posits.len() as f64;
+            let size = posits
+                .iter()
+                .map(|p| p.x.abs().max(p.y.abs()).max(p.z.abs()))
+                .fold(0., f64::max) as f32;
+
+            reset_camera_to(
+                scene,
+                &mut state.ui.view_depth,
+                engine_updates,
+                center,
+                size,
+            );
+        }
+
+        draw_load_preview(&state.volatile.load_preview, scene);
+        engine_updates.entities = true;
+    }
+
+    // Draw a newly-loaded large molecule in stages, one per frame, so the first appears quickly.
+    if let Some(stage) = state.volatile.load_stage {
+        state.volatile.load_stage = stage.next();
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+
+    if state.volatile.pending_load.is_some() && state.poll_pending_load() {
+        draw_molecule(state, scene);
+        engine_updates.entities = true;
+    }
+
+    if let Some(task) = &state.volatile.md_run {
+        // If cancelled, the run stops at its next step, and returns the snapshots so far.
+        match task.poll() {
+            TaskStatus::Running => (),
+            TaskStatus::Done(Ok(run)) => {
+                state.volatile.md_run = None;
+                finish_md_run(state, scene, run);
+                engine_updates.entities = true;
+            }
+            TaskStatus::Done(Err(e)) => {
+                state.volatile.md_run = None;
+                handle_err(&mut state.ui, e.to_string());
+            }
+            TaskStatus::Failed => {
+                state.volatile.md_run = None;
+                handle_err(&mut state.ui, "The MD run failed".to_owned());
+            }
+        }
+    }
+
     if state.volatile.mol_pending_data_avail.is_some() {
         if let Some(mol) = &mut state.molecule {
             if mol.poll_data_avail(&mut state.volatile.mol_pending_data_avail) {